@@ -0,0 +1,93 @@
+//! Lucas–Lehmer tests on fixed arrays of limbs against num-bigint.
+//!
+//! `ll_iteration_p*` time 50 iterations of one exponent with
+//! [`MersenneModulus`] (`num_bigint`) and with a [`SmallMersenne`] of the
+//! size the test picks for it (`fixed`). `sweep_to_4096` runs the whole
+//! test of every prime exponent up to 4096, the part of a sweep of
+//! `2..=20000` that the fixed arrays take over: `num_bigint` is a bare
+//! loop of [`MersenneModulus::square_sub2`] for each, and `selected` is
+//! [`is_mersenne_prime`], checks and all, as a search runs it. Above
+//! [`MAX_FIXED_EXPONENT`](mersenne::arith::fixed::MAX_FIXED_EXPONENT)
+//! both run the same code, so the rest of the sweep takes as long as it
+//! did. Run with `cargo bench --bench fixed_limbs`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mersenne::arith::fixed::SmallMersenne;
+use mersenne::arith::MersenneModulus;
+use mersenne::{is_mersenne_prime, is_prime};
+use num_bigint::BigUint;
+
+const ITERATIONS: u64 = 50;
+
+/// Times `ITERATIONS` iterations of `M(p)` both ways, from a residue
+/// partway through the test.
+fn iterations<const LIMBS: usize>(c: &mut Criterion, p: u64) {
+    let ctx = MersenneModulus::new(p);
+    let fixed = SmallMersenne::<LIMBS>::new(p);
+    let mut start = BigUint::from(4u32);
+    for _ in 0..40 {
+        start = ctx.square_sub2(&start);
+    }
+    let limbs = fixed.residue(&start);
+
+    let mut group = c.benchmark_group(format!("ll_iteration_p{}", p));
+    group.throughput(Throughput::Elements(ITERATIONS));
+    group.bench_function("num_bigint", |b| {
+        b.iter(|| {
+            let mut s = start.clone();
+            for _ in 0..ITERATIONS {
+                s = ctx.square_sub2(&s);
+            }
+            s
+        })
+    });
+    group.bench_function("fixed", |b| {
+        b.iter(|| {
+            let mut s = limbs;
+            for _ in 0..ITERATIONS {
+                s = fixed.square_sub2(&s);
+            }
+            s
+        })
+    });
+    group.finish();
+}
+
+fn ll_iteration(c: &mut Criterion) {
+    iterations::<2>(c, 127);
+    iterations::<32>(c, 1279);
+    iterations::<64>(c, 3217);
+}
+
+fn sweep(c: &mut Criterion) {
+    let exponents: Vec<u64> = (3..=4096).filter(|&p| is_prime(p)).collect();
+    let mut group = c.benchmark_group("sweep_to_4096");
+    group.sample_size(10);
+    group.bench_function("num_bigint", |b| {
+        b.iter(|| {
+            exponents
+                .iter()
+                .filter(|&&p| {
+                    let ctx = MersenneModulus::new(p);
+                    let mut s = BigUint::from(4u32);
+                    for _ in 0..p - 2 {
+                        s = ctx.square_sub2(&s);
+                    }
+                    s == BigUint::ZERO
+                })
+                .count()
+        })
+    });
+    group.bench_function("selected", |b| {
+        b.iter(|| {
+            exponents
+                .iter()
+                .filter(|&&p| is_mersenne_prime(p).is_prime())
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, ll_iteration, sweep);
+criterion_main!(benches);
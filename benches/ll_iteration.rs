@@ -0,0 +1,90 @@
+//! Lucas–Lehmer iteration throughput at p = 100003.
+//!
+//! `baseline` reproduces the original reduction, which rebuilt the modulus
+//! and cloned the input on every iteration; `full_reduction` reduced each
+//! square with [`MersenneModulus::reduce_full`], comparing it with the
+//! modulus; `context` is the current [`MersenneModulus`] path, with its
+//! single fold. Run with `cargo bench --bench ll_iteration`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use mersenne::arith::MersenneModulus;
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+const P: u64 = 100_003;
+const ITERATIONS: u64 = 50;
+
+fn baseline_mod_mersenne(n: &BigUint, p: u64) -> BigUint {
+    let modulus = (&BigUint::one() << p) - 1u32;
+    let mut n = n.clone();
+    while n.bits() > p {
+        let high = &n >> p;
+        let low = &n & &modulus;
+        n = high + low;
+    }
+    if n == modulus {
+        BigUint::zero()
+    } else {
+        n
+    }
+}
+
+/// A residue partway through the test, so every iteration works on full-size
+/// numbers instead of the tiny values near `s = 4`.
+fn warm_residue(ctx: &MersenneModulus) -> BigUint {
+    let mut s = BigUint::from(4u32);
+    for _ in 0..40 {
+        s = ctx.square_sub2(&s);
+    }
+    s
+}
+
+fn ll_iteration(c: &mut Criterion) {
+    let ctx = MersenneModulus::new(P);
+    let start = warm_residue(&ctx);
+
+    let mut group = c.benchmark_group("ll_iteration_p100003");
+    group.throughput(Throughput::Elements(ITERATIONS));
+    group.sample_size(20);
+
+    group.bench_function("baseline", |b| {
+        b.iter(|| {
+            let mut s = start.clone();
+            for _ in 0..ITERATIONS {
+                s = &s * &s - 2u32;
+                s = baseline_mod_mersenne(&s, P);
+            }
+            s
+        })
+    });
+
+    group.bench_function("full_reduction", |b| {
+        b.iter(|| {
+            let mut s = start.clone();
+            for _ in 0..ITERATIONS {
+                let square = ctx.reduce_full(&s * &s);
+                s = if square < BigUint::from(2u32) {
+                    square + ctx.modulus() - 2u32
+                } else {
+                    square - 2u32
+                };
+            }
+            s
+        })
+    });
+
+    group.bench_function("context", |b| {
+        b.iter(|| {
+            let mut s = start.clone();
+            for _ in 0..ITERATIONS {
+                s = ctx.square_sub2(&s);
+            }
+            s
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, ll_iteration);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+//! Reducing the square of a residue modulo `M(p)`, at p = 1000003 and
+//! p = 10000019, without the squaring itself.
+//!
+//! `baseline` is the reduction `mod_mersenne` and `reduce_full` did before:
+//! folding while the number is longer than `p` bits and then comparing it
+//! with the modulus; `reduce_full` is the current full reduction;
+//! `single_fold` is the Lucas–Lehmer loop's
+//! [`MersenneModulus::reduce_once`], and `single_fold_canonical` adds the
+//! [`MersenneModulus::canonical`] a residue gets on leaving the loop. Run
+//! with `cargo bench --bench reduction`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use mersenne::arith::MersenneModulus;
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+fn baseline(ctx: &MersenneModulus, mut n: BigUint) -> BigUint {
+    while n.bits() > ctx.p() {
+        let high = &n >> ctx.p();
+        n &= ctx.modulus();
+        n += high;
+    }
+    if n == *ctx.modulus() {
+        BigUint::zero()
+    } else {
+        n
+    }
+}
+
+/// The product of two residues with their bits all over the place, so the
+/// fold carries like a real square does.
+fn product(ctx: &MersenneModulus) -> BigUint {
+    let mut digits = Vec::new();
+    let mut x = 0x9E37_79B9_7F4A_7C15u64;
+    for _ in 0..ctx.p().div_ceil(32) {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        digits.push(x as u32);
+    }
+    let a = ctx.reduce_full(BigUint::new(digits));
+    let b = ctx.reduce_full(&a + 12345u32);
+    a * b
+}
+
+fn reduction(c: &mut Criterion) {
+    for p in [1_000_003, 10_000_019] {
+        let ctx = MersenneModulus::new(p);
+        let n = product(&ctx);
+
+        let mut group = c.benchmark_group(format!("reduction_p{}", p));
+        group.sample_size(20);
+        group.bench_function("baseline", |b| {
+            b.iter_batched(|| n.clone(), |n| baseline(&ctx, n), BatchSize::LargeInput)
+        });
+        group.bench_function("reduce_full", |b| {
+            b.iter_batched(|| n.clone(), |n| ctx.reduce_full(n), BatchSize::LargeInput)
+        });
+        group.bench_function("single_fold", |b| {
+            b.iter_batched(|| n.clone(), |n| ctx.reduce_once(n), BatchSize::LargeInput)
+        });
+        group.bench_function("single_fold_canonical", |b| {
+            b.iter_batched(
+                || n.clone(),
+                |n| ctx.canonical(ctx.reduce_once(n)),
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, reduction);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+//! Records the git commit being built as `MERSENNE_GIT_COMMIT`, for
+//! `mersenne::system`. Builds outside a git checkout leave it unset.
+//!
+//! With the `ffi` feature, also writes the C header `include/mersenne.h`
+//! for `src/ffi.rs`.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // Rebuilding when HEAD moves keeps the hash current without rerunning
+    // this for every edit. A missing path would rerun it on every build.
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=MERSENNE_GIT_COMMIT={}", commit.trim());
+    }
+    #[cfg(feature = "ffi")]
+    header();
+}
+
+/// Generates the C header from the items of `src/ffi.rs` alone, so that
+/// the constants of the rest of the crate stay out of it.
+#[cfg(feature = "ffi")]
+fn header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file("cbindgen.toml").expect("cbindgen.toml is invalid");
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src("src/ffi.rs")
+        .generate()
+        .expect("cannot generate the C header")
+        .write_to_file("include/mersenne.h");
+}
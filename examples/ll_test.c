@@ -0,0 +1,52 @@
+/*
+ * Tests a few exponents through the C interface of the `ffi` feature.
+ *
+ *     cargo build --features ffi
+ *     cc examples/ll_test.c -Iinclude -Ltarget/debug -lmersenne -o ll_test
+ *     LD_LIBRARY_PATH=target/debug ./ll_test
+ */
+
+#include <inttypes.h>
+#include <stdio.h>
+
+#include "mersenne.h"
+
+struct progress {
+    uint64_t calls;
+    uint64_t last;
+};
+
+static void count(uint64_t iteration, uint64_t total, void *user) {
+    struct progress *progress = user;
+    progress->calls++;
+    progress->last = iteration;
+    (void)total;
+}
+
+static void cancel(uint64_t iteration, uint64_t total, void *user) {
+    mersenne_cancel_request(user);
+    (void)iteration;
+    (void)total;
+}
+
+int main(void) {
+    struct progress progress = {0, 0};
+    int32_t result = mersenne_ll_test(127, count, &progress);
+    printf("M(127): %s, %" PRIu64 " progress reports up to iteration %" PRIu64 "\n",
+           result == MERSENNE_PRIME ? "prime" : "not prime", progress.calls, progress.last);
+
+    uint64_t res64;
+    result = mersenne_res64(11, &res64);
+    printf("M(11): %s, res64 %016" PRIX64 "\n",
+           result == MERSENNE_COMPOSITE ? "composite" : "not composite", res64);
+
+    printf("M(15): %s\n",
+           mersenne_ll_test(15, NULL, NULL) == MERSENNE_INVALID_EXPONENT ? "invalid exponent"
+                                                                          : "tested");
+
+    MersenneCancel *token = mersenne_cancel_new();
+    result = mersenne_ll_test_cancellable(4423, cancel, token, token);
+    printf("M(4423): %s\n", result == MERSENNE_CANCELLED ? "cancelled" : "not cancelled");
+    mersenne_cancel_free(token);
+    return 0;
+}
@@ -0,0 +1,15 @@
+// Runs the tests for index.html, off the page's thread.
+import init, { test_exponent } from "./pkg/mersenne.js";
+
+const ready = init();
+
+self.addEventListener("message", async ({ data: p }) => {
+  await ready;
+  try {
+    const report = test_exponent(p, (iteration, total) =>
+      self.postMessage({ type: "progress", iteration, total }));
+    self.postMessage({ type: "done", report });
+  } catch (error) {
+    self.postMessage({ type: "error", message: String(error) });
+  }
+});
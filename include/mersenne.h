@@ -0,0 +1,133 @@
+#ifndef MERSENNE_H
+#define MERSENNE_H
+
+/* Generated from src/ffi.rs by build.rs with the `ffi` feature. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * `M(p)` is composite.
+ */
+#define MERSENNE_COMPOSITE 0
+
+/**
+ * `M(p)` is prime.
+ */
+#define MERSENNE_PRIME 1
+
+/**
+ * The test was cancelled through its [`MersenneCancel`] token.
+ */
+#define MERSENNE_CANCELLED -1
+
+/**
+ * `p` is not prime, so there is no Lucas–Lehmer test of `M(p)`; it is
+ * composite.
+ */
+#define MERSENNE_INVALID_EXPONENT -2
+
+/**
+ * A pointer that must not be null was null.
+ */
+#define MERSENNE_NULL_POINTER -3
+
+/**
+ * The library panicked. This is a bug in it.
+ */
+#define MERSENNE_PANIC -4
+
+/**
+ * The residue kept failing its sanity checks, which only hardware or
+ * arithmetic errors cause, so the test gave up without a result.
+ */
+#define MERSENNE_ARITHMETIC_ERROR -5
+
+/**
+ * A cancellation token. Raising it with [`mersenne_cancel_request`], from
+ * any thread, stops the tests that were given it before their next
+ * iteration.
+ */
+typedef struct MersenneCancel MersenneCancel;
+
+/**
+ * Called with `(iteration, total, user)` about every 1% of a test and on
+ * its final iteration, on the thread running the test. It must not
+ * unwind.
+ */
+typedef void (*mersenne_progress_cb)(uint64_t, uint64_t, void*);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Makes a new token, to be freed with [`mersenne_cancel_free`].
+ */
+struct MersenneCancel *mersenne_cancel_new(void);
+
+/**
+ * Raises `token`. Tests given it from now on are cancelled at once. Null
+ * is ignored.
+ *
+ * # Safety
+ *
+ * `token` must be null or come from [`mersenne_cancel_new`] and not have
+ * been freed.
+ */
+void mersenne_cancel_request(const struct MersenneCancel *token);
+
+/**
+ * Frees `token`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `token` must be null or come from [`mersenne_cancel_new`], not have
+ * been freed, and not be in use by a running test.
+ */
+void mersenne_cancel_free(struct MersenneCancel *token);
+
+/**
+ * Runs a Lucas–Lehmer test of `M(p)`: [`MERSENNE_PRIME`],
+ * [`MERSENNE_COMPOSITE`] or [`MERSENNE_INVALID_EXPONENT`]. `cb`, if not
+ * null, is called with `user` as the test goes.
+ *
+ * # Safety
+ *
+ * `cb` must be safe to call with `user`.
+ */
+int32_t mersenne_ll_test(uint64_t p, mersenne_progress_cb cb, void *user);
+
+/**
+ * Same as [`mersenne_ll_test`], but returns [`MERSENNE_CANCELLED`] once
+ * `token`, if not null, is raised.
+ *
+ * # Safety
+ *
+ * `cb` must be safe to call with `user`, and `token` must be null or a
+ * live token from [`mersenne_cancel_new`].
+ */
+int32_t mersenne_ll_test_cancellable(uint64_t p,
+                                     mersenne_progress_cb cb,
+                                     void *user,
+                                     const struct MersenneCancel *token);
+
+/**
+ * Runs a Lucas–Lehmer test of `M(p)` and stores the low 64 bits of its
+ * final residue in `out`: 0 if `M(p)` is prime. Returns the same codes as
+ * [`mersenne_ll_test`], and leaves `out` alone unless the test finished.
+ *
+ * # Safety
+ *
+ * `out` must be null or point to writable memory for a `uint64_t`.
+ */
+int32_t mersenne_res64(uint64_t p, uint64_t *out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MERSENNE_H */
@@ -0,0 +1,212 @@
+//! Python bindings: the `mersenne` module.
+//!
+//! ```python
+//! import mersenne
+//!
+//! mersenne.is_mersenne_prime(127)   # True
+//! mersenne.search(2, 1000, threads=4, progress=lambda done, total: print(done, total))
+//! mersenne.trial_factor(23, 10)     # 47
+//! ```
+//!
+//! Build and test it with maturin, from this directory:
+//!
+//! ```text
+//! maturin develop && pytest tests
+//! ```
+//!
+//! The work runs on threads of its own without the GIL, so other Python
+//! threads keep going. The calling thread waits for it with the GIL
+//! released, taking it back a few times a second to check for Ctrl-C and
+//! to call the progress callback, at most every [`PROGRESS_INTERVAL`]. A
+//! `KeyboardInterrupt`, or an exception raised by the callback, stops the
+//! work before its next iteration and is raised from the call.
+
+use mersenne::{factor, primality, sieve, TestControl};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often the waiting thread looks for Ctrl-C.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The least time between two calls of a progress callback.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How far the work has got: `done` of `total`, in iterations or
+/// exponents.
+struct Progress {
+    done: AtomicU64,
+    total: u64,
+}
+
+/// Runs `work` on a thread of its own while the calling thread, holding
+/// the GIL only in between waits, checks for signals and calls `callback`
+/// with the progress. Once either raises, `stop` is raised for `work` to
+/// see, and the exception is returned after `work` is done.
+fn run_detached<T, F>(
+    py: Python<'_>,
+    stop: &AtomicBool,
+    progress: &Progress,
+    callback: Option<&Bound<'_, PyAny>>,
+    work: F,
+) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let finished = (Mutex::new(false), Condvar::new());
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(|| {
+            let result = work();
+            *finished.0.lock().unwrap() = true;
+            finished.1.notify_one();
+            result
+        });
+        let mut reported = None;
+        let mut last_call = Instant::now();
+        let mut failure = None;
+        loop {
+            let over = py.allow_threads(|| {
+                let done = finished.0.lock().unwrap();
+                let (done, _) = finished
+                    .1
+                    .wait_timeout_while(done, POLL_INTERVAL, |done| !*done)
+                    .unwrap();
+                *done
+            });
+            if failure.is_none() {
+                let due = over || last_call.elapsed() >= PROGRESS_INTERVAL;
+                let now = progress.done.load(Ordering::Relaxed);
+                let outcome = py.check_signals().and_then(|()| match callback {
+                    Some(callback) if due && reported != Some(now) => {
+                        reported = Some(now);
+                        last_call = Instant::now();
+                        callback.call1((now, progress.total)).map(drop)
+                    }
+                    _ => Ok(()),
+                });
+                if let Err(error) = outcome {
+                    stop.store(true, Ordering::Relaxed);
+                    failure = Some(error);
+                }
+            }
+            if over {
+                break;
+            }
+        }
+        let result = match worker.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        };
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    })
+}
+
+/// Whether `M(p) = 2^p - 1` is prime, by a Lucas–Lehmer test.
+///
+/// `progress`, if given, is called with `(iteration, total)` as the test
+/// goes.
+#[pyfunction]
+#[pyo3(signature = (p, progress=None))]
+fn is_mersenne_prime(
+    py: Python<'_>,
+    p: u64,
+    progress: Option<&Bound<'_, PyAny>>,
+) -> PyResult<bool> {
+    if !primality::is_prime(p) {
+        return Ok(false);
+    }
+    let stop = AtomicBool::new(false);
+    let state = Progress {
+        done: AtomicU64::new(0),
+        total: p.saturating_sub(2),
+    };
+    let result = run_detached(py, &stop, &state, progress, || {
+        let control = TestControl::new(&stop).publish_to(&state.done);
+        mersenne::is_mersenne_prime_interruptible(p, None, control, |_| {})
+    })?;
+    match result {
+        Ok(result) => Ok(result.is_prime()),
+        Err(interrupted) if interrupted.anomaly.is_some() => Err(PyRuntimeError::new_err(
+            "the test kept failing its arithmetic checks",
+        )),
+        // Otherwise only stopped by an exception, which run_detached has returned.
+        Err(_) => Err(PyRuntimeError::new_err("the test was interrupted")),
+    }
+}
+
+/// The exponents `p` in `start..=end` with `M(p)` prime, in increasing
+/// order.
+///
+/// Each exponent is trial factored first and then tested, on `threads`
+/// threads, by default one per core. `progress`, if given, is called with
+/// `(exponents done, exponents in the range)`.
+#[pyfunction]
+#[pyo3(signature = (start, end, threads=None, progress=None))]
+fn search(
+    py: Python<'_>,
+    start: u64,
+    end: u64,
+    threads: Option<usize>,
+    progress: Option<&Bound<'_, PyAny>>,
+) -> PyResult<Vec<u64>> {
+    if threads == Some(0) {
+        return Err(PyValueError::new_err("threads must be at least 1"));
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+    let exponents: Vec<u64> = sieve::primes(start, end).collect();
+    let stop = AtomicBool::new(false);
+    let state = Progress {
+        done: AtomicU64::new(0),
+        total: exponents.len() as u64,
+    };
+    run_detached(py, &stop, &state, progress, || {
+        pool.install(|| {
+            exponents
+                .par_iter()
+                .copied()
+                .filter(|&p| {
+                    let prime = !stop.load(Ordering::Relaxed)
+                        && factor::trial_factor(p, factor::worthwhile_tf_depth(p)).is_none()
+                        && mersenne::is_mersenne_prime_interruptible(
+                            p,
+                            None,
+                            TestControl::new(&stop),
+                            |_| {},
+                        )
+                        .is_ok_and(|result| result.is_prime());
+                    state.done.fetch_add(1, Ordering::Relaxed);
+                    prime
+                })
+                .collect()
+        })
+    })
+}
+
+/// The smallest factor of `M(p)` below `2^bits`, or `None`. `bits` is at
+/// most 64.
+#[pyfunction]
+fn trial_factor(py: Python<'_>, p: u64, bits: u32) -> PyResult<Option<u64>> {
+    if bits > 64 {
+        return Err(PyValueError::new_err("bits must be at most 64"));
+    }
+    Ok(py.allow_threads(|| factor::trial_factor(p, bits)))
+}
+
+#[pymodule]
+#[pyo3(name = "mersenne")]
+fn mersenne_python(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(is_mersenne_prime, module)?)?;
+    module.add_function(wrap_pyfunction!(search, module)?)?;
+    module.add_function(wrap_pyfunction!(trial_factor, module)?)?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+"""Tests of the Python bindings. Run `maturin develop` first."""
+
+import _thread
+import threading
+import time
+
+import pytest
+
+import mersenne
+
+MERSENNE_PRIME_EXPONENTS = [2, 3, 5, 7, 13, 17, 19, 31, 61, 89, 107, 127, 521, 607]
+
+
+def test_is_mersenne_prime():
+    assert [p for p in range(700) if mersenne.is_mersenne_prime(p)] == MERSENNE_PRIME_EXPONENTS
+
+
+def test_is_mersenne_prime_reports_progress():
+    calls = []
+    assert mersenne.is_mersenne_prime(4423, progress=lambda done, total: calls.append((done, total)))
+    assert calls[-1] == (4421, 4421)
+
+
+def test_search():
+    assert mersenne.search(2, 700) == MERSENNE_PRIME_EXPONENTS
+    assert mersenne.search(100, 130, threads=2) == [107, 127]
+    assert mersenne.search(14, 16) == []
+
+
+def test_search_reports_progress():
+    calls = []
+    assert mersenne.search(2, 1000, progress=lambda done, total: calls.append((done, total)))
+    # 168 primes below 1000.
+    assert calls[-1] == (168, 168)
+    assert all(done <= total for done, total in calls)
+
+
+def test_search_checks_threads():
+    with pytest.raises(ValueError):
+        mersenne.search(2, 100, threads=0)
+
+
+def test_trial_factor():
+    assert mersenne.trial_factor(11, 5) == 23
+    assert mersenne.trial_factor(23, 6) == 47
+    assert mersenne.trial_factor(29, 8) == 233
+    assert mersenne.trial_factor(31, 64) is None
+    with pytest.raises(ValueError):
+        mersenne.trial_factor(31, 65)
+
+
+def test_an_exception_from_the_callback_stops_the_test():
+    def fail(done, total):
+        raise ZeroDivisionError
+
+    with pytest.raises(ZeroDivisionError):
+        mersenne.is_mersenne_prime(44497, progress=fail)
+
+
+def test_keyboard_interrupt_cancels_the_test():
+    threading.Timer(0.2, _thread.interrupt_main).start()
+    started = time.monotonic()
+    with pytest.raises(KeyboardInterrupt):
+        mersenne.is_mersenne_prime(110503)
+    assert time.monotonic() - started < 5
+
+
+def test_the_gil_is_released():
+    ticks = 0
+    done = threading.Event()
+
+    def test():
+        mersenne.is_mersenne_prime(9941)
+        done.set()
+
+    thread = threading.Thread(target=test)
+    thread.start()
+    while not done.is_set():
+        ticks += 1
+        time.sleep(0.001)
+    thread.join()
+    assert ticks > 10
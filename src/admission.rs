@@ -0,0 +1,255 @@
+//! Admission control for `--max-mem`: tests only start while the memory
+//! they are estimated to need, added up, stays under the limit.
+//!
+//! Tests are admitted strictly in the order they asked, so the scheduling
+//! still follows `--order`, and a large exponent waiting for room is not
+//! overtaken by a stream of small ones. A test estimated to need more than
+//! the whole limit runs once nothing else is, rather than never.
+//!
+//! A run without `--max-mem` is checked against the memory the machine has
+//! available instead: one whose largest test alone would not fit safely is
+//! refused unless `--force` is given, and one whose tests would not fit
+//! all at once gets a limit of its own.
+
+use log::{debug, warn};
+use mersenne::arith::peak_test_bytes;
+use mersenne::report::Form;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// How often a waiting test looks for Ctrl-C.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The memory the process takes before its first test, counted once when
+/// a run is checked against the available memory.
+pub const PROCESS_BYTES: u64 = 8 << 20;
+
+/// The share of the available memory a run plans on taking at most, which
+/// leaves room for the estimates to be off and for the rest of the
+/// machine.
+pub const SAFE_SHARE: f64 = 0.8;
+
+/// The memory a test of `bits`-bit residues, such as one of `M(bits)`, is
+/// estimated to need, in bytes.
+pub fn estimated_bytes(bits: u64) -> u64 {
+    peak_test_bytes(bits)
+}
+
+/// The part of `available` bytes the tests of a run can safely take.
+pub fn usable(available: u64) -> u64 {
+    ((available as f64 * SAFE_SHARE) as u64).saturating_sub(PROCESS_BYTES)
+}
+
+/// Bytes as megabytes, `1.5 MB`; a megabyte is 2^20 bytes, as for
+/// `--max-mem`.
+pub fn format_mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1u64 << 20) as f64)
+}
+
+/// The physical memory of this machine in bytes, where it can be found.
+pub fn physical_memory() -> Option<u64> {
+    #[cfg(unix)]
+    {
+        // SAFETY: sysconf only reads system configuration.
+        let (pages, page_size) = unsafe {
+            (
+                libc::sysconf(libc::_SC_PHYS_PAGES),
+                libc::sysconf(libc::_SC_PAGESIZE),
+            )
+        };
+        if pages > 0 && page_size > 0 {
+            return Some((pages as u64).saturating_mul(page_size as u64));
+        }
+    }
+    None
+}
+
+/// The memory this machine can give a run now without swapping, in bytes:
+/// what Linux counts as available, or failing that the physical memory,
+/// and no more than the memory cgroup of the process has left.
+pub fn available_memory() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let meminfo = std::fs::read_to_string("/proc/meminfo").ok();
+        let available = meminfo.as_deref().and_then(|meminfo| {
+            let line = meminfo
+                .lines()
+                .find(|line| line.starts_with("MemAvailable:"))?;
+            let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+            Some(kb.saturating_mul(1024))
+        });
+        let available = available.or_else(physical_memory);
+        match (available, cgroup_headroom()) {
+            (Some(available), Some(headroom)) => Some(available.min(headroom)),
+            (available, headroom) => available.or(headroom),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    physical_memory()
+}
+
+/// What the cgroup v2 memory limit of this process leaves, if it has one.
+#[cfg(target_os = "linux")]
+fn cgroup_headroom() -> Option<u64> {
+    let read = |name: &str| std::fs::read_to_string(format!("/sys/fs/cgroup/{}", name)).ok();
+    let limit: u64 = read("memory.max")?.trim().parse().ok()?;
+    let current: u64 = read("memory.current")
+        .and_then(|current| current.trim().parse().ok())
+        .unwrap_or(0);
+    Some(limit.saturating_sub(current))
+}
+
+/// The budget a run without `--max-mem` gets when `tests` tests at once of
+/// `name`, its largest number, would need more than a run can safely take
+/// of `available` bytes: the tests then start only while they fit, as
+/// with `--max-mem`, down to one at a time.
+pub fn implicit_budget(
+    name: &str,
+    bits: u64,
+    tests: usize,
+    available: u64,
+) -> Option<MemoryBudget> {
+    let usable = usable(available);
+    let needed = estimated_bytes(bits).saturating_mul(tests as u64);
+    if tests <= 1 || needed <= usable {
+        return None;
+    }
+    warn!(
+        "{} tests of {} at once would need about {} of memory, more than the {} a run can \
+         safely take of the {} available; tests will start only while they fit, as with \
+         --max-mem {}.",
+        tests,
+        name,
+        format_mb(needed),
+        format_mb(usable),
+        format_mb(available),
+        usable >> 20
+    );
+    Some(MemoryBudget::new(usable))
+}
+
+/// The memory limit and the tests admitted under it.
+pub struct MemoryBudget {
+    limit: u64,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Estimated bytes of the tests running now.
+    admitted: u64,
+    running: usize,
+    /// Tickets handed out, and the one whose turn it is.
+    next_ticket: u64,
+    serving: u64,
+}
+
+/// A snapshot for the status output.
+#[derive(Debug, Clone, Copy)]
+pub struct BudgetStatus {
+    pub limit: u64,
+    pub admitted: u64,
+    pub running: usize,
+    /// Tests waiting for room.
+    pub waiting: u64,
+}
+
+impl BudgetStatus {
+    /// For example `memory 412.0 MB of 1024.0 MB, 1 waiting`.
+    pub fn summary(&self) -> String {
+        format!(
+            "memory {} of {}, {} waiting",
+            format_mb(self.admitted),
+            format_mb(self.limit),
+            self.waiting
+        )
+    }
+}
+
+/// Holds a test's share of the budget until dropped.
+pub struct Admission<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for Admission<'_> {
+    fn drop(&mut self) {
+        let mut state = self.budget.state.lock().unwrap();
+        state.admitted -= self.bytes;
+        state.running -= 1;
+        self.budget.changed.notify_all();
+    }
+}
+
+impl MemoryBudget {
+    /// A budget of `limit` bytes.
+    pub fn new(limit: u64) -> MemoryBudget {
+        MemoryBudget {
+            limit,
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Waits until a test of the `form` number of exponent `p` fits, and
+    /// reserves its memory. Returns `None` if `stop` is raised first.
+    pub fn admit(&self, form: Form, p: u64, stop: &AtomicBool) -> Option<Admission<'_>> {
+        let bytes = estimated_bytes(form.bits(p));
+        let name = form.number(p);
+        if bytes > self.limit {
+            warn!(
+                "{} needs about {}, more than --max-mem allows; it will run on its own.",
+                name,
+                format_mb(bytes)
+            );
+        }
+        let mut state = self.state.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        let mut announced = false;
+        loop {
+            let fits = state.admitted + bytes <= self.limit || state.running == 0;
+            if state.serving == ticket && fits {
+                break;
+            }
+            if stop.load(Ordering::SeqCst) {
+                return None;
+            }
+            if state.serving == ticket && !announced {
+                debug!(
+                    "Waiting to test {}: it needs about {} and {} of {} is in use.",
+                    name,
+                    format_mb(bytes),
+                    format_mb(state.admitted),
+                    format_mb(self.limit)
+                );
+                announced = true;
+            }
+            state = self
+                .changed
+                .wait_timeout(state, STOP_CHECK_INTERVAL)
+                .unwrap()
+                .0;
+        }
+        state.serving += 1;
+        state.admitted += bytes;
+        state.running += 1;
+        self.changed.notify_all();
+        Some(Admission {
+            budget: self,
+            bytes,
+        })
+    }
+
+    pub fn status(&self) -> BudgetStatus {
+        let state = self.state.lock().unwrap();
+        BudgetStatus {
+            limit: self.limit,
+            admitted: state.admitted,
+            running: state.running,
+            waiting: state.next_ticket - state.serving,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Binding worker threads to cores, for `--pin-threads`.
+//!
+//! On a machine with several sockets, a test the scheduler moves to another
+//! socket leaves its residue in the first socket's memory, and the squarings,
+//! which are limited by memory bandwidth, slow down badly. Pinned, each
+//! worker thread stays on a core of its own: the cores of `--cpus` if given,
+//! or every core the program may run on, handed out in order as the threads
+//! are set up and shared round-robin once there are more threads than cores.
+//!
+//! Where the cores cannot be listed or a thread cannot be bound, a warning
+//! says so and the threads run unpinned, as without `--pin-threads`.
+
+use core_affinity::CoreId;
+use log::warn;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// The CPUs of `--cpus`, such as `0-7,16-23`, in increasing order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuList(Vec<usize>);
+
+impl FromStr for CpuList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<CpuList, String> {
+        let number = |text: &str| {
+            text.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("{:?} is not a CPU number", text.trim()))
+        };
+        let mut cpus = Vec::new();
+        for part in s.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (number(first)?, number(last)?);
+                    if first > last {
+                        return Err(format!("the CPU range {} runs backwards", part.trim()));
+                    }
+                    cpus.extend(first..=last);
+                }
+                None => cpus.push(number(part)?),
+            }
+        }
+        cpus.sort_unstable();
+        cpus.dedup();
+        Ok(CpuList(cpus))
+    }
+}
+
+impl fmt::Display for CpuList {
+    /// The CPUs with runs written as ranges, `0-7,16-23`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for &cpu in &self.0 {
+            match runs.last_mut() {
+                Some((_, last)) if *last + 1 == cpu => *last = cpu,
+                _ => runs.push((cpu, cpu)),
+            }
+        }
+        let runs: Vec<String> = runs
+            .into_iter()
+            .map(|(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{}-{}", first, last)
+                }
+            })
+            .collect();
+        f.write_str(&runs.join(","))
+    }
+}
+
+/// The cores to pin to, and which threads got which.
+#[derive(Debug)]
+pub struct Pinning {
+    cores: Vec<CoreId>,
+    /// The threads set up so far, by name, with their cores.
+    layout: Mutex<Vec<(&'static str, Vec<usize>)>>,
+}
+
+impl Pinning {
+    /// Pins to the cores of `cpus`, or to every core. `None`, after a
+    /// warning, if the platform does not list its cores or none of `cpus`
+    /// is among them.
+    pub fn new(cpus: Option<&CpuList>) -> Option<Pinning> {
+        let Some(mut cores) = core_affinity::get_core_ids().filter(|cores| !cores.is_empty())
+        else {
+            warn!("cannot pin threads: this platform does not list its cores; running unpinned.");
+            return None;
+        };
+        if let Some(CpuList(cpus)) = cpus {
+            let missing: Vec<String> = cpus
+                .iter()
+                .filter(|&&cpu| !cores.iter().any(|core| core.id == cpu))
+                .map(|cpu| cpu.to_string())
+                .collect();
+            if !missing.is_empty() {
+                warn!(
+                    "--cpus: this process cannot run on CPU(s) {}; leaving them out.",
+                    missing.join(", ")
+                );
+            }
+            cores.retain(|core| cpus.contains(&core.id));
+            if cores.is_empty() {
+                warn!("cannot pin threads: none of --cpus is available; running unpinned.");
+                return None;
+            }
+        }
+        cores.sort_unstable();
+        Some(Pinning {
+            cores,
+            layout: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The cores for `threads` more threads called `name`, one each, taken
+    /// after those of the threads before them.
+    pub fn assign(&self, name: &'static str, threads: usize) -> Vec<CoreId> {
+        let mut layout = self.layout.lock().unwrap();
+        let before: usize = layout.iter().map(|(_, ids)| ids.len()).sum();
+        let cores: Vec<CoreId> = (before..before + threads)
+            .map(|i| self.cores[i % self.cores.len()])
+            .collect();
+        if before <= self.cores.len() && before + threads > self.cores.len() {
+            warn!(
+                "--pin-threads: more threads than the {} CPU(s) to pin them to; some share a CPU.",
+                self.cores.len()
+            );
+        }
+        layout.push((name, cores.iter().map(|core| core.id).collect()));
+        cores
+    }
+
+    /// Which threads are pinned where, such as `trial factoring on CPU 0,
+    /// primality test on CPUs 1-4`.
+    pub fn layout(&self) -> String {
+        let layout = self.layout.lock().unwrap();
+        let parts: Vec<String> = layout
+            .iter()
+            .filter(|(_, ids)| !ids.is_empty())
+            .map(|(name, ids)| {
+                let mut cpus = ids.clone();
+                cpus.sort_unstable();
+                cpus.dedup();
+                let plural = if cpus.len() == 1 { "" } else { "s" };
+                format!("{} on CPU{} {}", name, plural, CpuList(cpus))
+            })
+            .collect();
+        parts.join(", ")
+    }
+}
+
+/// Pins the current thread to `core`, warning the first time that fails.
+pub fn pin_current(core: CoreId) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if !core_affinity::set_for_current(core) && !WARNED.swap(true, Ordering::Relaxed) {
+        warn!("cannot pin threads on this platform; running unpinned.");
+    }
+}
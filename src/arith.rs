@@ -0,0 +1,876 @@
+//! Arithmetic modulo a Mersenne number `M(p) = 2^p - 1`, modulo `2^p + 1`
+//! for testing Wagstaff and Fermat numbers, and modulo `k·2^n - 1` for
+//! testing Riesel numbers.
+
+pub mod choice;
+pub mod fixed;
+#[cfg(feature = "gmp")]
+pub mod gmp;
+#[cfg(feature = "native")]
+mod parallel;
+
+/// Without the `native` feature there are no threads to split squarings
+/// across, so a team can never be started.
+#[cfg(not(feature = "native"))]
+mod parallel {
+    use num_bigint::BigUint;
+
+    #[derive(Debug)]
+    pub(crate) enum Team {}
+
+    impl Team {
+        pub(crate) fn new(_threads: usize) -> Option<Team> {
+            None
+        }
+
+        pub(crate) fn square_folded(&self, _x: &BigUint, _p: u64) -> BigUint {
+            match *self {}
+        }
+
+        pub(crate) fn square(&self, _x: &BigUint) -> BigUint {
+            match *self {}
+        }
+    }
+}
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use parallel::Team;
+use std::sync::Arc;
+
+/// The arithmetic a Lucas–Lehmer test needs, so the test loop, its
+/// checkpointing and its progress reporting are shared by every backend,
+/// and with the Lucas–Lehmer–Riesel test through [`RieselModulus`].
+pub(crate) trait MersenneArith {
+    /// A residue modulo `M(p)` in the backend's own representation.
+    type Residue: Clone;
+
+    fn new(p: u64) -> Self;
+
+    /// Splits each squaring across `threads` threads. Backends that cannot
+    /// split them ignore this and square on the calling thread.
+    fn with_threads(self, _threads: usize) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+
+    /// Converts from the portable representation used by checkpoints.
+    fn residue_of(&self, n: &BigUint) -> Self::Residue;
+
+    fn to_biguint(&self, x: &Self::Residue) -> BigUint;
+
+    /// Reduces `n` into the canonical range `0..2^p - 1`.
+    fn reduce(&self, n: Self::Residue) -> Self::Residue;
+
+    /// One Lucas–Lehmer iteration: `s^2 - 2 mod M(p)`.
+    fn square_sub2(&self, s: &Self::Residue) -> Self::Residue;
+
+    /// `x^2 - 2^k mod M(p)`, for `k < p`.
+    fn square_sub_pow2(&self, x: &Self::Residue, k: u64) -> Self::Residue;
+
+    /// `x · 2^k mod M(p)`, for `k < p`. Modulo a Mersenne number this is a
+    /// rotation of the low `p` bits.
+    fn mul_pow2(&self, x: &Self::Residue, k: u64) -> Self::Residue;
+
+    /// Stores `s` shifted by `shift`, as `s · 2^shift`.
+    fn shifted(&self, s: &Self::Residue, shift: Shift) -> Self::Residue {
+        self.mul_pow2(s, shift.bits)
+    }
+
+    /// Undoes [`shifted`](Self::shifted).
+    fn unshifted(&self, x: &Self::Residue, shift: Shift) -> Self::Residue {
+        if shift.bits == 0 {
+            x.clone()
+        } else {
+            self.mul_pow2(x, shift.p - shift.bits)
+        }
+    }
+
+    /// One Lucas–Lehmer iteration on a residue stored with `shift`.
+    ///
+    /// If `x = s · 2^k`, then `x^2 - 2^(2k+1) = (s^2 - 2) · 2^2k`, so the
+    /// iteration runs on the shifted value directly and the shift doubles.
+    /// Returns the new residue and its shift.
+    fn square_sub2_shifted(&self, x: &Self::Residue, shift: Shift) -> (Self::Residue, Shift) {
+        let next = shift.squared();
+        if next.bits == 0 {
+            (self.square_sub2(x), next)
+        } else {
+            (self.square_sub_pow2(x, (next.bits + 1) % next.p), next)
+        }
+    }
+
+    fn is_zero(x: &Self::Residue) -> bool;
+
+    /// The low 64 bits of `x`.
+    fn res64(x: &Self::Residue) -> u64;
+
+    /// Whether `x` is in the canonical range the iterations keep residues
+    /// in, `0` up to but not including the modulus. Cheap enough to check
+    /// after every iteration.
+    fn is_canonical(&self, x: &Self::Residue) -> bool;
+}
+
+/// The backend `is_mersenne_prime` and `mod_mersenne` use: GMP with the
+/// `gmp` feature, num-bigint otherwise.
+#[cfg(feature = "gmp")]
+pub(crate) type Backend = gmp::GmpModulus;
+#[cfg(not(feature = "gmp"))]
+pub(crate) type Backend = MersenneModulus;
+
+/// The name of [`Backend`], for reports of the build in use.
+#[cfg(feature = "gmp")]
+pub const BACKEND_NAME: &str = "GMP";
+#[cfg(not(feature = "gmp"))]
+pub const BACKEND_NAME: &str = "num-bigint";
+
+/// Residue-sized buffers a test holds at its peak: the residue, its
+/// square at twice the size, the scratch of num-bigint's Toom-3 squaring,
+/// and the copies taken for checkpoints and Jacobi checks. Measured as the
+/// peak resident set of single-threaded tests of `M(p)` for `p` from one
+/// to 64 million, less that of the process before its first test, it
+/// comes to 13 to 15 residues.
+const PEAK_RESIDUES: u64 = 15;
+
+/// The memory a test of one thread on `bits`-bit residues, such as a
+/// Lucas–Lehmer test of `M(bits)`, is estimated to need at its peak, in
+/// bytes. It never falls as `bits` grows.
+pub fn peak_test_bytes(bits: u64) -> u64 {
+    bits.div_ceil(8).saturating_mul(PEAK_RESIDUES)
+}
+
+/// How far a Lucas–Lehmer residue is rotated: the test stores `s · 2^bits
+/// mod M(p)` instead of `s`.
+///
+/// Every iteration doubles the shift, so a test run with a nonzero shift
+/// puts entirely different bit patterns through the squarings than an
+/// unshifted one, while the unshifted residues agree. Comparing the two
+/// catches hardware and arithmetic errors that a repeat of the same run
+/// would reproduce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shift {
+    p: u64,
+    bits: u64,
+}
+
+impl Shift {
+    /// A shift of `bits` modulo `p`.
+    pub fn new(p: u64, bits: u64) -> Shift {
+        Shift { p, bits: bits % p }
+    }
+
+    /// No shift at all.
+    pub fn none(p: u64) -> Shift {
+        Shift::new(p, 0)
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// The shift after one more squaring.
+    pub fn squared(self) -> Shift {
+        Shift::new(self.p, ((self.bits as u128 * 2) % self.p as u128) as u64)
+    }
+
+    /// The shift after `iterations` more squarings: `bits · 2^iterations
+    /// mod p`.
+    pub fn after(self, iterations: u64) -> Shift {
+        let doublings =
+            BigUint::from(2u32).modpow(&BigUint::from(iterations), &BigUint::from(self.p));
+        let bits = BigUint::from(self.bits) * doublings % self.p;
+        Shift::new(self.p, bits.iter_u64_digits().next().unwrap_or(0))
+    }
+}
+
+/// Reduction context for a fixed exponent, so the modulus is built once per
+/// test rather than once per iteration.
+#[derive(Debug, Clone)]
+pub struct MersenneModulus {
+    p: u64,
+    modulus: BigUint,
+    /// Set with [`with_threads`](Self::with_threads).
+    team: Option<Arc<Team>>,
+}
+
+impl MersenneModulus {
+    pub fn new(p: u64) -> MersenneModulus {
+        MersenneModulus {
+            p,
+            modulus: (BigUint::one() << p) - 1u32,
+            team: None,
+        }
+    }
+
+    /// Splits [`square`](Self::square) and the Lucas–Lehmer iterations
+    /// across `threads` threads of their own, which pays off for exponents
+    /// in the hundreds of thousands and up. The results are the same as on
+    /// one thread. If the threads cannot be started, squarings stay on the
+    /// calling thread.
+    pub fn with_threads(self, threads: usize) -> MersenneModulus {
+        MersenneModulus {
+            team: (threads > 1)
+                .then(|| Team::new(threads))
+                .flatten()
+                .map(Arc::new),
+            ..self
+        }
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+
+    /// The modulus `2^p - 1`.
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    /// Reduces `n`, of any size, into the canonical range `0..2^p - 1`.
+    ///
+    /// Since `2^p ≡ 1`, the bits above position `p` are folded back onto the
+    /// low bits. Each fold of a number above `2^2p` all but halves its
+    /// length; once below, a single [`reduce_once`](Self::reduce_once) and
+    /// [`canonical`](Self::canonical) finish it, without asking for its
+    /// length again. The mask and the add happen in place on `n`'s buffer,
+    /// so each fold allocates only the shifted-out high part.
+    pub fn reduce_full(&self, mut n: BigUint) -> BigUint {
+        while n.bits() > 2 * self.p {
+            let high = &n >> self.p;
+            n &= &self.modulus;
+            n += high;
+        }
+        self.canonical(self.reduce_once(n))
+    }
+
+    /// Reduces `n < 2^2p`, such as the product of two residues, into
+    /// `0..=2^p - 1` with a single fold, for the Lucas–Lehmer loop.
+    ///
+    /// Writing `n = h·2^p + l` with `h, l < 2^p`, the fold is one shift, one
+    /// mask and one add, and `h + l` is below `2^(p+1)`: the only carry the
+    /// add can leave is into bit `p`, and subtracting `2^p - 1` once then
+    /// takes it off, as clearing that bit and adding 1. There is no
+    /// comparison with the modulus, so `2^p - 1` itself, a second form of
+    /// zero, is left alone; subtracting from the result, as the iterations
+    /// do, makes it canonical anyway, and [`canonical`](Self::canonical)
+    /// does it where nothing else will.
+    pub fn reduce_once(&self, mut n: BigUint) -> BigUint {
+        debug_assert!(n.bits() <= 2 * self.p);
+        let high = &n >> self.p;
+        n &= &self.modulus;
+        n += high;
+        if n.bit(self.p) {
+            n.set_bit(self.p, false);
+            n += 1u32;
+        }
+        n
+    }
+
+    /// Takes `n <= 2^p - 1`, as [`reduce_once`](Self::reduce_once) leaves
+    /// it, into the canonical range, turning `2^p - 1` into 0. The only
+    /// comparison with the modulus in the reduction, and only needed once a
+    /// residue leaves the loop.
+    pub fn canonical(&self, n: BigUint) -> BigUint {
+        debug_assert!(n <= self.modulus);
+        if n == self.modulus {
+            BigUint::zero()
+        } else {
+            n
+        }
+    }
+
+    /// `a * b mod M(p)`.
+    pub fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        self.reduce_full(a * b)
+    }
+
+    /// `x^2 mod M(p)`.
+    pub fn square(&self, x: &BigUint) -> BigUint {
+        self.reduce_full(self.square_once(x))
+    }
+
+    /// `x^2` for a residue `x`, reduced by [`reduce_once`](Self::reduce_once).
+    fn square_once(&self, x: &BigUint) -> BigUint {
+        match &self.team {
+            Some(team) => self.reduce_once(team.square_folded(x, self.p)),
+            None => self.reduce_once(x * x),
+        }
+    }
+
+    /// One Lucas–Lehmer iteration: `s^2 - 2 mod M(p)`, in the canonical
+    /// range for a residue `s`.
+    pub fn square_sub2(&self, s: &BigUint) -> BigUint {
+        // At most 2^p - 1, so never 2^p + 1 or more once 2 is taken off.
+        // Whether it is below 2 is read off its length, which is stored.
+        let mut square = self.square_once(s);
+        if square.bits() < 2 {
+            square += &self.modulus;
+        }
+        square -= 2u32;
+        square
+    }
+
+    /// `x^2 - 2^k mod M(p)`, for `k < p`, in the canonical range for a
+    /// residue `x`.
+    pub fn square_sub_pow2(&self, x: &BigUint, k: u64) -> BigUint {
+        let mut square = self.square_once(x);
+        // Below 2^k exactly when it has at most k bits.
+        if square.bits() <= k {
+            square += &self.modulus;
+        }
+        square - (BigUint::one() << k)
+    }
+
+    /// `x · 2^k mod M(p)`, for `k < p`.
+    pub fn mul_pow2(&self, x: &BigUint, k: u64) -> BigUint {
+        self.reduce_full(x << k)
+    }
+}
+
+/// Reduction modulo `2^p + 1`, three times the Wagstaff number `W(p)`.
+///
+/// Working modulo `2^p + 1` rather than `W(p)` keeps the reduction as cheap
+/// as modulo `M(p)`: since `2^p ≡ -1`, the bits above position `p` are
+/// subtracted from the low bits instead of added to them.
+///
+/// With `p = 2^n` the modulus is the Fermat number `F(n)` itself.
+#[derive(Debug, Clone)]
+pub struct WagstaffModulus {
+    p: u64,
+    /// `2^p + 1`.
+    modulus: BigUint,
+    /// `2^p - 1`, the mask of the low `p` bits.
+    low_bits: BigUint,
+    team: Option<Arc<Team>>,
+}
+
+impl WagstaffModulus {
+    pub fn new(p: u64) -> WagstaffModulus {
+        WagstaffModulus {
+            p,
+            modulus: (BigUint::one() << p) + 1u32,
+            low_bits: (BigUint::one() << p) - 1u32,
+            team: None,
+        }
+    }
+
+    /// Splits [`square`](Self::square) across `threads` threads, as
+    /// [`MersenneModulus::with_threads`] does.
+    pub fn with_threads(self, threads: usize) -> WagstaffModulus {
+        WagstaffModulus {
+            team: (threads > 1)
+                .then(|| Team::new(threads))
+                .flatten()
+                .map(Arc::new),
+            ..self
+        }
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+
+    /// The modulus `2^p + 1`.
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    /// Reduces `n` into the canonical range `0..2^p + 1`.
+    pub fn reduce(&self, n: BigUint) -> BigUint {
+        if n < self.modulus {
+            return n;
+        }
+        let high = &n >> self.p;
+        let low = n & &self.low_bits;
+        if low >= high {
+            low - high
+        } else {
+            // low - high is negative: reduce its magnitude and negate.
+            let magnitude = self.reduce(high - low);
+            if magnitude.is_zero() {
+                magnitude
+            } else {
+                &self.modulus - magnitude
+            }
+        }
+    }
+
+    /// `a * b mod 2^p + 1`.
+    pub fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        self.reduce(a * b)
+    }
+
+    /// `x^2 mod 2^p + 1`.
+    pub fn square(&self, x: &BigUint) -> BigUint {
+        match &self.team {
+            Some(team) => self.reduce(team.square(x)),
+            None => self.reduce(x * x),
+        }
+    }
+}
+
+/// Reduction modulo a Riesel number `k·2^n - 1`, for odd `k`.
+///
+/// Since `k·2^n ≡ 1`, writing the bits above position `n` as `a·k + r`
+/// folds `a·k·2^n` back to `a`, so a reduction costs a shift and a division
+/// by the small `k` instead of a division by the modulus. With `k = 1` this
+/// is the fold of [`MersenneModulus::reduce`].
+#[derive(Debug, Clone)]
+pub struct RieselModulus {
+    k: u64,
+    n: u64,
+    /// `k·2^n - 1`.
+    modulus: BigUint,
+    /// `2^n - 1`, the mask of the low `n` bits.
+    low_bits: BigUint,
+    team: Option<Arc<Team>>,
+}
+
+impl RieselModulus {
+    pub fn new(k: u64, n: u64) -> RieselModulus {
+        RieselModulus {
+            k,
+            n,
+            modulus: (BigUint::from(k) << n) - 1u32,
+            low_bits: (BigUint::one() << n) - 1u32,
+            team: None,
+        }
+    }
+
+    /// Splits [`square`](Self::square) across `threads` threads, as
+    /// [`MersenneModulus::with_threads`] does.
+    pub fn with_threads(self, threads: usize) -> RieselModulus {
+        RieselModulus {
+            team: (threads > 1)
+                .then(|| Team::new(threads))
+                .flatten()
+                .map(Arc::new),
+            ..self
+        }
+    }
+
+    pub fn k(&self) -> u64 {
+        self.k
+    }
+
+    pub fn n(&self) -> u64 {
+        self.n
+    }
+
+    /// The modulus `k·2^n - 1`.
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    /// Reduces `x` into the canonical range `0..k·2^n - 1`.
+    ///
+    /// Each fold takes off a multiple of the modulus, `a` times it, until
+    /// the high part is below `k`; what is left is then under twice the
+    /// modulus.
+    pub fn reduce(&self, mut x: BigUint) -> BigUint {
+        while x >= self.modulus {
+            let high = &x >> self.n;
+            if high < BigUint::from(self.k) {
+                x -= &self.modulus;
+                continue;
+            }
+            let (a, r) = high.div_rem(&BigUint::from(self.k));
+            x &= &self.low_bits;
+            x += a;
+            x += r << self.n;
+        }
+        x
+    }
+
+    /// `a * b mod k·2^n - 1`.
+    pub fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        self.reduce(a * b)
+    }
+
+    /// `x^2 mod k·2^n - 1`.
+    pub fn square(&self, x: &BigUint) -> BigUint {
+        match &self.team {
+            Some(team) => self.reduce(team.square(x)),
+            None => self.reduce(x * x),
+        }
+    }
+
+    /// `x^2 - 2^j mod k·2^n - 1`, for `2^j` below the modulus.
+    pub fn square_sub_pow2(&self, x: &BigUint, j: u64) -> BigUint {
+        let power = BigUint::one() << j;
+        let mut square = self.square(x);
+        if square < power {
+            square += &self.modulus;
+        }
+        square - power
+    }
+}
+
+impl MersenneArith for RieselModulus {
+    type Residue = BigUint;
+
+    /// The modulus `2^p - 1`, which is `k·2^n - 1` with `k = 1`.
+    fn new(p: u64) -> Self {
+        RieselModulus::new(1, p)
+    }
+
+    fn with_threads(self, threads: usize) -> Self {
+        RieselModulus::with_threads(self, threads)
+    }
+
+    fn residue_of(&self, n: &BigUint) -> BigUint {
+        self.reduce(n.clone())
+    }
+
+    fn to_biguint(&self, x: &BigUint) -> BigUint {
+        x.clone()
+    }
+
+    fn reduce(&self, n: BigUint) -> BigUint {
+        RieselModulus::reduce(self, n)
+    }
+
+    fn square_sub2(&self, s: &BigUint) -> BigUint {
+        RieselModulus::square_sub_pow2(self, s, 1)
+    }
+
+    fn square_sub_pow2(&self, x: &BigUint, k: u64) -> BigUint {
+        RieselModulus::square_sub_pow2(self, x, k)
+    }
+
+    /// Only a rotation for `k = 1`; shifted tests need that.
+    fn mul_pow2(&self, x: &BigUint, k: u64) -> BigUint {
+        self.reduce(x << k)
+    }
+
+    fn is_zero(x: &BigUint) -> bool {
+        x.is_zero()
+    }
+
+    fn res64(x: &BigUint) -> u64 {
+        crate::res64(x)
+    }
+
+    fn is_canonical(&self, x: &BigUint) -> bool {
+        *x < self.modulus
+    }
+}
+
+impl MersenneArith for MersenneModulus {
+    type Residue = BigUint;
+
+    fn new(p: u64) -> Self {
+        MersenneModulus::new(p)
+    }
+
+    fn with_threads(self, threads: usize) -> Self {
+        MersenneModulus::with_threads(self, threads)
+    }
+
+    /// Reduced, since the iterations only take residues.
+    fn residue_of(&self, n: &BigUint) -> BigUint {
+        self.reduce_full(n.clone())
+    }
+
+    fn to_biguint(&self, x: &BigUint) -> BigUint {
+        x.clone()
+    }
+
+    fn reduce(&self, n: BigUint) -> BigUint {
+        self.reduce_full(n)
+    }
+
+    fn square_sub2(&self, s: &BigUint) -> BigUint {
+        MersenneModulus::square_sub2(self, s)
+    }
+
+    fn square_sub_pow2(&self, x: &BigUint, k: u64) -> BigUint {
+        MersenneModulus::square_sub_pow2(self, x, k)
+    }
+
+    fn mul_pow2(&self, x: &BigUint, k: u64) -> BigUint {
+        MersenneModulus::mul_pow2(self, x, k)
+    }
+
+    fn is_zero(x: &BigUint) -> bool {
+        x.is_zero()
+    }
+
+    fn res64(x: &BigUint) -> u64 {
+        crate::res64(x)
+    }
+
+    fn is_canonical(&self, x: &BigUint) -> bool {
+        x.bits() <= self.p && *x != self.modulus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peak_memory_grows_with_the_exponent() {
+        let mut sizes: Vec<u64> = (1..40)
+            .map(|k| 1u64 << k)
+            .flat_map(|n| [n - 1, n, n + 1])
+            .collect();
+        sizes.sort_unstable();
+        let mut last = 0;
+        for bits in sizes {
+            let bytes = peak_test_bytes(bits);
+            assert!(
+                bytes >= last,
+                "{} bits: {} bytes, down from {}",
+                bits,
+                bytes,
+                last
+            );
+            last = bytes;
+        }
+        assert_eq!(peak_test_bytes(100_000_000), 187_500_000);
+        assert_eq!(peak_test_bytes(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn reduce_matches_remainder() {
+        let ctx = MersenneModulus::new(61);
+        let n = BigUint::parse_bytes(b"123456789abcdef0123456789abcdef0123456789", 16).unwrap();
+        assert_eq!(ctx.reduce_full(n.clone()), &n % ctx.modulus());
+        assert_eq!(ctx.reduce_full(ctx.modulus().clone()), BigUint::zero());
+        assert_eq!(ctx.reduce_full(BigUint::zero()), BigUint::zero());
+    }
+
+    /// A Lucas–Lehmer iteration with a full reduction of the square, as
+    /// every iteration did before [`MersenneModulus::reduce_once`].
+    fn square_sub2_fully_reduced(ctx: &MersenneModulus, s: &BigUint) -> BigUint {
+        let square = ctx.reduce_full(s * s);
+        if square < BigUint::from(2u32) {
+            square + ctx.modulus() - 2u32
+        } else {
+            square - 2u32
+        }
+    }
+
+    /// A residue modulo `M(p)` from splitmix64 seeded with `seed`.
+    fn random_residue(p: u64, seed: &mut u64) -> BigUint {
+        let mut next = || {
+            *seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = *seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        let digits: Vec<u64> = (0..p.div_ceil(64)).map(|_| next()).collect();
+        let mut bytes: Vec<u8> = digits.iter().flat_map(|d| d.to_le_bytes()).collect();
+        bytes.truncate(p.div_ceil(8) as usize);
+        BigUint::from_bytes_le(&bytes) % MersenneModulus::new(p).modulus()
+    }
+
+    #[test]
+    fn reduce_once_stays_below_the_modulus() {
+        let mut seed = 1;
+        for p in [3, 31, 32, 33, 61, 64, 65, 127, 521] {
+            let ctx = MersenneModulus::new(p);
+            let m = ctx.modulus().clone();
+            let mut cases = vec![BigUint::zero(), BigUint::one(), &m - 1u32, m.clone()];
+            cases.extend((0..20).map(|_| random_residue(p, &mut seed)));
+            for a in &cases {
+                for b in &cases[..6] {
+                    let once = ctx.reduce_once(a * b);
+                    assert!(once <= m, "M({})", p);
+                    assert_eq!(ctx.reduce_full(once), a * b % &m, "M({})", p);
+                }
+            }
+        }
+    }
+
+    /// The full reduction as it was before it finished with
+    /// [`MersenneModulus::reduce_once`]: folding until the number fits, and
+    /// then comparing it with the modulus.
+    fn reduce_by_folding(ctx: &MersenneModulus, mut n: BigUint) -> BigUint {
+        while n.bits() > ctx.p() {
+            n = (&n >> ctx.p()) + (&n & ctx.modulus());
+        }
+        if n == *ctx.modulus() {
+            BigUint::zero()
+        } else {
+            n
+        }
+    }
+
+    #[test]
+    fn reduce_full_agrees_with_folding_until_it_fits() {
+        let mut seed = 75;
+        for p in [2, 3, 5, 31, 61, 63, 64, 65, 127, 521, 4423] {
+            let ctx = MersenneModulus::new(p);
+            let m = ctx.modulus().clone();
+            let mut cases = vec![
+                BigUint::zero(),
+                m.clone(),
+                &m + 1u32,
+                &m * &m,
+                BigUint::one() << (2 * p),
+                (BigUint::one() << (2 * p)) - 1u32,
+                (BigUint::one() << (5 * p)) - 1u32,
+            ];
+            for _ in 0..30 {
+                let (a, b) = (random_residue(p, &mut seed), random_residue(p, &mut seed));
+                cases.push(&a * &b);
+                cases.push(&a * &b * &a + &b);
+                cases.push(&a << (p + 1));
+            }
+            for n in cases {
+                let expected = reduce_by_folding(&ctx, n.clone());
+                assert_eq!(expected, &n % &m, "M({})", p);
+                assert_eq!(ctx.reduce_full(n.clone()), expected, "M({}), {}", p, n);
+                if n.bits() <= 2 * p {
+                    assert_eq!(ctx.canonical(ctx.reduce_once(n)), expected, "M({})", p);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_fold_iterations_match_full_reductions() {
+        let mut seed = 2024;
+        // Every prime below 2000: 302 odd exponents.
+        for p in crate::sieve::primes(3, 2000) {
+            let ctx = MersenneModulus::new(p);
+            let mut fast = random_residue(p, &mut seed);
+            let mut full = fast.clone();
+            for _ in 0..50 {
+                fast = ctx.square_sub2(&fast);
+                full = square_sub2_fully_reduced(&ctx, &full);
+            }
+            assert_eq!(fast, full, "M({})", p);
+        }
+        for p in crate::sieve::primes(3, 700) {
+            let ctx = MersenneModulus::new(p);
+            let (mut fast, mut full) = (BigUint::from(4u32), BigUint::from(4u32));
+            for _ in 0..p - 2 {
+                fast = ctx.square_sub2(&fast);
+                full = square_sub2_fully_reduced(&ctx, &full);
+            }
+            assert_eq!(fast, full, "M({})", p);
+        }
+        // The second form of zero, 2^p - 1, comes out canonical.
+        let ctx = MersenneModulus::new(89);
+        let m = ctx.modulus().clone();
+        assert_eq!(ctx.square_sub2(&m), &m - 2u32);
+        assert_eq!(ctx.square_sub_pow2(&m, 5), &m - 32u32);
+    }
+
+    #[test]
+    fn square_sub2_wraps_below_two() {
+        let ctx = MersenneModulus::new(7);
+        assert_eq!(ctx.square_sub2(&BigUint::zero()), BigUint::from(125u32));
+        assert_eq!(ctx.square_sub2(&BigUint::one()), BigUint::from(126u32));
+        assert_eq!(ctx.square_sub2(&BigUint::from(4u32)), BigUint::from(14u32));
+        assert_eq!(ctx.square_sub2(&BigUint::from(12u32)), BigUint::from(15u32));
+    }
+
+    #[test]
+    fn shifted_iterations_track_the_unshifted_ones() {
+        for p in [7, 89, 127] {
+            let ctx = MersenneModulus::new(p);
+            for bits in [1, 2, p / 2, p - 1] {
+                let initial = Shift::new(p, bits);
+                let mut shift = initial;
+                let mut s = BigUint::from(4u32);
+                let mut x = MersenneArith::shifted(&ctx, &s, shift);
+                for i in 1..=p - 2 {
+                    s = ctx.square_sub2(&s);
+                    (x, shift) = ctx.square_sub2_shifted(&x, shift);
+                    assert_eq!(ctx.unshifted(&x, shift), s, "M({}), shift {}", p, bits);
+                    assert_eq!(shift, initial.after(i));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn threads_do_not_change_the_iterations() {
+        let one = MersenneModulus::new(9689);
+        let three = MersenneModulus::new(9689).with_threads(3);
+        let (mut a, mut b) = (BigUint::from(4u32), BigUint::from(4u32));
+        for _ in 0..100 {
+            a = one.square_sub2(&a);
+            b = three.square_sub2(&b);
+            assert_eq!(a, b);
+        }
+        assert_eq!(one.square_sub_pow2(&a, 77), three.square_sub_pow2(&a, 77));
+    }
+
+    #[test]
+    fn wagstaff_reduce_matches_remainder() {
+        for p in [5, 61, 127, 9689] {
+            let ctx = WagstaffModulus::new(p);
+            let threaded = WagstaffModulus::new(p).with_threads(3);
+            let m = ctx.modulus().clone();
+            let mut x = BigUint::from(3u32);
+            for _ in 0..20 {
+                let expected = &x * &x % &m;
+                assert_eq!(ctx.square(&x), expected, "2^{} + 1", p);
+                assert_eq!(threaded.square(&x), expected, "2^{} + 1", p);
+                x = expected + 12345u32;
+                x %= &m;
+            }
+            assert_eq!(ctx.reduce(m.clone()), BigUint::zero());
+            assert_eq!(ctx.reduce(&m - 1u32), &m - 1u32);
+            assert_eq!(ctx.reduce(&m * 7u32 + 5u32), BigUint::from(5u32));
+            // (2^p - 1) · 2^p ≡ (-2) · (-1) = 2.
+            assert_eq!(ctx.mul(&(&m - 2u32), &(&m - 1u32)), BigUint::from(2u32));
+        }
+    }
+
+    #[test]
+    fn riesel_reduce_matches_remainder() {
+        for (k, n) in [(1, 61), (3, 2), (15, 229), (9, 40), (12345, 200)] {
+            let ctx = RieselModulus::new(k, n);
+            let m = ctx.modulus().clone();
+            let mut x = BigUint::from(3u32);
+            for _ in 0..20 {
+                let expected = &x * &x % &m;
+                assert_eq!(ctx.square(&x), expected, "{}*2^{} - 1", k, n);
+                x = expected + 12345u32;
+                x %= &m;
+            }
+            assert_eq!(ctx.reduce(m.clone()), BigUint::zero());
+            assert_eq!(ctx.reduce(&m - 1u32), &m - 1u32);
+            assert_eq!(ctx.reduce(&m * 7u32 + 5u32), BigUint::from(5u32) % &m);
+            assert_eq!(ctx.reduce(&m * &m * 3u32 + 1u32), BigUint::one());
+        }
+        let mersenne = MersenneModulus::new(89);
+        let riesel = RieselModulus::new(1, 89);
+        let x = BigUint::parse_bytes(b"123456789abcdef0123456789abcdef", 16).unwrap()
+            % mersenne.modulus();
+        assert_eq!(
+            MersenneArith::square_sub2(&riesel, &x),
+            mersenne.square_sub2(&x)
+        );
+    }
+
+    #[test]
+    fn shifts_are_rotations() {
+        let ctx = MersenneModulus::new(7);
+        assert_eq!(
+            ctx.mul_pow2(&BigUint::from(0b1000001u32), 1),
+            BigUint::from(0b0000011u32)
+        );
+        assert_eq!(ctx.mul_pow2(&BigUint::from(5u32), 0), BigUint::from(5u32));
+        let shift = Shift::new(7, 3);
+        assert_eq!(
+            ctx.unshifted(
+                &MersenneArith::shifted(&ctx, &BigUint::from(42u32), shift),
+                shift
+            ),
+            BigUint::from(42u32)
+        );
+        assert_eq!(Shift::new(7, 3).after(2), Shift::new(7, 5));
+        assert_eq!(Shift::none(7).after(100), Shift::none(7));
+    }
+}
@@ -0,0 +1,312 @@
+//! Which arithmetic a Lucas–Lehmer test squares with.
+//!
+//! There are up to three backends: `bignum`, num-bigint's
+//! [`MersenneModulus`]; `fixed`, a [`SmallMersenne`] on the stack, for
+//! exponents up to [`FIXED_CAPACITY`]; and `gmp`, a
+//! [`GmpModulus`](super::gmp::GmpModulus), in builds with the `gmp`
+//! feature. Which is fastest at a size depends on the machine as much as
+//! on the size, so rather than trust fixed crossovers a [`Calibration`]
+//! times [`CALIBRATION_ITERATIONS`] squarings of each backend that takes
+//! the exponent, once per size, and ranks them.
+//!
+//! Every backend computes the same residues bit for bit, so the runs of a
+//! double-checked test may use different ones, and a disagreement between
+//! them is all the more telling.
+
+use super::fixed::{SmallMersenne, MAX_FIXED_EXPONENT};
+use super::{MersenneArith, MersenneModulus};
+use crate::clock::Clock;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The largest exponent the `fixed` backend takes, the capacity of its
+/// largest array.
+pub const FIXED_CAPACITY: u64 = 64 * 64;
+
+/// The squarings a [`Calibration`] times of each backend at a size.
+pub const CALIBRATION_ITERATIONS: u64 = 100;
+
+/// Above this many bits a size is not timed again: the ranking of this
+/// size stands for every larger one, whose squarings would make the
+/// calibration as long as a test.
+const CALIBRATION_LIMIT: u64 = 1 << 16;
+
+/// An arithmetic backend, as `--backend` names it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArithBackend {
+    Bignum,
+    Fixed,
+    Gmp,
+}
+
+impl ArithBackend {
+    pub const ALL: [ArithBackend; 3] =
+        [ArithBackend::Bignum, ArithBackend::Fixed, ArithBackend::Gmp];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArithBackend::Bignum => "bignum",
+            ArithBackend::Fixed => "fixed",
+            ArithBackend::Gmp => "gmp",
+        }
+    }
+
+    /// Whether this build has the backend.
+    pub fn is_available(self) -> bool {
+        self != ArithBackend::Gmp || cfg!(feature = "gmp")
+    }
+
+    /// Whether the backend can test `M(p)`.
+    pub fn handles(self, p: u64) -> bool {
+        match self {
+            ArithBackend::Fixed => p <= FIXED_CAPACITY,
+            ArithBackend::Gmp => p <= u64::from(u32::MAX),
+            ArithBackend::Bignum => true,
+        }
+    }
+
+    /// The backends of this build that can test `M(p)`.
+    pub fn candidates(p: u64) -> Vec<ArithBackend> {
+        ArithBackend::ALL
+            .into_iter()
+            .filter(|backend| backend.is_available() && backend.handles(p))
+            .collect()
+    }
+
+    /// The backend a test without one set runs on: `fixed` up to
+    /// [`MAX_FIXED_EXPONENT`], and above it GMP if the build has it.
+    pub fn default_for(p: u64) -> ArithBackend {
+        if p <= MAX_FIXED_EXPONENT {
+            ArithBackend::Fixed
+        } else if cfg!(feature = "gmp") {
+            ArithBackend::Gmp
+        } else {
+            ArithBackend::Bignum
+        }
+    }
+}
+
+impl fmt::Display for ArithBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ArithBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ArithBackend, String> {
+        let backend = ArithBackend::ALL
+            .into_iter()
+            .find(|backend| backend.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown backend {:?}; the backends are bignum, fixed and gmp",
+                    s
+                )
+            })?;
+        match backend.is_available() {
+            true => Ok(backend),
+            false => Err(format!(
+                "this build has no {} backend; it was built without the {} feature",
+                s, s
+            )),
+        }
+    }
+}
+
+/// What `--backend` asks for: one backend for every exponent it takes, or
+/// the fastest for each by a [`Calibration`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendChoice {
+    #[default]
+    Auto,
+    Only(ArithBackend),
+}
+
+impl fmt::Display for BackendChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendChoice::Auto => f.write_str("auto"),
+            BackendChoice::Only(backend) => backend.fmt(f),
+        }
+    }
+}
+
+impl FromStr for BackendChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BackendChoice, String> {
+        match s {
+            "auto" => Ok(BackendChoice::Auto),
+            _ => s.parse().map(BackendChoice::Only),
+        }
+    }
+}
+
+/// The backends that can test the exponents of one size, fastest first,
+/// with the time each took for [`CALIBRATION_ITERATIONS`] squarings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranking {
+    /// The exponent the backends were timed at.
+    pub size: u64,
+    /// Empty if only one backend can test the size, which then needs no
+    /// timing.
+    pub timings: Vec<(ArithBackend, Duration)>,
+    pub backends: Vec<ArithBackend>,
+}
+
+impl Ranking {
+    /// The fastest backend.
+    pub fn first(&self) -> ArithBackend {
+        self.backends[0]
+    }
+
+    /// The next fastest backend, for the second run of a double-check, or
+    /// the fastest if there is no other.
+    pub fn second(&self) -> ArithBackend {
+        self.backends
+            .get(1)
+            .copied()
+            .unwrap_or_else(|| self.first())
+    }
+}
+
+/// The rankings of the backends by size, each timed the first time an
+/// exponent of its size comes up and kept for the rest of the run.
+#[derive(Debug, Default)]
+pub struct Calibration {
+    rankings: Mutex<BTreeMap<u64, Ranking>>,
+}
+
+impl Calibration {
+    pub const fn new() -> Calibration {
+        Calibration {
+            rankings: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The ranking of the backends for `M(p)`, timed by `clock` if its size
+    /// has not come up before, in which case it is also handed to
+    /// `calibrated`. A size is `p` rounded up to a power of two, so the
+    /// timings stand for every exponent up to twice the last; sizes above
+    /// 65536 bits share that size's ranking. Backends that take the same
+    /// time keep the order of [`ArithBackend::default_for`] first, so a
+    /// clock that stands still ranks them as a test without a backend
+    /// would run.
+    pub fn rank(&self, p: u64, clock: &dyn Clock, calibrated: impl FnOnce(&Ranking)) -> Ranking {
+        let backends = ArithBackend::candidates(p);
+        if backends.len() == 1 {
+            return Ranking {
+                size: p,
+                timings: Vec::new(),
+                backends,
+            };
+        }
+        let size = p.max(64).next_power_of_two().min(CALIBRATION_LIMIT);
+        let mut rankings = self.rankings.lock().unwrap();
+        if let Some(ranking) = rankings.get(&size) {
+            return ranking.clone();
+        }
+        let default = ArithBackend::default_for(size);
+        let mut timings: Vec<_> = backends
+            .iter()
+            .map(|&backend| (backend, time(backend, size, clock)))
+            .collect();
+        timings.sort_by_key(|&(backend, time)| (time, backend != default));
+        let ranking = Ranking {
+            size,
+            backends: timings.iter().map(|&(backend, _)| backend).collect(),
+            timings,
+        };
+        calibrated(&ranking);
+        rankings.insert(size, ranking.clone());
+        ranking
+    }
+}
+
+/// The time `backend` takes for [`CALIBRATION_ITERATIONS`] Lucas–Lehmer
+/// iterations modulo `M(p)` from a residue with every byte set.
+fn time(backend: ArithBackend, p: u64, clock: &dyn Clock) -> Duration {
+    fn squarings<A: MersenneArith>(p: u64, clock: &dyn Clock) -> Duration {
+        let modulus = A::new(p);
+        let start = BigUint::from_bytes_le(&vec![0xA5; p.div_ceil(8) as usize]);
+        let mut s = modulus.residue_of(&start);
+        let started = clock.now();
+        for _ in 0..CALIBRATION_ITERATIONS {
+            s = modulus.square_sub2(&s);
+        }
+        std::hint::black_box(&s);
+        clock.now().saturating_sub(started)
+    }
+    match backend {
+        ArithBackend::Bignum => squarings::<MersenneModulus>(p, clock),
+        #[cfg(feature = "gmp")]
+        ArithBackend::Gmp => squarings::<super::gmp::GmpModulus>(p, clock),
+        #[cfg(not(feature = "gmp"))]
+        ArithBackend::Gmp => unreachable!("the gmp backend is not in this build"),
+        ArithBackend::Fixed => match p.div_ceil(64) {
+            0..=2 => squarings::<SmallMersenne<2>>(p, clock),
+            3..=4 => squarings::<SmallMersenne<4>>(p, clock),
+            5..=8 => squarings::<SmallMersenne<8>>(p, clock),
+            9..=16 => squarings::<SmallMersenne<16>>(p, clock),
+            17..=32 => squarings::<SmallMersenne<32>>(p, clock),
+            _ => squarings::<SmallMersenne<64>>(p, clock),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{default_clock, StoppedClock};
+
+    #[test]
+    fn backends_parse_only_if_the_build_has_them() {
+        assert_eq!("fixed".parse(), Ok(ArithBackend::Fixed));
+        assert_eq!("auto".parse(), Ok(BackendChoice::Auto));
+        assert_eq!(
+            "bignum".parse(),
+            Ok(BackendChoice::Only(ArithBackend::Bignum))
+        );
+        assert_eq!("gmp".parse::<ArithBackend>().is_ok(), cfg!(feature = "gmp"));
+        assert!("fast".parse::<BackendChoice>().is_err());
+    }
+
+    #[test]
+    fn calibration_ranks_the_backends_once_per_size() {
+        let calibration = Calibration::new();
+        let mut timed = 0;
+        let ranking = calibration.rank(1279, default_clock(), |_| timed += 1);
+        assert_eq!(ranking.size, 2048);
+        let mut backends = ranking.backends.clone();
+        backends.sort();
+        assert_eq!(backends, ArithBackend::candidates(1279));
+        assert_eq!(ranking.timings.len(), backends.len());
+
+        // 2000 is of the same size, so it is not timed again.
+        assert_eq!(
+            calibration.rank(2000, default_clock(), |_| timed += 1),
+            ranking
+        );
+        assert_eq!(timed, 1);
+
+        // Only bignum takes an exponent this large without GMP.
+        let large = calibration.rank(100_003, default_clock(), |_| timed += 1);
+        assert!(!large.backends.contains(&ArithBackend::Fixed));
+        assert_eq!(large.timings.is_empty(), !cfg!(feature = "gmp"));
+        assert_eq!(large.second(), large.backends[large.backends.len() - 1]);
+    }
+
+    #[test]
+    fn ties_go_to_the_default_backend() {
+        let ranking = Calibration::new().rank(521, &StoppedClock, |_| {});
+        assert_eq!(ranking.first(), ArithBackend::default_for(1024));
+    }
+}
@@ -0,0 +1,372 @@
+//! Arithmetic modulo `M(p)` in a fixed array of 64-bit limbs on the stack,
+//! for exponents up to [`MAX_FIXED_EXPONENT`].
+//!
+//! Below a few thousand bits, a num-bigint squaring spends as long
+//! allocating and sizing its result as multiplying, and a sweep of every
+//! exponent below 20000 is almost all such squarings. [`SmallMersenne`]
+//! keeps a residue in `[u64; LIMBS]` and its square in twice that, squares
+//! by schoolbook multiplication, computing each cross product once, and
+//! folds the square back below `2^p` in place, so an iteration allocates
+//! nothing. `LIMBS` is the capacity; only the `ceil(p / 64)` limbs `p`
+//! needs are worked on, and the rest stay zero.
+//!
+//! Residues are kept canonical, in `0..2^p - 1`, as with
+//! [`MersenneModulus`](super::MersenneModulus), so every result matches it
+//! bit for bit.
+
+use super::MersenneArith;
+use num_bigint::BigUint;
+
+/// The largest exponent the Lucas–Lehmer test hands to a [`SmallMersenne`]
+/// instead of the [`Backend`](super::Backend): up to here a fixed array
+/// squares faster. GMP's hand-written assembly catches up with it at about
+/// a thousand bits, and num-bigint at about four thousand.
+#[cfg(feature = "gmp")]
+pub const MAX_FIXED_EXPONENT: u64 = 16 * 64;
+#[cfg(not(feature = "gmp"))]
+pub const MAX_FIXED_EXPONENT: u64 = 64 * 64;
+
+/// Arithmetic modulo `M(p)` for `p` up to `64 · LIMBS`. See the [module
+/// docs](self).
+#[derive(Debug, Clone)]
+pub struct SmallMersenne<const LIMBS: usize> {
+    p: u64,
+    /// The limbs `p` bits take.
+    used: usize,
+    /// The bits of the top limb in use.
+    top_mask: u64,
+}
+
+impl<const LIMBS: usize> SmallMersenne<LIMBS> {
+    /// # Panics
+    ///
+    /// If `p` is below 2 or above `64 · LIMBS`.
+    pub fn new(p: u64) -> SmallMersenne<LIMBS> {
+        assert!(
+            p >= 2 && p <= 64 * LIMBS as u64,
+            "exponent {} does not fit in {} limbs",
+            p,
+            LIMBS
+        );
+        SmallMersenne {
+            p,
+            used: p.div_ceil(64) as usize,
+            top_mask: u64::MAX >> (64 * p.div_ceil(64) - p),
+        }
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+
+    /// `n mod M(p)`, in limbs.
+    pub fn residue(&self, n: &BigUint) -> [u64; LIMBS] {
+        let modulus = (BigUint::from(1u32) << self.p) - 1u32;
+        let mut limbs = [0; LIMBS];
+        for (limb, digit) in limbs.iter_mut().zip((n % modulus).iter_u64_digits()) {
+            *limb = digit;
+        }
+        limbs
+    }
+
+    pub fn to_biguint(&self, x: &[u64; LIMBS]) -> BigUint {
+        let bytes: Vec<u8> = x.iter().flat_map(|limb| limb.to_le_bytes()).collect();
+        BigUint::from_bytes_le(&bytes)
+    }
+
+    /// One Lucas–Lehmer iteration: `s^2 - 2 mod M(p)`.
+    pub fn square_sub2(&self, s: &[u64; LIMBS]) -> [u64; LIMBS] {
+        self.square_sub_pow2(s, 1)
+    }
+
+    /// `x^2 - 2^k mod M(p)`, for `k < p`.
+    pub fn square_sub_pow2(&self, x: &[u64; LIMBS], k: u64) -> [u64; LIMBS] {
+        let square = self.fold(&self.square(x));
+        self.sub_pow2(square, k)
+    }
+
+    /// `x · 2^k mod M(p)`, for `k < p`: a rotation of the low `p` bits.
+    pub fn mul_pow2(&self, x: &[u64; LIMBS], k: u64) -> [u64; LIMBS] {
+        let mut wide = [[0; LIMBS]; 2];
+        let t = wide.as_flattened_mut();
+        let (words, bits) = ((k / 64) as usize, (k % 64) as u32);
+        for (i, &limb) in x[..self.used].iter().enumerate() {
+            t[i + words] |= limb << bits;
+            if bits > 0 {
+                t[i + words + 1] |= limb >> (64 - bits);
+            }
+        }
+        self.fold(&wide)
+    }
+
+    /// `x^2`, for `x` below `2^(64 · used)`.
+    fn square(&self, x: &[u64; LIMBS]) -> [[u64; LIMBS]; 2] {
+        let x = &x[..self.used];
+        let n = x.len();
+        let mut wide = [[0; LIMBS]; 2];
+        let t = &mut wide.as_flattened_mut()[..2 * n];
+        // The cross products x[i]·x[j] for i < j, each once...
+        for (i, &xi) in x.iter().enumerate() {
+            let xi = u128::from(xi);
+            let mut carry = 0u128;
+            for (limb, &xj) in t[2 * i + 1..i + n].iter_mut().zip(&x[i + 1..]) {
+                let sum = xi * u128::from(xj) + u128::from(*limb) + carry;
+                *limb = sum as u64;
+                carry = sum >> 64;
+            }
+            t[i + n] = carry as u64;
+        }
+        // ...doubled, plus the squares on the diagonal.
+        let mut high_bit = 0;
+        let mut carry = 0u128;
+        for (pair, &xi) in t.chunks_exact_mut(2).zip(x) {
+            let square = u128::from(xi) * u128::from(xi);
+            let doubled = [(pair[0] << 1) | high_bit, (pair[1] << 1) | (pair[0] >> 63)];
+            high_bit = pair[1] >> 63;
+            let low = u128::from(doubled[0]) + (square & u128::from(u64::MAX)) + carry;
+            let high = u128::from(doubled[1]) + (square >> 64) + (low >> 64);
+            pair[0] = low as u64;
+            pair[1] = high as u64;
+            carry = high >> 64;
+        }
+        wide
+    }
+
+    /// `t mod M(p)` for `t < 2^2p`, in the canonical range: the bits from
+    /// `p` up are added onto the ones below, since `2^p ≡ 1`, and a carry
+    /// into bit `p` is folded the same way.
+    fn fold(&self, wide: &[[u64; LIMBS]; 2]) -> [u64; LIMBS] {
+        let t = wide.as_flattened();
+        let n = self.used;
+        let (words, bits) = ((self.p / 64) as usize, (self.p % 64) as u32);
+        let mut sum = [0; LIMBS];
+        let mut carry = false;
+        let mut add = |i: usize, low: u64, high: u64| {
+            let (partial, first) = low.overflowing_add(high);
+            let (total, second) = partial.overflowing_add(u64::from(carry));
+            sum[i] = total;
+            carry = first || second;
+        };
+        if bits == 0 {
+            // The high half starts on a limb.
+            for (i, (&low, &high)) in t[..n].iter().zip(&t[n..2 * n]).enumerate() {
+                add(i, low, high);
+            }
+        } else {
+            // Then p < 64·n, so words + n is still inside t.
+            for i in 0..n - 1 {
+                add(
+                    i,
+                    t[i],
+                    (t[words + i] >> bits) | (t[words + i + 1] << (64 - bits)),
+                );
+            }
+            let high = (t[words + n - 1] >> bits) | (t[words + n] << (64 - bits));
+            add(n - 1, t[n - 1] & self.top_mask, high);
+        }
+        // The sum is below 2^(p+1), so at most bit p is set above the rest.
+        let overflowed = match bits {
+            0 => carry,
+            _ => sum[n - 1] >> bits & 1 == 1,
+        };
+        if overflowed {
+            sum[n - 1] &= self.top_mask;
+            for limb in &mut sum[..n] {
+                let (incremented, wrapped) = limb.overflowing_add(1);
+                *limb = incremented;
+                if !wrapped {
+                    break;
+                }
+            }
+        }
+        // 2^p - 1 is the other form of zero.
+        if sum[n - 1] == self.top_mask && sum[..n - 1].iter().all(|&limb| limb == u64::MAX) {
+            return [0; LIMBS];
+        }
+        sum
+    }
+
+    /// `x - 2^k mod M(p)` for a canonical `x` and `k < p`, canonical.
+    fn sub_pow2(&self, mut x: [u64; LIMBS], k: u64) -> [u64; LIMBS] {
+        let n = self.used;
+        let mut borrow = 1u64 << (k % 64);
+        for limb in &mut x[(k / 64) as usize..n] {
+            let (difference, wrapped) = limb.overflowing_sub(borrow);
+            *limb = difference;
+            borrow = u64::from(wrapped);
+            if borrow == 0 {
+                break;
+            }
+        }
+        if borrow == 1 {
+            // Below 2^k, so 2^p - 1 is added back: within p bits the
+            // wrapped difference is 2^p too much, so one comes off.
+            x[n - 1] &= self.top_mask;
+            for limb in &mut x[..n] {
+                let (decremented, wrapped) = limb.overflowing_sub(1);
+                *limb = decremented;
+                if !wrapped {
+                    break;
+                }
+            }
+        }
+        x
+    }
+}
+
+impl<const LIMBS: usize> MersenneArith for SmallMersenne<LIMBS> {
+    type Residue = [u64; LIMBS];
+
+    fn new(p: u64) -> Self {
+        SmallMersenne::new(p)
+    }
+
+    fn residue_of(&self, n: &BigUint) -> [u64; LIMBS] {
+        self.residue(n)
+    }
+
+    fn to_biguint(&self, x: &[u64; LIMBS]) -> BigUint {
+        SmallMersenne::to_biguint(self, x)
+    }
+
+    /// Through a `BigUint`, so that it takes anything; only milestones need
+    /// it.
+    fn reduce(&self, n: [u64; LIMBS]) -> [u64; LIMBS] {
+        self.residue(&SmallMersenne::to_biguint(self, &n))
+    }
+
+    fn square_sub2(&self, s: &[u64; LIMBS]) -> [u64; LIMBS] {
+        SmallMersenne::square_sub2(self, s)
+    }
+
+    fn square_sub_pow2(&self, x: &[u64; LIMBS], k: u64) -> [u64; LIMBS] {
+        SmallMersenne::square_sub_pow2(self, x, k)
+    }
+
+    fn mul_pow2(&self, x: &[u64; LIMBS], k: u64) -> [u64; LIMBS] {
+        SmallMersenne::mul_pow2(self, x, k)
+    }
+
+    fn is_zero(x: &[u64; LIMBS]) -> bool {
+        x.iter().all(|&limb| limb == 0)
+    }
+
+    fn res64(x: &[u64; LIMBS]) -> u64 {
+        x[0]
+    }
+
+    fn is_canonical(&self, x: &[u64; LIMBS]) -> bool {
+        let n = self.used;
+        let all_ones = x[..n - 1].iter().all(|&limb| limb == u64::MAX);
+        x[n..].iter().all(|&limb| limb == 0)
+            && x[n - 1] & !self.top_mask == 0
+            && !(all_ones && x[n - 1] == self.top_mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::MersenneModulus;
+
+    /// Runs `check` with a `SmallMersenne` of the limbs `p` needs, rounded
+    /// up to a power of two as the Lucas–Lehmer test picks them.
+    fn for_limbs(p: u64, check: impl FnOnce(&dyn Fn(&BigUint, u64) -> [BigUint; 3])) {
+        fn ops<const LIMBS: usize>(p: u64) -> impl Fn(&BigUint, u64) -> [BigUint; 3] {
+            let ctx = SmallMersenne::<LIMBS>::new(p);
+            move |x, k| {
+                let limbs = ctx.residue(x);
+                assert!(ctx.is_canonical(&limbs));
+                [
+                    ctx.square_sub2(&limbs),
+                    ctx.square_sub_pow2(&limbs, k),
+                    ctx.mul_pow2(&limbs, k),
+                ]
+                .map(|result| {
+                    assert!(ctx.is_canonical(&result), "p = {}", p);
+                    ctx.to_biguint(&result)
+                })
+            }
+        }
+        match p.div_ceil(64) {
+            0..=2 => check(&ops::<2>(p)),
+            3..=4 => check(&ops::<4>(p)),
+            5..=8 => check(&ops::<8>(p)),
+            9..=16 => check(&ops::<16>(p)),
+            17..=32 => check(&ops::<32>(p)),
+            _ => check(&ops::<64>(p)),
+        }
+    }
+
+    #[test]
+    fn agrees_with_num_bigint_for_every_exponent() {
+        let mut seed = 0x5EED_u64;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        // Every exponent the largest size the test picks can hold.
+        for p in 2..=64 * 64 {
+            let reference = MersenneModulus::new(p);
+            let m = reference.modulus().clone();
+            let digits: Vec<u64> = (0..p.div_ceil(64)).map(|_| next()).collect();
+            let random = BigUint::from_bytes_le(
+                &digits
+                    .iter()
+                    .flat_map(|d| d.to_le_bytes())
+                    .collect::<Vec<u8>>(),
+            ) % &m;
+            // The edges too: 0, 1, 2^64 - 1, the largest residue, and one
+            // whose square is below 2^k.
+            let mut cases = vec![BigUint::ZERO, BigUint::from(1u32), &m - 1u32, random];
+            if p > 64 {
+                cases.push(BigUint::from(u64::MAX));
+            }
+            for x in cases {
+                let k = next() % p;
+                for_limbs(p, |ops| {
+                    let [sub2, sub_pow2, rotated] = ops(&x, k);
+                    assert_eq!(sub2, reference.square_sub2(&x), "p = {}, x = {}", p, x);
+                    assert_eq!(
+                        sub_pow2,
+                        reference.square_sub_pow2(&x, k),
+                        "p = {}, x = {}, k = {}",
+                        p,
+                        x,
+                        k
+                    );
+                    assert_eq!(
+                        rotated,
+                        reference.canonical(reference.mul_pow2(&x, k)),
+                        "p = {}, x = {}, k = {}",
+                        p,
+                        x,
+                        k
+                    );
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn the_other_zero_and_out_of_range_residues_are_not_canonical() {
+        let ctx = SmallMersenne::<4>::new(127);
+        assert!(!ctx.is_canonical(&[u64::MAX, u64::MAX >> 1, 0, 0]));
+        assert!(!ctx.is_canonical(&[0, 1 << 63, 0, 0]));
+        assert!(!ctx.is_canonical(&[0, 0, 1, 0]));
+        assert!(ctx.is_canonical(&[u64::MAX - 1, u64::MAX >> 1, 0, 0]));
+        assert_eq!(
+            MersenneArith::reduce(&ctx, [u64::MAX, u64::MAX >> 1, 0, 0]),
+            [0; 4]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit")]
+    fn exponents_beyond_the_limbs_are_rejected() {
+        SmallMersenne::<2>::new(129);
+    }
+}
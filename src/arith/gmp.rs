@@ -0,0 +1,153 @@
+//! Arithmetic modulo `M(p)` on GMP integers, enabled by the `gmp` feature.
+//!
+//! GMP switches to FFT multiplication for large operands, which makes each
+//! squaring far cheaper than num-bigint's above a few hundred thousand bits.
+
+use super::MersenneArith;
+use num_bigint::BigUint;
+use rug::integer::Order;
+use rug::{Assign, Integer};
+
+/// The GMP counterpart of [`super::MersenneModulus`].
+#[derive(Debug, Clone)]
+pub struct GmpModulus {
+    p: u32,
+    modulus: Integer,
+}
+
+impl GmpModulus {
+    pub fn new(p: u64) -> GmpModulus {
+        let p = u32::try_from(p).expect("exponent too large for GMP shifts");
+        GmpModulus {
+            p,
+            modulus: (Integer::from(1) << p) - 1u32,
+        }
+    }
+
+    /// [`MersenneModulus::reduce_once`](super::MersenneModulus::reduce_once)
+    /// on GMP integers: one fold of `n < 2^2p` into `0..=2^p - 1`.
+    fn reduce_once(&self, mut n: Integer) -> Integer {
+        let high = Integer::from(&n >> self.p);
+        n.keep_bits_mut(self.p);
+        n += high;
+        if n.get_bit(self.p) {
+            n.set_bit(self.p, false);
+            n += 1u32;
+        }
+        n
+    }
+}
+
+impl MersenneArith for GmpModulus {
+    type Residue = Integer;
+
+    fn new(p: u64) -> Self {
+        GmpModulus::new(p)
+    }
+
+    /// Reduced, since the iterations only take residues.
+    fn residue_of(&self, n: &BigUint) -> Integer {
+        self.reduce(Integer::from_digits(&n.to_u32_digits(), Order::Lsf))
+    }
+
+    fn to_biguint(&self, x: &Integer) -> BigUint {
+        BigUint::new(x.to_digits::<u32>(Order::Lsf))
+    }
+
+    /// As [`MersenneModulus::reduce_full`](super::MersenneModulus::reduce_full)
+    /// does it: folds down to `2^2p`, then one fold and the comparison.
+    fn reduce(&self, mut n: Integer) -> Integer {
+        let mut high = Integer::new();
+        while n.significant_bits() > 2 * self.p {
+            high.assign(&n >> self.p);
+            n.keep_bits_mut(self.p);
+            n += &high;
+        }
+
+        let n = self.reduce_once(n);
+        if n == self.modulus {
+            Integer::new()
+        } else {
+            n
+        }
+    }
+
+    fn square_sub2(&self, s: &Integer) -> Integer {
+        let mut square = self.reduce_once(Integer::from(s.square_ref()));
+        if square < 2 {
+            square += &self.modulus;
+        }
+        square -= 2u32;
+        square
+    }
+
+    fn square_sub_pow2(&self, x: &Integer, k: u64) -> Integer {
+        let mut square = self.reduce_once(Integer::from(x.square_ref()));
+        if u64::from(square.significant_bits()) <= k {
+            square += &self.modulus;
+        }
+        square -= Integer::from(1) << k as u32;
+        square
+    }
+
+    fn mul_pow2(&self, x: &Integer, k: u64) -> Integer {
+        self.reduce(Integer::from(x << k as u32))
+    }
+
+    fn is_zero(x: &Integer) -> bool {
+        *x == 0
+    }
+
+    fn res64(x: &Integer) -> u64 {
+        x.to_u64_wrapping()
+    }
+
+    fn is_canonical(&self, x: &Integer) -> bool {
+        *x >= 0 && x.significant_bits() <= self.p && *x != self.modulus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::MersenneModulus;
+
+    #[test]
+    fn agrees_with_num_bigint() {
+        for p in [7, 61, 89, 107, 127, 521, 607, 1279] {
+            let gmp = GmpModulus::new(p);
+            let bigint = MersenneModulus::new(p);
+            let mut a = Integer::from(4);
+            let mut b = BigUint::from(4u32);
+            for _ in 0..p - 2 {
+                a = gmp.square_sub2(&a);
+                b = bigint.square_sub2(&b);
+                assert_eq!(gmp.to_biguint(&a), b, "M({})", p);
+            }
+        }
+    }
+
+    #[test]
+    fn shifted_iterations_agree_with_num_bigint() {
+        use crate::arith::Shift;
+        let gmp = GmpModulus::new(521);
+        let bigint = MersenneModulus::new(521);
+        let (mut a, mut b) = (Integer::from(4), BigUint::from(4u32));
+        let (mut shift_a, mut shift_b) = (Shift::new(521, 100), Shift::new(521, 100));
+        a = gmp.shifted(&a, shift_a);
+        b = MersenneArith::shifted(&bigint, &b, shift_b);
+        for _ in 0..519 {
+            (a, shift_a) = gmp.square_sub2_shifted(&a, shift_a);
+            (b, shift_b) = bigint.square_sub2_shifted(&b, shift_b);
+            assert_eq!(gmp.to_biguint(&a), b);
+        }
+    }
+
+    #[test]
+    fn converts_both_ways() {
+        let gmp = GmpModulus::new(127);
+        let n = BigUint::parse_bytes(b"123456789abcdef0123456789abcdef", 16).unwrap();
+        assert_eq!(gmp.to_biguint(&gmp.residue_of(&n)), n);
+        assert_eq!(gmp.to_biguint(&Integer::new()), BigUint::default());
+    }
+}
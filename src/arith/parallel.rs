@@ -0,0 +1,244 @@
+//! Squaring and reduction split across threads, so the Lucas–Lehmer test
+//! of one huge exponent can use more than one core.
+//!
+//! A square is split by halves, `(h·B + l)^2 = h^2·B^2 + 2hl·B + l^2`, and
+//! the three partial products are computed in parallel, splitting further
+//! while there are threads to spare. The fold of the reduction modulo
+//! `M(p)` adds the low and high `p` bits limb by limb in parallel chunks,
+//! then passes the carries between chunks. Both are exact, so the results
+//! are bit-identical to the sequential ones.
+
+use num_bigint::BigUint;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::sync::mpsc;
+
+/// Operands below this many bits are multiplied on one thread; splitting
+/// them costs more than it saves.
+const PARALLEL_MIN_BITS: u64 = 1 << 13;
+
+/// The threads one test splits its squarings across.
+///
+/// The work runs on a pool of its own, and the test waits for it on a
+/// channel rather than inside rayon. A test running on another rayon pool
+/// would otherwise pick up that pool's work, such as the next exponent,
+/// while it waits, and stall until that finished too.
+#[derive(Debug)]
+pub(crate) struct Team {
+    pool: ThreadPool,
+    threads: usize,
+}
+
+impl Team {
+    /// A team of `threads` threads, or `None` if they cannot be started.
+    pub(crate) fn new(threads: usize) -> Option<Team> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("square-{}", i))
+            .build()
+            .ok()?;
+        Some(Team { pool, threads })
+    }
+
+    /// `x^2`, folded once modulo `M(p)`: congruent to `x^2` and at most a
+    /// bit longer than `p` bits.
+    pub(crate) fn square_folded(&self, x: &BigUint, p: u64) -> BigUint {
+        self.run(x, move |x, threads| fold(square(x, threads), p, threads))
+    }
+
+    /// `x^2`, unreduced.
+    pub(crate) fn square(&self, x: &BigUint) -> BigUint {
+        self.run(x, square)
+    }
+
+    /// Runs `f(x, threads)` on the team and waits for the result.
+    fn run<F>(&self, x: &BigUint, f: F) -> BigUint
+    where
+        F: FnOnce(&BigUint, usize) -> BigUint + Send + 'static,
+    {
+        let x = x.clone();
+        let threads = self.threads;
+        let (sender, receiver) = mpsc::channel();
+        self.pool.spawn(move || {
+            let _ = sender.send(f(&x, threads));
+        });
+        receiver.recv().expect("squaring thread panicked")
+    }
+}
+
+/// `x^2`, split across up to `threads` threads.
+pub(crate) fn square(x: &BigUint, threads: usize) -> BigUint {
+    if threads < 2 || x.bits() < PARALLEL_MIN_BITS {
+        return x * x;
+    }
+    let split = x.bits() / 2 / 32 * 32;
+    let (high, low) = split_at(x, split);
+    let ((high_square, low_square), cross) = both(
+        threads,
+        |threads| {
+            both(
+                threads,
+                |threads| square(&high, threads),
+                |threads| square(&low, threads),
+            )
+        },
+        |threads| multiply(&high, &low, threads),
+    );
+    (high_square << (2 * split)) + (cross << (split + 1)) + low_square
+}
+
+/// `a * b`, split across up to `threads` threads by halving the longer
+/// operand.
+pub(crate) fn multiply(a: &BigUint, b: &BigUint, threads: usize) -> BigUint {
+    let (a, b) = if a.bits() >= b.bits() { (a, b) } else { (b, a) };
+    if threads < 2 || a.bits() < PARALLEL_MIN_BITS {
+        return a * b;
+    }
+    let split = a.bits() / 2 / 32 * 32;
+    let (high, low) = split_at(a, split);
+    let (high_product, low_product) = both(
+        threads,
+        |threads| multiply(&high, b, threads),
+        |threads| multiply(&low, b, threads),
+    );
+    (high_product << split) + low_product
+}
+
+/// Runs `a` and `b`, in parallel if there are at least two threads, sharing
+/// the threads between them.
+fn both<A, B, RA, RB>(threads: usize, a: A, b: B) -> (RA, RB)
+where
+    A: FnOnce(usize) -> RA + Send,
+    B: FnOnce(usize) -> RB + Send,
+    RA: Send,
+    RB: Send,
+{
+    if threads < 2 {
+        (a(1), b(1))
+    } else {
+        rayon::join(|| a(threads - threads / 2), || b(threads / 2))
+    }
+}
+
+/// `(x >> bits, x mod 2^bits)`, for `bits` a multiple of 32.
+fn split_at(x: &BigUint, bits: u64) -> (BigUint, BigUint) {
+    let digits = x.to_u32_digits();
+    let at = ((bits / 32) as usize).min(digits.len());
+    (
+        BigUint::from_slice(&digits[at..]),
+        BigUint::from_slice(&digits[..at]),
+    )
+}
+
+/// `(n mod 2^p) + (n >> p)`, which is congruent to `n` modulo `M(p)`,
+/// computed in up to `threads` chunks of limbs.
+pub(crate) fn fold(n: BigUint, p: u64, threads: usize) -> BigUint {
+    if n.bits() <= p {
+        return n;
+    }
+    let digits = n.to_u32_digits();
+    let word = (p / 32) as usize;
+    let offset = (p % 32) as u32;
+    let digit = |i: usize| digits.get(i).copied().unwrap_or(0) as u64;
+    let low = |i: usize| match i.cmp(&word) {
+        std::cmp::Ordering::Less => digit(i),
+        std::cmp::Ordering::Equal => digit(i) & ((1u64 << offset) - 1),
+        std::cmp::Ordering::Greater => 0,
+    };
+    let high = |i: usize| ((digit(word + i + 1) << 32 | digit(word + i)) >> offset) & 0xFFFF_FFFF;
+
+    // One digit to spare for the final carry.
+    let len = (word + 1).max(digits.len() - word) + 1;
+    let chunk = len.div_ceil(threads.max(1));
+    let mut sum = vec![0u32; len];
+    let carries: Vec<u64> = sum
+        .par_chunks_mut(chunk)
+        .enumerate()
+        .map(|(c, out)| {
+            let mut carry = 0;
+            for (j, d) in out.iter_mut().enumerate() {
+                let i = c * chunk + j;
+                let s = low(i) + high(i) + carry;
+                *d = s as u32;
+                carry = s >> 32;
+            }
+            carry
+        })
+        .collect();
+
+    let mut carry = 0;
+    for (out, own_carry) in sum.chunks_mut(chunk).zip(carries) {
+        for d in out.iter_mut() {
+            if carry == 0 {
+                break;
+            }
+            let s = *d as u64 + carry;
+            *d = s as u32;
+            carry = s >> 32;
+        }
+        carry += own_carry;
+    }
+    debug_assert_eq!(carry, 0);
+    BigUint::new(sum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::One;
+    use rand::Rng;
+
+    fn random(bits: u64) -> BigUint {
+        let mut bytes = vec![0u8; bits.div_ceil(8) as usize];
+        rand::thread_rng().fill(&mut bytes[..]);
+        BigUint::from_bytes_le(&bytes) >> (bytes.len() as u64 * 8 - bits)
+    }
+
+    #[test]
+    fn squares_and_products_match_the_sequential_ones() {
+        for bits in [100, 8192, 20_000, 100_003] {
+            let x = random(bits);
+            let y = random(bits / 3 + 1);
+            for threads in 1..=5 {
+                assert_eq!(square(&x, threads), &x * &x, "{} bits", bits);
+                assert_eq!(multiply(&x, &y, threads), &x * &y, "{} bits", bits);
+            }
+        }
+        let all_ones = (BigUint::one() << 50_000u32) - 1u32;
+        assert_eq!(square(&all_ones, 4), &all_ones * &all_ones);
+    }
+
+    #[test]
+    fn folds_match_the_sequential_fold() {
+        for p in [31, 32, 33, 9689, 44497] {
+            let modulus = (BigUint::one() << p) - 1u32;
+            let mut cases = vec![random(2 * p), random(p / 2)];
+            // All ones carries through every limb.
+            cases.push(&modulus * &modulus);
+            cases.push((BigUint::one() << (2 * p)) - 1u32);
+            for n in cases {
+                let expected = (&n & &modulus) + (&n >> p);
+                for threads in 1..=5 {
+                    let folded = fold(n.clone(), p, threads);
+                    if n.bits() > p {
+                        assert_eq!(folded, expected, "M({}), {} threads", p, threads);
+                    } else {
+                        assert_eq!(folded, n);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_team_squares_and_folds() {
+        let team = Team::new(3).unwrap();
+        let p = 21701;
+        let modulus = (BigUint::one() << p) - 1u32;
+        let x = random(p) % &modulus;
+        let folded = team.square_folded(&x, p);
+        assert!(folded.bits() <= p + 1);
+        assert_eq!(folded % &modulus, &x * &x % &modulus);
+        assert_eq!(team.square(&x), &x * &x);
+    }
+}
@@ -0,0 +1,405 @@
+//! The audit log written with `--audit-log`, and its verification.
+//!
+//! Each Lucas–Lehmer test appends one JSON line holding the residue before
+//! the first iteration, after every [`milestone_interval`] iterations and
+//! after the last, so anyone can re-derive each stretch between two
+//! milestones on their own and confirm the run without repeating it in
+//! one piece:
+//!
+//! ```text
+//! {"exponent":127,"test":"LL","interval":8,"milestones":[{"iteration":0,"residue":"4"},...,{"iteration":125,"residue":"0"}],"prime":true,"res64":"0000000000000000","previous":"00000000","checksum":"5d1c2a0b"}
+//! ```
+//!
+//! Residues are in hexadecimal and unshifted. A line written by a run
+//! names it after the Res64, as in `"run":"20240501T120000Z-3f9a2c"`; see
+//! [`run`](crate::run). A test that resumed from a
+//! checkpoint starts at the checkpoint's iteration, so the stretch before
+//! it cannot be checked.
+//!
+//! `checksum` is the CRC-32 of the line up to the comma before it, and
+//! `previous` is the checksum of the line before (`00000000` on the
+//! first), so an edited, dropped or reordered line breaks the chain. The
+//! checksums catch accidents and casual edits, not a forger who recomputes
+//! them.
+
+use crate::arith::{Backend, MersenneArith};
+use crate::checkpoint::crc32;
+use crate::report::format_res64;
+use crate::run::RunId;
+use num_bigint::BigUint;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// How many stretches a test is cut into.
+pub const SEGMENTS: u64 = 16;
+
+/// The `previous` of the first line of a log.
+const NO_PREVIOUS: &str = "00000000";
+
+/// The iterations between the milestones of a test of `M(p)`.
+pub fn milestone_interval(p: u64) -> u64 {
+    p.saturating_sub(2).div_ceil(SEGMENTS).max(1)
+}
+
+/// The residue of a test after `iteration` iterations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Milestone {
+    pub iteration: u64,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub residue: BigUint,
+}
+
+fn to_hex<S: Serializer>(residue: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&residue.to_str_radix(16))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    BigUint::parse_bytes(text.as_bytes(), 16)
+        .ok_or_else(|| serde::de::Error::custom("residue is not hexadecimal"))
+}
+
+/// One line of the log: the milestones of a test of `M(exponent)`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub exponent: u64,
+    /// Always `LL`; lines for other tests may be added later.
+    pub test: String,
+    pub interval: u64,
+    pub milestones: Vec<Milestone>,
+    pub prime: bool,
+    pub res64: String,
+    /// The run that wrote the line; lines from before run IDs have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunId>,
+    /// The checksum of the line before.
+    pub previous: String,
+}
+
+impl AuditRecord {
+    /// The record of a test of `M(p)` with `milestones`, the last of which
+    /// is the final residue.
+    pub fn new(p: u64, milestones: Vec<Milestone>) -> AuditRecord {
+        let last = milestones
+            .last()
+            .map(|m| m.residue.clone())
+            .unwrap_or_default();
+        AuditRecord {
+            exponent: p,
+            test: "LL".to_string(),
+            interval: milestone_interval(p),
+            milestones,
+            prime: last == BigUint::default(),
+            res64: format_res64(crate::res64(&last)),
+            run: None,
+            previous: NO_PREVIOUS.to_string(),
+        }
+    }
+
+    /// The line for the record, ending in its checksum.
+    fn line(&self) -> (String, String) {
+        let mut line = serde_json::to_string(self).unwrap();
+        line.pop();
+        let checksum = format!("{:08x}", crc32(line.as_bytes()));
+        line.push_str(&format!(",\"checksum\":\"{}\"}}", checksum));
+        (line, checksum)
+    }
+
+    /// The stretches between consecutive milestones.
+    pub fn segments(&self) -> impl Iterator<Item = (&Milestone, &Milestone)> {
+        self.milestones.iter().zip(self.milestones.iter().skip(1))
+    }
+}
+
+/// An audit log opened for appending.
+#[derive(Debug)]
+pub struct AuditLog {
+    file: File,
+    /// The checksum of the last line.
+    last: String,
+    run: Option<RunId>,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, creating it if needed. A last line whose
+    /// write never finished is cut off, as in the ledger.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<AuditLog> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let complete = complete_length(&mut file)?;
+        if complete < file.metadata()?.len() {
+            file.set_len(complete)?;
+            file.sync_data()?;
+        }
+        let last = last_checksum(&mut file, complete)?;
+        Ok(AuditLog {
+            file,
+            last,
+            run: None,
+        })
+    }
+
+    /// Names `run` on every line from now on.
+    pub fn for_run(self, run: RunId) -> AuditLog {
+        AuditLog {
+            run: Some(run),
+            ..self
+        }
+    }
+
+    /// Appends `record`, chained to the line before, and syncs it to disk.
+    pub fn record(&mut self, record: AuditRecord) -> io::Result<()> {
+        let record = AuditRecord {
+            run: self.run.clone().or(record.run),
+            previous: self.last.clone(),
+            ..record
+        };
+        let (line, checksum) = record.line();
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.file.sync_data()?;
+        self.last = checksum;
+        Ok(())
+    }
+}
+
+/// The length of `file` up to the end of its last complete line.
+fn complete_length(file: &mut File) -> io::Result<u64> {
+    const BLOCK: u64 = 64 * 1024;
+    let mut end = file.metadata()?.len();
+    let mut block = vec![0; BLOCK as usize];
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK);
+        let block = &mut block[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(block)?;
+        if let Some(newline) = block.iter().rposition(|&b| b == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// The checksum that ends the line ending at `end` in `file`.
+fn last_checksum(file: &mut File, end: u64) -> io::Result<String> {
+    if end == 0 {
+        return Ok(NO_PREVIOUS.to_string());
+    }
+    let start = end.saturating_sub(64);
+    let mut tail = vec![0; (end - start) as usize];
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    tail.trim_end()
+        .strip_suffix("\"}")
+        .and_then(|rest| rest.rsplit_once("\"checksum\":\""))
+        .map(|(_, checksum)| checksum.to_string())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the last line is not an audit record",
+            )
+        })
+}
+
+/// Why an audit log does not hold together.
+#[derive(Debug)]
+pub enum AuditError {
+    Io(io::Error),
+    /// Line `line`, counting from 1, is damaged or out of place.
+    Line {
+        line: usize,
+        problem: String,
+    },
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditError::Io(e) => write!(f, "I/O error: {}", e),
+            AuditError::Line { line, problem } => write!(f, "line {}: {}", line, problem),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<io::Error> for AuditError {
+    fn from(e: io::Error) -> Self {
+        AuditError::Io(e)
+    }
+}
+
+/// Reads the log at `path`, checking every checksum and link of the chain
+/// but none of the arithmetic.
+pub fn read_log<P: AsRef<Path>>(path: P) -> Result<Vec<AuditRecord>, AuditError> {
+    let mut records = Vec::new();
+    let mut previous = NO_PREVIOUS.to_string();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        let problem = |problem: &str| AuditError::Line {
+            line: number + 1,
+            problem: problem.to_string(),
+        };
+        let (body, checksum) = line
+            .trim_end()
+            .strip_suffix("\"}")
+            .and_then(|rest| rest.rsplit_once(",\"checksum\":\""))
+            .ok_or_else(|| problem("no checksum"))?;
+        if format!("{:08x}", crc32(body.as_bytes())) != checksum {
+            return Err(problem("checksum mismatch"));
+        }
+        let record: AuditRecord = serde_json::from_str(&format!("{}}}", body))
+            .map_err(|e| problem(&format!("not an audit record: {}", e)))?;
+        if record.previous != previous {
+            return Err(problem(
+                "does not follow the line before; a line was removed, added or moved",
+            ));
+        }
+        previous = checksum.to_string();
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Whether `to` follows from `from` in the Lucas–Lehmer test of `M(p)`,
+/// recomputing every iteration between them. A milestone at iteration 0
+/// must hold the starting value 4.
+pub fn verify_segment(p: u64, from: &Milestone, to: &Milestone) -> bool {
+    if p < 3 || to.iteration < from.iteration || to.iteration > p - 2 {
+        return false;
+    }
+    let modulus = Backend::new(p);
+    let start = modulus.reduce(modulus.residue_of(&from.residue));
+    if from.iteration == 0 && modulus.to_biguint(&start) != BigUint::from(4u32) {
+        return false;
+    }
+    let mut s = start;
+    for _ in from.iteration..to.iteration {
+        s = modulus.square_sub2(&s);
+    }
+    let expected = modulus.reduce(modulus.residue_of(&to.residue));
+    modulus.to_biguint(&modulus.reduce(s)) == modulus.to_biguint(&expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{is_mersenne_prime_interruptible, LlResult, TestControl, TestEvent};
+    use std::sync::atomic::AtomicBool;
+
+    fn milestones(p: u64) -> Vec<Milestone> {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).record_milestones_every(milestone_interval(p));
+        let mut milestones = Vec::new();
+        is_mersenne_prime_interruptible(p, None, control, |event| {
+            if let TestEvent::Milestone { iteration, residue } = event {
+                milestones.push(Milestone {
+                    iteration,
+                    residue: residue.clone(),
+                });
+            }
+        })
+        .unwrap();
+        milestones
+    }
+
+    #[test]
+    fn tests_report_their_milestones() {
+        let found = milestones(127);
+        let iterations: Vec<u64> = found.iter().map(|m| m.iteration).collect();
+        let mut expected: Vec<u64> = (0..125).step_by(8).collect();
+        expected.push(125);
+        assert_eq!(iterations, expected);
+        assert_eq!(found[0].residue, BigUint::from(4u32));
+        let record = AuditRecord::new(127, found);
+        assert!(record.prime);
+        assert!(record
+            .segments()
+            .all(|(from, to)| verify_segment(127, from, to)));
+
+        let record = AuditRecord::new(101, milestones(101));
+        let LlResult::Composite { res64 } = crate::is_mersenne_prime(101) else {
+            panic!("M(101) is composite");
+        };
+        assert!(!record.prime);
+        assert_eq!(record.res64, format_res64(res64));
+    }
+
+    #[test]
+    fn wrong_residues_fail_verification() {
+        let record = AuditRecord::new(127, milestones(127));
+        let mut bad = record.milestones[5].clone();
+        bad.residue += 1u32;
+        assert!(!verify_segment(127, &record.milestones[4], &bad));
+        assert!(!verify_segment(127, &bad, &record.milestones[6]));
+        let start = Milestone {
+            iteration: 0,
+            residue: BigUint::from(10u32),
+        };
+        assert!(!verify_segment(127, &start, &start));
+        assert!(!verify_segment(
+            127,
+            &record.milestones[6],
+            &record.milestones[5]
+        ));
+    }
+
+    #[test]
+    fn the_log_is_a_checksummed_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.record(AuditRecord::new(107, milestones(107))).unwrap();
+        drop(log);
+        let run: RunId = "nightly-7".parse().unwrap();
+        let mut log = AuditLog::open(&path).unwrap().for_run(run.clone());
+        log.record(AuditRecord::new(127, milestones(127))).unwrap();
+
+        let records = read_log(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((&records[0].run, &records[1].run), (&None, &Some(run)));
+        assert_eq!(records[0].previous, NO_PREVIOUS);
+        assert_eq!(records[1].previous, records[0].line().1);
+        assert_eq!(records[1].milestones.len(), 17);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let edited = text.replacen("\"prime\":true", "\"prime\":false", 1);
+        std::fs::write(&path, edited).unwrap();
+        assert!(matches!(
+            read_log(&path),
+            Err(AuditError::Line { line: 1, .. })
+        ));
+        std::fs::write(&path, format!("{}\n", lines[1])).unwrap();
+        match read_log(&path) {
+            Err(AuditError::Line { line: 1, problem }) => {
+                assert!(problem.contains("does not follow"), "{}", problem)
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_torn_last_line_is_cut_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        AuditLog::open(&path)
+            .unwrap()
+            .record(AuditRecord::new(107, milestones(107)))
+            .unwrap();
+        let whole = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, format!("{}{{\"exponent\":12", whole)).unwrap();
+        AuditLog::open(&path)
+            .unwrap()
+            .record(AuditRecord::new(127, milestones(127)))
+            .unwrap();
+        assert_eq!(read_log(&path).unwrap().len(), 2);
+    }
+}
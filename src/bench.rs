@@ -0,0 +1,99 @@
+//! The `bench` subcommand: measures Lucas–Lehmer iteration throughput.
+//!
+//! Every measurement starts from the same full-size residue and runs a fixed
+//! number of iterations, so numbers from different builds of this crate on
+//! the same machine can be compared directly.
+
+use crate::progress::format_duration;
+use mersenne::arith::MersenneModulus;
+use num_bigint::BigUint;
+use rayon::ThreadPool;
+use std::hint::black_box;
+use std::io::{self, Write};
+use std::time::Instant;
+
+/// Representative exponent sizes benchmarked unless others are given.
+const DEFAULT_EXPONENTS: [u64; 3] = [100003, 1000003, 10000019];
+
+/// 1000 iterations, scaled down for large exponents so the default bench
+/// finishes within a minute or so.
+fn default_iterations(p: u64) -> u64 {
+    match p {
+        0..=200_000 => 1000,
+        200_001..=2_000_000 => 100,
+        _ => 10,
+    }
+}
+
+/// Times `iterations` Lucas–Lehmer iterations and returns iterations per
+/// second. The residue starts at `M(p) / 3`, which has every other bit set,
+/// so the first iterations are not cheaper than the rest.
+pub fn measure(modulus: &MersenneModulus, iterations: u64) -> f64 {
+    let mut s: BigUint = modulus.modulus() / 3u32;
+    let started = Instant::now();
+    for _ in 0..iterations {
+        s = modulus.square_sub2(&s);
+    }
+    let seconds = started.elapsed().as_secs_f64();
+    black_box(&s);
+    iterations as f64 / seconds.max(f64::EPSILON)
+}
+
+/// Benchmarks each exponent on one thread, then on every thread of `pool`
+/// at once, and prints a row per exponent as it finishes.
+pub fn run(pool: &ThreadPool, exponents: &[u64], iterations: Option<u64>) {
+    let exponents = if exponents.is_empty() {
+        &DEFAULT_EXPONENTS[..]
+    } else {
+        exponents
+    };
+    let threads = pool.current_num_threads();
+
+    println!(
+        "Lucas-Lehmer iteration throughput, on 1 thread and on {} threads at once:",
+        threads
+    );
+    println!(
+        "{:>10}  {:>10}  {:>15}  {:>10}  {:>15}  {:>10}  {:>8}",
+        "Exponent",
+        "Iterations",
+        "1 thread",
+        "Full test",
+        format!("{} threads", threads),
+        "Full tests",
+        "Scaling"
+    );
+
+    for &p in exponents {
+        let iterations = iterations.unwrap_or_else(|| default_iterations(p));
+        let modulus = MersenneModulus::new(p);
+        let full_test = p.saturating_sub(2) as f64;
+
+        let single = measure(&modulus, iterations);
+        let per_thread = pool.broadcast(|_| measure(&modulus, iterations));
+        let slowest = per_thread.iter().copied().fold(f64::INFINITY, f64::min);
+        let total: f64 = per_thread.iter().sum();
+
+        println!(
+            "{:>10}  {:>10}  {:>10.1} it/s  {:>10}  {:>10.1} it/s  {:>10}  {:>7.0}%",
+            p,
+            iterations,
+            single,
+            format_duration(full_test / single),
+            total,
+            format_duration(full_test / slowest),
+            100.0 * total / (single * threads as f64)
+        );
+        io::stdout().flush().ok();
+    }
+
+    println!("\nFull test: one complete test on an otherwise idle machine.");
+    println!(
+        "Full tests: time per test with {} tests running side by side.",
+        threads
+    );
+    println!(
+        "Scaling: parallel throughput as a share of {} times the single-thread rate.",
+        threads
+    );
+}
@@ -0,0 +1,887 @@
+//! On-disk checkpoints for resuming interrupted Lucas–Lehmer tests.
+//!
+//! A checkpoint records the residue `s` after a given iteration, together
+//! with the exponent and a checksum so damaged or mismatched files are
+//! rejected instead of silently resumed, and the build and machine that
+//! wrote it.
+//!
+//! It is also stamped with what the residue depends on: the test, the
+//! shift of its residue, the arithmetic backend and the version of the
+//! crate; and with the [run](crate::run) that wrote it.
+//! [`Checkpoint::resumable_by`] only lets a test resume a checkpoint of
+//! the same test with the same shift. One written by another backend needs
+//! its residue checked first, and one written by another version, or by
+//! another run, is resumed as it is.
+//!
+//! The file is an 88-byte header followed by the residue, unshifted, and
+//! then in UTF-8 the writer, the [`SystemInfo`](crate::system::SystemInfo)
+//! line, the test, as in [`TestKind::as_str`], the backend, as in
+//! [`BACKEND_NAME`], the version and the run ID. Every number is
+//! little-endian:
+//!
+//! | bytes  | field                                          |
+//! |--------|------------------------------------------------|
+//! | 0..8   | magic, `MERSCKPT`                              |
+//! | 8..12  | format version, [`FORMAT_VERSION`]             |
+//! | 12..16 | CRC-32 of everything from byte 16 to the end   |
+//! | 16..24 | exponent `p`                                   |
+//! | 24..32 | iteration                                      |
+//! | 32..40 | residue length in bytes                        |
+//! | 40..48 | writer length in bytes                         |
+//! | 48..56 | shift of the residue in bits                   |
+//! | 56..64 | test length in bytes                           |
+//! | 64..72 | backend length in bytes                        |
+//! | 72..80 | version length in bytes                        |
+//! | 80..88 | run ID length in bytes                         |
+//! | 88..   | residue, writer, test, backend, version, run   |
+//!
+//! Files of versions 1 to 3 are still read. Version 3 files stop after the
+//! version length and the version, so their run is unknown. Version 2
+//! files stop after the writer length and the writer, and version 1 files
+//! after the residue length and the residue, so their writer is unknown.
+//! Both are of Lucas–Lehmer tests, and their shift, backend and version
+//! are unknown.
+
+use crate::arith::BACKEND_NAME;
+use crate::pacer::Every;
+use crate::report::TestKind;
+use crate::run::RunId;
+use crate::system::SystemInfo;
+use num_bigint::BigUint;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"MERSCKPT";
+const HEADER_LEN: usize = 88;
+/// The header of version 3, which had no run.
+const V3_HEADER_LEN: usize = 80;
+/// The header of version 2, which had no stamps.
+const V2_HEADER_LEN: usize = 48;
+/// The header of version 1, which had no writer either.
+const V1_HEADER_LEN: usize = 40;
+
+/// The tests a checkpoint can be of.
+const TESTS: [TestKind; 4] = [
+    TestKind::LucasLehmer,
+    TestKind::Prp,
+    TestKind::Pepin,
+    TestKind::Llr,
+];
+
+/// The version of the checkpoint format written by [`Checkpoint::to_bytes`];
+/// it and versions 1 to 3 are read.
+pub const FORMAT_VERSION: u32 = 4;
+
+/// The saved state of a Lucas–Lehmer test after `iteration` squarings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub p: u64,
+    pub iteration: u64,
+    pub residue: BigUint,
+    /// The build and machine that saved it, as the [`SystemInfo`] line;
+    /// `None` for version 1 files.
+    pub written_by: Option<String>,
+    /// The test it is a checkpoint of.
+    pub test: TestKind,
+    /// The shift of the test's residue in bits, as in
+    /// [`Shift`](crate::arith::Shift); `residue` itself is unshifted.
+    /// `None` before version 3.
+    pub shift: Option<u64>,
+    /// The arithmetic backend that computed it, as [`BACKEND_NAME`];
+    /// `None` before version 3.
+    pub backend: Option<String>,
+    /// The version of the crate that wrote it; `None` before version 3.
+    pub version: Option<String>,
+    /// The run that wrote it; `None` before version 4, and for tests run
+    /// outside one.
+    pub run: Option<RunId>,
+}
+
+/// How a test can resume a checkpoint that [`Checkpoint::resumable_by`]
+/// accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resumable {
+    /// Whether its residue has to pass a Jacobi check first, because
+    /// another arithmetic backend computed it, or one not recorded.
+    pub verify: bool,
+    /// The version of the crate that wrote it, if it is not this one.
+    pub other_version: Option<String>,
+}
+
+/// Why a checkpoint file could not be used.
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    NotACheckpoint,
+    UnsupportedVersion(u32),
+    Truncated,
+    ChecksumMismatch,
+    WrongExponent {
+        expected: u64,
+        found: u64,
+    },
+    InvalidState(String),
+    /// A sound checkpoint of another test or shift, which resuming would
+    /// turn into a wrong result.
+    Incompatible(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "I/O error: {}", e),
+            CheckpointError::NotACheckpoint => write!(
+                f,
+                "not a checkpoint file, or one written by an older version"
+            ),
+            CheckpointError::UnsupportedVersion(version) => write!(
+                f,
+                "checkpoint format version {} is not supported (expected {})",
+                version, FORMAT_VERSION
+            ),
+            CheckpointError::Truncated => write!(f, "file is truncated"),
+            CheckpointError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            CheckpointError::WrongExponent { expected, found } => write!(
+                f,
+                "checkpoint is for p = {}, expected p = {}",
+                found, expected
+            ),
+            CheckpointError::InvalidState(msg) => write!(f, "{}", msg),
+            CheckpointError::Incompatible(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl Checkpoint {
+    /// The checkpoint of an unshifted Lucas–Lehmer test of `p` at
+    /// `iteration`, written by this build on this machine.
+    pub fn new(p: u64, iteration: u64, residue: BigUint) -> Checkpoint {
+        Checkpoint {
+            p,
+            iteration,
+            residue,
+            written_by: Some(SystemInfo::current().to_string()),
+            test: TestKind::LucasLehmer,
+            shift: Some(0),
+            backend: Some(BACKEND_NAME.to_string()),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            run: None,
+        }
+    }
+
+    /// The same checkpoint, written by `run`.
+    pub fn in_run(self, run: Option<RunId>) -> Checkpoint {
+        Checkpoint { run, ..self }
+    }
+
+    /// The same checkpoint, of a test whose residue is shifted by `bits`.
+    pub fn with_shift(self, bits: u64) -> Checkpoint {
+        Checkpoint {
+            shift: Some(bits),
+            ..self
+        }
+    }
+
+    /// Whether a `test` of the checkpoint's exponent with a residue shifted
+    /// by `shift` bits can resume it, and how; or why not, as
+    /// [`CheckpointError::Incompatible`]. A checkpoint that does not record
+    /// its shift can only be resumed without one.
+    pub fn resumable_by(&self, test: TestKind, shift: u64) -> Result<Resumable, CheckpointError> {
+        if self.test != test {
+            return Err(CheckpointError::Incompatible(format!(
+                "it is of a {} test, not {} one",
+                self.test,
+                article(test)
+            )));
+        }
+        match self.shift {
+            Some(bits) if bits != shift => {
+                return Err(CheckpointError::Incompatible(format!(
+                    "its residue is shifted by {} bits, not {}",
+                    bits, shift
+                )))
+            }
+            None if shift != 0 => {
+                return Err(CheckpointError::Incompatible(format!(
+                    "it does not record its shift, so it cannot be resumed with a shift of {} bits",
+                    shift
+                )))
+            }
+            _ => {}
+        }
+        let version = env!("CARGO_PKG_VERSION");
+        Ok(Resumable {
+            verify: self.backend.as_deref() != Some(BACKEND_NAME),
+            other_version: self.version.clone().filter(|other| other != version),
+        })
+    }
+
+    /// Serializes the checkpoint in the format described in the module
+    /// documentation.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let residue = self.residue.to_bytes_le();
+        let text = |field: &Option<String>| field.as_deref().unwrap_or("").as_bytes().to_vec();
+        let tail = [
+            text(&self.written_by),
+            self.test.as_str().as_bytes().to_vec(),
+            text(&self.backend),
+            text(&self.version),
+            text(&self.run.clone().map(String::from)),
+        ];
+        let tail_len: usize = tail.iter().map(Vec::len).sum();
+        let mut out = Vec::with_capacity(HEADER_LEN + residue.len() + tail_len);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&self.p.to_le_bytes());
+        out.extend_from_slice(&self.iteration.to_le_bytes());
+        out.extend_from_slice(&(residue.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(tail[0].len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.shift.unwrap_or(0).to_le_bytes());
+        for field in &tail[1..] {
+            out.extend_from_slice(&(field.len() as u64).to_le_bytes());
+        }
+        out.extend_from_slice(&residue);
+        for field in &tail {
+            out.extend_from_slice(field);
+        }
+        let crc = crc32(&out[16..]);
+        out[12..16].copy_from_slice(&crc.to_le_bytes());
+        out
+    }
+
+    /// Parses and validates a checkpoint for exponent `p`.
+    pub fn from_bytes(bytes: &[u8], p: u64) -> Result<Checkpoint, CheckpointError> {
+        let checkpoint = Checkpoint::parse(bytes)?;
+        if checkpoint.p != p {
+            return Err(CheckpointError::WrongExponent {
+                expected: p,
+                found: checkpoint.p,
+            });
+        }
+        Ok(checkpoint)
+    }
+
+    /// Parses and validates a checkpoint for whatever exponent it records.
+    pub fn parse(bytes: &[u8]) -> Result<Checkpoint, CheckpointError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC[..] {
+            return Err(CheckpointError::NotACheckpoint);
+        }
+        if bytes.len() < V1_HEADER_LEN {
+            return Err(CheckpointError::Truncated);
+        }
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let read_u32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let version = read_u32(8);
+        let header = match version {
+            1 => V1_HEADER_LEN,
+            2 => V2_HEADER_LEN,
+            3 => V3_HEADER_LEN,
+            FORMAT_VERSION => HEADER_LEN,
+            _ => return Err(CheckpointError::UnsupportedVersion(version)),
+        };
+        if bytes.len() < header {
+            return Err(CheckpointError::Truncated);
+        }
+        let stored_checksum = read_u32(12);
+        let p = read_u64(16);
+        let iteration = read_u64(24);
+        // The residue, then the writer and the stamps, as far as the
+        // version has them.
+        let mut lengths = vec![read_u64(32)];
+        if version >= 2 {
+            lengths.push(read_u64(40));
+        }
+        if version >= 3 {
+            lengths.extend([read_u64(56), read_u64(64), read_u64(72)]);
+        }
+        if version >= 4 {
+            lengths.push(read_u64(80));
+        }
+
+        let stored = (bytes.len() - header) as u64;
+        let expected = lengths
+            .iter()
+            .fold(0u64, |sum, &length| sum.saturating_add(length));
+        if stored < expected {
+            return Err(CheckpointError::Truncated);
+        }
+        if stored > expected {
+            return Err(CheckpointError::InvalidState(format!(
+                "{} stray bytes at the end of the file",
+                stored - expected
+            )));
+        }
+        if crc32(&bytes[16..]) != stored_checksum {
+            return Err(CheckpointError::ChecksumMismatch);
+        }
+        if iteration > p.saturating_sub(2) {
+            return Err(CheckpointError::InvalidState(format!(
+                "iteration {} is past the end of the test",
+                iteration
+            )));
+        }
+        let mut fields = Vec::with_capacity(lengths.len());
+        let mut rest = &bytes[header..];
+        for &length in &lengths {
+            let (field, after) = rest.split_at(length as usize);
+            fields.push(field);
+            rest = after;
+        }
+        let text =
+            |at: usize, name: &str| match fields.get(at).map(|field| std::str::from_utf8(field)) {
+                None | Some(Ok("")) => Ok(None),
+                Some(Ok(text)) => Ok(Some(text.to_string())),
+                Some(Err(_)) => Err(CheckpointError::InvalidState(format!(
+                    "the {} is not UTF-8",
+                    name
+                ))),
+            };
+        let written_by = text(1, "writer")?;
+        let test = match text(2, "test")? {
+            _ if version < 3 => TestKind::LucasLehmer,
+            name => {
+                let name = name.unwrap_or_default();
+                TESTS
+                    .into_iter()
+                    .find(|test| test.as_str() == name)
+                    .ok_or_else(|| {
+                        CheckpointError::InvalidState(format!("unknown test {:?}", name))
+                    })?
+            }
+        };
+        let backend = text(3, "backend")?;
+        let version_text = text(4, "version")?;
+        let run = text(5, "run ID")?
+            .map(|run| run.parse().map_err(CheckpointError::InvalidState))
+            .transpose()?;
+        let residue = fields[0];
+
+        let residue = BigUint::from_bytes_le(residue);
+        if residue.bits() > p {
+            return Err(CheckpointError::InvalidState(
+                "residue is not reduced modulo 2^p - 1".to_string(),
+            ));
+        }
+
+        Ok(Checkpoint {
+            p,
+            iteration,
+            residue,
+            written_by,
+            test,
+            shift: (version >= 3).then(|| read_u64(48)),
+            backend,
+            version: version_text,
+            run,
+        })
+    }
+
+    /// The format version of the checkpoint file `bytes`, if it is one.
+    pub fn format_version(bytes: &[u8]) -> Option<u32> {
+        let version = bytes.get(8..12)?;
+        (bytes[..8] == MAGIC[..]).then(|| u32::from_le_bytes(version.try_into().unwrap()))
+    }
+}
+
+/// A directory of `M<p>.ckpt` files, written every `interval` iterations
+/// or about every `period` of a test's time.
+#[derive(Debug, Clone)]
+pub struct CheckpointStore {
+    dir: PathBuf,
+    interval: u64,
+    period: Option<Duration>,
+    run: Option<RunId>,
+}
+
+impl CheckpointStore {
+    /// Creates the store, creating `dir` if it does not exist yet.
+    pub fn new<P: AsRef<Path>>(dir: P, interval: u64) -> io::Result<CheckpointStore> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(CheckpointStore {
+            dir: dir.as_ref().to_path_buf(),
+            interval: interval.max(1),
+            period: None,
+            run: None,
+        })
+    }
+
+    /// Writes checkpoints about every `period` of a test's active time
+    /// instead of every `interval` iterations, paced as in
+    /// [`pacer`](crate::pacer).
+    pub fn every(self, period: Duration) -> CheckpointStore {
+        CheckpointStore {
+            period: Some(period),
+            ..self
+        }
+    }
+
+    /// Stamps the checkpoints the tests write with `run`.
+    pub fn for_run(self, run: RunId) -> CheckpointStore {
+        CheckpointStore {
+            run: Some(run),
+            ..self
+        }
+    }
+
+    /// The run the checkpoints are stamped with, if any.
+    pub fn run(&self) -> Option<&RunId> {
+        self.run.as_ref()
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn period(&self) -> Option<Duration> {
+        self.period
+    }
+
+    /// When a test that starts at `now` writes its checkpoints.
+    pub(crate) fn schedule(&self, now: Duration) -> Every {
+        Every::new(self.interval, self.period, now)
+    }
+
+    /// The checkpoint file used for exponent `p`.
+    pub fn path(&self, p: u64) -> PathBuf {
+        self.dir.join(format!("M{}.ckpt", p))
+    }
+
+    /// The exponents with a checkpoint file in the store, in no particular
+    /// order.
+    pub fn exponents(&self) -> io::Result<Vec<u64>> {
+        let mut exponents = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let p = name.to_str().and_then(|name| {
+                name.strip_prefix('M')?
+                    .strip_suffix(".ckpt")?
+                    .parse::<u64>()
+                    .ok()
+            });
+            exponents.extend(p);
+        }
+        Ok(exponents)
+    }
+
+    /// Loads the checkpoint for `p`, returning `Ok(None)` if there is none.
+    pub fn load(&self, p: u64) -> Result<Option<Checkpoint>, CheckpointError> {
+        match fs::read(self.path(p)) {
+            Ok(bytes) => Checkpoint::from_bytes(&bytes, p).map(Some),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Saves `checkpoint`, replacing any earlier one for its exponent.
+    ///
+    /// The checkpoint is written to a temporary file that is then renamed
+    /// over the old one, so a test stopped or killed in the middle of a save
+    /// leaves the previous checkpoint in place rather than a torn one.
+    pub fn save(&self, checkpoint: &Checkpoint) -> io::Result<()> {
+        let path = self.path(checkpoint.p);
+        let temporary = path.with_extension("ckpt.tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(&checkpoint.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)
+    }
+
+    /// Deletes the checkpoint for `p`; a missing file is not an error.
+    pub fn remove(&self, p: u64) -> io::Result<()> {
+        match fs::remove_file(self.path(p)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `test` with its indefinite article, as in "an LL".
+fn article(test: TestKind) -> String {
+    match test {
+        TestKind::LucasLehmer | TestKind::Llr => format!("an {}", test),
+        TestKind::Prp | TestKind::Pepin => format!("a {}", test),
+    }
+}
+
+/// The CRC-32 of `bytes`, with the IEEE polynomial used by zip and PNG.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !crc32_update(!0, bytes)
+}
+
+/// Feeds `bytes` to a CRC-32 computed piece by piece, which starts from
+/// `!0` and is inverted at the end, as [`crc32`] does.
+pub(crate) fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Checkpoint {
+        Checkpoint {
+            p: 127,
+            iteration: 50,
+            residue: BigUint::parse_bytes(b"123456789abcdef0123456789", 16).unwrap(),
+            written_by: Some("Mersenne 0.1.0 on 4 cores".to_string()),
+            test: TestKind::LucasLehmer,
+            shift: Some(17),
+            backend: Some("num-bigint".to_string()),
+            version: Some("0.1.0".to_string()),
+            run: "20240501T120000Z-3f9a2c".parse().ok(),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let checkpoint = sample();
+        let parsed = Checkpoint::from_bytes(&checkpoint.to_bytes(), 127).unwrap();
+        assert_eq!(parsed, checkpoint);
+    }
+
+    #[test]
+    fn rejects_corrupted_residue() {
+        let mut bytes = sample().to_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0x01;
+        assert!(matches!(
+            Checkpoint::from_bytes(&bytes, 127),
+            Err(CheckpointError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let bytes = sample().to_bytes();
+        assert!(matches!(
+            Checkpoint::from_bytes(&bytes[..10], 127),
+            Err(CheckpointError::Truncated)
+        ));
+        assert!(matches!(
+            Checkpoint::from_bytes(&bytes[..bytes.len() - 1], 127),
+            Err(CheckpointError::Truncated)
+        ));
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(matches!(
+            Checkpoint::from_bytes(&longer, 127),
+            Err(CheckpointError::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_every_single_byte_corruption() {
+        let bytes = sample().to_bytes();
+        for at in 0..bytes.len() {
+            for flip in [0x01, 0x80] {
+                let mut corrupted = bytes.clone();
+                corrupted[at] ^= flip;
+                assert!(
+                    Checkpoint::from_bytes(&corrupted, 127).is_err(),
+                    "byte {} ^ {:#x}",
+                    at,
+                    flip
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        assert!(matches!(
+            Checkpoint::from_bytes(b"", 127),
+            Err(CheckpointError::NotACheckpoint)
+        ));
+        assert!(matches!(
+            Checkpoint::from_bytes(b"exponent=127 iteration=50", 127),
+            Err(CheckpointError::NotACheckpoint)
+        ));
+        let mut bytes = sample().to_bytes();
+        bytes[8] = 5;
+        assert!(matches!(
+            Checkpoint::from_bytes(&bytes, 127),
+            Err(CheckpointError::UnsupportedVersion(5))
+        ));
+    }
+
+    #[test]
+    fn header_holds_the_documented_fields() {
+        let bytes = sample().to_bytes();
+        assert_eq!(&bytes[..8], b"MERSCKPT");
+        assert_eq!(bytes[8..12], 4u32.to_le_bytes());
+        assert_eq!(bytes[16..24], 127u64.to_le_bytes());
+        assert_eq!(bytes[24..32], 50u64.to_le_bytes());
+        assert_eq!(bytes[32..40], 13u64.to_le_bytes());
+        assert_eq!(bytes[40..48], 25u64.to_le_bytes());
+        assert_eq!(bytes[48..56], 17u64.to_le_bytes());
+        assert_eq!(bytes[56..64], 2u64.to_le_bytes());
+        assert_eq!(bytes[64..72], 10u64.to_le_bytes());
+        assert_eq!(bytes[72..80], 5u64.to_le_bytes());
+        assert_eq!(bytes[80..88], 23u64.to_le_bytes());
+        assert_eq!(
+            &bytes[88 + 13..],
+            b"Mersenne 0.1.0 on 4 coresLLnum-bigint0.1.020240501T120000Z-3f9a2c"
+        );
+        assert_eq!(Checkpoint::format_version(&bytes), Some(4));
+        assert_eq!(Checkpoint::format_version(b"exponent=127"), None);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    /// A file of format `version`, 1, 2 or 3, with the fields of
+    /// [`sample`].
+    fn old_file(version: u32) -> Vec<u8> {
+        if version == 3 {
+            let mut bytes = sample().in_run(None).to_bytes();
+            bytes.drain(80..88);
+            bytes[8..12].copy_from_slice(&3u32.to_le_bytes());
+            let crc = crc32(&bytes[16..]);
+            bytes[12..16].copy_from_slice(&crc.to_le_bytes());
+            return bytes;
+        }
+        let residue = sample().residue.to_bytes_le();
+        let writer = sample().written_by.unwrap();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        for field in [127, 50, residue.len() as u64] {
+            bytes.extend_from_slice(&u64::to_le_bytes(field));
+        }
+        if version == 2 {
+            bytes.extend_from_slice(&(writer.len() as u64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&residue);
+        if version == 2 {
+            bytes.extend_from_slice(writer.as_bytes());
+        }
+        let crc = crc32(&bytes[16..]);
+        bytes[12..16].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn reads_older_files_as_unstamped_lucas_lehmer_checkpoints() {
+        let bytes = old_file(3);
+        assert_eq!(
+            Checkpoint::from_bytes(&bytes, 127).unwrap(),
+            sample().in_run(None)
+        );
+        assert_eq!(Checkpoint::format_version(&bytes), Some(3));
+        let unstamped = Checkpoint {
+            run: None,
+            shift: None,
+            backend: None,
+            version: None,
+            ..sample()
+        };
+        let bytes = old_file(2);
+        assert_eq!(Checkpoint::from_bytes(&bytes, 127).unwrap(), unstamped);
+        assert_eq!(Checkpoint::format_version(&bytes), Some(2));
+        let bytes = old_file(1);
+        assert_eq!(
+            Checkpoint::from_bytes(&bytes, 127).unwrap(),
+            Checkpoint {
+                written_by: None,
+                ..unstamped
+            }
+        );
+        assert_eq!(Checkpoint::format_version(&bytes), Some(1));
+    }
+
+    #[test]
+    fn stamps_round_trip_and_unknown_tests_are_rejected() {
+        for test in TESTS {
+            let checkpoint = Checkpoint { test, ..sample() };
+            let parsed = Checkpoint::from_bytes(&checkpoint.to_bytes(), 127).unwrap();
+            assert_eq!(parsed, checkpoint);
+        }
+        let mut bytes = sample().to_bytes();
+        let at = 88 + 13 + 25;
+        bytes[at..at + 2].copy_from_slice(b"XY");
+        let crc = crc32(&bytes[16..]);
+        bytes[12..16].copy_from_slice(&crc.to_le_bytes());
+        assert!(matches!(
+            Checkpoint::from_bytes(&bytes, 127),
+            Err(CheckpointError::InvalidState(message)) if message == "unknown test \"XY\""
+        ));
+    }
+
+    /// How an LL test with `shift` can resume `checkpoint`, or why not.
+    fn resume(checkpoint: &Checkpoint, shift: u64) -> Result<Resumable, String> {
+        checkpoint
+            .resumable_by(TestKind::LucasLehmer, shift)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn only_the_same_test_and_shift_can_resume() {
+        let this = Checkpoint::new(127, 50, BigUint::from(3u32)).with_shift(17);
+        let same = Resumable {
+            verify: false,
+            other_version: None,
+        };
+        assert_eq!(resume(&this, 17), Ok(same.clone()));
+
+        let prp = Checkpoint {
+            test: TestKind::Prp,
+            ..this.clone()
+        };
+        let message = "it is of a PRP test, not an LL one";
+        assert_eq!(resume(&prp, 17), Err(message.to_string()));
+        // The test is checked first.
+        assert_eq!(resume(&prp, 5), Err(message.to_string()));
+        assert_eq!(
+            resume(&this, 5),
+            Err("its residue is shifted by 17 bits, not 5".to_string())
+        );
+        assert_eq!(
+            resume(&this.clone().with_shift(0), 17),
+            Err("its residue is shifted by 0 bits, not 17".to_string())
+        );
+
+        // Without a recorded shift only an unshifted test can resume it.
+        let unknown = Checkpoint {
+            shift: None,
+            ..this.clone()
+        };
+        assert_eq!(resume(&unknown, 0), Ok(same));
+        assert_eq!(
+            resume(&unknown, 17),
+            Err(
+                "it does not record its shift, so it cannot be resumed with a shift of 17 bits"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn other_backends_need_verifying_and_other_versions_a_notice() {
+        let this = Checkpoint::new(127, 50, BigUint::from(3u32));
+        let other_backend = Checkpoint {
+            backend: Some("Elsewhere".to_string()),
+            ..this.clone()
+        };
+        let unknown_backend = Checkpoint {
+            backend: None,
+            ..this.clone()
+        };
+        for checkpoint in [&other_backend, &unknown_backend] {
+            assert_eq!(
+                resume(checkpoint, 0),
+                Ok(Resumable {
+                    verify: true,
+                    other_version: None,
+                })
+            );
+        }
+
+        let older = Checkpoint {
+            version: Some("0.0.1".to_string()),
+            ..this.clone()
+        };
+        assert_eq!(
+            resume(&older, 0),
+            Ok(Resumable {
+                verify: false,
+                other_version: Some("0.0.1".to_string()),
+            })
+        );
+        let both = Checkpoint {
+            version: Some("0.0.1".to_string()),
+            ..other_backend
+        };
+        assert_eq!(
+            resume(&both, 0),
+            Ok(Resumable {
+                verify: true,
+                other_version: Some("0.0.1".to_string()),
+            })
+        );
+        // An unrecorded version is no notice.
+        let unversioned = Checkpoint {
+            version: None,
+            ..this
+        };
+        assert_eq!(resume(&unversioned, 0).unwrap().other_version, None);
+    }
+
+    #[test]
+    fn new_checkpoints_name_this_machine() {
+        let checkpoint = Checkpoint::new(127, 50, BigUint::from(3u32));
+        let written_by = SystemInfo::current().to_string();
+        assert_eq!(checkpoint.written_by.as_deref(), Some(written_by.as_str()));
+        let parsed = Checkpoint::from_bytes(&checkpoint.to_bytes(), 127).unwrap();
+        assert_eq!(parsed, checkpoint);
+        assert_eq!(checkpoint.backend.as_deref(), Some(BACKEND_NAME));
+        assert_eq!(
+            checkpoint.version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+    }
+
+    #[test]
+    fn rejects_checkpoint_for_other_exponent() {
+        assert!(matches!(
+            Checkpoint::from_bytes(&sample().to_bytes(), 89),
+            Err(CheckpointError::WrongExponent {
+                expected: 89,
+                found: 127
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_iteration_past_end() {
+        let checkpoint = Checkpoint {
+            iteration: 126,
+            ..sample()
+        };
+        assert!(matches!(
+            Checkpoint::from_bytes(&checkpoint.to_bytes(), 127),
+            Err(CheckpointError::InvalidState(_))
+        ));
+    }
+
+    #[test]
+    fn store_saves_loads_and_removes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path().join("ckpt"), 10).unwrap();
+        assert!(store.load(127).unwrap().is_none());
+
+        store.save(&sample()).unwrap();
+        assert!(store.path(127).ends_with("M127.ckpt"));
+        assert_eq!(store.load(127).unwrap(), Some(sample()));
+        let later = Checkpoint {
+            iteration: 100,
+            ..sample()
+        };
+        store.save(&later).unwrap();
+        assert_eq!(store.load(127).unwrap(), Some(later));
+        assert_eq!(fs::read_dir(dir.path().join("ckpt")).unwrap().count(), 1);
+
+        fs::write(dir.path().join("ckpt").join("notes.txt"), "").unwrap();
+        assert_eq!(store.exponents().unwrap(), [127]);
+
+        store.remove(127).unwrap();
+        assert!(store.load(127).unwrap().is_none());
+        store.remove(127).unwrap();
+        assert!(store.exponents().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+//! Splitting a run across machines with `--chunk i/n`.
+//!
+//! Chunk `i` of `n` takes every `n`-th candidate, starting from the `i`-th,
+//! so each machine gets a similar mix of small and large exponents instead
+//! of a contiguous slab. Every machine must be given the same range and the
+//! same filtering options for the chunks to fit together.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Chunk `index` of `count`, counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunk {
+    index: u64,
+    count: u64,
+}
+
+impl Chunk {
+    /// Chunk `index` of `count`, if `1 <= index <= count`.
+    pub fn new(index: u64, count: u64) -> Option<Chunk> {
+        (1..=count)
+            .contains(&index)
+            .then_some(Chunk { index, count })
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The candidates of this chunk: those whose position in `candidates`,
+    /// counting from 0, is `index - 1` modulo `count`.
+    pub fn select<I>(self, candidates: I) -> impl Iterator<Item = u64>
+    where
+        I: Iterator<Item = u64>,
+    {
+        candidates
+            .enumerate()
+            .filter(move |(position, _)| self.takes(*position as u64))
+            .map(|(_, p)| p)
+    }
+
+    /// Whether the candidate at `position`, counting from 0, is in this
+    /// chunk.
+    pub fn takes(&self, position: u64) -> bool {
+        position % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Chunk {
+    type Err = String;
+
+    /// Parses `i/n`, such as `2/4`.
+    fn from_str(s: &str) -> Result<Chunk, String> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected <i>/<n>, such as 2/4, not {:?}", s))?;
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u64>()
+                .map_err(|e| format!("{:?}: {}", n, e))
+        };
+        let (index, count) = (parse(index)?, parse(count)?);
+        Chunk::new(index, count)
+            .ok_or_else(|| format!("chunk {} of {} does not exist", index, count))
+    }
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sieve;
+    use std::collections::HashSet;
+
+    #[test]
+    fn parses_and_prints() {
+        assert_eq!("2/4".parse(), Ok(Chunk::new(2, 4).unwrap()));
+        assert_eq!(Chunk::new(2, 4).unwrap().to_string(), "2/4");
+        assert_eq!("1/1".parse::<Chunk>().unwrap().count(), 1);
+        for bad in ["0/4", "5/4", "1/0", "2", "a/4", "2/4/8", ""] {
+            assert!(bad.parse::<Chunk>().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn takes_every_nth_candidate() {
+        let chunk = Chunk::new(2, 3).unwrap();
+        assert_eq!(
+            chunk.select(sieve::primes(2, 50)).collect::<Vec<_>>(),
+            vec![3, 11, 19, 31, 43]
+        );
+    }
+
+    #[test]
+    fn chunks_cover_the_candidates_exactly_once() {
+        let all: Vec<u64> = sieve::primes(1_000, 20_000).collect();
+        for count in [1, 2, 3, 4, 7, 1000, 5000] {
+            let mut union = HashSet::new();
+            let mut total = 0;
+            for index in 1..=count {
+                let chunk = Chunk::new(index, count).unwrap();
+                for p in chunk.select(all.iter().copied()) {
+                    assert!(union.insert(p), "M({}) in two chunks of {}", p, count);
+                    total += 1;
+                }
+            }
+            assert_eq!(total, all.len());
+            assert_eq!(union, all.iter().copied().collect::<HashSet<_>>());
+        }
+    }
+
+    #[test]
+    fn chunk_sizes_differ_by_at_most_one() {
+        let all: Vec<u64> = sieve::primes(2, 10_000).collect();
+        let sizes: Vec<usize> = (1..=4)
+            .map(|index| {
+                Chunk::new(index, 4)
+                    .unwrap()
+                    .select(all.iter().copied())
+                    .count()
+            })
+            .collect();
+        assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1);
+    }
+}
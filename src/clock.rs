@@ -0,0 +1,162 @@
+//! Where the tests take the time from.
+//!
+//! A test times itself for [`Progress::elapsed`](crate::Progress::elapsed)
+//! and checks its [`TestControl::stop_at`](crate::TestControl::stop_at)
+//! deadline against the [`Clock`] in its
+//! [`TestControl`](crate::TestControl). `std::time::Instant` panics on
+//! `wasm32-unknown-unknown`, so there the default clock stands still, and
+//! callers with a clock of their own, such as the browser's, hand it in with
+//! [`TestControl::with_clock`](crate::TestControl::with_clock).
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A source of the time.
+pub trait Clock: fmt::Debug + Sync {
+    /// The time since a fixed moment of the clock's choosing. It never goes
+    /// backwards.
+    fn now(&self) -> Duration;
+}
+
+/// `std::time::Instant`, counted from the first time any test reads it.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        use std::sync::OnceLock;
+        use std::time::Instant;
+
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed()
+    }
+}
+
+/// A clock that always reads zero, for platforms without one: tests then
+/// report no elapsed time and never reach a deadline in the future.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StoppedClock;
+
+impl Clock for StoppedClock {
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// The clock tests use unless given another: [`SystemClock`], or
+/// [`StoppedClock`] where there is none.
+pub fn default_clock() -> &'static dyn Clock {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    return &SystemClock;
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    return &StoppedClock;
+}
+
+/// The CPU time the calling thread has used, where the platform gives it.
+pub fn thread_cpu_time() -> Option<Duration> {
+    #[cfg(all(unix, feature = "native"))]
+    {
+        let mut time = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: clock_gettime only writes the timespec it is given.
+        if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } == 0 {
+            return Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32));
+        }
+    }
+    None
+}
+
+/// Adds up the compute time of a test, which
+/// [`TestControl::time_compute_with`](crate::TestControl::time_compute_with)
+/// hands it to: the time the test's thread spent running its iterations,
+/// without the time it waited for a core while other threads had them, and
+/// without pauses and checkpoint writes.
+///
+/// It is the CPU time of the test's thread by [`thread_cpu_time`], or where
+/// there is none the time by the test's clock, which still leaves out the
+/// pauses and checkpoint writes. The threads a squaring is split across
+/// with [`TestControl::split_across`](crate::TestControl::split_across)
+/// are not counted.
+#[derive(Debug, Default)]
+pub struct ComputeTimer {
+    state: Mutex<TimerState>,
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    /// How many loops are timing: a test that hands its work to another
+    /// loop is timed once.
+    running: u32,
+    /// When the current stretch began, by [`ComputeTimer::now`].
+    since: Duration,
+    total: Duration,
+}
+
+impl ComputeTimer {
+    pub fn new() -> ComputeTimer {
+        ComputeTimer::default()
+    }
+
+    /// The compute time up to the end of the test, or of its last stretch
+    /// before a pause or checkpoint write.
+    pub fn total(&self) -> Duration {
+        self.state.lock().unwrap().total
+    }
+
+    fn now(clock: &dyn Clock) -> Duration {
+        thread_cpu_time().unwrap_or_else(|| clock.now())
+    }
+
+    /// Starts timing on this thread, until [`ComputeTimer::stop`].
+    pub(crate) fn start(&self, clock: &dyn Clock) {
+        let mut state = self.state.lock().unwrap();
+        state.running += 1;
+        if state.running == 1 {
+            state.since = ComputeTimer::now(clock);
+        }
+    }
+
+    pub(crate) fn stop(&self, clock: &dyn Clock) {
+        let mut state = self.state.lock().unwrap();
+        state.running = state.running.saturating_sub(1);
+        if state.running == 0 {
+            let stretch = ComputeTimer::now(clock).saturating_sub(state.since);
+            state.total += stretch;
+        }
+    }
+
+    /// Runs `f` without counting its time, as for a checkpoint write.
+    pub(crate) fn exclude<T>(&self, clock: &dyn Clock, f: impl FnOnce() -> T) -> T {
+        let running = {
+            let mut state = self.state.lock().unwrap();
+            if state.running > 0 {
+                let stretch = ComputeTimer::now(clock).saturating_sub(state.since);
+                state.total += stretch;
+            }
+            state.running
+        };
+        let result = f();
+        if running > 0 {
+            self.state.lock().unwrap().since = ComputeTimer::now(clock);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_system_clock_moves_forwards() {
+        let first = SystemClock.now();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(SystemClock.now() >= first + Duration::from_millis(5));
+        assert_eq!(StoppedClock.now(), Duration::ZERO);
+    }
+}
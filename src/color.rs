@@ -0,0 +1,111 @@
+//! Colors on the terminal, chosen with `--color`.
+//!
+//! Text is colored by wrapping it in a [`Paint`] for the stream it goes
+//! to, which adds the escape codes only if that stream is a terminal and
+//! `--color` allows it. Format strings stay free of escape codes, so output
+//! redirected to a file or a pipe, `--json` lines and the results file are
+//! byte for byte the same whatever the setting.
+
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The `--color` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color terminals, unless `NO_COLOR` is set or `TERM` is `dumb`.
+    Auto,
+    /// Color terminals whatever the environment says.
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColorChoice, String> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("unknown color setting {:?}", s)),
+        }
+    }
+}
+
+static STDOUT: AtomicBool = AtomicBool::new(false);
+static STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Decides, once at start-up, whether stdout and stderr get colors.
+pub fn init(choice: ColorChoice) {
+    let wanted = match choice {
+        ColorChoice::Never => false,
+        ColorChoice::Always => true,
+        ColorChoice::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+        }
+    };
+    STDOUT.store(wanted && io::stdout().is_terminal(), Ordering::Relaxed);
+    STDERR.store(wanted && io::stderr().is_terminal(), Ordering::Relaxed);
+}
+
+/// What a piece of text is, which decides its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// A prime was found: bold green.
+    Found,
+    /// A composite, which is most of the output of a search: dim.
+    Composite,
+    /// Yellow.
+    Warning,
+    /// Red.
+    Error,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Found => "1;32",
+            Style::Composite => "2",
+            Style::Warning => "33",
+            Style::Error => "31",
+        }
+    }
+}
+
+/// `text` in `style`, if its stream is colored.
+pub struct Paint<T> {
+    style: Style,
+    text: T,
+    enabled: bool,
+}
+
+impl<T: fmt::Display> fmt::Display for Paint<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.enabled {
+            write!(f, "\x1b[{}m{}\x1b[0m", self.style.code(), self.text)
+        } else {
+            self.text.fmt(f)
+        }
+    }
+}
+
+/// `text` in `style` for printing on stdout.
+pub fn stdout<T: fmt::Display>(style: Style, text: T) -> Paint<T> {
+    Paint {
+        style,
+        text,
+        enabled: STDOUT.load(Ordering::Relaxed),
+    }
+}
+
+/// `text` in `style` for printing on stderr.
+pub fn stderr<T: fmt::Display>(style: Style, text: T) -> Paint<T> {
+    Paint {
+        style,
+        text,
+        enabled: STDERR.load(Ordering::Relaxed),
+    }
+}
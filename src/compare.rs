@@ -0,0 +1,214 @@
+//! Comparing two runs by the Res64 milestones recorded with `--milestones`,
+//! for `compare`.
+//!
+//! Each run is read from a results file or from `--json` output, one
+//! report per line; lines that are neither, such as headers and the
+//! summary, are skipped, and of several lines for one number the last
+//! counts. Two runs of a number are compared at the iterations both have a
+//! milestone at, in order, so runs with different `--milestones` counts
+//! can still be compared where they line up; the first that differs is
+//! where the runs diverged.
+
+use crate::report::{Form, Res64Milestone, TestReport};
+use crate::results::ResultLine;
+use std::collections::BTreeMap;
+
+/// What one run found for a number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub res64: Option<String>,
+    pub milestones: Vec<Res64Milestone>,
+}
+
+/// The runs in a results file or `--json` output, by number.
+pub type Runs = BTreeMap<(Form, u64), Run>;
+
+/// How two runs of a number compare.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    /// Every milestone in common, and the final Res64, match.
+    Identical { milestones: usize },
+    /// The runs first differ at `iteration`, milestone `index` (from 1) of
+    /// the `milestones` they have in common.
+    Diverged {
+        iteration: u64,
+        index: usize,
+        milestones: usize,
+    },
+    /// The milestones match but the final Res64s do not.
+    FinalDiffers { milestones: usize },
+    /// The runs have no milestone iteration in common.
+    NoMilestones,
+}
+
+impl Comparison {
+    /// Whether the runs are known to disagree.
+    pub fn is_divergent(self) -> bool {
+        matches!(
+            self,
+            Comparison::Diverged { .. } | Comparison::FinalDiffers { .. }
+        )
+    }
+}
+
+/// Reads the runs in `text`, the contents of a results file or of `--json`
+/// output.
+pub fn read_runs(text: &str) -> Runs {
+    let mut runs = Runs::new();
+    for line in text.lines() {
+        let run = if line.starts_with('{') {
+            serde_json::from_str::<TestReport>(line).ok().map(|report| {
+                (
+                    (report.form, report.exponent),
+                    Run {
+                        res64: report.res64,
+                        milestones: report.milestones,
+                    },
+                )
+            })
+        } else {
+            parse_results_line(line)
+        };
+        if let Some((number, run)) = run {
+            runs.insert(number, run);
+        }
+    }
+    runs
+}
+
+/// The number and run of a results line, or `None` for a header or a line
+/// that is not a result.
+fn parse_results_line(line: &str) -> Option<((Form, u64), Run)> {
+    let line = ResultLine::parse(line)?;
+    let milestones = match line.field("milestones") {
+        Some(list) => list
+            .split(',')
+            .map(|milestone| {
+                let (iteration, res64) = milestone.split_once(':')?;
+                Some(Res64Milestone {
+                    iteration: iteration.parse().ok()?,
+                    res64: res64.to_string(),
+                })
+            })
+            .collect::<Option<_>>()?,
+        None => Vec::new(),
+    };
+    let run = Run {
+        res64: line.field("res64").map(str::to_string),
+        milestones,
+    };
+    Some((line.number(), run))
+}
+
+/// Compares two runs of the same number.
+pub fn compare(a: &Run, b: &Run) -> Comparison {
+    let of_b: BTreeMap<u64, &str> = b
+        .milestones
+        .iter()
+        .map(|milestone| (milestone.iteration, milestone.res64.as_str()))
+        .collect();
+    let common: Vec<(u64, bool)> = a
+        .milestones
+        .iter()
+        .filter_map(|milestone| {
+            let other = of_b.get(&milestone.iteration)?;
+            Some((
+                milestone.iteration,
+                milestone.res64.eq_ignore_ascii_case(other),
+            ))
+        })
+        .collect();
+    let milestones = common.len();
+    if milestones == 0 {
+        return Comparison::NoMilestones;
+    }
+    if let Some(index) = common.iter().position(|&(_, matches)| !matches) {
+        return Comparison::Diverged {
+            iteration: common[index].0,
+            index: index + 1,
+            milestones,
+        };
+    }
+    match (&a.res64, &b.res64) {
+        (Some(x), Some(y)) if !x.eq_ignore_ascii_case(y) => Comparison::FinalDiffers { milestones },
+        _ => Comparison::Identical { milestones },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(res64: &str, milestones: &[(u64, &str)]) -> Run {
+        Run {
+            res64: Some(res64.to_string()),
+            milestones: milestones
+                .iter()
+                .map(|&(iteration, res64)| Res64Milestone {
+                    iteration,
+                    res64: res64.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reads_results_lines_and_json_reports() {
+        let text = "\
+# 2024-05-01T12:00:00Z version=0.1.0 cpu=Some CPU
+2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=000000001B57CB0B milestones=14:00000000000000AA,27:000000001B57CB0B seconds=0.001
+2024-05-01T12:00:00Z exponent=31 form=riesel k=15 result=prime test=LLR seconds=0.000
+{\"exponent\":37,\"form\":\"wagstaff\",\"prime\":false,\"test\":\"PRP\",\"seconds\":0.0,\"res64\":\"0000000000000001\",\"factor\":null,\"factor_stage\":null,\"shift\":null,\"double_check\":null,\"confirmation\":null,\"confirm_res64\":null,\"timed_out_at\":null,\"failed_at\":null,\"errors\":0,\"milestones\":[{\"iteration\":37,\"res64\":\"0000000000000001\"}]}
+{\"summary\":{\"tested\":3}}
+";
+        let runs = read_runs(text);
+        assert_eq!(runs.len(), 3);
+        assert_eq!(
+            runs[&(Form::Mersenne, 29)],
+            run(
+                "000000001B57CB0B",
+                &[(14, "00000000000000AA"), (27, "000000001B57CB0B")]
+            )
+        );
+        assert_eq!(
+            runs[&(Form::Riesel { k: 15 }, 31)],
+            Run {
+                res64: None,
+                milestones: Vec::new(),
+            }
+        );
+        assert_eq!(
+            runs[&(Form::Wagstaff, 37)],
+            run("0000000000000001", &[(37, "0000000000000001")])
+        );
+    }
+
+    #[test]
+    fn finds_the_first_milestone_in_common_that_differs() {
+        let a = run("0000000000000003", &[(10, "A"), (20, "B"), (30, "C")]);
+        assert_eq!(compare(&a, &a), Comparison::Identical { milestones: 3 });
+        let b = run("0000000000000003", &[(10, "A"), (20, "X"), (30, "Y")]);
+        assert_eq!(
+            compare(&a, &b),
+            Comparison::Diverged {
+                iteration: 20,
+                index: 2,
+                milestones: 3
+            }
+        );
+        let coarser = run("0000000000000003", &[(15, "Z"), (30, "c")]);
+        assert_eq!(
+            compare(&a, &coarser),
+            Comparison::Identical { milestones: 1 }
+        );
+        let other_final = run("0000000000000004", &[(10, "A")]);
+        assert_eq!(
+            compare(&a, &other_final),
+            Comparison::FinalDiffers { milestones: 1 }
+        );
+        assert_eq!(
+            compare(&a, &run("0000000000000003", &[])),
+            Comparison::NoMilestones
+        );
+    }
+}
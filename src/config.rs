@@ -0,0 +1,375 @@
+//! Default options from a config file and the environment.
+//!
+//! The options of `search`, `test`, `fermat` and `work` can also be set in
+//! `mersenne.toml` in the working directory, or the file named with
+//! `--config`, and in `MERSENNE_*` environment variables such as
+//! `MERSENNE_THREADS=4`. Keys are the long option names with `_` for `-`:
+//!
+//! ```toml
+//! threads = 4
+//! checkpoint_dir = "checkpoints"
+//! results = "results.txt"
+//! status_interval = 300
+//! skip_known = true
+//! ```
+//!
+//! An option on the command line wins over the environment, which wins
+//! over the file, which wins over the built-in default. Values from the
+//! file and the environment are turned back into command-line arguments
+//! and parsed with the rest, so they are checked exactly like ones typed
+//! in. Flags can only be switched on this way: `skip_known = false` is the
+//! same as leaving the key out. `verbose` takes a level, the number of
+//! times `-v` is given, so `verbose = 2` is `-vv`; `true` is level 1.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::clap::ArgMatches;
+
+/// The file read when `--config` is not given, if it exists.
+pub const DEFAULT_FILE: &str = "mersenne.toml";
+
+/// How the value of a key is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Flag,
+    /// A flag that can be given more than once, such as `-vv`.
+    Count,
+    Number,
+    Text,
+}
+
+/// A value of a key, as it becomes a command-line argument.
+enum Value {
+    On,
+    Off,
+    Times(u8),
+    Argument(OsString),
+}
+
+/// The types config keys can have.
+trait Setting {
+    const KIND: Kind;
+    fn value(&self) -> Value;
+}
+
+impl Setting for bool {
+    const KIND: Kind = Kind::Flag;
+    fn value(&self) -> Value {
+        if *self {
+            Value::On
+        } else {
+            Value::Off
+        }
+    }
+}
+
+/// The level of a flag that can be given more than once, such as
+/// `verbose = 2` for `-vv`. `true` and `false` are levels 1 and 0.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(from = "Level")]
+struct Count(u8);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Level {
+    Times(u8),
+    Flag(bool),
+}
+
+impl From<Level> for Count {
+    fn from(level: Level) -> Count {
+        match level {
+            Level::Times(times) => Count(times),
+            Level::Flag(on) => Count(u8::from(on)),
+        }
+    }
+}
+
+impl Setting for Count {
+    const KIND: Kind = Kind::Count;
+    fn value(&self) -> Value {
+        match self.0 {
+            0 => Value::Off,
+            times => Value::Times(times),
+        }
+    }
+}
+
+macro_rules! numbers {
+    ($($type:ty),*) => {$(
+        impl Setting for $type {
+            const KIND: Kind = Kind::Number;
+            fn value(&self) -> Value {
+                Value::Argument(self.to_string().into())
+            }
+        }
+    )*};
+}
+numbers!(u32, u64, usize, f64);
+
+impl Setting for String {
+    const KIND: Kind = Kind::Text;
+    fn value(&self) -> Value {
+        Value::Argument(self.into())
+    }
+}
+
+impl Setting for PathBuf {
+    const KIND: Kind = Kind::Text;
+    fn value(&self) -> Value {
+        Value::Argument(self.into())
+    }
+}
+
+macro_rules! config {
+    ($($key:ident: $type:ty,)*) => {
+        /// The contents of a config file: one optional key per option.
+        #[derive(Debug, Default, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        pub struct Config {
+            $($key: Option<$type>,)*
+        }
+
+        /// Every key, with its kind.
+        const KEYS: &[(&str, Kind)] = &[$((stringify!($key), <$type as Setting>::KIND),)*];
+
+        impl Config {
+            /// The value of `key` in the file, if it is set there.
+            fn value(&self, key: &str) -> Option<Value> {
+                match key {
+                    $(stringify!($key) => self.$key.as_ref().map(Setting::value),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+// Every option except --config and --print-config themselves, and the ones
+// that make a run do something else entirely.
+config! {
+    results: PathBuf,
+    retest: bool,
+    ledger: PathBuf,
+    db: PathBuf,
+    csv: PathBuf,
+    csv_append: PathBuf,
+    audit_log: PathBuf,
+    milestones: u64,
+    proof_dir: PathBuf,
+    proof_power: u32,
+    save_residue: PathBuf,
+    res2048: bool,
+    primenet_results: PathBuf,
+    primenet_user: String,
+    primenet_computer: String,
+    skip_known: bool,
+    exponent_filter: String,
+    known_factors: PathBuf,
+    verbose: Count,
+    quiet: bool,
+    progress_every: String,
+    rate_window: usize,
+    slowdown_warning: u32,
+    log_level: String,
+    log_file: PathBuf,
+    events: PathBuf,
+    color: String,
+    raw_numbers: bool,
+    digit_separator: String,
+    form: String,
+    k: u64,
+    prp: bool,
+    double_check: bool,
+    shift: String,
+    backend: String,
+    confirm: bool,
+    max_test_seconds: u64,
+    time_limit: String,
+    checkpoint_dir: PathBuf,
+    force_restart: bool,
+    jacobi_interval: u64,
+    on_error: String,
+    retries: u32,
+    checkpoint_every: String,
+    checkpoint_interval: u64,
+    tf_depth: u32,
+    p1_b1: u64,
+    p1_b2: u64,
+    print_number: bool,
+    save_number: PathBuf,
+    perfect: bool,
+    status_interval: u64,
+    status_file: PathBuf,
+    status_file_interval: u64,
+    no_summary: bool,
+    summary_top: usize,
+    json: bool,
+    order: String,
+    ordered: bool,
+    threads: usize,
+    ll_threads: usize,
+    tf_threads: usize,
+    threads_per_test: usize,
+    nice: bool,
+    cpu_limit: u32,
+    pause_when_busy: f64,
+    pause_file: PathBuf,
+    control: PathBuf,
+    control_socket: PathBuf,
+    pin_threads: bool,
+    cpus: String,
+    max_mem: u64,
+    force: bool,
+    chunk: String,
+    run_id: String,
+    sieve_cache: PathBuf,
+    sieve_cache_max: u64,
+    notify_cmd: String,
+    notify_url: String,
+}
+
+/// Where the value of an option came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    CommandLine,
+    Environment(String),
+    File(PathBuf),
+}
+
+/// The command line with the config file and environment folded in.
+pub struct Merged {
+    pub args: Vec<OsString>,
+    /// Where each key that is set came from; the rest are defaults.
+    pub sources: HashMap<&'static str, Source>,
+}
+
+impl Merged {
+    /// The keys taken from the environment or the file, with where from,
+    /// such as `threads (MERSENNE_THREADS), results (mersenne.toml)`.
+    pub fn describe(&self) -> String {
+        let keys: Vec<String> = KEYS
+            .iter()
+            .filter_map(|&(key, _)| match self.sources.get(key)? {
+                Source::CommandLine => None,
+                Source::Environment(variable) => Some(format!("{} ({})", key, variable)),
+                Source::File(path) => Some(format!("{} ({})", key, path.display())),
+            })
+            .collect();
+        keys.join(", ")
+    }
+}
+
+/// Reads the config file `path`, or [`DEFAULT_FILE`] if there is one, and
+/// the environment, and appends their values to `args` for every option
+/// that `matches`, the options given on the command line, does not have.
+pub fn merge(
+    mut args: Vec<OsString>,
+    matches: &ArgMatches,
+    path: Option<&Path>,
+) -> Result<Merged, String> {
+    let default = Path::new(DEFAULT_FILE);
+    let path = path.or_else(|| default.exists().then_some(default));
+    let config = match path {
+        Some(path) => load(path)?,
+        None => Config::default(),
+    };
+
+    let mut sources = HashMap::new();
+    for &(key, kind) in KEYS {
+        let option = key.replace('_', "-");
+        if matches.occurrences_of(&option) > 0 {
+            sources.insert(key, Source::CommandLine);
+            continue;
+        }
+        let variable = format!("MERSENNE_{}", key.to_uppercase());
+        let (value, source) = match std::env::var_os(&variable) {
+            Some(text) => (
+                environment(&variable, kind, text)?,
+                Source::Environment(variable),
+            ),
+            None => match (config.value(key), path) {
+                (Some(value), Some(path)) => (value, Source::File(path.to_path_buf())),
+                _ => continue,
+            },
+        };
+        match value {
+            Value::Off => continue,
+            Value::On => args.push(format!("--{}", option).into()),
+            Value::Times(times) => {
+                args.extend((0..times).map(|_| format!("--{}", option).into()));
+            }
+            Value::Argument(argument) => {
+                args.push(format!("--{}", option).into());
+                args.push(argument);
+            }
+        }
+        sources.insert(key, source);
+    }
+    Ok(Merged { args, sources })
+}
+
+/// Parses the config file at `path`.
+fn load(path: &Path) -> Result<Config, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read the config file {}: {}", path.display(), e))?;
+    toml::from_str(&text).map_err(|e| {
+        format!(
+            "the config file {} is not valid: {}",
+            path.display(),
+            e.to_string().trim_end()
+        )
+    })
+}
+
+/// The value of the environment variable `variable`.
+fn environment(variable: &str, kind: Kind, text: OsString) -> Result<Value, String> {
+    match (kind, text.to_str()) {
+        (Kind::Flag | Kind::Count, Some("1" | "true" | "yes")) => Ok(Value::On),
+        (Kind::Flag | Kind::Count, Some("" | "0" | "false" | "no")) => Ok(Value::Off),
+        (Kind::Count, level) => match level.and_then(|level| level.parse().ok()) {
+            Some(times) => Ok(Value::Times(times)),
+            None => Err(format!(
+                "{} should be a level such as 2, or true or false, not {:?}.",
+                variable, text
+            )),
+        },
+        (Kind::Flag, _) => Err(format!(
+            "{} should be true or false, not {:?}.",
+            variable, text
+        )),
+        (Kind::Number | Kind::Text, _) => Ok(Value::Argument(text)),
+    }
+}
+
+/// Prints every key with its effective value in `matches`, the parsed
+/// merged command line, and where the value came from, for
+/// `--print-config`.
+pub fn print(matches: &ArgMatches, sources: &HashMap<&'static str, Source>) {
+    for &(key, kind) in KEYS {
+        let option = key.replace('_', "-");
+        let value = match kind {
+            Kind::Flag => Some(matches.is_present(&option).to_string()),
+            Kind::Count => Some(matches.occurrences_of(&option).to_string()),
+            Kind::Number => matches
+                .value_of_os(&option)
+                .map(|text| text.to_string_lossy().into_owned()),
+            Kind::Text => matches
+                .value_of_os(&option)
+                .map(|text| toml::Value::String(text.to_string_lossy().into_owned()).to_string()),
+        };
+        let source = match sources.get(key) {
+            Some(Source::CommandLine) => "command line".to_string(),
+            Some(Source::Environment(variable)) => variable.clone(),
+            Some(Source::File(path)) => path.display().to_string(),
+            None => "default".to_string(),
+        };
+        match value {
+            Some(value) => println!("{:<40} # {}", format!("{} = {}", key, value), source),
+            None => println!("# {} is not set", key),
+        }
+    }
+}
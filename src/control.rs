@@ -0,0 +1,219 @@
+//! Commands to a running run from outside it, for servers whose
+//! supervisors make signals awkward: `--control` names a file and
+//! `--control-socket` a Unix domain socket, which both take single words.
+//!
+//! - `pause` holds the tests, as `--pause-file` does, until `resume`;
+//! - `stop` lets the running tests checkpoint and stop, as Ctrl-C does;
+//! - `status` writes a heartbeat line and the `--status-file` at once.
+//!
+//! The file is looked at every few seconds, and emptied once its commands
+//! are taken, so `echo pause > control` works again and again:
+//!
+//! ```text
+//! $ echo pause > control
+//! $ echo resume > control
+//! ```
+//!
+//! A client of the socket writes a command on a line and reads back `ok`,
+//! or the reason it was not taken:
+//!
+//! ```text
+//! $ echo stop | nc -U control.sock
+//! ok
+//! ```
+
+use chrono::Local;
+use log::{info, warn};
+use mersenne::search::CancellationToken;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+/// How often the control file is read.
+const FILE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the socket is looked at for a connection.
+#[cfg(unix)]
+const SOCKET_INTERVAL: Duration = Duration::from_millis(200);
+
+/// A command of the control file or socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Pause,
+    Resume,
+    Stop,
+    Status,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Command, String> {
+        match s {
+            "pause" => Ok(Command::Pause),
+            "resume" => Ok(Command::Resume),
+            "stop" => Ok(Command::Stop),
+            "status" => Ok(Command::Status),
+            _ => Err(format!(
+                "unknown command {:?}; the commands are pause, resume, stop and status",
+                s
+            )),
+        }
+    }
+}
+
+/// Where the commands come from, and what they act on.
+pub struct Control<'a> {
+    pub file: Option<&'a Path>,
+    pub socket: Option<&'a Path>,
+    /// Raised by `pause` and lowered by `resume`; the pause watch holds the
+    /// tests while it is up.
+    pub held: &'a AtomicBool,
+    pub token: &'a CancellationToken,
+    /// Each gets a message on `status`.
+    pub refresh: Vec<Sender<()>>,
+}
+
+impl Control<'_> {
+    /// Carries out `command`, which came from `source`.
+    fn carry_out(&self, command: Command, source: &str) {
+        match command {
+            Command::Pause => self.held.store(true, Ordering::SeqCst),
+            Command::Resume => self.held.store(false, Ordering::SeqCst),
+            Command::Stop => {
+                if !self.token.cancel() {
+                    info!(
+                        "[{}] Stopping after saving running tests: `stop` came through {}.",
+                        now(),
+                        source
+                    );
+                }
+            }
+            Command::Status => {
+                info!("[{}] Status asked for through {}.", now(), source);
+                for refresh in &self.refresh {
+                    let _ = refresh.send(());
+                }
+            }
+        }
+    }
+
+    /// Takes commands until `done` gets a message or is dropped.
+    pub fn watch(self, done: Receiver<()>) {
+        #[cfg(unix)]
+        let listener = self.socket.and_then(|path| match listen(path) {
+            Ok(listener) => Some(listener),
+            Err(e) => {
+                warn!(
+                    "cannot listen on {}: {}; --control-socket does nothing.",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        });
+        #[cfg(not(unix))]
+        if self.socket.is_some() {
+            warn!("--control-socket needs Unix domain sockets, which this platform lacks.");
+        }
+        #[cfg(unix)]
+        let interval = match listener {
+            Some(_) => SOCKET_INTERVAL,
+            None => FILE_INTERVAL,
+        };
+        #[cfg(not(unix))]
+        let interval = FILE_INTERVAL;
+
+        let mut last_file = std::time::Instant::now() - FILE_INTERVAL;
+        loop {
+            if let Some(path) = self.file.filter(|_| last_file.elapsed() >= FILE_INTERVAL) {
+                last_file = std::time::Instant::now();
+                self.read_file(path);
+            }
+            #[cfg(unix)]
+            if let (Some(listener), Some(path)) = (&listener, self.socket) {
+                self.accept(listener, path);
+            }
+            if let Err(RecvTimeoutError::Timeout) = done.recv_timeout(interval) {
+                continue;
+            }
+            break;
+        }
+        #[cfg(unix)]
+        if let (Some(_), Some(path)) = (listener, self.socket) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Takes the commands in the control file, if any, and empties it.
+    fn read_file(&self, path: &Path) {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                warn!("cannot read the control file {}: {}", path.display(), e);
+                return;
+            }
+        };
+        if text.trim().is_empty() {
+            return;
+        }
+        if let Err(e) = fs::write(path, "") {
+            warn!("cannot empty the control file {}: {}", path.display(), e);
+        }
+        let source = path.display().to_string();
+        for word in text.split_whitespace() {
+            match word.parse() {
+                Ok(command) => self.carry_out(command, &source),
+                Err(e) => warn!("ignoring the control file {}: {}.", source, e),
+            }
+        }
+    }
+
+    /// Serves the clients waiting on the socket, a command each.
+    #[cfg(unix)]
+    fn accept(&self, listener: &std::os::unix::net::UnixListener, path: &Path) {
+        use std::io::{BufRead, BufReader, Write};
+
+        while let Ok((mut stream, _)) = listener.accept() {
+            let _ = stream.set_nonblocking(false);
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let mut line = String::new();
+            if BufReader::new(&stream).read_line(&mut line).is_err() {
+                continue;
+            }
+            let reply = match line.trim().parse() {
+                Ok(command) => {
+                    self.carry_out(command, &path.display().to_string());
+                    "ok".to_string()
+                }
+                Err(e) => e,
+            };
+            let _ = writeln!(stream, "{}", reply);
+        }
+    }
+}
+
+/// Listens on `path`, in place of the socket of an earlier run that was
+/// left behind.
+#[cfg(unix)]
+fn listen(path: &Path) -> io::Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixListener;
+
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// The time a change of state is logged with, to the second.
+pub fn now() -> String {
+    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
@@ -0,0 +1,408 @@
+//! The bookkeeping behind `serve`: which exponents of a range have been
+//! handed out to workers, which are done, and the protocol workers use to
+//! ask for work and report back.
+//!
+//! # Protocol
+//!
+//! Plain HTTP/1.1 with JSON bodies, one request per connection. There are
+//! three requests, so a client in any language is a few lines of code.
+//!
+//! `GET /work` leases the next exponent to the caller:
+//!
+//! ```text
+//! {"exponent":100003,"lease_seconds":86400,"finished":false}
+//! ```
+//!
+//! When every remaining exponent is leased to someone else, `exponent` is
+//! `null` and the client should ask again in a minute or so; once the whole
+//! range is done, `finished` is `true` as well and the client can stop.
+//!
+//! `POST /result` reports a finished test. The body is the JSON object
+//! `--json` prints for it, a [`TestReport`]:
+//!
+//! ```text
+//! {"exponent":100003,"prime":false,"test":"LL","seconds":812.4,"res64":"A1B2C3D4E5F60718","factor":null,"factor_stage":null,"shift":null,"double_check":null,"timed_out_at":null}
+//! ```
+//!
+//! and the answer is `{"accepted":true}`, or `false` if the exponent is
+//! outside the range or already done. A report for a test that timed out
+//! or failed (`timed_out_at`, `failed_at` or `panic` set) hands the exponent back
+//! for someone else to try.
+//! A body that is not a report gets status 400.
+//!
+//! `GET /status` describes the run, for people and monitoring:
+//!
+//! ```text
+//! {"start_exponent":100000,"end_exponent":200000,"completed":1200,"leased":16,"finished":false}
+//! ```
+//!
+//! A lease that is not answered with a result within `lease_seconds`,
+//! because the worker died or was switched off, runs out and the exponent
+//! goes to the next worker that asks. A result that arrives after that is
+//! still accepted, if nobody else has reported one first.
+//!
+//! # Persistence
+//!
+//! Finished exponents go to a results file in the [`crate::results`]
+//! format, and the leases and the position in the range to a small JSON
+//! state file, replaced as a whole after every change. A restarted server
+//! reads both back, so it neither loses nor repeats work, and leases held
+//! when it stopped run out at the same time as they would have.
+
+use crate::report::{Form, TestReport};
+use crate::results::{self, ResultsFile};
+use crate::{is_prime, sieve};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The answer to `GET /work`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkResponse {
+    /// The exponent leased to the caller, or `None` if there is nothing to
+    /// hand out right now.
+    pub exponent: Option<u64>,
+    pub lease_seconds: u64,
+    /// Whether every exponent of the range is done.
+    pub finished: bool,
+}
+
+/// The answer to `POST /result`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultResponse {
+    /// Whether the report was recorded as the result of its exponent.
+    pub accepted: bool,
+}
+
+/// The answer to `GET /status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub start_exponent: u64,
+    pub end_exponent: u64,
+    pub completed: usize,
+    pub leased: usize,
+    pub finished: bool,
+}
+
+/// What the state file holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct State {
+    start_exponent: u64,
+    end_exponent: u64,
+    /// Every prime in the range below this has been handed out at least
+    /// once.
+    next: u64,
+    /// Leased exponents, and when their leases run out in Unix seconds.
+    leases: BTreeMap<u64, u64>,
+    /// Exponents whose leases ran out, handed out again before new ones.
+    returned: BTreeSet<u64>,
+}
+
+/// The exponents of a range and who is testing them.
+#[derive(Debug)]
+pub struct Coordinator {
+    state: State,
+    state_path: PathBuf,
+    lease_seconds: u64,
+    results: ResultsFile,
+    completed: HashSet<u64>,
+}
+
+impl Coordinator {
+    /// Serves `start..=end` with leases of `lease_seconds`, keeping its
+    /// state at `state_path` and the results at `results_path`, and picking
+    /// up where an earlier server with the same files stopped.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the state file is for
+    /// a different range.
+    pub fn open(
+        start: u64,
+        end: u64,
+        lease_seconds: u64,
+        state_path: &Path,
+        results_path: &Path,
+    ) -> io::Result<Coordinator> {
+        let mut state = match fs::read_to_string(state_path) {
+            Ok(text) => serde_json::from_str::<State>(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => State {
+                start_exponent: start,
+                end_exponent: end,
+                next: start,
+                leases: BTreeMap::new(),
+                returned: BTreeSet::new(),
+            },
+            Err(e) => return Err(e),
+        };
+        if (state.start_exponent, state.end_exponent) != (start, end) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is for the range {} to {}",
+                    state_path.display(),
+                    state.start_exponent,
+                    state.end_exponent
+                ),
+            ));
+        }
+
+        let completed: HashSet<u64> = results::recorded_exponents(results_path, Form::Mersenne)?
+            .into_iter()
+            .filter(|p| (start..=end).contains(p))
+            .collect();
+        // A result recorded just before a crash may not have reached the
+        // state file.
+        state.leases.retain(|p, _| !completed.contains(p));
+        state.returned.retain(|p| !completed.contains(p));
+
+        let coordinator = Coordinator {
+            state,
+            state_path: state_path.to_path_buf(),
+            lease_seconds,
+            results: ResultsFile::open(results_path)?,
+            completed,
+        };
+        coordinator.save()?;
+        Ok(coordinator)
+    }
+
+    /// Leases the next exponent at Unix time `now`: one whose lease ran
+    /// out if there is one, otherwise the next untested prime of the range.
+    pub fn next_work(&mut self, now: u64) -> io::Result<WorkResponse> {
+        self.expire(now);
+        let exponent = match self.state.returned.pop_first() {
+            Some(p) => Some(p),
+            None => self.next_new(),
+        };
+        if let Some(p) = exponent {
+            self.state
+                .leases
+                .insert(p, now.saturating_add(self.lease_seconds));
+            self.save()?;
+        }
+        Ok(WorkResponse {
+            exponent,
+            lease_seconds: self.lease_seconds,
+            finished: exponent.is_none() && self.is_finished(),
+        })
+    }
+
+    /// Records `report`, returning whether it was accepted as the result of
+    /// its exponent.
+    pub fn record(&mut self, report: &TestReport) -> io::Result<bool> {
+        let p = report.exponent;
+        let in_range = (self.state.start_exponent..=self.state.end_exponent).contains(&p);
+        if !in_range || !is_prime(p) || self.completed.contains(&p) {
+            return Ok(false);
+        }
+        if !report.has_result() {
+            if self.state.leases.remove(&p).is_some() {
+                self.state.returned.insert(p);
+                self.save()?;
+            }
+            return Ok(false);
+        }
+
+        self.results.record(report)?;
+        self.completed.insert(p);
+        self.state.leases.remove(&p);
+        self.state.returned.remove(&p);
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        ServerStatus {
+            start_exponent: self.state.start_exponent,
+            end_exponent: self.state.end_exponent,
+            completed: self.completed.len(),
+            leased: self.state.leases.len(),
+            finished: self.is_finished(),
+        }
+    }
+
+    /// Whether every exponent of the range is done.
+    pub fn is_finished(&self) -> bool {
+        self.state.leases.is_empty()
+            && self.state.returned.is_empty()
+            && self.untested().next().is_none()
+    }
+
+    /// Returns the exponents whose leases ran out by `now` to the pool.
+    fn expire(&mut self, now: u64) {
+        let expired: Vec<u64> = self
+            .state
+            .leases
+            .iter()
+            .filter(|&(_, &expires)| expires <= now)
+            .map(|(&p, _)| p)
+            .collect();
+        for p in expired {
+            self.state.leases.remove(&p);
+            self.state.returned.insert(p);
+        }
+    }
+
+    /// The primes not yet handed out and not already done.
+    fn untested(&self) -> impl Iterator<Item = u64> + '_ {
+        sieve::primes(self.state.next, self.state.end_exponent)
+            .filter(|p| !self.completed.contains(p))
+    }
+
+    fn next_new(&mut self) -> Option<u64> {
+        let p = self.untested().next();
+        self.state.next = match p {
+            Some(p) => p + 1,
+            None => self.state.end_exponent.saturating_add(1),
+        };
+        p
+    }
+
+    /// Writes the state to a temporary file and renames it over the state
+    /// file, so a crash never leaves it half-written.
+    fn save(&self) -> io::Result<()> {
+        let tmp = self.state_path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(&self.state)? + "\n")?;
+        fs::rename(&tmp, &self.state_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::format_res64;
+
+    fn report(exponent: u64) -> TestReport {
+        TestReport {
+            res64: Some(format_res64(1)),
+            ..TestReport::lucas_lehmer(exponent, 1.0)
+        }
+    }
+
+    fn open(dir: &Path, start: u64, end: u64) -> Coordinator {
+        Coordinator::open(
+            start,
+            end,
+            100,
+            &dir.join("state.json"),
+            &dir.join("results.txt"),
+        )
+        .unwrap()
+    }
+
+    fn lease(coordinator: &mut Coordinator, now: u64) -> Option<u64> {
+        coordinator.next_work(now).unwrap().exponent
+    }
+
+    #[test]
+    fn hands_out_each_prime_once_and_finishes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = open(dir.path(), 10, 30);
+        let mut handed_out = Vec::new();
+        while let Some(p) = lease(&mut coordinator, 0) {
+            handed_out.push(p);
+        }
+        assert_eq!(handed_out, vec![11, 13, 17, 19, 23, 29]);
+        let work = coordinator.next_work(0).unwrap();
+        assert_eq!((work.exponent, work.finished), (None, false));
+
+        for &p in &handed_out {
+            assert!(coordinator.record(&report(p)).unwrap());
+        }
+        assert!(!coordinator.record(&report(11)).unwrap());
+        assert!(!coordinator.record(&report(31)).unwrap());
+        let work = coordinator.next_work(0).unwrap();
+        assert_eq!((work.exponent, work.finished), (None, true));
+        assert_eq!(
+            coordinator.status(),
+            ServerStatus {
+                start_exponent: 10,
+                end_exponent: 30,
+                completed: 6,
+                leased: 0,
+                finished: true,
+            }
+        );
+    }
+
+    #[test]
+    fn lapsed_leases_go_to_the_next_worker() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = open(dir.path(), 2, 5);
+        assert_eq!(lease(&mut coordinator, 1000), Some(2));
+        assert_eq!(lease(&mut coordinator, 1000), Some(3));
+        assert_eq!(lease(&mut coordinator, 1099), Some(5));
+        // The leases of 2 and 3 run out at 1100.
+        assert_eq!(lease(&mut coordinator, 1099), None);
+        assert_eq!(lease(&mut coordinator, 1100), Some(2));
+        assert_eq!(lease(&mut coordinator, 1100), Some(3));
+
+        // A late result still counts if nobody else has reported one.
+        assert!(coordinator.record(&report(3)).unwrap());
+        assert!(!coordinator.record(&report(3)).unwrap());
+    }
+
+    #[test]
+    fn timed_out_tests_return_their_exponent() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = open(dir.path(), 2, 3);
+        assert_eq!(lease(&mut coordinator, 0), Some(2));
+        let timed_out = TestReport {
+            timed_out_at: Some(1),
+            ..report(2)
+        };
+        assert!(!coordinator.record(&timed_out).unwrap());
+        assert_eq!(lease(&mut coordinator, 0), Some(2));
+    }
+
+    #[test]
+    fn a_restarted_server_carries_on() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = open(dir.path(), 2, 13);
+        assert_eq!(lease(&mut coordinator, 0), Some(2));
+        assert_eq!(lease(&mut coordinator, 0), Some(3));
+        assert_eq!(lease(&mut coordinator, 0), Some(5));
+        assert!(coordinator.record(&report(3)).unwrap());
+        drop(coordinator);
+
+        let mut coordinator = open(dir.path(), 2, 13);
+        assert_eq!(coordinator.status().completed, 1);
+        assert_eq!(coordinator.status().leased, 2);
+        // 2 and 5 are still leased until 100.
+        assert_eq!(lease(&mut coordinator, 50), Some(7));
+        assert_eq!(lease(&mut coordinator, 100), Some(2));
+
+        let other_range = Coordinator::open(
+            2,
+            17,
+            100,
+            &dir.path().join("state.json"),
+            &dir.path().join("results.txt"),
+        );
+        assert_eq!(other_range.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn results_recorded_before_a_crash_are_not_handed_out_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut coordinator = open(dir.path(), 2, 7);
+        assert_eq!(lease(&mut coordinator, 0), Some(2));
+        drop(coordinator);
+        // As if the server died between writing the result and the state.
+        ResultsFile::open(dir.path().join("results.txt"))
+            .unwrap()
+            .record(&report(2))
+            .unwrap();
+        ResultsFile::open(dir.path().join("results.txt"))
+            .unwrap()
+            .record(&report(5))
+            .unwrap();
+
+        let mut coordinator = open(dir.path(), 2, 7);
+        assert_eq!(coordinator.status().leased, 0);
+        assert_eq!(lease(&mut coordinator, 0), Some(3));
+        assert_eq!(lease(&mut coordinator, 0), Some(7));
+        assert_eq!(lease(&mut coordinator, 0), None);
+    }
+}
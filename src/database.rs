@@ -0,0 +1,781 @@
+//! The SQLite results database written with `--db`.
+//!
+//! Where the results file and the ledger are one file per search, the
+//! database collects every run on every machine that points at it. It has
+//! two tables:
+//!
+//! - `runs`, one row per run: its [run ID](crate::run), when it started
+//!   and ended and how, the form and range it covered, the host and build,
+//!   and its command line.
+//! - `results`, one row per number: its status (`prime`, `composite`,
+//!   `factored`, `timeout` or `error`, as in the results file), test, Res64,
+//!   factor, the stage that found the factor, seconds, the run that
+//!   produced it, and when the number was first and last recorded. The
+//!   full [`TestReport`] is kept as JSON too, so it reads back exactly.
+//!
+//! A number has one row however often it is tested: recording it again
+//! replaces its result, except that a timed-out test never replaces a
+//! final result. Numbers are keyed by form, `k` (0 for forms without one)
+//! and exponent, so one database can hold every form.
+//!
+//! A database of schema version 1, from before runs had IDs, gets the
+//! `run_id` column when it is next opened to write; read as it is, its
+//! runs have no ID.
+//!
+//! ```text
+//! $ sqlite3 results.db "SELECT exponent, factor FROM results WHERE status = 'factored' LIMIT 2"
+//! 23|47
+//! 29|233
+//! ```
+
+use crate::report::{Form, TestReport};
+use crate::run::RunId;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OpenFlags, Row, ToSql};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// The schema version this build writes, kept in `PRAGMA user_version`.
+pub const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    run_id TEXT,
+    started TEXT NOT NULL,
+    ended TEXT,
+    outcome TEXT,
+    form TEXT NOT NULL,
+    k INTEGER NOT NULL,
+    start_exponent INTEGER NOT NULL,
+    end_exponent INTEGER NOT NULL,
+    host TEXT NOT NULL,
+    system TEXT NOT NULL,
+    command TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS results (
+    form TEXT NOT NULL,
+    k INTEGER NOT NULL,
+    exponent INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    test TEXT,
+    res64 TEXT,
+    factor TEXT,
+    stage TEXT,
+    seconds REAL NOT NULL,
+    run INTEGER REFERENCES runs (id),
+    first_recorded TEXT NOT NULL,
+    updated TEXT NOT NULL,
+    report TEXT NOT NULL,
+    PRIMARY KEY (form, k, exponent)
+);
+";
+
+/// How long a write waits for another process holding the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why the database cannot be used.
+#[derive(Debug)]
+pub enum DatabaseError {
+    Sqlite(rusqlite::Error),
+    /// The file was written by a build with a newer schema.
+    Newer(i64),
+    /// A stored report that does not parse.
+    Corrupt(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Sqlite(e) => write!(f, "SQLite error: {}", e),
+            DatabaseError::Newer(version) => write!(
+                f,
+                "it has schema version {}, newer than the {} this build knows",
+                version, SCHEMA_VERSION
+            ),
+            DatabaseError::Corrupt(problem) => write!(f, "{}", problem),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(e: rusqlite::Error) -> Self {
+        DatabaseError::Sqlite(e)
+    }
+}
+
+/// What a run records about itself as it starts.
+#[derive(Debug, Clone)]
+pub struct NewRun {
+    pub run_id: RunId,
+    pub started: DateTime<Utc>,
+    pub form: Form,
+    pub start_exponent: u64,
+    pub end_exponent: u64,
+    pub host: String,
+    /// The build and machine, as [`SystemInfo`](crate::system::SystemInfo)
+    /// prints them.
+    pub system: String,
+    pub command: String,
+}
+
+/// A row of `runs`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Run {
+    pub id: i64,
+    /// `None` for a run recorded before runs had IDs.
+    pub run_id: Option<RunId>,
+    pub started: String,
+    /// When it ended, and how: `done`, `interrupted` or `time limit`.
+    /// Neither is set for a run still going, or one that crashed.
+    pub ended: Option<String>,
+    pub outcome: Option<String>,
+    #[serde(flatten)]
+    pub form: Form,
+    pub start_exponent: u64,
+    pub end_exponent: u64,
+    pub host: String,
+    pub system: String,
+    pub command: String,
+}
+
+impl Run {
+    /// Whether the run got through its whole range.
+    pub fn is_finished(&self) -> bool {
+        self.outcome.as_deref() == Some("done")
+    }
+}
+
+/// A row of `results`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredResult {
+    #[serde(flatten)]
+    pub report: TestReport,
+    pub status: &'static str,
+    /// The row of `runs` that recorded it; `run`, in JSON, is the ID of the
+    /// run in the report.
+    #[serde(rename = "run_row")]
+    pub run: Option<i64>,
+    pub first_recorded: String,
+    pub updated: String,
+}
+
+/// The results of the numbers in the range of some runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RangeTotals {
+    #[serde(flatten)]
+    pub form: Form,
+    pub start_exponent: u64,
+    pub end_exponent: u64,
+    pub tested: u64,
+    pub primes: u64,
+    pub factored: u64,
+    pub composite: u64,
+    pub timed_out: u64,
+    pub seconds: f64,
+}
+
+/// An open results database.
+pub struct Database {
+    connection: Connection,
+    /// Whether `runs` has the `run_id` column, which a database of schema
+    /// version 1 opened for reading does not.
+    run_ids: bool,
+    /// The run its reads are limited to, if any.
+    only: Option<RunId>,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its tables if need
+    /// be.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database, DatabaseError> {
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(DatabaseError::Newer(version));
+        }
+        if version == 1 {
+            connection.execute_batch("ALTER TABLE runs ADD COLUMN run_id TEXT")?;
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(Database {
+            connection,
+            run_ids: true,
+            only: None,
+        })
+    }
+
+    /// Opens the database at `path` for reading only, or `None` if there
+    /// is no such file or it holds no results database.
+    pub fn open_existing<P: AsRef<Path>>(path: P) -> Result<Option<Database>, DatabaseError> {
+        if !path.as_ref().exists() {
+            return Ok(None);
+        }
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(DatabaseError::Newer(version));
+        }
+        if version == 0 {
+            // Empty, or not ours: nothing to read.
+            return Ok(None);
+        }
+        Ok(Some(Database {
+            connection,
+            run_ids: version >= 2,
+            only: None,
+        }))
+    }
+
+    /// Limits what the database reads back to run `id`: its row of
+    /// [`runs`](Database::runs), the results it recorded last, and the
+    /// ranges it covered.
+    pub fn only_run(self, id: RunId) -> Database {
+        Database {
+            only: Some(id),
+            ..self
+        }
+    }
+
+    /// Adds a row for a run that is starting, and returns its id.
+    pub fn start_run(&self, run: &NewRun) -> Result<i64, DatabaseError> {
+        self.connection.execute(
+            "INSERT INTO runs (run_id, started, form, k, start_exponent, end_exponent, host, system,
+                               command)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                run.run_id.as_str(),
+                timestamp(run.started),
+                run.form.as_str(),
+                k_of(run.form),
+                run.start_exponent as i64,
+                run.end_exponent as i64,
+                run.host,
+                run.system,
+                run.command,
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Records that run `id` ended at `ended`, with `outcome` such as
+    /// `done`.
+    pub fn end_run(
+        &self,
+        id: i64,
+        ended: DateTime<Utc>,
+        outcome: &str,
+    ) -> Result<(), DatabaseError> {
+        self.connection.execute(
+            "UPDATE runs SET ended = ?1, outcome = ?2 WHERE id = ?3",
+            params![timestamp(ended), outcome, id],
+        )?;
+        Ok(())
+    }
+
+    /// Records `report`, from run `run`, at `now`, replacing any earlier
+    /// result for the number unless `report` timed out or failed and that
+    /// one did not.
+    pub fn record(
+        &self,
+        run: Option<i64>,
+        report: &TestReport,
+        now: DateTime<Utc>,
+    ) -> Result<(), DatabaseError> {
+        let json = serde_json::to_string(report).expect("reports serialize");
+        self.connection.execute(
+            "INSERT INTO results (form, k, exponent, status, test, res64, factor, stage, seconds,
+                                  run, first_recorded, updated, report)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?11, ?12)
+             ON CONFLICT (form, k, exponent) DO UPDATE SET
+                 status = excluded.status, test = excluded.test, res64 = excluded.res64,
+                 factor = excluded.factor, stage = excluded.stage, seconds = excluded.seconds,
+                 run = excluded.run, updated = excluded.updated, report = excluded.report
+             WHERE excluded.status NOT IN ('timeout', 'error')
+                 OR results.status IN ('timeout', 'error')",
+            params![
+                report.form.as_str(),
+                k_of(report.form),
+                report.exponent as i64,
+                status(report),
+                report.test.map(|test| test.as_str()),
+                report.res64,
+                report.factor,
+                report.factor_stage.map(|stage| stage.as_str()),
+                report.seconds,
+                run,
+                timestamp(now),
+                json,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The exponents of `form` with a final result: every recorded one but
+    /// those whose test timed out or failed.
+    pub fn recorded_exponents(&self, form: Form) -> Result<HashSet<u64>, DatabaseError> {
+        let mut statement = self.connection.prepare(
+            "SELECT exponent FROM results
+             WHERE form = ?1 AND k = ?2 AND status NOT IN ('timeout', 'error')",
+        )?;
+        let exponents = statement
+            .query_map(params![form.as_str(), k_of(form)], |row| {
+                row.get::<_, i64>(0)
+            })?
+            .map(|exponent| exponent.map(|exponent| exponent as u64))
+            .collect::<Result<_, _>>()?;
+        Ok(exponents)
+    }
+
+    /// Every run, oldest first.
+    pub fn runs(&self) -> Result<Vec<Run>, DatabaseError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT id, started, ended, outcome, form, k, start_exponent, end_exponent, host,
+                    system, command, {run_id}
+             FROM runs WHERE ?1 IS NULL OR {run_id} = ?1 ORDER BY id",
+            run_id = self.run_id_column()
+        ))?;
+        let rows = statement.query_map([self.only_param()], |row| {
+            Ok(Run {
+                id: row.get(0)?,
+                run_id: row
+                    .get::<_, Option<String>>(11)?
+                    .and_then(|id| id.parse().ok()),
+                started: row.get(1)?,
+                ended: row.get(2)?,
+                outcome: row.get(3)?,
+                form: form_of(row, 4)?,
+                start_exponent: row.get::<_, i64>(6)? as u64,
+                end_exponent: row.get::<_, i64>(7)? as u64,
+                host: row.get(8)?,
+                system: row.get(9)?,
+                command: row.get(10)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The [`TestReport::work_units`] of every result.
+    pub fn work_units(&self) -> Result<f64, DatabaseError> {
+        let results = self.results()?;
+        Ok(results
+            .iter()
+            .map(|result| result.report.work_units())
+            .sum())
+    }
+
+    /// Every result, by form, `k` and exponent.
+    pub fn results(&self) -> Result<Vec<StoredResult>, DatabaseError> {
+        self.query_results("TRUE", "ORDER BY form, k, exponent", &[])
+    }
+
+    /// The `count` results that took the longest, slowest first.
+    pub fn slowest(&self, count: usize) -> Result<Vec<StoredResult>, DatabaseError> {
+        self.query_results(
+            "TRUE",
+            "ORDER BY seconds DESC, exponent LIMIT ?2",
+            &[&(count as i64)],
+        )
+    }
+
+    /// The results whose tests timed out or failed, which the next run
+    /// tries again.
+    pub fn unfinished(&self) -> Result<Vec<StoredResult>, DatabaseError> {
+        self.query_results(
+            "status IN ('timeout', 'error')",
+            "ORDER BY form, k, exponent",
+            &[],
+        )
+    }
+
+    /// The totals of the results in each range that a run covered, by
+    /// form and range.
+    pub fn range_totals(&self) -> Result<Vec<RangeTotals>, DatabaseError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT ranges.form, ranges.k, ranges.start_exponent, ranges.end_exponent,
+                    COUNT(results.exponent),
+                    COALESCE(SUM(results.status = 'prime'), 0),
+                    COALESCE(SUM(results.status = 'factored'), 0),
+                    COALESCE(SUM(results.status = 'composite'), 0),
+                    COALESCE(SUM(results.status = 'timeout'), 0),
+                    COALESCE(SUM(results.seconds), 0.0)
+             FROM (SELECT DISTINCT form, k, start_exponent, end_exponent FROM runs
+                   WHERE ?1 IS NULL OR {run_id} = ?1) AS ranges
+             LEFT JOIN results ON results.form = ranges.form AND results.k = ranges.k
+                 AND results.exponent BETWEEN ranges.start_exponent AND ranges.end_exponent
+                 AND {in_run}
+             GROUP BY ranges.form, ranges.k, ranges.start_exponent, ranges.end_exponent
+             ORDER BY ranges.form, ranges.k, ranges.start_exponent, ranges.end_exponent",
+            run_id = self.run_id_column(),
+            in_run = self.in_run()
+        ))?;
+        let rows = statement.query_map([self.only_param()], |row| {
+            Ok(RangeTotals {
+                form: form_of(row, 0)?,
+                start_exponent: row.get::<_, i64>(2)? as u64,
+                end_exponent: row.get::<_, i64>(3)? as u64,
+                tested: row.get::<_, i64>(4)? as u64,
+                primes: row.get::<_, i64>(5)? as u64,
+                factored: row.get::<_, i64>(6)? as u64,
+                composite: row.get::<_, i64>(7)? as u64,
+                timed_out: row.get::<_, i64>(8)? as u64,
+                seconds: row.get(9)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The results that match `filter`, in the order and limit of `rest`,
+    /// whose parameters `params` are numbered from `?2`: `?1` is the run
+    /// the reads are limited to.
+    fn query_results(
+        &self,
+        filter: &str,
+        rest: &str,
+        params: &[&dyn ToSql],
+    ) -> Result<Vec<StoredResult>, DatabaseError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT report, run, first_recorded, updated FROM results
+             WHERE ({}) AND {} {}",
+            filter,
+            self.in_run(),
+            rest
+        ))?;
+        let only = self.only_param();
+        let params: Vec<&dyn ToSql> = std::iter::once(&only as &dyn ToSql)
+            .chain(params.iter().copied())
+            .collect();
+        let rows = statement.query_map(params.as_slice(), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        let mut results = Vec::new();
+        for row in rows {
+            let (json, run, first_recorded, updated) = row?;
+            let report: TestReport = serde_json::from_str(&json).map_err(|e| {
+                DatabaseError::Corrupt(format!("a stored report does not parse: {}", e))
+            })?;
+            results.push(StoredResult {
+                status: status(&report),
+                report,
+                run,
+                first_recorded,
+                updated,
+            });
+        }
+        Ok(results)
+    }
+
+    /// The run the reads are limited to, as the query parameter `?1`.
+    fn only_param(&self) -> Option<&str> {
+        self.only.as_ref().map(RunId::as_str)
+    }
+
+    /// The ID of a row of `runs`, in SQL.
+    fn run_id_column(&self) -> &'static str {
+        match self.run_ids {
+            true => "run_id",
+            false => "NULL",
+        }
+    }
+
+    /// Whether a row of `results` was recorded by the run in `?1`, if any.
+    fn in_run(&self) -> String {
+        format!(
+            "(?1 IS NULL OR results.run IN (SELECT id FROM runs WHERE {} = ?1))",
+            self.run_id_column()
+        )
+    }
+}
+
+/// The exponents of `form` with a final result in the database at `path`,
+/// read without creating it. A missing file has none.
+pub fn recorded_exponents<P: AsRef<Path>>(
+    path: P,
+    form: Form,
+) -> Result<HashSet<u64>, DatabaseError> {
+    match Database::open_existing(path)? {
+        Some(database) => database.recorded_exponents(form),
+        None => Ok(HashSet::new()),
+    }
+}
+
+/// The status of `report` as the results file writes it.
+pub fn status(report: &TestReport) -> &'static str {
+    if report.is_timed_out() {
+        "timeout"
+    } else if report.is_failed() {
+        "error"
+    } else if report.is_factored() {
+        "factored"
+    } else if report.prime {
+        "prime"
+    } else {
+        "composite"
+    }
+}
+
+/// The `k` column: that of a Riesel number, 0 for the other forms.
+fn k_of(form: Form) -> i64 {
+    match form {
+        Form::Riesel { k } => k as i64,
+        _ => 0,
+    }
+}
+
+/// The form in columns `column` and `column + 1` of `row`.
+fn form_of(row: &Row, column: usize) -> rusqlite::Result<Form> {
+    let name: String = row.get(column)?;
+    let k: i64 = row.get(column + 1)?;
+    let form = name.parse::<Form>().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, e.into())
+    })?;
+    Ok(match form {
+        Form::Riesel { .. } => Form::Riesel { k: k as u64 },
+        form => form,
+    })
+}
+
+/// Times as the results file writes them, such as `2024-05-01T12:00:00Z`.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{format_res64, FactoringStage};
+    use chrono::TimeZone;
+
+    fn composite(exponent: u64, res64: u64) -> TestReport {
+        TestReport {
+            res64: Some(format_res64(res64)),
+            ..TestReport::lucas_lehmer(exponent, 0.5)
+        }
+    }
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, hour, 0, 0).unwrap()
+    }
+
+    fn run(database: &Database, form: Form, start: u64, end: u64) -> i64 {
+        database
+            .start_run(&NewRun {
+                run_id: format!("run-{}-{}", start, end).parse().unwrap(),
+                started: at(12),
+                form,
+                start_exponent: start,
+                end_exponent: end,
+                host: "box1".to_string(),
+                system: "Mersenne 0.1.0".to_string(),
+                command: "Mersenne search 2 100".to_string(),
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn recording_again_replaces_the_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path().join("results.db")).unwrap();
+        let first = run(&database, Form::Mersenne, 2, 100);
+        database
+            .record(Some(first), &composite(29, 1), at(12))
+            .unwrap();
+        let second = run(&database, Form::Mersenne, 29, 29);
+        database
+            .record(Some(second), &composite(29, 2), at(13))
+            .unwrap();
+
+        let results = database.results().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].report, composite(29, 2));
+        assert_eq!(results[0].status, "composite");
+        assert_eq!(results[0].run, Some(second));
+        assert_eq!(results[0].first_recorded, "2024-05-01T12:00:00Z");
+        assert_eq!(results[0].updated, "2024-05-01T13:00:00Z");
+    }
+
+    #[test]
+    fn timed_out_tests_do_not_replace_final_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path().join("results.db")).unwrap();
+        let timed_out = TestReport {
+            timed_out_at: Some(5),
+            res64: None,
+            ..composite(31, 0)
+        };
+        database.record(None, &timed_out, at(12)).unwrap();
+        assert_eq!(database.unfinished().unwrap().len(), 1);
+        assert!(database
+            .recorded_exponents(Form::Mersenne)
+            .unwrap()
+            .is_empty());
+
+        database.record(None, &composite(31, 7), at(13)).unwrap();
+        database.record(None, &timed_out, at(14)).unwrap();
+        let results = database.results().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].report, composite(31, 7));
+        assert!(database.unfinished().unwrap().is_empty());
+    }
+
+    #[test]
+    fn forms_are_kept_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.db");
+        let database = Database::open(&path).unwrap();
+        database.record(None, &composite(29, 1), at(12)).unwrap();
+        for form in [Form::Wagstaff, Form::Riesel { k: 3 }, Form::Riesel { k: 5 }] {
+            let report = TestReport {
+                form,
+                ..composite(29, 1)
+            };
+            database.record(None, &report, at(12)).unwrap();
+        }
+        assert_eq!(database.results().unwrap().len(), 4);
+        assert_eq!(
+            recorded_exponents(&path, Form::Riesel { k: 5 }).unwrap(),
+            HashSet::from([29])
+        );
+        assert!(recorded_exponents(&path, Form::Fermat).unwrap().is_empty());
+        assert!(
+            recorded_exponents(dir.path().join("missing.db"), Form::Mersenne)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!dir.path().join("missing.db").exists());
+    }
+
+    #[test]
+    fn runs_and_range_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::open(dir.path().join("results.db")).unwrap();
+        let first = run(&database, Form::Mersenne, 2, 40);
+        let factored = TestReport {
+            test: None,
+            res64: None,
+            factor: Some("47".to_string()),
+            factor_stage: Some(FactoringStage::TrialFactoring),
+            ..composite(23, 0)
+        };
+        let prime = TestReport {
+            prime: true,
+            res64: None,
+            ..composite(31, 0)
+        };
+        for report in [&factored, &prime, &composite(37, 1)] {
+            database.record(Some(first), report, at(12)).unwrap();
+        }
+        database.end_run(first, at(13), "done").unwrap();
+        run(&database, Form::Mersenne, 30, 60);
+
+        let runs = database.runs().unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(runs[0].is_finished());
+        assert_eq!(runs[0].ended.as_deref(), Some("2024-05-01T13:00:00Z"));
+        assert_eq!(runs[1].outcome, None);
+        assert!(!runs[1].is_finished());
+
+        let totals = database.range_totals().unwrap();
+        assert_eq!(totals.len(), 2);
+        assert_eq!((totals[0].tested, totals[0].primes), (3, 1));
+        assert_eq!((totals[0].factored, totals[0].composite), (1, 1));
+        assert_eq!(totals[0].seconds, 1.5);
+        assert_eq!((totals[1].start_exponent, totals[1].tested), (30, 2));
+
+        let slowest = database.slowest(1).unwrap();
+        assert_eq!(slowest.len(), 1);
+    }
+
+    #[test]
+    fn reads_can_be_limited_to_a_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.db");
+        let database = Database::open(&path).unwrap();
+        let first = run(&database, Form::Mersenne, 2, 40);
+        let second = run(&database, Form::Mersenne, 30, 60);
+        database
+            .record(Some(first), &composite(23, 1), at(12))
+            .unwrap();
+        database
+            .record(Some(second), &composite(37, 2), at(12))
+            .unwrap();
+        drop(database);
+
+        let database = Database::open_existing(&path)
+            .unwrap()
+            .unwrap()
+            .only_run("run-30-60".parse().unwrap());
+        let runs = database.runs().unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].run_id.as_ref().unwrap().as_str(), "run-30-60");
+        let results = database.results().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].report.exponent, 37);
+        assert_eq!(database.slowest(5).unwrap().len(), 1);
+        let totals = database.range_totals().unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!((totals[0].start_exponent, totals[0].tested), (30, 1));
+    }
+
+    #[test]
+    fn version_1_databases_get_run_ids_when_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.db");
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(&SCHEMA.replace("    run_id TEXT,\n", ""))
+            .unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO runs (started, form, k, start_exponent, end_exponent, host, system,
+                                   command)
+                 VALUES ('2024-05-01T12:00:00Z', 'mersenne', 0, 2, 40, 'box1', 'old', 'old');
+                 PRAGMA user_version = 1;",
+            )
+            .unwrap();
+        drop(connection);
+
+        let old = Database::open_existing(&path).unwrap().unwrap();
+        assert_eq!(old.runs().unwrap()[0].run_id, None);
+        let only = old.only_run("run-2-40".parse().unwrap());
+        assert!(only.runs().unwrap().is_empty());
+        assert!(only.results().unwrap().is_empty());
+
+        let database = Database::open(&path).unwrap();
+        run(&database, Form::Mersenne, 2, 40);
+        let runs = database.runs().unwrap();
+        assert_eq!(runs[0].run_id, None);
+        assert_eq!(runs[1].run_id.as_ref().unwrap().as_str(), "run-2-40");
+    }
+
+    #[test]
+    fn newer_schemas_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.db");
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        assert!(matches!(
+            Database::open(&path),
+            Err(DatabaseError::Newer(version)) if version == SCHEMA_VERSION + 1
+        ));
+        assert!(matches!(
+            Database::open_existing(&path),
+            Err(DatabaseError::Newer(_))
+        ));
+    }
+}
@@ -0,0 +1,456 @@
+//! What changed between two runs of the same numbers, for `diff`: after
+//! new hardware or a new version, say.
+//!
+//! Each run is read from whichever of the files this crate writes it is
+//! in, told apart by their first line: a results file with `--results`,
+//! `--json` output or a `--ledger`, a [`TestReport`] per line, or a CSV
+//! file with `--csv`, which starts with its header. Timeouts and errors
+//! are not results and are skipped, and of several lines for one number
+//! the last counts. A CSV row does not say its form, so its numbers are
+//! taken to be Mersenne numbers.
+//!
+//! A file that several runs wrote to can be narrowed to one of them by
+//! its [run ID](crate::run), which results lines, reports and CSV rows
+//! all carry; lines without one belong to no run.
+//!
+//! The runs are lined up by number. A number one run found prime and the
+//! other did not is a disagreement, the most serious of differences: one
+//! of the machines got a test wrong. Numbers both found composite by the
+//! same test should end with the same Res64 too. The times of the numbers
+//! both runs took some time over are compared as ratios, the second run's
+//! over the first's, of which the median says how much faster or slower
+//! the second run was.
+
+use crate::report::{Form, TestKind, TestReport};
+use crate::results::{field, ResultLine};
+use crate::run::RunId;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+/// The format a run was read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Results,
+    Json,
+    Csv,
+}
+
+impl Format {
+    /// The format of `text`, by its first line that is not blank.
+    pub fn detect(text: &str) -> Format {
+        match text.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) if line.starts_with('{') => Format::Json,
+            Some(line) if line.starts_with("exponent,") => Format::Csv,
+            _ => Format::Results,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Results => "a results file",
+            Format::Json => "JSON reports",
+            Format::Csv => "CSV",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What one run found for a number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub prime: bool,
+    /// The test that decided it, if a test did and the file says which.
+    pub test: Option<TestKind>,
+    pub res64: Option<String>,
+    pub seconds: f64,
+}
+
+/// The entries of a run, by number.
+pub type Entries = BTreeMap<(Form, u64), Entry>;
+
+/// Reads the entries in `text`, in the format it is in, which is returned
+/// with them, only those of run `run` if one is given.
+pub fn read_entries(text: &str, run: Option<&RunId>) -> Result<(Format, Entries), String> {
+    let of_run = |id: Option<&str>| run.is_none_or(|run| id == Some(run.as_str()));
+    let format = Format::detect(text);
+    let entries = match format {
+        Format::Json => text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<TestReport>(line).ok())
+            .filter(TestReport::has_result)
+            .filter(|report| of_run(report.run.as_ref().map(RunId::as_str)))
+            .map(|report| {
+                let entry = Entry {
+                    prime: report.prime,
+                    test: report.test.filter(|_| report.factor.is_none()),
+                    res64: report.res64,
+                    seconds: report.seconds,
+                };
+                ((report.form, report.exponent), entry)
+            })
+            .collect(),
+        Format::Results => text
+            .lines()
+            .filter(|line| of_run(field(line, "run")))
+            .filter_map(parse_results_line)
+            .collect(),
+        Format::Csv => read_csv(text, &of_run)?,
+    };
+    Ok((format, entries))
+}
+
+/// The number and entry of a results line, or `None` for a header, a
+/// timeout, an error or a line that is not a result.
+fn parse_results_line(line: &str) -> Option<((Form, u64), Entry)> {
+    let line = ResultLine::parse(line)?;
+    let prime = match line.field("result")? {
+        "prime" => true,
+        "composite" | "factored" => false,
+        _ => return None,
+    };
+    let entry = Entry {
+        prime,
+        test: line.field("test").and_then(|test| test.parse().ok()),
+        res64: line.field("res64").map(str::to_string),
+        seconds: line.field("seconds")?.parse().ok()?,
+    };
+    Some((line.number(), entry))
+}
+
+/// The entries of a CSV file with the columns of `--csv`, looked up by
+/// name, of the rows whose `run_id` `of_run` takes. A row of a number that
+/// was neither found prime nor eliminated is not a result.
+fn read_csv(text: &str, of_run: &dyn Fn(Option<&str>) -> bool) -> Result<Entries, String> {
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| format!("the CSV header has no {} column", name))
+    };
+    let exponent = column("exponent")?;
+    let stage = column("stage_eliminated")?;
+    let is_prime = column("is_prime")?;
+    let res64 = column("res64")?;
+    let seconds = column("elapsed_seconds")?;
+    // Files from before runs had IDs lack the column.
+    let run_id = column("run_id").ok();
+
+    let mut entries = Entries::new();
+    for (row, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| e.to_string())?;
+        let get = |index: usize| record.get(index).unwrap_or("");
+        let invalid = |name: &str| format!("row {} has an invalid {}", row + 1, name);
+        if !of_run(run_id.map(get).filter(|id| !id.is_empty())) {
+            continue;
+        }
+        let prime: bool = get(is_prime).parse().map_err(|_| invalid("is_prime"))?;
+        if !prime && get(stage).is_empty() {
+            continue;
+        }
+        let entry = Entry {
+            prime,
+            test: get(stage).parse().ok(),
+            res64: Some(get(res64).to_string()).filter(|res64| !res64.is_empty()),
+            seconds: get(seconds)
+                .parse()
+                .map_err(|_| invalid("elapsed_seconds"))?,
+        };
+        let p = get(exponent).parse().map_err(|_| invalid("exponent"))?;
+        entries.insert((Form::Mersenne, p), entry);
+    }
+    Ok(entries)
+}
+
+/// A number of either run, as the JSON of a [`Diff`] gives it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Number {
+    #[serde(flatten)]
+    pub form: Form,
+    pub exponent: u64,
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.form.number(self.exponent))
+    }
+}
+
+/// A number one run found prime and the other did not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Disagreement {
+    #[serde(flatten)]
+    pub number: Number,
+    pub prime_in_a: bool,
+    pub prime_in_b: bool,
+}
+
+/// A number both runs tested alike whose Res64s differ.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Res64Mismatch {
+    #[serde(flatten)]
+    pub number: Number,
+    pub a: String,
+    pub b: String,
+}
+
+/// How the times of the numbers in both runs compare, as ratios of the
+/// second run's time over the first's: below 1 the second was faster.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Timing {
+    /// The numbers both runs took some time over.
+    pub compared: usize,
+    pub median_ratio: f64,
+    pub min_ratio: f64,
+    pub max_ratio: f64,
+    /// The numbers the second run took less time over, and more.
+    pub faster: usize,
+    pub slower: usize,
+}
+
+impl Timing {
+    /// The timing of the pairs of `(a, b)` times, or `None` if none has
+    /// both times above zero.
+    pub fn of(times: impl IntoIterator<Item = (f64, f64)>) -> Option<Timing> {
+        let mut ratios: Vec<f64> = times
+            .into_iter()
+            .filter(|&(a, b)| a > 0.0 && b > 0.0)
+            .map(|(a, b)| b / a)
+            .collect();
+        if ratios.is_empty() {
+            return None;
+        }
+        ratios.sort_by(f64::total_cmp);
+        let middle = ratios.len() / 2;
+        let median_ratio = match ratios.len() % 2 {
+            0 => (ratios[middle - 1] + ratios[middle]) / 2.0,
+            _ => ratios[middle],
+        };
+        Some(Timing {
+            compared: ratios.len(),
+            median_ratio,
+            min_ratio: ratios[0],
+            max_ratio: ratios[ratios.len() - 1],
+            faster: ratios.iter().filter(|&&ratio| ratio < 1.0).count(),
+            slower: ratios.iter().filter(|&&ratio| ratio > 1.0).count(),
+        })
+    }
+}
+
+/// How two runs differ.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diff {
+    /// The numbers in both runs.
+    pub common: usize,
+    pub only_in_a: Vec<Number>,
+    pub only_in_b: Vec<Number>,
+    pub disagreements: Vec<Disagreement>,
+    pub res64_mismatches: Vec<Res64Mismatch>,
+    pub timing: Option<Timing>,
+}
+
+impl Diff {
+    /// Whether the runs disagree on a result, by primality or Res64.
+    pub fn is_divergent(&self) -> bool {
+        !self.disagreements.is_empty() || !self.res64_mismatches.is_empty()
+    }
+}
+
+/// One of the runs, as the JSON of a [`Diff`] names it.
+#[derive(Debug, Serialize)]
+pub struct Run<'a> {
+    pub file: &'a Path,
+    pub format: Format,
+    /// The run it was narrowed to, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<&'a RunId>,
+    /// The numbers it has results for.
+    pub results: usize,
+}
+
+/// A [`Diff`] as the one line of `diff --json`, after the runs it is of.
+#[derive(Debug, Serialize)]
+pub struct DiffLine<'a> {
+    pub a: Run<'a>,
+    pub b: Run<'a>,
+    #[serde(flatten)]
+    pub diff: &'a Diff,
+}
+
+/// Lines up the runs `a` and `b` by number. Res64s are compared only of
+/// numbers both runs found composite by the same test, or by a test at
+/// least one of them does not name; tests of different kinds end with
+/// different Res64s.
+pub fn diff(a: &Entries, b: &Entries) -> Diff {
+    let number = |&(form, exponent): &(Form, u64)| Number { form, exponent };
+    let mut diff = Diff {
+        common: 0,
+        only_in_a: a
+            .keys()
+            .filter(|key| !b.contains_key(key))
+            .map(number)
+            .collect(),
+        only_in_b: b
+            .keys()
+            .filter(|key| !a.contains_key(key))
+            .map(number)
+            .collect(),
+        disagreements: Vec::new(),
+        res64_mismatches: Vec::new(),
+        timing: None,
+    };
+    let both: Vec<_> = a
+        .iter()
+        .filter_map(|(key, x)| Some((number(key), x, b.get(key)?)))
+        .collect();
+    diff.common = both.len();
+    for &(number, x, y) in &both {
+        if x.prime != y.prime {
+            diff.disagreements.push(Disagreement {
+                number,
+                prime_in_a: x.prime,
+                prime_in_b: y.prime,
+            });
+            continue;
+        }
+        let alike = match (x.test, y.test) {
+            (Some(s), Some(t)) => s == t,
+            _ => true,
+        };
+        if let (Some(r), Some(s)) = (&x.res64, &y.res64) {
+            if alike && !x.prime && !r.eq_ignore_ascii_case(s) {
+                diff.res64_mismatches.push(Res64Mismatch {
+                    number,
+                    a: r.clone(),
+                    b: s.clone(),
+                });
+            }
+        }
+    }
+    diff.timing = Timing::of(both.iter().map(|(_, x, y)| (x.seconds, y.seconds)));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESULTS: &str = "\
+# 2024-05-01T12:00:00Z version=0.1.0 cpu=Some CPU
+2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=000000001B57CB0B seconds=2.000
+2024-05-01T12:00:00Z exponent=31 result=prime test=LL seconds=1.000
+2024-05-01T12:00:00Z exponent=37 result=factored factor=223 stage=TF seconds=0.500
+2024-05-01T12:00:00Z exponent=41 result=timeout test=LL iteration=10 percent=25.0 seconds=9.000
+2024-05-01T12:00:00Z exponent=43 result=composite test=LL res64=0000000000000001 seconds=4.000
+";
+
+    const CSV: &str = "\
+exponent,candidate_source,stage_eliminated,is_prime,res64,iterations,elapsed_seconds,iters_per_second,started_at,finished_at,range,work_units
+29,range,LL,false,000000001B57CB0B,27,1.000,27.0,2024-05-01T12:00:00Z,2024-05-01T12:00:01Z,,1
+31,range,,true,0000000000000000,29,0.500,58.0,2024-05-01T12:00:00Z,2024-05-01T12:00:00Z,,1
+37,range,TF,false,,,0.250,,2024-05-01T12:00:00Z,2024-05-01T12:00:00Z,,0
+43,range,,false,,10,9.000,1.1,2024-05-01T12:00:00Z,2024-05-01T12:00:09Z,,1
+47,range,LL,false,0000000000000002,45,1.000,45.0,2024-05-01T12:00:00Z,2024-05-01T12:00:01Z,,1
+";
+
+    #[test]
+    fn every_format_is_told_apart_and_read() {
+        let (format, results) = read_entries(RESULTS, None).unwrap();
+        assert_eq!(format, Format::Results);
+        let exponents = |entries: &Entries| entries.keys().map(|&(_, p)| p).collect::<Vec<_>>();
+        assert_eq!(exponents(&results), [29, 31, 37, 43]);
+        assert_eq!(results[&(Form::Mersenne, 37)].test, None);
+
+        let (format, csv) = read_entries(CSV, None).unwrap();
+        assert_eq!(format, Format::Csv);
+        assert_eq!(exponents(&csv), [29, 31, 37, 47]);
+        assert_eq!(csv[&(Form::Mersenne, 29)].test, Some(TestKind::LucasLehmer));
+        assert_eq!(csv[&(Form::Mersenne, 37)].res64, None);
+
+        let json = "{\"exponent\":37,\"form\":\"wagstaff\",\"prime\":false,\"test\":\"PRP\",\
+                    \"seconds\":0.5,\"res64\":\"0000000000000001\",\"errors\":0}\n\
+                    {\"summary\":{\"tested\":1}}\n";
+        let (format, reports) = read_entries(json, None).unwrap();
+        assert_eq!(format, Format::Json);
+        assert_eq!(reports.keys().collect::<Vec<_>>(), [&(Form::Wagstaff, 37)]);
+
+        assert!(read_entries("exponent,res64\n29,1\n", None).is_err());
+    }
+
+    #[test]
+    fn differences_are_sorted_out_by_kind() {
+        let (_, a) = read_entries(RESULTS, None).unwrap();
+        let (_, mut b) = read_entries(CSV, None).unwrap();
+        b.get_mut(&(Form::Mersenne, 29)).unwrap().res64 = Some("00000000DEADBEEF".into());
+        b.get_mut(&(Form::Mersenne, 31)).unwrap().prime = false;
+        let diff = diff(&a, &b);
+        let mersenne = |exponent| Number {
+            form: Form::Mersenne,
+            exponent,
+        };
+        assert_eq!(diff.common, 3);
+        assert_eq!(diff.only_in_a, [mersenne(43)]);
+        assert_eq!(diff.only_in_b, [mersenne(47)]);
+        assert_eq!(
+            diff.disagreements,
+            [Disagreement {
+                number: mersenne(31),
+                prime_in_a: true,
+                prime_in_b: false,
+            }]
+        );
+        assert_eq!(diff.res64_mismatches.len(), 1);
+        assert_eq!(diff.res64_mismatches[0].number, mersenne(29));
+        assert!(diff.is_divergent());
+
+        // Every time was halved.
+        let timing = diff.timing.unwrap();
+        assert_eq!((timing.compared, timing.faster, timing.slower), (3, 3, 0));
+        assert_eq!(timing.median_ratio, 0.5);
+    }
+
+    #[test]
+    fn files_of_several_runs_can_be_narrowed_to_one() {
+        let results = "\
+2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=0000000000000001 seconds=2.000 run=a
+2024-05-01T13:00:00Z exponent=29 result=composite test=LL res64=0000000000000002 seconds=1.000 run=b
+2024-05-01T13:00:00Z exponent=31 result=prime test=LL seconds=1.000 run=b
+";
+        let a: RunId = "a".parse().unwrap();
+        let (_, entries) = read_entries(results, Some(&a)).unwrap();
+        assert_eq!(entries.len(), 1);
+        let res64 = entries[&(Form::Mersenne, 29)].res64.as_deref();
+        assert_eq!(res64, Some("0000000000000001"));
+        let (_, entries) = read_entries(results, None).unwrap();
+        let res64 = entries[&(Form::Mersenne, 29)].res64.as_deref();
+        assert_eq!((entries.len(), res64), (2, Some("0000000000000002")));
+
+        let csv = format!("{},run_id\n", CSV.lines().next().unwrap())
+            + "29,range,LL,false,0000000000000001,27,1.000,27.0,,,,1,a\n"
+            + "31,range,,true,0000000000000000,29,0.500,58.0,,,,1,b\n";
+        let (_, entries) = read_entries(&csv, Some(&a)).unwrap();
+        assert_eq!(entries.keys().collect::<Vec<_>>(), [&(Form::Mersenne, 29)]);
+        // A file without run IDs has nothing of any one run.
+        assert!(read_entries(CSV, Some(&a)).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn the_median_ratio_of_an_even_count_is_between_the_middle_two() {
+        let timing = Timing::of([(1.0, 1.0), (1.0, 2.0), (2.0, 1.0), (1.0, 4.0), (0.0, 1.0)]);
+        let timing = timing.unwrap();
+        assert_eq!(timing.compared, 4);
+        assert_eq!(timing.median_ratio, 1.5);
+        assert_eq!((timing.min_ratio, timing.max_ratio), (0.5, 4.0));
+        assert_eq!((timing.faster, timing.slower), (1, 2));
+        assert_eq!(Timing::of([(0.0, 0.0)]), None);
+    }
+}
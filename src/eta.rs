@@ -0,0 +1,210 @@
+//! Estimates of how long the rest of a run will take.
+//!
+//! A Lucas–Lehmer test of `M(p)` does `p` squarings of `p`-bit numbers, so
+//! its cost grows roughly as `p² · log p`. The estimate sums that cost over
+//! the exponents still to test and converts it to seconds with a rate
+//! measured from the tests completed so far, or from a short benchmark
+//! before the first one finishes. Other forms are costed by the size of
+//! their residues, [`Form::bits`], in place of `p`.
+//!
+//! A run over a huge range starts testing before it knows all of its
+//! exponents: they are [discovered](Eta::discover) in the background, and
+//! there is no estimate until the last one has been.
+
+use crate::bench;
+use crate::progress::format_duration;
+use chrono::{Duration, Local};
+use mersenne::arith::MersenneModulus;
+use mersenne::report::{Form, TestReport};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Relative cost of testing `M(p)`.
+pub fn cost(p: u64) -> f64 {
+    let p = p as f64;
+    p * p * p.ln().max(1.0)
+}
+
+/// Progress of a run, shared between the workers that record results and
+/// whoever prints the estimate.
+pub struct Eta {
+    form: Form,
+    threads: usize,
+    /// The share of a core each test is held to by `--cpu-limit`.
+    share: f64,
+    /// The largest residue size discovered, in bits.
+    largest: AtomicU64,
+    state: Mutex<State>,
+}
+
+struct State {
+    total: usize,
+    done: usize,
+    /// Whether more exponents may still be discovered.
+    discovering: bool,
+    total_cost: f64,
+    done_cost: f64,
+    done_seconds: f64,
+    /// Seconds per unit of cost from the start-up benchmark, used until the
+    /// first test completes.
+    benchmark_rate: Option<f64>,
+}
+
+impl Eta {
+    /// Sets up an estimate for testing `form` numbers on `threads`
+    /// threads, whose exponents are yet to be passed to [`Eta::discover`].
+    pub fn new(form: Form, threads: usize) -> Eta {
+        Eta {
+            form,
+            threads: threads.max(1),
+            share: 1.0,
+            largest: AtomicU64::new(0),
+            state: Mutex::new(State {
+                total: 0,
+                done: 0,
+                discovering: true,
+                total_cost: 0.0,
+                done_cost: 0.0,
+                done_seconds: 0.0,
+                benchmark_rate: None,
+            }),
+        }
+    }
+
+    /// The same estimate for tests held to `share` of a core each, which
+    /// take that much longer than the benchmark says.
+    pub fn throttled_to(self, share: f64) -> Eta {
+        Eta { share, ..self }
+    }
+
+    /// Adds `p` to the exponents to test.
+    pub fn discover(&self, p: u64) {
+        let bits = self.form.bits(p);
+        self.largest.fetch_max(bits, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        state.total += 1;
+        state.total_cost += cost(bits);
+    }
+
+    /// Marks every exponent as discovered, so the estimate can be made.
+    pub fn discovered(&self) {
+        self.state.lock().unwrap().discovering = false;
+    }
+
+    /// Whether exponents are still being discovered.
+    pub fn is_discovering(&self) -> bool {
+        self.state.lock().unwrap().discovering
+    }
+
+    /// The exponents discovered so far.
+    pub fn total(&self) -> usize {
+        self.state.lock().unwrap().total
+    }
+
+    /// The cost of every exponent discovered so far, added up.
+    pub fn total_cost(&self) -> f64 {
+        self.state.lock().unwrap().total_cost
+    }
+
+    /// Exponents done so far, and the total.
+    pub fn progress(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.done, state.total)
+    }
+
+    /// Times a few iterations at the largest exponent to get a first rate,
+    /// slowed to the share of a core the tests get. The resulting estimate
+    /// ignores trial factoring, so it errs on the long side. The rate
+    /// measured once tests finish takes in their throttle as it is.
+    pub fn calibrate(&self) {
+        let p = self.largest.load(Ordering::Relaxed);
+        let iterations = if p > 2_000_000 { 2 } else { 20 };
+        let per_second = bench::measure(&MersenneModulus::new(p), iterations);
+        let seconds = p.saturating_sub(2) as f64 / per_second / self.share;
+        self.state.lock().unwrap().benchmark_rate = Some(seconds / cost(p));
+    }
+
+    /// Accounts for a finished exponent. One eliminated by a factor cost
+    /// next to nothing, so it leaves the total instead of making the rate
+    /// look faster.
+    pub fn record(&self, report: &TestReport) {
+        let mut state = self.state.lock().unwrap();
+        let cost = cost(report.form.bits(report.exponent));
+        state.done += 1;
+        if report.factor.is_some() {
+            state.total_cost -= cost;
+            return;
+        }
+        state.done_cost += cost;
+        state.done_seconds += report.seconds;
+    }
+
+    /// Estimated wall-clock seconds left, if there is any basis for one
+    /// and every exponent has been discovered.
+    pub fn remaining_seconds(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        if state.discovering {
+            return None;
+        }
+        let rate = if state.done_cost > 0.0 && state.done_seconds > 0.0 {
+            state.done_seconds / state.done_cost
+        } else {
+            state.benchmark_rate?
+        };
+        Some(self.seconds_at(&state, rate))
+    }
+
+    /// Estimated wall-clock seconds left at the rate of the start-up
+    /// benchmark, whatever the tests have done since. Exponents that
+    /// finished early, trial factoring them before the estimate is made,
+    /// say little of how long the rest take.
+    pub fn benchmark_seconds(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        if state.discovering {
+            return None;
+        }
+        Some(self.seconds_at(&state, state.benchmark_rate?))
+    }
+
+    fn seconds_at(&self, state: &State, rate: f64) -> f64 {
+        let remaining_cost = (state.total_cost - state.done_cost).max(0.0);
+        rate * remaining_cost / self.threads as f64
+    }
+
+    /// For example `12 exponents done, 829 to go, ETA 2025-07-03 14:20 (3d 02h remaining)`,
+    /// or `12 exponents done, candidates discovered so far: 9120` while
+    /// they are still being discovered.
+    pub fn status_line(&self) -> String {
+        let (done, total) = self.progress();
+        if self.is_discovering() {
+            return format!(
+                "{} exponents done, candidates discovered so far: {}",
+                done, total
+            );
+        }
+        match self.remaining_seconds() {
+            Some(seconds) => format!(
+                "{} exponents done, {} to go, ETA {} ({} remaining)",
+                done,
+                total.saturating_sub(done),
+                finish_time(seconds),
+                format_duration(seconds)
+            ),
+            None => format!(
+                "{} exponents done, {} to go, no ETA yet",
+                done,
+                total.saturating_sub(done)
+            ),
+        }
+    }
+}
+
+/// The local time `seconds` from now, to the minute.
+pub fn finish_time(seconds: f64) -> String {
+    let finish = Duration::try_seconds(seconds.min(1e15) as i64)
+        .and_then(|ahead| Local::now().checked_add_signed(ahead));
+    match finish {
+        Some(finish) => finish.format("%Y-%m-%d %H:%M").to_string(),
+        None => "never".to_string(),
+    }
+}
@@ -0,0 +1,248 @@
+//! The event stream written with `--events`: one JSON object per line for
+//! each moment of a run worth watching from outside, for log shippers such
+//! as Vector or Loki.
+//!
+//! Every event has a `timestamp`, in UTC with milliseconds as in
+//! `2024-05-01T12:00:00.000Z`, the `run` ID of the run that sent it (see
+//! [`mersenne::run`]), and an `event` naming it:
+//!
+//! - `run_started`: the `source` of the exponents, as in the CSV file of
+//!   `--csv` (`range`, `list`, `stdin`, `worktodo` or `server`), the
+//!   `form` (with `k` for Riesel numbers), the `start` and `end` exponents
+//!   and the `threads` testing;
+//! - `candidate_generated`: an `exponent` the run will test and its
+//!   `index` among them, from 0; only every
+//!   [`CANDIDATE_SAMPLE`]th is sent;
+//! - `test_started`: the `exponent`, the `test`, such as `LL` or `PRP`,
+//!   and its `iterations`, once factoring has not eliminated it;
+//! - `checkpoint_written`: the `exponent` and `iteration` saved;
+//! - `test_progress`: the `exponent`, the `iteration` reached of `total`
+//!   and `iters_per_second` since the test's previous `test_progress`, or
+//!   since it started; at most one every [`PROGRESS_INTERVAL`] per test,
+//!   the first included;
+//! - `test_finished`: the `exponent`, whether it is `prime`, the `stage`
+//!   that eliminated it (`TF`, `P-1` or the test) if any, its `res64` and
+//!   `factor` if it has them, the `seconds` spent on it, and whether its
+//!   test `timed_out` or `failed`;
+//! - `prime_found`: the `exponent`, the `test` and the `digits` of the
+//!   prime, right after its `test_finished`;
+//! - `run_finished`: the counts `tested`, `primes`, `factored`,
+//!   `composite` and `failed`, the `seconds` the run took and its
+//!   `outcome`, `done`, `interrupted` or `time_limit`;
+//! - `error`: the `message` of an error, as shown on stderr.
+//!
+//! Event names and fields stay as they are, so queries can rely on them;
+//! new ones may be added. A field with nothing to say is left out rather
+//! than `null`. With `--events -` the events go to stdout and the results
+//! that would go there go to stderr instead.
+//!
+//! The iterations tested in each hour, from the progress of every test:
+//!
+//! ```text
+//! jq -s 'map(select(.event == "test_progress"))
+//!   | group_by(.exponent)
+//!   | map(. as $test | range(1; length)
+//!       | {hour: $test[.].timestamp[:13],
+//!          iterations: ($test[.].iteration - $test[. - 1].iteration)})
+//!   | flatten | group_by(.hour)
+//!   | map({hour: .[0].hour, iterations_per_second: (map(.iterations) | add / 3600)})' events.jsonl
+//! ```
+
+use chrono::Utc;
+use mersenne::report::{Form, RunSummary, TestKind, TestReport};
+use mersenne::run::RunId;
+use mersenne::Progress;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Every how many candidates a `candidate_generated` event is sent.
+pub const CANDIDATE_SAMPLE: u64 = 1000;
+
+/// The least time between two `test_progress` events of one test.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Where events go, once [`init`] has opened it, and the run they are of.
+static STREAM: Mutex<Option<(Box<dyn Write + Send>, RunId)>> = Mutex::new(None);
+
+/// Set while the events have stdout to themselves.
+static ON_STDOUT: AtomicBool = AtomicBool::new(false);
+
+/// One event; the variants and their fields are only ever added to.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    RunStarted {
+        source: &'a str,
+        #[serde(flatten)]
+        form: Form,
+        start: u64,
+        end: u64,
+        threads: usize,
+    },
+    CandidateGenerated {
+        exponent: u64,
+        index: u64,
+    },
+    TestStarted {
+        exponent: u64,
+        test: TestKind,
+        iterations: u64,
+    },
+    CheckpointWritten {
+        exponent: u64,
+        iteration: u64,
+    },
+    TestProgress {
+        exponent: u64,
+        iteration: u64,
+        total: u64,
+        iters_per_second: f64,
+    },
+    TestFinished {
+        exponent: u64,
+        prime: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        stage: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        res64: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        factor: Option<&'a str>,
+        seconds: f64,
+        timed_out: bool,
+        failed: bool,
+    },
+    PrimeFound {
+        exponent: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        test: Option<TestKind>,
+        digits: u64,
+    },
+    RunFinished {
+        tested: usize,
+        primes: usize,
+        factored: usize,
+        composite: usize,
+        failed: usize,
+        seconds: f64,
+        outcome: &'a str,
+    },
+    Error {
+        message: &'a str,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: String,
+    run: &'a RunId,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Sends the events of run `run` to `path` from now on, or to stdout if it
+/// is `-`. The file is appended to, so several runs can share it.
+pub fn init(path: &Path, run: &RunId) -> io::Result<()> {
+    let stream: Box<dyn Write + Send> = if path == Path::new("-") {
+        ON_STDOUT.store(true, Ordering::SeqCst);
+        Box::new(io::stdout())
+    } else {
+        Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+    };
+    *STREAM.lock().unwrap() = Some((stream, run.clone()));
+    Ok(())
+}
+
+/// Whether the events have stdout, so results go to stderr.
+pub fn on_stdout() -> bool {
+    ON_STDOUT.load(Ordering::SeqCst)
+}
+
+/// Writes `event`, if there is an event stream.
+pub fn emit(event: Event) {
+    let mut stream = STREAM.lock().unwrap();
+    let Some((stream, run)) = stream.as_mut() else {
+        return;
+    };
+    let line = Line {
+        timestamp: Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        run,
+        event: &event,
+    };
+    let line = serde_json::to_string(&line).expect("events serialize");
+    // Like the log file, there is nowhere left to report a failed write.
+    let _ = writeln!(stream, "{}", line).and_then(|()| stream.flush());
+}
+
+/// Writes `test_finished` for `report`, and `prime_found` if it is prime.
+pub fn finished(report: &TestReport) {
+    let stage = match (report.factor_stage, report.test) {
+        (Some(stage), _) => Some(stage.as_str()),
+        (None, Some(test)) if !report.prime && report.has_result() => Some(test.as_str()),
+        _ => None,
+    };
+    emit(Event::TestFinished {
+        exponent: report.exponent,
+        prime: report.prime,
+        stage,
+        res64: report.res64.as_deref(),
+        factor: report.factor.as_deref(),
+        seconds: report.seconds,
+        timed_out: report.is_timed_out(),
+        failed: report.is_failed(),
+    });
+    if report.prime {
+        emit(Event::PrimeFound {
+            exponent: report.exponent,
+            test: report.test,
+            digits: report.form.digit_count(report.exponent),
+        });
+    }
+}
+
+/// Writes `run_finished` for `summary`, of a run that was `interrupted`,
+/// perhaps by its time limit running out.
+pub fn run_finished(summary: &RunSummary, interrupted: bool, time_up: bool) {
+    emit(Event::RunFinished {
+        tested: summary.tested,
+        primes: summary.primes.len(),
+        factored: summary.factored,
+        composite: summary.composite,
+        failed: summary.failed.len(),
+        seconds: summary.seconds,
+        outcome: match (time_up, interrupted) {
+            (true, _) => "time_limit",
+            (false, true) => "interrupted",
+            (false, false) => "done",
+        },
+    });
+}
+
+/// Writes `test_progress` for `report` on `p`, unless one was written
+/// less than [`PROGRESS_INTERVAL`] before. `last` holds when the test's
+/// previous one was written and at which iteration.
+pub fn progress(p: u64, report: &Progress, last: &mut Option<(Instant, u64)>) {
+    let (seconds, iterations) = match *last {
+        Some((at, _)) if at.elapsed() < PROGRESS_INTERVAL => return,
+        Some((at, iteration)) => (
+            at.elapsed().as_secs_f64(),
+            report.iteration.saturating_sub(iteration),
+        ),
+        None => (report.elapsed.as_secs_f64(), report.iteration),
+    };
+    *last = Some((Instant::now(), report.iteration));
+    emit(Event::TestProgress {
+        exponent: p,
+        iteration: report.iteration,
+        total: report.total,
+        iters_per_second: if seconds > 0.0 {
+            iterations as f64 / seconds
+        } else {
+            0.0
+        },
+    });
+}
@@ -0,0 +1,295 @@
+//! Cheap factoring stages run before the Lucas–Lehmer test.
+
+use crate::arith::MersenneModulus;
+use crate::sieve;
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::One;
+use std::collections::HashMap;
+
+/// Small primes used to sieve out factor candidates with obvious divisors.
+const SIEVE_PRIMES: [u64; 10] = [3, 5, 7, 11, 13, 17, 19, 23, 29, 31];
+
+/// Searches for a factor of `2^p - 1` below `2^bit_depth`.
+///
+/// Any factor `q` of `M(p)` for odd prime `p` has the form `2kp + 1` and
+/// satisfies `q ≡ ±1 (mod 8)`, so only those candidates are tried, and those
+/// divisible by a small prime are skipped. The search also stops at
+/// `sqrt(M(p))`, so a prime `M(p)` is never reported as its own factor.
+/// Returns the smallest factor found. `bit_depth` is capped at 64.
+pub fn trial_factor(p: u64, bit_depth: u32) -> Option<u64> {
+    search(p, bit_depth, Sign::Minus)
+}
+
+/// Searches for a factor of the Wagstaff number `(2^p + 1) / 3` below
+/// `2^bit_depth`, for prime `p`.
+///
+/// A factor `q` has `2^p ≡ -1 (mod q)`, so 2 has order `2p` and `q` has the
+/// form `2kp + 1` here too; since `-2 ≡ 2^(p+1)` is then a square, `q ≡ 1`
+/// or `3 (mod 8)`. As with [`trial_factor`], the search stops at the
+/// square root and returns the smallest factor found.
+pub fn wagstaff_trial_factor(p: u64, bit_depth: u32) -> Option<u64> {
+    search(p, bit_depth, Sign::Plus)
+}
+
+/// Which of `2^p - 1` and `2^p + 1` a search is for.
+#[derive(Clone, Copy)]
+enum Sign {
+    Minus,
+    Plus,
+}
+
+fn search(p: u64, bit_depth: u32, sign: Sign) -> Option<u64> {
+    if p < 3 || bit_depth == 0 {
+        return None;
+    }
+    let limit: u128 = 1u128 << bit_depth.min(64);
+    let step = 2 * p as u128;
+    // Wagstaff numbers are about a third of 2^p, so 3·q^2 is compared
+    // with 2^p for them.
+    let scale: u128 = match sign {
+        Sign::Minus => 1,
+        Sign::Plus => 3,
+    };
+
+    let mut q = step + 1;
+    while q < limit {
+        if p < 128 && (q * q).saturating_mul(scale) >= 1u128 << p {
+            break;
+        }
+        let q64 = q as u64;
+        // The candidate filter is cheap, so it goes first and spares most
+        // candidates the modular exponentiation.
+        if is_candidate(q64, sign)
+            && match sign {
+                Sign::Minus => pow2_mod(p, q64) == 1,
+                Sign::Plus => pow2_mod(p, q64) == q64 - 1,
+            }
+        {
+            return Some(q64);
+        }
+        q += step;
+    }
+    None
+}
+
+/// The deepest trial factoring worth doing before a Lucas–Lehmer test of `p`.
+///
+/// Searching to `2^b` tries about `2^b / 2p` candidates, while the LL test
+/// costs about `p * (p / 64)^2` limb operations. Past the depth where the two
+/// meet, it is cheaper to just run the LL test, which matters for the many
+/// small exponents in a sweep.
+pub fn worthwhile_tf_depth(p: u64) -> u32 {
+    let ll_cost = (p as f64).powi(3) / 4096.0;
+    (2.0 * p as f64 * ll_cost).log2().max(0.0) as u32
+}
+
+/// Runs Pollard's P−1 method on `2^p - 1` with bounds `b1` and `b2`.
+///
+/// Every factor `q` of `M(p)` has `q - 1 = 2kp`, so stage 1 computes
+/// `x = 3^(2p·E) mod M(p)`, where `E` is the product of every prime power up
+/// to `b1`, and finds `q` in `gcd(x - 1, M(p))` whenever `k` is `b1`-smooth.
+/// Stage 2 then catches `k` with all but one prime factor below `b1` and the
+/// remaining one in `(b1, b2]`. A `b2` of at most `b1` skips stage 2.
+///
+/// Returns the factor found, which may be a product of several primes, or
+/// `None` if the gcd is trivial. It is `None` too if every factor turns up
+/// at once, since the gcd is then `M(p)` itself.
+pub fn pminus1(p: u64, b1: u64, b2: u64) -> Option<BigUint> {
+    if p < 3 || b1 == 0 {
+        return None;
+    }
+    let modulus = MersenneModulus::new(p);
+
+    let mut x = pow_mod(&modulus, &BigUint::from(3u32), 2 * p);
+    for q in sieve::primes(2, b1) {
+        let mut power = q;
+        while power <= b1 / q {
+            power *= q;
+        }
+        x = pow_mod(&modulus, &x, power);
+    }
+    if let Some(factor) = nontrivial_gcd(&modulus, &x - 1u32) {
+        return Some(factor);
+    }
+    if b2 <= b1 {
+        return None;
+    }
+
+    // Stage 2 steps from one prime q to the next by multiplying x^q by
+    // x^gap. Gaps between primes are small and even, so their powers are
+    // cached.
+    let mut gaps: HashMap<u64, BigUint> = HashMap::new();
+    let mut primes = sieve::primes(b1 + 1, b2);
+    let first = primes.next()?;
+    let mut x_q = pow_mod(&modulus, &x, first);
+    let mut product = &x_q - 1u32;
+    let mut previous = first;
+    for q in primes {
+        let step = gaps
+            .entry(q - previous)
+            .or_insert_with(|| pow_mod(&modulus, &x, q - previous));
+        x_q = modulus.mul(&x_q, step);
+        product = modulus.mul(&product, &(&x_q - 1u32));
+        previous = q;
+    }
+    nontrivial_gcd(&modulus, product)
+}
+
+/// `gcd(n, M(p))`, if it is neither 1 nor `M(p)`.
+fn nontrivial_gcd(modulus: &MersenneModulus, n: BigUint) -> Option<BigUint> {
+    let g = n.gcd(modulus.modulus());
+    if g.is_one() || g == *modulus.modulus() {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+/// `x^e mod M(p)` by left-to-right binary exponentiation.
+fn pow_mod(modulus: &MersenneModulus, x: &BigUint, e: u64) -> BigUint {
+    let mut result = BigUint::one();
+    for bit in (0..64 - e.leading_zeros()).rev() {
+        result = modulus.square(&result);
+        if (e >> bit) & 1 == 1 {
+            result = modulus.mul(&result, x);
+        }
+    }
+    result
+}
+
+fn is_candidate(q: u64, sign: Sign) -> bool {
+    let allowed = match sign {
+        Sign::Minus => [1, 7],
+        Sign::Plus => [1, 3],
+    };
+    if !allowed.contains(&(q % 8)) {
+        return false;
+    }
+    SIEVE_PRIMES
+        .iter()
+        .all(|&prime| q == prime || !q.is_multiple_of(prime))
+}
+
+/// Computes `2^p mod q` by left-to-right binary exponentiation.
+fn pow2_mod(p: u64, q: u64) -> u64 {
+    let mut result: u64 = 1;
+    if q <= u32::MAX as u64 {
+        for bit in (0..64 - p.leading_zeros()).rev() {
+            result = result * result % q;
+            if (p >> bit) & 1 == 1 {
+                result = (result << 1) % q;
+            }
+        }
+    } else {
+        let q = q as u128;
+        let mut wide = result as u128;
+        for bit in (0..64 - p.leading_zeros()).rev() {
+            wide = wide * wide % q;
+            if (p >> bit) & 1 == 1 {
+                wide = (wide << 1) % q;
+            }
+        }
+        result = wide as u64;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_smallest_factors_of_composite_mersenne_numbers() {
+        assert_eq!(trial_factor(11, 32), Some(23));
+        assert_eq!(trial_factor(23, 32), Some(47));
+        assert_eq!(trial_factor(29, 32), Some(233));
+        assert_eq!(trial_factor(37, 32), Some(223));
+        assert_eq!(trial_factor(59, 32), Some(179951));
+        assert_eq!(trial_factor(67, 32), Some(193707721));
+    }
+
+    #[test]
+    fn respects_bit_depth() {
+        // 193707721 is a 28-bit number.
+        assert_eq!(trial_factor(67, 27), None);
+        assert_eq!(trial_factor(67, 28), Some(193707721));
+        assert_eq!(trial_factor(67, 0), None);
+    }
+
+    #[test]
+    fn finds_factors_of_wagstaff_numbers() {
+        // W(29) = 59 * 3033169, W(37) = 1777 * 25781083.
+        assert_eq!(wagstaff_trial_factor(29, 32), Some(59));
+        assert_eq!(wagstaff_trial_factor(37, 32), Some(1777));
+        for p in [3, 5, 7, 11, 13, 17, 19, 23, 31, 43] {
+            assert_eq!(wagstaff_trial_factor(p, 40), None, "W({}) is prime", p);
+        }
+        for p in (41..200).filter(|&p| crate::is_prime(p)) {
+            if let Some(q) = wagstaff_trial_factor(p, 20) {
+                let w = crate::number::wagstaff_number(p);
+                assert_eq!(w % q, BigUint::from(0u32), "{} divides W({})", q, p);
+            }
+        }
+    }
+
+    #[test]
+    fn worthwhile_depth_grows_with_exponent() {
+        assert!(worthwhile_tf_depth(100) < 20);
+        assert!(worthwhile_tf_depth(1000) < 32);
+        assert!(worthwhile_tf_depth(3000) > 32);
+        assert!(worthwhile_tf_depth(100_003) >= 55);
+    }
+
+    #[test]
+    fn finds_nothing_for_mersenne_primes() {
+        for p in [2, 3, 5, 7, 13, 17, 19, 31, 61] {
+            assert_eq!(trial_factor(p, 40), None, "M({}) is prime", p);
+        }
+    }
+
+    #[test]
+    fn factors_above_32_bits_use_wide_arithmetic() {
+        // M(67) = 193707721 * 761838257287; the 40-bit cofactor takes the
+        // u128 path.
+        assert_eq!(pow2_mod(67, 761838257287), 1);
+        assert_eq!(pow2_mod(67, 193707721), 1);
+        assert_ne!(pow2_mod(67, 761838257289), 1);
+    }
+
+    // The factors used below, with k = (q - 1) / 2p:
+    //   M(67): 193707721, k = 2^2 · 3^3 · 5 · 2677
+    //          761838257287, k = 3^2 · 29 · 2551 · 8539
+    //   M(71): 228479, k = 1609
+
+    #[test]
+    fn pminus1_stage1_finds_smooth_factors() {
+        assert_eq!(pminus1(67, 3000, 0), Some(BigUint::from(193707721u32)));
+        assert_eq!(pminus1(71, 2000, 0), Some(BigUint::from(228479u32)));
+        assert_eq!(pminus1(67, 1000, 0), None);
+    }
+
+    #[test]
+    fn pminus1_stage2_finds_one_larger_prime() {
+        assert_eq!(pminus1(67, 1000, 3000), Some(BigUint::from(193707721u32)));
+        assert_eq!(pminus1(67, 1000, 2000), None);
+    }
+
+    #[test]
+    fn pminus1_finds_nothing_for_mersenne_primes() {
+        for p in [3, 5, 7, 13, 17, 19, 31, 61, 89, 107, 127] {
+            assert_eq!(pminus1(p, 1000, 5000), None, "M({}) is prime", p);
+        }
+    }
+
+    #[test]
+    fn pow_mod_matches_repeated_multiplication() {
+        let modulus = MersenneModulus::new(61);
+        let x = BigUint::from(123456789u32);
+        let mut expected = BigUint::one();
+        for _ in 0..100 {
+            expected = modulus.mul(&expected, &x);
+        }
+        assert_eq!(pow_mod(&modulus, &x, 100), expected);
+    }
+}
@@ -0,0 +1,135 @@
+//! Pépin's test of Fermat numbers `F(n) = 2^(2^n) + 1`.
+//!
+//! For `n >= 1`, `F(n)` is prime exactly when
+//! `3^((F(n) - 1) / 2) ≡ -1 (mod F(n))`. The power is `2^(2^n - 1)`, so
+//! the test is `2^n - 1` squarings of 3 modulo `F(n)`: the squaring chain
+//! of the PRP test in [`crate::prp`], Gerbicz checks included, run in the
+//! arithmetic modulo `2^p + 1` with `p = 2^n`. Unlike a PRP test, the
+//! answer is a proof either way.
+
+use crate::arith::WagstaffModulus;
+use crate::prp::{self, GerbiczParams, PrpModulus, PrpResult};
+use crate::{res64, Interrupted, TestControl, TestEvent};
+use num_bigint::BigUint;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+
+/// The outcome of Pépin's test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PepinResult {
+    Prime,
+    /// `res64` is the low 64 bits of `3^((F(n) - 1) / 2) mod F(n)`.
+    Composite {
+        res64: u64,
+    },
+}
+
+impl PepinResult {
+    pub fn is_prime(&self) -> bool {
+        matches!(self, PepinResult::Prime)
+    }
+}
+
+impl fmt::Display for PepinResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PepinResult::Prime => write!(f, "prime"),
+            PepinResult::Composite { res64 } => write!(f, "composite, Res64: 0x{:016X}", res64),
+        }
+    }
+}
+
+/// Arithmetic modulo `F(n)`, with Pépin's verdict on the final residue.
+struct FermatModulus(WagstaffModulus);
+
+impl PrpModulus for FermatModulus {
+    fn iterations(&self) -> u64 {
+        self.0.p() - 1
+    }
+
+    fn modulus(&self) -> &BigUint {
+        self.0.modulus()
+    }
+
+    fn square(&self, x: &BigUint) -> BigUint {
+        self.0.square(x)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        self.0.mul(a, b)
+    }
+
+    fn verdict(&self, x: &BigUint) -> PrpResult {
+        let res64 = res64(x);
+        if *x == self.0.modulus() - 1u32 {
+            PrpResult::ProbablePrime { res64 }
+        } else {
+            PrpResult::Composite { res64 }
+        }
+    }
+}
+
+/// Runs Pépin's test on `F(n)`, for `n < 64`.
+pub fn pepin_test(n: u64) -> PepinResult {
+    let never = AtomicBool::new(false);
+    match pepin_test_interruptible(n, TestControl::new(&never), |_| {}) {
+        Ok(result) => result,
+        Err(_) => unreachable!("the stop flag is never raised"),
+    }
+}
+
+/// Same as [`pepin_test`], but with the events and interruption of
+/// [`prp::prp_test_interruptible`]. Progress counts the `2^n - 1`
+/// squarings.
+pub fn pepin_test_interruptible<F>(
+    n: u64,
+    control: TestControl,
+    on_event: F,
+) -> Result<PepinResult, Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    assert!(n < 64, "F({}) is too large to test", n);
+    // 3 divides F(0) = 3 itself, so base 3 cannot say anything about it.
+    if n == 0 {
+        return Ok(PepinResult::Prime);
+    }
+    let modulus = FermatModulus(WagstaffModulus::new(1 << n).with_threads(control.threads));
+    let params = GerbiczParams::for_exponent(modulus.iterations());
+    let result = prp::run(&modulus, params, control, on_event, |_, _| {})?;
+    Ok(match result {
+        PrpResult::ProbablePrime { .. } => PepinResult::Prime,
+        PrpResult::Composite { res64 } => PepinResult::Composite { res64 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_fermat_primes() {
+        for n in 0..=4 {
+            assert_eq!(pepin_test(n), PepinResult::Prime, "F({})", n);
+        }
+        let composites = [
+            (5, 0x9D894F),
+            (6, 0xA497F7120F395E35),
+            (7, 0x95984E80E902C504),
+            (8, 0x6507E50AC84D66B3),
+        ];
+        for (n, res64) in composites {
+            assert_eq!(pepin_test(n), PepinResult::Composite { res64 }, "F({})", n);
+        }
+    }
+
+    #[test]
+    fn splitting_squarings_does_not_change_the_result() {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).split_across(3);
+        assert_eq!(
+            pepin_test_interruptible(10, control, |_| {}),
+            Ok(pepin_test(10))
+        );
+    }
+}
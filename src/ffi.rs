@@ -0,0 +1,228 @@
+//! A C interface to the Lucas–Lehmer test, built with the `ffi` feature.
+//!
+//! The crate then also builds as a C library (`libmersenne.so`,
+//! `mersenne.dll` or `libmersenne.dylib`), and `include/mersenne.h`,
+//! generated from this module by the build script, declares it:
+//!
+//! ```c
+//! #include "mersenne.h"
+//!
+//! uint64_t res64;
+//! if (mersenne_res64(127, &res64) == MERSENNE_PRIME) { ... }
+//! ```
+//!
+//! The tests return one of the `MERSENNE_*` codes. A panic never
+//! crosses into C: it is caught and returned as [`MERSENNE_PANIC`].
+
+use crate::{LlResult, TestControl, TestEvent};
+use std::ffi::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `M(p)` is composite.
+pub const MERSENNE_COMPOSITE: i32 = 0;
+/// `M(p)` is prime.
+pub const MERSENNE_PRIME: i32 = 1;
+/// The test was cancelled through its [`MersenneCancel`] token.
+pub const MERSENNE_CANCELLED: i32 = -1;
+/// `p` is not prime, so there is no Lucas–Lehmer test of `M(p)`; it is
+/// composite.
+pub const MERSENNE_INVALID_EXPONENT: i32 = -2;
+/// A pointer that must not be null was null.
+pub const MERSENNE_NULL_POINTER: i32 = -3;
+/// The library panicked. This is a bug in it.
+pub const MERSENNE_PANIC: i32 = -4;
+/// The residue kept failing its sanity checks, which only hardware or
+/// arithmetic errors cause, so the test gave up without a result.
+pub const MERSENNE_ARITHMETIC_ERROR: i32 = -5;
+
+/// Called with `(iteration, total, user)` about every 1% of a test and on
+/// its final iteration, on the thread running the test. It must not
+/// unwind.
+#[allow(non_camel_case_types)]
+pub type mersenne_progress_cb = Option<unsafe extern "C" fn(u64, u64, *mut c_void)>;
+
+/// A cancellation token. Raising it with [`mersenne_cancel_request`], from
+/// any thread, stops the tests that were given it before their next
+/// iteration.
+pub struct MersenneCancel {
+    cancelled: AtomicBool,
+}
+
+/// Makes a new token, to be freed with [`mersenne_cancel_free`].
+#[no_mangle]
+pub extern "C" fn mersenne_cancel_new() -> *mut MersenneCancel {
+    Box::into_raw(Box::new(MersenneCancel {
+        cancelled: AtomicBool::new(false),
+    }))
+}
+
+/// Raises `token`. Tests given it from now on are cancelled at once. Null
+/// is ignored.
+///
+/// # Safety
+///
+/// `token` must be null or come from [`mersenne_cancel_new`] and not have
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn mersenne_cancel_request(token: *const MersenneCancel) {
+    if let Some(token) = token.as_ref() {
+        token.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Frees `token`. Null is ignored.
+///
+/// # Safety
+///
+/// `token` must be null or come from [`mersenne_cancel_new`], not have
+/// been freed, and not be in use by a running test.
+#[no_mangle]
+pub unsafe extern "C" fn mersenne_cancel_free(token: *mut MersenneCancel) {
+    if !token.is_null() {
+        drop(Box::from_raw(token));
+    }
+}
+
+/// Runs a Lucas–Lehmer test of `M(p)`: [`MERSENNE_PRIME`],
+/// [`MERSENNE_COMPOSITE`] or [`MERSENNE_INVALID_EXPONENT`]. `cb`, if not
+/// null, is called with `user` as the test goes.
+///
+/// # Safety
+///
+/// `cb` must be safe to call with `user`.
+#[no_mangle]
+pub unsafe extern "C" fn mersenne_ll_test(
+    p: u64,
+    cb: mersenne_progress_cb,
+    user: *mut c_void,
+) -> i32 {
+    mersenne_ll_test_cancellable(p, cb, user, std::ptr::null())
+}
+
+/// Same as [`mersenne_ll_test`], but returns [`MERSENNE_CANCELLED`] once
+/// `token`, if not null, is raised.
+///
+/// # Safety
+///
+/// `cb` must be safe to call with `user`, and `token` must be null or a
+/// live token from [`mersenne_cancel_new`].
+#[no_mangle]
+pub unsafe extern "C" fn mersenne_ll_test_cancellable(
+    p: u64,
+    cb: mersenne_progress_cb,
+    user: *mut c_void,
+    token: *const MersenneCancel,
+) -> i32 {
+    match run(p, cb, user, token.as_ref()) {
+        Ok(LlResult::Prime) => MERSENNE_PRIME,
+        Ok(_) => MERSENNE_COMPOSITE,
+        Err(code) => code,
+    }
+}
+
+/// Runs a Lucas–Lehmer test of `M(p)` and stores the low 64 bits of its
+/// final residue in `out`: 0 if `M(p)` is prime. Returns the same codes as
+/// [`mersenne_ll_test`], and leaves `out` alone unless the test finished.
+///
+/// # Safety
+///
+/// `out` must be null or point to writable memory for a `uint64_t`.
+#[no_mangle]
+pub unsafe extern "C" fn mersenne_res64(p: u64, out: *mut u64) -> i32 {
+    let Some(out) = out.as_mut() else {
+        return MERSENNE_NULL_POINTER;
+    };
+    match run(p, None, std::ptr::null_mut(), None) {
+        Ok(LlResult::Prime) => {
+            *out = 0;
+            MERSENNE_PRIME
+        }
+        Ok(LlResult::Composite { res64 }) => {
+            *out = res64;
+            MERSENNE_COMPOSITE
+        }
+        Ok(LlResult::Aborted { .. }) => MERSENNE_CANCELLED,
+        Err(code) => code,
+    }
+}
+
+/// The test behind the exported functions, or the code to return instead.
+unsafe fn run(
+    p: u64,
+    cb: mersenne_progress_cb,
+    user: *mut c_void,
+    token: Option<&MersenneCancel>,
+) -> Result<LlResult, i32> {
+    let never = AtomicBool::new(false);
+    let stop = token.map_or(&never, |token| &token.cancelled);
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        if !crate::primality::is_prime(p) {
+            return Err(MERSENNE_INVALID_EXPONENT);
+        }
+        let result =
+            crate::is_mersenne_prime_interruptible(p, None, TestControl::new(stop), |event| {
+                if let (TestEvent::Progress(progress), Some(cb)) = (event, cb) {
+                    cb(progress.iteration, progress.total, user);
+                }
+            });
+        result.map_err(|interrupted| match interrupted.anomaly {
+            Some(_) => MERSENNE_ARITHMETIC_ERROR,
+            None => MERSENNE_CANCELLED,
+        })
+    }))
+    .unwrap_or(Err(MERSENNE_PANIC))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    unsafe extern "C" fn count(_iteration: u64, _total: u64, user: *mut c_void) {
+        *(user as *mut u64) += 1;
+    }
+
+    unsafe extern "C" fn cancel(_iteration: u64, _total: u64, user: *mut c_void) {
+        mersenne_cancel_request(user as *const MersenneCancel);
+    }
+
+    #[test]
+    fn tests_exponents_and_reports_progress() {
+        let mut calls = 0u64;
+        let user = &mut calls as *mut u64 as *mut c_void;
+        unsafe {
+            assert_eq!(mersenne_ll_test(127, Some(count), user), MERSENNE_PRIME);
+            assert!(calls > 1);
+            assert_eq!(
+                mersenne_ll_test(11, None, ptr::null_mut()),
+                MERSENNE_COMPOSITE
+            );
+            assert_eq!(
+                mersenne_ll_test(15, None, ptr::null_mut()),
+                MERSENNE_INVALID_EXPONENT
+            );
+            let mut res64 = 1;
+            assert_eq!(mersenne_res64(11, &mut res64), MERSENNE_COMPOSITE);
+            assert_eq!(res64, 0x6C8);
+            assert_eq!(mersenne_res64(11, ptr::null_mut()), MERSENNE_NULL_POINTER);
+        }
+    }
+
+    #[test]
+    fn a_raised_token_cancels_the_test() {
+        unsafe {
+            let token = mersenne_cancel_new();
+            let user = token as *mut c_void;
+            assert_eq!(
+                mersenne_ll_test_cancellable(4423, Some(cancel), user, token),
+                MERSENNE_CANCELLED
+            );
+            assert_eq!(
+                mersenne_ll_test_cancellable(7, None, ptr::null_mut(), token),
+                MERSENNE_CANCELLED
+            );
+            mersenne_cancel_free(token);
+        }
+    }
+}
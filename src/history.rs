@@ -0,0 +1,211 @@
+//! The `report` subcommand: what a `--db` results database holds, across
+//! every run and machine that wrote to it, or with `--run` of one run.
+
+use crate::progress::{format_duration, format_work};
+use mersenne::database::{Database, DatabaseError, StoredResult};
+use mersenne::report::Form;
+use serde::Serialize;
+use std::str::FromStr;
+
+/// How `report --export` prints the results.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Export {
+    Csv,
+    /// One JSON object per line, like `--json` output.
+    Json,
+}
+
+impl FromStr for Export {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Export, String> {
+        match s {
+            "csv" => Ok(Export::Csv),
+            "json" => Ok(Export::Json),
+            _ => Err(format!("unknown export format {:?}", s)),
+        }
+    }
+}
+
+/// The columns of a CSV export.
+const CSV_HEADER: &str =
+    "form,k,exponent,status,test,res64,factor,stage,seconds,run,first_recorded,updated,work_units,\
+     run_id";
+
+/// Prints every result of `database` as `format`.
+pub fn export(database: &Database, format: Export) -> Result<(), DatabaseError> {
+    let results = database.results()?;
+    if format == Export::Csv {
+        println!("{}", CSV_HEADER);
+    }
+    for result in &results {
+        match format {
+            Export::Csv => println!("{}", csv_line(result)),
+            Export::Json => {
+                let line = ExportLine {
+                    result,
+                    work_units: result.report.work_units(),
+                };
+                println!("{}", serde_json::to_string(&line).unwrap())
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A result as a line of a JSON export, with its work.
+#[derive(Serialize)]
+struct ExportLine<'a> {
+    #[serde(flatten)]
+    result: &'a StoredResult,
+    work_units: f64,
+}
+
+fn csv_line(result: &StoredResult) -> String {
+    let report = &result.report;
+    let k = match report.form {
+        Form::Riesel { k } => k.to_string(),
+        _ => String::new(),
+    };
+    [
+        report.form.as_str().to_string(),
+        k,
+        report.exponent.to_string(),
+        result.status.to_string(),
+        report.test.map_or("", |test| test.as_str()).to_string(),
+        report.res64.clone().unwrap_or_default(),
+        report.factor.clone().unwrap_or_default(),
+        report
+            .factor_stage
+            .map_or("", |stage| stage.as_str())
+            .to_string(),
+        format!("{:.3}", report.seconds),
+        result.run.map_or_else(String::new, |run| run.to_string()),
+        result.first_recorded.clone(),
+        result.updated.clone(),
+        report.work_units().to_string(),
+        report
+            .run
+            .as_ref()
+            .map_or_else(String::new, |run| run.to_string()),
+    ]
+    .join(",")
+}
+
+/// Prints the runs of `database`, the totals of each range they covered,
+/// its `slowest` slowest results and the work left unfinished.
+pub fn print(database: &Database, slowest: usize) -> Result<(), DatabaseError> {
+    let runs = database.runs()?;
+    let results = database.results()?;
+    let unfinished: Vec<_> = runs.iter().filter(|run| !run.is_finished()).collect();
+    let mut hosts: Vec<&str> = runs.iter().map(|run| run.host.as_str()).collect();
+    hosts.sort_unstable();
+    hosts.dedup();
+    println!(
+        "Runs: {} ({} unfinished), on {} host(s)",
+        runs.len(),
+        unfinished.len(),
+        hosts.len()
+    );
+    let count = |status: &str| {
+        results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    };
+    println!(
+        "Results: {}: {} prime, {} factored, {} composite, {} timed out",
+        results.len(),
+        count("prime"),
+        count("factored"),
+        count("composite"),
+        count("timeout")
+    );
+    let work: f64 = results
+        .iter()
+        .map(|result| result.report.work_units())
+        .sum();
+    println!("Lifetime work: {} units", format_work(work));
+
+    let totals = database.range_totals()?;
+    if !totals.is_empty() {
+        println!("\nTotals per range:");
+        println!(
+            "  {:<28}  {:>8}  {:>6}  {:>8}  {:>9}  {:>9}  {:>12}",
+            "Range", "Tested", "Primes", "Factored", "Composite", "Timed out", "Time"
+        );
+        for range in &totals {
+            let name = format!(
+                "{} to {}",
+                range.form.number(range.start_exponent),
+                range.form.number(range.end_exponent)
+            );
+            println!(
+                "  {:<28}  {:>8}  {:>6}  {:>8}  {:>9}  {:>9}  {:>12}",
+                name,
+                range.tested,
+                range.primes,
+                range.factored,
+                range.composite,
+                range.timed_out,
+                format_duration(range.seconds)
+            );
+        }
+    }
+
+    let slow = database.slowest(slowest)?;
+    if !slow.is_empty() {
+        println!("\nSlowest exponents:");
+        for result in &slow {
+            let report = &result.report;
+            let method = report
+                .test
+                .map(|test| test.as_str())
+                .or(report.factor_stage.map(|stage| stage.as_str()))
+                .unwrap_or("-");
+            println!(
+                "  {:<16} {:<9} {:<5} {:>12}",
+                report.form.number(report.exponent),
+                result.status,
+                method,
+                format_duration(report.seconds)
+            );
+        }
+    }
+
+    let retried = database.unfinished()?;
+    if !unfinished.is_empty() || !retried.is_empty() {
+        println!("\nUnfinished work:");
+        for run in &unfinished {
+            println!(
+                "  run {}{}: {} to {} on {}, started {}, {}",
+                run.id,
+                run.run_id
+                    .as_ref()
+                    .map_or_else(String::new, |id| format!(" ({})", id)),
+                run.form.number(run.start_exponent),
+                run.form.number(run.end_exponent),
+                run.host,
+                run.started,
+                match &run.outcome {
+                    Some(outcome) => format!("stopped: {}", outcome),
+                    None => "never ended (still running, or crashed)".to_string(),
+                }
+            );
+        }
+        for result in &retried {
+            let report = &result.report;
+            let name = report.form.number(report.exponent);
+            if let Some(message) = &report.panic {
+                println!("  {} panicked: {}", name, message);
+                continue;
+            }
+            let (what, iteration) = match report.failed_at {
+                Some(iteration) => ("failed", iteration),
+                None => ("timed out", report.timed_out_at.unwrap_or(0)),
+            };
+            println!("  {} {} at iteration {}", name, what, iteration);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! Just enough HTTP/1.1 for `serve` and `work`: one request per
+//! connection, JSON bodies sized by `Content-Length`, no TLS. See
+//! [`mersenne::coordinator`] for the requests themselves.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Bodies longer than this are refused; reports are a few hundred bytes.
+const MAX_BODY: usize = 1 << 20;
+
+/// How long either side waits on a silent peer.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A request as the server reads it.
+pub struct Request {
+    pub method: String,
+    /// The path without any query string.
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Reads the header lines up to the blank line, returning the first line
+/// and the `Content-Length`.
+fn read_head<R: BufRead>(reader: &mut R) -> io::Result<(String, usize)> {
+    let mut first = String::new();
+    reader.read_line(&mut first)?;
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("connection closed in the headers"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("bad Content-Length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(invalid("body too long"));
+    }
+    Ok((first.trim_end().to_string(), length))
+}
+
+pub fn read_request(stream: &TcpStream) -> io::Result<Request> {
+    let mut reader = BufReader::new(stream);
+    let (first, length) = read_head(&mut reader)?;
+    let mut parts = first.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return Err(invalid(format!("bad request line {:?}", first))),
+    };
+    let path = target.split('?').next().unwrap_or(target);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        body,
+    })
+}
+
+/// Sends the JSON `body` as the response and closes the connection.
+pub fn respond(mut stream: &TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Sends `method path` with `body` serialized as JSON to the server at
+/// `base`, such as `http://host:7070`, and decodes the JSON answer.
+pub fn call<B: Serialize, T: DeserializeOwned>(
+    base: &str,
+    method: &str,
+    path: &str,
+    body: Option<&B>,
+) -> io::Result<T> {
+    let authority = base
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid(format!("{} is not an http:// URL", base)))?
+        .trim_end_matches('/');
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid(format!("{} has no address", authority)))?;
+
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let body = match body {
+        Some(body) => serde_json::to_string(body)?,
+        None => String::new(),
+    };
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        authority,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(stream);
+    let (status_line, length) = read_head(&mut reader)?;
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid(format!("bad status line {:?}", status_line)))?;
+    let mut answer = vec![0; length];
+    reader.read_exact(&mut answer)?;
+    if status != 200 {
+        return Err(invalid(format!(
+            "the server answered {}: {}",
+            status,
+            String::from_utf8_lossy(&answer)
+        )));
+    }
+    Ok(serde_json::from_slice(&answer)?)
+}
@@ -0,0 +1,107 @@
+//! Durations and large numbers written for people: `8d 12h 3m 1s` rather
+//! than `734581.22 seconds`, and `25,860,001` rather than `25860001`.
+
+use std::time::Duration;
+
+/// The units of [`humanize_duration`], largest first; a year is 365 days.
+const UNITS: [(u64, &str); 5] = [
+    (365 * 86400, "y"),
+    (86400, "d"),
+    (3600, "h"),
+    (60, "m"),
+    (1, "s"),
+];
+
+/// `duration` for people: `0s`, `<1ms`, `340ms` and `42.5s` below a
+/// minute, and from a minute on whole seconds from the largest unit down,
+/// as in `5m 3s`, `8d 12h 3m 1s` or `2y 0d 4h 0m 9s`.
+pub fn humanize_duration(duration: Duration) -> String {
+    if duration.is_zero() {
+        return "0s".to_string();
+    }
+    if duration < Duration::from_millis(1) {
+        return "<1ms".to_string();
+    }
+    if duration < Duration::from_secs(1) {
+        return format!("{}ms", duration.as_millis());
+    }
+    let tenths = (duration.as_secs_f64() * 10.0).round() as u64;
+    if tenths < 600 {
+        return format!("{}.{}s", tenths / 10, tenths % 10);
+    }
+    let mut left = (duration.as_secs_f64().round() as u64).max(60);
+    let mut parts = Vec::new();
+    for (size, unit) in UNITS {
+        if left >= size || !parts.is_empty() {
+            parts.push(format!("{}{}", left / size, unit));
+            left %= size;
+        }
+    }
+    parts.join(" ")
+}
+
+/// `n` with its digits in groups of three, as in `25,860,001`.
+pub fn group_digits(n: u64) -> String {
+    group_digits_with(n, ",")
+}
+
+/// `n` with `separator` between its groups of three digits; an empty
+/// separator leaves it as it is.
+pub fn group_digits_with(n: u64, separator: &str) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3 * separator.len());
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(seconds: f64) -> String {
+        humanize_duration(Duration::from_secs_f64(seconds))
+    }
+
+    #[test]
+    fn durations_are_humanized() {
+        assert_eq!(secs(0.0), "0s");
+        assert_eq!(secs(0.0004), "<1ms");
+        assert_eq!(secs(0.001), "1ms");
+        assert_eq!(secs(0.34), "340ms");
+        assert_eq!(secs(0.9999), "999ms");
+        assert_eq!(secs(1.0), "1.0s");
+        assert_eq!(secs(42.46), "42.5s");
+        assert_eq!(secs(59.96), "1m 0s");
+        assert_eq!(secs(303.4), "5m 3s");
+        assert_eq!(secs(3612.0), "1h 0m 12s");
+        assert_eq!(secs(734581.22), "8d 12h 3m 1s");
+        assert_eq!(secs(365.0 * 86400.0 - 1.0), "364d 23h 59m 59s");
+        assert_eq!(secs(365.0 * 86400.0), "1y 0d 0h 0m 0s");
+        assert_eq!(
+            secs(2.0 * 365.0 * 86400.0 + 4.0 * 3600.0 + 9.0),
+            "2y 0d 4h 0m 9s"
+        );
+        assert_eq!(
+            humanize_duration(Duration::from_secs(100 * 365 * 86400 + 1)),
+            "100y 0d 0h 0m 1s"
+        );
+    }
+
+    #[test]
+    fn digits_are_grouped_in_threes() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(1000), "1,000");
+        assert_eq!(group_digits(25860001), "25,860,001");
+        assert_eq!(group_digits(100000), "100,000");
+        assert_eq!(group_digits(u64::MAX), "18,446,744,073,709,551,615");
+        assert_eq!(group_digits_with(25860001, " "), "25 860 001");
+        assert_eq!(group_digits_with(25860001, "'"), "25'860'001");
+        assert_eq!(group_digits_with(25860001, ""), "25860001");
+    }
+}
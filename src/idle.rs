@@ -0,0 +1,182 @@
+//! Staying out of the way of other work on the machine: `--nice`, which
+//! lowers the priority of the run, and `--pause-when-busy` and
+//! `--pause-file`, which raise the [`Pause`] that the tests wait on, as
+//! does a `pause` through the [`control`](crate::control) file or socket.
+//! Each pause and resumption is logged with its time.
+//!
+//! The load that pauses the tests is that of the other programs: the CPU
+//! time of the whole machine, from `/proc/stat`, less the run's own, from
+//! `/proc/self/stat`, over the last second, in cores. The run's own load
+//! thus never pauses it, and while it is paused the measure is the same.
+
+use crate::control;
+use crate::progress::format_duration;
+use log::{info, warn};
+use mersenne::pause::Pause;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How often the load and the pause file are looked at.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Lowers the priority of the calling thread, and of the threads it starts
+/// from now on, to the lowest there is short of idle scheduling: nice 19, or
+/// the below-normal priority class of the whole process on Windows.
+pub fn lower_priority() {
+    match set_low_priority() {
+        Ok(level) => info!("Running at {} priority.", level),
+        Err(e) => warn!(
+            "cannot lower the priority ({}); running at normal priority.",
+            e
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn set_low_priority() -> Result<&'static str, String> {
+    // On Linux this only affects the calling thread, which the threads it
+    // starts take it from.
+    match unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } {
+        0 => Ok("nice 19"),
+        _ => Err(std::io::Error::last_os_error().to_string()),
+    }
+}
+
+#[cfg(windows)]
+fn set_low_priority() -> Result<&'static str, String> {
+    use std::ffi::c_void;
+
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x4000;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcess() -> *mut c_void;
+        fn SetPriorityClass(process: *mut c_void, class: u32) -> i32;
+    }
+    match unsafe { SetPriorityClass(GetCurrentProcess(), BELOW_NORMAL_PRIORITY_CLASS) } {
+        0 => Err(std::io::Error::last_os_error().to_string()),
+        _ => Ok("below-normal"),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn set_low_priority() -> Result<&'static str, String> {
+    Err("not supported on this platform".to_string())
+}
+
+/// When to pause the tests.
+#[derive(Debug, Clone, Copy)]
+pub struct Busy<'a> {
+    /// While other programs keep more than this many cores busy.
+    pub load: Option<f64>,
+    /// While this file exists.
+    pub file: Option<&'a Path>,
+    /// While this is raised, by `pause` through `--control`.
+    pub held: Option<&'a AtomicBool>,
+}
+
+/// Raises and lowers `pause` as `busy` says, checking every second, until
+/// `done` gets a message or is dropped, and lowers it at the end.
+pub fn watch(busy: Busy, pause: &Pause, done: Receiver<()>) {
+    let mut meter = busy.load.and_then(|_| match LoadMeter::new() {
+        Some(meter) => Some(meter),
+        None => {
+            warn!("cannot read the system load from /proc; --pause-when-busy does nothing here.");
+            None
+        }
+    });
+    loop {
+        let load = meter.as_mut().and_then(LoadMeter::sample);
+        let held = busy.held.is_some_and(|held| held.load(Ordering::SeqCst));
+        let reason = match (busy.file, busy.load, load) {
+            _ if held => Some("asked to through --control".to_string()),
+            (Some(file), _, _) if file.exists() => Some(format!("{} exists", file.display())),
+            (_, Some(limit), Some(load)) if load > limit => Some(format!(
+                "other programs are keeping {:.1} CPU(s) busy",
+                load
+            )),
+            _ => None,
+        };
+        match reason {
+            Some(reason) => {
+                if pause.set(true).is_some() {
+                    info!("[{}] Pausing the tests: {}.", control::now(), reason);
+                }
+            }
+            None => resume(pause),
+        }
+        if let Err(RecvTimeoutError::Timeout) = done.recv_timeout(CHECK_INTERVAL) {
+            continue;
+        }
+        resume(pause);
+        return;
+    }
+}
+
+/// Lowers `pause`, saying for how long it was raised.
+fn resume(pause: &Pause) {
+    if let Some(lasted) = pause.set(false) {
+        info!(
+            "[{}] Resuming the tests after {} paused.",
+            control::now(),
+            format_duration(lasted.as_secs_f64())
+        );
+    }
+}
+
+/// The CPU time of other programs between samples, from `/proc`.
+struct LoadMeter {
+    cores: f64,
+    /// Busy and total time of the machine and the run's own time, in
+    /// clock ticks, at the last sample.
+    last: (u64, u64, u64),
+}
+
+impl LoadMeter {
+    fn new() -> Option<LoadMeter> {
+        let stat = fs::read_to_string("/proc/stat").ok()?;
+        let cores = stat
+            .lines()
+            .filter(|line| line.starts_with("cpu") && !line.starts_with("cpu "))
+            .count();
+        Some(LoadMeter {
+            cores: cores.max(1) as f64,
+            last: LoadMeter::read()?,
+        })
+    }
+
+    /// The cores other programs kept busy on average since the last sample.
+    fn sample(&mut self) -> Option<f64> {
+        let now = LoadMeter::read()?;
+        let (busy, total, own) = (
+            now.0.saturating_sub(self.last.0),
+            now.1.saturating_sub(self.last.1),
+            now.2.saturating_sub(self.last.2),
+        );
+        self.last = now;
+        (total > 0).then(|| busy.saturating_sub(own) as f64 / total as f64 * self.cores)
+    }
+
+    /// The busy and total ticks of all cores, and the run's own ticks.
+    fn read() -> Option<(u64, u64, u64)> {
+        let stat = fs::read_to_string("/proc/stat").ok()?;
+        let ticks: Vec<u64> = stat
+            .lines()
+            .next()?
+            .split_whitespace()
+            .skip(1)
+            .map(|field| field.parse().ok())
+            .collect::<Option<_>>()?;
+        // user nice system idle iowait irq softirq steal ...; guest time is
+        // in user time already.
+        let total: u64 = ticks.iter().take(8).sum();
+        let idle = ticks.get(3)? + ticks.get(4).copied().unwrap_or(0);
+        let own = fs::read_to_string("/proc/self/stat").ok()?;
+        // The fields after the parenthesised command name, from the state.
+        let fields: Vec<&str> = own.rsplit_once(')')?.1.split_whitespace().collect();
+        let own = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+        Some((total - idle, total, own))
+    }
+}
@@ -0,0 +1,169 @@
+//! The exponents of the known Mersenne primes.
+
+/// Every `p` for which `M(p)` is known to be prime, in increasing order.
+///
+/// The list is complete up to the largest exponent only as far as the
+/// GIMPS double-checking milestone has reached; there may be undiscovered
+/// Mersenne primes between the last few entries.
+pub const MERSENNE_EXPONENTS: &[u64] = &[
+    2, 3, 5, 7, 13, 17, 19, 31, 61, 89, 107, 127, 521, 607, 1279, 2203, 2281, 3217, 4253, 4423,
+    9689, 9941, 11213, 19937, 21701, 23209, 44497, 86243, 110503, 132049, 216091, 756839, 859433,
+    1257787, 1398269, 2976221, 3021377, 6972593, 13466917, 20996011, 24036583, 25964951, 30402457,
+    32582657, 37156667, 42643801, 43112609, 57885161, 74207281, 77232917, 82589933, 136279841,
+];
+
+/// The year each prime in [`MERSENNE_EXPONENTS`] was discovered, or `None`
+/// for the ones known since antiquity.
+pub const DISCOVERY_YEARS: &[Option<u16>] = &[
+    None,
+    None,
+    None,
+    None,
+    Some(1456),
+    Some(1588),
+    Some(1588),
+    Some(1772),
+    Some(1883),
+    Some(1911),
+    Some(1914),
+    Some(1876),
+    Some(1952),
+    Some(1952),
+    Some(1952),
+    Some(1952),
+    Some(1952),
+    Some(1957),
+    Some(1961),
+    Some(1961),
+    Some(1963),
+    Some(1963),
+    Some(1963),
+    Some(1971),
+    Some(1978),
+    Some(1979),
+    Some(1979),
+    Some(1982),
+    Some(1988),
+    Some(1983),
+    Some(1985),
+    Some(1992),
+    Some(1994),
+    Some(1996),
+    Some(1996),
+    Some(1997),
+    Some(1998),
+    Some(1999),
+    Some(2001),
+    Some(2003),
+    Some(2004),
+    Some(2005),
+    Some(2005),
+    Some(2006),
+    Some(2008),
+    Some(2009),
+    Some(2008),
+    Some(2013),
+    Some(2016),
+    Some(2017),
+    Some(2018),
+    Some(2024),
+];
+
+/// Returns `true` if `M(p)` is a known Mersenne prime.
+pub fn is_known_mersenne_exponent(p: u64) -> bool {
+    MERSENNE_EXPONENTS.binary_search(&p).is_ok()
+}
+
+/// The known Mersenne prime exponents `p` with `start <= p <= end`.
+pub fn known_between(start: u64, end: u64) -> &'static [u64] {
+    let from = MERSENNE_EXPONENTS.partition_point(|&p| p < start);
+    let to = MERSENNE_EXPONENTS.partition_point(|&p| p <= end);
+    &MERSENNE_EXPONENTS[from..to.max(from)]
+}
+
+/// `e^γ`, with `γ` the Euler–Mascheroni constant.
+const E_TO_GAMMA: f64 = 1.781_072_417_990_198;
+
+/// The number of Mersenne primes `M(p)` with `start <= p <= end` that the
+/// Lenstra–Pomerance–Wagstaff conjecture predicts.
+///
+/// The conjecture says the number with `p <= x` grows like
+/// `e^γ / ln 2 · ln x`, so each doubling of the exponent holds about 1.78
+/// of them and each power of ten about 5.92. It is a statement about
+/// averages: the actual counts scatter around it, and it is no use for the
+/// first few exponents.
+pub fn expected_mersenne_primes(start: u64, end: u64) -> f64 {
+    let start = start.max(2);
+    if end < start {
+        return 0.0;
+    }
+    // The integers start..=end cover the interval [start, end + 1).
+    E_TO_GAMMA * ((end as f64 + 1.0) / start as f64).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::is_prime;
+
+    #[test]
+    fn table_is_sorted_and_prime() {
+        assert_eq!(MERSENNE_EXPONENTS.len(), 52);
+        assert!(MERSENNE_EXPONENTS.windows(2).all(|w| w[0] < w[1]));
+        assert!(MERSENNE_EXPONENTS.iter().all(|&p| is_prime(p)));
+        assert_eq!(DISCOVERY_YEARS.len(), MERSENNE_EXPONENTS.len());
+    }
+
+    #[test]
+    fn lookup() {
+        assert!(is_known_mersenne_exponent(2));
+        assert!(is_known_mersenne_exponent(4423));
+        assert!(is_known_mersenne_exponent(136279841));
+        assert!(!is_known_mersenne_exponent(11));
+        assert!(!is_known_mersenne_exponent(4421));
+    }
+
+    #[test]
+    fn known_between_is_inclusive() {
+        assert_eq!(known_between(2, 7), &[2, 3, 5, 7]);
+        assert_eq!(known_between(8, 30), &[13, 17, 19]);
+        assert_eq!(known_between(4423, 4423), &[4423]);
+        assert!(known_between(4424, 9688).is_empty());
+        assert!(known_between(100, 10).is_empty());
+        assert_eq!(known_between(0, u64::MAX).len(), MERSENNE_EXPONENTS.len());
+    }
+
+    #[test]
+    fn expected_counts_follow_the_wagstaff_conjecture() {
+        let close = |a: f64, b: f64| (a - b).abs() < 0.01;
+        // The figures GIMPS quotes: about 1.78 primes per doubling of the
+        // exponent, and 5.92 per power of ten.
+        assert!(close(expected_mersenne_primes(1_000_000, 1_999_999), 1.78));
+        assert!(close(expected_mersenne_primes(1_000_000, 9_999_999), 5.92));
+        assert!(close(expected_mersenne_primes(10, 99), 5.92));
+        assert_eq!(expected_mersenne_primes(100, 99), 0.0);
+        assert_eq!(
+            expected_mersenne_primes(0, 1000),
+            expected_mersenne_primes(2, 1000)
+        );
+
+        // The primes found so far stay within a few of the prediction.
+        for (start, end) in [
+            (2, 10_000),
+            (2, 1_000_000),
+            (2, 10_000_000),
+            (10_000, 1_000_000),
+        ] {
+            let expected = expected_mersenne_primes(start, end);
+            let known = known_between(start, end).len() as f64;
+            assert!(
+                (expected - known).abs() < 3.0,
+                "{} to {}: expected {:.2}, known {}",
+                start,
+                end,
+                expected,
+                known
+            );
+        }
+    }
+}
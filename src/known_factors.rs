@@ -0,0 +1,173 @@
+//! Factors of Mersenne numbers found elsewhere, such as the exports of
+//! mersenne.ca, which `--known-factors` reads so that a run does not test
+//! exponents already shown composite.
+//!
+//! The file holds an exponent and a factor of `M(exponent)` on each line,
+//! separated by a comma:
+//!
+//! ```text
+//! exponent,factor
+//! 37,223
+//! 67,193707721
+//! ```
+//!
+//! A header line, blank lines and lines starting with `#` are skipped. An
+//! exponent may come with several factors, on a line each; one is all it
+//! takes to leave it out, so only the first is kept. Exports run to
+//! millions of lines, so the file is read a line at a time, and the
+//! factors are kept as the text they were given in, to be echoed in the
+//! results.
+//!
+//! Every factor is checked by [`divides_mersenne`] as it is read, which
+//! takes one modular exponentiation, and one that does not divide its
+//! Mersenne number makes a bad line like any other: a mistyped factor
+//! must not turn a prime into a composite.
+
+use num_bigint::BigUint;
+use num_traits::One;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+/// A line of a known factors file that was not read, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadLine {
+    /// Counted from 1.
+    pub line: u64,
+    pub reason: String,
+}
+
+/// A factor for each exponent of a known factors file.
+#[derive(Debug, Clone, Default)]
+pub struct KnownFactors {
+    factors: HashMap<u64, String>,
+}
+
+impl KnownFactors {
+    /// Reads a known factors file from `reader`, handing every line that
+    /// cannot be read, or whose factor does not divide its Mersenne number,
+    /// to `bad` and going on without it.
+    pub fn read(reader: impl BufRead, mut bad: impl FnMut(BadLine)) -> io::Result<KnownFactors> {
+        let mut known = KnownFactors::default();
+        for (number, line) in (1..).zip(reader.lines()) {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || (number == 1 && is_header(line)) {
+                continue;
+            }
+            match parse_line(line) {
+                Ok((p, factor)) => {
+                    known.factors.entry(p).or_insert_with(|| factor.to_string());
+                }
+                Err(reason) => bad(BadLine {
+                    line: number,
+                    reason,
+                }),
+            }
+        }
+        Ok(known)
+    }
+
+    /// The factor of `M(p)` the file gave, if any.
+    pub fn get(&self, p: u64) -> Option<&str> {
+        self.factors.get(&p).map(String::as_str)
+    }
+
+    pub fn contains(&self, p: u64) -> bool {
+        self.factors.contains_key(&p)
+    }
+
+    /// The number of exponents with a factor.
+    pub fn len(&self) -> usize {
+        self.factors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.factors.is_empty()
+    }
+
+    /// Every exponent with its factor, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.factors.iter().map(|(&p, factor)| (p, factor.as_str()))
+    }
+}
+
+/// Whether `line` names the columns rather than giving a factor, as the
+/// first line of an export does.
+fn is_header(line: &str) -> bool {
+    !line.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// The exponent and factor of a line such as `37,223`.
+fn parse_line(line: &str) -> Result<(u64, &str), String> {
+    let Some((p, factor)) = line.split_once(',') else {
+        return Err(format!("{:?} is not an exponent and a factor", line));
+    };
+    let (p, factor) = (p.trim(), factor.trim());
+    let p = p
+        .parse()
+        .map_err(|_| format!("{:?} is not an exponent", p))?;
+    let digits = factor.trim_start_matches('0');
+    if !factor.bytes().all(|b| b.is_ascii_digit()) || digits.is_empty() || digits == "1" {
+        return Err(format!("{:?} is not a factor", factor));
+    }
+    match digits.parse() {
+        Ok(factor) if divides_mersenne(p, &factor) => Ok((p, digits)),
+        _ => Err(format!("{} does not divide M({})", digits, p)),
+    }
+}
+
+/// Whether `factor` is a proper factor of `M(p) = 2^p - 1`: more than 1,
+/// less than `M(p)` itself and with `2^p ≡ 1 (mod factor)`. Takes time in
+/// `p`'s bits, not its size, so it is quick for any exponent.
+pub fn divides_mersenne(p: u64, factor: &BigUint) -> bool {
+    // `M(p)` is the only number of `p` bits with every bit set.
+    let below = factor.bits() < p || factor.bits() == p && factor.count_ones() < p;
+    *factor > BigUint::one()
+        && below
+        && BigUint::from(2u32)
+            .modpow(&BigUint::from(p), factor)
+            .is_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_first_factor_of_each_exponent() {
+        let text = "exponent,factor\n37,223\n\n# P-1\n67, 193707721\n37,616318177\n11,23\n";
+        let mut bad = Vec::new();
+        let known = KnownFactors::read(text.as_bytes(), |line| bad.push(line)).unwrap();
+        assert_eq!(known.len(), 3);
+        assert_eq!(known.get(37), Some("223"));
+        assert_eq!(known.get(67), Some("193707721"));
+        assert!(!known.contains(13));
+        assert!(bad.is_empty());
+    }
+
+    #[test]
+    fn bad_lines_are_handed_back_and_skipped() {
+        let text = "37,223\n41\nx,13367\n43,-431\n47,1\n53,6361\n61,7\n7,127\n59,179951\n";
+        let mut bad = Vec::new();
+        let known = KnownFactors::read(text.as_bytes(), |line| bad.push(line)).unwrap();
+        let lines: Vec<u64> = bad.iter().map(|line| line.line).collect();
+        assert_eq!(lines, [2, 3, 4, 5, 7, 8]);
+        assert_eq!(bad[4].reason, "7 does not divide M(61)");
+        assert_eq!(known.len(), 3);
+        assert_eq!(known.get(53), Some("6361"));
+        // The known primes M(61) and M(7) stay prime.
+        assert!(!known.contains(61) && !known.contains(7));
+    }
+
+    #[test]
+    fn factors_are_checked_by_modular_exponentiation() {
+        assert!(divides_mersenne(11, &BigUint::from(23u32)));
+        assert!(divides_mersenne(67, &BigUint::from(193_707_721u32)));
+        assert!(!divides_mersenne(67, &BigUint::from(193_707_723u32)));
+        assert!(!divides_mersenne(13, &BigUint::from(1u32)));
+        // M(p) divides itself, but is no factor of it.
+        assert!(!divides_mersenne(7, &BigUint::from(127u32)));
+        assert!(!divides_mersenne(11, &BigUint::from(2047u32)));
+        assert!(divides_mersenne(11, &BigUint::from(89u32)));
+    }
+}
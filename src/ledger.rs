@@ -0,0 +1,236 @@
+//! The progress ledger written with `--ledger`: the final result of every
+//! exponent a search has finished, which decides what a re-run skips.
+//!
+//! Unlike the results file, which is a log for people, the ledger is read
+//! back to schedule work, so it is kept in a form that reads back exactly:
+//! one [`TestReport`] per line as JSON, the same objects `--json` prints.
+//! Each line is appended and synced to disk before the next is written. A
+//! crash can only tear the last line, and that line is dropped on the next
+//! open; any other line that does not parse is an error rather than
+//! something to skip, since skipping it would silently re-run or lose work.
+//!
+//! Timed-out tests are not final, so they are not recorded. Results for
+//! different forms, and for Riesel numbers with different `k`, are told
+//! apart by their `form` and `k`, so one ledger can hold them all.
+
+use crate::report::{Form, TestReport};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// A ledger opened for appending, with the reports it already holds.
+#[derive(Debug)]
+pub struct Ledger {
+    file: File,
+    reports: Reports,
+}
+
+/// Reports by form and exponent.
+type Reports = BTreeMap<(Form, u64), TestReport>;
+
+impl Ledger {
+    /// Opens the ledger at `path`, creating it if it does not exist.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if a line other than a
+    /// torn last one is not a report.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Ledger> {
+        let path = path.as_ref();
+        let (reports, complete) = read(path)?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        if let Some(complete) = complete {
+            file.set_len(complete)?;
+            file.sync_data()?;
+        }
+        Ok(Ledger { file, reports })
+    }
+
+    /// Whether the number of `form` with exponent `p` is finished.
+    pub fn contains(&self, form: Form, p: u64) -> bool {
+        self.reports.contains_key(&(form, p))
+    }
+
+    pub fn len(&self) -> usize {
+        self.reports.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reports.is_empty()
+    }
+
+    /// The [`TestReport::work_units`] of every recorded report.
+    pub fn work_units(&self) -> f64 {
+        self.reports().map(TestReport::work_units).sum()
+    }
+
+    /// The recorded reports, by form and then exponent.
+    pub fn reports(&self) -> impl Iterator<Item = &TestReport> {
+        self.reports.values()
+    }
+
+    /// Appends `report` and syncs it to disk, unless its test timed out or
+    /// failed.
+    pub fn record(&mut self, report: &TestReport) -> io::Result<()> {
+        if !report.has_result() {
+            return Ok(());
+        }
+        let mut line = serde_json::to_string(report)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.reports
+            .insert((report.form, report.exponent), report.clone());
+        Ok(())
+    }
+}
+
+/// Every exponent of `form` the ledger at `path` has a result for, read
+/// without creating the file or dropping a torn last line. A missing file
+/// has no entries.
+pub fn finished_exponents<P: AsRef<Path>>(path: P, form: Form) -> io::Result<HashSet<u64>> {
+    let (reports, _) = read(path.as_ref())?;
+    Ok(reports
+        .into_keys()
+        .filter(|&(found, _)| found == form)
+        .map(|(_, p)| p)
+        .collect())
+}
+
+/// The reports in the ledger at `path`, and the length to cut the file to
+/// if its last line is torn.
+fn read(path: &Path) -> io::Result<(Reports, Option<u64>)> {
+    let text = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    // Everything after the last newline is a line whose write never
+    // finished.
+    let complete = text.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let mut reports = BTreeMap::new();
+    for (number, line) in text[..complete].split(|&b| b == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let report: TestReport = serde_json::from_slice(line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} is not a test report: {}", number + 1, e),
+            )
+        })?;
+        reports.insert((report.form, report.exponent), report);
+    }
+    let torn = (complete < text.len()).then_some(complete as u64);
+    Ok((reports, torn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{format_res64, FactoringStage};
+
+    fn composite(exponent: u64) -> TestReport {
+        TestReport {
+            res64: Some(format_res64(0x1234)),
+            ..TestReport::lucas_lehmer(exponent, 0.5)
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.jsonl");
+        let mut ledger = Ledger::open(&path).unwrap();
+        assert!(ledger.is_empty());
+        let factored = TestReport {
+            test: None,
+            res64: None,
+            factor: Some("47".to_string()),
+            factor_stage: Some(FactoringStage::TrialFactoring),
+            ..composite(23)
+        };
+        ledger.record(&composite(11)).unwrap();
+        ledger.record(&factored).unwrap();
+        ledger
+            .record(&TestReport {
+                timed_out_at: Some(5),
+                ..composite(29)
+            })
+            .unwrap();
+        drop(ledger);
+
+        let ledger = Ledger::open(&path).unwrap();
+        assert_eq!(ledger.len(), 2);
+        assert!(
+            ledger.contains(Form::Mersenne, 11)
+                && ledger.contains(Form::Mersenne, 23)
+                && !ledger.contains(Form::Mersenne, 29)
+        );
+        assert_eq!(
+            ledger.reports().cloned().collect::<Vec<_>>(),
+            vec![composite(11), factored]
+        );
+    }
+
+    #[test]
+    fn drops_a_torn_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.jsonl");
+        let mut ledger = Ledger::open(&path).unwrap();
+        ledger.record(&composite(11)).unwrap();
+        drop(ledger);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"exponent\":13,\"pri").unwrap();
+        drop(file);
+
+        let mut ledger = Ledger::open(&path).unwrap();
+        assert_eq!(ledger.len(), 1);
+        ledger.record(&composite(17)).unwrap();
+        drop(ledger);
+        let ledger = Ledger::open(&path).unwrap();
+        assert!(
+            ledger.contains(Form::Mersenne, 11)
+                && ledger.contains(Form::Mersenne, 17)
+                && !ledger.contains(Form::Mersenne, 13)
+        );
+    }
+
+    #[test]
+    fn refuses_a_damaged_line_in_the_middle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.jsonl");
+        let line = serde_json::to_string(&composite(11)).unwrap();
+        std::fs::write(&path, format!("{}\nnot json\n{}\n", line, line)).unwrap();
+        let error = Ledger::open(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 2 "), "{}", error);
+    }
+
+    #[test]
+    fn finished_exponents_leaves_the_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.jsonl");
+        assert!(finished_exponents(&path, Form::Mersenne)
+            .unwrap()
+            .is_empty());
+        assert!(!path.exists());
+
+        let mut ledger = Ledger::open(&path).unwrap();
+        ledger.record(&composite(11)).unwrap();
+        ledger
+            .record(&TestReport {
+                form: Form::Wagstaff,
+                ..composite(13)
+            })
+            .unwrap();
+        drop(ledger);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"exponent\":17,\"pri").unwrap();
+        drop(file);
+        let length = std::fs::metadata(&path).unwrap().len();
+
+        let finished = finished_exponents(&path, Form::Mersenne).unwrap();
+        assert_eq!(finished, HashSet::from([11]));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), length);
+    }
+}
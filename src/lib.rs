@@ -0,0 +1,1831 @@
+//! Lucas–Lehmer primality testing for Mersenne numbers `M(p) = 2^p - 1`.
+
+pub mod arith;
+pub mod audit;
+pub mod checkpoint;
+pub mod chunk;
+pub mod clock;
+pub mod compare;
+pub mod coordinator;
+#[cfg(feature = "native")]
+pub mod database;
+#[cfg(feature = "native")]
+pub mod diff;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod factor;
+pub mod fermat;
+pub mod humanize;
+pub mod known;
+pub mod known_factors;
+pub mod ledger;
+pub mod number;
+pub mod numeric;
+pub mod pacer;
+pub mod pause;
+pub mod primality;
+pub mod primenet;
+pub mod proof;
+pub mod prp;
+pub mod report;
+pub mod residue;
+pub mod results;
+pub mod riesel;
+pub mod run;
+pub mod sample;
+#[cfg(feature = "native")]
+pub mod search;
+pub mod sieve;
+pub mod sieve_cache;
+pub mod small;
+pub mod stream;
+pub mod system;
+pub mod throttle;
+pub mod throughput;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod worktodo;
+
+use arith::choice::ArithBackend;
+use arith::fixed::SmallMersenne;
+use arith::{Backend, MersenneArith, MersenneModulus, Shift};
+use checkpoint::{Checkpoint, CheckpointError, CheckpointStore};
+use clock::{Clock, ComputeTimer};
+use pacer::Every;
+use pause::Pause;
+use throttle::Throttle;
+use report::TestKind;
+use num_bigint::{BigUint, ToBigUint};
+use std::fmt;
+use std::io;
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+pub use factor::trial_factor;
+pub use number::perfect_number;
+pub use primality::is_prime;
+pub use small::is_mersenne_prime_small;
+
+/// Reduces `n` modulo the Mersenne number `2^p - 1`.
+///
+/// Since `2^p ≡ 1 (mod 2^p - 1)`, the bits above position `p` can be folded
+/// back onto the low bits with a shift and an add instead of a division.
+/// The result is always in the canonical range `0..2^p - 1`. When reducing
+/// repeatedly for the same `p`, build a [`arith::MersenneModulus`] once
+/// instead; its [`reduce_once`](arith::MersenneModulus::reduce_once) is the
+/// cheaper single fold the Lucas–Lehmer loop uses on squares.
+pub fn mod_mersenne(n: BigUint, p: u64) -> BigUint {
+    let backend = Backend::new(p);
+    let reduced = MersenneArith::reduce(&backend, backend.residue_of(&n));
+    backend.to_biguint(&reduced)
+}
+
+/// Reduces `n` modulo the Fermat number `F(k) = 2^(2^k) + 1`, for `k < 64`.
+///
+/// Since `2^(2^k) ≡ -1 (mod F(k))`, the bits above position `2^k` are
+/// folded back by subtracting them from the low bits, the counterpart of
+/// [`mod_mersenne`]. The result is always in the canonical range
+/// `0..F(k)`.
+pub fn mod_fermat(n: BigUint, k: u32) -> BigUint {
+    arith::WagstaffModulus::new(1 << k).reduce(n)
+}
+
+/// Reduces `n` modulo the Riesel number `k·2^bits - 1`, for odd `k`.
+///
+/// Since `k·2^bits ≡ 1`, the bits above position `bits`, written as
+/// `a·k + r`, fold back as `a + r·2^bits`: the generalization of
+/// [`mod_mersenne`], which is the case `k = 1`. The result is always in the
+/// canonical range `0..k·2^bits - 1`.
+pub fn mod_riesel(n: BigUint, k: u64, bits: u64) -> BigUint {
+    arith::RieselModulus::new(k, bits).reduce(n)
+}
+
+/// The outcome of a Lucas–Lehmer test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlResult {
+    Prime,
+    /// `res64` is the low 64 bits of the final residue, which other LL
+    /// implementations report too, so independent runs can be compared.
+    Composite {
+        res64: u64,
+    },
+    /// The callback of [`is_mersenne_prime_with`] cancelled the test after
+    /// `iteration` iterations, or the test gave up there after more than
+    /// [`MAX_ANOMALY_RETRIES`] arithmetic anomalies, so it has no result.
+    Aborted {
+        iteration: u64,
+    },
+}
+
+impl LlResult {
+    pub fn is_prime(&self) -> bool {
+        matches!(self, LlResult::Prime)
+    }
+}
+
+impl fmt::Display for LlResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LlResult::Prime => write!(f, "prime"),
+            LlResult::Composite { res64 } => write!(f, "composite, Res64: 0x{:016X}", res64),
+            LlResult::Aborted { iteration } => write!(f, "aborted after iteration {}", iteration),
+        }
+    }
+}
+
+/// The low 64 bits of `residue`.
+pub fn res64(residue: &BigUint) -> u64 {
+    residue.iter_u64_digits().next().unwrap_or(0)
+}
+
+/// Runs the Lucas–Lehmer test on `2^p - 1`.
+///
+/// The test is only meaningful for prime `p`; use [`is_prime`] to filter
+/// exponents first. `M(0)` and `M(1)` are reported as composite with a zero
+/// residue.
+pub fn is_mersenne_prime(p: u64) -> LlResult {
+    is_mersenne_prime_with_progress(p, |_, _| {})
+}
+
+/// How far a test has got, as reported by [`TestEvent::Progress`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// `iteration` of `total` squarings are done.
+    pub iteration: u64,
+    pub total: u64,
+    /// The time since the test started, or since it resumed from a
+    /// checkpoint.
+    pub elapsed: Duration,
+    /// The low 64 bits of the current residue, for showing that a test is
+    /// moving. Tests that cannot get it cheaply leave it out.
+    pub current_res64_hint: Option<u64>,
+}
+
+/// Something worth reporting that happened during a Lucas–Lehmer or PRP test.
+#[derive(Debug)]
+pub enum TestEvent<'a> {
+    /// Sent every [`TestControl::report_progress_every`] iterations, or
+    /// about every [`TestControl::report_progress_each`] period, and on the
+    /// final one.
+    Progress(Progress),
+    /// The test picked up from a checkpoint instead of starting over.
+    /// `written_by` is the build and machine that saved it, if recorded.
+    /// `verified` says that another arithmetic backend, or one not
+    /// recorded, computed it, so its residue had to pass a Jacobi check
+    /// first, and `other_version` is the version of the crate that wrote
+    /// it if it is not this one; see [`Checkpoint::resumable_by`].
+    Resumed {
+        iteration: u64,
+        written_by: Option<&'a str>,
+        verified: bool,
+        other_version: Option<&'a str>,
+    },
+    /// An existing checkpoint was unusable and the test restarted from `s = 4`.
+    CheckpointDiscarded(&'a CheckpointError),
+    /// A checkpoint of the residue after `iteration` iterations was
+    /// written.
+    CheckpointSaved { iteration: u64 },
+    /// Writing a checkpoint failed; the test carries on without it.
+    CheckpointFailed(&'a io::Error),
+    /// A PRP test's Gerbicz check failed at `iteration`, so the test went
+    /// back to the last verified state at `resumed_from`.
+    GerbiczMismatch { iteration: u64, resumed_from: u64 },
+    /// A Lucas–Lehmer test's Jacobi check failed at `iteration`, so the test
+    /// went back to the last verified residue at `resumed_from`.
+    JacobiMismatch { iteration: u64, resumed_from: u64 },
+    /// A Lucas–Lehmer residue failed a sanity check at `iteration`. With
+    /// [`OnAnomaly::Retry`] the test went back to `resumed_from`; otherwise
+    /// `resumed_from` is `None` and the test carried on or gave up, as
+    /// [`TestControl::on_anomaly`] says.
+    Anomaly {
+        iteration: u64,
+        anomaly: Anomaly,
+        resumed_from: Option<u64>,
+    },
+    /// The unshifted residue after `iteration` iterations, sent as
+    /// [`TestControl::record_milestones_every`] asks. After a
+    /// [`JacobiMismatch`](TestEvent::JacobiMismatch) or
+    /// [`GerbiczMismatch`](TestEvent::GerbiczMismatch) the milestones past
+    /// `resumed_from` are sent again.
+    Milestone {
+        iteration: u64,
+        residue: &'a BigUint,
+    },
+    /// The Res64 of the unshifted residue after `iteration` iterations,
+    /// sent as [`TestControl::record_res64_every`] asks and, like
+    /// milestones, sent again past `resumed_from` after a recomputation.
+    Res64 { iteration: u64, res64: u64 },
+}
+
+/// A test that stopped early because its stop flag was raised, its
+/// deadline passed or it gave up after an arithmetic anomaly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted {
+    /// The last completed iteration.
+    pub iteration: u64,
+    pub total: u64,
+    /// Whether the test ran out of time rather than being stopped.
+    pub timed_out: bool,
+    /// The anomaly the test gave up on, if that is why it stopped. The
+    /// residue it ended with is not saved.
+    pub anomaly: Option<Anomaly>,
+}
+
+/// A Lucas–Lehmer residue that no correct iteration produces.
+///
+/// Every residue is checked to be below the modulus after every iteration,
+/// which costs next to nothing. Every [`ANOMALY_CHECK_INTERVAL`] iterations
+/// the residue is also checked against `0`, `2` and `-2`: `s -> s^2 - 2`
+/// takes them to `-2`, `2` and `2`, so from any of them the test could only
+/// end on a residue of 2, and before the final iteration only a hardware or
+/// arithmetic error gets there. A prime reaches 0 on its final iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// Negative, or not reduced below the modulus.
+    OutOfRange,
+    /// One of the fixed points `0`, `2` or `-2`, before the final
+    /// iteration.
+    Stuck(i8),
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::OutOfRange => write!(f, "the residue is out of range"),
+            Anomaly::Stuck(value) => write!(f, "the residue is {} before the end", value),
+        }
+    }
+}
+
+/// What a Lucas–Lehmer test does about an [`Anomaly`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnAnomaly {
+    /// Go back to the later of the last checkpoint written and the last
+    /// residue that passed a Jacobi check, or else to the start, and redo
+    /// the iterations from there. After [`MAX_ANOMALY_RETRIES`] retries the
+    /// test gives up as with [`Abort`](OnAnomaly::Abort).
+    #[default]
+    Retry,
+    /// Give up on the test, with [`Interrupted::anomaly`] set.
+    Abort,
+    /// Carry on with the residue, so the result is almost certainly wrong.
+    Continue,
+}
+
+impl FromStr for OnAnomaly {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OnAnomaly, String> {
+        match s {
+            "retry" => Ok(OnAnomaly::Retry),
+            "abort" => Ok(OnAnomaly::Abort),
+            "continue" => Ok(OnAnomaly::Continue),
+            _ => Err(format!("unknown error policy {:?}", s)),
+        }
+    }
+}
+
+/// Iterations between looks at the clock for [`TestControl::deadline`].
+const DEADLINE_CHECK_INTERVAL: u64 = 1000;
+
+/// Iterations between the checks for [`Anomaly::Stuck`] residues.
+pub const ANOMALY_CHECK_INTERVAL: u64 = 1000;
+
+/// How many anomalies [`OnAnomaly::Retry`] retries in one test.
+pub const MAX_ANOMALY_RETRIES: u32 = 3;
+
+/// How a caller stops a running test, watches it from another thread, and
+/// has it checked for errors or run with a shifted residue.
+#[derive(Debug, Clone, Copy)]
+pub struct TestControl<'a> {
+    /// Checked before every iteration. Once it is raised, the test stops
+    /// with [`Interrupted`].
+    pub stop: &'a AtomicBool,
+    /// If set, receives the number of the last completed iteration after
+    /// every iteration.
+    pub iteration: Option<&'a AtomicU64>,
+    /// Iterations between Jacobi checks of a Lucas–Lehmer residue, or
+    /// `None` for no checks. See [`default_jacobi_interval`].
+    pub jacobi_interval: Option<u64>,
+    /// The initial shift of a Lucas–Lehmer residue, in bits; see
+    /// [`arith::Shift`]. The result does not depend on it.
+    pub shift: u64,
+    /// Where the test takes the time from; see [`clock`].
+    pub clock: &'a dyn Clock,
+    /// Once the [active time](TestControl::active_time) reaches this, the
+    /// test stops with [`Interrupted`] as if `stop` had been raised, but
+    /// with `timed_out` set. It is checked about every thousand iterations.
+    pub deadline: Option<Duration>,
+    /// Threads each squaring is split across; with 1 the whole test runs
+    /// on the calling thread. The result does not depend on it.
+    pub threads: usize,
+    /// Iterations between [`TestEvent::Progress`] events, or `None` for
+    /// about one every 1% of the test.
+    pub progress_interval: Option<u64>,
+    /// The active time aimed for between [`TestEvent::Progress`] events,
+    /// which overrides `progress_interval`; see [`pacer`].
+    pub progress_period: Option<Duration>,
+    /// Iterations between [`TestEvent::Milestone`] events of a Lucas–Lehmer
+    /// or PRP test, or `None` for none.
+    pub milestone_interval: Option<u64>,
+    /// Iterations between [`TestEvent::Res64`] events of a Lucas–Lehmer or
+    /// PRP test, or `None` for none.
+    pub res64_interval: Option<u64>,
+    /// What a Lucas–Lehmer test does when a residue fails the checks of
+    /// [`Anomaly`].
+    pub on_anomaly: OnAnomaly,
+    /// If set, checked along with `stop`; while it is raised the test
+    /// waits. See [`pause`].
+    pub pause: Option<&'a Pause>,
+    /// If set, adds up the compute time of the test.
+    pub compute: Option<&'a ComputeTimer>,
+    /// If set, looked at along with `stop`; the test sleeps when it says
+    /// so. See [`throttle`].
+    pub throttle: Option<&'a Throttle>,
+    /// The arithmetic of a Lucas–Lehmer test of a Mersenne number, or
+    /// `None` for [`ArithBackend::default_for`] its exponent. The result
+    /// does not depend on it.
+    pub backend: Option<ArithBackend>,
+}
+
+impl<'a> TestControl<'a> {
+    pub fn new(stop: &'a AtomicBool) -> TestControl<'a> {
+        TestControl {
+            stop,
+            iteration: None,
+            jacobi_interval: None,
+            shift: 0,
+            clock: clock::default_clock(),
+            deadline: None,
+            threads: 1,
+            progress_interval: None,
+            progress_period: None,
+            milestone_interval: None,
+            res64_interval: None,
+            on_anomaly: OnAnomaly::Retry,
+            pause: None,
+            compute: None,
+            throttle: None,
+            backend: None,
+        }
+    }
+
+    /// Also publishes the iteration counter to `iteration`.
+    pub fn publish_to(self, iteration: &'a AtomicU64) -> TestControl<'a> {
+        TestControl {
+            iteration: Some(iteration),
+            ..self
+        }
+    }
+
+    /// Also runs a Jacobi check every `interval` iterations; 0 disables it.
+    pub fn check_jacobi_every(self, interval: u64) -> TestControl<'a> {
+        TestControl {
+            jacobi_interval: (interval > 0).then_some(interval),
+            ..self
+        }
+    }
+
+    /// Also shifts the Lucas–Lehmer residue by `bits` from the start.
+    pub fn with_shift(self, bits: u64) -> TestControl<'a> {
+        TestControl {
+            shift: bits,
+            ..self
+        }
+    }
+
+    /// Also takes the time from `clock` instead of the default one.
+    pub fn with_clock(self, clock: &'a dyn Clock) -> TestControl<'a> {
+        TestControl { clock, ..self }
+    }
+
+    /// Also waits while `pause` is raised, leaving the time out of the
+    /// test's elapsed time and deadline; so set it before the deadline.
+    pub fn pause_with(self, pause: &'a Pause) -> TestControl<'a> {
+        TestControl {
+            pause: Some(pause),
+            ..self
+        }
+    }
+
+    /// Also holds the test to the share of a core `throttle` allows. Its
+    /// sleeps count towards the test's elapsed time and deadline.
+    pub fn throttle_with(self, throttle: &'a Throttle) -> TestControl<'a> {
+        TestControl {
+            throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Also adds the compute time of the test to `timer`, which leaves out
+    /// the time its thread waited for a core, its pauses and its
+    /// checkpoint writes.
+    pub fn time_compute_with(self, timer: &'a ComputeTimer) -> TestControl<'a> {
+        TestControl {
+            compute: Some(timer),
+            ..self
+        }
+    }
+
+    /// Also runs a Lucas–Lehmer test of a Mersenne number on `backend`,
+    /// which must be in the build and [handle](ArithBackend::handles) its
+    /// exponent.
+    pub fn on_backend(self, backend: ArithBackend) -> TestControl<'a> {
+        TestControl {
+            backend: Some(backend),
+            ..self
+        }
+    }
+
+    /// The time by the clock, less the time spent paused: what a test's
+    /// elapsed time and deadline are measured in.
+    pub fn active_time(&self) -> Duration {
+        let paused = self.pause.map_or(Duration::ZERO, Pause::total);
+        self.clock.now().saturating_sub(paused)
+    }
+
+    /// Also gives up on the test once its active time reads `deadline`.
+    pub fn stop_at(self, deadline: Duration) -> TestControl<'a> {
+        TestControl {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Also gives up on the test once `limit` has passed from now, by its
+    /// clock and not counting pauses; so set those first.
+    pub fn stop_after(self, limit: Duration) -> TestControl<'a> {
+        let deadline = self.active_time() + limit;
+        self.stop_at(deadline)
+    }
+
+    /// Also splits each squaring across `threads` threads, for a single
+    /// huge exponent; see [`arith::MersenneModulus::with_threads`].
+    pub fn split_across(self, threads: usize) -> TestControl<'a> {
+        TestControl {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    /// Also reports progress every `iterations` iterations rather than
+    /// every 1%; 0 goes back to 1%.
+    pub fn report_progress_every(self, iterations: u64) -> TestControl<'a> {
+        TestControl {
+            progress_interval: (iterations > 0).then_some(iterations),
+            ..self
+        }
+    }
+
+    /// Also reports progress about every `period` of the test's active
+    /// time, however long its iterations take, rather than every so many
+    /// iterations. With a clock that stands still, such as the default one
+    /// on the web, only the last iteration is reported.
+    pub fn report_progress_each(self, period: Duration) -> TestControl<'a> {
+        TestControl {
+            progress_period: Some(period),
+            ..self
+        }
+    }
+
+    /// Also reports the full residue of a Lucas–Lehmer or PRP test before
+    /// its first iteration, every `iterations` iterations and after its
+    /// last, for an [`audit`] log or a PRP [`proof`]; 0 turns this off. The
+    /// Lucas–Lehmer tests of small exponents then run the full loop instead
+    /// of [`is_mersenne_prime_small`].
+    pub fn record_milestones_every(self, iterations: u64) -> TestControl<'a> {
+        TestControl {
+            milestone_interval: (iterations > 0).then_some(iterations),
+            ..self
+        }
+    }
+
+    /// Also reports the Res64 of a Lucas–Lehmer or PRP test every
+    /// `iterations` iterations and after its last, so that two runs can be
+    /// compared along the way; 0 turns this off. Unlike milestones this
+    /// costs no more than the progress reports, but small Lucas–Lehmer
+    /// tests also run the full loop.
+    pub fn record_res64_every(self, iterations: u64) -> TestControl<'a> {
+        TestControl {
+            res64_interval: (iterations > 0).then_some(iterations),
+            ..self
+        }
+    }
+
+    /// Also handles a Lucas–Lehmer [`Anomaly`] as `policy` says rather than
+    /// retrying it.
+    pub fn on_anomaly(self, policy: OnAnomaly) -> TestControl<'a> {
+        TestControl {
+            on_anomaly: policy,
+            ..self
+        }
+    }
+
+    /// When the progress events of a test of `total` iterations are due.
+    pub(crate) fn progress_schedule(&self, total: u64) -> Every {
+        let interval = self.progress_interval.unwrap_or(total / 100);
+        Every::new(interval, self.progress_period, self.active_time())
+    }
+
+    /// Whether a test with `completed` of `total` iterations done should
+    /// stop here, after waiting out any pause and sleep of its throttle.
+    pub(crate) fn interruption(&self, completed: u64, total: u64) -> Option<Interrupted> {
+        if let Some(pause) = self.pause.filter(|pause| pause.is_paused()) {
+            self.off_the_clock(|| pause.wait(self.stop));
+        }
+        if let Some(sleep) = self.throttle.and_then(|throttle| throttle.due(self.clock)) {
+            self.off_the_clock(|| std::thread::sleep(sleep));
+        }
+        let timed_out = completed.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+            && self
+                .deadline
+                .is_some_and(|deadline| self.active_time() >= deadline);
+        (timed_out || self.stop.load(Ordering::Relaxed)).then_some(Interrupted {
+            iteration: completed,
+            total,
+            timed_out,
+            anomaly: None,
+        })
+    }
+
+    /// Times the compute of a test loop until the returned guard is
+    /// dropped, if there is a [`ComputeTimer`].
+    pub(crate) fn timing(&self) -> Timing<'_> {
+        if let Some(timer) = self.compute {
+            timer.start(self.clock);
+        }
+        Timing {
+            timer: self.compute,
+            clock: self.clock,
+        }
+    }
+
+    /// Runs `f`, such as a checkpoint write, without counting its time as
+    /// compute time.
+    pub(crate) fn off_the_clock<T>(&self, f: impl FnOnce() -> T) -> T {
+        match self.compute {
+            Some(timer) => timer.exclude(self.clock, f),
+            None => f(),
+        }
+    }
+
+    pub(crate) fn publish(&self, iteration: u64) {
+        if let Some(counter) = self.iteration {
+            counter.store(iteration, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Stops the timing of [`TestControl::timing`] when dropped.
+pub(crate) struct Timing<'a> {
+    timer: Option<&'a ComputeTimer>,
+    clock: &'a dyn Clock,
+}
+
+impl Drop for Timing<'_> {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer {
+            timer.stop(self.clock);
+        }
+    }
+}
+
+/// A Jacobi check interval that keeps the checks to about 1% of the work.
+///
+/// A check costs roughly `13 · p^0.415` squarings with this crate's
+/// quadratic Jacobi symbol, so for exponents around a million it comes out
+/// at one check every few hours.
+pub fn default_jacobi_interval(p: u64) -> u64 {
+    (1300.0 * (p as f64).powf(0.415)) as u64
+}
+
+/// Whether the Lucas–Lehmer residue `s` passes the Jacobi check.
+///
+/// Every `s_i` after the first satisfies `(s_i - 2 | M(p)) = -1`, since
+/// `s_i - 2 = (s_{i-1} - 2)(s_{i-1} + 2)` and `s_{i-1} + 2 = s_{i-2}^2`
+/// reduce it to `(12 | M(p)) = -1`. A corrupted residue gives `+1` about
+/// half the time. A symbol of 0 means `s - 2` shares a factor with a
+/// composite `M(p)`, which a correct residue can do too, so it passes.
+fn passes_jacobi_check(s: &BigUint, modulus: &BigUint) -> bool {
+    let s_minus_2 = if *s >= BigUint::from(2u32) {
+        s - 2u32
+    } else {
+        s + modulus - 2u32
+    };
+    numeric::jacobi(&s_minus_2, modulus) != 1
+}
+
+/// Which of the fixed points of [`Anomaly::Stuck`] the unshifted residue
+/// `s` is, if any.
+fn stuck_at(s: &BigUint, minus_two: &BigUint) -> Option<i8> {
+    if *s == BigUint::ZERO {
+        Some(0)
+    } else if *s == BigUint::from(2u32) {
+        Some(2)
+    } else if s == minus_two {
+        Some(-2)
+    } else {
+        None
+    }
+}
+
+/// Same as [`is_mersenne_prime`], but reports progress while iterating.
+///
+/// `progress` is called with `(iteration, total_iterations)` roughly every
+/// 1% of the test and once more on the final iteration. It is never called
+/// for `p <= 2`, which need no iterations.
+pub fn is_mersenne_prime_with_progress<F>(p: u64, mut progress: F) -> LlResult
+where
+    F: FnMut(u64, u64),
+{
+    is_mersenne_prime_with(p, |report| {
+        progress(report.iteration, report.total);
+        ControlFlow::Continue(())
+    })
+}
+
+/// Same as [`is_mersenne_prime`], but hands every [`Progress`] report to
+/// `callback`, which can cancel the test by returning
+/// [`ControlFlow::Break`].
+///
+/// Progress is reported as by [`is_mersenne_prime_with_progress`];
+/// [`is_mersenne_prime_interruptible`] with
+/// [`TestControl::report_progress_every`] reports it at any other
+/// granularity. A cancelled test stops before its next iteration and
+/// returns [`LlResult::Aborted`]. Nothing is written to disk, so there is
+/// nothing to clean up.
+pub fn is_mersenne_prime_with<F>(p: u64, mut callback: F) -> LlResult
+where
+    F: FnMut(Progress) -> ControlFlow<()>,
+{
+    let cancelled = AtomicBool::new(false);
+    let result =
+        is_mersenne_prime_interruptible(p, None, TestControl::new(&cancelled), |event| {
+            if let TestEvent::Progress(progress) = event {
+                if callback(progress).is_break() {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            }
+        });
+    result.unwrap_or_else(|interrupted| LlResult::Aborted {
+        iteration: interrupted.iteration,
+    })
+}
+
+/// Same as [`is_mersenne_prime`], but with checkpointing and event reporting.
+///
+/// With a checkpoint store, the test resumes from a saved checkpoint for `p`
+/// if a valid one exists, saves its state every
+/// [`CheckpointStore::interval`] iterations, and removes the checkpoint
+/// once it finishes. Everything noteworthy is reported through `on_event`.
+pub fn is_mersenne_prime_with_events<F>(
+    p: u64,
+    checkpoints: Option<&CheckpointStore>,
+    on_event: F,
+) -> LlResult
+where
+    F: FnMut(TestEvent),
+{
+    let never = AtomicBool::new(false);
+    match is_mersenne_prime_interruptible(p, checkpoints, TestControl::new(&never), on_event) {
+        Ok(result) => result,
+        // The stop flag is never raised.
+        Err(interrupted) => LlResult::Aborted {
+            iteration: interrupted.iteration,
+        },
+    }
+}
+
+/// The general form of [`is_mersenne_prime`].
+///
+/// Behaves like [`is_mersenne_prime_with_events`], but checks
+/// `control.stop` before every iteration and publishes its progress through
+/// `control`. Once the flag is raised or the deadline in `control` has
+/// passed, the test saves a checkpoint for the last completed iteration (if
+/// it has a store) and returns [`Interrupted`] instead of finishing.
+///
+/// With a Jacobi interval set in `control`, the residue is checked that
+/// often, and a failed check sends the test back to the last residue that
+/// passed, reported as [`TestEvent::JacobiMismatch`].
+///
+/// Every residue is also checked for an [`Anomaly`], handled as
+/// [`TestControl::on_anomaly`] says and reported as
+/// [`TestEvent::Anomaly`].
+///
+/// With a shift set in `control`, the residue is stored shifted throughout
+/// the test. Checkpoints always hold the unshifted residue, so they can be
+/// resumed with any shift.
+///
+/// Unshifted tests of exponents up to [`small::MAX_SMALL_EXPONENT`] are
+/// handed to [`is_mersenne_prime_small`]. They finish in microseconds, so
+/// they never read or write checkpoints and report only their final
+/// iteration. The rest run on [`TestControl::backend`], by default a
+/// [`SmallMersenne`] of the fewest limbs that hold them up to
+/// [`MAX_FIXED_EXPONENT`](arith::fixed::MAX_FIXED_EXPONENT), since a fixed
+/// array on the stack squares faster than any integer that allocates; see
+/// [`arith::choice`].
+///
+/// # Panics
+///
+/// If the backend of `control` is not in the build or cannot take `p`.
+pub fn is_mersenne_prime_interruptible<F>(
+    p: u64,
+    checkpoints: Option<&CheckpointStore>,
+    control: TestControl,
+    on_event: F,
+) -> Result<LlResult, Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    fn fixed<const LIMBS: usize, F: FnMut(TestEvent)>(
+        p: u64,
+        checkpoints: Option<&CheckpointStore>,
+        control: TestControl,
+        on_event: F,
+    ) -> Result<LlResult, Interrupted> {
+        lucas_lehmer::<SmallMersenne<LIMBS>, _, _>(p, checkpoints, control, on_event, |_, _| {})
+    }
+    let backend = control
+        .backend
+        .unwrap_or_else(|| ArithBackend::default_for(p));
+    assert!(
+        backend.is_available() && backend.handles(p),
+        "the {} backend cannot test M({})",
+        backend,
+        p
+    );
+    match p.div_ceil(64) {
+        _ if backend == ArithBackend::Bignum => {
+            lucas_lehmer::<MersenneModulus, _, _>(p, checkpoints, control, on_event, |_, _| {})
+        }
+        #[cfg(feature = "gmp")]
+        _ if backend == ArithBackend::Gmp => {
+            let fault = |_: u64, _: &mut _| {};
+            lucas_lehmer::<arith::gmp::GmpModulus, _, _>(p, checkpoints, control, on_event, fault)
+        }
+        0..=2 => fixed::<2, _>(p, checkpoints, control, on_event),
+        3..=4 => fixed::<4, _>(p, checkpoints, control, on_event),
+        5..=8 => fixed::<8, _>(p, checkpoints, control, on_event),
+        9..=16 => fixed::<16, _>(p, checkpoints, control, on_event),
+        17..=32 => fixed::<32, _>(p, checkpoints, control, on_event),
+        _ => fixed::<64, _>(p, checkpoints, control, on_event),
+    }
+}
+
+/// The Lucas–Lehmer test loop, for any arithmetic backend. `fault` is
+/// called after every iteration and may tamper with the residue, so tests
+/// can check that errors are caught.
+fn lucas_lehmer<A, F, G>(
+    p: u64,
+    checkpoints: Option<&CheckpointStore>,
+    control: TestControl,
+    mut on_event: F,
+    fault: G,
+) -> Result<LlResult, Interrupted>
+where
+    A: MersenneArith,
+    F: FnMut(TestEvent),
+    G: FnMut(u64, &mut A::Residue),
+{
+    if p < 2 {
+        return Ok(LlResult::Composite { res64: 0 });
+    }
+    if p == 2 {
+        return Ok(LlResult::Prime);
+    }
+
+    let _timing = control.timing();
+    let total_iterations = p - 2;
+    let initial_shift = Shift::new(p, control.shift);
+    let small = p <= small::MAX_SMALL_EXPONENT
+        && control.milestone_interval.is_none()
+        && control.res64_interval.is_none();
+    if small && initial_shift == Shift::none(p) {
+        if let Some(interrupted) = control.interruption(0, total_iterations) {
+            return Err(interrupted);
+        }
+        let started = control.active_time();
+        let residue = small::small_residue(p);
+        control.publish(total_iterations);
+        on_event(TestEvent::Progress(Progress {
+            iteration: total_iterations,
+            total: total_iterations,
+            elapsed: control.active_time().saturating_sub(started),
+            current_res64_hint: Some(residue),
+        }));
+        return Ok(if residue == 0 {
+            LlResult::Prime
+        } else {
+            LlResult::Composite { res64: residue }
+        });
+    }
+
+    let modulus = A::new(p).with_threads(control.threads);
+    let start = modulus.residue_of(&4u32.to_biguint().unwrap());
+    // Only built when checking, since the backend keeps its own copy.
+    let jacobi_modulus = control
+        .jacobi_interval
+        .map(|_| (BigUint::from(1u32) << p) - 1u32);
+    let setup = LlSetup {
+        modulus,
+        start,
+        iterations: total_iterations,
+        p,
+        jacobi_modulus,
+    };
+    run_lucas_lehmer(setup, checkpoints, control, on_event, fault)
+}
+
+/// What [`run_lucas_lehmer`] iterates: `s -> s^2 - 2` from `start`, modulo
+/// `modulus`, `iterations` times.
+pub(crate) struct LlSetup<A: MersenneArith> {
+    pub modulus: A,
+    /// The unshifted residue before the first iteration.
+    pub start: A::Residue,
+    pub iterations: u64,
+    /// The exponent checkpoints are saved under and shifts are taken
+    /// modulo, which only mean something for a Mersenne modulus.
+    pub p: u64,
+    /// The modulus for Jacobi checks, if the residues are checked.
+    pub jacobi_modulus: Option<BigUint>,
+}
+
+/// The iterations, checkpoints and checks of a Lucas–Lehmer test, shared
+/// with the Lucas–Lehmer–Riesel test. The number is prime exactly when the
+/// final residue is zero.
+pub(crate) fn run_lucas_lehmer<A, F, G>(
+    setup: LlSetup<A>,
+    checkpoints: Option<&CheckpointStore>,
+    control: TestControl,
+    mut on_event: F,
+    mut fault: G,
+) -> Result<LlResult, Interrupted>
+where
+    A: MersenneArith,
+    F: FnMut(TestEvent),
+    G: FnMut(u64, &mut A::Residue),
+{
+    let LlSetup {
+        modulus,
+        start,
+        iterations: total_iterations,
+        p,
+        jacobi_modulus,
+    } = setup;
+    // Also here for the Riesel tests, which come straight here.
+    let _timing = control.timing();
+    let started = control.active_time();
+    let mut progress_due = control.progress_schedule(total_iterations);
+
+    let initial_shift = Shift::new(p, control.shift);
+    let mut shift = initial_shift;
+    let mut s = modulus.shifted(&start, shift);
+    let mut first_iteration = 1;
+
+    if let Some(store) = checkpoints {
+        match control.off_the_clock(|| store.load(p)) {
+            Ok(Some(checkpoint)) => {
+                let resumable =
+                    checkpoint.resumable_by(TestKind::LucasLehmer, initial_shift.bits());
+                let verify = resumable.as_ref().is_ok_and(|resumable| resumable.verify);
+                // Only Mersenne numbers are checkpointed, so without checks
+                // the modulus to verify against is M(p).
+                let corrupted = checkpoint.iteration > 0
+                    && match &jacobi_modulus {
+                        Some(modulus) => !passes_jacobi_check(&checkpoint.residue, modulus),
+                        None if verify => {
+                            let modulus = (BigUint::from(1u32) << p) - 1u32;
+                            !passes_jacobi_check(&checkpoint.residue, &modulus)
+                        }
+                        None => false,
+                    };
+                match resumable {
+                    Err(e) => on_event(TestEvent::CheckpointDiscarded(&e)),
+                    Ok(_) if corrupted => on_event(TestEvent::CheckpointDiscarded(
+                        &CheckpointError::InvalidState(
+                            "residue fails the Jacobi check".to_string(),
+                        ),
+                    )),
+                    Ok(resumable) => {
+                        on_event(TestEvent::Resumed {
+                            iteration: checkpoint.iteration,
+                            written_by: checkpoint.written_by.as_deref(),
+                            verified: resumable.verify,
+                            other_version: resumable.other_version.as_deref(),
+                        });
+                        first_iteration = checkpoint.iteration + 1;
+                        control.publish(checkpoint.iteration);
+                        shift = initial_shift.after(checkpoint.iteration);
+                        s = modulus.shifted(&modulus.residue_of(&checkpoint.residue), shift);
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => on_event(TestEvent::CheckpointDiscarded(&e)),
+        }
+    }
+
+    let milestone_residue =
+        |s: &A::Residue, shift| modulus.to_biguint(&modulus.reduce(modulus.unshifted(s, shift)));
+    if control.milestone_interval.is_some() {
+        on_event(TestEvent::Milestone {
+            iteration: first_iteration - 1,
+            residue: &milestone_residue(&s, shift),
+        });
+    }
+
+    // The last residue known to be good, for the Jacobi check to go back to.
+    let mut verified = (first_iteration - 1, s.clone(), shift);
+    // The last residue checkpointed, which an anomaly can go back to too.
+    let mut saved = verified.clone();
+    let mut retries = 0;
+    // 0^2 - 2, which is -2 in any backend.
+    let minus_two = modulus.to_biguint(&modulus.square_sub2(&modulus.residue_of(&BigUint::ZERO)));
+    let mut checkpoint_due = checkpoints.map(|store| store.schedule(control.active_time()));
+    let mut i = first_iteration;
+    while i <= total_iterations {
+        if let Some(interrupted) = control.interruption(i - 1, total_iterations) {
+            if let Some(store) = checkpoints {
+                let residue = modulus.to_biguint(&modulus.unshifted(&s, shift));
+                let checkpoint = Checkpoint::new(p, interrupted.iteration, residue)
+                    .with_shift(initial_shift.bits())
+                    .in_run(store.run().cloned());
+                match control.off_the_clock(|| store.save(&checkpoint)) {
+                    Ok(()) => on_event(TestEvent::CheckpointSaved {
+                        iteration: interrupted.iteration,
+                    }),
+                    Err(e) => on_event(TestEvent::CheckpointFailed(&e)),
+                }
+            }
+            return Err(interrupted);
+        }
+        (s, shift) = modulus.square_sub2_shifted(&s, shift);
+        fault(i, &mut s);
+        control.publish(i);
+
+        let anomaly = if !modulus.is_canonical(&s) {
+            Some(Anomaly::OutOfRange)
+        } else if i % ANOMALY_CHECK_INTERVAL == 0 && i < total_iterations {
+            stuck_at(&modulus.to_biguint(&modulus.unshifted(&s, shift)), &minus_two)
+                .map(Anomaly::Stuck)
+        } else {
+            None
+        };
+        if let Some(anomaly) = anomaly {
+            match control.on_anomaly {
+                OnAnomaly::Continue => on_event(TestEvent::Anomaly {
+                    iteration: i,
+                    anomaly,
+                    resumed_from: None,
+                }),
+                OnAnomaly::Retry if retries < MAX_ANOMALY_RETRIES => {
+                    retries += 1;
+                    let back = if saved.0 > verified.0 { &saved } else { &verified };
+                    on_event(TestEvent::Anomaly {
+                        iteration: i,
+                        anomaly,
+                        resumed_from: Some(back.0),
+                    });
+                    i = back.0 + 1;
+                    s = back.1.clone();
+                    shift = back.2;
+                    continue;
+                }
+                _ => {
+                    on_event(TestEvent::Anomaly {
+                        iteration: i,
+                        anomaly,
+                        resumed_from: None,
+                    });
+                    return Err(Interrupted {
+                        iteration: i - 1,
+                        total: total_iterations,
+                        timed_out: false,
+                        anomaly: Some(anomaly),
+                    });
+                }
+            }
+        }
+
+        if let (Some(interval), Some(jacobi_modulus)) = (control.jacobi_interval, &jacobi_modulus) {
+            if i % interval == 0 {
+                let unshifted = modulus.to_biguint(&modulus.unshifted(&s, shift));
+                if passes_jacobi_check(&unshifted, jacobi_modulus) {
+                    verified = (i, s.clone(), shift);
+                } else {
+                    on_event(TestEvent::JacobiMismatch {
+                        iteration: i,
+                        resumed_from: verified.0,
+                    });
+                    i = verified.0 + 1;
+                    s = verified.1.clone();
+                    shift = verified.2;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(interval) = control.milestone_interval {
+            if i % interval == 0 || i == total_iterations {
+                on_event(TestEvent::Milestone {
+                    iteration: i,
+                    residue: &milestone_residue(&s, shift),
+                });
+            }
+        }
+        if let Some(interval) = control.res64_interval {
+            if i % interval == 0 || i == total_iterations {
+                on_event(TestEvent::Res64 {
+                    iteration: i,
+                    res64: A::res64(&modulus.unshifted(&s, shift)),
+                });
+            }
+        }
+
+        if progress_due.due(i, || control.active_time()) || i == total_iterations {
+            on_event(TestEvent::Progress(Progress {
+                iteration: i,
+                total: total_iterations,
+                elapsed: control.active_time().saturating_sub(started),
+                current_res64_hint: Some(A::res64(&modulus.unshifted(&s, shift))),
+            }));
+        }
+
+        if let (Some(store), Some(due)) = (checkpoints, &mut checkpoint_due) {
+            if due.due(i, || control.active_time()) && i != total_iterations {
+                let residue = modulus.to_biguint(&modulus.unshifted(&s, shift));
+                let checkpoint = Checkpoint::new(p, i, residue)
+                    .with_shift(initial_shift.bits())
+                    .in_run(store.run().cloned());
+                match control.off_the_clock(|| store.save(&checkpoint)) {
+                    Ok(()) => {
+                        saved = (i, s.clone(), shift);
+                        on_event(TestEvent::CheckpointSaved { iteration: i });
+                    }
+                    Err(e) => on_event(TestEvent::CheckpointFailed(&e)),
+                }
+            }
+        }
+        i += 1;
+    }
+
+    if let Some(store) = checkpoints {
+        if let Err(e) = store.remove(p) {
+            on_event(TestEvent::CheckpointFailed(&e));
+        }
+    }
+
+    if A::is_zero(&s) {
+        Ok(LlResult::Prime)
+    } else {
+        Ok(LlResult::Composite {
+            res64: A::res64(&modulus.unshifted(&s, shift)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_traits::Zero;
+    use std::time::Duration;
+
+    #[test]
+    fn known_mersenne_prime_exponents() {
+        for p in [2, 3, 5, 7, 13, 17, 19, 31] {
+            assert_eq!(
+                is_mersenne_prime(p),
+                LlResult::Prime,
+                "M({}) should be prime",
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn composite_mersenne_numbers() {
+        for p in [11, 23, 29] {
+            assert!(
+                !is_mersenne_prime(p).is_prime(),
+                "M({}) should be composite",
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn exponents_below_two_are_not_prime() {
+        assert!(!is_mersenne_prime(0).is_prime());
+        assert!(!is_mersenne_prime(1).is_prime());
+    }
+
+    #[test]
+    fn composite_res64_values() {
+        let cases = [
+            (11, 0x00000000000006C8),
+            (23, 0x00000000005D32F7),
+            (29, 0x000000001B57CB0B),
+            (101, 0xD0DD748DD7817436),
+            (1277, 0x5613A480590E78BA),
+        ];
+        for (p, res64) in cases {
+            assert_eq!(
+                is_mersenne_prime(p),
+                LlResult::Composite { res64 },
+                "M({})",
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn fixed_limbs_agree_with_num_bigint_over_whole_tests() {
+        let never = AtomicBool::new(false);
+        let primes = (3..1300).filter(|&p| primality::is_prime(p));
+        for p in primes.chain([2203, 4093]) {
+            for shift in [0, p / 3] {
+                let reference = lucas_lehmer::<arith::MersenneModulus, _, _>(
+                    p,
+                    None,
+                    TestControl::new(&never).with_shift(shift),
+                    |_| {},
+                    |_, _| {},
+                );
+                let fixed = is_mersenne_prime_interruptible(
+                    p,
+                    None,
+                    TestControl::new(&never).with_shift(shift),
+                    |_| {},
+                );
+                assert_eq!(fixed, reference, "M({}) with a shift of {}", p, shift);
+            }
+        }
+    }
+
+    #[test]
+    fn every_backend_reaches_the_same_residues() {
+        let never = AtomicBool::new(false);
+        for p in [67, 89, 127, 257, 521, 1277, 2203, 4093, 4423, 9689] {
+            let results: Vec<_> = ArithBackend::candidates(p)
+                .into_iter()
+                .map(|backend| {
+                    let control = TestControl::new(&never)
+                        .on_backend(backend)
+                        .record_res64_every(p / 4);
+                    let mut res64s = Vec::new();
+                    let result = is_mersenne_prime_interruptible(p, None, control, |event| {
+                        if let TestEvent::Res64 { iteration, res64 } = event {
+                            res64s.push((iteration, res64));
+                        }
+                    });
+                    (backend, result, res64s)
+                })
+                .collect();
+            let (_, result, res64s) = &results[0];
+            for (backend, other, others) in &results[1..] {
+                assert_eq!(other, result, "M({}) on {}", p, backend);
+                assert_eq!(others, res64s, "the Res64s of M({}) on {}", p, backend);
+            }
+        }
+    }
+
+    #[test]
+    fn res64_is_zero_padded_upper_hex() {
+        let result = LlResult::Composite { res64: 0x5D32F7 };
+        assert_eq!(result.to_string(), "composite, Res64: 0x00000000005D32F7");
+    }
+
+    #[test]
+    fn mod_mersenne_folds_into_canonical_range() {
+        let m7 = BigUint::from(127u32);
+        assert_eq!(
+            mod_mersenne(BigUint::from(1000u32), 7),
+            BigUint::from(1000u32 % 127)
+        );
+        assert_eq!(mod_mersenne(m7.clone(), 7), BigUint::zero());
+        assert_eq!(mod_mersenne(&m7 * &m7 * 3u32, 7), BigUint::zero());
+        assert_eq!(
+            mod_mersenne(BigUint::from(126u32), 7),
+            BigUint::from(126u32)
+        );
+    }
+
+    #[test]
+    fn mod_fermat_folds_into_canonical_range() {
+        let f3 = BigUint::from(257u32);
+        assert_eq!(
+            mod_fermat(BigUint::from(100_000u32), 3),
+            BigUint::from(100_000u32 % 257)
+        );
+        assert_eq!(mod_fermat(f3.clone(), 3), BigUint::zero());
+        assert_eq!(mod_fermat(&f3 * &f3 * 5u32, 3), BigUint::zero());
+        // 2^8 ≡ -1, and 2^16 ≡ 1.
+        assert_eq!(mod_fermat(BigUint::from(256u32), 3), BigUint::from(256u32));
+        assert_eq!(mod_fermat(BigUint::from(65536u32), 3), BigUint::from(1u32));
+    }
+
+    #[test]
+    fn mod_riesel_folds_into_canonical_range() {
+        // 15·2^4 - 1 = 239.
+        assert_eq!(
+            mod_riesel(BigUint::from(100_000u32), 15, 4),
+            BigUint::from(100_000u32 % 239)
+        );
+        assert_eq!(mod_riesel(BigUint::from(239u32), 15, 4), BigUint::zero());
+        assert_eq!(mod_riesel(BigUint::from(240u32), 15, 4), BigUint::from(1u32));
+        let n = BigUint::parse_bytes(b"123456789abcdef0123456789abcdef0123456789", 16).unwrap();
+        assert_eq!(mod_riesel(n.clone(), 1, 61), mod_mersenne(n, 61));
+    }
+
+    #[test]
+    fn progress_reports_final_iteration() {
+        let mut last = None;
+        is_mersenne_prime_with_progress(31, |i, total| last = Some((i, total)));
+        assert_eq!(last, Some((29, 29)));
+    }
+
+    #[test]
+    fn callback_sees_progress_and_can_cancel() {
+        let mut reports = Vec::new();
+        let result = is_mersenne_prime_with(101, |progress| {
+            reports.push(progress);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(result, LlResult::Composite { res64: 0xD0DD748DD7817436 });
+        assert_eq!(reports.len(), 99);
+        let last = reports.last().unwrap();
+        assert_eq!((last.iteration, last.total), (99, 99));
+        assert_eq!(last.current_res64_hint, Some(0xD0DD748DD7817436));
+        assert_eq!(
+            reports[49].current_res64_hint,
+            Some(res64(&residue_after(101, 50)))
+        );
+        assert!(reports.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+
+        let mut seen = 0;
+        let aborted = is_mersenne_prime_with(127, |progress| {
+            seen = progress.iteration;
+            if progress.iteration == 40 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(aborted, LlResult::Aborted { iteration: 40 });
+        assert!(!aborted.is_prime());
+        assert_eq!(seen, 40);
+    }
+
+    #[test]
+    fn progress_granularity_is_configurable() {
+        let never = AtomicBool::new(false);
+        let mut iterations = Vec::new();
+        let control = TestControl::new(&never).report_progress_every(300);
+        let result = is_mersenne_prime_interruptible(1279, None, control, |event| {
+            if let TestEvent::Progress(progress) = event {
+                iterations.push(progress.iteration);
+            }
+        });
+        assert!(result.unwrap().is_prime());
+        assert_eq!(iterations, [300, 600, 900, 1200, 1277]);
+    }
+
+    /// A clock on which every iteration published to it takes a
+    /// millisecond.
+    #[derive(Debug)]
+    struct IterationClock<'a>(&'a AtomicU64);
+
+    impl Clock for IterationClock<'_> {
+        fn now(&self) -> Duration {
+            Duration::from_millis(self.0.load(Ordering::Relaxed))
+        }
+    }
+
+    #[test]
+    fn progress_and_checkpoints_can_be_paced_by_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 1)
+            .unwrap()
+            .every(Duration::from_secs(2));
+        let never = AtomicBool::new(false);
+        let iteration = AtomicU64::new(0);
+        let clock = IterationClock(&iteration);
+        let control = TestControl::new(&never)
+            .publish_to(&iteration)
+            .with_clock(&clock)
+            .report_progress_each(Duration::from_secs(1));
+        let (mut progress, mut saved) = (vec![0], vec![0]);
+        let result = is_mersenne_prime_interruptible(4423, Some(&store), control, |event| {
+            match event {
+                TestEvent::Progress(report) => progress.push(report.iteration),
+                TestEvent::CheckpointSaved { iteration } => saved.push(iteration),
+                _ => {}
+            }
+        });
+        assert!(result.unwrap().is_prime());
+        assert_eq!(progress.pop(), Some(4421));
+        for (marks, every) in [(progress, 1000u64), (saved, 2000)] {
+            assert_eq!(marks.len() as u64, 4421 / every + 1, "{:?}", marks);
+            for gap in marks.windows(2).map(|pair| pair[1] - pair[0]) {
+                assert!((every..every + every / 8 + 2).contains(&gap), "{:?}", marks);
+            }
+        }
+    }
+
+    /// The Lucas–Lehmer residue after `iterations` squarings, computed directly.
+    fn residue_after(p: u64, iterations: u64) -> BigUint {
+        let mut s = BigUint::from(4u32);
+        for _ in 0..iterations {
+            s = mod_mersenne(&s * &s - 2u32, p);
+        }
+        s
+    }
+
+    #[test]
+    fn resumes_from_checkpoint_and_removes_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 5).unwrap();
+        for p in [89, 97] {
+            store
+                .save(&Checkpoint::new(p, 20, residue_after(p, 20)))
+                .unwrap();
+
+            let mut resumed_at = None;
+            let result = is_mersenne_prime_with_events(p, Some(&store), |event| {
+                if let TestEvent::Resumed { iteration, .. } = event {
+                    resumed_at = Some(iteration);
+                }
+            });
+            assert_eq!(result.is_prime(), p == 89);
+            assert_eq!(resumed_at, Some(20));
+            assert!(!store.path(p).exists());
+        }
+    }
+
+    #[test]
+    fn interrupted_test_saves_a_resumable_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 1000).unwrap();
+        let stop = AtomicBool::new(false);
+        let control = TestControl::new(&stop);
+        let interrupted = is_mersenne_prime_interruptible(127, Some(&store), control, |event| {
+            if let TestEvent::Progress(Progress { iteration: 50, .. }) = event {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(
+            interrupted,
+            Err(Interrupted {
+                iteration: 50,
+                total: 125,
+                timed_out: false,
+                anomaly: None,
+            })
+        );
+        let checkpoint = store.load(127).unwrap().unwrap();
+        assert_eq!(checkpoint.iteration, 50);
+        assert_eq!(checkpoint.residue, residue_after(127, 50));
+
+        assert!(is_mersenne_prime_with_events(127, Some(&store), |_| {}).is_prime());
+    }
+
+    #[test]
+    fn every_lucas_lehmer_residue_passes_the_jacobi_check() {
+        for p in [89, 97, 101, 107] {
+            let modulus = (BigUint::from(1u32) << p) - 1u32;
+            for i in 1..=p - 2 {
+                assert!(
+                    passes_jacobi_check(&residue_after(p, i), &modulus),
+                    "M({}) s_{}",
+                    p,
+                    i
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn jacobi_check_rolls_back_after_a_corrupted_residue() {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).check_jacobi_every(10);
+        let mut injected = false;
+        let mut mismatches = Vec::new();
+        let result = lucas_lehmer::<arith::MersenneModulus, _, _>(
+            127,
+            None,
+            control,
+            |event| {
+                if let TestEvent::JacobiMismatch {
+                    iteration,
+                    resumed_from,
+                } = event
+                {
+                    mismatches.push((iteration, resumed_from));
+                }
+            },
+            |i, s| {
+                // (3 - 2 | M) = +1, which no correct residue gives.
+                if i == 60 && !injected {
+                    injected = true;
+                    *s = BigUint::from(3u32);
+                }
+            },
+        );
+        assert_eq!(result, Ok(LlResult::Prime));
+        assert_eq!(mismatches, vec![(60, 50)]);
+    }
+
+    /// The iteration, anomaly and `resumed_from` of each anomaly event.
+    type Anomalies = Vec<(u64, Anomaly, Option<u64>)>;
+
+    /// Runs a test of `M(p)` that `corrupt` may break at each iteration,
+    /// with the anomalies it caught.
+    fn with_faults(
+        p: u64,
+        policy: OnAnomaly,
+        mut corrupt: impl FnMut(u64, &mut BigUint),
+    ) -> (Result<LlResult, Interrupted>, Anomalies) {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).on_anomaly(policy);
+        let mut anomalies = Vec::new();
+        let result = lucas_lehmer::<arith::MersenneModulus, _, _>(
+            p,
+            None,
+            control,
+            |event| {
+                if let TestEvent::Anomaly {
+                    iteration,
+                    anomaly,
+                    resumed_from,
+                } = event
+                {
+                    anomalies.push((iteration, anomaly, resumed_from));
+                }
+            },
+            &mut corrupt,
+        );
+        (result, anomalies)
+    }
+
+    #[test]
+    fn out_of_range_residue_is_retried() {
+        // M(127) itself: zero, but not reduced.
+        let mut injected = false;
+        let (result, anomalies) = with_faults(127, OnAnomaly::Retry, |i, s| {
+            if i == 60 && !injected {
+                injected = true;
+                *s = (BigUint::from(1u32) << 127) - 1u32;
+            }
+        });
+        assert_eq!(result, Ok(LlResult::Prime));
+        assert_eq!(anomalies, vec![(60, Anomaly::OutOfRange, Some(0))]);
+    }
+
+    #[test]
+    fn stuck_residue_is_caught_at_the_next_check() {
+        let mut injected = false;
+        let (result, anomalies) = with_faults(1279, OnAnomaly::Retry, |i, s| {
+            if i == 999 && !injected {
+                injected = true;
+                *s = BigUint::ZERO;
+            }
+        });
+        assert_eq!(result, Ok(LlResult::Prime));
+        assert_eq!(anomalies, vec![(1000, Anomaly::Stuck(-2), Some(0))]);
+    }
+
+    #[test]
+    fn anomaly_policies_abort_or_carry_on() {
+        let corrupt = |i, s: &mut BigUint| {
+            if i == 60 {
+                *s = (BigUint::from(1u32) << 127) - 1u32;
+            }
+        };
+        let (result, anomalies) = with_faults(127, OnAnomaly::Abort, corrupt);
+        assert_eq!(
+            result,
+            Err(Interrupted {
+                iteration: 59,
+                total: 125,
+                timed_out: false,
+                anomaly: Some(Anomaly::OutOfRange),
+            })
+        );
+        assert_eq!(anomalies, vec![(60, Anomaly::OutOfRange, None)]);
+
+        let (result, anomalies) = with_faults(127, OnAnomaly::Continue, corrupt);
+        assert!(matches!(result, Ok(LlResult::Composite { .. })));
+        assert_eq!(anomalies, vec![(60, Anomaly::OutOfRange, None)]);
+    }
+
+    #[test]
+    fn repeated_anomalies_give_up_after_the_retries() {
+        let (result, anomalies) = with_faults(127, OnAnomaly::Retry, |i, s| {
+            if i == 60 {
+                *s = (BigUint::from(1u32) << 127) - 1u32;
+            }
+        });
+        assert_eq!(result.unwrap_err().anomaly, Some(Anomaly::OutOfRange));
+        assert_eq!(anomalies.len() as u32, MAX_ANOMALY_RETRIES + 1);
+        assert_eq!(anomalies.last(), Some(&(60, Anomaly::OutOfRange, None)));
+    }
+
+    #[test]
+    fn checkpoint_failing_the_jacobi_check_is_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 1000).unwrap();
+        store
+            .save(&Checkpoint::new(89, 20, BigUint::from(3u32)))
+            .unwrap();
+
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).check_jacobi_every(10);
+        let mut discarded = false;
+        let result = is_mersenne_prime_interruptible(89, Some(&store), control, |event| {
+            if let TestEvent::CheckpointDiscarded(_) = event {
+                discarded = true;
+            }
+        });
+        assert_eq!(result, Ok(LlResult::Prime));
+        assert!(discarded);
+    }
+
+    #[test]
+    fn checkpoints_of_other_shifts_or_backends_are_checked_before_resuming() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 1000).unwrap();
+        let never = AtomicBool::new(false);
+        // Whether the test resumed verified, or why it discarded it.
+        let resume = |checkpoint: Checkpoint, shift| {
+            store.save(&checkpoint).unwrap();
+            let control = TestControl::new(&never).with_shift(shift);
+            let mut outcome = None;
+            let result = is_mersenne_prime_interruptible(89, Some(&store), control, |event| {
+                match event {
+                    TestEvent::Resumed { verified, .. } => outcome = Some(Ok(verified)),
+                    TestEvent::CheckpointDiscarded(e) => outcome = Some(Err(e.to_string())),
+                    _ => {}
+                }
+            });
+            assert_eq!(result, Ok(LlResult::Prime));
+            outcome.unwrap()
+        };
+        let good = Checkpoint::new(89, 20, residue_after(89, 20));
+        assert_eq!(resume(good.clone(), 0), Ok(false));
+        assert_eq!(resume(good.clone().with_shift(7), 7), Ok(false));
+        let shifted = Err("its residue is shifted by 0 bits, not 7".to_string());
+        assert_eq!(resume(good, 7), shifted);
+
+        // Another backend's residue is checked even without Jacobi checks.
+        let elsewhere = |residue| Checkpoint {
+            backend: Some("Elsewhere".to_string()),
+            ..Checkpoint::new(89, 20, residue)
+        };
+        assert_eq!(resume(elsewhere(residue_after(89, 20)), 0), Ok(true));
+        let corrupted = Err("residue fails the Jacobi check".to_string());
+        assert_eq!(resume(elsewhere(BigUint::from(3u32)), 0), corrupted);
+    }
+
+    #[test]
+    fn paused_tests_wait_and_leave_the_pause_out_of_their_time() {
+        let never = AtomicBool::new(false);
+        let pause = std::sync::Arc::new(Pause::new());
+        let compute = ComputeTimer::new();
+        let control = TestControl::new(&never)
+            .pause_with(&pause)
+            .time_compute_with(&compute)
+            .report_progress_every(1);
+        let mut last = None;
+        let started = std::time::Instant::now();
+        let result = lucas_lehmer::<arith::MersenneModulus, _, _>(
+            127,
+            None,
+            control,
+            |event| {
+                if let TestEvent::Progress(progress) = event {
+                    last = Some(progress.elapsed);
+                    if progress.iteration == 50 {
+                        pause.set(true);
+                        let pause = pause.clone();
+                        std::thread::spawn(move || {
+                            std::thread::sleep(Duration::from_millis(300));
+                            pause.set(false);
+                        });
+                    }
+                }
+            },
+            |_, _| {},
+        );
+        assert_eq!(result, Ok(LlResult::Prime));
+        let wall = started.elapsed();
+        assert!(pause.total() >= Duration::from_millis(300));
+        assert!(last.unwrap() <= wall - pause.total());
+        assert!(compute.total() <= wall - pause.total());
+    }
+
+    #[cfg(all(unix, feature = "native"))]
+    #[test]
+    fn compute_time_leaves_out_waiting_for_a_core() {
+        // Four threads to each core: each test spends most of its time on
+        // the clock waiting for its turn.
+        let cores = std::thread::available_parallelism().map_or(1, usize::from);
+        let threads = 4 * cores;
+        let start = std::sync::Barrier::new(threads);
+        let never = AtomicBool::new(false);
+        let times: Vec<(Duration, Duration)> = std::thread::scope(|scope| {
+            let tests: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let compute = ComputeTimer::new();
+                        let control = TestControl::new(&never).time_compute_with(&compute);
+                        start.wait();
+                        let started = std::time::Instant::now();
+                        let result = is_mersenne_prime_interruptible(4423, None, control, |_| {});
+                        assert_eq!(result, Ok(LlResult::Prime));
+                        (compute.total(), started.elapsed())
+                    })
+                })
+                .collect();
+            tests.into_iter().map(|test| test.join().unwrap()).collect()
+        });
+        let compute: Duration = times.iter().map(|&(compute, _)| compute).sum();
+        let wall: Duration = times.iter().map(|&(_, wall)| wall).sum();
+        assert!(compute > Duration::ZERO);
+        assert!(compute < wall / 2, "{:?} of compute in {:?}", compute, wall);
+    }
+
+    #[test]
+    fn passed_deadline_times_out_with_a_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 100_000).unwrap();
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).stop_after(Duration::ZERO);
+        let result = is_mersenne_prime_interruptible(4423, Some(&store), control, |_| {});
+        assert_eq!(
+            result,
+            Err(Interrupted {
+                iteration: 0,
+                total: 4421,
+                timed_out: true,
+                anomaly: None,
+            })
+        );
+        assert_eq!(store.load(4423).unwrap().unwrap().iteration, 0);
+
+        let control = TestControl::new(&never).stop_after(Duration::from_secs(3600));
+        let result = is_mersenne_prime_interruptible(4423, None, control, |_| {});
+        assert_eq!(result, Ok(LlResult::Prime));
+    }
+
+    #[test]
+    fn shifted_tests_agree_with_unshifted_ones() {
+        let never = AtomicBool::new(false);
+        for p in [11, 61, 67, 89, 101, 127] {
+            let expected = is_mersenne_prime(p);
+            for bits in [1, 7, p - 1] {
+                let control = TestControl::new(&never).with_shift(bits);
+                let result = is_mersenne_prime_interruptible(p, None, control, |_| {});
+                assert_eq!(result, Ok(expected), "M({}), shift {}", p, bits);
+            }
+        }
+    }
+
+    #[test]
+    fn res64_milestones_are_evenly_spaced_and_ignore_the_shift() {
+        let never = AtomicBool::new(false);
+        let res64s = |p: u64, bits: u64| {
+            let control = TestControl::new(&never)
+                .with_shift(bits)
+                .record_res64_every(500);
+            let mut res64s = Vec::new();
+            let result = is_mersenne_prime_interruptible(p, None, control, |event| {
+                if let TestEvent::Res64 { iteration, res64 } = event {
+                    res64s.push((iteration, res64));
+                }
+            });
+            (result.unwrap(), res64s)
+        };
+        let (result, unshifted) = res64s(2203, 0);
+        let iterations: Vec<u64> = unshifted.iter().map(|&(iteration, _)| iteration).collect();
+        assert_eq!(iterations, [500, 1000, 1500, 2000, 2201]);
+        assert_eq!(result, LlResult::Prime);
+        assert_eq!(unshifted.last(), Some(&(2201, 0)));
+        assert_eq!(res64s(2203, 1000).1, unshifted);
+
+        let (result, res64s) = res64s(2207, 0);
+        let LlResult::Composite { res64 } = result else {
+            panic!("M(2207) is composite");
+        };
+        assert_eq!(res64s.last(), Some(&(2205, res64)));
+    }
+
+    #[test]
+    fn shifted_residues_match_unshifted_ones_at_every_iteration() {
+        let never = AtomicBool::new(false);
+        let residues = |p: u64, bits: u64| {
+            let control = TestControl::new(&never)
+                .with_shift(bits)
+                .record_milestones_every(1);
+            let mut residues = Vec::new();
+            is_mersenne_prime_interruptible(p, None, control, |event| {
+                if let TestEvent::Milestone { residue, .. } = event {
+                    residues.push(residue.clone());
+                }
+            })
+            .unwrap();
+            residues
+        };
+        for p in (3..160).filter(|&p| is_prime(p)) {
+            let unshifted = residues(p, 0);
+            assert_eq!(unshifted.len() as u64, p - 1);
+            for bits in [1, 2, p / 2, p - 2, p - 1, p + 3] {
+                assert_eq!(residues(p, bits), unshifted, "M({}), shift {}", p, bits);
+            }
+        }
+    }
+
+    #[test]
+    fn shifted_test_saves_and_resumes_unshifted_checkpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 1000).unwrap();
+        let stop = AtomicBool::new(false);
+        let control = TestControl::new(&stop).with_shift(40);
+        let interrupted = is_mersenne_prime_interruptible(107, Some(&store), control, |event| {
+            if let TestEvent::Progress(Progress { iteration: 50, .. }) = event {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+        assert!(interrupted.is_err());
+        assert_eq!(
+            store.load(107).unwrap().unwrap().residue,
+            residue_after(107, 50)
+        );
+
+        stop.store(false, Ordering::Relaxed);
+        let control = TestControl::new(&stop).with_shift(3).check_jacobi_every(10);
+        let result = is_mersenne_prime_interruptible(107, Some(&store), control, |event| {
+            assert!(!matches!(event, TestEvent::JacobiMismatch { .. }));
+        });
+        assert_eq!(result, Ok(LlResult::Prime));
+    }
+
+    #[test]
+    fn split_squarings_give_the_same_residues() {
+        // Stopping part way leaves the residue in a checkpoint to compare.
+        let checkpoint_after_stopping = |p: u64, threads: usize| {
+            let dir = tempfile::tempdir().unwrap();
+            let store = CheckpointStore::new(dir.path(), 1_000_000).unwrap();
+            let stop = AtomicBool::new(false);
+            let control = TestControl::new(&stop).split_across(threads);
+            let result = is_mersenne_prime_interruptible(p, Some(&store), control, |event| {
+                if let TestEvent::Progress(progress) = event {
+                    if progress.iteration >= 200 {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+            assert!(result.is_err());
+            store.load(p).unwrap().unwrap()
+        };
+        for p in [9689, 21701] {
+            let one = checkpoint_after_stopping(p, 1);
+            assert_eq!(one.residue, residue_after(p, one.iteration));
+            for threads in [2, 3] {
+                assert_eq!(checkpoint_after_stopping(p, threads), one, "M({})", p);
+            }
+        }
+    }
+
+    #[test]
+    fn default_jacobi_interval_grows_with_exponent() {
+        assert!(default_jacobi_interval(10_000) > 10_000);
+        assert!(default_jacobi_interval(1_000_000) > default_jacobi_interval(100_000));
+    }
+
+    #[test]
+    fn corrupted_checkpoint_restarts_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = CheckpointStore::new(dir.path(), 5).unwrap();
+        std::fs::write(store.path(89), b"definitely not a checkpoint").unwrap();
+
+        let mut discarded = false;
+        let result = is_mersenne_prime_with_events(89, Some(&store), |event| {
+            if let TestEvent::CheckpointDiscarded(_) = event {
+                discarded = true;
+            }
+        });
+        assert!(result.is_prime());
+        assert!(discarded);
+    }
+
+    #[test]
+    fn is_prime_small_values() {
+        let primes: Vec<u64> = (0..50).filter(|&n| is_prime(n)).collect();
+        assert_eq!(
+            primes,
+            vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]
+        );
+    }
+}
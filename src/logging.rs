@@ -0,0 +1,104 @@
+//! The logger behind the `log` macros: messages go to stderr, and with
+//! `--log-file` also to a file, one timestamped line each.
+//!
+//! Stdout carries only results, so everything else this program says goes
+//! through here. Errors also go to the `--events` stream, if any. On stderr, errors and warnings keep their `Error:` and
+//! `Warning:` prefixes, in red and yellow on a terminal that takes
+//! [`color`]s, and everything else is printed as is; the file gets plain
+//! lines like
+//!
+//! ```text
+//! 2024-05-01T12:00:00.000Z INFO  Searching for Mersenne primes in the range p = 2 to p = 31...
+//! 2024-05-01T12:00:03.125Z WARN  could not write to the results file: disk full
+//! ```
+
+use crate::color::{self, Style};
+use crate::events::{self, Event};
+use chrono::Utc;
+use indicatif::MultiProgress;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The progress bars on stderr, if any, which are hidden while a message
+/// is printed so it does not end up in the middle of one.
+static BARS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        if record.level() == Level::Error {
+            events::emit(Event::Error { message: &message });
+        }
+        let print = || match record.level() {
+            Level::Error => eprintln!(
+                "{}",
+                color::stderr(Style::Error, format_args!("Error: {}", message))
+            ),
+            Level::Warn => eprintln!(
+                "{}",
+                color::stderr(Style::Warning, format_args!("Warning: {}", message))
+            ),
+            _ => eprintln!("{}", message),
+        };
+        match &*BARS.lock().unwrap() {
+            Some(bars) => bars.suspend(print),
+            None => print(),
+        }
+
+        if let Some(file) = &self.file {
+            let line = format!(
+                "{} {:<5} {}",
+                Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ"),
+                record.level(),
+                message
+            );
+            // There is nowhere left to report a failed write.
+            let _ = writeln!(file.lock().unwrap(), "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+/// Installs the logger, showing messages up to `level` and appending them
+/// to `file` too if given.
+pub fn init(level: LevelFilter, file: Option<&Path>) -> io::Result<()> {
+    let file = match file {
+        Some(path) => Some(Mutex::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+    log::set_max_level(level);
+    // Only fails if a logger is already installed, which would be a bug.
+    log::set_boxed_logger(Box::new(Logger { level, file })).expect("logger installed twice");
+    Ok(())
+}
+
+/// Hides `bars` while messages are printed, until [`detach_progress`].
+pub fn attach_progress(bars: &MultiProgress) {
+    *BARS.lock().unwrap() = Some(bars.clone());
+}
+
+pub fn detach_progress() {
+    *BARS.lock().unwrap() = None;
+}
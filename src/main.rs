@@ -1,149 +1,4514 @@
-use num_bigint::{BigUint, ToBigUint};
-use num_traits::{One, Zero};
-use std::time::Instant;
+/// Prints a line of results: to stdout, or to stderr while `--events -`
+/// has stdout.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if crate::events::on_stdout() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod admission;
+mod affinity;
+mod bench;
+mod color;
+mod config;
+mod control;
+mod eta;
+mod events;
+mod history;
+mod http;
+mod idle;
+mod logging;
+mod notify;
+mod pipeline;
+mod plan;
+mod progress;
+mod selftest;
+mod server;
+mod single;
+mod spreadsheet;
+mod status;
+mod summary;
+mod worker;
+
+use mersenne::arith::choice::{
+    ArithBackend, BackendChoice, Calibration, CALIBRATION_ITERATIONS, FIXED_CAPACITY,
+};
+use mersenne::audit::{self, AuditError, AuditLog, AuditRecord, Milestone};
+use mersenne::checkpoint::{Checkpoint, CheckpointError, CheckpointStore, FORMAT_VERSION};
+use mersenne::clock::ComputeTimer;
+use mersenne::chunk::Chunk;
+use mersenne::compare::{self, Comparison};
+use mersenne::diff;
+use mersenne::database::{self, Database, NewRun};
+use mersenne::fermat::{pepin_test_interruptible, PepinResult};
+use mersenne::factor::{pminus1, wagstaff_trial_factor, worthwhile_tf_depth};
+use mersenne::known::{
+    expected_mersenne_primes, is_known_mersenne_exponent, known_between, DISCOVERY_YEARS,
+    MERSENNE_EXPONENTS,
+};
+use mersenne::known_factors::KnownFactors;
+use mersenne::ledger::{self, Ledger};
+use mersenne::number::{
+    self, decimal_digits, digit_count, fermat_number, mersenne_number, riesel_number,
+    wagstaff_number,
+};
+use mersenne::worktodo::{Line, WorkTodo};
+use mersenne::primality::rejection;
+use mersenne::primenet::{Identity, PrimeNetFile, PrimeNetResult};
+use mersenne::proof::{self, Proof, MAX_POWER};
+use mersenne::prp::{prp_test_interruptible, wagstaff_prp_test_interruptible, PrpResult};
+use mersenne::report::{
+    format_res2048, format_res64, Confirmation, DoubleCheck, ExponentRange, FactoringStage, Form,
+    Res64Milestone, ReportLine, RunSummary, StageSummary, SummaryLine, TestKind, TestReport,
+};
+use mersenne::residue::{self, ResidueHeader, ResidueSummary};
+use mersenne::results::{self, ResultsFile};
+use mersenne::riesel::is_riesel_prime_interruptible;
+use mersenne::run::{RunContext, RunId};
+use mersenne::sample::{self, Recorded, Tally};
+use mersenne::search::CancellationToken;
+use mersenne::sieve_cache::{self, SieveCache, SieveCacheError};
+use mersenne::stream::{ExponentReader, Token};
+use mersenne::system::{self, SystemInfo};
+use mersenne::throttle::Throttle;
+use mersenne::{
+    is_mersenne_prime_interruptible, perfect_number, sieve, trial_factor, Interrupted,
+    default_jacobi_interval, res64, LlResult, OnAnomaly, TestControl, TestEvent,
+};
+use num_bigint::BigUint;
+use admission::MemoryBudget;
+use affinity::{CpuList, Pinning};
+use eta::Eta;
+use history::Export;
+use chrono::Utc;
+use color::{ColorChoice, Style};
+use log::{debug, error, info, warn, Level, LevelFilter};
+use notify::Notifier;
+use pipeline::{Candidate, Outcome, Stage};
+use plan::{plan, riesel_testable, Census, Disposition, ExponentFilter, PlanLine, WorkPlan};
+use progress::{format_duration, group, ProgressDisplay, OVERALL_INTERVAL};
+use spreadsheet::Spreadsheet;
+use status::{Activity, Heartbeat, RunProgress};
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, Once, OnceLock};
+use std::time::{Duration, Instant};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use rayon::prelude::*;
 use structopt::StructOpt;
-use std::io::{self, Write};
 
-#[derive(StructOpt)]
-struct Options {
-    start_exponent: u64,
+#[derive(StructOpt)]
+#[structopt(after_help = "EXIT STATUS:
+    0    at least one Mersenne prime was found; selftest and bench: success
+    1    the run completed without finding a Mersenne prime
+    2    invalid arguments or unusable input files
+    3    interrupted with Ctrl-C
+    4    internal error, or tests failed and --retries did not recover them
+    5    the self-test gave a wrong answer
+    6    stopped by --time-limit; run again to continue
+    7    a --confirm re-run disagreed with a prime result
+    8    audit-verify, verify-proof, verify-residue, verify-sample or compare found something
+         that does not check out
+    9    no exponent in the range is left to test")]
+enum Command {
+    /// Search every prime exponent in a range
+    Search {
+        /// First exponent of the range
+        #[structopt(required_unless = "ranges")]
+        start_exponent: Option<u64>,
+
+        /// Last exponent of the range (inclusive)
+        #[structopt(required_unless_one = &["count", "ranges"])]
+        end_exponent: Option<u64>,
+
+        /// Instead of a last exponent, go on from the first until <n>
+        /// exponents are to be tested, leaving out the ones --skip-known,
+        /// --ledger, --results and --db skip
+        #[structopt(
+            long,
+            value_name = "n",
+            parse(try_from_str = parse_positive),
+            conflicts_with = "end-exponent"
+        )]
+        count: Option<u64>,
+
+        /// Search several ranges of exponents instead of one, such as
+        /// 20000-25000,130000-140000, with a summary of each as well as of
+        /// them all; the ranges cannot overlap
+        #[structopt(
+            long,
+            use_delimiter = true,
+            value_name = "start-end",
+            conflicts_with_all = &["start-exponent", "count"]
+        )]
+        ranges: Vec<ExponentRange>,
+
+        #[structopt(flatten)]
+        options: Options,
+    },
+
+    /// Test particular exponents, or the assignments in a worktodo file
+    ///
+    /// The Lucas-Lehmer test of a single exponent, as in `test 110503`,
+    /// checkpoints to the current directory unless --checkpoint-dir is
+    /// given, carries on from a checkpoint left there with --resume, and ends
+    /// with a report of the test: its result, Res64, speed, checkpoints and
+    /// error checks.
+    Test {
+        /// Prime exponents to test, separated by spaces or commas
+        #[structopt(
+            use_delimiter = true,
+            value_name = "p",
+            required_unless_one = &["worktodo", "stdin"]
+        )]
+        exponents: Vec<u64>,
+
+        /// Take Test= and DoubleCheck= assignments from a Prime95-style worktodo
+        /// file, removing each line once its test completes
+        #[structopt(long, parse(from_os_str), conflicts_with = "exponents")]
+        worktodo: Option<PathBuf>,
+
+        /// Read the exponents from standard input, separated by whitespace or
+        /// newlines, and test each as it arrives until the input ends, as in
+        /// `candidate-gen | Mersenne test --stdin`. Malformed and rejected
+        /// exponents are reported with their line number and skipped
+        #[structopt(long, conflicts_with_all = &["exponents", "worktodo"])]
+        stdin: bool,
+
+        /// With --stdin, stop at the first malformed or rejected exponent
+        /// instead of skipping it
+        #[structopt(long, requires = "stdin")]
+        strict: bool,
+
+        /// Carry on from the checkpoint a single exponent's test left in the
+        /// current directory. Without it such a checkpoint stops the test,
+        /// unless --force-restart deletes it; a --checkpoint-dir is always
+        /// resumed from
+        #[structopt(long, conflicts_with_all = &["worktodo", "stdin"])]
+        resume: bool,
+
+        #[structopt(flatten)]
+        options: Options,
+    },
+
+    /// Test the Fermat numbers F(n) = 2^(2^n) + 1 for every n in a range with
+    /// Pépin's test, which proves each one prime or composite. F(n) has 2^n
+    /// bits, so each n takes about four times as long as the one before.
+    Fermat {
+        /// First index of the range
+        start_n: u64,
+
+        /// Last index of the range (inclusive), at most 63
+        end_n: u64,
+
+        #[structopt(flatten)]
+        options: Options,
+    },
+
+    /// Check this build against known Mersenne primes and composites
+    Selftest {
+        /// Number of worker threads [default: all cores]
+        #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+        threads: Option<usize>,
+    },
+
+    /// Measure Lucas-Lehmer iteration throughput, on one thread and on all
+    Bench {
+        /// Exponents to benchmark [default: 100003,1000003,10000019]
+        #[structopt(long, use_delimiter = true, value_name = "p,...")]
+        exponents: Vec<u64>,
+
+        /// Iterations to time per exponent [default: 1000, fewer for
+        /// exponents above 100000 so the bench finishes in reasonable time]
+        #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+        iterations: Option<u64>,
+
+        /// Number of threads for the parallel measurement [default: all cores]
+        #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+        threads: Option<usize>,
+    },
+
+    /// List the known Mersenne primes with their discovery years and sizes
+    ListKnown,
+
+    /// Print the version, the git commit it was built from, the CPU, core
+    /// count, OS and arithmetic backend: what runs record with their results
+    Info {
+        /// Print them as one JSON object
+        #[structopt(long)]
+        json: bool,
+    },
+
+    /// Check the chain of checksums of an --audit-log file and recompute
+    /// the stretches of each test between its recorded residues; exits
+    /// with status 8 if anything does not check out
+    AuditVerify {
+        /// The audit log
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+
+        /// Recompute only this many stretches, picked at random, instead of
+        /// all of them
+        #[structopt(long, value_name = "count")]
+        spot_check: Option<usize>,
+
+        /// Number of stretches to recompute at once [default: all cores]
+        #[structopt(long)]
+        threads: Option<usize>,
+    },
+
+    /// Check a PRP proof written with --proof-dir and print the result it
+    /// proves; exits with status 8 if the proof does not hold
+    VerifyProof {
+        /// The proof file, such as M86243.proof
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+
+    /// Check a residue written with --save-residue and print its Res64 and
+    /// Res2048; with --from, recompute the test's last iterations from an
+    /// earlier checkpoint and exit with status 8 unless they end on it
+    VerifyResidue {
+        /// The residue file, such as M86243.residue
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+
+        /// A checkpoint of the same Lucas-Lehmer test, such as M86243.ckpt
+        /// from --checkpoint-dir, to recompute the residue from
+        #[structopt(long, value_name = "checkpoint", parse(from_os_str))]
+        from: Option<PathBuf>,
+    },
+
+    /// Compare the Res64 milestones of two runs recorded with --milestones,
+    /// from their results files or --json output, and print for each
+    /// number whether they agree or where they first diverged; exits with
+    /// status 8 if any diverged
+    Compare {
+        /// The first run
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+
+        /// The other run
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+    },
+
+    /// Summarize what changed between two runs of the same numbers, read
+    /// from their results files, --csv files, --json output or ledgers in
+    /// any mix: the numbers only one has, those one found prime and the
+    /// other did not, Res64s that differ and how the times changed; exits
+    /// with status 8 if the runs disagree on any result
+    Diff {
+        /// The first run
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+
+        /// The other run, whose times are compared with the first's
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+
+        /// Compare only the results of this run ID in the first file
+        #[structopt(long, value_name = "id")]
+        run_a: Option<RunId>,
+
+        /// Compare only the results of this run ID in the other file
+        #[structopt(long, value_name = "id")]
+        run_b: Option<RunId>,
+
+        /// Print the differences as one JSON object
+        #[structopt(long)]
+        json: bool,
+    },
+
+    /// Re-run the tests of a reproducible random sample of the composite
+    /// results in a results file, --ledger or --json output and compare
+    /// their Res64s, to estimate how often the machine that produced them
+    /// got a test wrong; exits with status 8 if any disagree
+    VerifySample {
+        /// The results file, ledger or --json output to sample
+        #[structopt(long, value_name = "path", parse(from_os_str))]
+        results: PathBuf,
+
+        /// The share of the composites to re-run, rounded up to a whole
+        /// number of them
+        #[structopt(long, value_name = "share", default_value = "0.01",
+                    parse(try_from_str = parse_fraction))]
+        fraction: f64,
+
+        /// Seed of the sample: the same seed picks the same exponents from
+        /// the same results [default: random, and printed]
+        #[structopt(long, value_name = "n")]
+        seed: Option<u64>,
+
+        /// The numbers whose results are sampled
+        #[structopt(long, value_name = "form", default_value = "mersenne",
+                    possible_values = &["mersenne", "wagstaff", "riesel"])]
+        form: Form,
+
+        /// The multiplier k of the Riesel numbers sampled with --form riesel
+        /// [default: 1]
+        #[structopt(long, value_name = "k")]
+        k: Option<u64>,
+
+        /// File to write the exponents that disagree to, one per line, for
+        /// testing them again in full with test
+        #[structopt(long, value_name = "path", parse(from_os_str),
+                    default_value = "mismatches.txt")]
+        mismatches: PathBuf,
+
+        /// Number of tests to run at once [default: all cores]
+        #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+        threads: Option<usize>,
+    },
+
+    /// Summarize a --db results database: its runs, the totals of every
+    /// range they covered, the slowest exponents and the unfinished work
+    Report {
+        /// The database
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+
+        /// How many of the slowest exponents to list
+        #[structopt(long, value_name = "n", default_value = "10")]
+        slowest: usize,
+
+        /// Print every result as csv or json, one line each, instead
+        #[structopt(long, value_name = "format")]
+        export: Option<Export>,
+
+        /// Report only on the run with this ID: its row, the results it
+        /// recorded last and the ranges it covered
+        #[structopt(long, value_name = "id")]
+        run: Option<RunId>,
+    },
+
+    /// Check a checkpoint file written by --checkpoint-dir and print what it
+    /// records; exits with status 2 if a test would refuse to resume from it
+    CheckpointInfo {
+        /// The checkpoint file, such as M86243.ckpt
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+
+    /// Hand out the prime exponents of a range to `work` clients over HTTP
+    /// and collect their results. Stopping and starting again with the
+    /// same files carries on where it left off.
+    Serve {
+        /// First and last exponent of the range (inclusive)
+        #[structopt(long, number_of_values = 2, value_names = &["start", "end"],
+                    required = true)]
+        range: Vec<u64>,
+
+        /// Address to listen on; use 0.0.0.0:<port> to accept workers from
+        /// other machines
+        #[structopt(long, value_name = "addr", default_value = "127.0.0.1:7070")]
+        listen: String,
+
+        /// How long a worker has to report an exponent before it is handed
+        /// to someone else, for example 24h or 3d
+        #[structopt(long, value_name = "duration", default_value = "24h",
+                    parse(try_from_str = parse_duration))]
+        lease: Duration,
+
+        /// File recording which exponents are leased to whom
+        #[structopt(long, value_name = "path", parse(from_os_str),
+                    default_value = "mersenne-server.json")]
+        state: PathBuf,
+
+        /// Results file, in the format of --results, that every reported
+        /// exponent is appended to
+        #[structopt(long, value_name = "path", parse(from_os_str),
+                    default_value = "mersenne-results.txt")]
+        results: PathBuf,
+    },
+
+    /// Test exponents handed out by a `serve` server, such as
+    /// http://host:7070, and report the results back to it, until its
+    /// range is done. --threads tests run at once; options that choose
+    /// exponents, such as --order and --chunk, do not apply.
+    Work {
+        /// The server to work for
+        #[structopt(long, value_name = "url")]
+        server: String,
+
+        #[structopt(flatten)]
+        options: Options,
+    },
+}
+
+// Options shared by `search`, `test` and `work`. Not a doc comment, because
+// structopt would show it as the help text of each subcommand.
+#[derive(StructOpt)]
+struct Options {
+    /// Read default options from this TOML file instead of mersenne.toml in
+    /// the working directory. Keys are option names with _ for -, such as
+    /// checkpoint_dir = "ckpt"; MERSENNE_* environment variables, such as
+    /// MERSENNE_THREADS=4, override the file, and options given here
+    /// override both.
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// Print the options in effect after merging the config file, the
+    /// environment and the command line, with where each value came from,
+    /// and exit
+    #[structopt(long)]
+    print_config: bool,
+
+    /// Append one line per completed exponent to this file, and skip exponents
+    /// it already has results for
+    #[structopt(long, parse(from_os_str))]
+    results: Option<PathBuf>,
+
+    /// Test exponents again even if the results file or --db already has
+    /// them
+    #[structopt(long)]
+    retest: bool,
+
+    /// Record the final result of every exponent in this file as it
+    /// finishes, syncing each line to disk, and skip the exponents it
+    /// already has, so running the same command again resumes an
+    /// interrupted search. Unlike --results it decides what runs, and
+    /// --retest does not override it.
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    ledger: Option<PathBuf>,
+
+    /// Store every result in this SQLite database, with a row for each run
+    /// and one for each exponent, which a re-test with --retest updates.
+    /// Like --results it skips the exponents it already has; `report`
+    /// summarizes it. Machines can share one database file.
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    db: Option<PathBuf>,
+
+    /// Write a CSV row for every exponent to this file as it finishes, after
+    /// a header row of the columns: exponent, candidate_source,
+    /// stage_eliminated, is_prime, res64, iterations, elapsed_seconds,
+    /// iters_per_second, started_at, finished_at and range. Replaces the
+    /// file if it exists
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    csv: Option<PathBuf>,
+
+    /// Like --csv, but add to the file, writing the header only if it is
+    /// new
+    #[structopt(long, value_name = "path", parse(from_os_str), conflicts_with = "csv")]
+    csv_append: Option<PathBuf>,
+
+    /// Append the residues of every Lucas-Lehmer test to this file at the
+    /// start, every 1/16 of the way and at the end, in a chain of
+    /// checksummed lines that `audit-verify` checks by recomputing the
+    /// stretches in between. Each line holds 17 full residues, about 4*p
+    /// bytes for M(p).
+    #[structopt(long, value_name = "path", parse(from_os_str), conflicts_with = "prp")]
+    audit_log: Option<PathBuf>,
+
+    /// Record the Res64 of every Lucas-Lehmer and PRP test at <k> evenly
+    /// spaced iterations, the last of them the final one, in its results
+    /// and --json report, for `compare` to tell where two runs of an
+    /// exponent part ways
+    #[structopt(long, value_name = "k", parse(try_from_str = parse_positive))]
+    milestones: Option<u64>,
+
+    /// Write a proof of every PRP test to this directory, such as
+    /// M86243.proof, which `verify-proof` checks with about 1/2^power of
+    /// the work of the test. Needs --prp or --form wagstaff.
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    proof_dir: Option<PathBuf>,
+
+    /// Write the full final residue of every Lucas-Lehmer and PRP test to
+    /// this directory, such as M86243.residue, for other programs to
+    /// cross-verify and `verify-residue` to check: about p/8 bytes for M(p)
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    save_residue: Option<PathBuf>,
+
+    /// Give the low 2048 bits of the final residue of every Lucas-Lehmer
+    /// and PRP test after its Res64, as 512 hex digits, in the results
+    /// file, --json and --csv, for programs that compare more than the Res64
+    #[structopt(long)]
+    res2048: bool,
+
+    /// The power of --proof-dir proofs, 1 to 12: a test keeps 2^power
+    /// residues in memory, 2^power * p/8 bytes for M(p), and checking the
+    /// proof takes about 1/2^power of its work
+    #[structopt(long, value_name = "n", default_value = "8",
+                parse(try_from_str = parse_proof_power))]
+    proof_power: u32,
+
+    /// Print a summary of --ledger, and how much of the requested exponents
+    /// it covers, without testing anything
+    #[structopt(long, requires = "ledger")]
+    ledger_report: bool,
+
+    /// Print what the run would do without testing anything: how many
+    /// candidates there are, which are skipped and why, and how long the
+    /// rest would take by number of digits. Nothing is written, not even
+    /// an empty --results file or --ledger.
+    #[structopt(long, conflicts_with = "ledger-report")]
+    dry_run: bool,
+
+    /// Append a PrimeNet v5 JSON result line per completed primality test to
+    /// this file, for manual submission to mersenne.org
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    primenet_results: Option<PathBuf>,
+
+    /// PrimeNet user ID to put in --primenet-results lines
+    #[structopt(long, value_name = "user", requires = "primenet-results")]
+    primenet_user: Option<String>,
+
+    /// PrimeNet computer name to put in --primenet-results lines
+    #[structopt(long, value_name = "name", requires = "primenet-results")]
+    primenet_computer: Option<String>,
+
+    /// Leave out the exponents of the 52 known Mersenne primes
+    #[structopt(long)]
+    skip_known: bool,
+
+    /// Test only exponents p of a special shape: sophie-germain keeps those
+    /// with 2p+1 prime, safe those with (p-1)/2 prime and twin those with
+    /// p-2 or p+2 prime. double-mersenne tests the double Mersenne numbers
+    /// MM(q) = M(2^q-1) small enough to be worth testing, MM(2) to MM(7),
+    /// whatever the range
+    #[structopt(long, value_name = "filter", default_value = "all",
+                possible_values = &["all", "sophie-germain", "safe", "twin", "double-mersenne"])]
+    exponent_filter: ExponentFilter,
+
+    /// Leave out the exponents of Mersenne numbers with a factor in this
+    /// file of exponent,factor lines, such as an export of mersenne.ca, and
+    /// record them as factored by it. Lines whose factor does not divide
+    /// its Mersenne number are skipped with a warning
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    known_factors: Option<PathBuf>,
+
+    /// Say more on stderr: -v when each exponent starts and finishes, with
+    /// its time (--log-level debug); -vv the progress of each test as well
+    /// (--log-level trace)
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Print nothing but the results: no summary on stdout and only warnings
+    /// and errors on stderr
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// How often a test reports its progress, which its progress bar, speed
+    /// and ETA are updated from, for example 10s or 1m, however long its
+    /// iterations take
+    #[structopt(long, value_name = "duration", default_value = "1s",
+                parse(try_from_str = parse_duration))]
+    progress_every: Duration,
+
+    /// How many of the latest progress intervals of a test its speed and
+    /// ETA are averaged over
+    #[structopt(long, value_name = "n", default_value = "10",
+                parse(try_from_str = parse_positive))]
+    rate_window: usize,
+
+    /// Warn when the speed of a test suddenly falls by more than this many
+    /// percent below its average over --rate-window, such as when another
+    /// program takes over the cores or the machine throttles
+    #[structopt(long, value_name = "percent", default_value = "30",
+                parse(try_from_str = parse_percent))]
+    slowdown_warning: u32,
+
+    /// Least severe messages to show on stderr and write to --log-file: error,
+    /// warn, info, debug or trace. Results always go to stdout regardless.
+    #[structopt(long, value_name = "level", default_value = "info",
+                possible_values = &["error", "warn", "info", "debug", "trace"])]
+    log_level: LevelFilter,
+
+    /// Also append every message shown on stderr to this file, with a
+    /// timestamp and level on each line
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Also append a JSON event for each moment of the run worth watching,
+    /// such as a test starting or a prime found, one per line, to this
+    /// file; with -, write them to stdout and move the results to stderr
+    #[structopt(long, value_name = "path|-", parse(from_os_str))]
+    events: Option<PathBuf>,
+
+    /// When to color the output: auto colors a terminal unless NO_COLOR is
+    /// set or TERM is dumb, always colors one regardless, and never turns
+    /// colors off. Output that is not a terminal is never colored.
+    #[structopt(long, value_name = "when", default_value = "auto",
+                possible_values = &["auto", "always", "never"])]
+    color: ColorChoice,
+
+    /// Print numbers without separators between their groups of digits,
+    /// as 25860001 rather than 25,860,001, for scripts that read the
+    /// output; --json output never has them
+    #[structopt(long)]
+    raw_numbers: bool,
+
+    /// What to put between groups of three digits in numbers printed for
+    /// people, such as "," for 25,860,001, " " or "'"
+    #[structopt(long, value_name = "text", default_value = ",")]
+    digit_separator: String,
+
+    /// The numbers to test: mersenne, M(p) = 2^p - 1, wagstaff,
+    /// W(p) = (2^p + 1) / 3 for odd prime p, or riesel, k*2^n - 1 for the
+    /// odd k given with --k and every n with k < 2^n. Wagstaff numbers are
+    /// trial factored and then given the --prp test modulo W(p); Riesel
+    /// numbers get the Lucas-Lehmer-Riesel test. Neither can be
+    /// checkpointed, double-checked or P-1 factored, and they have no
+    /// PrimeNet results.
+    #[structopt(long, value_name = "form", default_value = "mersenne",
+                possible_values = &["mersenne", "wagstaff", "riesel"])]
+    form: Form,
+
+    /// The multiplier k of the Riesel numbers k*2^n - 1 tested with
+    /// --form riesel [default: 1]
+    #[structopt(long, value_name = "k")]
+    k: Option<u64>,
+
+    /// Run a base-3 Fermat probable-prime test with Gerbicz error checking
+    /// instead of Lucas-Lehmer. Slower, but hardware errors are detected and
+    /// recomputed instead of silently producing a wrong result.
+    #[structopt(long, conflicts_with = "checkpoint-dir")]
+    prp: bool,
+
+    /// Test each exponent twice, the second time with the Lucas-Lehmer
+    /// residue shifted by a random number of bits or by --shift, and report
+    /// whether the results MATCH. A MISMATCH means one run went wrong, so a
+    /// third run with another shift breaks the tie. Checkpoints cover the
+    /// first run only.
+    #[structopt(long, conflicts_with = "prp")]
+    double_check: bool,
+
+    /// Store the Lucas-Lehmer residue shifted by this many bits, or by a
+    /// random number of them with `random`, which puts different bit
+    /// patterns through the arithmetic without changing the result. With
+    /// --double-check it is the shift of the second run. The shift goes in
+    /// the results.
+    #[structopt(long, value_name = "bits|random", conflicts_with = "prp")]
+    shift: Option<ShiftChoice>,
+
+    /// The arithmetic of the Lucas-Lehmer tests: bignum (num-bigint), fixed
+    /// (arrays on the stack, for exponents up to 4096) or gmp (in builds
+    /// with the gmp feature). auto times 100 iterations of each at every
+    /// size the run comes to and takes the fastest, and the next fastest for
+    /// the second run of a --double-check. The backend goes in the results
+    #[structopt(long, value_name = "backend", default_value = "auto",
+                possible_values = &["auto", "bignum", "fixed", "gmp"])]
+    backend: BackendChoice,
+
+    /// Re-run every prime result at once by the other method, on one
+    /// thread: a PRP test for a Lucas-Lehmer result, a Lucas-Lehmer test
+    /// with a random shift for a --prp one. The result is CONFIRMED or, if
+    /// the re-run disagrees, a CONFLICT: both Res64s are shown, the results
+    /// file marks the exponent review=needed, and the run exits with
+    /// status 7.
+    #[structopt(long)]
+    confirm: bool,
+
+    /// Give up on any single test that runs longer than this, saving a
+    /// checkpoint if --checkpoint-dir is set, and carry on with the rest.
+    /// Timed-out exponents are listed at the end and are tested again by the
+    /// next run with the same --results file.
+    #[structopt(long, value_name = "seconds", parse(try_from_str = parse_positive))]
+    max_test_seconds: Option<u64>,
+
+    /// Stop after this long, for example 8h, 45m or 1h30m: no new tests are
+    /// started, and running tests checkpoint (with --checkpoint-dir) and
+    /// stop as they would for Ctrl-C. Running the same command again picks
+    /// up where it left off.
+    #[structopt(long, value_name = "duration", parse(try_from_str = parse_duration))]
+    time_limit: Option<Duration>,
+
+    /// Directory for periodic checkpoints, so interrupted tests can resume
+    /// (Lucas-Lehmer only)
+    #[structopt(long, parse(from_os_str))]
+    checkpoint_dir: Option<PathBuf>,
+
+    /// Delete the checkpoints this run cannot resume, those of another test
+    /// or shift, and start their tests over, instead of stopping with an
+    /// error
+    #[structopt(long)]
+    force_restart: bool,
+
+    /// Iterations between Jacobi error checks of the Lucas-Lehmer residue; a
+    /// failed check recomputes from the last good residue. 0 disables them.
+    /// [default: about 1% of the work, every few hours for large exponents]
+    #[structopt(long, value_name = "iterations")]
+    jacobi_interval: Option<u64>,
+
+    /// What to do when a Lucas-Lehmer residue fails a sanity check (out of
+    /// range, or stuck at 0 or +-2 before the end), which only hardware or
+    /// arithmetic errors cause: retry from the last checkpoint or good
+    /// residue, giving up after 3 retries; abort the test, which the next
+    /// run tries again; or continue regardless. Errors caught are counted
+    /// in the results and the summary.
+    #[structopt(long, value_name = "policy", default_value = "retry",
+                possible_values = &["retry", "abort", "continue"])]
+    on_error: OnAnomaly,
+
+    /// Test the exponents whose tests failed, by panicking or after
+    /// arithmetic errors, again at the end of the run, one at a time, up to
+    /// this many times each. A run with failures left exits with status 4.
+    #[structopt(long, value_name = "n", default_value = "0")]
+    retries: u32,
+
+    /// How often a test writes a checkpoint (with --checkpoint-dir), for
+    /// example 5m or 1h, however long its iterations take
+    #[structopt(long, value_name = "duration", default_value = "5m",
+                parse(try_from_str = parse_duration))]
+    checkpoint_every: Duration,
+
+    /// Write a checkpoint every this many iterations instead of
+    /// --checkpoint-every
+    #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+    checkpoint_interval: Option<u64>,
+
+    /// Trial factor up to 2^<bits> before running Lucas-Lehmer (0 disables). Without it, up
+    /// to 2^32, or less for small exponents whose test is quicker than the search
+    #[structopt(long, value_name = "bits")]
+    tf_depth: Option<u32>,
+
+    /// Stage 1 bound for P-1 factoring after trial factoring (0 disables P-1)
+    #[structopt(long, value_name = "B1", default_value = "0")]
+    p1_b1: u64,
+
+    /// Stage 2 bound for P-1 factoring (0, or anything up to B1, skips stage 2)
+    #[structopt(long, value_name = "B2", default_value = "0")]
+    p1_b2: u64,
+
+    /// Print the full decimal value of each prime found, once the
+    /// search is over
+    #[structopt(long)]
+    print_number: bool,
+
+    /// Write the decimal value of each Mersenne prime found to <dir>/M<p>.txt,
+    /// or <dir>/W<p>.txt for a Wagstaff prime and <dir>/F<n>.txt for a
+    /// Fermat prime
+    #[structopt(long, value_name = "dir", parse(from_os_str))]
+    save_number: Option<PathBuf>,
+
+    /// Also compute the even perfect number 2^(p-1)(2^p - 1) of each Mersenne
+    /// prime found and report its size. --print-number and --save-number
+    /// then cover it too, saving it as <dir>/M<p>.perfect.txt.
+    #[structopt(long)]
+    perfect: bool,
+
+    /// Print a one-line status report to stderr every <seconds>: exponents
+    /// done and to go, the share of the run's work done, weighted by the
+    /// size of each test, the progress of each running test and the overall
+    /// iteration rate (0 disables)
+    #[structopt(long, value_name = "seconds", default_value = "600")]
+    status_interval: u64,
+
+    /// Keep a JSON status document at this path for other programs to poll:
+    /// start time, range, exponents done, the share of the work done,
+    /// running tests, estimated completion and primes found. It is replaced
+    /// atomically on each update.
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    status_file: Option<PathBuf>,
+
+    /// Seconds between updates of --status-file
+    #[structopt(long, value_name = "seconds", default_value = "60",
+                parse(try_from_str = parse_positive))]
+    status_file_interval: u64,
+
+    /// Skip the end-of-run table and totals; print only the per-exponent lines
+    #[structopt(long)]
+    no_summary: bool,
+
+    /// How many of the slowest exponents the summary lists; 0 lists none
+    #[structopt(long, value_name = "n", default_value = "10")]
+    summary_top: usize,
+
+    /// Print results as newline-delimited JSON on stdout; other output goes to stderr
+    #[structopt(long)]
+    json: bool,
+
+    /// Order in which exponents are handed to the worker threads: smallest,
+    /// largest or random. Smallest reports small primes soonest, but a run
+    /// can end with one huge test keeping a single core busy while the rest
+    /// idle; largest starts the longest tests first so the others fill in
+    /// around them and the run finishes sooner. Largest and random collect
+    /// every candidate before starting.
+    #[structopt(long, value_name = "order", default_value = "smallest",
+                possible_values = &["smallest", "largest", "random"])]
+    order: Order,
+
+    /// Print each result only once those of the exponents handed out before
+    /// it are printed, which with --order smallest is exponent order.
+    /// Without it a result is printed the moment its test finishes, so
+    /// with several threads a quick test can overtake a slow one.
+    #[structopt(long)]
+    ordered: bool,
+
+    /// Number of worker threads [default: all cores]. With --threads 1,
+    /// exponents are tested one at a time in --order order and their
+    /// results printed in that order, as with --ordered, which also keeps
+    /// -vv progress output readable.
+    #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+    threads: Option<usize>,
+
+    /// Threads for the primality tests [default: --threads]. Factoring
+    /// has threads of its own and runs ahead of the tests, so the tests
+    /// only ever get the exponents it did not eliminate.
+    #[structopt(long, value_name = "n", parse(try_from_str = parse_positive))]
+    ll_threads: Option<usize>,
+
+    /// Threads for trial factoring, and as many again for P-1 with
+    /// --p1-b1.
+    #[structopt(long, value_name = "n", default_value = "1",
+                parse(try_from_str = parse_positive))]
+    tf_threads: usize,
+
+    /// Threads each test splits its squarings across. To test one huge
+    /// exponent on every core, use --threads-per-test with the number of
+    /// cores; a range search is faster with the default of one thread per
+    /// test. Runs use up to --threads times this many threads.
+    #[structopt(long, value_name = "n", default_value = "1",
+                parse(try_from_str = parse_positive))]
+    threads_per_test: usize,
+
+    /// Run at the lowest priority, nice 19 (below normal on Windows), so the
+    /// run only gets the CPU time other programs leave
+    #[structopt(long)]
+    nice: bool,
+
+    /// Hold each test to <percent> of a core, to keep a laptop cool: its
+    /// thread runs for that share of every 100ms, by the CPU time it
+    /// takes, and sleeps for the rest. The limit is per test thread, so a
+    /// run takes about --threads times it; lower --threads first, which
+    /// costs nothing, and leave the last fraction of a core to
+    /// --cpu-limit. The sleeps count in a test's time and its ETA but not
+    /// in its compute time
+    #[structopt(long, value_name = "percent", parse(try_from_str = parse_percent))]
+    cpu_limit: Option<u32>,
+
+    /// Pause the tests while other programs keep more than <load> cores
+    /// busy, and resume them once they keep no more than that, checking
+    /// every second; needs /proc, as on Linux. Time spent paused does not
+    /// count in the times of the tests.
+    #[structopt(long, value_name = "load", parse(try_from_str = parse_load))]
+    pause_when_busy: Option<f64>,
+
+    /// Pause the tests while <path> exists, checking every second, like
+    /// --pause-when-busy
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    pause_file: Option<PathBuf>,
+
+    /// Take commands from <path>, checking it every 2 seconds and emptying
+    /// it once they are taken: pause and resume the tests, stop them after
+    /// saving them as Ctrl-C does, or print their status and update
+    /// --status-file at once
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    control: Option<PathBuf>,
+
+    /// Take the commands of --control from clients of a Unix domain socket
+    /// at <path>, a line each, answering each with ok or why not
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    control_socket: Option<PathBuf>,
+
+    /// Pin each worker thread to a core of its own, so that on a machine
+    /// with several sockets a test does not move away from its memory.
+    /// The layout is reported at the start; where threads cannot be
+    /// pinned they run unpinned after a warning. The primality tests are
+    /// left unpinned with --threads-per-test above 1.
+    #[structopt(long)]
+    pin_threads: bool,
+
+    /// The CPUs to pin worker threads to, such as 0-7,16-23, instead of
+    /// all of them; implies --pin-threads. With --threads, this keeps a
+    /// run to one socket.
+    #[structopt(long, value_name = "list")]
+    cpus: Option<CpuList>,
+
+    /// Only start a test while the memory the running tests are estimated
+    /// to need, about 15 times p/8 bytes each, stays under <MB>
+    /// (2^20 bytes), however many threads are free. Tests wait their turn
+    /// in --order order; one that needs more than the limit runs alone.
+    #[structopt(long, value_name = "MB", parse(try_from_str = parse_positive))]
+    max_mem: Option<u64>,
+
+    /// Test exponents whose estimated memory is more than a run safely
+    /// takes of the memory this machine has available, 80% of it, instead
+    /// of refusing them. Without --max-mem, a run whose tests would not all
+    /// fit at once starts them only as they fit either way
+    #[structopt(long)]
+    force: bool,
+
+    /// Take only chunk <i> of <n> of the candidates, such as 2/4: every
+    /// n-th candidate starting from the i-th, so n machines given the same
+    /// range and options split it between them without overlap. Results
+    /// lines record the chunk.
+    #[structopt(long, value_name = "i/n")]
+    chunk: Option<Chunk>,
+
+    /// Give the run this ID, which every file it writes records, instead of
+    /// its start time and random digits: up to 64 letters, digits, '-',
+    /// '_', '.' and ':'
+    #[structopt(long, value_name = "id")]
+    run_id: Option<RunId>,
+
+    /// Keep the sieved candidates in this file, which later runs over some
+    /// of the same exponents read instead of sieving them again, adding the
+    /// blocks of exponents they sieve. A damaged file is started over
+    #[structopt(long, value_name = "path", parse(from_os_str))]
+    sieve_cache: Option<PathBuf>,
+
+    /// The largest --sieve-cache file, in MB (2^20 bytes); past it the
+    /// lowest exponents are dropped first. 1 MB covers about 8 million
+    /// exponents
+    #[structopt(long, value_name = "MB", default_value = "64",
+                parse(try_from_str = parse_positive))]
+    sieve_cache_max: u64,
+
+    /// Run this shell command whenever a prime is found, and once more when
+    /// the run ends. It gets MERSENNE_EVENT (prime, finished or stopped),
+    /// MERSENNE_EXPONENT, MERSENNE_DIGITS, MERSENNE_ELAPSED (seconds),
+    /// MERSENNE_MESSAGE and, at the end, MERSENNE_TESTED and MERSENNE_PRIMES
+    /// in its environment, and the whole event as JSON in MERSENNE_JSON.
+    #[structopt(long, value_name = "cmd")]
+    notify_cmd: Option<String>,
+
+    /// POST the same events as --notify-cmd as JSON to this webhook. The
+    /// message is in both "text" and "content", which Slack and Discord
+    /// webhooks show as it is. Failed notifications are logged and do not
+    /// affect the run.
+    #[structopt(long, value_name = "url")]
+    notify_url: Option<String>,
+
+    /// Panic the first time this exponent is tested, to exercise the failure
+    /// path and --retries
+    #[structopt(long, hidden = true)]
+    debug_panic_on: Option<u64>,
+
+    /// Set for `test` of a single exponent, whose run ends with the report
+    /// of [`single::print_report`] instead of the summary.
+    #[structopt(skip)]
+    single: bool,
+}
+
+impl Options {
+    /// Whether results are printed in the order the exponents were handed
+    /// out: with `--ordered`, and with `--threads 1`, where factoring still
+    /// runs ahead of the tests on threads of its own.
+    fn in_order(&self) -> bool {
+        self.ordered || self.threads == Some(1)
+    }
+
+    /// The share of a core `--cpu-limit` holds each test to.
+    fn cpu_share(&self) -> Option<f64> {
+        self.cpu_limit.map(|percent| f64::from(percent) / 100.0)
+    }
+
+    /// `--log-level`, raised to at least debug by `-v` and trace by `-vv`,
+    /// and lowered to at most warn by `--quiet`.
+    fn log_level(&self) -> LevelFilter {
+        match self.verbose {
+            _ if self.quiet => self.log_level.min(LevelFilter::Warn),
+            0 => self.log_level,
+            1 => self.log_level.max(LevelFilter::Debug),
+            _ => LevelFilter::Trace,
+        }
+    }
+
+    /// Whether the end-of-run summary goes to stdout.
+    fn summary(&self) -> bool {
+        !self.no_summary && !self.quiet
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Order {
+    Smallest,
+    Largest,
+    Random,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Order, String> {
+        match s {
+            "smallest" => Ok(Order::Smallest),
+            "largest" => Ok(Order::Largest),
+            "random" => Ok(Order::Random),
+            _ => Err(format!("unknown order {:?}", s)),
+        }
+    }
+}
+
+/// The `--shift` of a Lucas-Lehmer test.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ShiftChoice {
+    Bits(u64),
+    Random,
+}
+
+impl ShiftChoice {
+    /// The shift for a test of `M(p)`, below `p`.
+    fn bits(self, p: u64) -> u64 {
+        match self {
+            ShiftChoice::Bits(bits) => bits % p.max(1),
+            ShiftChoice::Random => rand::thread_rng().gen_range(1..p.max(2)),
+        }
+    }
+}
+
+impl FromStr for ShiftChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ShiftChoice, String> {
+        match s {
+            "random" => Ok(ShiftChoice::Random),
+            _ => match s.parse() {
+                Ok(0) => Err("a shift of 0 is no shift; leave --shift out".to_string()),
+                Ok(bits) => Ok(ShiftChoice::Bits(bits)),
+                Err(_) => Err(format!("expected a number of bits or random, not {:?}", s)),
+            },
+        }
+    }
+}
+
+/// The largest `n` whose Fermat number `fermat` accepts: `F(n)` has `2^n`
+/// bits, which must fit in a `u64`.
+const MAX_FERMAT_INDEX: u64 = 63;
+
+/// The deepest trial factoring done without `--tf-depth`, in bits.
+const DEFAULT_TF_DEPTH: u32 = 32;
+
+/// Cancelled by the first Ctrl-C or when `--time-limit` runs out: running
+/// tests checkpoint and stop, and no new tests are started, as they do on
+/// a `stop` through `--control`. Paused while `--pause-when-busy`,
+/// `--pause-file` or `--control` hold the tests.
+static CANCEL: CancellationToken = CancellationToken::new();
+
+/// Raised by a `pause` through --control and lowered by a `resume`; the
+/// tests are held while it is up.
+static HELD: AtomicBool = AtomicBool::new(false);
+
+/// Raised along with cancelling `CANCEL` when it was the time limit that
+/// ran out.
+static TIME_UP: AtomicBool = AtomicBool::new(false);
+
+/// Raised once `--debug-panic-on` has panicked.
+static DEBUG_PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// The `--sieve-cache` of the run, which [`primes`] sieves through.
+static SIEVE_CACHE: OnceLock<SieveCache> = OnceLock::new();
+
+/// The `--known-factors` of the run, whose exponents [`plan`] leaves out
+/// and [`trial_factoring`] records as factored.
+static KNOWN_FACTORS: OnceLock<KnownFactors> = OnceLock::new();
+
+/// The timings `--backend auto` picks the arithmetic of each size by.
+static CALIBRATION: Calibration = Calibration::new();
+
+/// Done once a `--backend` has been given an exponent it cannot take.
+static BACKEND_OUTGROWN: Once = Once::new();
+
+// Exit statuses, as documented in `--help`.
+const EXIT_SUCCESS: u8 = 0;
+const EXIT_NONE_FOUND: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_INTERRUPTED: u8 = 3;
+const EXIT_INTERNAL_ERROR: u8 = 4;
+const EXIT_SELFTEST_FAILED: u8 = 5;
+const EXIT_TIME_LIMIT: u8 = 6;
+const EXIT_CONFIRM_CONFLICT: u8 = 7;
+const EXIT_AUDIT_FAILED: u8 = 8;
+const EXIT_NO_WORK: u8 = 9;
+
+/// Prints a result that has no JSON form, such as a decimal expansion. It
+/// goes to the log instead in `--json` mode, so stdout carries nothing but
+/// JSON lines.
+macro_rules! say {
+    ($options:expr, $($arg:tt)*) => {
+        if $options.json {
+            info!($($arg)*);
+        } else {
+            outln!($($arg)*);
+        }
+    };
+}
+
+/// Tests `p` from start to finish: trial factoring, P-1 and then the
+/// primality test, for the forms that have each.
+fn test_exponent(
+    p: u64,
+    options: &Options,
+    checkpoints: Option<&CheckpointStore>,
+    audit_log: Option<&Mutex<AuditLog>>,
+    display: &ProgressDisplay,
+    activity: &Activity,
+) -> Result<TestReport, Interrupted> {
+    // Factoring only looks at the pause here, so that a pause before it
+    // does not count in its time.
+    CANCEL.wait_while_paused();
+    if let Some(report) = known_factoring(p, options, 0.0) {
+        return Ok(report);
+    }
+    let started = Instant::now();
+    if let Some(report) = trial_factoring(p, options, 0.0) {
+        return Ok(report);
+    }
+    let spent = started.elapsed().as_secs_f64();
+    if let Some(report) = pminus1_factoring(p, options, spent) {
+        return Ok(report);
+    }
+    let spent = started.elapsed().as_secs_f64();
+    primality_test(p, options, checkpoints, audit_log, display, activity, spent)
+}
+
+/// Runs `work`, catching a panic in it, whose message it returns instead.
+/// The panic hook has printed the panic already.
+fn catch_panic<T>(work: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(panic::AssertUnwindSafe(work)).map_err(|payload| {
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+        };
+        if message.is_empty() {
+            "panicked without a message".to_string()
+        } else {
+            message
+        }
+    })
+}
+
+/// The report of `p` whose test panicked with `message` after `seconds` on
+/// `p`, which counts as failed.
+fn panicked(p: u64, form: Form, message: String, seconds: f64) -> TestReport {
+    warn!("the test of {} panicked; recording it as failed.", form.number(p));
+    TestReport {
+        exponent: p,
+        form,
+        prime: false,
+        test: None,
+        seconds,
+        compute_seconds: None,
+        res64: None,
+        res2048: None,
+        factor: None,
+        factor_stage: None,
+        shift: None,
+        double_check: None,
+        backend: None,
+        check_backend: None,
+        confirmation: None,
+        confirm_res64: None,
+        timed_out_at: None,
+        failed_at: None,
+        errors: 0,
+        checkpoints: 0,
+        throughput: None,
+        panic: Some(message),
+        milestones: Vec::new(),
+        range: None,
+        run: None,
+    }
+}
+
+/// The report of `p` shown composite by `factor`, found by `stage` after
+/// `seconds` on `p`.
+fn factored(p: u64, form: Form, factor: String, stage: FactoringStage, seconds: f64) -> TestReport {
+    TestReport {
+        exponent: p,
+        form,
+        prime: false,
+        test: None,
+        seconds,
+        compute_seconds: None,
+        res64: None,
+        res2048: None,
+        factor: Some(factor),
+        factor_stage: Some(stage),
+        shift: None,
+        double_check: None,
+        backend: None,
+        check_backend: None,
+        confirmation: None,
+        confirm_res64: None,
+        timed_out_at: None,
+        failed_at: None,
+        errors: 0,
+        checkpoints: 0,
+        throughput: None,
+        panic: None,
+        milestones: Vec::new(),
+        range: None,
+        run: None,
+    }
+}
+
+/// The report of `p` as factored by the factor `--known-factors` gives of
+/// `M(p)`, if it gives one, which is looked up before any factoring.
+fn known_factoring(p: u64, options: &Options, spent: f64) -> Option<TestReport> {
+    let factor = known_factor(p)?.to_string();
+    Some(factored(p, options.form, factor, FactoringStage::External, spent))
+}
+
+/// Trial factors `p` to `--tf-depth`, the first thing done with an
+/// exponent, and returns its report if a factor turns up. `spent` is the
+/// seconds already spent on `p`, which the report includes.
+fn trial_factoring(p: u64, options: &Options, spent: f64) -> Option<TestReport> {
+    let form = options.form;
+    debug!("Testing {} = {}", form.number(p), form.formula(p));
+    if options.debug_panic_on == Some(p) && !DEBUG_PANICKED.swap(true, Ordering::SeqCst) {
+        panic!("--debug-panic-on {}", p);
+    }
+    let started = Instant::now();
+    // A depth asked for is searched in full.
+    let tf_depth = options
+        .tf_depth
+        .unwrap_or_else(|| DEFAULT_TF_DEPTH.min(worthwhile_tf_depth(p)));
+    let factor = match form {
+        Form::Mersenne => trial_factor(p, tf_depth),
+        Form::Wagstaff => wagstaff_trial_factor(p, tf_depth),
+        // Factors of F(n) have the form k·2^(n+2) + 1, which the Mersenne
+        // and Wagstaff searches do not cover, nor do they cover those of
+        // k·2^n - 1.
+        Form::Fermat | Form::Riesel { .. } => None,
+    }?;
+    let seconds = spent + started.elapsed().as_secs_f64();
+    Some(factored(p, form, factor.to_string(), FactoringStage::TrialFactoring, seconds))
+}
+
+/// Runs P-1 on `M(p)` with `--p1-b1` and `--p1-b2` and returns its report
+/// if a factor turns up, like [`trial_factoring`].
+fn pminus1_factoring(p: u64, options: &Options, spent: f64) -> Option<TestReport> {
+    if options.form != Form::Mersenne {
+        return None;
+    }
+    let started = Instant::now();
+    let factor = pminus1(p, options.p1_b1, options.p1_b2)?;
+    let seconds = spent + started.elapsed().as_secs_f64();
+    Some(factored(p, options.form, factor.to_string(), FactoringStage::PMinus1, seconds))
+}
+
+/// Runs the primality test for `p`'s form, with the options' checks,
+/// double-check and confirmation, on an exponent that factoring did not
+/// eliminate. `spent` is the seconds factoring took, which the report
+/// includes.
+fn primality_test(
+    p: u64,
+    options: &Options,
+    checkpoints: Option<&CheckpointStore>,
+    audit_log: Option<&Mutex<AuditLog>>,
+    display: &ProgressDisplay,
+    activity: &Activity,
+    spent: f64,
+) -> Result<TestReport, Interrupted> {
+    let form = options.form;
+    let name = form.number(p);
+    let started = Instant::now();
+    let paused = CANCEL.pauses().total();
+
+    // Wagstaff numbers have no Lucas-Lehmer test.
+    let kind = match form {
+        Form::Fermat => TestKind::Pepin,
+        Form::Riesel { .. } => TestKind::Llr,
+        Form::Wagstaff => TestKind::Prp,
+        Form::Mersenne if options.prp => TestKind::Prp,
+        Form::Mersenne => TestKind::LucasLehmer,
+    };
+    let running = activity.start(p, kind.iterations(p));
+    events::emit(events::Event::TestStarted {
+        exponent: p,
+        test: kind,
+        iterations: kind.iterations(p),
+    });
+    let jacobi_interval = options
+        .jacobi_interval
+        .unwrap_or_else(|| default_jacobi_interval(p));
+    let compute = ComputeTimer::new();
+    let throttle = options.cpu_share().map(Throttle::new);
+    let mut control = CANCEL
+        .control()
+        .time_compute_with(&compute)
+        .publish_to(&running.iteration)
+        .check_jacobi_every(jacobi_interval)
+        .split_across(options.threads_per_test)
+        .report_progress_each(options.progress_every)
+        .on_anomaly(options.on_error);
+    if let Some(throttle) = &throttle {
+        control = control.throttle_with(throttle);
+    }
+    if let Some(seconds) = options.max_test_seconds {
+        control = control.stop_after(Duration::from_secs(seconds));
+    }
+    let mut progress = display.start(form, p);
+    let mut milestones = Vec::new();
+    let mut res64s = Vec::new();
+    let mut errors = 0;
+    let mut checkpoints_written = 0;
+    let mut last_progress = None;
+    let mut on_event = |event: TestEvent| match event {
+        TestEvent::Progress(report) => {
+            events::progress(p, &report, &mut last_progress);
+            progress.update(report)
+        }
+        TestEvent::Resumed {
+            iteration,
+            written_by,
+            verified,
+            other_version,
+        } => {
+            running.resumed(iteration);
+            info!("Resuming {} from iteration {}.", name, iteration);
+            let here = SystemInfo::current().to_string();
+            if let Some(there) = written_by.filter(|&there| there != here) {
+                info!("The checkpoint for {} was written by {}; this is {}.", name, there, here);
+            }
+            if verified {
+                info!(
+                    "The checkpoint for {} was computed with another arithmetic backend; its \
+                     residue passed a Jacobi check.",
+                    name
+                );
+            }
+            if let Some(version) = other_version {
+                info!(
+                    "The checkpoint for {} was written by version {}; this is version {}.",
+                    name,
+                    version,
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+        }
+        TestEvent::CheckpointDiscarded(e) => warn!(
+            "ignoring checkpoint for {} ({}); restarting the test.",
+            name, e
+        ),
+        TestEvent::CheckpointSaved { iteration } => {
+            checkpoints_written += 1;
+            events::emit(events::Event::CheckpointWritten { exponent: p, iteration })
+        }
+        TestEvent::CheckpointFailed(e) => warn!("could not write checkpoint for {}: {}", name, e),
+        TestEvent::GerbiczMismatch {
+            iteration,
+            resumed_from,
+        } => {
+            errors += 1;
+            milestones.retain(|milestone: &Milestone| milestone.iteration <= resumed_from);
+            res64s.retain(|milestone: &Res64Milestone| milestone.iteration <= resumed_from);
+            warn!(
+                "Gerbicz check failed for {} at iteration {}; recomputing from iteration {}.",
+                name, iteration, resumed_from
+            )
+        }
+        TestEvent::JacobiMismatch {
+            iteration,
+            resumed_from,
+        } => {
+            errors += 1;
+            milestones.retain(|milestone: &Milestone| milestone.iteration <= resumed_from);
+            res64s.retain(|milestone: &Res64Milestone| milestone.iteration <= resumed_from);
+            warn!(
+                "Jacobi check failed for {} at iteration {}; recomputing from iteration {}.",
+                name, iteration, resumed_from
+            )
+        }
+        TestEvent::Anomaly {
+            iteration,
+            anomaly,
+            resumed_from,
+        } => {
+            errors += 1;
+            let next = match resumed_from {
+                Some(resumed_from) => {
+                    milestones.retain(|milestone| milestone.iteration <= resumed_from);
+                    res64s.retain(|milestone| milestone.iteration <= resumed_from);
+                    format!("recomputing from iteration {}", resumed_from)
+                }
+                None if options.on_error == OnAnomaly::Continue => "carrying on".to_string(),
+                None => "giving up on the test".to_string(),
+            };
+            warn!(
+                "arithmetic anomaly in {} at iteration {}: {}; {}.",
+                name, iteration, anomaly, next
+            )
+        }
+        TestEvent::Milestone { iteration, residue } => milestones.push(Milestone {
+            iteration,
+            residue: residue.clone(),
+        }),
+        TestEvent::Res64 { iteration, res64 } => res64s.push(Res64Milestone {
+            iteration,
+            res64: format_res64(res64),
+        }),
+    };
+    // Only the first run records --milestones, like the audit log, and
+    // --save-residue and --res2048 keep its final residue, which comes as a
+    // milestone.
+    let recorded = match options.milestones {
+        Some(count) => control.record_res64_every(kind.iterations(p).div_ceil(count)),
+        None => control,
+    };
+    let recorded = match options.save_residue.is_some() || options.res2048 {
+        true => recorded.record_milestones_every(kind.iterations(p)),
+        false => recorded,
+    };
+    let mut checked = None;
+    let mut shifted = None;
+    let (mut backend, mut check_backend) = (None, None);
+    let outcome = if kind == TestKind::Pepin {
+        pepin_test_interruptible(p, control, &mut on_event).map(|result| match result {
+            PepinResult::Prime => (true, None),
+            PepinResult::Composite { res64 } => (false, Some(format_res64(res64))),
+        })
+    } else if let (TestKind::Llr, Form::Riesel { k }) = (kind, form) {
+        is_riesel_prime_interruptible(k, p, control, &mut on_event).map(ll_outcome)
+    } else if kind == TestKind::Prp {
+        let proved = match options.proof_dir {
+            Some(_) => {
+                recorded.record_milestones_every(proof::residue_interval(p, options.proof_power))
+            }
+            None => recorded,
+        };
+        let result = match form {
+            Form::Wagstaff => wagstaff_prp_test_interruptible(p, proved, &mut on_event),
+            _ => prp_test_interruptible(p, proved, &mut on_event),
+        };
+        result.map(|result| (result.is_probable_prime(), Some(format_res64(result.res64()))))
+    } else {
+        // Only the first run goes in the audit log.
+        let audited = match audit_log {
+            Some(_) => recorded.record_milestones_every(audit::milestone_interval(p)),
+            None => recorded,
+        };
+        // With a random shift, a test that resumes keeps its checkpoint's.
+        let resuming = checkpoints.and_then(|store| store.load(p).ok().flatten());
+        let shift = options.shift.map(|choice| match resumed_shift(resuming.as_ref()) {
+            Some(bits) if choice == ShiftChoice::Random && !options.double_check => bits,
+            _ => choice.bits(p),
+        });
+        let (first_backend, second_backend) = backends(p, options.backend);
+        backend = Some(first_backend);
+        let first = match shift {
+            Some(bits) if !options.double_check => {
+                shifted = Some(bits);
+                audited.with_shift(bits)
+            }
+            _ => audited,
+        };
+        debug!("Testing {} on the {} backend.", name, first_backend);
+        let first = first.on_backend(first_backend);
+        let result = match is_mersenne_prime_interruptible(p, checkpoints, first, &mut on_event) {
+            Ok(first) if options.double_check && p >= 3 => {
+                check_backend = Some(second_backend);
+                let second = control.on_backend(second_backend);
+                double_check(p, first, shift, second, &mut on_event).map(
+                    |(result, shift, outcome)| {
+                        checked = Some((shift, outcome));
+                        result
+                    },
+                )
+            }
+            result => result,
+        };
+        result.map(ll_outcome)
+    };
+    // Pauses do not count.
+    let paused = CANCEL.pauses().total() - paused;
+    let seconds = spent + started.elapsed().saturating_sub(paused).as_secs_f64();
+    let compute_seconds = compute.total().as_secs_f64();
+
+    let mut confirmed = None;
+    if let (Ok((true, res64)), true) = (&outcome, options.confirm) {
+        match confirm(p, kind, res64.as_deref(), control, &mut on_event) {
+            Ok(confirmation) => confirmed = Some(confirmation),
+            Err(interrupted) if interrupted.timed_out => {
+                warn!("the confirmation of {} ran out of time; the result is unconfirmed.", name)
+            }
+            Err(interrupted) if interrupted.anomaly.is_some() => {
+                warn!("the confirmation of {} failed; the result is unconfirmed.", name)
+            }
+            Err(interrupted) => return Err(interrupted),
+        }
+    }
+    let throughput = progress.throughput();
+    drop(progress);
+    let last = milestones.last().filter(|last| last.iteration == kind.iterations(p));
+    let res2048 = last
+        .filter(|_| options.res2048)
+        .map(|last| format_res2048(&last.residue));
+    if let (Some(dir), Ok(_), Some(last)) = (&options.save_residue, &outcome, last) {
+        let header = ResidueHeader {
+            p,
+            test: kind,
+            iterations: last.iteration,
+            shift: shifted.unwrap_or(0),
+        };
+        save_residue(dir, form, &header, &last.residue);
+    }
+    if let (Some(audit_log), Ok(_), false) = (audit_log, &outcome, milestones.is_empty()) {
+        let record = AuditRecord::new(p, milestones);
+        if let Err(e) = audit_log.lock().unwrap().record(record) {
+            warn!("could not write to the audit log: {}", e);
+        }
+    } else if let (Some(dir), Ok(_), false) = (&options.proof_dir, &outcome, milestones.is_empty()) {
+        write_proof(dir, form, p, options.proof_power, &milestones);
+    }
+
+    let report = TestReport {
+        exponent: p,
+        form,
+        prime: false,
+        test: Some(kind),
+        seconds,
+        compute_seconds: Some(compute_seconds),
+        res64: None,
+        res2048: None,
+        factor: None,
+        factor_stage: None,
+        shift: None,
+        double_check: None,
+        backend,
+        check_backend: None,
+        confirmation: None,
+        confirm_res64: None,
+        timed_out_at: None,
+        failed_at: None,
+        errors,
+        checkpoints: checkpoints_written,
+        throughput,
+        panic: None,
+        milestones: res64s,
+        range: None,
+        run: None,
+    };
+    match outcome {
+        Ok((prime, res64)) => Ok(TestReport {
+            prime,
+            // Like the Res64, left out of a Lucas-Lehmer test that found a
+            // prime, whose residue is zero.
+            res2048: res2048.filter(|_| res64.is_some()),
+            res64,
+            shift: checked.map(|(shift, _)| shift).or(shifted),
+            double_check: checked.map(|(_, outcome)| outcome),
+            check_backend: checked.and(check_backend),
+            confirmation: confirmed.as_ref().map(|(confirmation, _)| *confirmation),
+            confirm_res64: confirmed.map(|(_, res64)| res64),
+            ..report
+        }),
+        Err(interrupted) if interrupted.timed_out => Ok(TestReport {
+            timed_out_at: Some(interrupted.iteration),
+            ..report
+        }),
+        Err(interrupted) if interrupted.anomaly.is_some() => Ok(TestReport {
+            failed_at: Some(interrupted.iteration),
+            ..report
+        }),
+        Err(interrupted) => Err(interrupted),
+    }
+}
+
+/// Writes the final residue of the test `header` describes to `dir`.
+fn save_residue(dir: &Path, form: Form, header: &ResidueHeader, residue: &BigUint) {
+    let name = form.number(header.p);
+    let path = dir.join(residue::file_name(form, header.p));
+    let written = fs::create_dir_all(dir).and_then(|()| residue::write(&path, header, residue));
+    match written {
+        Ok(()) => info!("Wrote the residue of {} to {}.", name, path.display()),
+        Err(e) => warn!("could not write the residue of {}: {}", name, e),
+    }
+}
+
+/// Builds the proof of the PRP test of `p` of `power` from its `residues`
+/// and writes it to `dir`.
+fn write_proof(dir: &Path, form: Form, p: u64, power: u32, residues: &[Milestone]) {
+    let name = form.number(p);
+    let path = dir.join(proof::file_name(form, p));
+    let written = fs::create_dir_all(dir)
+        .map_err(|e| e.to_string())
+        .and_then(|()| Proof::build(form, p, power, residues).map_err(|e| e.to_string()))
+        .and_then(|proof| proof.write(&path).map_err(|e| e.to_string()));
+    match written {
+        Ok(()) => info!("Wrote the proof of {} to {}.", name, path.display()),
+        Err(e) => warn!("could not write the proof of {}: {}", name, e),
+    }
+}
+
+/// The `--confirm` re-run of a prime result for `M(p)` from a `kind` test,
+/// on one thread and by the other method: a PRP test for a Lucas-Lehmer
+/// result, and for a PRP result a Lucas-Lehmer test with a random shift.
+/// `res64` is that of the first run, if it has one. Returns how the re-run
+/// came out and its Res64.
+fn confirm<F>(
+    p: u64,
+    kind: TestKind,
+    res64: Option<&str>,
+    control: TestControl,
+    on_event: F,
+) -> Result<(Confirmation, String), Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    let control = control.split_across(1);
+    let first = res64.map_or_else(|| format_res64(0), str::to_string);
+    let (prime, res64, test) = if kind == TestKind::LucasLehmer {
+        let result = prp_test_interruptible(p, control, on_event)?;
+        (result.is_probable_prime(), format_res64(result.res64()), TestKind::Prp)
+    } else {
+        let shift = rand::thread_rng().gen_range(1..p.max(2));
+        let control = control.with_shift(shift);
+        let result = is_mersenne_prime_interruptible(p, None, control, on_event)?;
+        let (prime, res64) = ll_outcome(result);
+        (prime, res64.unwrap_or_else(|| format_res64(0)), TestKind::LucasLehmer)
+    };
+    if prime {
+        Ok((Confirmation::Confirmed, res64))
+    } else {
+        warn!(
+            "confirmation CONFLICT for M({}): {} found it prime (Res64 {}), {} found it composite (Res64 {}); it needs manual review.",
+            p, kind, first, test, res64
+        );
+        Ok((Confirmation::Conflict, res64))
+    }
+}
+
+/// Whether a Lucas-Lehmer or LLR result is prime, and its Res64 if not.
+fn ll_outcome(result: LlResult) -> (bool, Option<String>) {
+    match result {
+        LlResult::Prime => (true, None),
+        LlResult::Composite { res64 } => (false, Some(format_res64(res64))),
+        LlResult::Aborted { .. } => unreachable!("only is_mersenne_prime_with aborts a test"),
+    }
+}
+
+/// The backends of the first and second runs of a Lucas-Lehmer test of
+/// `M(p)`: the one `choice` names for both, or with `auto` the fastest at
+/// the size of `p` and the next fastest, timed the first time the size
+/// comes up.
+fn backends(p: u64, choice: BackendChoice) -> (ArithBackend, ArithBackend) {
+    match choice {
+        BackendChoice::Only(backend) if backend.handles(p) => return (backend, backend),
+        BackendChoice::Only(backend) => BACKEND_OUTGROWN.call_once(|| {
+            warn!(
+                "--backend {} takes exponents up to {} only; larger ones run on the backend \
+                 --backend auto picks.",
+                backend,
+                group(FIXED_CAPACITY)
+            )
+        }),
+        BackendChoice::Auto => {}
+    }
+    let ranking = CALIBRATION.rank(p, mersenne::clock::default_clock(), |ranking| {
+        let timings: Vec<_> = ranking
+            .timings
+            .iter()
+            .map(|(backend, time)| format!("{} {:.2?}", backend, time))
+            .collect();
+        debug!(
+            "Timed {} iterations of each backend at {} bits: {}; --backend auto takes {}.",
+            CALIBRATION_ITERATIONS,
+            group(ranking.size),
+            timings.join(", "),
+            ranking.first()
+        );
+    });
+    (ranking.first(), ranking.second())
+}
+
+/// The rest of a `--double-check` of `M(p)` once the normal run has given
+/// `first`: a run with `shift`, or a random shift if it is `None` or a
+/// multiple of `p`, and, if the two disagree, a third with another shift
+/// to break the tie. Returns the result to report, the shift of the second
+/// run and how the runs compared.
+fn double_check<F>(
+    p: u64,
+    first: LlResult,
+    shift: Option<u64>,
+    control: TestControl,
+    mut on_event: F,
+) -> Result<(LlResult, u64, DoubleCheck), Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    let mut rng = rand::thread_rng();
+    let shift = shift
+        .filter(|&bits| bits != 0)
+        .unwrap_or_else(|| rng.gen_range(1..p));
+    let second =
+        is_mersenne_prime_interruptible(p, None, control.with_shift(shift), &mut on_event)?;
+    if second == first {
+        return Ok((first, shift, DoubleCheck::Match));
+    }
+
+    let tiebreak_shift = loop {
+        let bits = rng.gen_range(1..p);
+        if bits != shift {
+            break bits;
+        }
+    };
+    warn!(
+        "double-check MISMATCH for M({}): {} unshifted, {} with shift {}; running a third test with shift {}.",
+        p, first, second, shift, tiebreak_shift
+    );
+    let third =
+        is_mersenne_prime_interruptible(p, None, control.with_shift(tiebreak_shift), on_event)?;
+    if third == first || third == second {
+        Ok((third, shift, DoubleCheck::Mismatch))
+    } else {
+        warn!(
+            "the third test of M({}) agrees with neither ({}); the result cannot be trusted.",
+            p, third
+        );
+        Ok((first, shift, DoubleCheck::Unresolved))
+    }
+}
+
+fn print_report(report: &TestReport, options: &Options) {
+    if options.json {
+        outln!("{}", serde_json::to_string(&ReportLine::new(report)).unwrap());
+        return;
+    }
+
+    let (p, form) = (report.exponent, report.form);
+    let name = form.number(p);
+    let test = match (report.test, report.shift, report.double_check) {
+        (Some(test), Some(shift), None) => format!("{}, shift {}", test, shift),
+        (test, _, _) => test.map_or("", TestKind::as_str).to_string(),
+    };
+    let verbose = log::log_enabled!(Level::Debug);
+    let test = match report.backend.filter(|_| verbose) {
+        Some(backend) => format!("{} on {}", test, backend),
+        None => test,
+    };
+    let line = if let Some(factor) = &report.factor {
+        let stage = report.factor_stage.map_or("", FactoringStage::as_str);
+        color::stdout(Style::Composite, format!("{} has factor {} ({})", name, factor, stage))
+    } else if let Some(message) = &report.panic {
+        color::stdout(
+            Style::Warning,
+            format!("{} failed: its test panicked ({}).", name, message),
+        )
+    } else if let Some(percent) = report.percent_complete() {
+        color::stdout(
+            Style::Warning,
+            format!(
+                "{} timed out at {:.1}% complete ({}) after {}.",
+                name,
+                percent,
+                test,
+                format_duration(report.seconds)
+            ),
+        )
+    } else if report.prime {
+        let (kind, res64) = match report.test {
+            Some(TestKind::Prp) => (
+                "probable prime",
+                format!(" Res64: 0x{}", report.res64.as_deref().unwrap_or_default()),
+            ),
+            _ => ("prime", String::new()),
+        };
+        color::stdout(
+            Style::Found,
+            format!(
+                "*** Found {} {}: {} ({}), {} digits, tested in {}.{}",
+                form.title(),
+                kind,
+                name,
+                test,
+                group(form.digit_count(p)),
+                format_duration(report.seconds),
+                res64
+            ),
+        )
+    } else if let Some(res64) = &report.res64 {
+        let line = if verbose {
+            format!(
+                "{} is composite ({}), tested in {}. Res64: 0x{}",
+                name,
+                test,
+                format_duration(report.seconds),
+                res64
+            )
+        } else {
+            format!("{} is composite ({}). Res64: 0x{}", name, test, res64)
+        };
+        color::stdout(Style::Composite, line)
+    } else {
+        return;
+    };
+    outln!("{}", line);
+    if let (Some(double_check), Some(shift)) = (report.double_check, report.shift) {
+        let on = match report.check_backend.filter(|_| verbose) {
+            Some(backend) => format!(", on {}", backend),
+            None => String::new(),
+        };
+        let line = format!("{} double-check: {} (shift {}{})", name, double_check, shift, on);
+        if double_check == DoubleCheck::Match {
+            outln!("{}", line);
+        } else {
+            outln!("{}", color::stdout(Style::Warning, line));
+        }
+    }
+    if let (Some(confirmation), Some(res64)) = (report.confirmation, &report.confirm_res64) {
+        let style = match confirmation {
+            Confirmation::Conflict => Style::Warning,
+            Confirmation::Confirmed => Style::Found,
+        };
+        let line = format!("{} confirmation: {} (Res64: 0x{})", name, confirmation, res64);
+        outln!("{}", color::stdout(style, line));
+    }
+}
+
+/// Writes the decimal expansions asked for with `--print-number` and
+/// `--save-number`, and the perfect numbers asked for with `--perfect`.
+/// This runs after the search, one prime at a time, because the conversion
+/// is itself slow for large exponents.
+fn write_numbers(primes: &[u64], options: &Options) {
+    let expand = options.print_number || options.save_number.is_some();
+    if !expand && !options.perfect {
+        return;
+    }
+    if let Some(dir) = &options.save_number {
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("cannot create {}: {}", dir.display(), e);
+            return;
+        }
+    }
+
+    for &p in primes {
+        if expand {
+            let name = options.form.number(p);
+            match options.form {
+                Form::Mersenne => {
+                    write_number(&name, &format!("M{}", p), options, || mersenne_number(p))
+                }
+                Form::Wagstaff => {
+                    write_number(&name, &format!("W{}", p), options, || wagstaff_number(p))
+                }
+                Form::Fermat => {
+                    write_number(&name, &format!("F{}", p), options, || fermat_number(p))
+                }
+                Form::Riesel { k } => {
+                    write_number(&name, &format!("R{}_{}", k, p), options, || riesel_number(k, p))
+                }
+            }
+        }
+        if options.perfect {
+            let perfect = perfect_number(p);
+            say!(
+                options,
+                "Perfect number from M({}): 2^{} * (2^{} - 1), {} digits.",
+                p,
+                p - 1,
+                p,
+                group(decimal_digits(&perfect))
+            );
+            if expand {
+                let name = format!("perfect number from M({})", p);
+                write_number(&name, &format!("M{}.perfect", p), options, || perfect);
+            }
+        }
+    }
+}
+
+/// Prints and/or saves as `<file_stem>.txt` the decimal expansion of the
+/// number built by `value`.
+fn write_number<F>(name: &str, file_stem: &str, options: &Options, value: F)
+where
+    F: FnOnce() -> BigUint,
+{
+    let spinner = progress::spinner(format!("Converting {} to decimal", name));
+    let decimal = number::wrap(&value().to_string(), 80);
+    spinner.finish_and_clear();
+
+    if options.print_number {
+        say!(options, "\n{} =\n{}", name, decimal.trim_end());
+    }
+    if let Some(dir) = &options.save_number {
+        let path = dir.join(format!("{}.txt", file_stem));
+        if let Err(e) = fs::write(&path, &decimal) {
+            warn!("cannot write {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// The exponents a run was asked to cover.
+enum Selection {
+    /// Every prime in `start..=end`, generated lazily by the sieve.
+    Range(u64, u64),
+    /// Explicitly named exponents, tested in the given order.
+    List(Vec<u64>),
+    /// Every integer in `start..=end`, for Fermat and Riesel numbers, whose
+    /// exponents need not be prime.
+    Every(u64, u64),
+    /// The exponents of several ranges, sorted and without overlaps: primes,
+    /// or with `every` every integer.
+    Ranges { ranges: Vec<ExponentRange>, every: bool },
+    /// The exponents from `start` on, primes or with `every` every integer,
+    /// until `count` of them are to be tested. [`plan::resolve`] turns it
+    /// into the range they span before anything is planned.
+    Next { start: u64, count: u64, every: bool },
+    /// The exponents of `test --stdin`, as they arrive. Like the primes of a
+    /// range, they reach the tests straight from [`Selection::candidates`],
+    /// but they can only be walked once, and their bounds are not known
+    /// before the input ends.
+    Stream(Feed),
+}
+
+impl Selection {
+    /// The smallest and largest exponent covered, for the summary.
+    fn bounds(&self) -> (u64, u64) {
+        match self {
+            Selection::Range(start, end) | Selection::Every(start, end) => (*start, *end),
+            Selection::Next { start, .. } => (*start, u64::MAX),
+            Selection::Ranges { ranges, .. } => (
+                ranges.first().map_or(0, |range| range.start),
+                ranges.last().map_or(0, |range| range.end),
+            ),
+            Selection::List(exponents) => (
+                exponents.iter().copied().min().unwrap_or(0),
+                exponents.iter().copied().max().unwrap_or(0),
+            ),
+            Selection::Stream(_) => (0, 0),
+        }
+    }
+
+    /// The number of exponents a [`Selection::Next`] asks for.
+    fn count(&self) -> Option<u64> {
+        match self {
+            Selection::Next { count, .. } => Some(*count),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, p: u64) -> bool {
+        match self {
+            Selection::Range(start, end) | Selection::Every(start, end) => {
+                (*start..=*end).contains(&p)
+            }
+            Selection::Next { start, .. } => p >= *start,
+            Selection::Ranges { .. } => self.range_of(p).is_some(),
+            Selection::List(exponents) => exponents.contains(&p),
+            Selection::Stream(_) => false,
+        }
+    }
+
+    fn candidates(&self) -> Box<dyn Iterator<Item = u64> + Send + '_> {
+        match self {
+            Selection::Range(start, end) => primes(*start, *end),
+            Selection::List(exponents) => Box::new(exponents.iter().copied()),
+            Selection::Every(start, end) => Box::new(*start..=*end),
+            Selection::Next { start, every: false, .. } => primes(*start, u64::MAX),
+            Selection::Next { start, every: true, .. } => Box::new(*start..),
+            Selection::Ranges { ranges, every: false } => {
+                Box::new(ranges.iter().flat_map(|range| primes(range.start, range.end)))
+            }
+            Selection::Ranges { ranges, every: true } => {
+                Box::new(ranges.iter().flat_map(|range| range.start..=range.end))
+            }
+            Selection::Stream(feed) => Box::new(std::iter::from_fn(|| feed.next())),
+        }
+    }
+
+    /// The range of a [`Selection::Ranges`] that `p` is in.
+    fn range_of(&self, p: u64) -> Option<ExponentRange> {
+        match self {
+            Selection::Ranges { ranges, .. } => {
+                ranges.iter().copied().find(|range| range.contains(p))
+            }
+            _ => None,
+        }
+    }
+
+    /// The ranges of a [`Selection::Ranges`], and none of the others.
+    fn ranges(&self) -> &[ExponentRange] {
+        match self {
+            Selection::Ranges { ranges, .. } => ranges,
+            _ => &[],
+        }
+    }
+}
+
+/// The primes of `start..=end`, through the `--sieve-cache` if there is
+/// one.
+fn primes(start: u64, end: u64) -> Box<dyn Iterator<Item = u64> + Send> {
+    match SIEVE_CACHE.get() {
+        Some(cache) => Box::new(cache.primes(start, end)),
+        None => Box::new(sieve::primes(start, end)),
+    }
+}
+
+/// The totals of each range of a [`Selection::Ranges`], from the reports
+/// tagged with it; their seconds are those spent on their exponents.
+fn range_summaries(selection: &Selection, reports: &[TestReport]) -> Vec<RunSummary> {
+    let ranges = selection.ranges();
+    ranges
+        .iter()
+        .map(|&range| {
+            let mut summary = RunSummary::new(range.start, range.end);
+            for report in reports.iter().filter(|report| report.range == Some(range)) {
+                summary.record(report);
+                summary.seconds += report.seconds;
+            }
+            summary
+        })
+        .collect()
+}
+
+/// Sorts the ranges of `--ranges`, refusing ranges that overlap.
+fn sort_ranges(ranges: &mut [ExponentRange]) -> Result<(), String> {
+    ranges.sort_unstable();
+    match ranges.windows(2).find(|pair| pair[1].start <= pair[0].end) {
+        Some(pair) => Err(format!(
+            "the ranges {} and {} overlap; give each exponent in one range only.",
+            pair[0], pair[1]
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Prints the table of known Mersenne primes for `list-known`.
+fn list_known() {
+    println!("{:>3}  {:>10}  {:>10}  Discovered", "#", "Exponent", "Digits");
+    for (rank, (&p, year)) in MERSENNE_EXPONENTS.iter().zip(DISCOVERY_YEARS).enumerate() {
+        let year = year.map_or_else(|| "antiquity".to_string(), |year| year.to_string());
+        println!(
+            "{:>3}  {:>10}  {:>10}  {}",
+            rank + 1,
+            p,
+            group(digit_count(p)),
+            year
+        );
+    }
+}
+
+/// Prints the build and machine for `info`.
+fn info_command(json: bool) {
+    let system = SystemInfo::current();
+    if json {
+        println!("{}", serde_json::to_string(system).unwrap());
+        return;
+    }
+    println!("Mersenne {}", system.version);
+    println!("Commit: {}", system.commit.unwrap_or("unknown"));
+    println!("CPU: {}", system.cpu.as_deref().unwrap_or("unknown"));
+    println!("Cores: {}", system.cores);
+    println!("OS: {} ({})", system.os, system.arch);
+    println!("Arithmetic: {}", system.backend);
+}
+
+/// Checks an audit log for `audit-verify`: every link of its chain, the
+/// final residue of every test, and all or `spot_check` of the stretches
+/// between milestones, recomputed on `threads` threads.
+fn audit_verify(file: &Path, spot_check: Option<usize>, threads: Option<usize>) -> u8 {
+    let records = match audit::read_log(file) {
+        Ok(records) => records,
+        Err(AuditError::Io(e)) => {
+            error!("cannot read {}: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+        Err(e) => {
+            error!("the audit log {} is broken at {}", file.display(), e);
+            return EXIT_AUDIT_FAILED;
+        }
+    };
+    let pool = match thread_pool(threads, None) {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("cannot start worker threads: {}", e);
+            return EXIT_INTERNAL_ERROR;
+        }
+    };
+
+    let mut failed = 0;
+    for record in &records {
+        let p = record.exponent;
+        let last = record.milestones.last();
+        if last.is_none_or(|last| last.iteration != p.saturating_sub(2)) {
+            error!("M({}): the record does not end with the final residue.", p);
+            failed += 1;
+        } else if last.is_some_and(|last| {
+            (last.residue == BigUint::default()) != record.prime
+                || format_res64(res64(&last.residue)) != record.res64
+        }) {
+            error!("M({}): the result does not match the final residue.", p);
+            failed += 1;
+        }
+        if let Some(first) = record.milestones.first().filter(|first| first.iteration > 0) {
+            warn!(
+                "M({}): the test resumed from a checkpoint, so iterations 1 to {} cannot be checked.",
+                p, first.iteration
+            );
+        }
+    }
+
+    let mut segments: Vec<_> = records
+        .iter()
+        .flat_map(|record| record.segments().map(move |segment| (record.exponent, segment)))
+        .collect();
+    let total = segments.len();
+    if let Some(count) = spot_check {
+        segments.shuffle(&mut rand::thread_rng());
+        segments.truncate(count);
+        segments.sort_by_key(|(p, (from, _))| (*p, from.iteration));
+    }
+    let checked = segments.len();
+    let bad: Vec<_> = pool.install(|| {
+        segments
+            .par_iter()
+            .filter(|(p, (from, to))| !audit::verify_segment(*p, from, to))
+            .collect()
+    });
+    for (p, (from, to)) in &bad {
+        error!(
+            "M({}): iteration {} does not follow from iteration {}.",
+            p, to.iteration, from.iteration
+        );
+    }
+    failed += bad.len();
+
+    println!(
+        "{} test(s), {} of {} stretch(es) recomputed, {} problem(s).",
+        records.len(),
+        checked,
+        total,
+        failed
+    );
+    if failed > 0 {
+        println!("Audit log FAILED.");
+        EXIT_AUDIT_FAILED
+    } else {
+        println!("Audit log OK.");
+        EXIT_SUCCESS
+    }
+}
+
+/// Checks a proof file for `verify-proof` and prints the result it proves.
+fn verify_proof(file: &Path) -> u8 {
+    let proof = match Proof::read(file) {
+        Ok(proof) => proof,
+        Err(e) => {
+            error!("cannot use the proof {}: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    println!("Proof: {}", file.display());
+    println!("Number: {}", proof.form.number(proof.exponent));
+    println!("Power: {}", proof.power);
+    println!("Iterations proved: {} of {}", proof.iterations(), proof.exponent);
+    let started = Instant::now();
+    match proof.verify() {
+        Ok(Some(result)) => {
+            println!("Result: {}", result);
+            println!("Proof OK, checked in {}.", format_duration(started.elapsed().as_secs_f64()));
+            EXIT_SUCCESS
+        }
+        Ok(None) => {
+            println!("Proof FAILED: its residues do not follow from each other.");
+            EXIT_AUDIT_FAILED
+        }
+        Err(e) => {
+            error!("cannot use the proof {}: {}", file.display(), e);
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Checks the residue `file` for `verify-residue`, and with `from`
+/// recomputes it from that checkpoint.
+fn verify_residue(file: &Path, from: Option<&Path>) -> u8 {
+    let summary = match ResidueSummary::read(file) {
+        Ok(summary) => summary,
+        Err(e) => {
+            error!("cannot use the residue {}: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    let header = summary.header;
+    println!("Residue: {}", file.display());
+    println!("Exponent: {}", header.p);
+    println!("Test: {}", header.test);
+    println!("Iterations: {}", header.iterations);
+    println!("Shift: {}", header.shift);
+    println!("Res64: {}", format_res64(summary.res64()));
+    println!("Res2048: {}", summary.res2048());
+    let Some(from) = from else {
+        return EXIT_SUCCESS;
+    };
+    let checkpoint = match fs::read(from)
+        .map_err(CheckpointError::from)
+        .and_then(|bytes| Checkpoint::from_bytes(&bytes, header.p))
+    {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            error!("cannot use the checkpoint {}: {}", from.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    if checkpoint.test != header.test {
+        error!(
+            "cannot use the checkpoint {}: it is of a {} test, and the residue of a {} test",
+            from.display(),
+            checkpoint.test,
+            header.test
+        );
+        return EXIT_USAGE;
+    }
+    let started = Instant::now();
+    let recomputed = match residue::continue_from(&checkpoint, header.iterations) {
+        Ok(recomputed) => recomputed,
+        Err(e) => {
+            error!("cannot use the checkpoint {}: {}", from.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    let took = format_duration(started.elapsed().as_secs_f64());
+    match residue::matches(file, &recomputed) {
+        Ok((_, true)) => {
+            println!(
+                "Iterations {} to {} recomputed from {} in {}: the residue matches.",
+                checkpoint.iteration + 1,
+                header.iterations,
+                from.display(),
+                took
+            );
+            EXIT_SUCCESS
+        }
+        Ok((_, false)) => {
+            println!(
+                "Iterations {} to {} recomputed from {} in {}: the residue does NOT match \
+                 (Res64 {}).",
+                checkpoint.iteration + 1,
+                header.iterations,
+                from.display(),
+                took,
+                format_res64(res64(&recomputed))
+            );
+            EXIT_AUDIT_FAILED
+        }
+        Err(e) => {
+            error!("cannot use the residue {}: {}", file.display(), e);
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Compares the runs in `a` and `b` for `compare`.
+fn compare_runs(a: &Path, b: &Path) -> u8 {
+    let read = |file: &Path| match fs::read_to_string(file) {
+        Ok(text) => Some(compare::read_runs(&text)),
+        Err(e) => {
+            error!("cannot read {}: {}", file.display(), e);
+            None
+        }
+    };
+    let (Some(runs_a), Some(runs_b)) = (read(a), read(b)) else {
+        return EXIT_USAGE;
+    };
+    let mut diverged = 0;
+    for (&(form, p), run) in &runs_a {
+        let number = form.number(p);
+        let Some(other) = runs_b.get(&(form, p)) else {
+            println!("{}: only in {}", number, a.display());
+            continue;
+        };
+        let comparison = compare::compare(run, other);
+        diverged += usize::from(comparison.is_divergent());
+        match comparison {
+            Comparison::Identical { milestones } => {
+                println!("{}: identical ({} milestone(s))", number, milestones)
+            }
+            Comparison::Diverged { iteration, index, milestones } => println!(
+                "{}: diverged at iteration {} (milestone {} of {})",
+                number, iteration, index, milestones
+            ),
+            Comparison::FinalDiffers { milestones } => println!(
+                "{}: {} milestone(s) agree but the final Res64s differ",
+                number, milestones
+            ),
+            Comparison::NoMilestones => println!("{}: no milestones in common", number),
+        }
+    }
+    for &(form, p) in runs_b.keys().filter(|number| !runs_a.contains_key(number)) {
+        println!("{}: only in {}", form.number(p), b.display());
+    }
+    if diverged > 0 {
+        EXIT_AUDIT_FAILED
+    } else {
+        EXIT_SUCCESS
+    }
+}
+
+/// Summarizes what changed from the run in `a` to the run in `b` for
+/// `diff`, each narrowed to the run ID given with it if any, as a table or
+/// with `json` as one JSON object.
+fn diff_runs(
+    (a, run_a): (&Path, Option<&RunId>),
+    (b, run_b): (&Path, Option<&RunId>),
+    json: bool,
+) -> u8 {
+    let read = |file: &Path, run| match fs::read_to_string(file).map_err(|e| e.to_string()) {
+        Ok(text) => match diff::read_entries(&text, run) {
+            Ok(read) => Some(read),
+            Err(e) => {
+                error!("cannot read {} as CSV: {}", file.display(), e);
+                None
+            }
+        },
+        Err(e) => {
+            error!("cannot read {}: {}", file.display(), e);
+            None
+        }
+    };
+    let (Some((format_a, runs_a)), Some((format_b, runs_b))) = (read(a, run_a), read(b, run_b))
+    else {
+        return EXIT_USAGE;
+    };
+    let diff = diff::diff(&runs_a, &runs_b);
+    let status = if diff.is_divergent() { EXIT_AUDIT_FAILED } else { EXIT_SUCCESS };
+    if json {
+        let line = diff::DiffLine {
+            a: diff::Run { file: a, format: format_a, run: run_a, results: runs_a.len() },
+            b: diff::Run { file: b, format: format_b, run: run_b, results: runs_b.len() },
+            diff: &diff,
+        };
+        println!("{}", serde_json::to_string(&line).unwrap());
+        return status;
+    }
+
+    let of_run = |run: Option<&RunId>| run.map_or_else(String::new, |run| format!(", run {}", run));
+    println!("a: {}{} ({}, {} results)", a.display(), of_run(run_a), format_a, runs_a.len());
+    println!("b: {}{} ({}, {} results)", b.display(), of_run(run_b), format_b, runs_b.len());
+    println!("{} number(s) in both.", diff.common);
+    let verdict = |entry: Option<&diff::Entry>| match entry {
+        Some(entry) if entry.prime => "prime".to_string(),
+        Some(_) => "composite".to_string(),
+        None => "-".to_string(),
+    };
+    let mut rows: Vec<[String; 4]> = Vec::new();
+    for disagreement in &diff.disagreements {
+        let key = (disagreement.number.form, disagreement.number.exponent);
+        let (x, y) = (verdict(runs_a.get(&key)), verdict(runs_b.get(&key)));
+        rows.push([disagreement.number.to_string(), x, y, "DISAGREEMENT".to_string()]);
+    }
+    for mismatch in &diff.res64_mismatches {
+        let (x, y) = (mismatch.a.clone(), mismatch.b.clone());
+        rows.push([mismatch.number.to_string(), x, y, "Res64 mismatch".to_string()]);
+    }
+    for number in &diff.only_in_a {
+        let x = verdict(runs_a.get(&(number.form, number.exponent)));
+        rows.push([number.to_string(), x, verdict(None), "only in a".to_string()]);
+    }
+    for number in &diff.only_in_b {
+        let y = verdict(runs_b.get(&(number.form, number.exponent)));
+        rows.push([number.to_string(), verdict(None), y, "only in b".to_string()]);
+    }
+    if rows.is_empty() {
+        println!("No results differ.");
+    } else {
+        rows.insert(0, ["number", "a", "b", "difference"].map(str::to_string));
+        let width = |column: usize| rows.iter().map(|row| row[column].len()).max().unwrap();
+        let (w0, w1, w2) = (width(0), width(1), width(2));
+        println!();
+        for [number, x, y, difference] in &rows {
+            let line = format!("{:<w0$}  {:<w1$}  {:<w2$}  {}", number, x, y, difference);
+            match difference.as_str() {
+                "DISAGREEMENT" => println!("{}", color::stdout(Style::Error, line)),
+                "Res64 mismatch" => println!("{}", color::stdout(Style::Warning, line)),
+                _ => println!("{}", line),
+            }
+        }
+        println!();
+    }
+    match diff.timing {
+        Some(timing) => {
+            let change = match timing.median_ratio {
+                ratio if ratio < 1.0 => format!("{:.2}x faster", 1.0 / ratio),
+                ratio if ratio > 1.0 => format!("{:.2}x slower", ratio),
+                _ => "unchanged".to_string(),
+            };
+            println!(
+                "Times of b over a, of {} number(s): median {:.2} ({}), from {:.2} to {:.2}; \
+                 {} faster, {} slower.",
+                timing.compared,
+                timing.median_ratio,
+                change,
+                timing.min_ratio,
+                timing.max_ratio,
+                timing.faster,
+                timing.slower
+            );
+        }
+        None => println!("No number took time in both runs, so their times are not compared."),
+    }
+    if !diff.disagreements.is_empty() {
+        let line = format!(
+            "SERIOUS: {} number(s) found prime by one run and not the other; \
+             one of the machines got a test wrong.",
+            diff.disagreements.len()
+        );
+        println!("{}", color::stdout(Style::Error, line));
+    }
+    status
+}
+
+/// Re-runs the tests of the `fraction` of the composite results for `form`
+/// in `file` that `seed` picks, for `verify-sample`, on `threads` threads,
+/// and writes the exponents that disagree to `mismatches`.
+fn verify_sample(
+    file: &Path,
+    form: Form,
+    fraction: f64,
+    seed: u64,
+    mismatches: &Path,
+    threads: Option<usize>,
+) -> u8 {
+    let composites = match fs::read_to_string(file) {
+        Ok(text) => sample::read_composites(&text, form),
+        Err(e) => {
+            error!("cannot read {}: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    let picked = sample::pick(&composites, fraction, seed);
+    if picked.is_empty() {
+        error!("{} has no composite results of {} numbers.", file.display(), form);
+        return EXIT_USAGE;
+    }
+    let pool = match thread_pool(threads, None) {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("cannot start worker threads: {}", e);
+            return EXIT_INTERNAL_ERROR;
+        }
+    };
+    println!(
+        "Re-running {} of {} composite(s) with seed {}.",
+        picked.len(),
+        composites.len(),
+        seed
+    );
+    let reruns: Vec<_> = pool.install(|| {
+        picked
+            .par_iter()
+            .map(|&recorded| (recorded, rerun(form, recorded)))
+            .collect()
+    });
+
+    let mut tally = Tally::default();
+    let mut unchecked = 0;
+    for (recorded, rerun) in reruns {
+        let number = form.number(recorded.exponent);
+        match rerun {
+            Some(Ok((prime, res64))) => {
+                let mismatches = tally.mismatches.len();
+                tally.record(recorded, prime, res64.as_deref());
+                match tally.mismatches.get(mismatches).map(|mismatch| &mismatch.found) {
+                    None => println!(
+                        "{}: {} Res64 {} agrees",
+                        number,
+                        recorded.test,
+                        res64.unwrap_or_default()
+                    ),
+                    Some(Some(found)) => println!(
+                        "{}: {} MISMATCH: recorded Res64 {}, found {}",
+                        number, recorded.test, recorded.res64, found
+                    ),
+                    Some(None) => println!(
+                        "{}: {} MISMATCH: recorded Res64 {}, found it prime",
+                        number, recorded.test, recorded.res64
+                    ),
+                }
+            }
+            Some(Err(interrupted)) => {
+                unchecked += 1;
+                if interrupted.anomaly.is_some() {
+                    warn!("the re-run of {} failed; it is left out.", number);
+                }
+            }
+            None => {
+                unchecked += 1;
+                warn!(
+                    "{} has a {} result, which cannot be re-run; it is left out.",
+                    number, recorded.test
+                );
+            }
+        }
+    }
+    if CANCEL.is_cancelled() {
+        info!("Interrupted; {} re-run(s) did not finish.", unchecked);
+        return EXIT_INTERRUPTED;
+    }
+
+    let rate = tally.error_rate();
+    println!(
+        "{} re-run(s): {} agree, {} mismatch(es)",
+        tally.checked(),
+        tally.agreed,
+        tally.mismatches.len()
+    );
+    if let Some(rate) = rate {
+        println!(
+            "Error rate: {:.2}% (95% interval {:.2}% to {:.2}%)",
+            100.0 * rate.estimate,
+            100.0 * rate.low,
+            100.0 * rate.high
+        );
+    }
+    if tally.mismatches.is_empty() {
+        return EXIT_SUCCESS;
+    }
+    if let Err(e) = tally.write_mismatches(mismatches) {
+        error!("cannot write {}: {}", mismatches.display(), e);
+        return EXIT_INTERNAL_ERROR;
+    }
+    let options = match form {
+        Form::Mersenne => String::new(),
+        Form::Riesel { k } => format!("--form riesel --k {} ", k),
+        form => format!("--form {} ", form),
+    };
+    println!(
+        "Test them again in full with: test {}$(cat {})",
+        options,
+        mismatches.display()
+    );
+    EXIT_AUDIT_FAILED
+}
+
+/// Runs the test of `recorded` again, on one thread: whether it found the
+/// number prime and its Res64 if not, or `None` for a test that does not
+/// apply to `form`.
+fn rerun(form: Form, recorded: &Recorded) -> Option<Result<(bool, Option<String>), Interrupted>> {
+    let p = recorded.exponent;
+    let control = CANCEL.control();
+    let prp = |result: PrpResult| (result.is_probable_prime(), Some(format_res64(result.res64())));
+    Some(match (form, recorded.test) {
+        (Form::Mersenne, TestKind::LucasLehmer) => {
+            is_mersenne_prime_interruptible(p, None, control, |_| {}).map(ll_outcome)
+        }
+        (Form::Mersenne, TestKind::Prp) => prp_test_interruptible(p, control, |_| {}).map(prp),
+        (Form::Wagstaff, TestKind::Prp) => {
+            wagstaff_prp_test_interruptible(p, control, |_| {}).map(prp)
+        }
+        (Form::Riesel { k }, TestKind::Llr) => {
+            is_riesel_prime_interruptible(k, p, control, |_| {}).map(ll_outcome)
+        }
+        _ => return None,
+    })
+}
+
+/// Prints what the results database `file` holds for `report`, or of `run`
+/// only, or exports its results.
+fn database_report(file: &Path, slowest: usize, export: Option<Export>, run: Option<RunId>) -> u8 {
+    let database = match Database::open_existing(file) {
+        Ok(Some(database)) => match &run {
+            Some(run) => database.only_run(run.clone()),
+            None => database,
+        },
+        Ok(None) => {
+            error!("{} is not a results database.", file.display());
+            return EXIT_USAGE;
+        }
+        Err(e) => {
+            error!("cannot use the database {}: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    match (database.runs(), &run) {
+        (Ok(runs), Some(run)) if runs.is_empty() => {
+            error!("{} has no run {}.", file.display(), run);
+            return EXIT_USAGE;
+        }
+        (Err(e), _) => {
+            error!("cannot read the database {}: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+        _ => {}
+    }
+    let printed = match export {
+        Some(format) => history::export(&database, format),
+        None => history::print(&database, slowest),
+    };
+    match printed {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            error!("cannot read the database {}: {}", file.display(), e);
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Prints the contents of a checkpoint file for `checkpoint-info`.
+fn checkpoint_info(file: &Path) -> u8 {
+    let parsed = fs::read(file)
+        .map_err(CheckpointError::Io)
+        .and_then(|bytes| Ok((Checkpoint::parse(&bytes)?, bytes)));
+    let (checkpoint, bytes) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            error!("{} is not a usable checkpoint: {}", file.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    let total = checkpoint.p - 2;
+    println!("Checkpoint: {}", file.display());
+    let version = Checkpoint::format_version(&bytes).unwrap_or(FORMAT_VERSION);
+    println!("Format version: {}", version);
+    println!("Exponent: {} (M({}))", checkpoint.p, checkpoint.p);
+    println!(
+        "Iteration: {} of {} ({:.2}%)",
+        checkpoint.iteration,
+        total,
+        100.0 * checkpoint.iteration as f64 / total.max(1) as f64
+    );
+    println!(
+        "Residue: {} bytes, Res64: 0x{}",
+        checkpoint.residue.to_bytes_le().len(),
+        format_res64(res64(&checkpoint.residue))
+    );
+    let recorded = |field: Option<String>| field.unwrap_or_else(|| "not recorded".to_string());
+    println!("Written by: {}", recorded(checkpoint.written_by.clone()));
+    println!("Run: {}", recorded(checkpoint.run.as_ref().map(RunId::to_string)));
+    println!("Test: {}", checkpoint.test);
+    println!("Shift: {}", recorded(checkpoint.shift.map(|bits| format!("{} bits", bits))));
+    println!("Backend: {}", recorded(checkpoint.backend.clone()));
+    println!("Crate version: {}", recorded(checkpoint.version.clone()));
+    println!("Checksum: OK");
+    EXIT_SUCCESS
+}
+
+/// The candidates of this machine's chunk in `planned`, and how many of them
+/// the ledger has finished.
+fn chunk_progress<I: Iterator<Item = (u64, Disposition)>>(planned: I) -> (u64, u64) {
+    planned.fold((0, 0), |(total, done), (_, disposition)| match disposition {
+        Disposition::Test | Disposition::InResults => (total + 1, done),
+        Disposition::InLedger => (total + 1, done + 1),
+        _ => (total, done),
+    })
+}
+
+/// Prints what `ledger` holds and how much of `selection` it covers, for
+/// `--ledger-report`. `finished` holds the exponents of `options.form` in
+/// it.
+fn print_ledger_report(
+    ledger: &Ledger,
+    selection: &Selection,
+    finished: &HashSet<u64>,
+    options: &Options,
+) {
+    let form = options.form;
+    let reports: Vec<&TestReport> =
+        ledger.reports().filter(|report| report.form == form).collect();
+    let (total, done) = chunk_progress(plan(selection, options, finished, &HashSet::new()));
+    let (start, end) = selection.bounds();
+    outln!("Ledger: {} finished exponent(s)", reports.len());
+    outln!(
+        "p = {} to p = {}: {} of {} candidates done, {} to go",
+        start,
+        end,
+        done,
+        total,
+        total - done
+    );
+
+    let (mut factored, mut composite, mut seconds) = (0, 0, 0.0);
+    let mut primes = Vec::new();
+    let mut mismatches = Vec::new();
+    for report in reports {
+        seconds += report.seconds;
+        if report.factor.is_some() {
+            factored += 1;
+        } else if report.prime {
+            primes.push(form.number(report.exponent));
+        } else {
+            composite += 1;
+        }
+        if report.is_mismatch() {
+            mismatches.push(report.exponent.to_string());
+        }
+    }
+    if primes.is_empty() {
+        outln!("{} primes: none", form.title());
+    } else {
+        outln!("{} primes: {}", form.title(), primes.join(", "));
+    }
+    outln!("Factored: {}, composite: {}", factored, composite);
+    if !mismatches.is_empty() {
+        outln!("Double-check mismatches: {}", mismatches.join(", "));
+    }
+    outln!("Test time: {}", format_duration(seconds));
+}
+
+fn parse_positive<T>(s: &str) -> Result<T, String>
+where
+    T: std::str::FromStr + PartialEq + From<u8>,
+    T::Err: std::fmt::Display,
+{
+    match s.parse() {
+        Ok(n) if n == T::from(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
 
-    end_exponent: u64,
+/// Parses a `--proof-power`, 1 to [`MAX_POWER`].
+fn parse_proof_power(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(power) if (1..=MAX_POWER).contains(&power) => Ok(power),
+        Ok(_) => Err(format!("must be from 1 to {}", MAX_POWER)),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
 
-    #[structopt(short, long)]
-    verbose: bool,
+/// Parses a `--slowdown-warning` or `--cpu-limit`, 1 to 99 percent.
+fn parse_percent(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(percent) if (1..=99).contains(&percent) => Ok(percent),
+        Ok(_) => Err("must be from 1 to 99".to_string()),
+        Err(e) => Err(format!("{}", e)),
+    }
 }
 
-fn mod_mersenne(n: &BigUint, p: u64) -> BigUint {
-    let modulus = (&BigUint::one() << p) - 1u32;
-    let mut n = n.clone();
+/// Parses a `--fraction`, more than 0 and at most 1.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => Ok(fraction),
+        Ok(_) => Err("must be more than 0 and at most 1".to_string()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
 
-    while n.bits() > p {
-        let high = &n >> p;
-        let low = &n & &modulus;
-        n = high + low;
+/// Parses a load for --pause-when-busy: a number of cores, which may be
+/// fractional.
+fn parse_load(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(load) if load.is_finite() && load >= 0.0 => Ok(load),
+        Ok(_) => Err("must be a number of cores, 0 or more".to_string()),
+        Err(e) => Err(format!("{}", e)),
     }
+}
 
-    if n == modulus {
-        BigUint::zero()
-    } else {
-        n
+/// Parses durations like `45m`, `8h`, `1h30m` or `2d`; a bare number is in
+/// seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let mut seconds = 0u64;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("unknown unit '{}' in {:?}; use d, h, m or s", c, s)),
+        };
+        let n: u64 = number
+            .parse()
+            .map_err(|_| format!("expected a number before '{}' in {:?}", c, s))?;
+        seconds = n
+            .checked_mul(unit)
+            .and_then(|n| seconds.checked_add(n))
+            .ok_or_else(|| format!("{:?} is too long", s))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        if seconds != 0 {
+            return Err(format!("missing unit after {} in {:?}", number, s));
+        }
+        seconds = number.parse().map_err(|_| format!("{:?} is too long", s))?;
+    }
+    if seconds == 0 {
+        return Err("must be at least 1 second".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// A worker pool with `threads` threads, or one per core, pinned to cores
+/// of `pinning` if given.
+fn thread_pool(
+    threads: Option<usize>,
+    pinning: Option<&Pinning>,
+) -> Result<rayon::ThreadPool, rayon::ThreadPoolBuildError> {
+    let mut builder = rayon::ThreadPoolBuilder::new();
+    if let Some(threads) = threads {
+        builder = builder.num_threads(threads);
+    }
+    if let Some(pinning) = pinning {
+        let cores = pinning.assign("worker", threads.unwrap_or_else(rayon::current_num_threads));
+        builder = builder.start_handler(move |index| affinity::pin_current(cores[index]));
+    }
+    builder.build()
+}
+
+/// Starts watching for --pause-when-busy, --pause-file and a `pause`
+/// through --control in `scope`, if any of them can pause the tests. The
+/// watch goes on until the returned sender is dropped.
+fn watch_load<'scope>(
+    scope: &'scope std::thread::Scope<'scope, '_>,
+    options: &'scope Options,
+) -> Option<mpsc::Sender<()>> {
+    let controlled = options.control.is_some() || options.control_socket.is_some();
+    let busy = idle::Busy {
+        load: options.pause_when_busy,
+        file: options.pause_file.as_deref(),
+        held: controlled.then_some(&HELD),
+    };
+    if busy.load.is_none() && busy.file.is_none() && busy.held.is_none() {
+        return None;
+    }
+    let (sender, done) = mpsc::channel();
+    scope.spawn(move || idle::watch(busy, CANCEL.pauses(), done));
+    Some(sender)
+}
+
+/// Starts taking commands through --control and --control-socket in
+/// `scope`, if either is given; a `status` sends a message to each of
+/// `refresh`. The watch goes on until the returned sender is dropped.
+fn watch_control<'scope>(
+    scope: &'scope std::thread::Scope<'scope, '_>,
+    options: &'scope Options,
+    refresh: Vec<mpsc::Sender<()>>,
+) -> Option<mpsc::Sender<()>> {
+    if options.control.is_none() && options.control_socket.is_none() {
+        return None;
+    }
+    let control = control::Control {
+        file: options.control.as_deref(),
+        socket: options.control_socket.as_deref(),
+        held: &HELD,
+        token: &CANCEL,
+        refresh,
+    };
+    let (sender, done) = mpsc::channel();
+    scope.spawn(move || control.watch(done));
+    Some(sender)
+}
+
+/// Waits for the next update of a status report, `interval` on or sooner
+/// for a `status` through --control. Returns `false` once the run is over.
+fn next_update(wait: &mpsc::Receiver<()>, interval: Option<Duration>) -> bool {
+    match interval {
+        Some(interval) => {
+            !matches!(wait.recv_timeout(interval), Err(RecvTimeoutError::Disconnected))
+        }
+        None => wait.recv().is_ok(),
     }
 }
 
-fn is_mersenne_prime(p: u64, verbose: bool) -> bool {
-    if p < 2 {
+/// The cores to pin worker threads to with --pin-threads or --cpus.
+fn pinning(options: &Options) -> Option<Pinning> {
+    if !options.pin_threads && options.cpus.is_none() {
+        return None;
+    }
+    Pinning::new(options.cpus.as_ref())
+}
+
+/// Whether the primality tests can be pinned: not when they split their
+/// squarings, whose threads would inherit the one core of their test.
+fn pin_tests(options: &Options, pinning: Option<&Pinning>) -> bool {
+    if pinning.is_some() && options.threads_per_test > 1 {
+        warn!("--pin-threads leaves the primality tests unpinned with --threads-per-test.");
         return false;
     }
-    if p == 2 {
-        return true;
+    true
+}
+
+/// Checks the exponents given to `test`. They must be prime, since a
+/// composite one is almost certainly a typo, and odd for Wagstaff numbers;
+/// a composite one is rejected with a factor, to show why.
+/// Riesel exponents need not be prime, but must be in the range of the
+/// test.
+fn select_exponents(mut exponents: Vec<u64>, form: Form) -> Result<Selection, String> {
+    for &p in &exponents {
+        check_exponent(p, form)?;
+    }
+    exponents.sort_unstable();
+    exponents.dedup();
+    Ok(Selection::List(exponents))
+}
+
+/// Checks one exponent given to `test`, as [`select_exponents`] does.
+fn check_exponent(p: u64, form: Form) -> Result<(), String> {
+    if let Form::Riesel { k } = form {
+        return match riesel_testable(k, p) {
+            true => Ok(()),
+            false => Err(format!(
+                "{} is outside the Lucas-Lehmer-Riesel test, which needs n >= 2 and k < 2^n.",
+                form.number(p)
+            )),
+        };
+    }
+    if let Some(rejection) = rejection(p) {
+        return Err(format!(
+            "{}; it is not prime, so {} cannot be a {} prime.",
+            rejection,
+            form.number(p),
+            form.title()
+        ));
     }
+    if form == Form::Wagstaff && p == 2 {
+        return Err("W(2) = 5/3 is not an integer; Wagstaff exponents are odd primes.".to_string());
+    }
+    Ok(())
+}
+
+/// The exponents of `test --stdin`, read and checked one at a time as the
+/// run takes them. The run takes them no faster than its first stage has
+/// room for them, so a long input is never held in memory.
+struct Feed {
+    state: Mutex<FeedState>,
+    form: Form,
+    strict: bool,
+    /// Raised when `--strict` or a read error ended the input early.
+    failed: AtomicBool,
+}
+
+struct FeedState {
+    tokens: ExponentReader<Box<dyn BufRead + Send>>,
+    /// The exponents taken so far, so that a repeated one is tested once.
+    seen: HashSet<u64>,
+}
 
-    let total_iterations = p - 2;
-    let progress_interval = (total_iterations / 100).max(1); // Ensure progress_interval is at least 1
+impl Feed {
+    fn new(reader: Box<dyn BufRead + Send>, form: Form, strict: bool) -> Feed {
+        Feed {
+            state: Mutex::new(FeedState {
+                tokens: ExponentReader::new(reader),
+                seen: HashSet::new(),
+            }),
+            form,
+            strict,
+            failed: AtomicBool::new(false),
+        }
+    }
 
-    let mut s = 4u32.to_biguint().unwrap();
+    /// The next exponent to test, `None` once the input has ended.
+    fn next(&self) -> Option<u64> {
+        if self.failed.load(Ordering::SeqCst) {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let (line, problem) = match state.tokens.next()? {
+                Ok(Token::Exponent { line, p }) => match check_exponent(p, self.form) {
+                    Ok(()) if state.seen.insert(p) => return Some(p),
+                    Ok(()) => {
+                        debug!("line {}: {} was given before; testing it once.", line, p);
+                        continue;
+                    }
+                    Err(message) => (line, message),
+                },
+                Ok(Token::Malformed { line, text }) => {
+                    (line, format!("{:?} is not an exponent", text))
+                }
+                Err(e) => {
+                    error!("cannot read standard input: {}", e);
+                    self.failed.store(true, Ordering::SeqCst);
+                    return None;
+                }
+            };
+            if self.strict {
+                error!("line {} of standard input: {}.", line, problem.trim_end_matches('.'));
+                self.failed.store(true, Ordering::SeqCst);
+                return None;
+            }
+            warn!(
+                "skipping line {} of standard input: {}.",
+                line,
+                problem.trim_end_matches('.')
+            );
+        }
+    }
+}
 
-    for i in 1..=total_iterations {
-        s = &s * &s - 2u32;
-        s = mod_mersenne(&s, p);
+fn main() -> ExitCode {
+    // Worker panics are re-raised here by rayon or the thread scope; the
+    // default hook has already printed them.
+    let status = panic::catch_unwind(run).unwrap_or(EXIT_INTERNAL_ERROR);
+    ExitCode::from(status)
+}
 
-        if verbose {
-            if i % progress_interval == 0 || i == total_iterations {
-                let percent = (i * 100) / total_iterations;
-                print!("\rTesting p = {}: Progress: {}%", p, percent);
-                io::stdout().flush().unwrap();
+fn run() -> u8 {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let given = match Command::clap().get_matches_from_safe(&args) {
+        Ok(matches) => matches,
+        Err(e) if e.use_stderr() => {
+            eprintln!("{}", e.message);
+            return EXIT_USAGE;
+        }
+        Err(e) => {
+            // --help and --version
+            println!("{}", e.message);
+            return 0;
+        }
+    };
+    let mut command = Command::from_clap(&given);
+    if let (
+        Command::Search { options, .. }
+        | Command::Test { options, .. }
+        | Command::Fermat { options, .. }
+        | Command::Work { options, .. },
+        (_, Some(given)),
+    ) = (&command, given.subcommand())
+    {
+        let merged = match config::merge(args, given, options.config.as_deref()) {
+            Ok(merged) => merged,
+            Err(message) => {
+                eprintln!("Error: {}", message);
+                return EXIT_USAGE;
+            }
+        };
+        let matches = match Command::clap().get_matches_from_safe(&merged.args) {
+            Ok(matches) => matches,
+            Err(e) => {
+                eprintln!("{}", e.message);
+                eprintln!("Options taken from the configuration: {}", merged.describe());
+                return EXIT_USAGE;
+            }
+        };
+        command = Command::from_clap(&matches);
+        if let (
+            Command::Search { options, .. }
+            | Command::Test { options, .. }
+            | Command::Fermat { options, .. }
+            | Command::Work { options, .. },
+            (_, Some(matches)),
+        ) = (&command, matches.subcommand())
+        {
+            if options.print_config {
+                config::print(matches, &merged.sources);
+                return EXIT_SUCCESS;
             }
         }
     }
 
-    if verbose {
-        println!();
+    let (level, log_file, colors, events, separator, run_id) = match &command {
+        Command::Search { options, .. }
+        | Command::Test { options, .. }
+        | Command::Fermat { options, .. }
+        | Command::Work { options, .. } => (
+            options.log_level(),
+            options.log_file.as_deref(),
+            options.color,
+            options.events.as_deref(),
+            if options.raw_numbers { "" } else { options.digit_separator.as_str() },
+            options.run_id.clone(),
+        ),
+        _ => (LevelFilter::Info, None, ColorChoice::Auto, None, ",", None),
+    };
+    // Everything a search, test or work run writes carries its ID.
+    let run_context = RunContext::start(run_id, rand::random());
+    color::init(colors);
+    progress::init_digit_separator(separator);
+    if let Err(e) = logging::init(level, log_file) {
+        eprintln!("Error: cannot open the log file: {}", e);
+        return EXIT_USAGE;
+    }
+    if let Some(path) = events {
+        if let Err(e) = events::init(path, &run_context.id) {
+            error!("cannot open the event stream {}: {}", path.display(), e);
+            return EXIT_USAGE;
+        }
+    }
+
+    if let Command::Fermat { options, .. } = &mut command {
+        if options.form != Form::Mersenne {
+            error!("fermat tests Fermat numbers; --form cannot be used with it.");
+            return EXIT_USAGE;
+        }
+        options.form = Form::Fermat;
+    }
+    if let Command::Search { options, .. }
+    | Command::Test { options, .. }
+    | Command::Fermat { options, .. }
+    | Command::Work { options, .. } = &mut command
+    {
+        match (options.form, options.k) {
+            (Form::Riesel { .. }, k) => options.form = Form::Riesel { k: k.unwrap_or(1) },
+            (_, Some(_)) => {
+                error!("--k is the multiplier of Riesel numbers; it needs --form riesel.");
+                return EXIT_USAGE;
+            }
+            (_, None) => {}
+        }
+        if let Form::Riesel { k } = options.form {
+            if k.is_multiple_of(2) {
+                error!("--k must be odd; k*2^n - 1 with even k is also (k/2)*2^(n+1) - 1.");
+                return EXIT_USAGE;
+            }
+        }
+    }
+    if let Command::Search { options, .. }
+    | Command::Test { options, .. }
+    | Command::Fermat { options, .. }
+    | Command::Work { options, .. } = &command
+    {
+        if let Err(message) = check_form(options) {
+            error!("{}", message);
+            return EXIT_USAGE;
+        }
+    }
+
+    if let Err(e) = ctrlc::set_handler(|| {
+        if CANCEL.cancel() {
+            std::process::exit(EXIT_INTERRUPTED.into());
+        }
+        info!("Stopping after saving running tests; press Ctrl-C again to quit immediately.");
+    }) {
+        warn!("cannot install the Ctrl-C handler: {}", e);
+    }
+
+    match command {
+        Command::Search {
+            start_exponent,
+            end_exponent,
+            count,
+            mut ranges,
+            options,
+        } => {
+            let every = matches!(options.form, Form::Riesel { .. });
+            let selection = match (start_exponent, end_exponent, count) {
+                (None, _, _) => {
+                    if let Err(message) = sort_ranges(&mut ranges) {
+                        error!("{}", message);
+                        return EXIT_USAGE;
+                    }
+                    Selection::Ranges { ranges, every }
+                }
+                (Some(start), _, Some(count)) => Selection::Next {
+                    start,
+                    count,
+                    every,
+                },
+                (Some(start), Some(end), None) if every => Selection::Every(start, end),
+                (Some(start), Some(end), None) => Selection::Range(start, end),
+                (Some(_), None, None) => unreachable!("structopt requires one of them"),
+            };
+            run_tests(&options, selection, None, &run_context)
+        }
+        Command::Test {
+            exponents,
+            worktodo: Some(path),
+            options,
+            ..
+        } => {
+            debug_assert!(exponents.is_empty());
+            if options.form != Form::Mersenne {
+                error!(
+                    "worktodo assignments are for Mersenne numbers; --form {} cannot be used with --worktodo.",
+                    options.form
+                );
+                return EXIT_USAGE;
+            }
+            let worktodo = match WorkTodo::load(&path) {
+                Ok(worktodo) => worktodo,
+                Err(e) => {
+                    error!("cannot read {}: {}", path.display(), e);
+                    return EXIT_USAGE;
+                }
+            };
+            for (number, text, line) in worktodo.skipped_lines() {
+                let reason = match line {
+                    Line::Malformed(reason) => reason.as_str(),
+                    _ => "unsupported work type",
+                };
+                warn!(
+                    "leaving worktodo line {} untouched ({}): {}",
+                    number, reason, text
+                );
+            }
+            let exponents = worktodo.exponents();
+            if exponents.is_empty() {
+                info!("No Lucas-Lehmer assignments found in the worktodo file.");
+                return EXIT_NONE_FOUND;
+            }
+            run_tests(&options, Selection::List(exponents), Some(worktodo), &run_context)
+        }
+        Command::Test {
+            stdin: true,
+            strict,
+            options,
+            ..
+        } => {
+            if options.order != Order::Smallest {
+                error!("--stdin tests the exponents as they arrive, so it cannot take --order.");
+                return EXIT_USAGE;
+            }
+            let feed = Feed::new(Box::new(io::BufReader::new(io::stdin())), options.form, strict);
+            run_tests(&options, Selection::Stream(feed), None, &run_context)
+        }
+        Command::Test {
+            exponents,
+            worktodo: None,
+            resume,
+            options,
+            ..
+        } => match (select_exponents(exponents, options.form), resume) {
+            (Ok(Selection::List(list)), _) if list.len() == 1 => {
+                single::run(list[0], options, resume, &run_context)
+            }
+            (Ok(_), true) => {
+                error!("--resume takes a single exponent; a --checkpoint-dir is always resumed.");
+                EXIT_USAGE
+            }
+            (Ok(selection), false) => run_tests(&options, selection, None, &run_context),
+            (Err(message), _) => {
+                error!("{}", message);
+                EXIT_USAGE
+            }
+        },
+        Command::Fermat {
+            start_n,
+            end_n,
+            options,
+        } => {
+            if start_n > end_n {
+                error!("start_n should be less than or equal to end_n.");
+                return EXIT_USAGE;
+            }
+            if end_n > MAX_FERMAT_INDEX {
+                error!(
+                    "F({}) is too large to test; the largest is F({}).",
+                    end_n, MAX_FERMAT_INDEX
+                );
+                return EXIT_USAGE;
+            }
+            run_tests(&options, Selection::Every(start_n, end_n), None, &run_context)
+        }
+        Command::Bench {
+            exponents,
+            iterations,
+            threads,
+        } => match thread_pool(threads, None) {
+            Ok(pool) => {
+                bench::run(&pool, &exponents, iterations);
+                EXIT_SUCCESS
+            }
+            Err(e) => {
+                error!("cannot start worker threads: {}", e);
+                EXIT_INTERNAL_ERROR
+            }
+        },
+        Command::ListKnown => {
+            list_known();
+            EXIT_SUCCESS
+        }
+        Command::Info { json } => {
+            info_command(json);
+            EXIT_SUCCESS
+        }
+        Command::AuditVerify {
+            file,
+            spot_check,
+            threads,
+        } => audit_verify(&file, spot_check, threads),
+        Command::VerifyProof { file } => verify_proof(&file),
+        Command::VerifyResidue { file, from } => verify_residue(&file, from.as_deref()),
+        Command::Compare { a, b } => compare_runs(&a, &b),
+        Command::Diff {
+            a,
+            b,
+            run_a,
+            run_b,
+            json,
+        } => diff_runs((&a, run_a.as_ref()), (&b, run_b.as_ref()), json),
+        Command::VerifySample {
+            results,
+            fraction,
+            seed,
+            form,
+            k,
+            mismatches,
+            threads,
+        } => {
+            let form = match (form, k) {
+                (Form::Riesel { .. }, k) => Form::Riesel { k: k.unwrap_or(1) },
+                (_, Some(_)) => {
+                    error!("--k is the multiplier of Riesel numbers; it needs --form riesel.");
+                    return EXIT_USAGE;
+                }
+                (form, None) => form,
+            };
+            let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+            verify_sample(&results, form, fraction, seed, &mismatches, threads)
+        }
+        Command::CheckpointInfo { file } => checkpoint_info(&file),
+        Command::Report {
+            file,
+            slowest,
+            export,
+            run,
+        } => database_report(&file, slowest, export, run),
+        Command::Serve {
+            range,
+            listen,
+            lease,
+            state,
+            results,
+        } => {
+            let (start, end) = (range[0], range[1]);
+            if start > end {
+                error!("the start of --range should be less than or equal to its end.");
+                return EXIT_USAGE;
+            }
+            server::run(&server::ServeOptions {
+                start,
+                end,
+                listen: &listen,
+                lease,
+                state: &state,
+                results: &results,
+            })
+        }
+        Command::Work { server, options } => worker::run(&options, &server, &run_context),
+        Command::Selftest { threads } => match thread_pool(threads, None) {
+            Ok(pool) => {
+                if selftest::run(&pool) {
+                    EXIT_SUCCESS
+                } else {
+                    EXIT_SELFTEST_FAILED
+                }
+            }
+            Err(e) => {
+                error!("cannot start worker threads: {}", e);
+                EXIT_INTERNAL_ERROR
+            }
+        },
     }
-    s.is_zero()
 }
 
-fn is_prime(n: u64) -> bool {
-    if n <= 1 {
-        return false;
+/// The checkpoint store of `--checkpoint-dir` for `run`, if set, or the exit
+/// status if it cannot be used.
+fn checkpoint_store(options: &Options, run: &RunContext) -> Result<Option<CheckpointStore>, u8> {
+    match &options.checkpoint_dir {
+        Some(dir) => match CheckpointStore::new(dir, options.checkpoint_interval.unwrap_or(1)) {
+            Ok(store) => Ok(Some(match options.checkpoint_interval {
+                Some(_) => store,
+                None => store.every(options.checkpoint_every),
+            }
+            .for_run(run.id.clone()))),
+            Err(e) => {
+                error!(
+                    "cannot use checkpoint directory {}: {}",
+                    dir.display(),
+                    e
+                );
+                Err(EXIT_USAGE)
+            }
+        },
+        None => Ok(None),
     }
-    if n <= 3 {
-        return true;
+}
+
+/// Checks that the tests of `start..=end` can resume their checkpoints in
+/// `store`, or with --force-restart deletes those they cannot. Returns the
+/// exit status if one cannot be resumed. Checkpoints that cannot be read
+/// are left for their tests to discard.
+fn check_checkpoints(
+    store: &CheckpointStore,
+    options: &Options,
+    (start, end): (u64, u64),
+) -> Result<(), u8> {
+    if options.form != Form::Mersenne {
+        return Ok(());
     }
-    if n % 2 == 0 || n % 3 == 0 {
-        return false;
+    let exponents = match store.exponents() {
+        Ok(exponents) => exponents,
+        Err(e) => {
+            error!("cannot read the checkpoint directory: {}", e);
+            return Err(EXIT_USAGE);
+        }
+    };
+    for p in exponents.into_iter().filter(|p| (start..=end).contains(p)) {
+        let Ok(Some(checkpoint)) = store.load(p) else {
+            continue;
+        };
+        // A random shift is the checkpoint's, if it has one.
+        let shift = match (options.shift, options.double_check) {
+            (Some(ShiftChoice::Random), false) => {
+                resumed_shift(Some(&checkpoint)).unwrap_or_else(|| ShiftChoice::Random.bits(p))
+            }
+            (Some(choice), false) => choice.bits(p),
+            _ => 0,
+        };
+        let Err(e) = checkpoint.resumable_by(TestKind::LucasLehmer, shift) else {
+            continue;
+        };
+        let (path, name) = (store.path(p), options.form.number(p));
+        if !options.force_restart {
+            error!(
+                "cannot resume {} from {}: {}; use --force-restart to delete it and start the test \
+                 over.",
+                name,
+                path.display(),
+                e
+            );
+            return Err(EXIT_USAGE);
+        }
+        warn!("deleting {} ({}); the test of {} starts over.", path.display(), e, name);
+        if let Err(e) = store.remove(p) {
+            error!("cannot delete {}: {}", path.display(), e);
+            return Err(EXIT_USAGE);
+        }
+    }
+    Ok(())
+}
+
+/// The shift of the test that wrote `checkpoint`, for a test with a random
+/// shift to resume it with; `None` if it was unshifted or does not say.
+fn resumed_shift(checkpoint: Option<&Checkpoint>) -> Option<u64> {
+    checkpoint?.shift.filter(|&bits| bits != 0)
+}
+
+/// The audit log of `--audit-log` for `run`, if set, or the exit status if
+/// it cannot be used.
+fn audit_log(options: &Options, run: &RunContext) -> Result<Option<Mutex<AuditLog>>, u8> {
+    match &options.audit_log {
+        Some(path) => match AuditLog::open(path) {
+            Ok(log) => Ok(Some(Mutex::new(log.for_run(run.id.clone())))),
+            Err(e) => {
+                error!("cannot use the audit log {}: {}", path.display(), e);
+                Err(EXIT_USAGE)
+            }
+        },
+        None => Ok(None),
     }
-    let mut i = 5;
-    while i * i <= n {
-        if n % i == 0 || n % (i + 2) == 0 {
-            return false;
+}
+
+/// The CSV file of `--csv` or `--csv-append`, if set, with `source` for
+/// its candidate_source column, or the exit status if it cannot be used.
+fn spreadsheet(options: &Options, source: &'static str) -> Result<Option<Mutex<Spreadsheet>>, u8> {
+    let (path, append) = match (&options.csv, &options.csv_append) {
+        (Some(path), _) => (path, false),
+        (None, Some(path)) => (path, true),
+        (None, None) => return Ok(None),
+    };
+    match Spreadsheet::open(path, append, source) {
+        Ok(spreadsheet) => Ok(Some(Mutex::new(spreadsheet))),
+        Err(e) => {
+            error!("cannot open {}: {}", path.display(), e);
+            Err(EXIT_USAGE)
         }
-        i += 6;
     }
-    true
 }
 
-fn main() {
-    let options = Options::from_args();
+/// Refuses the options that only apply to Mersenne numbers when testing
+/// another form, --proof-dir without a PRP test, --save-residue and
+/// --res2048 without a Lucas-Lehmer or PRP test and --retest with nothing
+/// to retest.
+fn check_form(options: &Options) -> Result<(), String> {
+    if options.retest && options.results.is_none() && options.db.is_none() {
+        return Err("--retest tests again what --results or --db would skip, so it needs one of them."
+            .to_string());
+    }
+    if options.proof_dir.is_some() && !options.prp && options.form != Form::Wagstaff {
+        return Err("--proof-dir writes proofs of PRP tests, so it needs --prp or --form wagstaff."
+            .to_string());
+    }
+    let tested = matches!(options.form, Form::Mersenne | Form::Wagstaff);
+    if options.save_residue.is_some() && !tested {
+        return Err(format!(
+            "--save-residue saves the residues of Lucas-Lehmer and PRP tests, so it cannot be \
+             used with --form {}.",
+            options.form
+        ));
+    }
+    if options.res2048 && !tested {
+        return Err(format!(
+            "--res2048 reports the residues of Lucas-Lehmer and PRP tests, so it cannot be used \
+             with --form {}.",
+            options.form
+        ));
+    }
+    if options.form == Form::Mersenne {
+        return Ok(());
+    }
+    let mersenne_only = [
+        ("--double-check", options.double_check),
+        ("--confirm", options.confirm),
+        ("--checkpoint-dir", options.checkpoint_dir.is_some()),
+        ("--audit-log", options.audit_log.is_some()),
+        ("--shift", options.shift.is_some()),
+        ("--backend", options.backend != BackendChoice::Auto),
+        ("--p1-b1", options.p1_b1 > 0),
+        ("--skip-known", options.skip_known),
+        ("--known-factors", options.known_factors.is_some()),
+        (
+            "--exponent-filter double-mersenne",
+            options.exponent_filter == ExponentFilter::DoubleMersenne,
+        ),
+        ("--primenet-results", options.primenet_results.is_some()),
+        ("--perfect", options.perfect),
+    ];
+    match mersenne_only.iter().find(|(_, set)| *set) {
+        Some((flag, _)) => Err(format!(
+            "{} applies to Mersenne numbers only, so it cannot be used with --form {}.",
+            flag, options.form
+        )),
+        None => Ok(()),
+    }
+}
 
-    let start_p = options.start_exponent;
-    let end_p = options.end_exponent;
-    let verbose = options.verbose;
+/// Prints the plan of a run for `--dry-run`, reading its ledger, results
+/// file and database but writing nothing.
+fn dry_run(options: &Options, selection: Selection) -> u8 {
+    let finished = match &options.ledger {
+        Some(path) => match ledger::finished_exponents(path, options.form) {
+            Ok(finished) => finished,
+            Err(e) => {
+                error!("cannot use the ledger {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        None => HashSet::new(),
+    };
+    let mut recorded = match &options.results {
+        Some(path) if !options.retest => match results::recorded_exponents(path, options.form) {
+            Ok(recorded) => recorded,
+            Err(e) => {
+                error!("cannot read {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        _ => HashSet::new(),
+    };
+    if let (Some(path), false) = (&options.db, options.retest) {
+        match database::recorded_exponents(path, options.form) {
+            Ok(stored) => recorded.extend(stored),
+            Err(e) => {
+                error!("cannot use the database {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        }
+    }
+    let count = selection.count();
+    let selection = plan::resolve(selection, options, &finished, &recorded);
+    let threads = options.threads.unwrap_or_else(rayon::current_num_threads);
+    let planned = plan(&selection, options, &finished, &recorded);
+    let mut work =
+        WorkPlan::new(options.form, selection.bounds(), planned, threads).with_count(count);
+    work = work.with_ranges(selection.ranges()).with_filter(options.exponent_filter);
+    if let Some(share) = options.cpu_share() {
+        work = work.throttled_to(share);
+    }
+    if let (&Selection::Range(start, end), Form::Mersenne) = (&selection, options.form) {
+        work = work.with_expectation(start, end);
+    }
+    if options.json {
+        outln!("{}", serde_json::to_string(&PlanLine { plan: &work }).unwrap());
+    } else {
+        work.print();
+    }
+    EXIT_SUCCESS
+}
 
-    if start_p > end_p {
-        println!("Error: start_exponent should be less than or equal to end_exponent.");
-        return;
+/// Tests every exponent of `selection` as [`test_selection`] does, with the
+/// candidates sieved through the `--sieve-cache`, if there is one, which is
+/// saved afterwards.
+fn run_tests(
+    options: &Options,
+    selection: Selection,
+    worktodo: Option<WorkTodo>,
+    run: &RunContext,
+) -> u8 {
+    let Some(path) = &options.sieve_cache else {
+        return test_selection(options, selection, worktodo, run);
+    };
+    let mut shrunk = false;
+    let cache = SIEVE_CACHE.get_or_init(|| {
+        let cache = match SieveCache::load(path) {
+            Ok(cache) => cache,
+            Err(SieveCacheError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                SieveCache::new(sieve_cache::DEFAULT_BLOCK_SIZE)
+            }
+            Err(e) => {
+                warn!("cannot use the sieve cache {} ({}); starting it over.", path.display(), e);
+                SieveCache::new(sieve_cache::DEFAULT_BLOCK_SIZE)
+            }
+        };
+        let blocks = cache.len();
+        let cache = cache.with_max_bytes(options.sieve_cache_max.saturating_mul(1 << 20));
+        shrunk = cache.len() < blocks;
+        cache
+    });
+    let status = test_selection(options, selection, worktodo, run);
+    let (read, sieved) = cache.usage();
+    debug!(
+        "Sieve cache {}: {} blocks read, {} sieved, {} kept.",
+        path.display(),
+        read,
+        sieved,
+        cache.len()
+    );
+    if sieved > 0 || shrunk {
+        if let Err(e) = cache.save(path) {
+            warn!("could not write the sieve cache {}: {}", path.display(), e);
+        }
     }
+    status
+}
 
-    let exponents: Vec<u64> = (start_p..=end_p).filter(|&p| is_prime(p)).collect();
+/// The most bad lines of a `--known-factors` file warned about one by one;
+/// the rest are only counted.
+const BAD_LINE_WARNINGS: u64 = 10;
 
-    println!(
-        "Searching for Mersenne primes in the range p = {} to p = {}...",
-        start_p, end_p
+/// The factor `--known-factors` gives of `M(p)`, if any.
+fn known_factor(p: u64) -> Option<&'static str> {
+    KNOWN_FACTORS.get()?.get(p)
+}
+
+/// Reads the `--known-factors` of the run, if it has any and they were
+/// not read already, warning of the lines it skips.
+fn load_known_factors(options: &Options) -> Result<(), String> {
+    let Some(path) = options.known_factors.as_ref().filter(|_| KNOWN_FACTORS.get().is_none())
+    else {
+        return Ok(());
+    };
+    let cannot_read =
+        |e: io::Error| format!("cannot read the known factors {}: {}", path.display(), e);
+    let file = fs::File::open(path).map_err(cannot_read)?;
+    let mut bad = 0;
+    let known = KnownFactors::read(io::BufReader::new(file), |line| {
+        bad += 1;
+        if bad <= BAD_LINE_WARNINGS {
+            warn!("skipping line {} of {}: {}.", line.line, path.display(), line.reason);
+        }
+    })
+    .map_err(cannot_read)?;
+    if bad > BAD_LINE_WARNINGS {
+        warn!("skipped {} bad lines of {} in all.", group(bad), path.display());
+    }
+    info!(
+        "Read known factors of {} exponent(s) from {}.",
+        group(known.len() as u64),
+        path.display()
     );
+    let _ = KNOWN_FACTORS.set(known);
+    Ok(())
+}
 
-    let start_time = Instant::now();
+/// Tests every exponent of `selection` as `run`, completing `worktodo`
+/// assignments as they finish, and returns the exit status.
+fn test_selection(
+    options: &Options,
+    selection: Selection,
+    worktodo: Option<WorkTodo>,
+    run_context: &RunContext,
+) -> u8 {
+    let memory = admission::available_memory();
+    let selection = match plan::validate_options(selection, options, memory) {
+        Ok(selection) => selection,
+        Err(plan::OptionsError::NoCandidates(message)) => {
+            warn!("{}", message);
+            return EXIT_NO_WORK;
+        }
+        Err(e) => {
+            error!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+    if let Err(e) = load_known_factors(options) {
+        error!("{}", e);
+        return EXIT_USAGE;
+    }
+    if options.dry_run {
+        return dry_run(options, selection);
+    }
+    if options.nice {
+        idle::lower_priority();
+    }
+    let worktodo = worktodo.map(Mutex::new);
+
+    let ledger = match &options.ledger {
+        Some(path) => match Ledger::open(path) {
+            Ok(ledger) => Some(ledger),
+            Err(e) => {
+                error!("cannot use the ledger {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        None => None,
+    };
+    let finished: HashSet<u64> = ledger
+        .iter()
+        .flat_map(Ledger::reports)
+        .filter(|report| report.form == options.form)
+        .map(|report| report.exponent)
+        .collect();
+    if let (Some(ledger), true) = (&ledger, options.ledger_report) {
+        print_ledger_report(ledger, &selection, &finished, options);
+        return EXIT_SUCCESS;
+    }
+    let ledger = ledger.map(Mutex::new);
 
-    let results: Vec<u64> = exponents
-        .par_iter()
-        .filter_map(|&p| {
-            if verbose {
-                println!("Testing M({}) = 2^{} - 1", p, p);
+    let mut recorded = HashSet::new();
+    let results_file = match &options.results {
+        Some(path) => {
+            if !options.retest {
+                recorded = match results::recorded_exponents(path, options.form) {
+                    Ok(recorded) => recorded,
+                    Err(e) => {
+                        error!("cannot read {}: {}", path.display(), e);
+                        return EXIT_USAGE;
+                    }
+                };
             }
-            let exponent_start_time = Instant::now();
-            let is_prime_result = is_mersenne_prime(p, verbose);
-            let duration = exponent_start_time.elapsed();
+            match ResultsFile::open(path) {
+                Ok(file) => {
+                    let file = file.in_chunk(options.chunk).stamped(SystemInfo::current());
+                    Some(Mutex::new(file))
+                }
+                Err(e) => {
+                    error!("cannot open {}: {}", path.display(), e);
+                    return EXIT_USAGE;
+                }
+            }
+        }
+        None => None,
+    };
 
-            if is_prime_result {
-                println!(
-                    "Found Mersenne prime: M({}), tested in {:.2} seconds.",
-                    p,
-                    duration.as_secs_f64()
+    let database = match &options.db {
+        Some(path) => match Database::open(path) {
+            Ok(database) => Some(database),
+            Err(e) => {
+                error!("cannot use the database {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        None => None,
+    };
+    if let (Some(database), false) = (&database, options.retest) {
+        match database.recorded_exponents(options.form) {
+            Ok(stored) => recorded.extend(stored),
+            Err(e) => {
+                error!("cannot read the database: {}", e);
+                return EXIT_USAGE;
+            }
+        }
+    }
+    let count = selection.count();
+    let selection = plan::resolve(selection, options, &finished, &recorded);
+
+    let primenet_file = match &options.primenet_results {
+        Some(path) => match PrimeNetFile::open(path) {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                error!("cannot open {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        None => None,
+    };
+    let identity = Identity {
+        user: options.primenet_user.clone(),
+        computer: options.primenet_computer.clone(),
+    };
+
+    let checkpoints = match checkpoint_store(options, run_context) {
+        Ok(checkpoints) => checkpoints,
+        Err(status) => return status,
+    };
+    if let Some(store) = &checkpoints {
+        if let Err(status) = check_checkpoints(store, options, selection.bounds()) {
+            return status;
+        }
+    }
+    let audit_log = match audit_log(options, run_context) {
+        Ok(audit_log) => audit_log,
+        Err(status) => return status,
+    };
+    let source = match (&worktodo, &selection) {
+        (Some(_), _) => "worktodo",
+        (None, Selection::List(_)) => "list",
+        (None, Selection::Stream(_)) => "stdin",
+        (None, _) => "range",
+    };
+    let spreadsheet = match spreadsheet(options, source) {
+        Ok(spreadsheet) => spreadsheet,
+        Err(status) => return status,
+    };
+
+    let ll_threads = options
+        .ll_threads
+        .or(options.threads)
+        .unwrap_or_else(rayon::current_num_threads);
+
+    info!("Run {}.", run_context.id);
+    let chunk = options.chunk.map_or_else(String::new, |chunk| format!(", chunk {}", chunk));
+    match &selection {
+        Selection::Range(start_p, end_p) => {
+            match count {
+                Some(count) => info!(
+                    "Searching for {} primes among the next {} exponent(s) to test \
+                     from p = {}, up to p = {}{}...",
+                    options.form.title(),
+                    group(count),
+                    group(*start_p),
+                    group(*end_p),
+                    chunk
+                ),
+                None => info!(
+                    "Searching for {} primes in the range p = {} to p = {}{}...",
+                    options.form.title(),
+                    group(*start_p),
+                    group(*end_p),
+                    chunk
+                ),
+            }
+            if options.form == Form::Mersenne {
+                info!(
+                    "The range has {} known Mersenne prime(s); \
+                     the Wagstaff conjecture expects {:.2} Mersenne prime(s) in it.",
+                    known_between(*start_p, *end_p).len(),
+                    expected_mersenne_primes(*start_p, *end_p)
                 );
-                Some(p)
-            } else if verbose {
-                println!(
-                    "M({}) is composite, tested in {:.2} seconds.",
-                    p,
-                    duration.as_secs_f64()
+            }
+        }
+        Selection::Every(start, end) => match count {
+            Some(count) => info!(
+                "Testing the next {} number(s) to test from {}, up to {}{}...",
+                group(count),
+                options.form.number(*start),
+                options.form.number(*end),
+                chunk
+            ),
+            None => info!(
+                "Testing {} to {}{}...",
+                options.form.number(*start),
+                options.form.number(*end),
+                chunk
+            ),
+        },
+        Selection::List(exponents) => info!(
+            "Testing {} requested exponent(s){}: {}",
+            exponents.len(),
+            chunk,
+            exponents
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Selection::Ranges { ranges, .. } => {
+            let listed: Vec<String> = ranges
+                .iter()
+                .map(|range| format!("p = {} to p = {}", group(range.start), group(range.end)))
+                .collect();
+            info!(
+                "Searching for {} primes in {} ranges, {}{}...",
+                options.form.title(),
+                ranges.len(),
+                listed.join(", "),
+                chunk
+            );
+            if options.form == Form::Mersenne {
+                let known: usize =
+                    ranges.iter().map(|range| known_between(range.start, range.end).len()).sum();
+                let expected: f64 = ranges
+                    .iter()
+                    .map(|range| expected_mersenne_primes(range.start, range.end))
+                    .sum();
+                info!(
+                    "The ranges have {} known Mersenne prime(s); \
+                     the Wagstaff conjecture expects {:.2} Mersenne prime(s) in them.",
+                    known, expected
                 );
-                None
-            } else {
-                None
             }
+        }
+        Selection::Stream(_) => info!(
+            "Testing the {} exponents of standard input as they arrive{}...",
+            options.form.title(),
+            chunk
+        ),
+        Selection::Next { .. } => unreachable!("plan::resolve turns it into a range"),
+    }
+
+    let skip_known = |p: u64| options.skip_known && is_known_mersenne_exponent(p);
+    let known_skipped = MERSENNE_EXPONENTS
+        .iter()
+        .filter(|&&p| skip_known(p) && selection.contains(p))
+        .filter(|p| !recorded.contains(p) && !finished.contains(p))
+        .count();
+    if known_skipped > 0 {
+        info!(
+            "Skipping {} known Mersenne prime exponent(s) in range.",
+            known_skipped
+        );
+    }
+
+    // Filled in by the census once the tests are under way.
+    let eta = Eta::new(options.form, ll_threads).throttled_to(options.cpu_share().unwrap_or(1.0));
+
+    let start_time = Instant::now();
+
+    let display = ProgressDisplay::new(
+        log::log_enabled!(Level::Trace),
+        options.rate_window,
+        options.slowdown_warning,
+    );
+    let activity = Activity::new(options.form).under(&CANCEL);
+    let run_progress = RunProgress::new(&eta, &activity);
+    let (start_p, end_p) = selection.bounds();
+    events::emit(events::Event::RunStarted {
+        source,
+        form: options.form,
+        start: start_p,
+        end: end_p,
+        threads: ll_threads,
+    });
+    let mut summary = RunSummary::new(start_p, end_p);
+    let database = match database {
+        Some(database) => {
+            let run = NewRun {
+                run_id: run_context.id.clone(),
+                started: Utc::now(),
+                form: options.form,
+                start_exponent: start_p,
+                end_exponent: end_p,
+                host: system::host_name(),
+                system: SystemInfo::current().to_string(),
+                command: std::env::args().collect::<Vec<_>>().join(" "),
+            };
+            match database.start_run(&run) {
+                Ok(id) => Some((Mutex::new(database), id)),
+                Err(e) => {
+                    error!("cannot write to the database: {}", e);
+                    return EXIT_USAGE;
+                }
+            }
+        }
+        None => None,
+    };
+    // Exponents with a known factor go through a stage of their own, which
+    // records them as factored by it.
+    let candidates = plan(&selection, options, &finished, &recorded)
+        .filter(|&(_, disposition)| {
+            matches!(disposition, Disposition::Test | Disposition::KnownFactor)
+        })
+        .map(|(p, _)| p);
+    // Smallest first streams straight from the sieve; the other orders need
+    // every candidate up front.
+    let candidates: Box<dyn Iterator<Item = u64> + Send> = match options.order {
+        Order::Smallest => Box::new(candidates),
+        Order::Largest => {
+            let mut all: Vec<u64> = candidates.collect();
+            all.sort_unstable_by(|a, b| b.cmp(a));
+            Box::new(all.into_iter())
+        }
+        Order::Random => {
+            let mut all: Vec<u64> = candidates.collect();
+            all.shuffle(&mut rand::thread_rng());
+            Box::new(all.into_iter())
+        }
+    };
+
+    let notifier = Notifier::new(
+        options.form,
+        options.notify_cmd.clone(),
+        options.notify_url.clone(),
+    );
+    let largest = match selection {
+        Selection::Next { .. } | Selection::Stream(_) => None,
+        _ => Some(selection.bounds().1),
+    };
+    let budget = match (options.max_mem, largest, memory) {
+        (Some(mb), _, _) => Some(MemoryBudget::new(mb.saturating_mul(1 << 20))),
+        (None, Some(p), Some(available)) => {
+            let name = options.form.number(p);
+            admission::implicit_budget(&name, options.form.bits(p), ll_threads, available)
+        }
+        _ => None,
+    };
+    let record = |report: &TestReport| {
+        let p = report.exponent;
+        debug!("Finished {} in {}.", options.form.number(p), format_duration(report.seconds));
+        // The census leaves exponents with a known factor out of the tests
+        // the estimates are of.
+        if report.factor_stage != Some(FactoringStage::External) {
+            eta.record(report);
+            run_progress.record(report);
+        }
+        activity.record(report);
+        events::finished(report);
+        if let (Some(notifier), true) = (&notifier, report.prime) {
+            notifier.prime_found(report);
+        }
+        if let Some(results_file) = &results_file {
+            if let Err(e) = results_file.lock().unwrap().record(report) {
+                warn!("could not write to the results file: {}", e);
+            }
+        }
+        if let Some(spreadsheet) = &spreadsheet {
+            if let Err(e) = spreadsheet.lock().unwrap().record(report) {
+                warn!("could not write to the CSV file: {}", e);
+            }
+        }
+        if let Some(ledger) = &ledger {
+            if let Err(e) = ledger.lock().unwrap().record(report) {
+                warn!("could not write to the ledger: {}", e);
+            }
+        }
+        if let Some((database, run)) = &database {
+            if let Err(e) = database.lock().unwrap().record(Some(*run), report, Utc::now()) {
+                warn!("could not write to the database: {}", e);
+            }
+        }
+        if let Some(primenet_file) = &primenet_file {
+            let aid = worktodo.as_ref().and_then(|worktodo| {
+                worktodo.lock().unwrap().assignment_id(p).map(str::to_string)
+            });
+            match PrimeNetResult::from_report(report, &identity, aid.as_deref(), Utc::now()) {
+                Some(result) => {
+                    if let Err(e) = primenet_file.lock().unwrap().record(&result) {
+                        warn!("could not write to the PrimeNet results file: {}", e);
+                    }
+                }
+                None if report.is_mismatch() => warn!(
+                    "not writing a PrimeNet result for M({}): its double-check runs disagreed.",
+                    p
+                ),
+                None => {}
+            }
+        }
+        if let (Some(worktodo), true) = (&worktodo, report.has_result()) {
+            if let Err(e) = worktodo.lock().unwrap().complete(p) {
+                warn!("could not update the worktodo file: {}", e);
+            }
+        }
+    };
+    // Tags a report with the run and the --ranges range its exponent came
+    // from.
+    let tagged = |report: TestReport| TestReport {
+        range: selection.range_of(report.exponent),
+        run: Some(run_context.id.clone()),
+        ..report
+    };
+    let factoring = |factor: fn(u64, &Options, f64) -> Option<TestReport>| {
+        let (record, tagged) = (&record, &tagged);
+        move |candidate: Candidate| {
+            let (p, spent) = (candidate.p, candidate.seconds);
+            let report = tagged(match catch_panic(|| factor(p, options, spent)) {
+                Ok(Some(report)) => report,
+                Ok(None) => return Outcome::Passed(candidate),
+                Err(message) => panicked(p, options.form, message, spent),
+            });
+            record(&report);
+            Outcome::Finished(Some(Box::new(report)))
+        }
+    };
+    let known = factoring(known_factoring);
+    let trial_factor = factoring(trial_factoring);
+    let pminus1 = factoring(pminus1_factoring);
+    let test = |candidate: Candidate| -> Outcome {
+        let p = candidate.p;
+        // Held until the test is done; None once Ctrl-C was pressed.
+        let _admission = match &budget {
+            Some(budget) => match budget.admit(options.form, p, CANCEL.stop_flag()) {
+                Some(admission) => Some(admission),
+                None => return Outcome::Finished(None),
+            },
+            None => None,
+        };
+        let (checkpoints, audit_log) = (checkpoints.as_ref(), audit_log.as_ref());
+        let spent = candidate.seconds;
+        let tested = catch_panic(|| {
+            primality_test(p, options, checkpoints, audit_log, &display, &activity, spent)
         })
+        .unwrap_or_else(|message| Ok(panicked(p, options.form, message, spent)));
+        let report = match tested {
+            Ok(report) => report,
+            Err(interrupted) => {
+                let saved = if checkpoints.is_some() && !options.prp {
+                    "checkpoint saved"
+                } else {
+                    "progress not saved"
+                };
+                info!(
+                    "Interrupted at iteration {} of {} for p = {} ({}).",
+                    interrupted.iteration,
+                    interrupted.total,
+                    p,
+                    saved
+                );
+                return Outcome::Finished(None);
+            }
+        };
+        let report = tagged(report);
+        record(&report);
+        Outcome::Finished(Some(Box::new(report)))
+    };
+    let pinning = pinning(options);
+    // Looking up a factor takes no time to speak of, so one thread does.
+    let known_stage = options.known_factors.is_some().then(|| Stage::new("known factors", 1));
+    let trial_factoring_stage =
+        Stage::new("trial factoring", options.tf_threads).pinned(pinning.as_ref());
+    let pminus1_stage = (options.form == Form::Mersenne && options.p1_b1 > 0)
+        .then(|| Stage::new("P-1", options.tf_threads).pinned(pinning.as_ref()));
+    let mut test_stage = Stage::new("primality test", ll_threads);
+    if pin_tests(options, pinning.as_ref()) {
+        test_stage = test_stage.pinned(pinning.as_ref());
+    }
+    if let Some(pinning) = &pinning {
+        info!("Pinned threads: {}.", pinning.layout());
+    }
+    let stages: Vec<&Stage> = known_stage
+        .iter()
+        .chain([&trial_factoring_stage])
+        .chain(&pminus1_stage)
+        .chain([&test_stage])
         .collect();
 
-    let total_duration = start_time.elapsed();
+    // A stream can only be walked once, by the tests; they discover its
+    // exponents for the estimate as they take them.
+    let feed = match &selection {
+        Selection::Stream(feed) => Some(feed),
+        _ => None,
+    };
+    let counted = feed.is_none().then(|| plan(&selection, options, &finished, &recorded));
+    let (mut summary, mut reports, census) = std::thread::scope(|scope| {
+        let watching = watch_load(scope, options);
+        let controlled = options.control.is_some() || options.control_socket.is_some();
+        let (finished, wait) = mpsc::channel::<()>();
+        if options.status_interval > 0 || controlled {
+            let (eta, activity, budget, stages) = (&eta, &activity, budget.as_ref(), &stages);
+            let run_progress = &run_progress;
+            let interval = (options.status_interval > 0)
+                .then(|| Duration::from_secs(options.status_interval));
+            scope.spawn(move || {
+                let mut heartbeat = Heartbeat::new();
+                while next_update(&wait, interval) {
+                    info!("{}", heartbeat.line(eta, activity, run_progress, budget, stages));
+                }
+            });
+        }
+        let (overall_finished, tick) = mpsc::channel::<()>();
+        if display.has_overall() {
+            let (display, run_progress) = (&display, &run_progress);
+            scope.spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = tick.recv_timeout(OVERALL_INTERVAL) {
+                    display.overall(run_progress.percent());
+                }
+            });
+        }
+        let (limit_finished, limit_wait) = mpsc::channel::<()>();
+        if let Some(limit) = options.time_limit {
+            scope.spawn(move || {
+                let remaining = limit.saturating_sub(start_time.elapsed());
+                if let Err(RecvTimeoutError::Timeout) = limit_wait.recv_timeout(remaining) {
+                    TIME_UP.store(true, Ordering::SeqCst);
+                    CANCEL.cancel();
+                    info!(
+                        "Time limit of {} reached; stopping after saving running tests.",
+                        format_duration(limit.as_secs_f64())
+                    );
+                }
+            });
+        }
+        let (file_finished, file_wait) = mpsc::channel::<()>();
+        if let Some(path) = &options.status_file {
+            let (eta, activity, budget) = (&eta, &activity, budget.as_ref());
+            let run_progress = &run_progress;
+            let interval = Duration::from_secs(options.status_file_interval);
+            let bounds = selection.bounds();
+            scope.spawn(move || {
+                let update = || {
+                    let written = status::write_status_file(
+                        path,
+                        run_context,
+                        bounds,
+                        eta,
+                        activity,
+                        run_progress,
+                        budget,
+                    );
+                    if let Err(e) = written {
+                        warn!("could not write {}: {}", path.display(), e);
+                    }
+                };
+                update();
+                while next_update(&file_wait, Some(interval)) {
+                    update();
+                }
+                // Once more, so the file ends with the final state.
+                update();
+            });
+        }
+
+        let refresh = vec![finished.clone(), file_finished.clone()];
+        let controlling = watch_control(scope, options, refresh);
 
-    println!("\nMersenne primes found:");
-    for p in &results {
-        println!("M({}) is a Mersenne prime.", p);
+        // Results go to one output thread as the stages finish them, which
+        // prints them and keeps the tally for the summary. Stages send None
+        // for an interrupted test so that --ordered does not wait for it.
+        let (sender, receiver) = mpsc::channel::<(usize, Option<TestReport>)>();
+        let display = &display;
+        let output = scope.spawn(move || {
+            let mut reports = Vec::new();
+            let mut emit = |report: TestReport| {
+                display.suspend(|| print_report(&report, options));
+                summary.record(&report);
+                reports.push(report);
+            };
+            let mut held = BTreeMap::new();
+            let mut next = 0;
+            for (index, report) in receiver {
+                if !options.in_order() {
+                    report.map(&mut emit);
+                    continue;
+                }
+                held.insert(index, report);
+                while let Some(report) = held.remove(&next) {
+                    next += 1;
+                    report.map(&mut emit);
+                }
+            }
+            (summary, reports)
+        });
+
+        // Each stage takes its candidates in the order they reach it, so the
+        // tests start in --order order; with --max-mem each then waits for
+        // its turn at the budget. Once Ctrl-C is pressed no further
+        // candidates are taken, and the queued ones are dropped.
+        let stop = CANCEL.stop_flag();
+        let (test_queue, input) = test_stage.queue();
+        test_stage.spawn(scope, input, None, sender.clone(), stop, &test);
+        let factored = match &pminus1_stage {
+            Some(stage) => {
+                let (queue, input) = stage.queue();
+                stage.spawn(scope, input, Some(test_queue), sender.clone(), stop, &pminus1);
+                queue
+            }
+            None => test_queue,
+        };
+        let (queue, input) = trial_factoring_stage.queue();
+        let queue = match &known_stage {
+            Some(stage) => {
+                let (known_queue, known_input) = stage.queue();
+                let sender = sender.clone();
+                stage.spawn(scope, known_input, Some(queue), sender, stop, &known);
+                known_queue
+            }
+            None => queue,
+        };
+        trial_factoring_stage.spawn(scope, input, Some(factored), sender, stop, &trial_factor);
+
+        // Counting the candidates of a huge range takes a while, so it goes
+        // on beside the tests instead of holding them up; the candidates
+        // themselves reach the queue straight from the plan.
+        let eta = &eta;
+        let census = scope.spawn(move || {
+            let Some(counted) = counted else {
+                return Census::default();
+            };
+            let census = Census::take(counted, eta, stop);
+            if !census.complete {
+                return census;
+            }
+            if census.in_ledger > 0 {
+                let total = census.in_ledger + census.to_test + census.in_results;
+                info!("Resuming: {} of {} candidates already done.", census.in_ledger, total);
+            }
+            if eta.total() > 1 {
+                eta.calibrate();
+                if let Some(seconds) = eta.benchmark_seconds() {
+                    info!(
+                        "Estimated time for {} exponents: {}, finishing around {}.",
+                        eta.total(),
+                        format_duration(seconds),
+                        eta::finish_time(seconds)
+                    );
+                }
+            }
+            census
+        });
+        for (index, p) in candidates.take_while(|_| !CANCEL.is_cancelled()).enumerate() {
+            if feed.is_some() {
+                eta.discover(p);
+            }
+            if (index as u64).is_multiple_of(events::CANDIDATE_SAMPLE) {
+                let index = index as u64;
+                events::emit(events::Event::CandidateGenerated { exponent: p, index });
+            }
+            // Only fails once a stage's threads have died of a panic.
+            if !queue.send(Candidate { index, p, seconds: 0.0 }) {
+                break;
+            }
+        }
+        if feed.is_some() {
+            eta.discovered();
+        }
+        drop(queue);
+        let (summary, reports) = output.join().unwrap();
+        let census = census.join().unwrap();
+        drop(finished);
+        drop(file_finished);
+        drop(overall_finished);
+        drop(limit_finished);
+        drop(watching);
+        drop(controlling);
+        (summary, reports, census)
+    });
+    // Failed tests get their retries one at a time, with the machine to
+    // themselves, in case they failed for want of memory.
+    for attempt in 1..=options.retries {
+        let failed: Vec<usize> = (0..reports.len()).filter(|&i| reports[i].is_failed()).collect();
+        if failed.is_empty() || CANCEL.is_cancelled() {
+            break;
+        }
+        info!(
+            "Retrying {} failed test(s), attempt {} of {}.",
+            failed.len(),
+            attempt,
+            options.retries
+        );
+        for index in failed {
+            let p = reports[index].exponent;
+            run_progress.requeue(&reports[index]);
+            let (checkpoints, audit_log) = (checkpoints.as_ref(), audit_log.as_ref());
+            let tested = catch_panic(|| {
+                test_exponent(p, options, checkpoints, audit_log, &display, &activity)
+            });
+            let report = tagged(match tested {
+                Ok(Ok(report)) => report,
+                // Stopped by Ctrl-C or the time limit; the failure stands.
+                Ok(Err(_)) => break,
+                Err(message) => panicked(p, options.form, message, 0.0),
+            });
+            record(&report);
+            display.suspend(|| print_report(&report, options));
+            reports[index] = report;
+        }
+        let bounds = (summary.start_exponent, summary.end_exponent);
+        summary = RunSummary::from_reports(bounds.0, bounds.1, &reports, 0.0);
     }
+    // The summary lists the primes, and the table the reports, by exponent.
+    summary.primes.sort_unstable();
+    reports.sort_by_key(|report| report.exponent);
 
-    println!(
-        "\nTotal time taken: {:.2} seconds",
-        total_duration.as_secs_f64()
-    );
+    summary.seconds = start_time.elapsed().as_secs_f64();
+    // Every candidate the stages take ends up in `tested`.
+    summary.candidates = census.complete.then_some(census.to_test + census.known_factors);
+    if feed.is_some() {
+        summary.start_exponent = reports.first().map_or(0, |report| report.exponent);
+        summary.end_exponent = reports.last().map_or(0, |report| report.exponent);
+    }
+    if census.in_results > 0 {
+        let stores: Vec<String> = [&options.results, &options.db]
+            .into_iter()
+            .flatten()
+            .map(|path| path.display().to_string())
+            .collect();
+        info!(
+            "Skipped {} exponent(s) already in {} (use --retest to test them again).",
+            census.in_results,
+            stores.join(" or ")
+        );
+    }
+
+    let stage_summaries: Vec<StageSummary> = stages.iter().map(|stage| stage.summary()).collect();
+    let interrupted = CANCEL.is_cancelled();
+    if let Some((database, run)) = &database {
+        let outcome = if TIME_UP.load(Ordering::SeqCst) {
+            "time limit"
+        } else if interrupted {
+            "interrupted"
+        } else {
+            "done"
+        };
+        if let Err(e) = database.lock().unwrap().end_run(*run, Utc::now(), outcome) {
+            warn!("could not write to the database: {}", e);
+        }
+    }
+    if TIME_UP.load(Ordering::SeqCst) && eta.is_discovering() {
+        info!(
+            "Stopped at the time limit with {} exponents done; run the same command again to continue.",
+            eta.progress().0
+        );
+    } else if TIME_UP.load(Ordering::SeqCst) {
+        let (done, total) = eta.progress();
+        let work = run_progress
+            .percent()
+            .map_or_else(String::new, |percent| format!(" ({:.1}% of the work)", percent));
+        info!(
+            "Stopped at the time limit with {} of {} exponents done{}; run the same command again to continue.",
+            done,
+            total,
+            work
+        );
+    }
+    let range_summaries = range_summaries(&selection, &reports);
+    if !options.summary() {
+        // Only the per-exponent lines were asked for.
+    } else if options.json {
+        outln!(
+            "{}",
+            serde_json::to_string(&SummaryLine {
+                summary: &summary,
+                stages: &stage_summaries,
+                ranges: &range_summaries,
+                system: SystemInfo::current(),
+            })
+            .unwrap()
+        );
+    } else if let (true, [report]) = (options.single, &reports[..]) {
+        single::print_report(report, options);
+    } else {
+        let filtered = match &selection {
+            Selection::Range(start, end) if census.complete => {
+                Some((end - start).saturating_add(1) - census.generated)
+            }
+            Selection::Ranges { ranges, .. } if census.complete => {
+                let covered: u64 = ranges.iter().map(|range| range.end - range.start + 1).sum();
+                Some(covered - census.generated)
+            }
+            _ => None,
+        };
+        // Every run that kept its results where this one did counts towards
+        // the lifetime total, this one included.
+        let lifetime_work = match (&database, &ledger) {
+            (Some((database, _)), _) => database.lock().unwrap().work_units().ok(),
+            (None, Some(ledger)) => Some(ledger.lock().unwrap().work_units()),
+            (None, None) => None,
+        };
+        let context = summary::SummaryContext {
+            filtered,
+            known_skipped: options.skip_known.then_some(known_skipped),
+            lifetime_work,
+            threads: stage_summaries.iter().map(|stage| stage.threads).sum(),
+            form: options.form,
+            prp: options.prp,
+            stages: stage_summaries,
+            ranges: range_summaries,
+            top: options.summary_top,
+        };
+        summary::print_summary(&summary, &mut reports, &context);
+    }
+    if !interrupted {
+        write_numbers(&summary.primes, options);
+    }
+    if let Some(notifier) = notifier {
+        notifier.run_finished(&summary, interrupted);
+    }
+    events::run_finished(&summary, interrupted, TIME_UP.load(Ordering::SeqCst));
+
+    if reports.iter().any(TestReport::is_conflict) {
+        EXIT_CONFIRM_CONFLICT
+    } else if feed.is_some_and(|feed| feed.failed.load(Ordering::SeqCst)) {
+        EXIT_USAGE
+    } else if TIME_UP.load(Ordering::SeqCst) {
+        EXIT_TIME_LIMIT
+    } else if interrupted {
+        EXIT_INTERRUPTED
+    } else if !summary.failed.is_empty() {
+        EXIT_INTERNAL_ERROR
+    } else if summary.primes.is_empty() {
+        EXIT_NONE_FOUND
+    } else {
+        EXIT_SUCCESS
+    }
 }
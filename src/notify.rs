@@ -0,0 +1,228 @@
+//! `--notify-cmd` and `--notify-url`: telling someone when a prime turns
+//! up, and when the run ends, instead of leaving it for them to find in
+//! the log.
+//!
+//! A notification that fails is logged as a warning and otherwise ignored;
+//! it never changes a result or stops the run. Prime notifications are
+//! sent in the background so the test thread carries straight on, and the
+//! end-of-run notification waits for them so that it comes last.
+
+use crate::progress::format_duration;
+use log::{debug, warn};
+use mersenne::known::is_known_mersenne_exponent;
+use mersenne::report::{Form, RunSummary, TestKind, TestReport};
+use serde::Serialize;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long a `--notify-cmd` command may run before it is killed, and a
+/// `--notify-url` request may take.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What is sent to `--notify-url`. `text` and `content` hold the same
+/// message, so Slack and Discord webhooks take the payload as it is.
+#[derive(Debug, Clone, Serialize)]
+struct Payload {
+    /// `prime`, `finished` or `stopped`.
+    event: &'static str,
+    text: String,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exponent: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digits: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test: Option<TestKind>,
+    /// Seconds the test, or the whole run, took.
+    seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<RunSummary>,
+}
+
+impl Payload {
+    /// The environment `--notify-cmd` runs with.
+    fn environment(&self) -> Vec<(&'static str, String)> {
+        let mut environment = vec![
+            ("MERSENNE_EVENT", self.event.to_string()),
+            ("MERSENNE_MESSAGE", self.text.clone()),
+            ("MERSENNE_ELAPSED", format!("{:.2}", self.seconds)),
+            ("MERSENNE_JSON", serde_json::to_string(self).unwrap()),
+        ];
+        if let Some(p) = self.exponent {
+            environment.push(("MERSENNE_EXPONENT", p.to_string()));
+        }
+        if let Some(digits) = self.digits {
+            environment.push(("MERSENNE_DIGITS", digits.to_string()));
+        }
+        if let Some(test) = self.test {
+            environment.push(("MERSENNE_TEST", test.as_str().to_string()));
+        }
+        if let Some(summary) = &self.summary {
+            environment.push(("MERSENNE_TESTED", summary.tested.to_string()));
+            let primes: Vec<String> = summary.primes.iter().map(u64::to_string).collect();
+            environment.push(("MERSENNE_PRIMES", primes.join(",")));
+        }
+        environment
+    }
+}
+
+/// The command and webhook to notify.
+pub struct Notifier {
+    /// The form of the numbers the run tests.
+    form: Form,
+    command: Option<String>,
+    url: Option<String>,
+    /// Prime notifications still being sent.
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Notifier {
+    /// A notifier for `command` and `url`, or `None` if neither is set.
+    pub fn new(form: Form, command: Option<String>, url: Option<String>) -> Option<Notifier> {
+        if command.is_none() && url.is_none() {
+            return None;
+        }
+        Some(Notifier {
+            form,
+            command,
+            url,
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Notifies, in the background, that `report` found a prime.
+    pub fn prime_found(&self, report: &TestReport) {
+        let (p, form) = (report.exponent, report.form);
+        let digits = form.digit_count(p);
+        let kind = if report.test == Some(TestKind::Prp) {
+            "probable prime"
+        } else {
+            "prime"
+        };
+        let known = if form == Form::Mersenne && is_known_mersenne_exponent(p) {
+            ", already known"
+        } else {
+            ""
+        };
+        let text = format!(
+            "{} = {} is a {} {} ({} digits{}), tested in {}.",
+            form.number(p),
+            form.formula(p),
+            form.title(),
+            kind,
+            digits,
+            known,
+            format_duration(report.seconds)
+        );
+        let payload = Payload {
+            event: "prime",
+            content: text.clone(),
+            text,
+            exponent: Some(p),
+            digits: Some(digits),
+            test: report.test,
+            seconds: report.seconds,
+            summary: None,
+        };
+        let (command, url) = (self.command.clone(), self.url.clone());
+        let sending =
+            std::thread::spawn(move || send(command.as_deref(), url.as_deref(), &payload));
+        self.pending.lock().unwrap().push(sending);
+    }
+
+    /// Waits for earlier notifications, then notifies that the run ended
+    /// with `summary`, `stopped` early or not.
+    pub fn run_finished(self, summary: &RunSummary, stopped: bool) {
+        for sending in self.pending.into_inner().unwrap() {
+            let _ = sending.join();
+        }
+        let mut summary = summary.clone();
+        summary.primes.sort_unstable();
+        let title = self.form.title();
+        let primes = if summary.primes.is_empty() {
+            format!("no {} primes", title)
+        } else {
+            let primes: Vec<String> = summary
+                .primes
+                .iter()
+                .map(|&p| self.form.number(p))
+                .collect();
+            format!("{} primes {}", title, primes.join(", "))
+        };
+        let text = format!(
+            "{} p = {} to p = {} after {}: {} exponent(s) tested, {}.",
+            if stopped { "Stopped" } else { "Finished" },
+            summary.start_exponent,
+            summary.end_exponent,
+            format_duration(summary.seconds),
+            summary.tested,
+            primes
+        );
+        let payload = Payload {
+            event: if stopped { "stopped" } else { "finished" },
+            content: text.clone(),
+            text,
+            exponent: None,
+            digits: None,
+            test: None,
+            seconds: summary.seconds,
+            summary: Some(summary),
+        };
+        send(self.command.as_deref(), self.url.as_deref(), &payload);
+    }
+}
+
+/// Runs `command` and posts to `url`, logging rather than returning any
+/// failure.
+fn send(command: Option<&str>, url: Option<&str>, payload: &Payload) {
+    if let Some(command) = command {
+        match run_command(command, payload) {
+            Ok(()) => debug!("Ran --notify-cmd for the {} event.", payload.event),
+            Err(e) => warn!("--notify-cmd failed: {}", e),
+        }
+    }
+    if let Some(url) = url {
+        let posted = ureq::post(url).timeout(NOTIFY_TIMEOUT).send_json(payload);
+        match posted {
+            Ok(_) => debug!("Posted the {} event to --notify-url.", payload.event),
+            Err(e) => warn!("could not post to --notify-url: {}", e),
+        }
+    }
+}
+
+fn run_command(command: &str, payload: &Payload) -> Result<(), String> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .envs(payload.environment())
+        .stdin(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run {:?}: {}", command, e))?;
+    let deadline = Instant::now() + NOTIFY_TIMEOUT;
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("{:?} exited with {}", command, status)),
+            None if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "{:?} was killed after running for {}",
+                    command,
+                    format_duration(NOTIFY_TIMEOUT.as_secs_f64())
+                ));
+            }
+            None => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
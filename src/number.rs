@@ -0,0 +1,193 @@
+//! Sizes and decimal expansions of Mersenne numbers and the perfect numbers
+//! they give rise to, and of Wagstaff, Fermat and Riesel numbers.
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+/// `log10(2)` as a 0.64 fixed-point fraction.
+const LOG10_2_FIXED: u128 = 5553023288523357132;
+
+/// `log10(3)` as a 0.64 fixed-point fraction.
+const LOG10_3_FIXED: u128 = 8801333677940798499;
+
+/// Number of decimal digits of `M(p) = 2^p - 1`, without computing it.
+///
+/// `2^p` is never a power of ten, so `M(p)` has the same number of digits
+/// as `2^p`, which is `floor(p · log10(2)) + 1`.
+pub fn digit_count(p: u64) -> u64 {
+    ((p as u128 * LOG10_2_FIXED) >> 64) as u64 + 1
+}
+
+/// The Mersenne number `2^p - 1`.
+pub fn mersenne_number(p: u64) -> BigUint {
+    (BigUint::one() << p) - 1u32
+}
+
+/// Number of decimal digits of `W(p) = (2^p + 1) / 3`, for `p >= 2`,
+/// without computing it.
+///
+/// `W(p)` is never a power of ten, so it has `floor(log10(2^p / 3)) + 1`
+/// digits, which is `floor(p · log10(2) - log10(3)) + 1`.
+pub fn wagstaff_digit_count(p: u64) -> u64 {
+    ((p as u128 * LOG10_2_FIXED - LOG10_3_FIXED) >> 64) as u64 + 1
+}
+
+/// The Wagstaff number `(2^p + 1) / 3`, for odd `p`.
+pub fn wagstaff_number(p: u64) -> BigUint {
+    ((BigUint::one() << p) + 1u32) / 3u32
+}
+
+/// Number of decimal digits of the Fermat number `F(n) = 2^(2^n) + 1`, for
+/// `n < 64`, without computing it.
+///
+/// `2^(2^n) + 1` is never a power of ten either, so it has as many digits
+/// as `2^(2^n)`.
+pub fn fermat_digit_count(n: u64) -> u64 {
+    digit_count(1 << n)
+}
+
+/// The Fermat number `2^(2^n) + 1`.
+pub fn fermat_number(n: u64) -> BigUint {
+    (BigUint::one() << (1u64 << n)) + 1u32
+}
+
+/// Number of decimal digits of the Riesel number `k·2^n - 1`.
+///
+/// Unlike the other forms this is counted on the number itself, since
+/// `k·2^n` can be a power of ten, as `5·2^1` is.
+pub fn riesel_digit_count(k: u64, n: u64) -> u64 {
+    decimal_digits(&riesel_number(k, n))
+}
+
+/// The Riesel number `k·2^n - 1`.
+pub fn riesel_number(k: u64, n: u64) -> BigUint {
+    (BigUint::from(k) << n) - 1u32
+}
+
+/// The even perfect number `2^(p-1) · (2^p - 1)`, which is perfect exactly
+/// when `M(p)` is prime.
+pub fn perfect_number(p: u64) -> BigUint {
+    mersenne_number(p) << p.saturating_sub(1)
+}
+
+/// Number of decimal digits of `n`, without converting it to decimal.
+pub fn decimal_digits(n: &BigUint) -> u64 {
+    if n.is_zero() {
+        return 1;
+    }
+    // 2^(bits-1) <= n < 2^bits, so n has as many digits as 2^bits - 1 or
+    // one fewer.
+    let upper = digit_count(n.bits());
+    if *n >= BigUint::from(10u32).pow((upper - 1) as u32) {
+        upper
+    } else {
+        upper - 1
+    }
+}
+
+/// Breaks `digits` into lines of at most `width` characters, each ending
+/// in a newline.
+pub fn wrap(digits: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(digits.len() + digits.len() / width + 1);
+    for line in digits.as_bytes().chunks(width) {
+        // Decimal digits are ASCII, so any byte boundary is a char boundary.
+        wrapped.push_str(std::str::from_utf8(line).unwrap());
+        wrapped.push('\n');
+    }
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digit_count_matches_decimal_expansion() {
+        for p in 1..2000 {
+            assert_eq!(
+                digit_count(p),
+                mersenne_number(p).to_string().len() as u64,
+                "M({})",
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn digit_count_of_large_known_primes() {
+        assert_eq!(digit_count(4423), 1332);
+        assert_eq!(digit_count(82589933), 24862048);
+        assert_eq!(digit_count(136279841), 41024320);
+    }
+
+    #[test]
+    fn wagstaff_digit_count_matches_decimal_expansion() {
+        for p in (3..2000).step_by(2) {
+            assert_eq!(
+                wagstaff_digit_count(p),
+                wagstaff_number(p).to_string().len() as u64,
+                "W({})",
+                p
+            );
+        }
+        assert_eq!(wagstaff_number(5).to_string(), "11");
+        assert_eq!(wagstaff_number(7).to_string(), "43");
+    }
+
+    #[test]
+    fn fermat_digit_count_matches_decimal_expansion() {
+        for n in 0..14 {
+            assert_eq!(
+                fermat_digit_count(n),
+                fermat_number(n).to_string().len() as u64,
+                "F({})",
+                n
+            );
+        }
+        assert_eq!(fermat_number(4).to_string(), "65537");
+    }
+
+    #[test]
+    fn riesel_digit_count_matches_decimal_expansion() {
+        assert_eq!(riesel_digit_count(5, 1), 1);
+        assert_eq!(riesel_digit_count(125, 3), 3);
+        for (k, n) in [(3, 2), (15, 229), (1, 127), (9, 40)] {
+            assert_eq!(
+                riesel_digit_count(k, n),
+                riesel_number(k, n).to_string().len() as u64,
+                "{}·2^{} - 1",
+                k,
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn small_perfect_numbers() {
+        let perfect: Vec<String> = [2, 3, 5, 7]
+            .into_iter()
+            .map(|p| perfect_number(p).to_string())
+            .collect();
+        assert_eq!(perfect, ["6", "28", "496", "8128"]);
+        assert_eq!(perfect_number(13).to_string(), "33550336");
+    }
+
+    #[test]
+    fn decimal_digits_matches_expansion() {
+        for n in [0u64, 1, 9, 10, 99, 100, 999_999, 1_000_000, u64::MAX] {
+            let n = BigUint::from(n);
+            assert_eq!(decimal_digits(&n), n.to_string().len() as u64, "{}", n);
+        }
+        for p in [2, 3, 5, 7, 13, 31, 61, 89, 107, 127, 521, 607] {
+            let n = perfect_number(p);
+            assert_eq!(decimal_digits(&n), n.to_string().len() as u64, "P({})", p);
+        }
+    }
+
+    #[test]
+    fn wraps_at_width() {
+        assert_eq!(wrap("12345", 2), "12\n34\n5\n");
+        assert_eq!(wrap("1234", 2), "12\n34\n");
+        assert_eq!(wrap(&mersenne_number(7).to_string(), 80), "127\n");
+    }
+}
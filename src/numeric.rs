@@ -0,0 +1,341 @@
+//! Number-theoretic functions that num-bigint lacks: the Jacobi symbol of
+//! big integers, and the strong Lucas test and Pollard's rho on 64-bit
+//! ones, which [`check_exponent`](crate::primality::check_exponent) uses.
+
+use crate::primality::{is_prime, mul_mod};
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use std::mem;
+
+/// The Jacobi symbol `(a | n)`: 1, -1, or 0 if `a` and `n` share a factor.
+///
+/// # Panics
+///
+/// If `n` is even.
+pub fn jacobi(a: &BigUint, n: &BigUint) -> i8 {
+    assert!(n.bit(0), "the Jacobi symbol needs an odd modulus");
+    let mut a = a % n;
+    let mut n = n.clone();
+    let mut result = 1;
+
+    while !a.is_zero() {
+        // (2 | n) is -1 exactly when n ≡ 3 or 5 (mod 8).
+        let twos = a.trailing_zeros().unwrap_or(0);
+        if twos % 2 == 1 && matches!(low_bits(&n) % 8, 3 | 5) {
+            result = -result;
+        }
+        a >>= twos;
+
+        // Quadratic reciprocity, for odd a and n.
+        mem::swap(&mut a, &mut n);
+        if low_bits(&a) % 4 == 3 && low_bits(&n) % 4 == 3 {
+            result = -result;
+        }
+        a %= &n;
+    }
+
+    if n.is_one() {
+        result
+    } else {
+        0
+    }
+}
+
+fn low_bits(n: &BigUint) -> u64 {
+    n.iter_u64_digits().next().unwrap_or(0)
+}
+
+/// The Jacobi symbol `(a | n)` of 64-bit integers, for odd `n`.
+fn jacobi_small(mut a: u64, mut n: u64) -> i8 {
+    a %= n;
+    let mut result = 1;
+    while a != 0 {
+        let twos = a.trailing_zeros();
+        if twos % 2 == 1 && matches!(n % 8, 3 | 5) {
+            result = -result;
+        }
+        a >>= twos;
+        mem::swap(&mut a, &mut n);
+        if a % 4 == 3 && n % 4 == 3 {
+            result = -result;
+        }
+        a %= n;
+    }
+    if n == 1 {
+        result
+    } else {
+        0
+    }
+}
+
+/// Half of `x` modulo odd `n`, for `x < n`.
+fn halve_mod(x: u64, n: u64) -> u64 {
+    if x.is_multiple_of(2) {
+        x / 2
+    } else {
+        ((u128::from(x) + u128::from(n)) / 2) as u64
+    }
+}
+
+/// `a - b mod n`, for `a, b < n`.
+fn sub_mod(a: u64, b: u64, n: u64) -> u64 {
+    if a >= b {
+        a - b
+    } else {
+        n - (b - a)
+    }
+}
+
+/// `a + b mod n`, for `a, b < n`.
+fn add_mod(a: u64, b: u64, n: u64) -> u64 {
+    ((u128::from(a) + u128::from(b)) % u128::from(n)) as u64
+}
+
+/// Whether `n` is a strong Lucas probable prime, with the parameters of
+/// Selfridge's method A: `D` the first of 5, -7, 9, -11, ... with `(D | n)
+/// = -1`, `P = 1` and `Q = (1 - D) / 4`. Writing `n + 1 = d·2^s` with `d`
+/// odd, that is `U(d) ≡ 0` or `V(d·2^r) ≡ 0` for some `r < s`.
+///
+/// Every prime passes; the composites that do are the strong Lucas
+/// pseudoprimes, 5459, 5777, 10877, ..., none of which is a strong
+/// probable prime to base 2 up to `2^64` at least. Perfect squares have no
+/// such `D` and are rejected outright.
+pub fn is_strong_lucas_probable_prime(n: u64) -> bool {
+    if n < 3 || n.is_multiple_of(2) {
+        return n == 2;
+    }
+    if n.isqrt().pow(2) == n {
+        return false;
+    }
+    let mut d: i64 = 5;
+    loop {
+        let a = if d > 0 {
+            d as u64 % n
+        } else {
+            sub_mod(0, d.unsigned_abs() % n, n)
+        };
+        match jacobi_small(a, n) {
+            -1 => break,
+            0 if d.unsigned_abs() != n => return false,
+            0 => return true,
+            _ => d = if d > 0 { -(d + 2) } else { -d + 2 },
+        }
+    }
+    let big_d = if d > 0 {
+        d as u64 % n
+    } else {
+        sub_mod(0, d.unsigned_abs() % n, n)
+    };
+    // Q = (1 - D) / 4, as a residue.
+    let q = if d > 0 {
+        sub_mod(0, ((d - 1) / 4) as u64 % n, n)
+    } else {
+        ((1 - d) / 4) as u64 % n
+    };
+
+    let s = (n + 1).trailing_zeros();
+    let odd = (n + 1) >> s;
+    // U(k), V(k) and Q^k from k = 1, by the bits of `odd` after the first.
+    let (mut u, mut v, mut q_k) = (1, 1, q);
+    for bit in (0..odd.ilog2()).rev() {
+        // k -> 2k.
+        u = mul_mod(u, v, n);
+        v = sub_mod(mul_mod(v, v, n), add_mod(q_k, q_k, n), n);
+        q_k = mul_mod(q_k, q_k, n);
+        if odd >> bit & 1 == 1 {
+            // k -> k + 1, with P = 1.
+            (u, v) = (
+                halve_mod(add_mod(u, v, n), n),
+                halve_mod(add_mod(mul_mod(big_d, u, n), v, n), n),
+            );
+            q_k = mul_mod(q_k, q, n);
+        }
+    }
+    if u == 0 || v == 0 {
+        return true;
+    }
+    for _ in 1..s {
+        v = sub_mod(mul_mod(v, v, n), add_mod(q_k, q_k, n), n);
+        q_k = mul_mod(q_k, q_k, n);
+        if v == 0 {
+            return true;
+        }
+    }
+    false
+}
+
+/// How many steps of Pollard's rho go into the product before each gcd.
+const RHO_BATCH: u64 = 128;
+
+/// A factor of `n` other than 1 and `n`, found by Brent's variant of
+/// Pollard's rho with `x^2 + c` for `c = 1, 2, ...`; `None` if `n` is 1 or
+/// prime, or if none of the first few `c` turns one up. The factor need
+/// not be prime. Expect about `n^(1/4)` steps, a few thousand for
+/// semiprimes near `2^50`.
+pub fn pollard_rho(n: u64) -> Option<u64> {
+    if n < 4 || is_prime(n) {
+        return None;
+    }
+    if n.is_multiple_of(2) {
+        return Some(2);
+    }
+    (1..=20).find_map(|c| {
+        let step = |x: u64| add_mod(mul_mod(x, x, n), c, n);
+        let (mut y, mut r, mut product, mut g) = (2, 1, 1, 1);
+        let (mut x, mut saved) = (y, y);
+        while g == 1 {
+            x = y;
+            for _ in 0..r {
+                y = step(y);
+            }
+            let mut k = 0;
+            while k < r && g == 1 {
+                saved = y;
+                for _ in 0..RHO_BATCH.min(r - k) {
+                    y = step(y);
+                    product = mul_mod(product, x.abs_diff(y), n);
+                }
+                g = product.gcd(&n);
+                k += RHO_BATCH;
+            }
+            r *= 2;
+        }
+        if g == n {
+            // The batch overshot; retrace it one step at a time.
+            loop {
+                saved = step(saved);
+                g = x.abs_diff(saved).gcd(&n);
+                if g > 1 {
+                    break;
+                }
+            }
+        }
+        (g != n).then_some(g)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jacobi_u64(a: u64, n: u64) -> i8 {
+        jacobi(&BigUint::from(a), &BigUint::from(n))
+    }
+
+    /// Euler's criterion, for prime `n`.
+    fn legendre(a: u64, n: u64) -> i8 {
+        match BigUint::from(a).modpow(&BigUint::from((n - 1) / 2), &BigUint::from(n)) {
+            r if r.is_zero() => 0,
+            r if r.is_one() => 1,
+            _ => -1,
+        }
+    }
+
+    #[test]
+    fn known_values() {
+        // From the table of Jacobi symbols in the literature.
+        assert_eq!(jacobi_u64(1, 1), 1);
+        assert_eq!(jacobi_u64(2, 15), 1);
+        assert_eq!(jacobi_u64(7, 15), -1);
+        assert_eq!(jacobi_u64(5, 15), 0);
+        assert_eq!(jacobi_u64(19, 45), 1);
+        assert_eq!(jacobi_u64(8, 21), -1);
+        assert_eq!(jacobi_u64(1001, 9907), -1);
+        assert_eq!(jacobi_u64(0, 7), 0);
+    }
+
+    #[test]
+    fn matches_euler_criterion_for_primes() {
+        for n in [3u64, 5, 7, 11, 13, 101, 127, 8191, 131071] {
+            for a in 0..200 {
+                assert_eq!(jacobi_u64(a, n), legendre(a, n), "({} | {})", a, n);
+            }
+        }
+    }
+
+    #[test]
+    fn is_multiplicative_in_the_modulus() {
+        for a in 0..100 {
+            assert_eq!(
+                jacobi_u64(a, 3 * 5 * 7),
+                jacobi_u64(a, 3) * jacobi_u64(a, 5) * jacobi_u64(a, 7),
+                "a = {}",
+                a
+            );
+        }
+    }
+
+    #[test]
+    fn jacobi_of_u64_matches_big_integers() {
+        for n in (1..300).step_by(2) {
+            for a in 0..300 {
+                assert_eq!(jacobi_small(a, n), jacobi_u64(a, n), "({} | {})", a, n);
+            }
+        }
+    }
+
+    #[test]
+    fn strong_lucas_pseudoprimes_are_the_only_composites_passing() {
+        let passing: Vec<u64> = (3..100_000)
+            .filter(|&n| is_strong_lucas_probable_prime(n) && !is_prime(n))
+            .collect();
+        // OEIS A217255.
+        assert_eq!(
+            passing,
+            [5459, 5777, 10877, 16109, 18971, 22499, 24569, 25199, 40309, 58519, 75077, 97439]
+        );
+        for n in (3..100_000).filter(|&n| is_prime(n)) {
+            assert!(is_strong_lucas_probable_prime(n), "{} is prime", n);
+        }
+        assert!(is_strong_lucas_probable_prime(2));
+        assert!(!is_strong_lucas_probable_prime(4));
+    }
+
+    #[test]
+    fn strong_lucas_test_handles_carmichael_numbers_and_large_values() {
+        for n in [
+            561, 1105, 1729, 2465, 2821, 6601, 8911, 41041, 825265, 321197185,
+        ] {
+            assert!(!is_strong_lucas_probable_prime(n), "{} is composite", n);
+        }
+        // Strong pseudoprimes to every base up to 37 pass Miller–Rabin
+        // with those bases, but not this.
+        assert!(!is_strong_lucas_probable_prime(3825123056546413051));
+        for n in [18446744073709551557, (1 << 61) - 1, 1_000_000_007] {
+            assert!(is_strong_lucas_probable_prime(n), "{} is prime", n);
+        }
+        assert!(!is_strong_lucas_probable_prime(18446744030759878681)); // (2^32 - 5)^2
+        assert!(!is_strong_lucas_probable_prime(u64::MAX));
+    }
+
+    #[test]
+    fn pollard_rho_splits_composites() {
+        for n in [
+            15,
+            561,
+            8911,
+            4294967297,                  // F5 = 641 × 6700417
+            1_000_000_007 * 998_244_353, // two 30-bit primes
+            18446743979220271189,        // (2^32 - 5)(2^32 - 17)
+            18446744030759878681,        // (2^32 - 5)^2
+        ] {
+            let factor = pollard_rho(n).unwrap_or_else(|| panic!("no factor of {}", n));
+            assert!(
+                factor > 1 && factor < n && n % factor == 0,
+                "{} of {}",
+                factor,
+                n
+            );
+        }
+        for n in [0, 1, 2, 3, 13, 1_000_000_007, 18446744073709551557] {
+            assert_eq!(pollard_rho(n), None, "{}", n);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_even_modulus() {
+        jacobi_u64(3, 10);
+    }
+}
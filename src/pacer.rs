@@ -0,0 +1,240 @@
+//! Doing something in a test's loop about every so long, rather than every
+//! so many iterations.
+//!
+//! An iteration of `M(1279)` takes microseconds and one of `M(80000000)`
+//! tens of milliseconds, so no one number of iterations between
+//! checkpoints or progress reports suits both. A [`Pacer`] is given a
+//! target time instead. It measures how fast the loop is going and turns
+//! the target into a stride: the iterations between reads of the clock,
+//! about an eighth of the target apart, so that it is due within an eighth
+//! of the target of it passing. It measures again at each read, so the
+//! stride follows the loop as it speeds up or slows down.
+//!
+//! At first the stride is one iteration, and it at most doubles at each
+//! read; so for the first few reads the clock is read often while the speed
+//! is worked out, and a loop that speeds up is followed a little at a time,
+//! while one that slows down is followed at once.
+
+use std::time::Duration;
+
+/// The reads of the clock per target, once the stride is calibrated.
+const READS_PER_TARGET: u32 = 8;
+
+/// Due about every `target` of time, counting the iterations given to
+/// [`tick`](Self::tick). See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    target: Duration,
+    /// Iterations between reads of the clock.
+    stride: u64,
+    /// Iterations until the next read.
+    left: u64,
+    /// When the clock was last read.
+    read_at: Duration,
+    /// When it was last due.
+    due_at: Duration,
+}
+
+impl Pacer {
+    /// A pacer for `target`, starting at `now` by the clock that is passed
+    /// to [`tick`](Self::tick).
+    pub fn new(target: Duration, now: Duration) -> Pacer {
+        Pacer {
+            target,
+            stride: 1,
+            left: 1,
+            read_at: now,
+            due_at: now,
+        }
+    }
+
+    pub fn target(&self) -> Duration {
+        self.target
+    }
+
+    /// The iterations the target takes at the speed last measured.
+    pub fn stride(&self) -> u64 {
+        self.stride.saturating_mul(u64::from(READS_PER_TARGET))
+    }
+
+    /// Counts an iteration, reading the clock with `now` if its stride is
+    /// up, and says whether at least the target has passed since it was
+    /// last due, or since it started.
+    pub fn tick(&mut self, now: impl FnOnce() -> Duration) -> bool {
+        self.left -= 1;
+        if self.left > 0 {
+            return false;
+        }
+        let now = now();
+        let spent = now.saturating_sub(self.read_at).as_secs_f64();
+        let wanted = (self.target / READS_PER_TARGET).as_secs_f64();
+        let stride = if spent > 0.0 {
+            (self.stride as f64 * wanted / spent) as u64
+        } else {
+            u64::MAX
+        };
+        self.stride = stride.clamp(1, self.stride.saturating_mul(2));
+        self.left = self.stride;
+        self.read_at = now;
+        let due = now.saturating_sub(self.due_at) >= self.target;
+        if due {
+            self.due_at = now;
+        }
+        due
+    }
+}
+
+/// When something recurring in a test's loop is due: on the iterations that
+/// are multiples of a number, or paced by time.
+#[derive(Debug, Clone)]
+pub(crate) enum Every {
+    Multiple(u64),
+    Paced(Pacer),
+}
+
+impl Every {
+    /// Paced by `period` if there is one, starting at `now`, and otherwise
+    /// every `iterations` iterations.
+    pub(crate) fn new(iterations: u64, period: Option<Duration>, now: Duration) -> Every {
+        match period {
+            Some(period) => Every::Paced(Pacer::new(period, now)),
+            None => Every::Multiple(iterations.max(1)),
+        }
+    }
+
+    /// Whether it is due at `iteration`; call for every iteration, in
+    /// order, so that a pacer counts them all.
+    pub(crate) fn due(&mut self, iteration: u64, now: impl FnOnce() -> Duration) -> bool {
+        match self {
+            Every::Multiple(interval) => iteration.is_multiple_of(*interval),
+            Every::Paced(pacer) => pacer.tick(now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Runs `pacer` for `iterations` iterations that each take what `cost`
+    /// says for their index, and returns the times it was due at and how
+    /// often it read the clock.
+    fn run(
+        pacer: &mut Pacer,
+        iterations: u64,
+        cost: impl Fn(u64) -> Duration,
+    ) -> (Vec<Duration>, u64) {
+        let time = Cell::new(Duration::ZERO);
+        let reads = Cell::new(0);
+        let mut due = Vec::new();
+        for i in 0..iterations {
+            time.set(time.get() + cost(i));
+            let now = || {
+                reads.set(reads.get() + 1);
+                time.get()
+            };
+            if pacer.tick(now) {
+                due.push(time.get());
+            }
+        }
+        (due, reads.get())
+    }
+
+    fn gaps(due: &[Duration]) -> Vec<Duration> {
+        due.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[test]
+    fn a_steady_loop_is_due_about_every_target() {
+        let mut pacer = Pacer::new(ms(10_000), Duration::ZERO);
+        let (due, reads) = run(&mut pacer, 100_000, |_| ms(1));
+        assert_eq!(due.len(), 9);
+        assert!((ms(10_000)..ms(11_300)).contains(&due[0]), "{:?}", due);
+        for gap in gaps(&due) {
+            assert!((ms(10_000)..ms(11_300)).contains(&gap), "{:?}", due);
+        }
+        // About eight reads per target, once calibrated.
+        assert!(reads < 100, "{} reads", reads);
+        assert!(
+            (9000..=10_000).contains(&pacer.stride()),
+            "{}",
+            pacer.stride()
+        );
+    }
+
+    #[test]
+    fn a_loop_that_slows_down_is_followed_at_once() {
+        let mut pacer = Pacer::new(ms(1000), Duration::ZERO);
+        let cost = |i| Duration::from_micros(if i < 20_000 { 100 } else { 2_000 });
+        let (due, _) = run(&mut pacer, 30_000, cost);
+        let gaps = gaps(&due);
+        // The read after the change comes late, by up to the stride at the
+        // old speed; the rest are on time again.
+        assert!(
+            gaps.iter().all(|gap| (ms(1000)..ms(4000)).contains(gap)),
+            "{:?}",
+            gaps
+        );
+        let slow = &gaps[gaps.len() - 10..];
+        assert!(slow.iter().all(|&gap| gap < ms(1130)), "{:?}", slow);
+        assert!((400..=500).contains(&pacer.stride()), "{}", pacer.stride());
+    }
+
+    #[test]
+    fn a_loop_that_speeds_up_is_followed_within_a_few_reads() {
+        let mut pacer = Pacer::new(ms(1000), Duration::ZERO);
+        let cost = |i| Duration::from_micros(if i < 2_000 { 2_000 } else { 10 });
+        let (due, _) = run(&mut pacer, 1_000_000, cost);
+        let gaps = gaps(&due);
+        // Never early, and on time again once the stride has grown back.
+        assert!(gaps.iter().all(|&gap| gap >= ms(1000)), "{:?}", gaps);
+        let fast = &gaps[gaps.len() - 5..];
+        assert!(fast.iter().all(|&gap| gap < ms(1130)), "{:?}", fast);
+        assert!(
+            (90_000..=100_000).contains(&pacer.stride()),
+            "{}",
+            pacer.stride()
+        );
+    }
+
+    #[test]
+    fn uneven_iterations_still_pace_by_time() {
+        // Every hundredth iteration costs as much as the other 99.
+        let mut pacer = Pacer::new(ms(2000), Duration::ZERO);
+        let cost = |i| ms(if i % 100 == 99 { 99 } else { 1 });
+        let (due, _) = run(&mut pacer, 60_000, cost);
+        assert!(due.len() >= 50, "{} times", due.len());
+        let gaps = gaps(&due);
+        assert!(
+            gaps.iter().all(|gap| (ms(2000)..ms(2600)).contains(gap)),
+            "{:?}",
+            gaps
+        );
+    }
+
+    #[test]
+    fn a_clock_that_stands_still_is_never_due_and_rarely_read() {
+        let mut pacer = Pacer::new(ms(1000), Duration::ZERO);
+        let (due, reads) = run(&mut pacer, 1 << 20, |_| Duration::ZERO);
+        assert!(due.is_empty());
+        assert_eq!(reads, 20);
+    }
+
+    #[test]
+    fn every_counts_multiples_or_paces() {
+        let mut multiple = Every::new(3, None, Duration::ZERO);
+        let due: Vec<u64> = (1..=10)
+            .filter(|&i| multiple.due(i, || unreachable!()))
+            .collect();
+        assert_eq!(due, [3, 6, 9]);
+
+        let mut paced = Every::new(3, Some(ms(1000)), Duration::ZERO);
+        let due: Vec<u64> = (1..=10).filter(|&i| paced.due(i, || ms(300 * i))).collect();
+        assert_eq!(due, [4, 8]);
+    }
+}
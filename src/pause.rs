@@ -0,0 +1,135 @@
+//! Holding running tests without stopping them.
+//!
+//! A [`Pause`] given to a test with
+//! [`TestControl::pause_with`](crate::TestControl::pause_with) is looked at
+//! where the test looks at its stop flag, before every iteration. While it
+//! is raised the test waits there, keeping its residue, and carries on once
+//! it is lowered, or stops with [`Interrupted`](crate::Interrupted) if its
+//! stop flag is raised meanwhile. The time spent paused is kept, so tests
+//! can leave it out of [`Progress::elapsed`](crate::Progress::elapsed) and
+//! of their deadlines.
+
+use crate::clock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often a paused test looks at the pause and its stop flag again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A switch that holds the tests given it. See the [module docs](self).
+#[derive(Debug)]
+pub struct Pause {
+    paused: AtomicBool,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// When the current pause began, by the default clock.
+    since: Option<Duration>,
+    /// The pauses before it, added up.
+    earlier: Duration,
+}
+
+impl Pause {
+    /// A pause that is not raised.
+    pub const fn new() -> Pause {
+        Pause {
+            paused: AtomicBool::new(false),
+            state: Mutex::new(State {
+                since: None,
+                earlier: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Raises or lowers the pause. Returns, on lowering it, how long it was
+    /// raised; `None` if it already was as asked.
+    pub fn set(&self, paused: bool) -> Option<Duration> {
+        let now = clock::default_clock().now();
+        let mut state = self.state.lock().unwrap();
+        let lasted = match (paused, state.since) {
+            (true, None) => {
+                state.since = Some(now);
+                Duration::ZERO
+            }
+            (false, Some(since)) => {
+                state.since = None;
+                let lasted = now.saturating_sub(since);
+                state.earlier += lasted;
+                lasted
+            }
+            _ => return None,
+        };
+        self.paused.store(paused, Ordering::SeqCst);
+        Some(lasted)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// The time spent paused so far, including the current pause.
+    pub fn total(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let current = state.since.map_or(Duration::ZERO, |since| {
+            clock::default_clock().now().saturating_sub(since)
+        });
+        state.earlier + current
+    }
+
+    /// Waits while the pause is raised and `stop` is not.
+    pub fn wait(&self, stop: &AtomicBool) {
+        while self.is_paused() && !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Default for Pause {
+    fn default() -> Pause {
+        Pause::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn pauses_add_up() {
+        let pause = Pause::new();
+        assert_eq!(pause.set(false), None);
+        assert_eq!(pause.set(true), Some(Duration::ZERO));
+        assert_eq!(pause.set(true), None);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(pause.total() >= Duration::from_millis(20));
+        let lasted = pause.set(false).unwrap();
+        assert!(lasted >= Duration::from_millis(20));
+        assert_eq!(pause.total(), lasted);
+    }
+
+    #[test]
+    fn waiting_ends_when_the_pause_is_lowered_or_the_stop_raised() {
+        let pause = Arc::new(Pause::new());
+        let stop = AtomicBool::new(false);
+        pause.set(true);
+        let lowering = {
+            let pause = pause.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                pause.set(false);
+            })
+        };
+        pause.wait(&stop);
+        assert!(!pause.is_paused());
+        lowering.join().unwrap();
+
+        pause.set(true);
+        stop.store(true, Ordering::Relaxed);
+        pause.wait(&stop);
+        assert!(pause.is_paused());
+    }
+}
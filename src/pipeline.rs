@@ -0,0 +1,206 @@
+//! The stages a run puts its candidates through: trial factoring, P-1 and
+//! the primality test, each on threads of its own and joined by bounded
+//! queues.
+//!
+//! A stage either finishes a candidate, with a factor or a test result,
+//! or passes it on to the next. Since the stages do not share threads, the
+//! quick factoring stages run ahead of the primality tests instead of
+//! waiting behind them, and the queue in front of the tests stays full of
+//! survivors. The queues are bounded, so factoring never gets more than a
+//! few candidates ahead of the tests and a huge range is never held in
+//! memory. Every candidate still ends up as exactly one message to the
+//! output thread, `None` if the run stopped before it was done, so
+//! `--ordered` never waits forever.
+
+use crate::affinity::{self, Pinning};
+use core_affinity::CoreId;
+use mersenne::report::{StageSummary, TestReport};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::Scope;
+use std::time::Instant;
+
+/// Candidates waiting in front of a stage, per thread of the stage.
+const QUEUE_PER_THREAD: usize = 4;
+
+/// An exponent on its way through the stages.
+pub struct Candidate {
+    /// Its place in the run's order, for `--ordered`.
+    pub index: usize,
+    pub p: u64,
+    /// Seconds the stages before spent on it.
+    pub seconds: f64,
+}
+
+/// What a stage made of a candidate.
+pub enum Outcome {
+    /// Done, with the report to print, or `None` if it was interrupted.
+    Finished(Option<Box<TestReport>>),
+    /// On to the next stage.
+    Passed(Candidate),
+}
+
+/// A stage's counters, shared by its threads and read by the status
+/// output.
+pub struct Stage {
+    name: &'static str,
+    threads: usize,
+    /// Candidates the stage has dealt with.
+    done: AtomicU64,
+    /// Of those, the ones it showed to be composite.
+    eliminated: AtomicU64,
+    /// Time its threads spent working, added up.
+    busy_micros: AtomicU64,
+    /// Candidates in its queue.
+    waiting: AtomicU64,
+    /// The cores its threads are pinned to, one each, if they are.
+    cores: Vec<CoreId>,
+}
+
+impl Stage {
+    pub fn new(name: &'static str, threads: usize) -> Stage {
+        Stage {
+            name,
+            threads: threads.max(1),
+            done: AtomicU64::new(0),
+            eliminated: AtomicU64::new(0),
+            busy_micros: AtomicU64::new(0),
+            waiting: AtomicU64::new(0),
+            cores: Vec::new(),
+        }
+    }
+
+    /// Pins the stage's threads to cores of `pinning`, if there is one.
+    pub fn pinned(self, pinning: Option<&Pinning>) -> Stage {
+        match pinning {
+            Some(pinning) => Stage {
+                cores: pinning.assign(self.name, self.threads),
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    /// The bounded queue in front of the stage, and its other end.
+    pub fn queue(&self) -> (Queue<'_>, Receiver<Candidate>) {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(self.threads * QUEUE_PER_THREAD);
+        (
+            Queue {
+                sender,
+                stage: self,
+            },
+            receiver,
+        )
+    }
+
+    /// For the status line, such as `trial factoring 120 done, 80
+    /// eliminated, 4 queued`.
+    pub fn status(&self) -> String {
+        format!(
+            "{} {} done, {} eliminated, {} queued",
+            self.name,
+            self.done.load(Ordering::Relaxed),
+            self.eliminated.load(Ordering::Relaxed),
+            self.waiting.load(Ordering::Relaxed)
+        )
+    }
+
+    pub fn summary(&self) -> StageSummary {
+        StageSummary {
+            stage: self.name,
+            threads: self.threads,
+            exponents: self.done.load(Ordering::Relaxed),
+            eliminated: self.eliminated.load(Ordering::Relaxed),
+            busy_seconds: self.busy_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        }
+    }
+
+    /// Starts the stage's threads in `scope`. Each takes candidates from
+    /// `input` until it is closed and empty, runs `work` on them, sends
+    /// the finished ones to `output` and passes the rest to `next`. Once
+    /// `stop` is raised, candidates still queued are finished as
+    /// interrupted without any work.
+    pub fn spawn<'scope, 'env, F>(
+        &'env self,
+        scope: &'scope Scope<'scope, 'env>,
+        input: Receiver<Candidate>,
+        next: Option<Queue<'env>>,
+        output: Sender<(usize, Option<TestReport>)>,
+        stop: &'env AtomicBool,
+        work: &'env F,
+    ) where
+        F: Fn(Candidate) -> Outcome + Sync,
+    {
+        let input = Arc::new(Mutex::new(input));
+        for index in 0..self.threads {
+            let (input, next, output) = (input.clone(), next.clone(), output.clone());
+            let core = self.cores.get(index).copied();
+            scope.spawn(move || {
+                if let Some(core) = core {
+                    affinity::pin_current(core);
+                }
+                loop {
+                    let Ok(candidate) = input.lock().unwrap().recv() else {
+                        break;
+                    };
+                    self.waiting.fetch_sub(1, Ordering::Relaxed);
+                    let index = candidate.index;
+                    // A pause holds candidates between stages too, and is not
+                    // counted as work.
+                    crate::CANCEL.pauses().wait(stop);
+                    if stop.load(Ordering::SeqCst) {
+                        // The output thread only stops once every sender is gone.
+                        let _ = output.send((index, None));
+                        continue;
+                    }
+                    let started = Instant::now();
+                    let outcome = work(candidate);
+                    let elapsed = started.elapsed();
+                    self.busy_micros
+                        .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+                    self.done.fetch_add(1, Ordering::Relaxed);
+                    match outcome {
+                        Outcome::Finished(report) => {
+                            if report
+                                .as_ref()
+                                .is_some_and(|report| !report.prime && report.has_result())
+                            {
+                                self.eliminated.fetch_add(1, Ordering::Relaxed);
+                            }
+                            let _ = output.send((index, report.map(|report| *report)));
+                        }
+                        Outcome::Passed(mut candidate) => {
+                            candidate.seconds += elapsed.as_secs_f64();
+                            let sent = next.as_ref().is_some_and(|next| next.send(candidate));
+                            if !sent {
+                                let _ = output.send((index, None));
+                            }
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// The sending end of a stage's queue.
+#[derive(Clone)]
+pub struct Queue<'a> {
+    sender: SyncSender<Candidate>,
+    stage: &'a Stage,
+}
+
+impl Queue<'_> {
+    /// Queues `candidate`, waiting while the queue is full. Returns false
+    /// if the stage's threads are gone.
+    pub fn send(&self, candidate: Candidate) -> bool {
+        self.stage.waiting.fetch_add(1, Ordering::Relaxed);
+        if self.sender.send(candidate).is_ok() {
+            true
+        } else {
+            self.stage.waiting.fetch_sub(1, Ordering::Relaxed);
+            false
+        }
+    }
+}
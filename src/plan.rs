@@ -0,0 +1,738 @@
+//! Which exponents a run tests, and why it leaves out the rest.
+//!
+//! A run and its `--dry-run` both walk the selection through [`plan`], so
+//! the plan printed by a dry run is the work a real run does, in the same
+//! order before `--order` rearranges it. The plan groups the exponents to
+//! test by their number of digits and estimates each group's time the way
+//! [`Eta`] estimates a run, from a short benchmark at the group's largest
+//! exponent. For a Mersenne search it also gives the number of primes the
+//! range should hold, by [`expected_mersenne_primes`].
+//!
+//! With `--exponent-filter`, only exponents of a special shape are tested,
+//! such as Sophie Germain primes; the rest are left out like the known
+//! primes of `--skip-known`, and counted in the plan beside them.
+//!
+//! With `--known-factors`, exponents whose Mersenne numbers have a factor
+//! in the file are left out as well, but go on to be recorded as factored
+//! by it, since a run knows they are composite.
+//!
+//! A real run does not wait for the plan: its tests take the exponents as
+//! the plan yields them, while a [`Census`] of the same plan counts them
+//! beside the tests.
+
+use crate::admission::{estimated_bytes, format_mb, usable, PROCESS_BYTES, SAFE_SHARE};
+use crate::eta::Eta;
+use crate::progress::{format_duration, group};
+use crate::{Options, Selection};
+use log::{info, warn};
+use mersenne::known::{
+    expected_mersenne_primes, is_known_mersenne_exponent, known_between, MERSENNE_EXPONENTS,
+};
+use mersenne::primality::{
+    is_double_mersenne_exponent, is_safe_prime, is_sophie_germain_prime, is_twin_prime,
+};
+use mersenne::report::{ExponentRange, Form};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// What a run does with an exponent of its selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    Test,
+    /// A known Mersenne prime exponent, left out with `--skip-known`.
+    KnownPrime,
+    /// Outside what the form's test can handle: 2 for Wagstaff numbers,
+    /// since `W(2)` is not an integer, or a Riesel exponent with
+    /// `k >= 2^n`.
+    Untestable,
+    /// Not of the shape `--exponent-filter` asks for.
+    Filtered,
+    /// For another machine's `--chunk`.
+    OtherChunk,
+    /// Already finished in the `--ledger`.
+    InLedger,
+    /// Already in the `--results` file or the `--db`.
+    InResults,
+    /// With a factor of its Mersenne number in `--known-factors`.
+    KnownFactor,
+}
+
+/// The exponents of `selection`, each with what the run does with it.
+///
+/// `finished` holds the exponents done in the ledger and `recorded` those
+/// in the results file or database. Chunks are taken before either is
+/// consulted, so every machine splits the range the same way.
+pub fn plan<'a>(
+    selection: &'a Selection,
+    options: &'a Options,
+    finished: &'a HashSet<u64>,
+    recorded: &'a HashSet<u64>,
+) -> impl Iterator<Item = (u64, Disposition)> + Send + 'a {
+    let mut position = 0;
+    selection.candidates().map(move |p| {
+        let disposition = if options.skip_known && is_known_mersenne_exponent(p) {
+            Disposition::KnownPrime
+        } else if !testable(p, options.form) {
+            Disposition::Untestable
+        } else if !options.exponent_filter.keeps(p) {
+            Disposition::Filtered
+        } else {
+            let in_chunk = options.chunk.is_none_or(|chunk| chunk.takes(position));
+            position += 1;
+            if !in_chunk {
+                Disposition::OtherChunk
+            } else if finished.contains(&p) {
+                Disposition::InLedger
+            } else if recorded.contains(&p) {
+                Disposition::InResults
+            } else if crate::known_factor(p).is_some() {
+                Disposition::KnownFactor
+            } else {
+                Disposition::Test
+            }
+        };
+        (p, disposition)
+    })
+}
+
+/// Turns a [`Selection::Next`] into the range from its start to the
+/// exponent that completes its count of exponents to test, by [`plan`], so
+/// the run, its census and its dry run all take the same exponents, and
+/// returns the other selections as they are.
+///
+/// The count is of what [`plan`] leaves to test, before any factoring, so
+/// an exponent that trial factoring eliminates counts as tested.
+pub fn resolve(
+    selection: Selection,
+    options: &Options,
+    finished: &HashSet<u64>,
+    recorded: &HashSet<u64>,
+) -> Selection {
+    let Selection::Next {
+        start,
+        count,
+        every,
+    } = selection
+    else {
+        return selection;
+    };
+    let end = plan(&selection, options, finished, recorded)
+        .filter(|&(_, disposition)| disposition == Disposition::Test)
+        .nth(count.saturating_sub(1) as usize)
+        .map_or(u64::MAX, |(p, _)| p);
+    if every {
+        Selection::Every(start, end)
+    } else {
+        Selection::Range(start, end)
+    }
+}
+
+/// The exponents a run keeps, by `--exponent-filter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExponentFilter {
+    All,
+    /// `p` with `2p + 1` prime.
+    SophieGermain,
+    /// `p` with `(p - 1) / 2` prime.
+    Safe,
+    /// `p` with `p - 2` or `p + 2` prime.
+    Twin,
+    /// `p` a Mersenne prime itself, so that `M(p)` is a double Mersenne
+    /// number. A run with it tests these whatever its range.
+    DoubleMersenne,
+}
+
+impl ExponentFilter {
+    /// Whether the filter keeps the prime exponent `p`.
+    pub fn keeps(self, p: u64) -> bool {
+        match self {
+            ExponentFilter::All => true,
+            ExponentFilter::SophieGermain => is_sophie_germain_prime(p),
+            ExponentFilter::Safe => is_safe_prime(p),
+            ExponentFilter::Twin => is_twin_prime(p),
+            ExponentFilter::DoubleMersenne => is_double_mersenne_exponent(p),
+        }
+    }
+
+    /// What the exponents it leaves out are not, for the plan.
+    fn shape(self) -> &'static str {
+        match self {
+            ExponentFilter::All => "an exponent",
+            ExponentFilter::SophieGermain => "a Sophie Germain prime",
+            ExponentFilter::Safe => "a safe prime",
+            ExponentFilter::Twin => "a twin prime",
+            ExponentFilter::DoubleMersenne => "a Mersenne prime",
+        }
+    }
+}
+
+impl FromStr for ExponentFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExponentFilter, String> {
+        match s {
+            "all" => Ok(ExponentFilter::All),
+            "sophie-germain" => Ok(ExponentFilter::SophieGermain),
+            "safe" => Ok(ExponentFilter::Safe),
+            "twin" => Ok(ExponentFilter::Twin),
+            "double-mersenne" => Ok(ExponentFilter::DoubleMersenne),
+            _ => Err(format!("unknown exponent filter {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ExponentFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ExponentFilter::All => "all",
+            ExponentFilter::SophieGermain => "sophie-germain",
+            ExponentFilter::Safe => "safe",
+            ExponentFilter::Twin => "twin",
+            ExponentFilter::DoubleMersenne => "double-mersenne",
+        })
+    }
+}
+
+/// Whether the test for `form` applies to exponent `p` at all.
+fn testable(p: u64, form: Form) -> bool {
+    match form {
+        Form::Wagstaff => p != 2,
+        Form::Riesel { k } => riesel_testable(k, p),
+        _ => true,
+    }
+}
+
+/// Whether the Lucas-Lehmer-Riesel test applies to `k*2^n - 1`: it needs
+/// `n >= 2` and `k < 2^n`.
+pub fn riesel_testable(k: u64, n: u64) -> bool {
+    n >= 2 && (n >= 64 || k < 1 << n)
+}
+
+/// The share of a selection's exponents that `--skip-known` can leave out
+/// before a run warns that it skips most of what it was asked for.
+const KNOWN_SHARE_WARNING: f64 = 0.5;
+
+/// The largest exponent `--exponent-filter double-mersenne` tests, that of
+/// `MM(7)`. The double Mersenne numbers after it, from `MM(13) = M(8191)`
+/// to `MM(31)`, have known factors, and a test of any larger one would
+/// take longer than anyone can wait.
+const DOUBLE_MERSENNE_LIMIT: u64 = 127;
+
+/// Why a run cannot go ahead with the exponents it was given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionsError {
+    /// The range starts after it ends.
+    Reversed { start: u64, end: u64 },
+    /// The test of `number`, the largest of the selection, is estimated to
+    /// need `needed` bytes, more than the `available` of the machine.
+    TooLarge {
+        number: String,
+        needed: u64,
+        available: u64,
+    },
+    /// Nothing in the selection is left to test, which is not an error of
+    /// the input as such; it holds the message saying so.
+    NoCandidates(String),
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionsError::Reversed { start, end } => write!(
+                f,
+                "start_exponent ({}) should be less than or equal to end_exponent ({}).",
+                start, end
+            ),
+            OptionsError::TooLarge {
+                number,
+                needed,
+                available,
+            } => write!(
+                f,
+                "the test of {} needs about {} of memory, but this machine has {} available, \
+                 of which a run takes at most {:.0}%; the process would likely be killed for want \
+                 of memory. Use --force to test it anyway.",
+                number,
+                format_mb(*needed),
+                format_mb(*available),
+                100.0 * SAFE_SHARE
+            ),
+            OptionsError::NoCandidates(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Checks the exponents a run was given before anything is planned, and
+/// returns the selection to plan.
+///
+/// The range must not be reversed, the test of its largest exponent must
+/// fit safely in the `memory` the machine has available, if known, unless
+/// `--force` says otherwise, and it must hold at least one exponent to test
+/// once untestable ones and those `--skip-known` leaves out are gone,
+/// unless the exponents were named one by one. Exponents 0
+/// and 1, which are not prime, are dropped with a warning; a range whose
+/// only exponent is 2, which has no Lucas-Lehmer test, gets a note; and so
+/// does one that `--skip-known` mostly empties.
+pub fn validate_options(
+    selection: Selection,
+    options: &Options,
+    memory: Option<u64>,
+) -> Result<Selection, OptionsError> {
+    let form = options.form;
+    if let Selection::Range(start, end) | Selection::Every(start, end) = selection {
+        if start > end {
+            return Err(OptionsError::Reversed { start, end });
+        }
+    }
+    // Reading a stream to check it would leave nothing to test; its
+    // exponents are checked one by one as they arrive.
+    if let Selection::Stream(_) = selection {
+        return Ok(selection);
+    }
+    let filter = options.exponent_filter;
+    let selection = match filter {
+        ExponentFilter::DoubleMersenne => double_mersenne(&selection),
+        _ => selection,
+    };
+    let asked = describe(&selection);
+    let selection = match selection {
+        Selection::Range(start, end) if start < 2 => {
+            warn!("p = 0 and p = 1 are not prime exponents; starting at p = 2.");
+            Selection::Range(2, end)
+        }
+        Selection::Next {
+            start,
+            count,
+            every: false,
+        } if start < 2 => {
+            warn!("p = 0 and p = 1 are not prime exponents; starting at p = 2.");
+            Selection::Next {
+                start: 2,
+                count,
+                every: false,
+            }
+        }
+        selection => selection,
+    };
+
+    let largest = match selection {
+        Selection::Next { .. } => None,
+        _ => Some(selection.bounds().1),
+    };
+    if let (Some(p), Some(available)) = (largest.filter(|&p| p >= 2), memory) {
+        let needed = estimated_bytes(form.bits(p));
+        if needed > usable(available) {
+            let needed = needed + PROCESS_BYTES;
+            if !options.force {
+                return Err(OptionsError::TooLarge {
+                    number: form.number(p),
+                    needed,
+                    available,
+                });
+            }
+            warn!(
+                "the test of {} needs about {} of memory, more than a run should take of the {} \
+                 available; testing it anyway, as --force asks.",
+                form.number(p),
+                format_mb(needed),
+                format_mb(available)
+            );
+        }
+    }
+
+    // Counting the whole selection could take long, but only a few dozen
+    // exponents are known primes, so the count can stop once they are
+    // shown to be a small enough share of it.
+    let known = match options.skip_known {
+        true => MERSENNE_EXPONENTS
+            .iter()
+            .filter(|&&p| selection.contains(p) && filter.keeps(p))
+            .count(),
+        false => 0,
+    };
+    let enough = (known as f64 / KNOWN_SHARE_WARNING) as usize + 2;
+    let testable: Vec<u64> = selection
+        .candidates()
+        .filter(|&p| testable(p, form) && filter.keeps(p))
+        .take(enough)
+        .collect();
+    let complete = testable.len() < enough;
+    let named = matches!(selection, Selection::List(_));
+    if testable.len() == known && complete && !named {
+        let mut left_out = match filter {
+            ExponentFilter::All => String::new(),
+            _ => format!(" that pass --exponent-filter {}", filter),
+        };
+        if known > 0 {
+            left_out += " once --skip-known leaves out its known Mersenne primes";
+        }
+        return Err(OptionsError::NoCandidates(format!(
+            "0 candidate exponents in {}{} — nothing to do.",
+            asked, left_out
+        )));
+    }
+    if complete && known as f64 > KNOWN_SHARE_WARNING * testable.len() as f64 {
+        warn!(
+            "--skip-known leaves out {} of the {} exponents in {}.",
+            known,
+            testable.len(),
+            asked
+        );
+    }
+    if testable == [2] && form == Form::Mersenne && !options.prp {
+        info!("M(2) = 3 is too small for the Lucas-Lehmer test; it is reported prime without it.");
+    }
+    Ok(selection)
+}
+
+/// The double Mersenne numbers small enough to test, which
+/// `--exponent-filter double-mersenne` tests in place of `selection`.
+fn double_mersenne(selection: &Selection) -> Selection {
+    let mersenne = |q: u64| (1u64 << q) - 1;
+    let (feasible, too_large): (Vec<u64>, Vec<u64>) = MERSENNE_EXPONENTS
+        .iter()
+        .copied()
+        .filter(|&q| q < 64)
+        .partition(|&q| mersenne(q) <= DOUBLE_MERSENNE_LIMIT);
+    let named = |exponents: &[u64]| {
+        exponents
+            .iter()
+            .map(|&q| format!("MM({}) = M({})", q, mersenne(q)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    info!(
+        "--exponent-filter double-mersenne tests {} in place of {}.",
+        named(&feasible),
+        describe(selection)
+    );
+    warn!(
+        "leaving out {} and the larger double Mersenne numbers: past p = {} they have known \
+         factors or are too large for a test to be feasible.",
+        named(&too_large),
+        group(DOUBLE_MERSENNE_LIMIT)
+    );
+    Selection::List(feasible.into_iter().map(mersenne).collect())
+}
+
+/// The selection as the messages of [`validate_options`] name it, such as
+/// `the range p = 90 to p = 96`.
+fn describe(selection: &Selection) -> String {
+    match selection {
+        Selection::Range(start, end) => {
+            format!("the range p = {} to p = {}", group(*start), group(*end))
+        }
+        Selection::Every(start, end) => format!("{} to {}", group(*start), group(*end)),
+        Selection::Next { start, .. } => format!("the exponents from p = {}", group(*start)),
+        Selection::Ranges { .. } => "the ranges".to_string(),
+        Selection::List(_) => "the requested exponents".to_string(),
+        Selection::Stream(_) => "standard input".to_string(),
+    }
+}
+
+/// How many of a run's exponents it tests and how many it skips, from its
+/// plan.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Census {
+    /// Every exponent the selection generated.
+    pub generated: u64,
+    pub to_test: u64,
+    pub in_ledger: u64,
+    pub in_results: u64,
+    /// With a factor in `--known-factors`, which the run records without
+    /// testing.
+    pub known_factors: u64,
+    /// Whether the count got to the end of the plan.
+    pub complete: bool,
+}
+
+impl Census {
+    /// Counts `planned`, the output of [`plan`], and passes each exponent
+    /// to test to `eta`. Gives up with an incomplete count once `stop` is
+    /// raised.
+    pub fn take<I>(planned: I, eta: &Eta, stop: &AtomicBool) -> Census
+    where
+        I: IntoIterator<Item = (u64, Disposition)>,
+    {
+        let mut census = Census::default();
+        for (p, disposition) in planned {
+            if stop.load(Ordering::SeqCst) {
+                return census;
+            }
+            census.generated += 1;
+            match disposition {
+                Disposition::Test => {
+                    eta.discover(p);
+                    census.to_test += 1;
+                }
+                Disposition::InLedger => census.in_ledger += 1,
+                Disposition::InResults => census.in_results += 1,
+                Disposition::KnownFactor => census.known_factors += 1,
+                _ => {}
+            }
+        }
+        eta.discovered();
+        census.complete = true;
+        census
+    }
+}
+
+/// The plan of a run, as `--dry-run` reports it.
+#[derive(Debug, Serialize)]
+pub struct WorkPlan {
+    #[serde(flatten)]
+    pub form: Form,
+    pub start_exponent: u64,
+    pub end_exponent: u64,
+    /// Every exponent the selection generates.
+    pub candidates: u64,
+    pub known_primes: u64,
+    pub untestable: u64,
+    /// Left out by `--exponent-filter`, which `filter` is; both are left
+    /// out of the JSON without one.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub filtered: u64,
+    #[serde(skip_serializing_if = "keeps_all")]
+    pub filter: ExponentFilter,
+    pub other_chunks: u64,
+    pub in_ledger: u64,
+    pub in_results: u64,
+    /// Left out by `--known-factors`, and left out of the JSON without it.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub known_factors: u64,
+    pub to_test: u64,
+    /// For a Mersenne search of a range: its known Mersenne primes, and how
+    /// many it should hold by the Wagstaff conjecture.
+    pub known_in_range: Option<u64>,
+    pub expected_primes: Option<f64>,
+    /// With `--count`, the number of exponents asked for; the range ends at
+    /// the last of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    /// With `--ranges`, the ranges in between the start and end exponents.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ranges: Vec<ExponentRange>,
+    pub buckets: Vec<Bucket>,
+    /// Wall-clock seconds for all the tests, if a benchmark could be run.
+    pub estimated_seconds: Option<f64>,
+    pub threads: usize,
+}
+
+/// Wraps the plan as `{"plan": {...}}`, like the summary line of a run.
+#[derive(Debug, Serialize)]
+pub struct PlanLine<'a> {
+    pub plan: &'a WorkPlan,
+}
+
+/// The exponents to test with the same number of digits.
+#[derive(Debug, Serialize)]
+pub struct Bucket {
+    pub from: u64,
+    pub to: u64,
+    pub exponents: u64,
+    pub estimated_seconds: Option<f64>,
+}
+
+impl WorkPlan {
+    /// Tallies `planned`, the output of [`plan`], and estimates how long
+    /// the tests take on `threads` threads.
+    pub fn new<I>(form: Form, bounds: (u64, u64), planned: I, threads: usize) -> WorkPlan
+    where
+        I: IntoIterator<Item = (u64, Disposition)>,
+    {
+        let mut plan = WorkPlan {
+            form,
+            start_exponent: bounds.0,
+            end_exponent: bounds.1,
+            candidates: 0,
+            known_primes: 0,
+            untestable: 0,
+            filtered: 0,
+            filter: ExponentFilter::All,
+            other_chunks: 0,
+            in_ledger: 0,
+            in_results: 0,
+            known_factors: 0,
+            to_test: 0,
+            known_in_range: None,
+            expected_primes: None,
+            count: None,
+            ranges: Vec::new(),
+            buckets: Vec::new(),
+            estimated_seconds: None,
+            threads,
+        };
+        let mut by_digits: BTreeMap<u32, Eta> = BTreeMap::new();
+        for (p, disposition) in planned {
+            plan.candidates += 1;
+            let count = match disposition {
+                Disposition::Test => {
+                    by_digits
+                        .entry(p.max(1).ilog10())
+                        .or_insert_with(|| Eta::new(form, threads))
+                        .discover(p);
+                    &mut plan.to_test
+                }
+                Disposition::KnownPrime => &mut plan.known_primes,
+                Disposition::Untestable => &mut plan.untestable,
+                Disposition::Filtered => &mut plan.filtered,
+                Disposition::OtherChunk => &mut plan.other_chunks,
+                Disposition::InLedger => &mut plan.in_ledger,
+                Disposition::InResults => &mut plan.in_results,
+                Disposition::KnownFactor => &mut plan.known_factors,
+            };
+            *count += 1;
+        }
+
+        for (digits, eta) in by_digits {
+            eta.discovered();
+            eta.calibrate();
+            plan.buckets.push(Bucket {
+                from: 10u64.pow(digits),
+                to: 10u64.saturating_pow(digits + 1) - 1,
+                exponents: eta.total() as u64,
+                estimated_seconds: eta.remaining_seconds(),
+            });
+        }
+        plan.estimated_seconds = plan
+            .buckets
+            .iter()
+            .map(|bucket| bucket.estimated_seconds)
+            .sum();
+        plan
+    }
+
+    /// Adds the known and expected Mersenne primes of the range `start` to
+    /// `end` exponents.
+    pub fn with_expectation(self, start: u64, end: u64) -> WorkPlan {
+        WorkPlan {
+            known_in_range: Some(known_between(start, end).len() as u64),
+            expected_primes: Some(expected_mersenne_primes(start, end)),
+            ..self
+        }
+    }
+
+    /// Marks the plan as one of the next `count` exponents to test.
+    pub fn with_count(self, count: Option<u64>) -> WorkPlan {
+        WorkPlan { count, ..self }
+    }
+
+    /// Marks the plan as one of the exponents `filter` keeps.
+    pub fn with_filter(self, filter: ExponentFilter) -> WorkPlan {
+        WorkPlan { filter, ..self }
+    }
+
+    /// Stretches the estimates for tests held to `share` of a core each by
+    /// `--cpu-limit`.
+    pub fn throttled_to(self, share: f64) -> WorkPlan {
+        let slower = |seconds: Option<f64>| seconds.map(|seconds| seconds / share);
+        let buckets = self
+            .buckets
+            .into_iter()
+            .map(|bucket| Bucket {
+                estimated_seconds: slower(bucket.estimated_seconds),
+                ..bucket
+            })
+            .collect();
+        WorkPlan {
+            buckets,
+            estimated_seconds: slower(self.estimated_seconds),
+            ..self
+        }
+    }
+
+    /// Marks the plan as one of several ranges.
+    pub fn with_ranges(self, ranges: &[ExponentRange]) -> WorkPlan {
+        WorkPlan {
+            ranges: ranges.to_vec(),
+            ..self
+        }
+    }
+
+    /// Prints the plan for people.
+    pub fn print(&self) {
+        let (start, end) = (
+            self.form.number(self.start_exponent),
+            self.form.number(self.end_exponent),
+        );
+        if !self.ranges.is_empty() {
+            let ranges: Vec<String> = self.ranges.iter().map(ExponentRange::to_string).collect();
+            outln!(
+                "Plan for {} to {} in {} ranges, {}:",
+                start,
+                end,
+                ranges.len(),
+                ranges.join(", ")
+            );
+        } else if let Some(count) = self.count {
+            outln!(
+                "Plan for the next {} exponent(s) to test from {}, up to {}:",
+                count,
+                start,
+                end
+            );
+        } else {
+            outln!("Plan for {} to {}:", start, end);
+        }
+        outln!("Candidates: {}", self.candidates);
+        if let (Some(known), Some(expected)) = (self.known_in_range, self.expected_primes) {
+            outln!("Known Mersenne primes in the range: {}", known);
+            outln!(
+                "Expected Mersenne primes in the range: {:.2} (Wagstaff conjecture)",
+                expected
+            );
+        }
+        let filtered = format!(
+            "not {} (--exponent-filter {})",
+            self.filter.shape(),
+            self.filter
+        );
+        let skipped = [
+            (self.known_primes, "known Mersenne primes (--skip-known)"),
+            (self.untestable, "outside the test for this form"),
+            (self.filtered, filtered.as_str()),
+            (self.other_chunks, "in other chunks"),
+            (self.in_ledger, "already in the ledger"),
+            (self.in_results, "already in the results file or database"),
+            (self.known_factors, "known factors (--known-factors)"),
+        ];
+        for (count, reason) in skipped {
+            if count > 0 {
+                outln!("  skipped, {}: {}", reason, count);
+            }
+        }
+        outln!("To test: {}", self.to_test);
+        for bucket in &self.buckets {
+            outln!(
+                "  {:>11} to {:<11} {:>9} exponent(s), about {}",
+                bucket.from,
+                bucket.to,
+                bucket.exponents,
+                estimate(bucket.estimated_seconds)
+            );
+        }
+        if self.to_test > 0 {
+            outln!(
+                "Estimated time: {} on {} thread(s)",
+                estimate(self.estimated_seconds),
+                self.threads
+            );
+        }
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+fn keeps_all(filter: &ExponentFilter) -> bool {
+    *filter == ExponentFilter::All
+}
+
+fn estimate(seconds: Option<f64>) -> String {
+    seconds.map_or_else(|| "?".to_string(), format_duration)
+}
@@ -0,0 +1,309 @@
+//! Primality testing for 64-bit integers, used to pick candidate exponents
+//! and to check the exponents users give.
+
+use crate::numeric;
+
+/// Primes used both for the trial-division pre-filter and as Miller–Rabin
+/// witnesses. Testing against all of them is deterministic for every `u64`.
+const SMALL_PRIMES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// Returns `true` if `n` is prime.
+///
+/// Small factors are ruled out by trial division first; anything left over
+/// goes through a deterministic Miller–Rabin test with the witnesses
+/// 2, 3, 5, ..., 37, which is proven correct for all `n < 2^64`.
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for &prime in &SMALL_PRIMES {
+        if n == prime {
+            return true;
+        }
+        if n.is_multiple_of(prime) {
+            return false;
+        }
+    }
+    // No prime factor up to 37 means any n below 41^2 is prime.
+    if n < 41 * 41 {
+        return true;
+    }
+
+    SMALL_PRIMES.iter().all(|&a| is_strong_probable_prime(n, a))
+}
+
+/// What [`check_exponent`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExponentStatus {
+    Prime,
+    /// With a factor other than 1 and itself, unless Pollard's rho failed
+    /// to turn one up.
+    Composite {
+        factor: Option<u64>,
+    },
+    /// 0 and 1, which are neither.
+    Neither,
+}
+
+impl ExponentStatus {
+    pub fn is_prime(self) -> bool {
+        self == ExponentStatus::Prime
+    }
+}
+
+/// Checks an exponent a user gave, with the Baillie–PSW test, and finds a
+/// factor of it if it is composite, to show why it was rejected.
+///
+/// After trial division by the primes up to 37, `n` goes through a strong
+/// probable prime test to base 2 and a [strong Lucas
+/// test](numeric::is_strong_lucas_probable_prime); no composite below
+/// `2^64` passes both. A composite's factor is its smallest prime factor up
+/// to 37, or else what [`numeric::pollard_rho`] finds.
+pub fn check_exponent(n: u64) -> ExponentStatus {
+    if n < 2 {
+        return ExponentStatus::Neither;
+    }
+    for &prime in &SMALL_PRIMES {
+        if n == prime {
+            return ExponentStatus::Prime;
+        }
+        if n.is_multiple_of(prime) {
+            return ExponentStatus::Composite {
+                factor: Some(prime),
+            };
+        }
+    }
+    if n < 41 * 41 || is_strong_probable_prime(n, 2) && numeric::is_strong_lucas_probable_prime(n) {
+        ExponentStatus::Prime
+    } else {
+        ExponentStatus::Composite {
+            factor: numeric::pollard_rho(n),
+        }
+    }
+}
+
+/// Why `n` cannot be an exponent, as in `rejected exponent 44498 = 2 ×
+/// 22249`, or `None` if it is prime.
+pub fn rejection(n: u64) -> Option<String> {
+    match check_exponent(n) {
+        ExponentStatus::Prime => None,
+        ExponentStatus::Composite {
+            factor: Some(factor),
+        } => {
+            let factor = factor.min(n / factor);
+            Some(format!(
+                "rejected exponent {} = {} × {}",
+                n,
+                factor,
+                n / factor
+            ))
+        }
+        _ => Some(format!("rejected exponent {}", n)),
+    }
+}
+
+/// Whether `p` is a Sophie Germain prime: `p` and `2p + 1` are both
+/// prime.
+pub fn is_sophie_germain_prime(p: u64) -> bool {
+    is_prime(p)
+        && p.checked_mul(2)
+            .and_then(|q| q.checked_add(1))
+            .is_some_and(is_prime)
+}
+
+/// Whether `p` is a safe prime: `p` and `(p - 1) / 2` are both prime.
+pub fn is_safe_prime(p: u64) -> bool {
+    p > 2 && is_prime(p) && is_prime((p - 1) / 2)
+}
+
+/// Whether `p` is one of a pair of twin primes: `p` and `p - 2` or `p + 2`
+/// are prime.
+pub fn is_twin_prime(p: u64) -> bool {
+    is_prime(p) && (p > 2 && is_prime(p - 2) || p.checked_add(2).is_some_and(is_prime))
+}
+
+/// Whether `p` is itself a Mersenne prime `2^q - 1`, which makes `M(p)` the
+/// double Mersenne number `MM(q)`. Such a `q` is prime, since `2^q - 1` is.
+pub fn is_double_mersenne_exponent(p: u64) -> bool {
+    p.checked_add(1).is_some_and(u64::is_power_of_two) && is_prime(p)
+}
+
+/// Whether odd `n > a` is a strong probable prime to base `a`: writing
+/// `n - 1 = d·2^s` with `d` odd, `a^d ≡ 1` or `a^(d·2^r) ≡ -1` for some
+/// `r < s`.
+fn is_strong_probable_prime(n: u64, a: u64) -> bool {
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    let mut x = pow_mod(a, d, n);
+    if x == 1 || x == n - 1 {
+        return true;
+    }
+    for _ in 1..s {
+        x = mul_mod(x, x, n);
+        if x == n - 1 {
+            return true;
+        }
+    }
+    false
+}
+
+/// `a * b mod m`, computed in 128 bits so the product cannot overflow.
+pub(crate) fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+/// `base^exp mod m` by square-and-multiply.
+pub(crate) fn pow_mod(base: u64, mut exp: u64, m: u64) -> u64 {
+    let mut result = 1 % m;
+    let mut base = base % m;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = mul_mod(result, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exp >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference implementation for cross-checking small values.
+    fn trial_division(n: u64) -> bool {
+        n >= 2
+            && (2..)
+                .take_while(|i| i * i <= n)
+                .all(|i| !n.is_multiple_of(i))
+    }
+
+    #[test]
+    fn agrees_with_trial_division() {
+        for n in 0..20_000 {
+            assert_eq!(is_prime(n), trial_division(n), "n = {}", n);
+        }
+        for n in 1_000_000_000..1_000_001_000 {
+            assert_eq!(is_prime(n), trial_division(n), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn rejects_strong_pseudoprimes() {
+        // Smallest strong pseudoprimes to the first k prime bases.
+        for n in [
+            2047,
+            1373653,
+            25326001,
+            3215031751,
+            2152302898747,
+            3474749660383,
+            341550071728321,
+            3825123056546413051,
+        ] {
+            assert!(!is_prime(n), "{} is composite", n);
+        }
+    }
+
+    #[test]
+    fn rejects_carmichael_numbers() {
+        for n in [561, 1105, 1729, 2465, 2821, 6601, 8911, 41041, 825265] {
+            assert!(!is_prime(n), "{} is composite", n);
+        }
+    }
+
+    #[test]
+    fn handles_values_near_u64_max() {
+        assert!(is_prime(18446744073709551557)); // 2^64 - 59
+        assert!(is_prime(18446744073709551533));
+        assert!(!is_prime(u64::MAX));
+        assert!(!is_prime(u64::MAX - 1));
+        assert!(!is_prime(18446744030759878681)); // (2^32 - 5)^2
+        assert!(!is_prime(18446743979220271189)); // (2^32 - 5)(2^32 - 17)
+        assert!(is_prime((1 << 61) - 1));
+        assert!(!is_prime(4294967297)); // F5 = 641 * 6700417
+    }
+
+    #[test]
+    fn check_exponent_agrees_with_is_prime() {
+        for n in (0..20_000).chain(1_000_000_000..1_000_001_000) {
+            assert_eq!(check_exponent(n).is_prime(), is_prime(n), "n = {}", n);
+        }
+        assert_eq!(check_exponent(1), ExponentStatus::Neither);
+    }
+
+    #[test]
+    fn check_exponent_finds_factors_of_pseudoprimes() {
+        for n in [
+            // Carmichael numbers.
+            561,
+            1105,
+            41041,
+            825265,
+            // Strong pseudoprimes, to base 2 and to the first 12 primes.
+            2047,
+            3825123056546413051,
+            // Strong Lucas pseudoprimes.
+            5459,
+            97439,
+            // Semiprimes of two 32-bit primes.
+            18446743979220271189,
+            18446744030759878681,
+        ] {
+            let ExponentStatus::Composite {
+                factor: Some(factor),
+            } = check_exponent(n)
+            else {
+                panic!("{} is composite", n);
+            };
+            assert!(
+                factor > 1 && factor < n && n % factor == 0,
+                "{} of {}",
+                factor,
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn finds_primes_of_special_shapes() {
+        let below = |limit: u64, shape: fn(u64) -> bool| {
+            (0..limit).filter(|&p| shape(p)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            below(200, is_sophie_germain_prime),
+            [2, 3, 5, 11, 23, 29, 41, 53, 83, 89, 113, 131, 173, 179, 191]
+        );
+        assert_eq!(
+            below(200, is_safe_prime),
+            [5, 7, 11, 23, 47, 59, 83, 107, 167, 179]
+        );
+        assert_eq!(
+            below(100, is_twin_prime),
+            [3, 5, 7, 11, 13, 17, 19, 29, 31, 41, 43, 59, 61, 71, 73]
+        );
+        assert_eq!(
+            below(1 << 20, is_double_mersenne_exponent),
+            [3, 7, 31, 127, 8191, 131071, 524287]
+        );
+        assert!(is_double_mersenne_exponent((1 << 61) - 1));
+        assert!(!is_double_mersenne_exponent(u64::MAX));
+        // 2^64 - 59 is prime, but 2^65 - 117 is too big to test.
+        assert!(!is_sophie_germain_prime(18446744073709551557));
+        assert!(!is_twin_prime(18446744073709551557));
+    }
+
+    #[test]
+    fn rejections_show_a_factor() {
+        assert_eq!(
+            rejection(44498).as_deref(),
+            Some("rejected exponent 44498 = 2 × 22249")
+        );
+        assert_eq!(
+            rejection(5459).as_deref(),
+            Some("rejected exponent 5459 = 53 × 103")
+        );
+        assert_eq!(rejection(1).as_deref(), Some("rejected exponent 1"));
+        assert_eq!(rejection(44497), None);
+    }
+}
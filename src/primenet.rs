@@ -0,0 +1,319 @@
+//! Result lines in the PrimeNet v5 JSON format, written with
+//! `--primenet-results` for manual submission to mersenne.org.
+//!
+//! Each completed primality test gets one JSON object per line, in the form
+//! Prime95 and mprime produce, for example
+//!
+//! ```text
+//! {"status":"C","exponent":29,"worktype":"LL","res64":"000000001B57CB0B","shift-count":0,"error-code":"00000000","program":{"name":"Mersenne","version":"0.1.0"},"timestamp":"2024-05-01 12:00:00"}
+//! ```
+//!
+//! The server rejects lines with unexpected field names or formats, so the
+//! field names here must not change. Fields this program has nothing to put
+//! in, such as `fft-length`, are left out rather than guessed.
+
+use crate::report::{Form, TestKind, TestReport};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// `C` for composite, `P` for prime or probable prime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    #[serde(rename = "C")]
+    Composite,
+    #[serde(rename = "P")]
+    Prime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WorkType {
+    #[serde(rename = "LL")]
+    LucasLehmer,
+    /// A base-3 probable-prime test.
+    #[serde(rename = "PRP-3")]
+    Prp3,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Program {
+    pub name: String,
+    pub version: String,
+}
+
+impl Program {
+    /// This program, as it names itself to the server.
+    pub fn this() -> Program {
+        Program {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Who ran the test, from `--primenet-user` and `--primenet-computer`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub user: Option<String>,
+    pub computer: Option<String>,
+}
+
+/// PRP residue type 3: the residue is `3^(N+1) mod N` for `N = M(p)`,
+/// which is what [`crate::prp`] computes.
+const RESIDUE_TYPE_FERMAT_N_PLUS_1: u8 = 3;
+
+/// One result line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimeNetResult {
+    pub status: Status,
+    pub exponent: u64,
+    pub worktype: WorkType,
+    /// 16 uppercase hex digits. Absent for a Lucas–Lehmer prime, whose
+    /// residue is zero.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub res64: Option<String>,
+    /// PRP tests only; see [`RESIDUE_TYPE_FERMAT_N_PLUS_1`].
+    #[serde(rename = "residue-type", skip_serializing_if = "Option::is_none")]
+    pub residue_type: Option<u8>,
+    #[serde(rename = "shift-count")]
+    pub shift_count: u64,
+    /// Eight hex digits: the number of arithmetic errors the test caught,
+    /// [`TestReport::errors`].
+    #[serde(rename = "error-code")]
+    pub error_code: String,
+    pub program: Program,
+    /// UTC, as `YYYY-MM-DD HH:MM:SS`.
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub computer: Option<String>,
+    /// The assignment ID from the worktodo file, if the test was assigned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aid: Option<String>,
+}
+
+impl PrimeNetResult {
+    /// The result line for `report`, or `None` if there is nothing to
+    /// submit: the number is not a Mersenne number, the exponent was
+    /// factored, its test timed out or failed, or the runs of its
+    /// double-check disagreed and the result is in doubt.
+    ///
+    /// A test run with `--shift`, or that matched its double-check, is
+    /// reported with the shift of the shifted run, which produced the
+    /// residue.
+    pub fn from_report(
+        report: &TestReport,
+        identity: &Identity,
+        aid: Option<&str>,
+        timestamp: DateTime<Utc>,
+    ) -> Option<PrimeNetResult> {
+        let test = report.test?;
+        // PrimeNet only hands out and takes Mersenne numbers.
+        if report.form != Form::Mersenne
+            || report.is_factored()
+            || !report.has_result()
+            || report.is_mismatch()
+        {
+            return None;
+        }
+        let (worktype, residue_type) = match test {
+            TestKind::LucasLehmer => (WorkType::LucasLehmer, None),
+            TestKind::Prp => (WorkType::Prp3, Some(RESIDUE_TYPE_FERMAT_N_PLUS_1)),
+            TestKind::Pepin | TestKind::Llr => return None,
+        };
+        let shift_count = report.shift.unwrap_or(0);
+        Some(PrimeNetResult {
+            status: if report.prime {
+                Status::Prime
+            } else {
+                Status::Composite
+            },
+            exponent: report.exponent,
+            worktype,
+            res64: report.res64.clone(),
+            residue_type,
+            shift_count,
+            error_code: format!("{:08X}", report.errors),
+            program: Program::this(),
+            timestamp: timestamp.format("%Y-%m-%d %H:%M:%S").to_string(),
+            user: identity.user.clone(),
+            computer: identity.computer.clone(),
+            aid: aid.map(str::to_string),
+        })
+    }
+}
+
+/// A PrimeNet results file opened for appending.
+#[derive(Debug)]
+pub struct PrimeNetFile {
+    file: File,
+}
+
+impl PrimeNetFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<PrimeNetFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(PrimeNetFile { file })
+    }
+
+    /// Appends `result` as one line and flushes it to disk straight away.
+    pub fn record(&mut self, result: &PrimeNetResult) -> io::Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(result)?)?;
+        self.file.flush()?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{format_res64, DoubleCheck, FactoringStage};
+    use chrono::TimeZone;
+
+    fn report(exponent: u64, prime: bool, test: TestKind, res64: Option<u64>) -> TestReport {
+        TestReport {
+            prime,
+            test: Some(test),
+            res64: res64.map(format_res64),
+            ..TestReport::lucas_lehmer(exponent, 1.0)
+        }
+    }
+
+    fn line(report: &TestReport, identity: &Identity, aid: Option<&str>) -> Option<String> {
+        let at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        PrimeNetResult::from_report(report, identity, aid, at)
+            .map(|result| serde_json::to_string(&result).unwrap())
+    }
+
+    fn program() -> String {
+        format!(
+            r#""program":{{"name":"Mersenne","version":"{}"}}"#,
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+
+    #[test]
+    fn lucas_lehmer_lines() {
+        let composite = report(
+            86249,
+            false,
+            TestKind::LucasLehmer,
+            Some(0x6C3A7B1B62B247C2),
+        );
+        assert_eq!(
+            line(&composite, &Identity::default(), None).unwrap(),
+            format!(
+                r#"{{"status":"C","exponent":86249,"worktype":"LL","res64":"6C3A7B1B62B247C2","shift-count":0,"error-code":"00000000",{},"timestamp":"2024-05-01 12:00:00"}}"#,
+                program()
+            )
+        );
+        let prime = report(86243, true, TestKind::LucasLehmer, None);
+        assert_eq!(
+            line(&prime, &Identity::default(), None).unwrap(),
+            format!(
+                r#"{{"status":"P","exponent":86243,"worktype":"LL","shift-count":0,"error-code":"00000000",{},"timestamp":"2024-05-01 12:00:00"}}"#,
+                program()
+            )
+        );
+        let recovered = TestReport {
+            errors: 26,
+            ..prime
+        };
+        assert!(line(&recovered, &Identity::default(), None)
+            .unwrap()
+            .contains(r#""error-code":"0000001A""#));
+    }
+
+    #[test]
+    fn prp_lines_carry_the_residue_type() {
+        let prp = report(86243, true, TestKind::Prp, Some(9));
+        assert!(line(&prp, &Identity::default(), None).unwrap().starts_with(
+            r#"{"status":"P","exponent":86243,"worktype":"PRP-3","res64":"0000000000000009","residue-type":3,"shift-count":0,"#
+        ));
+    }
+
+    #[test]
+    fn identity_and_assignment_id_come_last() {
+        let identity = Identity {
+            user: Some("alice".to_string()),
+            computer: Some("box1".to_string()),
+        };
+        let composite = report(86249, false, TestKind::LucasLehmer, Some(1));
+        let aid = "0123456789ABCDEF0123456789ABCDEF";
+        assert!(line(&composite, &identity, Some(aid)).unwrap().ends_with(
+            r#""timestamp":"2024-05-01 12:00:00","user":"alice","computer":"box1","aid":"0123456789ABCDEF0123456789ABCDEF"}"#
+        ));
+    }
+
+    #[test]
+    fn shifted_runs_report_their_shift() {
+        let matched = TestReport {
+            shift: Some(1234),
+            double_check: Some(DoubleCheck::Match),
+            ..report(86249, false, TestKind::LucasLehmer, Some(1))
+        };
+        assert!(line(&matched, &Identity::default(), None)
+            .unwrap()
+            .contains(r#""shift-count":1234,"#));
+        let mismatched = TestReport {
+            double_check: Some(DoubleCheck::Mismatch),
+            ..matched.clone()
+        };
+        assert_eq!(line(&mismatched, &Identity::default(), None), None);
+        let shifted = TestReport {
+            double_check: None,
+            ..matched.clone()
+        };
+        assert!(line(&shifted, &Identity::default(), None)
+            .unwrap()
+            .contains(r#""shift-count":1234,"#));
+    }
+
+    #[test]
+    fn factored_timed_out_and_failed_exponents_have_no_line() {
+        let factored = TestReport {
+            test: None,
+            res64: None,
+            factor: Some("223".to_string()),
+            factor_stage: Some(FactoringStage::TrialFactoring),
+            ..report(37, false, TestKind::LucasLehmer, None)
+        };
+        assert_eq!(line(&factored, &Identity::default(), None), None);
+        let timed_out = TestReport {
+            timed_out_at: Some(1000),
+            ..report(86243, false, TestKind::LucasLehmer, None)
+        };
+        assert_eq!(line(&timed_out, &Identity::default(), None), None);
+        let failed = TestReport {
+            failed_at: Some(1000),
+            ..report(86243, false, TestKind::LucasLehmer, None)
+        };
+        assert_eq!(line(&failed, &Identity::default(), None), None);
+    }
+
+    #[test]
+    fn appends_one_line_per_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.json.txt");
+        let at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let result = PrimeNetResult::from_report(
+            &report(89, true, TestKind::LucasLehmer, None),
+            &Identity::default(),
+            None,
+            at,
+        )
+        .unwrap();
+        for _ in 0..2 {
+            PrimeNetFile::open(&path).unwrap().record(&result).unwrap();
+        }
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<PrimeNetResult>(lines[1]).unwrap(),
+            result
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Per-exponent progress display for `-vv` runs.
+//!
+//! On a terminal every in-flight exponent gets its own bar, so parallel tests
+//! no longer overwrite each other's `\r` lines. When stderr is not a
+//! terminal, progress is written to it as periodic plain lines instead.
+//! Progress never goes to stdout, which is kept for results.
+//!
+//! The bars are headed by an `overall` one for the whole run, filled by
+//! the share of its work done, as the heartbeat lines reckon it.
+//!
+//! Both show the speed of the latest progress interval and of the last
+//! `--rate-window` intervals, with the time left at the latter. Whether or
+//! not progress is shown, a test whose speed suddenly falls by more than
+//! `--slowdown-warning` percent gets a warning, and the speeds go in its
+//! report.
+
+use crate::logging;
+use chrono::Local;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::warn;
+use mersenne::humanize::{group_digits_with, humanize_duration};
+use mersenne::report::Form;
+use mersenne::throughput::{RateMeter, Throughput};
+use mersenne::Progress;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// How often a plain-text progress line is written for each exponent.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the `overall` bar is brought up to date.
+pub const OVERALL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The steps of the `overall` bar, a tenth of a percent each.
+const OVERALL_STEPS: u64 = 1000;
+
+pub struct ProgressDisplay {
+    mode: Mode,
+    /// The bar for the whole run, above those of the exponents.
+    overall: Option<ProgressBar>,
+    /// How many progress intervals speeds are averaged over.
+    window: usize,
+    /// The sudden fall in speed, in percent, that gets a warning.
+    slowdown_warning: u32,
+}
+
+enum Mode {
+    Off,
+    Bars(MultiProgress),
+    Plain,
+}
+
+impl ProgressDisplay {
+    pub fn new(enabled: bool, window: usize, slowdown_warning: u32) -> ProgressDisplay {
+        let mut overall = None;
+        let mode = if !enabled {
+            Mode::Off
+        } else if io::stderr().is_terminal() {
+            let bars = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+            logging::attach_progress(&bars);
+            let bar = bars.add(ProgressBar::new(OVERALL_STEPS));
+            bar.set_style(
+                ProgressStyle::with_template("{prefix} [{bar:30}] {msg}")
+                    .unwrap()
+                    .progress_chars("=> "),
+            );
+            bar.set_prefix("overall");
+            bar.set_message("counting the work of the run");
+            overall = Some(bar);
+            Mode::Bars(bars)
+        } else {
+            Mode::Plain
+        };
+        ProgressDisplay {
+            mode,
+            overall,
+            window,
+            slowdown_warning,
+        }
+    }
+
+    /// Runs `f` with the bars hidden, so lines it prints are not mangled.
+    /// Log messages hide the bars by themselves and must not be written
+    /// from `f`.
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        match &self.mode {
+            Mode::Bars(multi) => multi.suspend(f),
+            _ => f(),
+        }
+    }
+
+    /// Whether there is an `overall` bar for [`ProgressDisplay::overall`]
+    /// to fill.
+    pub fn has_overall(&self) -> bool {
+        self.overall.is_some()
+    }
+
+    /// Fills the `overall` bar to `percent` of the run's work, if it is
+    /// known yet.
+    pub fn overall(&self, percent: Option<f64>) {
+        if let (Some(bar), Some(percent)) = (&self.overall, percent) {
+            bar.set_position((percent * OVERALL_STEPS as f64 / 100.0).round() as u64);
+            bar.set_message(format!("{:.1}% of the work done", percent));
+        }
+    }
+
+    /// Starts tracking a test of the `form` number of exponent `p`.
+    pub fn start(&self, form: Form, p: u64) -> ExponentProgress<'_> {
+        let bar = match &self.mode {
+            Mode::Bars(multi) => {
+                let bar = multi.add(ProgressBar::new(p.saturating_sub(2)));
+                bar.set_style(
+                    ProgressStyle::with_template("{prefix} [{bar:30}] {percent:>3}% {msg}")
+                        .unwrap()
+                        .progress_chars("=> "),
+                );
+                bar.set_prefix(form.number(p));
+                Some(bar)
+            }
+            _ => None,
+        };
+        ExponentProgress {
+            display: self,
+            name: form.number(p),
+            bar,
+            meter: RateMeter::new(self.window, self.slowdown_warning),
+            last_line: Instant::now(),
+        }
+    }
+}
+
+pub struct ExponentProgress<'a> {
+    display: &'a ProgressDisplay,
+    /// The number under test, such as `M(31)`.
+    name: String,
+    bar: Option<ProgressBar>,
+    meter: RateMeter,
+    last_line: Instant,
+}
+
+impl ExponentProgress<'_> {
+    pub fn update(&mut self, progress: Progress) {
+        let Progress {
+            iteration, total, ..
+        } = progress;
+        let rate = self.meter.record(&progress);
+        if let Some((rate, fall)) = rate.and_then(|rate| Some((rate, rate.slowdown?))) {
+            warn!(
+                "{} slowed down to {} it/s, {:.0}% below its speed over the last {} progress intervals.",
+                self.name,
+                group(rate.current.round() as u64),
+                fall,
+                self.display.window
+            );
+        }
+        // Until a run has two reports, its speed is the average since it
+        // started.
+        let (current, average, remaining) = match rate {
+            Some(rate) => {
+                let remaining = rate
+                    .remaining
+                    .map_or(f64::INFINITY, |left| left.as_secs_f64());
+                (rate.current, rate.average, remaining)
+            }
+            None => {
+                let rate = iteration as f64 / progress.elapsed.as_secs_f64().max(f64::EPSILON);
+                (
+                    rate,
+                    rate,
+                    (total - iteration) as f64 / rate.max(f64::EPSILON),
+                )
+            }
+        };
+        let speeds = format!(
+            "{} it/s now, {} it/s average, ETA {}",
+            group(current.round() as u64),
+            group(average.round() as u64),
+            format_duration(remaining)
+        );
+        match &self.display.mode {
+            Mode::Off => {}
+            Mode::Bars(_) => {
+                if let Some(bar) = &self.bar {
+                    bar.set_length(total);
+                    bar.set_position(iteration);
+                    bar.set_message(format!("{} ({})", speeds, Local::now().format("%H:%M:%S")));
+                }
+            }
+            Mode::Plain => {
+                if self.last_line.elapsed() < PLAIN_INTERVAL || iteration == total {
+                    return;
+                }
+                self.last_line = Instant::now();
+                eprintln!(
+                    "{} Testing {}: {}% ({})",
+                    Local::now().format("%H:%M:%S"),
+                    self.name,
+                    iteration * 100 / total,
+                    speeds
+                );
+            }
+        }
+    }
+
+    /// The speeds of the test so far, for its report.
+    pub fn throughput(&self) -> Option<Throughput> {
+        self.meter.throughput()
+    }
+}
+
+impl Drop for ProgressDisplay {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.overall {
+            bar.finish_and_clear();
+        }
+        if let Mode::Bars(_) = self.mode {
+            logging::detach_progress();
+        }
+    }
+}
+
+impl Drop for ExponentProgress<'_> {
+    fn drop(&mut self) {
+        if let (Some(bar), Mode::Bars(multi)) = (&self.bar, &self.display.mode) {
+            bar.finish_and_clear();
+            multi.remove(bar);
+        }
+    }
+}
+
+/// A spinner on stderr for a long step whose progress cannot be measured,
+/// such as a decimal conversion. Hidden unless stderr is a terminal.
+pub fn spinner(message: String) -> ProgressBar {
+    let bar = if io::stderr().is_terminal() {
+        ProgressBar::new_spinner()
+    } else {
+        ProgressBar::hidden()
+    };
+    bar.set_style(ProgressStyle::with_template("{spinner} {msg} [{elapsed}]").unwrap());
+    bar.set_message(message);
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+/// Formats a duration for humans, as in
+/// [`humanize_duration`]: `340ms`, `42.0s`, `5m 3s` or `8d 12h 3m 1s`.
+pub fn format_duration(seconds: f64) -> String {
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => humanize_duration(duration),
+        Err(_) => "?".to_string(),
+    }
+}
+
+/// Formats [work units](mersenne::report::work_units) for humans: `12.7`
+/// from ten on, three significant digits below, as in `0.0427`, and
+/// `<0.000001` for the work of the smallest exponents.
+pub fn format_work(units: f64) -> String {
+    if units == 0.0 {
+        return "0".to_string();
+    }
+    if units < 0.000001 {
+        return "<0.000001".to_string();
+    }
+    if units >= 10.0 {
+        return format!("{:.1}", units);
+    }
+    let decimals = (2.0 - units.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", decimals, units)
+}
+
+static DIGIT_SEPARATOR: OnceLock<String> = OnceLock::new();
+
+/// Sets, once at start-up, what [`group`] puts between groups of digits:
+/// `--digit-separator`, or nothing with `--raw-numbers`.
+pub fn init_digit_separator(separator: &str) {
+    let _ = DIGIT_SEPARATOR.set(separator.to_string());
+}
+
+/// `n` with its digits grouped for humans, as in `25,860,001`.
+pub fn group(n: u64) -> String {
+    group_digits_with(n, DIGIT_SEPARATOR.get().map_or(",", String::as_str))
+}
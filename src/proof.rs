@@ -0,0 +1,692 @@
+//! Proofs of PRP results, in the style of Pietrzak's verifiable delay
+//! function, which let anyone check a PRP test with a fraction of a
+//! percent of its work.
+//!
+//! A PRP test computes `u_t = 3^(2^t)` by squaring. With power `n`, the
+//! test keeps `u` after every `L = p / 2^n` iterations, and the proof
+//! shows that `B = u_T`, for `T = 2^n · L`, is 3 squared `T` times. It
+//! holds `B` and `n` middles `μ_1..μ_n`. For the claim `y = x^(2^t)`, with
+//! `x = 3`, `y = B` and `t = T` at first, `μ` is the midpoint
+//! `x^(2^(t/2))`; a challenge `r` hashed from everything so far then folds
+//! both halves into the single claim
+//!
+//! ```text
+//! (x^r · μ)^(2^(t/2)) = μ^r · y
+//! ```
+//!
+//! of half the length. After `n` rounds the verifier checks the last
+//! claim by `L` squarings, then squares `B` the `p - T` times left and
+//! judges the result as the test would.
+//!
+//! The challenges come from SHA-256: of the file's header and `B` for the
+//! first, then of the hash before and each `μ`, with `r` the first eight
+//! bytes of a hash, little-endian. A prover who does not know `r` before
+//! committing to `μ` cannot make a wrong `B` pass.
+//!
+//! The file is the text header of [`Proof::header`] followed by `B` and
+//! the middles, each in `ceil(bits / 8)` little-endian bytes, where `bits`
+//! is the size of the modulus. It follows the layout of the PRP proofs of
+//! GIMPS clients but is not byte-compatible with them, and only this
+//! program reads it.
+
+use crate::arith::{MersenneModulus, WagstaffModulus};
+use crate::audit::Milestone;
+use crate::prp::{PrpModulus, PrpResult};
+use crate::report::Form;
+use num_bigint::BigUint;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The power used unless another is asked for: 256 residues kept, and a
+/// check that costs about 1/256 of the test.
+pub const DEFAULT_POWER: u32 = 8;
+
+/// The largest power: 4096 residues kept.
+pub const MAX_POWER: u32 = 12;
+
+/// The version of the format written by [`Proof::to_bytes`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The power of a proof of the test of exponent `p` asked to have
+/// `power`: lower for exponents too small to split `2^power` ways.
+pub fn effective_power(p: u64, power: u32) -> u32 {
+    power.min(p.max(1).ilog2())
+}
+
+/// The iterations between the residues a test of exponent `p` must keep
+/// for a proof of `power`, as [`Milestone`]s.
+pub fn residue_interval(p: u64, power: u32) -> u64 {
+    p >> effective_power(p, power)
+}
+
+/// The file name of the proof of `p`, such as `M86243.proof`.
+pub fn file_name(form: Form, p: u64) -> String {
+    format!("{}{}.proof", letter(form), p)
+}
+
+fn letter(form: Form) -> &'static str {
+    match form {
+        Form::Wagstaff => "W",
+        _ => "M",
+    }
+}
+
+/// A proof that the PRP test of `form` and `exponent` reached `end` after
+/// [`iterations`](Proof::iterations) squarings of 3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub form: Form,
+    pub exponent: u64,
+    pub power: u32,
+    /// `B`, the residue after [`iterations`](Proof::iterations).
+    pub end: BigUint,
+    /// `μ_1..μ_power`.
+    pub middles: Vec<BigUint>,
+}
+
+/// Why a proof could not be built or read.
+#[derive(Debug)]
+pub enum ProofError {
+    Io(io::Error),
+    /// Only Mersenne and Wagstaff numbers have PRP tests.
+    UnsupportedForm(Form),
+    /// The test did not report the residue after this iteration.
+    MissingResidue(u64),
+    /// The file is not a proof this program wrote.
+    Malformed(String),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::Io(e) => write!(f, "I/O error: {}", e),
+            ProofError::UnsupportedForm(form) => {
+                write!(f, "{} numbers have no PRP proofs", form)
+            }
+            ProofError::MissingResidue(iteration) => {
+                write!(f, "the residue after iteration {} is missing", iteration)
+            }
+            ProofError::Malformed(problem) => write!(f, "not a valid proof file: {}", problem),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+impl From<io::Error> for ProofError {
+    fn from(e: io::Error) -> Self {
+        ProofError::Io(e)
+    }
+}
+
+/// The arithmetic of the PRP test of `form` and `p`.
+fn modulus(form: Form, p: u64) -> Result<Box<dyn PrpModulus>, ProofError> {
+    match form {
+        Form::Mersenne if p >= 2 => Ok(Box::new(MersenneModulus::new(p))),
+        Form::Wagstaff if p >= 3 && !p.is_multiple_of(2) => Ok(Box::new(WagstaffModulus::new(p))),
+        Form::Mersenne | Form::Wagstaff => Err(ProofError::Malformed(format!(
+            "{} has no PRP test",
+            form.number(p)
+        ))),
+        _ => Err(ProofError::UnsupportedForm(form)),
+    }
+}
+
+impl Proof {
+    /// Builds the proof of power `power` of the PRP test of `form` and
+    /// `p` from `residues`, which must hold the residue after every
+    /// [`residue_interval`] iterations up to the proof's own
+    /// [`iterations`](Proof::iterations). Costs about `100 · 2^power`
+    /// multiplications.
+    pub fn build(
+        form: Form,
+        p: u64,
+        power: u32,
+        residues: &[Milestone],
+    ) -> Result<Proof, ProofError> {
+        let modulus = modulus(form, p)?;
+        let power = effective_power(p, power);
+        let interval = residue_interval(p, power);
+        let by_iteration: HashMap<u64, &BigUint> = residues
+            .iter()
+            .map(|milestone| (milestone.iteration, &milestone.residue))
+            .collect();
+        let kept = (0..=1u64 << power)
+            .map(|k| {
+                by_iteration
+                    .get(&(k * interval))
+                    .copied()
+                    .ok_or(ProofError::MissingResidue(k * interval))
+            })
+            .collect::<Result<Vec<&BigUint>, ProofError>>()?;
+
+        let mut proof = Proof {
+            form,
+            exponent: p,
+            power,
+            end: kept[kept.len() - 1].clone(),
+            middles: Vec::new(),
+        };
+        let mut hash = proof.first_hash(modulus.as_ref());
+        let mut challenges = Vec::new();
+        for round in 1..=power {
+            let middle = middle(modulus.as_ref(), &kept, power, round, &challenges);
+            hash = next_hash(modulus.as_ref(), &hash, &middle);
+            challenges.push(challenge(&hash));
+            proof.middles.push(middle);
+        }
+        Ok(proof)
+    }
+
+    /// `T`, the squarings of 3 that reach [`end`](Proof::end).
+    pub fn iterations(&self) -> u64 {
+        residue_interval(self.exponent, self.power) << self.power
+    }
+
+    /// The text the file starts with.
+    pub fn header(&self) -> String {
+        format!(
+            "PRP PROOF\nVERSION={}\nHASHSIZE=64\nPOWER={}\nNUMBER={}{}\n",
+            FORMAT_VERSION,
+            self.power,
+            letter(self.form),
+            self.exponent
+        )
+    }
+
+    /// The hash the challenges start from.
+    fn first_hash(&self, modulus: &dyn PrpModulus) -> [u8; 32] {
+        let mut data = self.header().into_bytes();
+        data.extend(encode(modulus, &self.end));
+        sha256(&data)
+    }
+
+    /// The challenges `r_1..r_power`, one for each middle.
+    fn challenges(&self, modulus: &dyn PrpModulus) -> Vec<u64> {
+        let mut hash = self.first_hash(modulus);
+        self.middles
+            .iter()
+            .map(|middle| {
+                hash = next_hash(modulus, &hash, middle);
+                challenge(&hash)
+            })
+            .collect()
+    }
+
+    /// The result of the test the proof is of, if the proof holds, or
+    /// `None` if it does not.
+    pub fn verify(&self) -> Result<Option<PrpResult>, ProofError> {
+        let modulus = modulus(self.form, self.exponent)?;
+        let modulus = modulus.as_ref();
+        if self.middles.len() != self.power as usize {
+            return Ok(None);
+        }
+        let mut x = BigUint::from(3u32);
+        let mut y = self.end.clone();
+        for (middle, r) in self.middles.iter().zip(self.challenges(modulus)) {
+            x = modulus.mul(&pow(modulus, &x, r), middle);
+            y = modulus.mul(&pow(modulus, middle, r), &y);
+        }
+        for _ in 0..residue_interval(self.exponent, self.power) {
+            x = modulus.square(&x);
+        }
+        if x != y {
+            return Ok(None);
+        }
+        let mut end = self.end.clone();
+        for _ in self.iterations()..modulus.iterations() {
+            end = modulus.square(&end);
+        }
+        Ok(Some(modulus.verdict(&end)))
+    }
+
+    /// The proof as the bytes of its file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let modulus = modulus(self.form, self.exponent).expect("a proof has a PRP test");
+        let mut bytes = self.header().into_bytes();
+        bytes.extend(encode(modulus.as_ref(), &self.end));
+        for middle in &self.middles {
+            bytes.extend(encode(modulus.as_ref(), middle));
+        }
+        bytes
+    }
+
+    /// Parses the bytes of a proof file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Proof, ProofError> {
+        let malformed = |problem: &str| ProofError::Malformed(problem.to_string());
+        let mut rest = bytes;
+        let mut line = |expected: &str| -> Result<String, ProofError> {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .ok_or_else(|| malformed("the header is cut short"))?;
+            let text = std::str::from_utf8(&rest[..end])
+                .map_err(|_| malformed("the header is not text"))?;
+            rest = &rest[end + 1..];
+            match expected.strip_suffix('=') {
+                Some(_) => text.strip_prefix(expected).map(str::to_string),
+                None => (text == expected).then(String::new),
+            }
+            .ok_or_else(|| {
+                ProofError::Malformed(format!("expected {}, found {:?}", expected, text))
+            })
+        };
+
+        line("PRP PROOF")?;
+        let version = line("VERSION=")?;
+        if version != FORMAT_VERSION.to_string() {
+            return Err(ProofError::Malformed(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+        line("HASHSIZE=64")?;
+        let power: u32 = line("POWER=")?
+            .parse()
+            .map_err(|_| malformed("the power is not a number"))?;
+        let number = line("NUMBER=")?;
+        let (form, exponent) = match number.split_at_checked(1) {
+            Some(("M", exponent)) => (Form::Mersenne, exponent),
+            Some(("W", exponent)) => (Form::Wagstaff, exponent),
+            _ => {
+                return Err(ProofError::Malformed(format!(
+                    "unknown number {:?}",
+                    number
+                )))
+            }
+        };
+        let exponent: u64 = exponent
+            .parse()
+            .map_err(|_| ProofError::Malformed(format!("unknown number {:?}", number)))?;
+        if power > MAX_POWER || power != effective_power(exponent, power) {
+            return Err(ProofError::Malformed(format!(
+                "power {} does not suit {}",
+                power,
+                form.number(exponent)
+            )));
+        }
+
+        let modulus = modulus(form, exponent)?;
+        let width = width(modulus.as_ref());
+        let expected = width * (power as usize + 1);
+        if rest.len() != expected {
+            return Err(ProofError::Malformed(format!(
+                "{} bytes of residues where {} belong",
+                rest.len(),
+                expected
+            )));
+        }
+        let mut residues = rest.chunks(width).map(BigUint::from_bytes_le);
+        let end = residues.next().unwrap();
+        let middles: Vec<BigUint> = residues.collect();
+        if std::iter::once(&end)
+            .chain(&middles)
+            .any(|residue| residue >= modulus.modulus())
+        {
+            return Err(malformed("a residue is not reduced"));
+        }
+        Ok(Proof {
+            form,
+            exponent,
+            power,
+            end,
+            middles,
+        })
+    }
+
+    /// Writes the proof to `path`, through a temporary file renamed into
+    /// place, so a half-written proof never appears under its name.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("proof.tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+
+    /// Reads the proof file at `path`.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Proof, ProofError> {
+        Proof::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// `μ_round` for a proof of `power` over `kept`, the residues `u_0..u_(2^power)`,
+/// given the challenges `r_1..r_(round-1)` of the rounds before.
+///
+/// The claim of round `round` starts from `x'`, a product of the `x` of the
+/// earlier rounds, so its midpoint is a product of powers of kept
+/// residues: `u_k` for the odd multiples `k` of `2^(power - round)`, each
+/// raised to the product of the challenges of the rounds whose half it is
+/// not in. Pairing them up, round by round from the last, takes one
+/// exponentiation by a challenge per pair.
+fn middle(
+    modulus: &dyn PrpModulus,
+    kept: &[&BigUint],
+    power: u32,
+    round: u32,
+    challenges: &[u64],
+) -> BigUint {
+    let step = 1 << (power - round);
+    let mut level: Vec<BigUint> = (0..1usize << (round - 1))
+        .map(|m| kept[step * (2 * m + 1)].clone())
+        .collect();
+    for &r in challenges.iter().rev() {
+        level = level
+            .chunks(2)
+            .map(|pair| modulus.mul(&pow(modulus, &pair[0], r), &pair[1]))
+            .collect();
+    }
+    level.pop().unwrap()
+}
+
+/// `x^e`.
+fn pow(modulus: &dyn PrpModulus, x: &BigUint, e: u64) -> BigUint {
+    let mut result = BigUint::from(1u32);
+    for bit in (0..u64::BITS - e.leading_zeros()).rev() {
+        result = modulus.square(&result);
+        if e >> bit & 1 == 1 {
+            result = modulus.mul(&result, x);
+        }
+    }
+    result
+}
+
+/// The bytes of a residue when encoded.
+fn width(modulus: &dyn PrpModulus) -> usize {
+    modulus.modulus().bits().div_ceil(8) as usize
+}
+
+/// `x` in [`width`] little-endian bytes.
+fn encode(modulus: &dyn PrpModulus, x: &BigUint) -> Vec<u8> {
+    let mut bytes = x.to_bytes_le();
+    bytes.resize(width(modulus), 0);
+    bytes
+}
+
+/// The hash after `hash` and the middle `μ`.
+fn next_hash(modulus: &dyn PrpModulus, hash: &[u8; 32], middle: &BigUint) -> [u8; 32] {
+    let mut data = hash.to_vec();
+    data.extend(encode(modulus, middle));
+    sha256(&data)
+}
+
+/// The challenge a hash gives: its first eight bytes, little-endian.
+fn challenge(hash: &[u8; 32]) -> u64 {
+    u64::from_le_bytes(hash[..8].try_into().unwrap())
+}
+
+/// SHA-256 of `data`, per FIPS 180-4.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut hash = [0; 32];
+    for (bytes, word) in hash.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prp::{prp_test_interruptible, wagstaff_prp_test_interruptible};
+    use crate::{TestControl, TestEvent};
+    use std::sync::atomic::AtomicBool;
+
+    /// The residues a PRP test of `form` and `p` keeps for a proof of
+    /// `power`.
+    fn residues(form: Form, p: u64, power: u32) -> Vec<Milestone> {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).record_milestones_every(residue_interval(p, power));
+        let mut residues = Vec::new();
+        let on_event = |event: TestEvent| {
+            if let TestEvent::Milestone { iteration, residue } = event {
+                residues.push(Milestone {
+                    iteration,
+                    residue: residue.clone(),
+                });
+            }
+        };
+        match form {
+            Form::Wagstaff => wagstaff_prp_test_interruptible(p, control, on_event),
+            _ => prp_test_interruptible(p, control, on_event),
+        }
+        .unwrap();
+        residues
+    }
+
+    fn hex(hash: [u8; 32]) -> String {
+        hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn sha256_matches_the_standard() {
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn middles_match_hand_computed_ones() {
+        // M(13) with power 2: L = 3, and u_k = 3^(2^(3k)) mod 8191.
+        let modulus = MersenneModulus::new(13);
+        let u: Vec<BigUint> = [3u32, 6561, 125, 4749, 8188]
+            .into_iter()
+            .map(BigUint::from)
+            .collect();
+        let kept: Vec<&BigUint> = u.iter().collect();
+        assert_eq!(middle(&modulus, &kept, 2, 1, &[]), BigUint::from(125u32));
+        // With r_1 = 5, x_1 = 3^5 · u_2, whose midpoint is u_1^5 · u_3.
+        assert_eq!(middle(&modulus, &kept, 2, 2, &[5]), BigUint::from(4995u32));
+    }
+
+    #[test]
+    fn challenges_match_hand_computed_ones() {
+        let proof = Proof::build(Form::Mersenne, 13, 2, &residues(Form::Mersenne, 13, 2)).unwrap();
+        assert_eq!(proof.iterations(), 12);
+        assert_eq!(proof.end, BigUint::from(8188u32));
+        assert_eq!(
+            proof.middles,
+            vec![BigUint::from(125u32), BigUint::from(5964u32)]
+        );
+        let modulus = MersenneModulus::new(13);
+        assert_eq!(
+            proof.challenges(&modulus),
+            vec![0x9f8f_f8ac_c12f_04f5, 0xec02_164b_933f_2c24]
+        );
+        assert_eq!(
+            proof.verify().unwrap(),
+            Some(PrpResult::ProbablePrime { res64: 9 })
+        );
+    }
+
+    #[test]
+    fn each_middle_is_the_midpoint_of_its_round() {
+        let (p, power) = (1277, 4);
+        let proof = Proof::build(
+            Form::Mersenne,
+            p,
+            power,
+            &residues(Form::Mersenne, p, power),
+        )
+        .unwrap();
+        let modulus = MersenneModulus::new(p);
+        let mut x = BigUint::from(3u32);
+        let mut length = proof.iterations();
+        for (middle, r) in proof.middles.iter().zip(proof.challenges(&modulus)) {
+            length /= 2;
+            let mut midpoint = x.clone();
+            for _ in 0..length {
+                midpoint = modulus.square(&midpoint);
+            }
+            assert_eq!(&midpoint, middle);
+            x = modulus.mul(&pow(&modulus, &x, r), middle);
+        }
+    }
+
+    #[test]
+    fn proofs_verify_to_the_test_result() {
+        let cases = [
+            (Form::Mersenne, 1279, DEFAULT_POWER),
+            (Form::Mersenne, 1277, DEFAULT_POWER),
+            (Form::Mersenne, 101, 3),
+            (Form::Mersenne, 3, DEFAULT_POWER),
+            (Form::Wagstaff, 127, 5),
+            (Form::Wagstaff, 131, 5),
+        ];
+        for (form, p, power) in cases {
+            let proof = Proof::build(form, p, power, &residues(form, p, power)).unwrap();
+            let expected = match form {
+                Form::Wagstaff => crate::prp::wagstaff_prp_test(p),
+                _ => crate::prp::prp_test(p),
+            };
+            assert_eq!(
+                proof.verify().unwrap(),
+                Some(expected),
+                "{}",
+                form.number(p)
+            );
+            assert_eq!(Proof::from_bytes(&proof.to_bytes()).unwrap(), proof);
+        }
+        assert_eq!(
+            Proof::build(
+                Form::Mersenne,
+                3,
+                DEFAULT_POWER,
+                &residues(Form::Mersenne, 3, 8)
+            )
+            .unwrap()
+            .power,
+            1
+        );
+    }
+
+    #[test]
+    fn tampered_proofs_fail() {
+        let proof =
+            Proof::build(Form::Mersenne, 1279, 6, &residues(Form::Mersenne, 1279, 6)).unwrap();
+        let mut wrong_end = proof.clone();
+        wrong_end.end += 1u32;
+        assert_eq!(wrong_end.verify().unwrap(), None);
+        for round in 0..proof.middles.len() {
+            let mut wrong_middle = proof.clone();
+            wrong_middle.middles[round] += 1u32;
+            assert_eq!(wrong_middle.verify().unwrap(), None, "round {}", round + 1);
+        }
+        let mut short = proof.clone();
+        short.middles.pop();
+        assert_eq!(short.verify().unwrap(), None);
+
+        assert!(matches!(
+            Proof::build(Form::Mersenne, 1279, 6, &residues(Form::Mersenne, 1279, 5)),
+            Err(ProofError::MissingResidue(_))
+        ));
+        assert!(matches!(
+            Proof::build(Form::Fermat, 4, 2, &[]),
+            Err(ProofError::UnsupportedForm(Form::Fermat))
+        ));
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let proof =
+            Proof::build(Form::Mersenne, 127, 3, &residues(Form::Mersenne, 127, 3)).unwrap();
+        let bytes = proof.to_bytes();
+        assert!(bytes.starts_with(b"PRP PROOF\nVERSION=1\nHASHSIZE=64\nPOWER=3\nNUMBER=M127\n"));
+        assert_eq!(bytes.len(), proof.header().len() + 4 * 16);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(file_name(Form::Mersenne, 127));
+        proof.write(&path).unwrap();
+        assert_eq!(Proof::read(&path).unwrap(), proof);
+
+        let malformed =
+            |bytes: &[u8]| matches!(Proof::from_bytes(bytes), Err(ProofError::Malformed(_)));
+        assert!(malformed(&bytes[..bytes.len() - 1]));
+        assert!(malformed(b"PRP PROOF\nVERSION=2\n"));
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        assert!(malformed(text.replacen("POWER=3", "POWER=9", 1).as_bytes()));
+        assert!(malformed(text.replacen("M127", "F127", 1).as_bytes()));
+        let mut unreduced = bytes.clone();
+        let end = proof.header().len();
+        unreduced[end..end + 16].fill(0xff);
+        assert!(malformed(&unreduced));
+    }
+}
@@ -0,0 +1,582 @@
+//! Base-3 Fermat probable-prime testing of Mersenne numbers, with Gerbicz
+//! error checking.
+//!
+//! The test computes `x = 3^(2^p) mod M(p)` by `p` squarings. If `M(p)` is
+//! prime then `3^(M(p) - 1) ≡ 1`, which is the same as `x ≡ 9`.
+//!
+//! Unlike the Lucas–Lehmer sequence, the squaring chain can be verified as
+//! it goes. Every `B` iterations the current residue is multiplied into a
+//! running product `d`; since each saved residue is the previous one raised
+//! to `2^B`, the products satisfy `d_k = 3 · d_(k-1)^(2^B)`. Checking that
+//! identity costs `B` squarings and catches an error anywhere since the last
+//! check, in which case the test rolls back to the last verified state.
+//!
+//! Wagstaff numbers `W(p) = (2^p + 1) / 3` are tested the same way, with
+//! the squarings done modulo `2^p + 1 = 3·W(p)`, which reduces as cheaply
+//! as `M(p)` does. If `W(p)` is prime then `3^(W(p) - 1) ≡ 1 (mod W(p))`,
+//! and since `3·(W(p) - 1) = 2^p - 2`, again `x ≡ 9 (mod W(p))`. The
+//! Gerbicz identity holds modulo `2^p + 1` just as well.
+
+use crate::arith::{MersenneModulus, WagstaffModulus};
+use crate::{res64, Interrupted, Progress, TestControl, TestEvent};
+use num_bigint::BigUint;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+
+/// The outcome of a PRP test. Both variants carry the low 64 bits of
+/// `3^(2^p) mod M(p)`, or `mod W(p)` for a Wagstaff number, which is `9`
+/// for a probable prime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrpResult {
+    ProbablePrime { res64: u64 },
+    Composite { res64: u64 },
+}
+
+impl PrpResult {
+    pub fn is_probable_prime(&self) -> bool {
+        matches!(self, PrpResult::ProbablePrime { .. })
+    }
+
+    pub fn res64(&self) -> u64 {
+        match *self {
+            PrpResult::ProbablePrime { res64 } | PrpResult::Composite { res64 } => res64,
+        }
+    }
+}
+
+impl fmt::Display for PrpResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrpResult::ProbablePrime { res64 } => {
+                write!(f, "probable prime, Res64: 0x{:016X}", res64)
+            }
+            PrpResult::Composite { res64 } => write!(f, "composite, Res64: 0x{:016X}", res64),
+        }
+    }
+}
+
+/// How often residues are folded into the Gerbicz product and how often the
+/// product is verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GerbiczParams {
+    /// Iterations between residues multiplied into the product.
+    block: u64,
+    /// Blocks between verifications.
+    blocks_per_check: u64,
+}
+
+impl GerbiczParams {
+    /// Blocks of 1000 iterations checked every 1000 blocks, i.e. about every
+    /// million iterations, scaled down so small exponents still get a few
+    /// checks.
+    pub(crate) fn for_exponent(p: u64) -> GerbiczParams {
+        let block = p.isqrt().clamp(1, 1000);
+        GerbiczParams {
+            block,
+            blocks_per_check: (p / (block * 8)).clamp(1, 1000),
+        }
+    }
+}
+
+/// Runs a base-3 Fermat PRP test on `2^p - 1`.
+///
+/// `M(0)` and `M(1)` are reported as composite with a zero residue.
+pub fn prp_test(p: u64) -> PrpResult {
+    prp_test_with_events(p, |_| {})
+}
+
+/// Same as [`prp_test`], but reports progress and detected errors through
+/// `on_event`.
+pub fn prp_test_with_events<F>(p: u64, on_event: F) -> PrpResult
+where
+    F: FnMut(TestEvent),
+{
+    let never = AtomicBool::new(false);
+    match prp_test_interruptible(p, TestControl::new(&never), on_event) {
+        Ok(result) => result,
+        Err(_) => unreachable!("the stop flag is never raised"),
+    }
+}
+
+/// Same as [`prp_test_with_events`], but publishes its progress through
+/// `control` and gives up with [`Interrupted`] once `control.stop` is
+/// raised or its deadline passes. PRP tests keep no checkpoints, so the
+/// work done so far is lost.
+pub fn prp_test_interruptible<F>(
+    p: u64,
+    control: TestControl,
+    on_event: F,
+) -> Result<PrpResult, Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    if p < 2 {
+        return Ok(PrpResult::Composite { res64: 0 });
+    }
+    let modulus = MersenneModulus::new(p).with_threads(control.threads);
+    run(
+        &modulus,
+        GerbiczParams::for_exponent(p),
+        control,
+        on_event,
+        |_, _| {},
+    )
+}
+
+/// Runs a base-3 probable-prime test on the Wagstaff number
+/// `W(p) = (2^p + 1) / 3`.
+///
+/// `p` should be an odd prime; an even `p` or one below 3, for which
+/// `W(p)` is not an integer or not prime, is reported as composite with a
+/// zero residue.
+pub fn wagstaff_prp_test(p: u64) -> PrpResult {
+    let never = AtomicBool::new(false);
+    match wagstaff_prp_test_interruptible(p, TestControl::new(&never), |_| {}) {
+        Ok(result) => result,
+        Err(_) => unreachable!("the stop flag is never raised"),
+    }
+}
+
+/// Same as [`wagstaff_prp_test`], but with the events and interruption of
+/// [`prp_test_interruptible`].
+pub fn wagstaff_prp_test_interruptible<F>(
+    p: u64,
+    control: TestControl,
+    on_event: F,
+) -> Result<PrpResult, Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    if p < 3 || p.is_multiple_of(2) {
+        return Ok(PrpResult::Composite { res64: 0 });
+    }
+    let modulus = WagstaffModulus::new(p).with_threads(control.threads);
+    run(
+        &modulus,
+        GerbiczParams::for_exponent(p),
+        control,
+        on_event,
+        |_, _| {},
+    )
+}
+
+/// The arithmetic a PRP test runs in, and how its final residue is judged.
+pub(crate) trait PrpModulus {
+    /// The number of squarings of 3 the test does.
+    fn iterations(&self) -> u64;
+
+    /// The modulus the squarings are reduced by.
+    fn modulus(&self) -> &BigUint;
+
+    fn square(&self, x: &BigUint) -> BigUint;
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint;
+
+    /// The result for the final residue `x`.
+    fn verdict(&self, x: &BigUint) -> PrpResult;
+}
+
+impl PrpModulus for MersenneModulus {
+    fn iterations(&self) -> u64 {
+        MersenneModulus::p(self)
+    }
+
+    fn modulus(&self) -> &BigUint {
+        MersenneModulus::modulus(self)
+    }
+
+    fn square(&self, x: &BigUint) -> BigUint {
+        MersenneModulus::square(self, x)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        MersenneModulus::mul(self, a, b)
+    }
+
+    fn verdict(&self, x: &BigUint) -> PrpResult {
+        let res64 = res64(x);
+        // 3 divides M(2) = 3 itself, so base 3 cannot say anything about it.
+        if self.p() == 2 || *x == self.reduce_full(BigUint::from(9u32)) {
+            PrpResult::ProbablePrime { res64 }
+        } else {
+            PrpResult::Composite { res64 }
+        }
+    }
+}
+
+impl PrpModulus for WagstaffModulus {
+    fn iterations(&self) -> u64 {
+        WagstaffModulus::p(self)
+    }
+
+    fn modulus(&self) -> &BigUint {
+        WagstaffModulus::modulus(self)
+    }
+
+    fn square(&self, x: &BigUint) -> BigUint {
+        WagstaffModulus::square(self, x)
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        WagstaffModulus::mul(self, a, b)
+    }
+
+    fn verdict(&self, x: &BigUint) -> PrpResult {
+        let residue = x % (self.modulus() / 3u32);
+        let res64 = res64(&residue);
+        // W(3) = 3 itself, which base 3 cannot say anything about either.
+        if self.p() == 3 || residue == BigUint::from(9u32) {
+            PrpResult::ProbablePrime { res64 }
+        } else {
+            PrpResult::Composite { res64 }
+        }
+    }
+}
+
+/// The last state that passed a Gerbicz check.
+struct Verified {
+    iteration: u64,
+    x: BigUint,
+    d: BigUint,
+}
+
+/// The PRP test proper: [`PrpModulus::iterations`] squarings of 3, with
+/// Gerbicz checks. `fault` is called after every squaring and may tamper
+/// with the residue, so tests can check that errors are caught.
+///
+/// With [`TestControl::record_milestones_every`], the residue is sent
+/// before the first squaring, every so many iterations and after the last,
+/// for a [`proof`](crate::proof). After a
+/// [`GerbiczMismatch`](TestEvent::GerbiczMismatch) those past
+/// `resumed_from` are sent again.
+pub(crate) fn run<M, F, G>(
+    modulus: &M,
+    params: GerbiczParams,
+    control: TestControl,
+    mut on_event: F,
+    mut fault: G,
+) -> Result<PrpResult, Interrupted>
+where
+    M: PrpModulus,
+    F: FnMut(TestEvent),
+    G: FnMut(u64, &mut BigUint),
+{
+    let _timing = control.timing();
+    let p = modulus.iterations();
+    let three = BigUint::from(3u32);
+    let started = control.active_time();
+    let mut progress_due = control.progress_schedule(p);
+    let check_interval = params.block * params.blocks_per_check;
+    // Iterations past the last block boundary are not covered by a Gerbicz
+    // check, so they are computed twice instead.
+    let last_boundary = p / params.block * params.block;
+
+    let mut verified = Verified {
+        iteration: 0,
+        x: three.clone(),
+        d: three.clone(),
+    };
+    let mut x = three.clone();
+    let mut d = three.clone();
+    let mut i = 0;
+    let milestone = |i: u64| {
+        control
+            .milestone_interval
+            .is_some_and(|interval| i.is_multiple_of(interval) || i == p)
+    };
+    let res64_milestone = |i: u64| {
+        control
+            .res64_interval
+            .is_some_and(|interval| i.is_multiple_of(interval) || i == p)
+    };
+    if milestone(0) {
+        on_event(TestEvent::Milestone {
+            iteration: 0,
+            residue: &x,
+        });
+    }
+
+    while i < last_boundary {
+        if let Some(interrupted) = control.interruption(i, p) {
+            return Err(interrupted);
+        }
+        x = modulus.square(&x);
+        i += 1;
+        fault(i, &mut x);
+        control.publish(i);
+        if milestone(i) {
+            on_event(TestEvent::Milestone {
+                iteration: i,
+                residue: &x,
+            });
+        }
+        if res64_milestone(i) {
+            on_event(TestEvent::Res64 {
+                iteration: i,
+                res64: res64(&x),
+            });
+        }
+
+        if progress_due.due(i, || control.active_time()) {
+            on_event(TestEvent::Progress(Progress {
+                iteration: i,
+                total: p,
+                elapsed: control.active_time().saturating_sub(started),
+                current_res64_hint: Some(res64(&x)),
+            }));
+        }
+
+        if i % params.block == 0 {
+            let previous = d;
+            d = modulus.mul(&previous, &x);
+            if i % check_interval == 0 || i == last_boundary {
+                let mut expected = previous;
+                for _ in 0..params.block {
+                    expected = modulus.square(&expected);
+                }
+                expected = modulus.mul(&expected, &three);
+
+                if expected == d {
+                    verified = Verified {
+                        iteration: i,
+                        x: x.clone(),
+                        d: d.clone(),
+                    };
+                } else {
+                    on_event(TestEvent::GerbiczMismatch {
+                        iteration: i,
+                        resumed_from: verified.iteration,
+                    });
+                    i = verified.iteration;
+                    x = verified.x.clone();
+                    d = verified.d.clone();
+                }
+            }
+        }
+    }
+
+    let x = loop {
+        let first = finish(modulus, &x, i, p, &mut fault, &mut |iteration, x| {
+            control.publish(iteration);
+            if milestone(iteration) {
+                on_event(TestEvent::Milestone {
+                    iteration,
+                    residue: x,
+                });
+            }
+            if res64_milestone(iteration) {
+                on_event(TestEvent::Res64 {
+                    iteration,
+                    res64: res64(x),
+                });
+            }
+            if progress_due.due(iteration, || control.active_time()) || iteration == p {
+                on_event(TestEvent::Progress(Progress {
+                    iteration,
+                    total: p,
+                    elapsed: control.active_time().saturating_sub(started),
+                    current_res64_hint: Some(res64(x)),
+                }));
+            }
+        });
+        let second = finish(modulus, &x, i, p, &mut fault, &mut |_, _| {});
+        if first == second {
+            break first;
+        }
+        on_event(TestEvent::GerbiczMismatch {
+            iteration: p,
+            resumed_from: i,
+        });
+    };
+
+    Ok(modulus.verdict(&x))
+}
+
+/// Squares `x` from iteration `from` up to `to`.
+fn finish<M, G, P>(
+    modulus: &M,
+    x: &BigUint,
+    from: u64,
+    to: u64,
+    fault: &mut G,
+    progress: &mut P,
+) -> BigUint
+where
+    M: PrpModulus,
+    G: FnMut(u64, &mut BigUint),
+    P: FnMut(u64, &BigUint),
+{
+    let mut x = x.clone();
+    for i in from + 1..=to {
+        x = modulus.square(&x);
+        fault(i, &mut x);
+        progress(i, &x);
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agrees_with_lucas_lehmer() {
+        for p in 2..200 {
+            if crate::is_prime(p) {
+                assert_eq!(
+                    prp_test(p).is_probable_prime(),
+                    crate::is_mersenne_prime(p).is_prime(),
+                    "M({})",
+                    p
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn res64_values() {
+        let cases = [
+            (11, 0x3A1),
+            (23, 0x282D53),
+            (29, 0x2F51FA2),
+            (31, 0x9),
+            (101, 0x88F0E84ACB377468),
+            (1277, 0x42D83C4FEBE2BC55),
+        ];
+        for (p, res64) in cases {
+            assert_eq!(prp_test(p).res64(), res64, "M({})", p);
+        }
+    }
+
+    #[test]
+    fn finds_the_wagstaff_primes() {
+        let exponents = [
+            3, 5, 7, 11, 13, 17, 19, 23, 31, 43, 61, 79, 101, 127, 167, 191, 199,
+        ];
+        for p in (3..200).filter(|&p| crate::is_prime(p)) {
+            assert_eq!(
+                wagstaff_prp_test(p).is_probable_prime(),
+                exponents.contains(&p),
+                "W({})",
+                p
+            );
+        }
+        assert_eq!(wagstaff_prp_test(29).res64(), 0x370216E);
+        assert_eq!(wagstaff_prp_test(37).res64(), 0x58866750D);
+        assert!(!wagstaff_prp_test(2).is_probable_prime());
+    }
+
+    #[test]
+    fn block_size_does_not_change_the_result() {
+        let reference = prp_test(1277);
+        for (block, blocks_per_check) in [(1, 1), (7, 3), (50, 2), (1000, 1000)] {
+            let params = GerbiczParams {
+                block,
+                blocks_per_check,
+            };
+            assert_eq!(
+                run(
+                    &MersenneModulus::new(1277),
+                    params,
+                    TestControl::new(&AtomicBool::new(false)),
+                    |_| {},
+                    |_, _| {}
+                ),
+                Ok(reference)
+            );
+        }
+    }
+
+    #[test]
+    fn rolls_back_after_a_corrupted_residue() {
+        let params = GerbiczParams {
+            block: 10,
+            blocks_per_check: 5,
+        };
+        let mut injected = false;
+        let mut mismatches = Vec::new();
+        let result = run(
+            &MersenneModulus::new(1277),
+            params,
+            TestControl::new(&AtomicBool::new(false)),
+            |event| {
+                if let TestEvent::GerbiczMismatch {
+                    iteration,
+                    resumed_from,
+                } = event
+                {
+                    mismatches.push((iteration, resumed_from));
+                }
+            },
+            |i, x| {
+                if i == 523 && !injected {
+                    injected = true;
+                    *x += 1u32;
+                }
+            },
+        );
+        assert_eq!(result, Ok(prp_test(1277)));
+        assert_eq!(mismatches, vec![(550, 500)]);
+    }
+
+    #[test]
+    fn res64_milestones_survive_a_rollback() {
+        let params = GerbiczParams {
+            block: 10,
+            blocks_per_check: 5,
+        };
+        let res64s = |corrupt: u64| {
+            let mut injected = false;
+            let mut res64s = Vec::new();
+            let result = run(
+                &MersenneModulus::new(1277),
+                params,
+                TestControl::new(&AtomicBool::new(false)).record_res64_every(100),
+                |event| {
+                    if let TestEvent::Res64 { iteration, res64 } = event {
+                        res64s.push((iteration, res64));
+                    }
+                },
+                |i, x| {
+                    if i == corrupt && !injected {
+                        injected = true;
+                        *x += 1u32;
+                    }
+                },
+            );
+            (result.unwrap(), res64s)
+        };
+        let (result, clean) = res64s(0);
+        assert_eq!(clean.len(), 13);
+        assert_eq!(clean.last(), Some(&(1277, result.res64())));
+        assert_eq!(res64s(523).1, clean);
+    }
+
+    #[test]
+    fn recomputes_a_corrupted_tail() {
+        let params = GerbiczParams {
+            block: 100,
+            blocks_per_check: 1,
+        };
+        let mut injected = false;
+        let mut mismatches = 0;
+        let result = run(
+            &MersenneModulus::new(1277),
+            params,
+            TestControl::new(&AtomicBool::new(false)),
+            |event| {
+                if let TestEvent::GerbiczMismatch { .. } = event {
+                    mismatches += 1;
+                }
+            },
+            |i, x| {
+                if i == 1250 && !injected {
+                    injected = true;
+                    *x += 1u32;
+                }
+            },
+        );
+        assert_eq!(result, Ok(prp_test(1277)));
+        assert_eq!(mismatches, 1);
+    }
+}
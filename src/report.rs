@@ -0,0 +1,1156 @@
+//! Serializable records of test outcomes, used for machine-readable output.
+
+use crate::arith::choice::ArithBackend;
+use crate::run::RunId;
+use crate::system::SystemInfo;
+use crate::throughput::Throughput;
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Which primality test produced a result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestKind {
+    #[serde(rename = "LL")]
+    LucasLehmer,
+    /// Base-3 Fermat probable-prime test; a `prime` result from it means
+    /// "probable prime".
+    #[serde(rename = "PRP")]
+    Prp,
+    /// Pépin's test of a Fermat number, which proves it prime or composite.
+    #[serde(rename = "Pepin")]
+    Pepin,
+    /// The Lucas–Lehmer–Riesel test of a Riesel number, which proves it
+    /// prime or composite.
+    #[serde(rename = "LLR")]
+    Llr,
+}
+
+impl TestKind {
+    /// The short name used in every output format: `LL`, `PRP`, `Pepin` or
+    /// `LLR`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TestKind::LucasLehmer => "LL",
+            TestKind::Prp => "PRP",
+            TestKind::Pepin => "Pepin",
+            TestKind::Llr => "LLR",
+        }
+    }
+
+    /// The number of squarings a full test of the number of exponent `p`
+    /// takes; for Pépin's test `p` is the index `n` of `F(n)`.
+    pub fn iterations(self, p: u64) -> u64 {
+        match self {
+            TestKind::LucasLehmer | TestKind::Llr => p.saturating_sub(2),
+            TestKind::Prp => p,
+            TestKind::Pepin => Form::Fermat.bits(p).saturating_sub(1),
+        }
+    }
+}
+
+impl fmt::Display for TestKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TestKind {
+    type Err = String;
+
+    /// Parses the short name of a test, as [`as_str`](Self::as_str) writes it.
+    fn from_str(s: &str) -> Result<TestKind, String> {
+        [
+            TestKind::LucasLehmer,
+            TestKind::Prp,
+            TestKind::Pepin,
+            TestKind::Llr,
+        ]
+        .into_iter()
+        .find(|test| test.as_str() == s)
+        .ok_or_else(|| format!("unknown test {:?}", s))
+    }
+}
+
+/// Which factoring stage found a factor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FactoringStage {
+    #[serde(rename = "TF")]
+    TrialFactoring,
+    /// Pollard's P−1 method, stage 1 or stage 2.
+    #[serde(rename = "P-1")]
+    PMinus1,
+    /// Not found by the run but given to it, by `--known-factors`.
+    #[serde(rename = "external")]
+    External,
+}
+
+impl FactoringStage {
+    /// The short name used in every output format: `TF`, `P-1` or
+    /// `external`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FactoringStage::TrialFactoring => "TF",
+            FactoringStage::PMinus1 => "P-1",
+            FactoringStage::External => "external",
+        }
+    }
+}
+
+impl fmt::Display for FactoringStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How the runs of a double-checked test compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DoubleCheck {
+    /// The normal and the shifted run agreed.
+    #[serde(rename = "MATCH")]
+    Match,
+    /// They disagreed, and a third run with another shift agreed with one
+    /// of them; the report gives that result.
+    #[serde(rename = "MISMATCH")]
+    Mismatch,
+    /// All three runs disagreed, so the report's result, from the first
+    /// run, cannot be trusted.
+    #[serde(rename = "UNRESOLVED")]
+    Unresolved,
+}
+
+impl DoubleCheck {
+    /// The name used in every output format: `MATCH`, `MISMATCH` or
+    /// `UNRESOLVED`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DoubleCheck::Match => "MATCH",
+            DoubleCheck::Mismatch => "MISMATCH",
+            DoubleCheck::Unresolved => "UNRESOLVED",
+        }
+    }
+}
+
+impl fmt::Display for DoubleCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How the independent re-run of a prime result with `--confirm` came out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Confirmation {
+    /// The other test found the number prime too.
+    #[serde(rename = "CONFIRMED")]
+    Confirmed,
+    /// The other test found it composite, so one of the two runs went
+    /// wrong and the exponent needs a manual review.
+    #[serde(rename = "CONFLICT")]
+    Conflict,
+}
+
+impl Confirmation {
+    /// The name used in every output format: `CONFIRMED` or `CONFLICT`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Confirmation::Confirmed => "CONFIRMED",
+            Confirmation::Conflict => "CONFLICT",
+        }
+    }
+}
+
+impl fmt::Display for Confirmation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Which kind of number an exponent was tested for.
+///
+/// Reports carry it as a `form` field, plus a `k` field for Riesel numbers.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "FormFields", into = "FormFields")]
+pub enum Form {
+    /// The Mersenne number `M(p) = 2^p - 1`.
+    #[default]
+    Mersenne,
+    /// The Wagstaff number `W(p) = (2^p + 1) / 3`, for odd `p`.
+    Wagstaff,
+    /// The Fermat number `F(n) = 2^(2^n) + 1`, whose "exponent" is the
+    /// index `n`.
+    Fermat,
+    /// The Riesel number `k·2^n - 1`, for odd `k`, whose exponent is `n`.
+    Riesel { k: u64 },
+}
+
+impl Form {
+    /// The name used in every output format: `mersenne`, `wagstaff`,
+    /// `fermat` or `riesel`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Form::Mersenne => "mersenne",
+            Form::Wagstaff => "wagstaff",
+            Form::Fermat => "fermat",
+            Form::Riesel { .. } => "riesel",
+        }
+    }
+
+    /// The number of exponent `p`, as in `M(31)`, `W(31)`, `F(4)` or
+    /// `15*2^31-1`.
+    pub fn number(self, p: u64) -> String {
+        match self {
+            Form::Mersenne => format!("M({})", p),
+            Form::Wagstaff => format!("W({})", p),
+            Form::Fermat => format!("F({})", p),
+            Form::Riesel { k } => format!("{}*2^{}-1", k, p),
+        }
+    }
+
+    /// The number written out, as in `2^31 - 1`, `(2^31 + 1) / 3`,
+    /// `2^(2^4) + 1` or `15 * 2^31 - 1`.
+    pub fn formula(self, p: u64) -> String {
+        match self {
+            Form::Mersenne => format!("2^{} - 1", p),
+            Form::Wagstaff => format!("(2^{} + 1) / 3", p),
+            Form::Fermat => format!("2^(2^{}) + 1", p),
+            Form::Riesel { k } => format!("{} * 2^{} - 1", k, p),
+        }
+    }
+
+    /// The number of decimal digits of the number of exponent `p`.
+    pub fn digit_count(self, p: u64) -> u64 {
+        match self {
+            Form::Mersenne => crate::number::digit_count(p),
+            Form::Wagstaff => crate::number::wagstaff_digit_count(p),
+            Form::Fermat => crate::number::fermat_digit_count(p),
+            Form::Riesel { k } => crate::number::riesel_digit_count(k, p),
+        }
+    }
+
+    /// The size in bits of the residues a test of exponent `p` works on,
+    /// which is what its time and memory grow with.
+    pub fn bits(self, p: u64) -> u64 {
+        match self {
+            Form::Mersenne | Form::Wagstaff => p,
+            Form::Fermat if p < 64 => 1 << p,
+            Form::Fermat => u64::MAX,
+            Form::Riesel { k } => p.saturating_add((64 - k.leading_zeros()) as u64),
+        }
+    }
+
+    /// `Mersenne`, `Wagstaff`, `Fermat` or `Riesel`, for messages such as
+    /// "Wagstaff prime".
+    pub fn title(self) -> &'static str {
+        match self {
+            Form::Mersenne => "Mersenne",
+            Form::Wagstaff => "Wagstaff",
+            Form::Fermat => "Fermat",
+            Form::Riesel { .. } => "Riesel",
+        }
+    }
+}
+
+impl fmt::Display for Form {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Form {
+    type Err = String;
+
+    /// Parses a form name; `riesel` is parsed with `k = 1`.
+    fn from_str(s: &str) -> Result<Form, String> {
+        match s {
+            "mersenne" => Ok(Form::Mersenne),
+            "wagstaff" => Ok(Form::Wagstaff),
+            "fermat" => Ok(Form::Fermat),
+            "riesel" => Ok(Form::Riesel { k: 1 }),
+            _ => Err(format!("unknown form {:?}", s)),
+        }
+    }
+}
+
+/// How a [`Form`] is written in a report.
+#[derive(Serialize, Deserialize)]
+struct FormFields {
+    /// Reports written before Wagstaff numbers were supported have no
+    /// form; they are all of Mersenne numbers.
+    #[serde(default = "mersenne")]
+    form: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    k: Option<u64>,
+}
+
+fn mersenne() -> String {
+    Form::Mersenne.as_str().to_string()
+}
+
+impl From<Form> for FormFields {
+    fn from(form: Form) -> FormFields {
+        FormFields {
+            form: form.as_str().to_string(),
+            k: match form {
+                Form::Riesel { k } => Some(k),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl TryFrom<FormFields> for Form {
+    type Error = String;
+
+    fn try_from(fields: FormFields) -> Result<Form, String> {
+        match (fields.form.parse()?, fields.k) {
+            (Form::Riesel { .. }, Some(k)) => Ok(Form::Riesel { k }),
+            (Form::Riesel { .. }, None) => Err("a riesel report needs a k".to_string()),
+            (form, None) => Ok(form),
+            (form, Some(_)) => Err(format!("a {} report has no k", form)),
+        }
+    }
+}
+
+/// A range of exponents, `start..=end`, written `start-end` as in
+/// `20000-25000`; a run given several with `--ranges` tags each report
+/// with the one it came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ExponentRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ExponentRange {
+    pub fn contains(self, p: u64) -> bool {
+        (self.start..=self.end).contains(&p)
+    }
+}
+
+impl fmt::Display for ExponentRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for ExponentRange {
+    type Err = String;
+
+    /// Parses `start-end`, refusing a range whose start is after its end.
+    fn from_str(s: &str) -> Result<ExponentRange, String> {
+        let bound = |bound: &str| {
+            bound
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("{:?} is not a range such as 20000-25000", s))
+        };
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("{:?} is not a range such as 20000-25000", s))?;
+        let (start, end) = (bound(start)?, bound(end)?);
+        if start > end {
+            return Err(format!(
+                "the range {} is reversed; its start is after its end",
+                s
+            ));
+        }
+        Ok(ExponentRange { start, end })
+    }
+}
+
+impl TryFrom<String> for ExponentRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<ExponentRange, String> {
+        s.parse()
+    }
+}
+
+impl From<ExponentRange> for String {
+    fn from(range: ExponentRange) -> String {
+        range.to_string()
+    }
+}
+
+/// The Res64 of a test partway through, recorded with `--milestones` so
+/// that two runs of an exponent can be compared along the way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Res64Milestone {
+    /// The iterations done.
+    pub iteration: u64,
+    /// As [`format_res64`] writes it.
+    pub res64: String,
+}
+
+/// The outcome of testing a single exponent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestReport {
+    pub exponent: u64,
+    /// Written as the `form` and `k` fields of [`Form`].
+    #[serde(flatten)]
+    pub form: Form,
+    pub prime: bool,
+    /// The primality test that was run, or `None` if a factoring stage
+    /// settled the exponent.
+    pub test: Option<TestKind>,
+    pub seconds: f64,
+    /// The time the primality test spent computing, as measured by a
+    /// [`ComputeTimer`](crate::clock::ComputeTimer): without pauses,
+    /// checkpoint writes or time spent waiting for a core. `None` if no test
+    /// was run or it was not measured. `seconds` is the time on the clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compute_seconds: Option<f64>,
+    /// Low 64 bits of the final residue as 16 uppercase hex digits. `None`
+    /// if no test was run, or if a Lucas–Lehmer test proved the number
+    /// prime (its residue is then zero). PRP tests always report it.
+    pub res64: Option<String>,
+    /// With `--res2048`, the low 2048 bits of the same residue, as
+    /// [`format_res2048`] writes them, for programs that compare more than
+    /// the Res64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub res2048: Option<String>,
+    /// A factor found before the primality test, in decimal, in which case
+    /// no test was run. P−1 factors can be far larger than 64 bits.
+    pub factor: Option<String>,
+    /// The stage that found `factor`.
+    pub factor_stage: Option<FactoringStage>,
+    /// The shift of the shifted Lucas–Lehmer run: the only run of a test
+    /// with `--shift`, or the second of a double-checked test.
+    pub shift: Option<u64>,
+    /// How the runs compared, if the test was double-checked.
+    pub double_check: Option<DoubleCheck>,
+    /// The arithmetic the Lucas–Lehmer test of a Mersenne number ran on;
+    /// with a double-check, that of the first run. Reports from before
+    /// `--backend` have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<ArithBackend>,
+    /// The arithmetic of the second run of a double-checked test.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub check_backend: Option<ArithBackend>,
+    /// How the re-run of a prime result with `--confirm` came out. Reports
+    /// from before `--confirm` have no such field.
+    #[serde(default)]
+    pub confirmation: Option<Confirmation>,
+    /// The Res64 of the confirming run, which used the other of the LL and
+    /// PRP tests.
+    #[serde(default)]
+    pub confirm_res64: Option<String>,
+    /// The last completed iteration of a test given up on for taking too
+    /// long. Such a test has no result: `prime` is false and `res64` is
+    /// `None`.
+    pub timed_out_at: Option<u64>,
+    /// The last good iteration of a test given up on after an arithmetic
+    /// anomaly, by `--on-error abort` or after too many retries. Like a
+    /// timed-out test it has no result.
+    #[serde(default)]
+    pub failed_at: Option<u64>,
+    /// How many arithmetic errors the checks caught during the test:
+    /// anomalies, and failed Jacobi and Gerbicz checks. Each was recovered
+    /// from unless the test failed or ran with `--on-error continue`.
+    #[serde(default)]
+    pub errors: u32,
+    /// How many checkpoints the primality test wrote.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub checkpoints: u32,
+    /// How fast the primality test went, from its progress reports. Reports
+    /// from before this was measured, and of tests with no progress
+    /// reports, have none.
+    #[serde(default)]
+    pub throughput: Option<Throughput>,
+    /// The message of a panic that ended the test. Like a failed test it
+    /// has no result.
+    #[serde(default)]
+    pub panic: Option<String>,
+    /// The Res64 of the primality test at evenly spaced iterations, the
+    /// last of them the final one, with `--milestones`.
+    #[serde(default)]
+    pub milestones: Vec<Res64Milestone>,
+    /// The `--ranges` range the exponent came from; runs of one range
+    /// leave it out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<ExponentRange>,
+    /// The [run](crate::run) that produced it; reports from before run IDs
+    /// have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunId>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+#[cfg(test)]
+impl TestReport {
+    /// A Lucas–Lehmer test of `M(exponent)` that took `seconds`, with every
+    /// other field empty: the base the tests build their reports on with
+    /// `..TestReport::lucas_lehmer(exponent, seconds)`.
+    pub(crate) fn lucas_lehmer(exponent: u64, seconds: f64) -> TestReport {
+        TestReport {
+            exponent,
+            form: Form::Mersenne,
+            prime: false,
+            test: Some(TestKind::LucasLehmer),
+            seconds,
+            compute_seconds: None,
+            res64: None,
+            res2048: None,
+            factor: None,
+            factor_stage: None,
+            shift: None,
+            double_check: None,
+            backend: None,
+            check_backend: None,
+            confirmation: None,
+            confirm_res64: None,
+            timed_out_at: None,
+            failed_at: None,
+            errors: 0,
+            checkpoints: 0,
+            throughput: None,
+            panic: None,
+            milestones: Vec::new(),
+            range: None,
+            run: None,
+        }
+    }
+}
+
+impl TestReport {
+    pub fn is_factored(&self) -> bool {
+        self.factor.is_some()
+    }
+
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out_at.is_some()
+    }
+
+    /// Whether the test was given up on after an arithmetic anomaly, or
+    /// ended in a panic.
+    pub fn is_failed(&self) -> bool {
+        self.failed_at.is_some() || self.panic.is_some()
+    }
+
+    /// Whether the exponent has a result: it was factored or its test ran
+    /// to the end. Timed-out and failed tests are tried again later.
+    pub fn has_result(&self) -> bool {
+        !self.is_timed_out() && !self.is_failed()
+    }
+
+    /// How far a timed-out test got, as a percentage.
+    pub fn percent_complete(&self) -> Option<f64> {
+        let iteration = self.timed_out_at?;
+        let total = self.test?.iterations(self.exponent).max(1);
+        Some(100.0 * iteration as f64 / total as f64)
+    }
+
+    /// Whether the `--confirm` re-run disagreed with the result.
+    pub fn is_conflict(&self) -> bool {
+        self.confirmation == Some(Confirmation::Conflict)
+    }
+
+    /// Whether the runs of a double-checked test disagreed.
+    pub fn is_mismatch(&self) -> bool {
+        matches!(
+            self.double_check,
+            Some(DoubleCheck::Mismatch | DoubleCheck::Unresolved)
+        )
+    }
+
+    /// Squarings per second of the primality test, or `None` if none ran
+    /// to completion.
+    /// A test resumed from a checkpoint did fewer iterations than this
+    /// assumes, so its rate is overstated.
+    pub fn iterations_per_second(&self) -> Option<f64> {
+        let test = self.test?;
+        if self.seconds > 0.0 && self.has_result() {
+            Some(test.iterations(self.exponent) as f64 / self.seconds)
+        } else {
+            None
+        }
+    }
+}
+
+/// The exponent whose Lucas-Lehmer test is one unit of work, by
+/// [`work_units`].
+///
+/// Work totals in results databases and ledgers, and those noted down
+/// from earlier runs, add up only while this stays the same, so it must
+/// never change.
+pub const WORK_UNIT_EXPONENT: u64 = 1_000_000;
+
+/// The work of a primality test of a `p`-bit number, such as `M(p)`, in
+/// units of the Lucas-Lehmer test of `M(1,000,000)`, as GIMPS counts its
+/// GHz-days: `p` squarings of `p`-bit numbers, each taking time in
+/// proportion to `p · log2(p)`, so `p² · log2(p)` in all. Below `p = 2`
+/// there is no test, and no work.
+pub fn work_units(p: u64) -> f64 {
+    if p < 2 {
+        return 0.0;
+    }
+    let cost = |p: f64| p * p * p.log2();
+    cost(p as f64) / cost(WORK_UNIT_EXPONENT as f64)
+}
+
+impl TestReport {
+    /// The [`work_units`] of the primality test runs behind the result:
+    /// one for each run that got to the end, so two for a double-checked
+    /// test whose runs agreed and three when a third run was needed, one
+    /// more for a `--confirm` run, and none for an exponent that was
+    /// factored or whose test was given up on.
+    pub fn work_units(&self) -> f64 {
+        if self.test.is_none() || !self.has_result() {
+            return 0.0;
+        }
+        let runs = match self.double_check {
+            None => 1,
+            Some(DoubleCheck::Match) => 2,
+            Some(_) => 3,
+        } + self.confirmation.is_some() as u32;
+        runs as f64 * work_units(self.form.bits(self.exponent))
+    }
+}
+
+/// Formats a 64-bit residue the way [`TestReport::res64`] stores it.
+pub fn format_res64(res64: u64) -> String {
+    format!("{:016X}", res64)
+}
+
+/// The bits of a residue that [`format_res2048`] keeps.
+pub const RES2048_BITS: u64 = 2048;
+
+/// Formats the low [`RES2048_BITS`] bits of `residue` the way
+/// [`TestReport::res2048`] stores them: 512 lowercase hex digits, the most
+/// significant first, zero-padded.
+pub fn format_res2048(residue: &BigUint) -> String {
+    let low = residue % (BigUint::from(1u32) << RES2048_BITS);
+    format!("{:0width$x}", low, width = (RES2048_BITS / 4) as usize)
+}
+
+/// Totals for a whole run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub start_exponent: u64,
+    pub end_exponent: u64,
+    /// Exponents the run set out to test, once it has found them all.
+    #[serde(default)]
+    pub candidates: Option<u64>,
+    pub tested: usize,
+    pub primes: Vec<u64>,
+    pub factored: usize,
+    pub composite: usize,
+    /// Exponents whose tests were given up on for taking too long.
+    pub timed_out: Vec<u64>,
+    /// Exponents whose tests were given up on after arithmetic anomalies or
+    /// ended in a panic.
+    #[serde(default)]
+    pub failed: Vec<u64>,
+    /// Arithmetic errors caught over all the tests; see
+    /// [`TestReport::errors`].
+    #[serde(default)]
+    pub errors: u32,
+    /// The [`TestReport::work_units`] of all the tests.
+    #[serde(default)]
+    pub work_units: f64,
+    pub seconds: f64,
+}
+
+impl RunSummary {
+    /// An empty summary, to be filled in with [`RunSummary::record`] as
+    /// results arrive.
+    pub fn new(start_exponent: u64, end_exponent: u64) -> RunSummary {
+        RunSummary {
+            start_exponent,
+            end_exponent,
+            candidates: None,
+            tested: 0,
+            primes: Vec::new(),
+            factored: 0,
+            composite: 0,
+            timed_out: Vec::new(),
+            failed: Vec::new(),
+            errors: 0,
+            work_units: 0.0,
+            seconds: 0.0,
+        }
+    }
+
+    pub fn from_reports(
+        start_exponent: u64,
+        end_exponent: u64,
+        reports: &[TestReport],
+        seconds: f64,
+    ) -> RunSummary {
+        let mut summary = RunSummary::new(start_exponent, end_exponent);
+        for report in reports {
+            summary.record(report);
+        }
+        summary.seconds = seconds;
+        summary
+    }
+
+    pub fn record(&mut self, report: &TestReport) {
+        self.tested += 1;
+        self.errors += report.errors;
+        self.work_units += report.work_units();
+        if report.is_factored() {
+            self.factored += 1;
+        } else if report.is_timed_out() {
+            self.timed_out.push(report.exponent);
+        } else if report.is_failed() {
+            self.failed.push(report.exponent);
+        } else if report.prime {
+            self.primes.push(report.exponent);
+        } else {
+            self.composite += 1;
+        }
+    }
+}
+
+/// Aggregate timings over the reports of a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingStats {
+    /// Sum of the per-exponent times. With several threads this exceeds
+    /// the wall time.
+    pub cpu_seconds: f64,
+    pub wall_seconds: f64,
+    pub mean_seconds: f64,
+    pub median_seconds: f64,
+    pub exponents_per_second: f64,
+}
+
+impl TimingStats {
+    /// `None` if there are no reports.
+    pub fn from_reports(reports: &[TestReport], wall_seconds: f64) -> Option<TimingStats> {
+        if reports.is_empty() {
+            return None;
+        }
+        let mut seconds: Vec<f64> = reports.iter().map(|report| report.seconds).collect();
+        seconds.sort_by(f64::total_cmp);
+        let n = seconds.len();
+        let median_seconds = if n % 2 == 1 {
+            seconds[n / 2]
+        } else {
+            (seconds[n / 2 - 1] + seconds[n / 2]) / 2.0
+        };
+        let cpu_seconds: f64 = seconds.iter().sum();
+        Some(TimingStats {
+            cpu_seconds,
+            wall_seconds,
+            mean_seconds: cpu_seconds / n as f64,
+            median_seconds,
+            exponents_per_second: if wall_seconds > 0.0 {
+                n as f64 / wall_seconds
+            } else {
+                0.0
+            },
+        })
+    }
+}
+
+/// The bounds of the buckets of [`DurationBucket::histogram`], in seconds:
+/// about three to each factor of ten, at round numbers of milliseconds,
+/// seconds, minutes, hours and days.
+const HISTOGRAM_BOUNDS: [f64; 30] = [
+    0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0,
+    120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0, 7200.0, 18000.0, 43200.0, 86400.0, 172800.0,
+    432000.0, 864000.0, 2592000.0,
+];
+
+/// How many of a run's reports took from `from` up to `to` seconds, or
+/// `from` or more if `to` is `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DurationBucket {
+    pub from: f64,
+    pub to: Option<f64>,
+    pub count: usize,
+}
+
+impl DurationBucket {
+    /// The reports counted by how long they took, in buckets that grow
+    /// about threefold, from the fastest report's bucket to the slowest's.
+    /// The empty buckets in between are kept, so the shape shows; there
+    /// are never more than 31 however many reports there are.
+    pub fn histogram(reports: &[TestReport]) -> Vec<DurationBucket> {
+        let index = |seconds: f64| HISTOGRAM_BOUNDS.partition_point(|&bound| bound <= seconds);
+        let Some(first) = reports.iter().map(|report| index(report.seconds)).min() else {
+            return Vec::new();
+        };
+        let last = reports
+            .iter()
+            .map(|report| index(report.seconds))
+            .max()
+            .unwrap();
+        let mut buckets: Vec<DurationBucket> = (first..=last)
+            .map(|i| DurationBucket {
+                from: if i == 0 { 0.0 } else { HISTOGRAM_BOUNDS[i - 1] },
+                to: HISTOGRAM_BOUNDS.get(i).copied(),
+                count: 0,
+            })
+            .collect();
+        for report in reports {
+            buckets[index(report.seconds) - first].count += 1;
+        }
+        buckets
+    }
+}
+
+/// A report as a line of `--json` output: its fields, then its
+/// [`TestReport::work_units`].
+#[derive(Debug, Serialize)]
+pub struct ReportLine<'a> {
+    #[serde(flatten)]
+    pub report: &'a TestReport,
+    pub work_units: f64,
+}
+
+impl ReportLine<'_> {
+    pub fn new(report: &TestReport) -> ReportLine<'_> {
+        ReportLine {
+            report,
+            work_units: report.work_units(),
+        }
+    }
+}
+
+/// Wraps the summary as `{"summary": {...}, "system": {...}}` so it is
+/// distinguishable from the per-exponent lines in a JSONL stream, with the
+/// build and machine of the run.
+#[derive(Debug, Serialize)]
+pub struct SummaryLine<'a> {
+    pub summary: &'a RunSummary,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub stages: &'a [StageSummary],
+    /// The totals of each range of a run of several `--ranges`.
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub ranges: &'a [RunSummary],
+    pub system: &'a SystemInfo,
+}
+
+/// What one stage of a run, such as trial factoring, did with its
+/// threads.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StageSummary {
+    pub stage: &'static str,
+    pub threads: usize,
+    /// Exponents the stage dealt with.
+    pub exponents: u64,
+    /// Of those, the ones it showed to be composite.
+    pub eliminated: u64,
+    /// Time its threads spent working, added up.
+    pub busy_seconds: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(exponent: u64, prime: bool, res64: Option<u64>, factor: Option<u64>) -> TestReport {
+        TestReport {
+            prime,
+            test: factor.is_none().then_some(TestKind::LucasLehmer),
+            res64: res64.map(format_res64),
+            factor: factor.map(|factor| factor.to_string()),
+            factor_stage: factor.map(|_| FactoringStage::TrialFactoring),
+            ..TestReport::lucas_lehmer(exponent, 0.5)
+        }
+    }
+
+    #[test]
+    fn serializes_with_stable_field_names() {
+        let json = serde_json::to_string(&report(31, true, None, None)).unwrap();
+        assert_eq!(
+            json,
+            r#"{"exponent":31,"form":"mersenne","prime":true,"test":"LL","seconds":0.5,"res64":null,"factor":null,"factor_stage":null,"shift":null,"double_check":null,"confirmation":null,"confirm_res64":null,"timed_out_at":null,"failed_at":null,"errors":0,"throughput":null,"panic":null,"milestones":[]}"#
+        );
+        let prp = TestReport {
+            test: Some(TestKind::Prp),
+            ..report(31, true, Some(9), None)
+        };
+        assert!(serde_json::to_string(&prp)
+            .unwrap()
+            .contains(r#""test":"PRP","seconds":0.5,"res64":"0000000000000009""#));
+        let json = serde_json::to_string(&report(23, false, Some(0x5D32F7), None)).unwrap();
+        assert!(json.contains(r#""res64":"00000000005D32F7""#));
+        let json = serde_json::to_string(&report(11, false, None, Some(23))).unwrap();
+        assert!(json.contains(r#""factor":"23","factor_stage":"TF""#));
+        let checked = TestReport {
+            shift: Some(12),
+            double_check: Some(DoubleCheck::Mismatch),
+            ..report(23, false, Some(0x5D32F7), None)
+        };
+        assert!(serde_json::to_string(&checked)
+            .unwrap()
+            .ends_with(r#""shift":12,"double_check":"MISMATCH","confirmation":null,"confirm_res64":null,"timed_out_at":null,"failed_at":null,"errors":0,"throughput":null,"panic":null,"milestones":[]}"#));
+        let conflict = TestReport {
+            confirmation: Some(Confirmation::Conflict),
+            confirm_res64: Some(format_res64(0x1234)),
+            ..report(31, true, None, None)
+        };
+        assert!(serde_json::to_string(&conflict)
+            .unwrap()
+            .contains(r#""confirmation":"CONFLICT","confirm_res64":"0000000000001234""#));
+    }
+
+    #[test]
+    fn res2048_is_fixed_width_lowercase_hex() {
+        // The LL test of M(11) = 2047 ends with S(9) = 1736 = 0x6c8, which
+        // fits in 2048 bits, so it is all there is.
+        let res2048 = format_res2048(&BigUint::from(1736u32));
+        assert_eq!(res2048.len(), 512);
+        assert_eq!(res2048, format!("{}6c8", "0".repeat(509)));
+        // The residue of a PRP test that finds a probable prime is 9.
+        assert_eq!(
+            format_res2048(&BigUint::from(9u32)),
+            format!("{}9", "0".repeat(511))
+        );
+        // Only the low 2048 bits are kept: 2^2048 - 1 is all f, one bit
+        // more drops out.
+        let ones = (BigUint::from(1u32) << 2048u32) - 1u32;
+        assert_eq!(format_res2048(&ones), "f".repeat(512));
+        let wider = (BigUint::from(0xABu32) << 2044u32) + 0xCDu32;
+        assert_eq!(format_res2048(&wider), format!("b{}cd", "0".repeat(509)));
+
+        let json = serde_json::to_string(&TestReport {
+            res2048: Some(format_res2048(&BigUint::from(1736u32))),
+            ..report(11, false, Some(1736), None)
+        })
+        .unwrap();
+        assert!(json.contains(r#""res64":"00000000000006C8","res2048":"000"#));
+        let json = serde_json::to_string(&report(11, false, Some(1736), None)).unwrap();
+        assert!(!json.contains("res2048"));
+    }
+
+    #[test]
+    fn forms_read_back_from_their_fields() {
+        let riesel = TestReport {
+            form: Form::Riesel { k: 15 },
+            test: Some(TestKind::Llr),
+            ..report(73, true, None, None)
+        };
+        let json = serde_json::to_string(&riesel).unwrap();
+        assert!(
+            json.starts_with(r#"{"exponent":73,"form":"riesel","k":15,"prime":true,"test":"LLR""#)
+        );
+        assert_eq!(serde_json::from_str::<TestReport>(&json).unwrap(), riesel);
+
+        let old = r#"{"exponent":31,"prime":true,"test":"LL","seconds":0.5,"res64":null,"factor":null,"factor_stage":null,"shift":null,"double_check":null,"timed_out_at":null}"#;
+        assert_eq!(
+            serde_json::from_str::<TestReport>(old).unwrap(),
+            report(31, true, None, None)
+        );
+        for bad in [r#""form":"riesel""#, r#""form":"wagstaff","k":3"#] {
+            let json = old.replace(r#""prime""#, &format!(r#"{},"prime""#, bad));
+            assert!(
+                serde_json::from_str::<TestReport>(&json).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn ranges_parse_and_tag_reports() {
+        let range: ExponentRange = "20000-25000".parse().unwrap();
+        assert_eq!(
+            range,
+            ExponentRange {
+                start: 20000,
+                end: 25000
+            }
+        );
+        assert!(range.contains(20000) && range.contains(25000) && !range.contains(25001));
+        for bad in ["25000-20000", "20000", "a-b", "20000-"] {
+            assert!(bad.parse::<ExponentRange>().is_err(), "{}", bad);
+        }
+
+        let tagged = TestReport {
+            range: Some(range),
+            ..report(20011, false, Some(1), None)
+        };
+        let json = serde_json::to_string(&tagged).unwrap();
+        assert!(json.ends_with(r#","range":"20000-25000"}"#));
+        assert_eq!(serde_json::from_str::<TestReport>(&json).unwrap(), tagged);
+        let untagged = serde_json::to_string(&report(20011, false, Some(1), None)).unwrap();
+        assert!(!untagged.contains("range"));
+    }
+
+    #[test]
+    fn summary_counts_each_kind_of_result() {
+        let reports = [
+            report(7, true, None, None),
+            report(11, false, None, Some(23)),
+            report(13, true, None, None),
+            report(101, false, Some(1), None),
+            TestReport {
+                timed_out_at: Some(30),
+                ..report(103, false, None, None)
+            },
+            TestReport {
+                failed_at: Some(20),
+                errors: 4,
+                ..report(107, false, None, None)
+            },
+            TestReport {
+                errors: 1,
+                ..report(109, false, Some(2), None)
+            },
+            TestReport {
+                panic: Some("capacity overflow".to_string()),
+                ..report(113, true, None, None)
+            },
+        ];
+        let summary = RunSummary::from_reports(2, 113, &reports, 1.0);
+        assert_eq!(summary.tested, 8);
+        assert_eq!(summary.timed_out, vec![103]);
+        assert_eq!(summary.failed, vec![107, 113]);
+        assert_eq!(summary.errors, 5);
+        assert_eq!(summary.primes, vec![7, 13]);
+        assert_eq!(summary.factored, 1);
+        assert_eq!(summary.composite, 2);
+
+        let line = SummaryLine {
+            summary: &summary,
+            stages: &[],
+            ranges: &[],
+            system: SystemInfo::current(),
+        };
+        let line = serde_json::to_string(&line).unwrap();
+        assert!(line.starts_with(r#"{"summary":{"start_exponent":2,"#));
+        assert!(line.contains(r#""system":{"version":""#));
+        assert!(!line.contains("stages"));
+    }
+
+    #[test]
+    fn timing_stats_over_reports() {
+        let mut reports = vec![
+            report(7, true, None, None),
+            report(11, false, None, Some(23)),
+            report(13, true, None, None),
+        ];
+        reports[0].seconds = 3.0;
+        reports[1].seconds = 1.0;
+        reports[2].seconds = 2.0;
+        let stats = TimingStats::from_reports(&reports, 1.5).unwrap();
+        assert_eq!(stats.cpu_seconds, 6.0);
+        assert_eq!(stats.mean_seconds, 2.0);
+        assert_eq!(stats.median_seconds, 2.0);
+        assert_eq!(stats.exponents_per_second, 2.0);
+
+        reports[2].seconds = 5.0;
+        let stats = TimingStats::from_reports(&reports[..2], 1.0).unwrap();
+        assert_eq!(stats.median_seconds, 2.0);
+        assert_eq!(TimingStats::from_reports(&[], 1.0), None);
+    }
+
+    #[test]
+    fn durations_are_bucketed_logarithmically() {
+        let took = |seconds: &[f64]| -> Vec<TestReport> {
+            seconds
+                .iter()
+                .map(|&seconds| TestReport {
+                    seconds,
+                    ..report(31, true, None, None)
+                })
+                .collect()
+        };
+        assert!(DurationBucket::histogram(&[]).is_empty());
+        assert_eq!(
+            DurationBucket::histogram(&took(&[0.5])),
+            [DurationBucket {
+                from: 0.5,
+                to: Some(1.0),
+                count: 1
+            }]
+        );
+
+        let buckets = DurationBucket::histogram(&took(&[0.0, 0.0004, 0.0015, 0.004, 0.005, 0.2]));
+        let counts: Vec<(f64, usize)> = buckets.iter().map(|b| (b.from, b.count)).collect();
+        assert_eq!(
+            counts,
+            [
+                (0.0, 2),
+                (0.001, 1),
+                (0.002, 1),
+                (0.005, 1),
+                (0.01, 0),
+                (0.02, 0),
+                (0.05, 0),
+                (0.1, 0),
+                (0.2, 1)
+            ]
+        );
+        assert_eq!(buckets[0].to, Some(0.001));
+
+        // A test longer than the last bound goes in an open bucket.
+        let buckets = DurationBucket::histogram(&took(&[3_000_000.0, 2_592_000.0]));
+        assert_eq!(
+            buckets,
+            [DurationBucket {
+                from: 2_592_000.0,
+                to: None,
+                count: 2
+            }]
+        );
+
+        // However many reports there are, the buckets stay few.
+        let many: Vec<f64> = (0..100_000).map(|i| f64::from(i) * 0.01).collect();
+        let buckets = DurationBucket::histogram(&took(&many));
+        assert_eq!(buckets.len(), 20);
+        assert_eq!(buckets.iter().map(|b| b.count).sum::<usize>(), 100_000);
+        assert_eq!(buckets.last().unwrap().from, 600.0);
+    }
+
+    #[test]
+    fn iteration_rate_depends_on_the_test() {
+        let mut ll = report(101, false, Some(1), None);
+        ll.seconds = 0.5;
+        assert_eq!(ll.iterations_per_second(), Some(198.0));
+        let prp = TestReport {
+            test: Some(TestKind::Prp),
+            ..ll.clone()
+        };
+        assert_eq!(prp.iterations_per_second(), Some(202.0));
+        let timed_out = TestReport {
+            timed_out_at: Some(33),
+            ..ll.clone()
+        };
+        assert_eq!(timed_out.iterations_per_second(), None);
+        assert!((timed_out.percent_complete().unwrap() - 33.33).abs() < 0.01);
+        assert_eq!(
+            report(11, false, None, Some(23)).iterations_per_second(),
+            None
+        );
+    }
+
+    #[test]
+    fn work_is_counted_in_units_of_the_test_of_a_million() {
+        assert_eq!(work_units(WORK_UNIT_EXPONENT), 1.0);
+        assert_eq!(work_units(0), 0.0);
+        assert_eq!(work_units(1), 0.0);
+        // Twice the exponent is four times the squarings' size times one
+        // more bit of their log.
+        let doubled = 4.0 * 21.0 / 20.0;
+        let ratio = work_units(2 << 20) / work_units(1 << 20);
+        assert!((ratio - doubled).abs() < 1e-12, "{}", ratio);
+        assert!((work_units(100_000_000) - 13333.333).abs() < 0.001);
+
+        assert_eq!(report(11, false, None, Some(23)).work_units(), 0.0);
+        let tested = report(1_000_000, false, Some(1), None);
+        assert_eq!(tested.work_units(), 1.0);
+        let checked = TestReport {
+            double_check: Some(DoubleCheck::Match),
+            ..tested.clone()
+        };
+        assert_eq!(checked.work_units(), 2.0);
+        let timed_out = TestReport {
+            timed_out_at: Some(1000),
+            ..tested.clone()
+        };
+        assert_eq!(timed_out.work_units(), 0.0);
+        let summary = RunSummary::from_reports(0, 0, &[tested, checked, timed_out], 1.0);
+        assert_eq!(summary.work_units, 3.0);
+    }
+}
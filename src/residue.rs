@@ -0,0 +1,425 @@
+//! The final residues of finished tests, written with `--save-residue` so
+//! that other programs, such as gpuowl, can cross-verify a test from its
+//! whole residue rather than its Res64.
+//!
+//! The file, such as `M86243.residue`, is a 56-byte header followed by the
+//! residue, unshifted, in `ceil(p / 64)` 64-bit limbs, least significant
+//! first. Every number is little-endian:
+//!
+//! | bytes  | field                                               |
+//! |--------|-----------------------------------------------------|
+//! | 0..8   | magic, `MERSRESD`                                   |
+//! | 8..12  | format version, [`FORMAT_VERSION`]                  |
+//! | 12..16 | CRC-32 of everything from byte 16 to the end        |
+//! | 16..24 | exponent `p`                                        |
+//! | 24..32 | iterations the test did                             |
+//! | 32..40 | shift of the test's residue in bits                 |
+//! | 40..48 | residue length in bytes                             |
+//! | 48..56 | test, as in [`TestKind::as_str`], padded with zeros |
+//! | 56..   | residue                                             |
+//!
+//! Residues are written and read a limb at a time, so one of hundreds of
+//! megabytes is never copied whole.
+
+use crate::arith::{Backend, MersenneArith};
+use crate::checkpoint::{crc32_update, Checkpoint};
+use crate::report::{format_res2048, Form, TestKind};
+use num_bigint::BigUint;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::iter;
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"MERSRESD";
+const HEADER_LEN: usize = 56;
+
+/// The limbs of a Res2048.
+const RES2048_LIMBS: usize = 2048 / 64;
+
+/// The version of the format written by [`write`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The file name of the residue of the test of `p`, such as
+/// `M86243.residue`.
+pub fn file_name(form: Form, p: u64) -> String {
+    let letter = match form {
+        Form::Wagstaff => "W",
+        _ => "M",
+    };
+    format!("{}{}.residue", letter, p)
+}
+
+/// What a residue file says about the test it is the residue of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResidueHeader {
+    pub p: u64,
+    pub test: TestKind,
+    /// The iterations the test did, which were all of them.
+    pub iterations: u64,
+    /// The shift of the test's residue in bits, as in
+    /// [`Shift`](crate::arith::Shift); the residue in the file is unshifted.
+    pub shift: u64,
+}
+
+impl ResidueHeader {
+    /// The number of limbs in the file, enough for any residue below
+    /// `2^p`.
+    pub fn limbs(&self) -> u64 {
+        self.p.div_ceil(64)
+    }
+
+    /// The header as the file starts with it, with a zero checksum.
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut test = [0; 8];
+        test[..self.test.as_str().len()].copy_from_slice(self.test.as_str().as_bytes());
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..8].copy_from_slice(MAGIC);
+        bytes[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.p.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.iterations.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.shift.to_le_bytes());
+        bytes[40..48].copy_from_slice(&(self.limbs() * 8).to_le_bytes());
+        bytes[48..56].copy_from_slice(&test);
+        bytes
+    }
+
+    /// Parses the header `bytes`, returning it with the checksum it
+    /// records.
+    fn parse(bytes: &[u8]) -> Result<(ResidueHeader, u32), ResidueError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC[..] {
+            return Err(ResidueError::NotAResidue);
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(ResidueError::Truncated);
+        }
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let read_u32 = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let version = read_u32(8);
+        if version != FORMAT_VERSION {
+            return Err(ResidueError::UnsupportedVersion(version));
+        }
+        let name = std::str::from_utf8(&bytes[48..56])
+            .map_err(|_| ResidueError::Malformed("the test is not UTF-8".to_string()))?;
+        let test = name
+            .trim_end_matches('\0')
+            .parse()
+            .map_err(ResidueError::Malformed)?;
+        let header = ResidueHeader {
+            p: read_u64(16),
+            test,
+            iterations: read_u64(24),
+            shift: read_u64(32),
+        };
+        let length = read_u64(40);
+        if length != header.limbs() * 8 {
+            return Err(ResidueError::Malformed(format!(
+                "a residue of p = {} takes {} bytes, not {}",
+                header.p,
+                header.limbs() * 8,
+                length
+            )));
+        }
+        Ok((header, read_u32(12)))
+    }
+}
+
+/// Why a residue file could not be used.
+#[derive(Debug)]
+pub enum ResidueError {
+    Io(io::Error),
+    NotAResidue,
+    UnsupportedVersion(u32),
+    Truncated,
+    ChecksumMismatch,
+    Malformed(String),
+}
+
+impl fmt::Display for ResidueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResidueError::Io(e) => write!(f, "I/O error: {}", e),
+            ResidueError::NotAResidue => write!(f, "not a residue file"),
+            ResidueError::UnsupportedVersion(version) => write!(
+                f,
+                "residue format version {} is not supported (expected {})",
+                version, FORMAT_VERSION
+            ),
+            ResidueError::Truncated => write!(f, "file is truncated"),
+            ResidueError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            ResidueError::Malformed(problem) => write!(f, "not a valid residue file: {}", problem),
+        }
+    }
+}
+
+impl std::error::Error for ResidueError {}
+
+impl From<io::Error> for ResidueError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => ResidueError::Truncated,
+            _ => ResidueError::Io(e),
+        }
+    }
+}
+
+/// Writes `residue`, unshifted and below `2^p`, as the final residue of
+/// the test `header` describes, to `path`.
+///
+/// The limbs are streamed from `residue` twice, once for the checksum and
+/// once into the file, through a temporary file that is then renamed over
+/// `path`.
+pub fn write<P: AsRef<Path>>(path: P, header: &ResidueHeader, residue: &BigUint) -> io::Result<()> {
+    assert!(
+        residue.bits() <= header.p,
+        "the residue is not reduced modulo 2^{} - 1",
+        header.p
+    );
+    let path = path.as_ref();
+    let limbs = || {
+        residue
+            .iter_u64_digits()
+            .chain(iter::repeat(0))
+            .take(header.limbs() as usize)
+    };
+    let mut bytes = header.to_bytes();
+    let crc = limbs().fold(crc32_update(!0, &bytes[16..]), |crc, limb| {
+        crc32_update(crc, &limb.to_le_bytes())
+    });
+    bytes[12..16].copy_from_slice(&(!crc).to_le_bytes());
+
+    let temporary = path.with_extension("residue.tmp");
+    let mut file = BufWriter::new(File::create(&temporary)?);
+    file.write_all(&bytes)?;
+    for limb in limbs() {
+        file.write_all(&limb.to_le_bytes())?;
+    }
+    file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// Reads the residue file at `path` a limb at a time, passing each limb
+/// and its index to `each`, and checks the file's checksum once all of
+/// them are read.
+fn stream<F>(path: &Path, mut each: F) -> Result<ResidueHeader, ResidueError>
+where
+    F: FnMut(u64, u64),
+{
+    let mut file = BufReader::new(File::open(path)?);
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    file.by_ref()
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut bytes)?;
+    let (header, stored_checksum) = ResidueHeader::parse(&bytes)?;
+    let mut crc = crc32_update(!0, &bytes[16..]);
+    let top_bits = header.p % 64;
+    for index in 0..header.limbs() {
+        let mut limb = [0; 8];
+        file.read_exact(&mut limb)?;
+        crc = crc32_update(crc, &limb);
+        let limb = u64::from_le_bytes(limb);
+        if index + 1 == header.limbs() && top_bits != 0 && limb >> top_bits != 0 {
+            return Err(ResidueError::Malformed(format!(
+                "the residue is not reduced modulo 2^{} - 1",
+                header.p
+            )));
+        }
+        each(index, limb);
+    }
+    let stray = file.bytes().count();
+    if stray > 0 {
+        return Err(ResidueError::Malformed(format!(
+            "{} stray bytes at the end of the file",
+            stray
+        )));
+    }
+    if !crc != stored_checksum {
+        return Err(ResidueError::ChecksumMismatch);
+    }
+    Ok(header)
+}
+
+/// A residue file's header and the low bits of its residue, the summaries
+/// other programs report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResidueSummary {
+    pub header: ResidueHeader,
+    /// The low 2048 bits, least significant limb first.
+    low: [u64; RES2048_LIMBS],
+}
+
+impl ResidueSummary {
+    /// Reads the residue file at `path`, checking it whole without holding
+    /// its residue.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<ResidueSummary, ResidueError> {
+        let mut low = [0; RES2048_LIMBS];
+        let header = stream(path.as_ref(), |index, limb| {
+            if let Some(slot) = low.get_mut(index as usize) {
+                *slot = limb;
+            }
+        })?;
+        Ok(ResidueSummary { header, low })
+    }
+
+    /// The low 64 bits of the residue.
+    pub fn res64(&self) -> u64 {
+        self.low[0]
+    }
+
+    /// The low 2048 bits of the residue as [`format_res2048`] writes them
+    /// in results: 512 lowercase hexadecimal digits, most significant
+    /// first.
+    pub fn res2048(&self) -> String {
+        let digits = self
+            .low
+            .iter()
+            .flat_map(|&limb| [limb as u32, (limb >> 32) as u32]);
+        format_res2048(&BigUint::new(digits.collect()))
+    }
+}
+
+/// Whether the residue in the file at `path` is `residue`, comparing them
+/// a limb at a time. Returns the file's header too.
+pub fn matches<P: AsRef<Path>>(
+    path: P,
+    residue: &BigUint,
+) -> Result<(ResidueHeader, bool), ResidueError> {
+    let mut expected = residue.iter_u64_digits().chain(iter::repeat(0));
+    let mut same = true;
+    let header = stream(path.as_ref(), |_, limb| {
+        same &= expected.next() == Some(limb);
+    })?;
+    Ok((header, same && residue.bits() <= header.p))
+}
+
+/// The residue after `iterations` iterations of the Lucas–Lehmer test
+/// `checkpoint` is of, unshifted, recomputed from it.
+pub fn continue_from(checkpoint: &Checkpoint, iterations: u64) -> Result<BigUint, String> {
+    if checkpoint.test != TestKind::LucasLehmer {
+        return Err(format!(
+            "it is a checkpoint of a {} test; only Lucas–Lehmer tests can be continued",
+            checkpoint.test
+        ));
+    }
+    if iterations < checkpoint.iteration {
+        return Err(format!(
+            "it is of iteration {}, after the residue's {}",
+            checkpoint.iteration, iterations
+        ));
+    }
+    let modulus = Backend::new(checkpoint.p);
+    let mut s = modulus.reduce(modulus.residue_of(&checkpoint.residue));
+    for _ in checkpoint.iteration..iterations {
+        s = modulus.square_sub2(&s);
+    }
+    Ok(modulus.to_biguint(&modulus.reduce(s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{is_mersenne_prime_interruptible, res64, TestControl, TestEvent};
+    use std::sync::atomic::AtomicBool;
+
+    /// The residues of the Lucas–Lehmer test of `M(p)` after every
+    /// iteration, from 0.
+    fn residues(p: u64) -> Vec<BigUint> {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).record_milestones_every(1);
+        let mut residues = Vec::new();
+        is_mersenne_prime_interruptible(p, None, control, |event| {
+            if let TestEvent::Milestone { residue, .. } = event {
+                residues.push(residue.clone());
+            }
+        })
+        .unwrap();
+        residues
+    }
+
+    fn header(p: u64) -> ResidueHeader {
+        ResidueHeader {
+            p,
+            test: TestKind::LucasLehmer,
+            iterations: p - 2,
+            shift: 17,
+        }
+    }
+
+    #[test]
+    fn residues_are_written_and_summarized() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(file_name(Form::Mersenne, 4423));
+        let last = residues(4423).pop().unwrap();
+        write(&path, &header(4423), &last).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            HEADER_LEN as u64 + 70 * 8
+        );
+
+        let summary = ResidueSummary::read(&path).unwrap();
+        assert_eq!(summary.header, header(4423));
+        assert_eq!(summary.res64(), res64(&last));
+        let low = &last % (BigUint::from(1u32) << 2048);
+        assert_eq!(summary.res2048(), format!("{:0512x}", low));
+        assert_eq!(matches(&path, &last).unwrap(), (header(4423), true));
+        assert!(!matches(&path, &(last + 1u32)).unwrap().1);
+    }
+
+    #[test]
+    fn small_residues_are_padded_to_the_exponent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("M127.residue");
+        write(&path, &header(127), &BigUint::from(5u32)).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), HEADER_LEN as u64 + 16);
+        let summary = ResidueSummary::read(&path).unwrap();
+        assert_eq!(summary.res64(), 5);
+        assert_eq!(summary.res2048(), format!("{:0512x}", 5));
+        assert!(matches(&path, &BigUint::from(5u32)).unwrap().1);
+        assert!(!matches(&path, &BigUint::from(0u32)).unwrap().1);
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("M127.residue");
+        write(&path, &header(127), &residues(127)[60]).unwrap();
+        let good = fs::read(&path).unwrap();
+
+        let mut flipped = good.clone();
+        flipped[HEADER_LEN + 3] ^= 1;
+        fs::write(&path, &flipped).unwrap();
+        assert!(matches!(
+            ResidueSummary::read(&path),
+            Err(ResidueError::ChecksumMismatch)
+        ));
+        fs::write(&path, &good[..good.len() - 1]).unwrap();
+        assert!(matches!(
+            ResidueSummary::read(&path),
+            Err(ResidueError::Truncated)
+        ));
+        fs::write(&path, [&good[..], &[0]].concat()).unwrap();
+        assert!(matches!(
+            ResidueSummary::read(&path),
+            Err(ResidueError::Malformed(_))
+        ));
+        fs::write(&path, b"MERSCKPT").unwrap();
+        assert!(matches!(
+            ResidueSummary::read(&path),
+            Err(ResidueError::NotAResidue)
+        ));
+    }
+
+    #[test]
+    fn tests_continue_from_a_checkpoint() {
+        let all = residues(521);
+        let checkpoint = Checkpoint::new(521, 300, all[300].clone());
+        assert_eq!(continue_from(&checkpoint, 519).unwrap(), all[519]);
+        assert_eq!(continue_from(&checkpoint, 300).unwrap(), all[300]);
+        assert!(continue_from(&checkpoint, 299).is_err());
+        let prp = Checkpoint {
+            test: TestKind::Prp,
+            ..checkpoint
+        };
+        assert!(continue_from(&prp, 519).is_err());
+    }
+}
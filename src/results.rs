@@ -0,0 +1,638 @@
+//! The append-only results file written with `--results`.
+//!
+//! Each completed exponent gets one line: a UTC timestamp followed by
+//! `key=value` fields, for example
+//!
+//! ```text
+//! 2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=000000001B57CB0B seconds=0.001
+//! 2024-05-01T12:00:00Z exponent=31 result=prime test=LL seconds=0.000
+//! 2024-05-01T12:00:00Z exponent=61 result=prime test=PRP res64=0000000000000009 seconds=0.000
+//! 2024-05-01T12:00:00Z exponent=37 result=factored factor=223 stage=TF seconds=0.000
+//! 2024-05-01T12:00:00Z exponent=89 result=prime test=LL double_check=MATCH shift=17 seconds=0.002
+//! ```
+//!
+//! An exponent left out for a factor of its Mersenne number in
+//! `--known-factors` is recorded as factored by it, with `stage=external`.
+//!
+//! A double-check whose runs disagreed is marked `double_check=MISMATCH`, or
+//! `double_check=UNRESOLVED` if the tie-breaking run agreed with neither.
+//!
+//! The result of a Lucas–Lehmer test of a Mersenne number names the
+//! [arithmetic backend](crate::arith::choice) it ran on, as in
+//! `backend=fixed`, and a double-checked one that of its second run as
+//! well, as in `check_backend=bignum`.
+//!
+//! A prime re-run with `--confirm` gets `confirm=CONFIRMED`, or, if the
+//! re-run found it composite,
+//!
+//! ```text
+//! 2024-05-01T12:00:00Z exponent=31 result=prime test=LL confirm=CONFLICT confirm_res64=0000000000001234 review=needed seconds=0.000
+//! ```
+//!
+//! A test given up on with `--max-test-seconds` is recorded as
+//!
+//! ```text
+//! 2024-05-01T12:00:00Z exponent=1000003 result=timeout test=LL iteration=123000 percent=12.3 seconds=3600.000
+//! ```
+//!
+//! and does not count as done, so the next run with the same results file
+//! tries it again. So does a test given up on after an arithmetic anomaly,
+//! recorded as `result=error` with the last good iteration. Any line of a
+//! test whose checks caught arithmetic errors ends with their number, as in
+//!
+//! ```text
+//! 2024-05-01T12:00:00Z exponent=44497 result=prime test=LL errors=1 seconds=52.310
+//! 2024-05-01T12:00:00Z exponent=86243 result=error test=LL iteration=4000 errors=4 seconds=3.102
+//! ```
+//!
+//! A test that panicked is recorded as `result=error reason=panic` too,
+//! without an iteration; its message is in the report.
+//!
+//! With `--milestones` a test's line lists the Res64 at each milestone
+//! iteration, as in
+//!
+//! ```text
+//! 2024-05-01T12:00:00Z exponent=4423 result=prime test=LL milestones=1106:533D0DC9B7A16984,2212:CF4D72C21C0B0E86,3318:ABD253B8030B8CA8,4421:0000000000000000 seconds=0.310
+//! ```
+//!
+//! With `--res2048` the Res64 is followed by the low 2048 bits of the same
+//! residue, as `res2048=` and 512 lowercase hex digits; without it lines
+//! stay as short as they were.
+//!
+//! The time a test spent computing, without the time it waited for a core,
+//! its pauses and its checkpoint writes, comes before its time on the clock,
+//! as in `compute=0.280 seconds=0.310`.
+//!
+//! A run with `--chunk` adds it to every line, as in `chunk=2/4`, so the
+//! files of the machines sharing a range can be told apart once merged. A
+//! run of several `--ranges` adds the one each exponent came from, as in
+//! `range=20000-25000`.
+//!
+//! Results for Wagstaff numbers, from `--form wagstaff`, carry
+//! `form=wagstaff`, and those for Riesel numbers carry their `k` as well,
+//! as in `form=riesel k=15`; a line without a form is for a Mersenne
+//! number.
+//!
+//! Each line ends with the [ID of the run](crate::run) that wrote it, as in
+//! `seconds=0.310 run=20240501T120000Z-3f9a2c`, followed by its chunk.
+//!
+//! Before its first result, each run writes a header line starting with
+//! `#` that names the build and machine, with the CPU model last since it
+//! runs to the end of the line:
+//!
+//! ```text
+//! # 2024-05-01T12:00:00Z version=0.1.0 commit=1a2b3c4d5e6f cores=4 os=linux arch=x86_64 backend=num-bigint cpu=Intel(R) Xeon(R) CPU @ 2.20GHz
+//! ```
+//!
+//! New fields may be added over time, so readers should look fields up by
+//! key rather than by position.
+
+use crate::chunk::Chunk;
+use crate::report::{Form, TestReport};
+use crate::system::SystemInfo;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Formats the results line for `report`, without a trailing newline.
+pub fn format_line(report: &TestReport, timestamp: DateTime<Utc>) -> String {
+    let mut line = format!(
+        "{} exponent={}",
+        timestamp.format("%Y-%m-%dT%H:%M:%SZ"),
+        report.exponent
+    );
+    if report.form != Form::Mersenne {
+        line.push_str(&format!(" form={}", report.form));
+    }
+    if let Form::Riesel { k } = report.form {
+        line.push_str(&format!(" k={}", k));
+    }
+    if let Some(factor) = &report.factor {
+        line.push_str(&format!(" result=factored factor={}", factor));
+        if let Some(stage) = report.factor_stage {
+            line.push_str(&format!(" stage={}", stage));
+        }
+    } else if let Some(iteration) = report.timed_out_at {
+        line.push_str(" result=timeout");
+        if let Some(test) = report.test {
+            line.push_str(&format!(" test={}", test));
+        }
+        line.push_str(&format!(
+            " iteration={} percent={:.1}",
+            iteration,
+            report.percent_complete().unwrap_or(0.0)
+        ));
+    } else if report.is_failed() {
+        line.push_str(" result=error");
+        if let Some(test) = report.test {
+            line.push_str(&format!(" test={}", test));
+        }
+        if let Some(iteration) = report.failed_at {
+            line.push_str(&format!(" iteration={}", iteration));
+        }
+        if report.panic.is_some() {
+            line.push_str(" reason=panic");
+        }
+    } else {
+        line.push_str(if report.prime {
+            " result=prime"
+        } else {
+            " result=composite"
+        });
+        if let Some(test) = report.test {
+            line.push_str(&format!(" test={}", test));
+        }
+        if let Some(res64) = &report.res64 {
+            line.push_str(&format!(" res64={}", res64));
+        }
+        if let Some(res2048) = &report.res2048 {
+            line.push_str(&format!(" res2048={}", res2048));
+        }
+        if let Some(double_check) = report.double_check {
+            line.push_str(&format!(" double_check={}", double_check));
+        }
+        if let Some(shift) = report.shift {
+            line.push_str(&format!(" shift={}", shift));
+        }
+        if let Some(confirmation) = report.confirmation {
+            line.push_str(&format!(" confirm={}", confirmation));
+        }
+        if let Some(res64) = &report.confirm_res64 {
+            line.push_str(&format!(" confirm_res64={}", res64));
+        }
+        if report.is_conflict() {
+            line.push_str(" review=needed");
+        }
+        if let Some(backend) = report.backend {
+            line.push_str(&format!(" backend={}", backend));
+        }
+        if let Some(backend) = report.check_backend {
+            line.push_str(&format!(" check_backend={}", backend));
+        }
+    }
+    if !report.milestones.is_empty() {
+        let milestones: Vec<String> = report
+            .milestones
+            .iter()
+            .map(|milestone| format!("{}:{}", milestone.iteration, milestone.res64))
+            .collect();
+        line.push_str(&format!(" milestones={}", milestones.join(",")));
+    }
+    if let Some(range) = report.range {
+        line.push_str(&format!(" range={}", range));
+    }
+    if report.errors > 0 {
+        line.push_str(&format!(" errors={}", report.errors));
+    }
+    if let Some(compute) = report.compute_seconds {
+        line.push_str(&format!(" compute={:.3}", compute));
+    }
+    line.push_str(&format!(" seconds={:.3}", report.seconds));
+    if let Some(run) = &report.run {
+        line.push_str(&format!(" run={}", run));
+    }
+    line
+}
+
+/// Looks up the value of `key` in a results line.
+pub fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_whitespace()
+        .filter_map(|token| token.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+/// A line of a results file, parsed as far as the number it is of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultLine<'a> {
+    pub form: Form,
+    pub exponent: u64,
+    text: &'a str,
+}
+
+impl<'a> ResultLine<'a> {
+    /// Parses the number of a results line, or returns `None` for a
+    /// header or a line that names no number. A line without `form=` is of
+    /// a Mersenne number.
+    pub fn parse(line: &'a str) -> Option<ResultLine<'a>> {
+        if line.starts_with('#') {
+            return None;
+        }
+        let exponent = field(line, "exponent")?.parse().ok()?;
+        let form = match field(line, "form")
+            .unwrap_or(Form::Mersenne.as_str())
+            .parse()
+            .ok()?
+        {
+            Form::Riesel { .. } => Form::Riesel {
+                k: field(line, "k")?.parse().ok()?,
+            },
+            form => form,
+        };
+        Some(ResultLine {
+            form,
+            exponent,
+            text: line,
+        })
+    }
+
+    /// The form and exponent of the line.
+    pub fn number(&self) -> (Form, u64) {
+        (self.form, self.exponent)
+    }
+
+    /// Looks up the value of `key` in the line.
+    pub fn field(&self, key: &str) -> Option<&'a str> {
+        field(self.text, key)
+    }
+
+    /// Whether the line records a result, rather than a timeout or an
+    /// error, which leave the number to be tested again.
+    pub fn is_done(&self) -> bool {
+        !matches!(self.field("result"), Some("timeout" | "error"))
+    }
+}
+
+/// Every exponent with a result for `form` in the results file at `path`.
+/// Timeouts and errors are not results, and a missing file has no
+/// entries.
+pub fn recorded_exponents<P: AsRef<Path>>(path: P, form: Form) -> io::Result<HashSet<u64>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(e) => return Err(e),
+    };
+    let mut exponents = HashSet::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match ResultLine::parse(&line) {
+            Some(result) if result.form == form && result.is_done() => {
+                exponents.insert(result.exponent);
+            }
+            _ => {}
+        }
+    }
+    Ok(exponents)
+}
+
+/// A results file opened for appending.
+#[derive(Debug)]
+pub struct ResultsFile {
+    file: File,
+    chunk: Option<Chunk>,
+    /// The header still to be written before the next result.
+    header: Option<String>,
+}
+
+impl ResultsFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<ResultsFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(ResultsFile {
+            file,
+            chunk: None,
+            header: None,
+        })
+    }
+
+    /// Records `chunk` on every line from now on.
+    pub fn in_chunk(self, chunk: Option<Chunk>) -> ResultsFile {
+        ResultsFile { chunk, ..self }
+    }
+
+    /// Writes a header naming `system` before the first result, so a run
+    /// that records nothing leaves the file alone.
+    pub fn stamped(self, system: &SystemInfo) -> ResultsFile {
+        ResultsFile {
+            header: Some(system.fields()),
+            ..self
+        }
+    }
+
+    /// Appends one line for `report` and flushes it to disk straight away,
+    /// so a crash loses at most the tests still in flight.
+    pub fn record(&mut self, report: &TestReport) -> io::Result<()> {
+        let now = Utc::now();
+        if let Some(header) = self.header.take() {
+            writeln!(
+                self.file,
+                "# {} {}",
+                now.format("%Y-%m-%dT%H:%M:%SZ"),
+                header
+            )?;
+        }
+        let mut line = format_line(report, now);
+        if let Some(chunk) = self.chunk {
+            line.push_str(&format!(" chunk={}", chunk));
+        }
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        self.file.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{
+        format_res2048, format_res64, Confirmation, DoubleCheck, ExponentRange, FactoringStage,
+        Res64Milestone, TestKind,
+    };
+    use chrono::TimeZone;
+    use num_bigint::BigUint;
+
+    fn report(exponent: u64, prime: bool, res64: Option<u64>, factor: Option<u64>) -> TestReport {
+        TestReport {
+            prime,
+            test: factor.is_none().then_some(TestKind::LucasLehmer),
+            res64: res64.map(format_res64),
+            factor: factor.map(|factor| factor.to_string()),
+            factor_stage: factor.map(|_| FactoringStage::TrialFactoring),
+            ..TestReport::lucas_lehmer(exponent, 1.25)
+        }
+    }
+
+    #[test]
+    fn formats_each_kind_of_result() {
+        let at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            format_line(&report(29, false, Some(0x1B57CB0B), None), at),
+            "2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=000000001B57CB0B seconds=1.250"
+        );
+        assert_eq!(
+            format_line(&report(31, true, None, None), at),
+            "2024-05-01T12:00:00Z exponent=31 result=prime test=LL seconds=1.250"
+        );
+        let prp = TestReport {
+            test: Some(TestKind::Prp),
+            ..report(61, true, Some(9), None)
+        };
+        assert_eq!(
+            format_line(&prp, at),
+            "2024-05-01T12:00:00Z exponent=61 result=prime test=PRP res64=0000000000000009 seconds=1.250"
+        );
+        let wide = TestReport {
+            res2048: Some(format_res2048(&BigUint::from(1736u32))),
+            ..report(11, false, Some(1736), None)
+        };
+        assert_eq!(
+            format_line(&wide, at),
+            format!(
+                "2024-05-01T12:00:00Z exponent=11 result=composite test=LL res64=00000000000006C8 \
+                 res2048={}6c8 seconds=1.250",
+                "0".repeat(509)
+            )
+        );
+        assert_eq!(
+            format_line(&report(37, false, None, Some(223)), at),
+            "2024-05-01T12:00:00Z exponent=37 result=factored factor=223 stage=TF seconds=1.250"
+        );
+        let p1 = TestReport {
+            factor: Some("193707721".to_string()),
+            factor_stage: Some(FactoringStage::PMinus1),
+            ..report(67, false, None, Some(0))
+        };
+        assert_eq!(
+            format_line(&p1, at),
+            "2024-05-01T12:00:00Z exponent=67 result=factored factor=193707721 stage=P-1 seconds=1.250"
+        );
+        let checked = TestReport {
+            shift: Some(17),
+            double_check: Some(DoubleCheck::Mismatch),
+            ..report(29, false, Some(0x1B57CB0B), None)
+        };
+        assert_eq!(
+            format_line(&checked, at),
+            "2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=000000001B57CB0B double_check=MISMATCH shift=17 seconds=1.250"
+        );
+        let conflict = TestReport {
+            confirmation: Some(Confirmation::Conflict),
+            confirm_res64: Some(format_res64(0x1234)),
+            ..report(31, true, None, None)
+        };
+        assert_eq!(
+            format_line(&conflict, at),
+            "2024-05-01T12:00:00Z exponent=31 result=prime test=LL confirm=CONFLICT confirm_res64=0000000000001234 review=needed seconds=1.250"
+        );
+        let timed_out = TestReport {
+            timed_out_at: Some(4421),
+            ..report(44497, false, None, None)
+        };
+        assert_eq!(
+            format_line(&timed_out, at),
+            "2024-05-01T12:00:00Z exponent=44497 result=timeout test=LL iteration=4421 percent=9.9 seconds=1.250"
+        );
+        let failed = TestReport {
+            failed_at: Some(4000),
+            errors: 4,
+            ..report(86243, false, None, None)
+        };
+        assert_eq!(
+            format_line(&failed, at),
+            "2024-05-01T12:00:00Z exponent=86243 result=error test=LL iteration=4000 errors=4 seconds=1.250"
+        );
+        let panicked = TestReport {
+            test: None,
+            panic: Some("capacity overflow".to_string()),
+            ..report(86243, false, None, None)
+        };
+        assert_eq!(
+            format_line(&panicked, at),
+            "2024-05-01T12:00:00Z exponent=86243 result=error reason=panic seconds=1.250"
+        );
+        let recovered = TestReport {
+            errors: 1,
+            ..report(44497, true, None, None)
+        };
+        assert_eq!(
+            format_line(&recovered, at),
+            "2024-05-01T12:00:00Z exponent=44497 result=prime test=LL errors=1 seconds=1.250"
+        );
+        let timed = TestReport {
+            compute_seconds: Some(0.5),
+            ..report(31, true, None, None)
+        };
+        assert_eq!(
+            format_line(&timed, at),
+            "2024-05-01T12:00:00Z exponent=31 result=prime test=LL compute=0.500 seconds=1.250"
+        );
+        let milestoned = TestReport {
+            milestones: vec![
+                Res64Milestone {
+                    iteration: 1000,
+                    res64: format_res64(0xABC),
+                },
+                Res64Milestone {
+                    iteration: 2000,
+                    res64: format_res64(1),
+                },
+            ],
+            range: Some(ExponentRange {
+                start: 2000,
+                end: 2500,
+            }),
+            ..report(2203, false, Some(1), None)
+        };
+        assert_eq!(
+            format_line(&milestoned, at),
+            "2024-05-01T12:00:00Z exponent=2203 result=composite test=LL res64=0000000000000001 milestones=1000:0000000000000ABC,2000:0000000000000001 range=2000-2500 seconds=1.250"
+        );
+    }
+
+    #[test]
+    fn appends_and_reads_back_exponents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.txt");
+        assert!(recorded_exponents(&path, Form::Mersenne)
+            .unwrap()
+            .is_empty());
+
+        let mut results = ResultsFile::open(&path).unwrap();
+        results.record(&report(31, true, None, None)).unwrap();
+        results.record(&report(37, false, None, Some(223))).unwrap();
+        let timed_out = TestReport {
+            timed_out_at: Some(10),
+            ..report(41, false, None, None)
+        };
+        results.record(&timed_out).unwrap();
+        let failed = TestReport {
+            failed_at: Some(10),
+            ..report(43, false, None, None)
+        };
+        results.record(&failed).unwrap();
+        let panicked = TestReport {
+            panic: Some("capacity overflow".to_string()),
+            ..report(47, false, None, None)
+        };
+        results.record(&panicked).unwrap();
+        drop(results);
+        ResultsFile::open(&path)
+            .unwrap()
+            .record(&report(29, false, Some(1), None))
+            .unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 6);
+        assert_eq!(
+            recorded_exponents(&path, Form::Mersenne).unwrap(),
+            [29, 31, 37].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn lines_end_with_their_run_and_chunk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.txt");
+        let chunk = "2/4".parse().ok();
+        let mut results = ResultsFile::open(&path).unwrap().in_chunk(chunk);
+        let report = TestReport {
+            run: "nightly-7".parse().ok(),
+            ..report(31, true, None, None)
+        };
+        results.record(&report).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            text.trim_end()
+                .ends_with(" seconds=1.250 run=nightly-7 chunk=2/4"),
+            "{}",
+            text
+        );
+        assert_eq!(field(&text, "chunk"), Some("2/4"));
+        assert_eq!(
+            recorded_exponents(&path, Form::Mersenne).unwrap(),
+            [31].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn stamped_files_start_each_run_with_a_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.txt");
+        let system = SystemInfo::current();
+        drop(ResultsFile::open(&path).unwrap().stamped(system));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        let mut results = ResultsFile::open(&path).unwrap().stamped(system);
+        results.record(&report(31, true, None, None)).unwrap();
+        results.record(&report(61, true, None, None)).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3, "{}", text);
+        assert!(lines[0].starts_with("# 20"), "{}", text);
+        assert!(lines[0].ends_with(&system.fields()), "{}", text);
+        assert_eq!(
+            recorded_exponents(&path, Form::Mersenne).unwrap(),
+            [31, 61].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn wagstaff_results_are_kept_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.txt");
+        let wagstaff = TestReport {
+            form: Form::Wagstaff,
+            test: Some(TestKind::Prp),
+            ..report(43, true, Some(9), None)
+        };
+        let at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            format_line(&wagstaff, at),
+            "2024-05-01T12:00:00Z exponent=43 form=wagstaff result=prime test=PRP res64=0000000000000009 seconds=1.250"
+        );
+
+        let mut results = ResultsFile::open(&path).unwrap();
+        results.record(&wagstaff).unwrap();
+        results.record(&report(31, true, None, None)).unwrap();
+        assert_eq!(
+            recorded_exponents(&path, Form::Wagstaff).unwrap(),
+            [43].into_iter().collect()
+        );
+        assert_eq!(
+            recorded_exponents(&path, Form::Mersenne).unwrap(),
+            [31].into_iter().collect()
+        );
+
+        let riesel = TestReport {
+            form: Form::Riesel { k: 15 },
+            test: Some(TestKind::Llr),
+            ..report(73, true, None, None)
+        };
+        assert_eq!(
+            format_line(&riesel, at),
+            "2024-05-01T12:00:00Z exponent=73 form=riesel k=15 result=prime test=LLR seconds=1.250"
+        );
+        results.record(&riesel).unwrap();
+        assert_eq!(
+            recorded_exponents(&path, Form::Riesel { k: 15 }).unwrap(),
+            [73].into_iter().collect()
+        );
+        assert!(recorded_exponents(&path, Form::Riesel { k: 3 })
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn field_lookup_ignores_unknown_keys() {
+        let line = "2024-05-01T12:00:00Z exponent=29 future=1 result=composite";
+        assert_eq!(field(line, "exponent"), Some("29"));
+        assert_eq!(field(line, "result"), Some("composite"));
+        assert_eq!(field(line, "res64"), None);
+    }
+
+    #[test]
+    fn result_lines_are_parsed_as_far_as_their_number() {
+        let line = "2024-05-01T12:00:00Z exponent=13 form=riesel k=15 result=timeout";
+        let result = ResultLine::parse(line).unwrap();
+        assert_eq!(result.number(), (Form::Riesel { k: 15 }, 13));
+        assert!(!result.is_done());
+        let line = "2024-05-01T12:00:00Z exponent=29 result=composite res64=1B57CB0B";
+        let result = ResultLine::parse(line).unwrap();
+        assert_eq!(result.number(), (Form::Mersenne, 29));
+        assert_eq!(result.field("res64"), Some("1B57CB0B"));
+        assert!(result.is_done());
+        assert!(ResultLine::parse("# exponent=29").is_none());
+        assert!(ResultLine::parse("2024-05-01T12:00:00Z exponent=13 form=riesel").is_none());
+    }
+}
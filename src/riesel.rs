@@ -0,0 +1,207 @@
+//! The Lucas–Lehmer–Riesel test of Riesel numbers `N = k·2^n - 1`.
+//!
+//! For odd `k < 2^n`, `N` is prime exactly when `u_(n-2) ≡ 0 (mod N)`,
+//! where `u_0 = V_k(P, 1)` and `u_i = u_(i-1)^2 - 2`: the Lucas–Lehmer
+//! iteration with a starting value that depends on `k`. With `k = 1` the
+//! start is 4 and the test is the Lucas–Lehmer test of `M(n)`. The
+//! iterations run in the loop the Lucas–Lehmer test uses, through
+//! [`RieselModulus`].
+
+use crate::arith::{MersenneArith, RieselModulus};
+use crate::{numeric, run_lucas_lehmer, Interrupted, LlResult, LlSetup, TestControl, TestEvent};
+use num_bigint::BigUint;
+use std::sync::atomic::AtomicBool;
+
+/// The starting value `u_0 = V_k(P, 1) mod k·2^n - 1` of the test, for odd
+/// `k` and `n >= 2`.
+///
+/// `P` is 4 when 3 does not divide `k`. Otherwise it is the smallest
+/// `P >= 3` with `(P - 2 | N) = 1` and `(P + 2 | N) = -1`, as in Rödseth's
+/// criterion. `V_k` is then computed with the usual ladder over the bits of
+/// `k`: `V_2m = V_m^2 - 2` and `V_(2m+1) = V_m·V_(m+1) - P`.
+pub fn llr_start_value(k: u64, n: u64) -> BigUint {
+    let modulus = RieselModulus::new(k, n);
+    let p = start_parameter(k, modulus.modulus());
+    let p = modulus.reduce(BigUint::from(p));
+    let two = modulus.reduce(BigUint::from(2u32));
+    let sub = |x: BigUint, y: &BigUint| {
+        if x >= *y {
+            x - y
+        } else {
+            x + modulus.modulus() - y
+        }
+    };
+
+    // (v, w) = (V_m, V_(m+1)), starting from m = 0.
+    let mut v = two.clone();
+    let mut w = p.clone();
+    for bit in (0..64 - k.leading_zeros()).rev() {
+        if (k >> bit) & 1 == 1 {
+            v = sub(modulus.mul(&v, &w), &p);
+            w = sub(modulus.square(&w), &two);
+        } else {
+            w = sub(modulus.mul(&v, &w), &p);
+            v = sub(modulus.square(&v), &two);
+        }
+    }
+    v
+}
+
+/// The `P` of [`llr_start_value`].
+fn start_parameter(k: u64, modulus: &BigUint) -> u64 {
+    if !k.is_multiple_of(3) {
+        return 4;
+    }
+    (3..)
+        .find(|&p| {
+            numeric::jacobi(&BigUint::from(p - 2), modulus) == 1
+                && numeric::jacobi(&BigUint::from(p + 2), modulus) == -1
+        })
+        .expect("k·2^n - 1 is not a square, so some P qualifies")
+}
+
+/// Runs the Lucas–Lehmer–Riesel test on `k·2^n - 1`.
+///
+/// # Panics
+///
+/// If `k` is even, `n < 2`, or `k >= 2^n`, where the test proves nothing.
+pub fn is_riesel_prime(k: u64, n: u64) -> LlResult {
+    let never = AtomicBool::new(false);
+    match is_riesel_prime_interruptible(k, n, TestControl::new(&never), |_| {}) {
+        Ok(result) => result,
+        // The stop flag is never raised.
+        Err(interrupted) => LlResult::Aborted {
+            iteration: interrupted.iteration,
+        },
+    }
+}
+
+/// Same as [`is_riesel_prime`], but with the events and interruption of
+/// [`crate::is_mersenne_prime_interruptible`]. Progress counts the `n - 2`
+/// iterations.
+///
+/// The Jacobi check and the shift in `control` are ignored: neither carries
+/// over from Mersenne numbers, where the check relies on `(12 | M(p)) = -1`
+/// and a shift on `2^p ≡ 1`.
+pub fn is_riesel_prime_interruptible<F>(
+    k: u64,
+    n: u64,
+    control: TestControl,
+    on_event: F,
+) -> Result<LlResult, Interrupted>
+where
+    F: FnMut(TestEvent),
+{
+    assert!(
+        k % 2 == 1 && n >= 2 && (n >= 64 || k < 1 << n),
+        "{}·2^{} - 1 is outside the Lucas–Lehmer–Riesel test",
+        k,
+        n
+    );
+    // 3 = 1·2^2 - 1 takes no iterations, and its start value 4 ≡ 1 is not
+    // zero.
+    if k == 1 && n == 2 {
+        return Ok(LlResult::Prime);
+    }
+    let modulus = RieselModulus::new(k, n).with_threads(control.threads);
+    let setup = LlSetup {
+        start: MersenneArith::reduce(&modulus, llr_start_value(k, n)),
+        modulus,
+        iterations: n - 2,
+        p: n,
+        jacobi_modulus: None,
+    };
+    let control = TestControl {
+        jacobi_interval: None,
+        ..control.with_shift(0)
+    };
+    run_lucas_lehmer(setup, None, control, on_event, |_, _| {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::is_prime;
+
+    #[test]
+    fn agrees_with_trial_division() {
+        for k in [1, 3, 5, 7, 9, 15, 105] {
+            for n in (2..=50).filter(|&n| k < 1 << n) {
+                let expected = is_prime(k * (1 << n) - 1);
+                assert_eq!(
+                    is_riesel_prime(k, n).is_prime(),
+                    expected,
+                    "{}·2^{} - 1",
+                    k,
+                    n
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finds_known_riesel_primes() {
+        // 3·2^2 - 1 = 11 is the smallest; the rest are from the published
+        // lists of n for k = 3, 5 and 15.
+        assert!(is_riesel_prime(3, 2).is_prime());
+        for n in [64, 76, 94, 103, 143] {
+            assert!(is_riesel_prime(3, n).is_prime(), "3·2^{} - 1", n);
+        }
+        for n in [54, 72, 148, 184] {
+            assert!(is_riesel_prime(5, n).is_prime(), "5·2^{} - 1", n);
+        }
+        for n in [73, 80, 82, 116, 125, 145, 157, 172, 202, 224, 266, 289, 293] {
+            assert!(is_riesel_prime(15, n).is_prime(), "15·2^{} - 1", n);
+        }
+        // 15·2^229 - 1 sometimes appears in lists of these primes by mistake.
+        for n in [65, 75, 95, 144, 229, 230] {
+            assert!(!is_riesel_prime(15, n).is_prime(), "15·2^{} - 1", n);
+        }
+    }
+
+    #[test]
+    fn matches_the_lucas_lehmer_test_for_k_1() {
+        for p in [61, 89, 101, 107, 127, 137] {
+            assert_eq!(
+                is_riesel_prime(1, p),
+                crate::is_mersenne_prime(p),
+                "M({})",
+                p
+            );
+        }
+    }
+
+    #[test]
+    fn start_values_and_residues() {
+        assert_eq!(llr_start_value(3, 2), BigUint::from(0u32));
+        assert_eq!(llr_start_value(5, 8), BigUint::from(724u32));
+        assert_eq!(llr_start_value(15, 10), BigUint::from(1854u32));
+        assert_eq!(llr_start_value(7, 5), BigUint::from(49u32));
+        assert_eq!(llr_start_value(1, 31), BigUint::from(4u32));
+        let composites = [
+            (3, 5, 0x34),
+            (5, 7, 0x23c),
+            (15, 12, 0x42f9),
+            (9, 40, 0x16e26388ce5),
+        ];
+        for (k, n, res64) in composites {
+            assert_eq!(
+                is_riesel_prime(k, n),
+                LlResult::Composite { res64 },
+                "{}·2^{} - 1",
+                k,
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn splitting_squarings_does_not_change_the_result() {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).split_across(3);
+        assert_eq!(
+            is_riesel_prime_interruptible(15, 293, control, |_| {}),
+            Ok(LlResult::Prime)
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! The identity of a run, which ties together everything it writes.
+//!
+//! Every run of `search`, `test`, `fermat` or `work` gets a [`RunId`] as it
+//! starts, such as `20240501T120000Z-3f9a2c`: the time in UTC and random
+//! digits that tell apart runs started in the same second. Its results
+//! lines, `--json` reports, ledger entries and CSV rows, its `--events`,
+//! `--status-file`, checkpoints and audit log lines, and its row in a
+//! `--db` all carry it, so the files of one run can be picked out of those
+//! of many. `--run-id` gives a run an ID of its own choosing instead, so
+//! a run that resumes the work of another can keep that one's identity.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The longest ID `--run-id` takes.
+pub const MAX_LEN: usize = 64;
+
+/// The ID of a run: at most [`MAX_LEN`] letters, digits and `-`, `_`, `.`
+/// or `:`, so it fits into every format unquoted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RunId(String);
+
+impl RunId {
+    /// The ID of a run that started at `started`, told apart from others
+    /// that started in the same second by the low 24 bits of `salt`, which
+    /// should be random.
+    pub fn new(started: DateTime<Utc>, salt: u32) -> RunId {
+        RunId(format!(
+            "{}-{:06x}",
+            started.format("%Y%m%dT%H%M%SZ"),
+            salt & 0xFF_FFFF
+        ))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RunId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for RunId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RunId, String> {
+        let allowed = |c: char| c.is_ascii_alphanumeric() || "-_.:".contains(c);
+        if s.is_empty() || s.len() > MAX_LEN || !s.chars().all(allowed) {
+            return Err(format!(
+                "invalid run ID {:?}; it takes 1 to {} letters, digits, '-', '_', '.' and ':'",
+                s, MAX_LEN
+            ));
+        }
+        Ok(RunId(s.to_string()))
+    }
+}
+
+impl TryFrom<String> for RunId {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<RunId, String> {
+        s.parse()
+    }
+}
+
+impl From<RunId> for String {
+    fn from(id: RunId) -> String {
+        id.0
+    }
+}
+
+/// What every output of a run shares: its ID and when it began. Built once
+/// at start-up and handed to whatever writes for the run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunContext {
+    pub id: RunId,
+    pub started: DateTime<Utc>,
+}
+
+impl RunContext {
+    /// A run starting now, with the ID `id` if it was given one and a new
+    /// one salted with `salt` otherwise.
+    pub fn start(id: Option<RunId>, salt: u32) -> RunContext {
+        let started = Utc::now();
+        RunContext {
+            id: id.unwrap_or_else(|| RunId::new(started, salt)),
+            started,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn new_ids_are_the_time_and_the_salt() {
+        let started = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let id = RunId::new(started, 0xAB3F_9A2C);
+        assert_eq!(id.as_str(), "20240501T120000Z-3f9a2c");
+        assert_eq!(id.as_str().parse(), Ok(id));
+    }
+
+    #[test]
+    fn ids_fit_every_format_unquoted() {
+        assert!("nightly-2024.05_r2:a".parse::<RunId>().is_ok());
+        for bad in [
+            "",
+            "two words",
+            "a=b",
+            "a,b",
+            "\"a\"",
+            &"x".repeat(MAX_LEN + 1),
+        ] {
+            assert!(bad.parse::<RunId>().is_err(), "{:?}", bad);
+        }
+        assert!(serde_json::from_str::<RunId>("\"a b\"").is_err());
+        let context = RunContext::start(Some("mine".parse().unwrap()), 0);
+        assert_eq!(context.id.as_str(), "mine");
+    }
+}
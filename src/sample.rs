@@ -0,0 +1,343 @@
+//! Re-verifying a random sample of the composite results of an earlier
+//! run, for `verify-sample`, to estimate how often the machine that
+//! produced them got a test wrong.
+//!
+//! The results are read from a results file, a `--ledger` or `--json`
+//! output, as for [`compare`](crate::compare); of several lines for one
+//! number the last counts. The sample is the given fraction of the
+//! composites, rounded up, that rank first by a hash of the seed and the
+//! exponent: the same seed picks the same exponents from the same results
+//! on any machine, whatever order their lines are in.
+//!
+//! Each picked exponent is tested again by the test that produced its
+//! result. The final Res64 does not depend on the shift of a Lucas-Lehmer
+//! test, so the re-run needs none; one that comes out different, or prime,
+//! is a mismatch. A machine that gets `e` tests of `n` wrong has an error
+//! rate of about `e/n`; [`Tally::error_rate`] gives the 95% Wilson
+//! interval around it, which stays honest for samples with no mismatches.
+
+use crate::report::{Form, TestKind, TestReport};
+use crate::results::ResultLine;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A composite result: the test of `exponent` and the Res64 it ended with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recorded {
+    pub exponent: u64,
+    pub test: TestKind,
+    pub res64: String,
+}
+
+/// Reads the composite results for `form` in `text`, the contents of a
+/// results file, a ledger or `--json` output, by exponent. Results that
+/// are not composites, such as factors and primes, replace an earlier
+/// composite of the same exponent; timeouts and errors are skipped.
+pub fn read_composites(text: &str, form: Form) -> Vec<Recorded> {
+    let mut results: BTreeMap<u64, Option<Recorded>> = BTreeMap::new();
+    for line in text.lines() {
+        let result = if line.starts_with('{') {
+            serde_json::from_str::<TestReport>(line)
+                .ok()
+                .filter(|report| report.form == form && report.has_result())
+                .map(|report| (report.exponent, composite_of_report(report)))
+        } else {
+            parse_results_line(line, form)
+        };
+        if let Some((exponent, recorded)) = result {
+            results.insert(exponent, recorded);
+        }
+    }
+    results.into_values().flatten().collect()
+}
+
+/// The composite result of `report`, if it is one.
+fn composite_of_report(report: TestReport) -> Option<Recorded> {
+    if report.prime || report.factor.is_some() {
+        return None;
+    }
+    Some(Recorded {
+        exponent: report.exponent,
+        test: report.test?,
+        res64: report.res64?,
+    })
+}
+
+/// The exponent of a results line for `form` and its composite result, if
+/// it is one, or `None` for a header, a timeout, an error or a line of
+/// another form.
+fn parse_results_line(line: &str, form: Form) -> Option<(u64, Option<Recorded>)> {
+    let line = ResultLine::parse(line)?;
+    let result = line.field("result");
+    if result.is_none() || !line.is_done() || line.form != form {
+        return None;
+    }
+    let recorded = (|| {
+        if result != Some("composite") {
+            return None;
+        }
+        Some(Recorded {
+            exponent: line.exponent,
+            test: line.field("test")?.parse().ok()?,
+            res64: line.field("res64")?.to_string(),
+        })
+    })();
+    Some((line.exponent, recorded))
+}
+
+/// The share `fraction` of `composites`, rounded up, picked by `seed`, by
+/// exponent. A `fraction` of 1 or more picks them all.
+pub fn pick(composites: &[Recorded], fraction: f64, seed: u64) -> Vec<&Recorded> {
+    let count = (composites.len() as f64 * fraction.clamp(0.0, 1.0)).ceil() as usize;
+    let mut ranked: Vec<&Recorded> = composites.iter().collect();
+    ranked.sort_by_key(|recorded| (rank(seed, recorded.exponent), recorded.exponent));
+    ranked.truncate(count);
+    ranked.sort_by_key(|recorded| recorded.exponent);
+    ranked
+}
+
+/// Where `exponent` ranks for `seed`: splitmix64 of the two, so that
+/// nearby exponents and seeds rank far apart.
+fn rank(seed: u64, exponent: u64) -> u64 {
+    let mix = |mut z: u64| {
+        z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    mix(mix(seed) ^ exponent)
+}
+
+/// A re-run that did not agree with the recorded result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub recorded: Recorded,
+    /// The Res64 of the re-run, or `None` if it found the number prime.
+    pub found: Option<String>,
+}
+
+/// How the re-runs of a sample compared with the recorded results.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tally {
+    pub agreed: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// A share of tests that went wrong, with its 95% confidence interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorRate {
+    pub estimate: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Tally {
+    /// Counts a re-run of `recorded` that found the number `prime`, or
+    /// composite with `res64`. Res64s are compared without regard to case.
+    pub fn record(&mut self, recorded: &Recorded, prime: bool, res64: Option<&str>) {
+        match res64 {
+            Some(res64) if !prime && res64.eq_ignore_ascii_case(&recorded.res64) => {
+                self.agreed += 1
+            }
+            _ => self.mismatches.push(Mismatch {
+                recorded: recorded.clone(),
+                found: res64.filter(|_| !prime).map(str::to_string),
+            }),
+        }
+    }
+
+    /// The re-runs counted.
+    pub fn checked(&self) -> usize {
+        self.agreed + self.mismatches.len()
+    }
+
+    /// The share of the re-runs that disagreed, with its Wilson score
+    /// interval, or `None` before any were counted.
+    pub fn error_rate(&self) -> Option<ErrorRate> {
+        const Z: f64 = 1.96;
+        let n = self.checked() as f64;
+        if n == 0.0 {
+            return None;
+        }
+        let estimate = self.mismatches.len() as f64 / n;
+        let scale = 1.0 + Z * Z / n;
+        let center = (estimate + Z * Z / (2.0 * n)) / scale;
+        let spread = Z / scale * (estimate * (1.0 - estimate) / n + Z * Z / (4.0 * n * n)).sqrt();
+        Some(ErrorRate {
+            estimate,
+            low: (center - spread).max(0.0),
+            high: (center + spread).min(1.0),
+        })
+    }
+
+    /// The exponents that disagreed, one per line, in order: an exponent
+    /// list for `test`.
+    pub fn mismatch_list(&self) -> String {
+        let mut exponents: Vec<u64> = self
+            .mismatches
+            .iter()
+            .map(|mismatch| mismatch.recorded.exponent)
+            .collect();
+        exponents.sort_unstable();
+        exponents.iter().map(|p| format!("{}\n", p)).collect()
+    }
+
+    /// Writes [`mismatch_list`](Self::mismatch_list) to `path`, replacing
+    /// what it held.
+    pub fn write_mismatches<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.mismatch_list())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(exponent: u64, res64: &str) -> Recorded {
+        Recorded {
+            exponent,
+            test: TestKind::LucasLehmer,
+            res64: res64.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_the_last_composite_result_of_each_exponent() {
+        let text = "\
+# 2024-05-01T12:00:00Z version=0.1.0 cpu=Some CPU
+2024-05-01T12:00:00Z exponent=29 result=composite test=LL res64=000000001B57CB0B seconds=0.001
+2024-05-01T12:00:00Z exponent=23 result=factored factor=47 stage=TF seconds=0.000
+2024-05-01T12:00:00Z exponent=31 result=prime test=LL seconds=0.000
+2024-05-01T12:00:00Z exponent=41 result=composite test=PRP res64=00000000000000AA seconds=0.001
+2024-05-01T12:00:00Z exponent=41 result=timeout test=PRP iteration=3 percent=7.3 seconds=0.001
+2024-05-01T12:00:00Z exponent=43 form=wagstaff result=composite test=PRP res64=0000000000000001 seconds=0.001
+2024-05-01T12:00:00Z exponent=47 result=composite test=LL res64=00000000000000BB seconds=0.001
+2024-05-01T12:00:00Z exponent=47 result=factored factor=2351 stage=P-1 seconds=0.001
+{\"exponent\":53,\"prime\":false,\"test\":\"LL\",\"seconds\":0.0,\"res64\":\"00000000000000CC\",\"factor\":null,\"factor_stage\":null,\"shift\":null,\"double_check\":null,\"confirmation\":null,\"confirm_res64\":null,\"timed_out_at\":null,\"failed_at\":null,\"errors\":0}
+{\"exponent\":59,\"prime\":false,\"test\":\"LL\",\"seconds\":0.0,\"res64\":null,\"factor\":null,\"factor_stage\":null,\"shift\":null,\"double_check\":null,\"confirmation\":null,\"confirm_res64\":null,\"timed_out_at\":12,\"failed_at\":null,\"errors\":0}
+{\"summary\":{\"tested\":3}}
+";
+        let composites = read_composites(text, Form::Mersenne);
+        assert_eq!(
+            composites,
+            [
+                recorded(29, "000000001B57CB0B"),
+                Recorded {
+                    exponent: 41,
+                    test: TestKind::Prp,
+                    res64: "00000000000000AA".to_string()
+                },
+                recorded(53, "00000000000000CC"),
+            ]
+        );
+        assert_eq!(
+            read_composites(text, Form::Wagstaff),
+            [Recorded {
+                exponent: 43,
+                test: TestKind::Prp,
+                res64: "0000000000000001".to_string()
+            }]
+        );
+        assert!(read_composites(text, Form::Riesel { k: 3 }).is_empty());
+    }
+
+    #[test]
+    fn picks_reproducibly_whatever_the_order() {
+        let composites: Vec<Recorded> = (1..=1000)
+            .map(|i| recorded(2 * i + 1, "0000000000000001"))
+            .collect();
+        let picked = pick(&composites, 0.02, 42);
+        assert_eq!(picked.len(), 20);
+        assert!(picked
+            .windows(2)
+            .all(|pair| pair[0].exponent < pair[1].exponent));
+        assert_eq!(pick(&composites, 0.02, 42), picked);
+
+        let mut reversed = composites.clone();
+        reversed.reverse();
+        assert_eq!(pick(&reversed, 0.02, 42), picked);
+
+        // Another seed picks others, and the picks are spread out.
+        let other = pick(&composites, 0.02, 43);
+        assert!(other.iter().filter(|r| picked.contains(r)).count() < 5);
+        assert!(picked[0].exponent < 500 && picked[19].exponent > 1500);
+
+        // At least one is picked from a short list, and never more than all.
+        assert_eq!(pick(&composites[..10], 0.02, 42).len(), 1);
+        assert_eq!(pick(&composites[..10], 5.0, 42).len(), 10);
+        assert!(pick(&[], 0.5, 42).is_empty());
+    }
+
+    #[test]
+    fn tallies_agreements_and_mismatches() {
+        let mut tally = Tally::default();
+        assert_eq!(tally.error_rate(), None);
+        for p in 0..97 {
+            tally.record(
+                &recorded(p, "00000000000000AA"),
+                false,
+                Some("00000000000000aa"),
+            );
+        }
+        tally.record(
+            &recorded(107, "00000000000000AA"),
+            false,
+            Some("00000000000000AB"),
+        );
+        tally.record(&recorded(103, "00000000000000AA"), true, None);
+        tally.record(
+            &recorded(101, "00000000000000AA"),
+            false,
+            Some("00000000000000AC"),
+        );
+        assert_eq!(tally.agreed, 97);
+        assert_eq!(tally.checked(), 100);
+        assert_eq!(
+            tally.mismatches[1],
+            Mismatch {
+                recorded: recorded(103, "00000000000000AA"),
+                found: None,
+            }
+        );
+        assert_eq!(
+            tally.mismatches[0].found.as_deref(),
+            Some("00000000000000AB")
+        );
+        assert_eq!(tally.mismatch_list(), "101\n103\n107\n");
+
+        let rate = tally.error_rate().unwrap();
+        assert_eq!(rate.estimate, 0.03);
+        assert!((rate.low - 0.0103).abs() < 1e-4, "{:?}", rate);
+        assert!((rate.high - 0.0845).abs() < 1e-4, "{:?}", rate);
+
+        // No mismatches still bounds the rate from above.
+        let clean = Tally {
+            agreed: 50,
+            mismatches: Vec::new(),
+        };
+        let rate = clean.error_rate().unwrap();
+        assert_eq!((rate.estimate, rate.low), (0.0, 0.0));
+        assert!((rate.high - 0.0713).abs() < 1e-4, "{:?}", rate);
+    }
+
+    #[test]
+    fn writes_the_mismatches_as_an_exponent_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mismatches.txt");
+        let mut tally = Tally::default();
+        tally.record(
+            &recorded(89, "0000000000000001"),
+            false,
+            Some("0000000000000002"),
+        );
+        tally.record(
+            &recorded(61, "0000000000000001"),
+            false,
+            Some("0000000000000003"),
+        );
+        tally.write_mismatches(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "61\n89\n");
+    }
+}
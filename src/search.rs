@@ -0,0 +1,407 @@
+//! Searching a range of Mersenne exponents from another program, with a
+//! [`CancellationToken`] that stops or pauses the whole search.
+//!
+//! [`search_range`] starts the search on threads of its own and returns a
+//! [`SearchHandle`] to steer it with and the [`Reports`] of the exponents
+//! as they finish, in the order they finish:
+//!
+//! ```no_run
+//! use mersenne::search::{search_range, SearchConfig};
+//!
+//! let (handle, reports) = search_range(SearchConfig::new(9000, 10000));
+//! for report in reports {
+//!     if report.prime {
+//!         println!("M({}) is prime", report.exponent);
+//!         handle.cancel();
+//!     }
+//! }
+//! ```
+//!
+//! Cancelling stops the search taking new exponents and interrupts the
+//! tests under way before their next iteration. Reports already finished
+//! are still yielded; the interrupted tests yield none, and
+//! [`SearchHandle::interrupted`] says how far they got. Pausing holds the
+//! tests under way where they are, and resuming carries them on.
+
+use crate::factor::{trial_factor, worthwhile_tf_depth};
+use crate::pause::Pause;
+use crate::report::{format_res64, FactoringStage, Form, TestKind, TestReport};
+use crate::sieve::{self, Primes};
+use crate::{is_mersenne_prime_interruptible, Interrupted, LlResult, TestControl};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// Stops or pauses every test that is given its [control](Self::control).
+#[derive(Debug)]
+pub struct CancellationToken {
+    stop: AtomicBool,
+    pause: Pause,
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+impl CancellationToken {
+    pub const fn new() -> CancellationToken {
+        CancellationToken {
+            stop: AtomicBool::new(false),
+            pause: Pause::new(),
+        }
+    }
+
+    /// Cancels everything the token controls; it cannot be undone. Returns
+    /// whether it was cancelled already.
+    pub fn cancel(&self) -> bool {
+        self.stop.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// Holds the tests until [`resume`](Self::resume) or a cancellation.
+    pub fn pause(&self) {
+        self.pause.set(true);
+    }
+
+    pub fn resume(&self) {
+        self.pause.set(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+
+    /// Waits while the token is paused and not cancelled.
+    pub fn wait_while_paused(&self) {
+        self.pause.wait(&self.stop);
+    }
+
+    /// The flag that [`cancel`](Self::cancel) raises, for what takes a
+    /// bare stop flag.
+    pub fn stop_flag(&self) -> &AtomicBool {
+        &self.stop
+    }
+
+    /// The pause behind [`pause`](Self::pause), which also keeps the time
+    /// spent paused.
+    pub fn pauses(&self) -> &Pause {
+        &self.pause
+    }
+
+    /// A [`TestControl`] that the token stops and pauses.
+    pub fn control(&self) -> TestControl<'_> {
+        TestControl::new(&self.stop).pause_with(&self.pause)
+    }
+}
+
+/// What [`search_range`] searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchConfig {
+    /// The prime exponents in `start..=end` are tested.
+    pub start: u64,
+    pub end: u64,
+    /// How many exponents are tested at once, each on a thread of its own.
+    pub threads: usize,
+    /// How deep to trial factor before testing, in bits, or `None` for
+    /// [`worthwhile_tf_depth`].
+    pub tf_depth: Option<u32>,
+}
+
+impl SearchConfig {
+    /// Searches `start..=end` one exponent at a time.
+    pub fn new(start: u64, end: u64) -> SearchConfig {
+        SearchConfig {
+            start,
+            end,
+            threads: 1,
+            tf_depth: None,
+        }
+    }
+}
+
+/// Steers a search started by [`search_range`]. Clones steer the same
+/// search.
+#[derive(Debug, Clone)]
+pub struct SearchHandle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    token: CancellationToken,
+    candidates: Mutex<Primes>,
+    /// The exponent each thread is testing, and its iterations done.
+    in_flight: Vec<(Mutex<Option<u64>>, AtomicU64)>,
+    interrupted: Mutex<Vec<(u64, Interrupted)>>,
+}
+
+impl SearchHandle {
+    /// Stops the search; see the [module docs](self).
+    pub fn cancel(&self) {
+        self.shared.token.cancel();
+    }
+
+    pub fn pause(&self) {
+        self.shared.token.pause();
+    }
+
+    pub fn resume(&self) {
+        self.shared.token.resume();
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.shared.token
+    }
+
+    /// The exponents being tested, with the iterations done in each.
+    pub fn in_flight(&self) -> Vec<(u64, u64)> {
+        self.shared
+            .in_flight
+            .iter()
+            .filter_map(|(p, iteration)| {
+                let p = (*p.lock().unwrap())?;
+                Some((p, iteration.load(Ordering::SeqCst)))
+            })
+            .collect()
+    }
+
+    /// The exponents whose tests stopped early, and where: those a
+    /// cancellation interrupted, and any given up on after an arithmetic
+    /// anomaly.
+    pub fn interrupted(&self) -> Vec<(u64, Interrupted)> {
+        self.shared.interrupted.lock().unwrap().clone()
+    }
+}
+
+/// The reports of a search, as its tests finish. It ends once the range
+/// is done or the search is cancelled, and dropping it cancels the search.
+#[derive(Debug)]
+pub struct Reports {
+    receiver: Receiver<TestReport>,
+    workers: Vec<JoinHandle<()>>,
+    shared: Arc<Shared>,
+}
+
+impl Iterator for Reports {
+    type Item = TestReport;
+
+    fn next(&mut self) -> Option<TestReport> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for Reports {
+    fn drop(&mut self) {
+        self.shared.token.cancel();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Starts searching the range of `config`; see the [module docs](self).
+pub fn search_range(config: SearchConfig) -> (SearchHandle, Reports) {
+    let threads = config.threads.max(1);
+    let shared = Arc::new(Shared {
+        token: CancellationToken::new(),
+        candidates: Mutex::new(sieve::primes(config.start, config.end)),
+        in_flight: (0..threads)
+            .map(|_| (Mutex::new(None), AtomicU64::new(0)))
+            .collect(),
+        interrupted: Mutex::new(Vec::new()),
+    });
+    let (sender, receiver) = mpsc::channel();
+    let workers = (0..threads)
+        .map(|index| {
+            let (shared, sender) = (shared.clone(), sender.clone());
+            thread::spawn(move || work(&shared, index, config.tf_depth, sender))
+        })
+        .collect();
+    let handle = SearchHandle {
+        shared: shared.clone(),
+    };
+    (
+        handle,
+        Reports {
+            receiver,
+            workers,
+            shared,
+        },
+    )
+}
+
+/// Tests exponents on thread `index` until there are none left or the
+/// search is cancelled.
+fn work(shared: &Shared, index: usize, tf_depth: Option<u32>, reports: Sender<TestReport>) {
+    let (testing, iteration) = &shared.in_flight[index];
+    loop {
+        shared.token.wait_while_paused();
+        if shared.token.is_cancelled() {
+            return;
+        }
+        let Some(p) = shared.candidates.lock().unwrap().next() else {
+            return;
+        };
+        iteration.store(0, Ordering::SeqCst);
+        *testing.lock().unwrap() = Some(p);
+        let tested = test(p, tf_depth, shared.token.control().publish_to(iteration));
+        *testing.lock().unwrap() = None;
+        match tested {
+            Ok(report) => {
+                if reports.send(report).is_err() {
+                    return;
+                }
+            }
+            Err(interrupted) => shared.interrupted.lock().unwrap().push((p, interrupted)),
+        }
+    }
+}
+
+/// Trial factors `M(p)` and, if no factor turns up, tests it.
+fn test(p: u64, tf_depth: Option<u32>, control: TestControl) -> Result<TestReport, Interrupted> {
+    let started = Instant::now();
+    let report = TestReport {
+        exponent: p,
+        form: Form::Mersenne,
+        prime: false,
+        test: None,
+        seconds: 0.0,
+        compute_seconds: None,
+        res64: None,
+        res2048: None,
+        factor: None,
+        factor_stage: None,
+        shift: None,
+        double_check: None,
+        backend: None,
+        check_backend: None,
+        confirmation: None,
+        confirm_res64: None,
+        timed_out_at: None,
+        failed_at: None,
+        errors: 0,
+        checkpoints: 0,
+        throughput: None,
+        panic: None,
+        milestones: Vec::new(),
+        range: None,
+        run: None,
+    };
+    let depth = tf_depth.unwrap_or_else(|| worthwhile_tf_depth(p));
+    if let Some(factor) = trial_factor(p, depth) {
+        return Ok(TestReport {
+            seconds: started.elapsed().as_secs_f64(),
+            factor: Some(factor.to_string()),
+            factor_stage: Some(FactoringStage::TrialFactoring),
+            ..report
+        });
+    }
+    let result = is_mersenne_prime_interruptible(p, None, control, |_| {})?;
+    Ok(TestReport {
+        prime: result.is_prime(),
+        test: Some(TestKind::LucasLehmer),
+        seconds: started.elapsed().as_secs_f64(),
+        compute_seconds: None,
+        res64: match result {
+            LlResult::Composite { res64 } => Some(format_res64(res64)),
+            _ => None,
+        },
+        ..report
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Waits until `handle` is testing an exponent of at least `p` and has
+    /// done `iterations` of it, and returns where it is.
+    fn wait_for(handle: &SearchHandle, p: u64, iterations: u64) -> (u64, u64) {
+        loop {
+            if let Some(&at) = handle
+                .in_flight()
+                .iter()
+                .find(|&&(q, done)| q >= p && done >= iterations)
+            {
+                return at;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn finds_the_primes_of_a_range() {
+        let config = SearchConfig {
+            threads: 2,
+            ..SearchConfig::new(2, 130)
+        };
+        let (_, reports) = search_range(config);
+        let mut primes: Vec<u64> = reports
+            .filter(|report| report.prime)
+            .map(|report| report.exponent)
+            .collect();
+        primes.sort_unstable();
+        assert_eq!(primes, [2, 3, 5, 7, 13, 17, 19, 31, 61, 89, 107, 127]);
+    }
+
+    #[test]
+    fn cancelling_interrupts_the_test_under_way_within_an_iteration() {
+        let config = SearchConfig {
+            tf_depth: Some(0),
+            ..SearchConfig::new(4400, 9941)
+        };
+        let (handle, reports) = search_range(config);
+        let (p, at) = wait_for(&handle, 4424, 100);
+        handle.cancel();
+        let reports: Vec<TestReport> = reports.collect();
+
+        // Everything below the interrupted exponent finished and is
+        // yielded, in order since one thread tests them all.
+        let expected: Vec<u64> = sieve::primes(4400, p - 1).collect();
+        let yielded: Vec<u64> = reports.iter().map(|report| report.exponent).collect();
+        assert_eq!(yielded, expected);
+        assert!(reports
+            .iter()
+            .any(|report| report.exponent == 4423 && report.prime));
+        let interrupted = handle.interrupted();
+        assert_eq!(interrupted.len(), 1);
+        let (q, stopped) = interrupted[0];
+        assert_eq!(q, p);
+        assert!(
+            (at..=at + 2).contains(&stopped.iteration),
+            "{} iterations after cancelling at {}",
+            stopped.iteration - at,
+            at
+        );
+        assert!(handle.in_flight().is_empty());
+    }
+
+    #[test]
+    fn pausing_holds_the_test_under_way() {
+        let config = SearchConfig {
+            tf_depth: Some(0),
+            ..SearchConfig::new(9941, 9941)
+        };
+        let (handle, reports) = search_range(config);
+        wait_for(&handle, 9941, 100);
+        handle.pause();
+        // The test stops before its next iteration, and stays there.
+        thread::sleep(Duration::from_millis(50));
+        let (_, held) = handle.in_flight()[0];
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(handle.in_flight(), [(9941, held)]);
+        handle.resume();
+        let reports: Vec<TestReport> = reports.collect();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].prime);
+        assert!(handle.interrupted().is_empty());
+    }
+}
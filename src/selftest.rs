@@ -0,0 +1,111 @@
+//! The `selftest` subcommand: checks the Lucas–Lehmer implementation
+//! against known answers before it is trusted with a long run.
+
+use crate::progress::format_duration;
+use mersenne::known::MERSENNE_EXPONENTS;
+use mersenne::report::format_res64;
+use mersenne::{is_mersenne_prime, LlResult};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::time::Instant;
+
+/// How many of the known Mersenne primes are checked, starting from the
+/// smallest. The rest take too long for a quick check.
+const PRIMES_CHECKED: usize = 20;
+
+/// Prime exponents whose Mersenne number is composite, with the low 64 bits
+/// of the final Lucas–Lehmer residue.
+const COMPOSITES: [(u64, u64); 20] = [
+    (11, 0x00000000000006C8),
+    (23, 0x00000000005D32F7),
+    (29, 0x000000001B57CB0B),
+    (37, 0x0000001B435853C0),
+    (41, 0x000000C771A34E19),
+    (43, 0x000005407522FC59),
+    (47, 0x000057F28CACB060),
+    (53, 0x0014A4AA2AF1C57D),
+    (59, 0x064099E5FCBCAF36),
+    (67, 0x677D24EE8AE3B2C2),
+    (71, 0xBB737B29D59E0C94),
+    (101, 0xD0DD748DD7817436),
+    (251, 0x8F5A3D3EBBFD8554),
+    (503, 0x3DD63406BBEDFEBD),
+    (1009, 0x5C0842EAA6DF00C6),
+    (1277, 0x5613A480590E78BA),
+    (2003, 0xFA6922742D975F44),
+    (3001, 0x1B916B735B21FCD4),
+    (4001, 0x2EB1882EE9B7207E),
+    (4421, 0x436652647E1E860B),
+];
+
+fn describe(result: LlResult) -> String {
+    match result {
+        LlResult::Prime => "prime".to_string(),
+        LlResult::Composite { res64 } => format!("composite, Res64 0x{}", format_res64(res64)),
+        LlResult::Aborted { iteration } => format!("aborted after iteration {}", iteration),
+    }
+}
+
+/// Runs every check on `pool` and prints one line per exponent. Returns
+/// `true` if every answer was right.
+pub fn run(pool: &ThreadPool) -> bool {
+    let mut cases: Vec<(u64, LlResult)> = MERSENNE_EXPONENTS[..PRIMES_CHECKED]
+        .iter()
+        .map(|&p| (p, LlResult::Prime))
+        .chain(
+            COMPOSITES
+                .iter()
+                .map(|&(p, res64)| (p, LlResult::Composite { res64 })),
+        )
+        .collect();
+    cases.sort_unstable_by_key(|&(p, _)| p);
+
+    println!("Self-test: checking {} exponents...", cases.len());
+    let start_time = Instant::now();
+    let outcomes: Vec<(LlResult, f64)> = pool.install(|| {
+        cases
+            .par_iter()
+            .map(|&(p, _)| {
+                let started = Instant::now();
+                let result = is_mersenne_prime(p);
+                (result, started.elapsed().as_secs_f64())
+            })
+            .collect()
+    });
+
+    let mut failures = 0;
+    for (&(p, expected), &(result, seconds)) in cases.iter().zip(&outcomes) {
+        if result == expected {
+            println!(
+                "  ok    M({}) is {} ({})",
+                p,
+                describe(result),
+                format_duration(seconds)
+            );
+        } else {
+            failures += 1;
+            println!(
+                "  FAIL  M({}): expected {}, got {}",
+                p,
+                describe(expected),
+                describe(result)
+            );
+        }
+    }
+
+    let seconds = start_time.elapsed().as_secs_f64();
+    if failures == 0 {
+        println!(
+            "Self-test passed: all {} exponents correct in {}.",
+            cases.len(),
+            format_duration(seconds)
+        );
+    } else {
+        println!(
+            "Self-test FAILED: {} of {} exponents gave a wrong result. Do not trust this build.",
+            failures,
+            cases.len()
+        );
+    }
+    failures == 0
+}
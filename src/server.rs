@@ -0,0 +1,173 @@
+//! `serve`: hands out the exponents of a range to `work` clients over
+//! HTTP and collects their results. The protocol and the state kept
+//! between runs are described in [`mersenne::coordinator`].
+
+use crate::http::{self, Request};
+use crate::{CANCEL, EXIT_INTERRUPTED, EXIT_USAGE};
+use log::{debug, error, info, warn};
+use mersenne::coordinator::{Coordinator, ResultResponse};
+use mersenne::report::TestReport;
+use serde::Serialize;
+use serde_json::json;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often the accept loop looks for Ctrl-C.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What `serve` was asked to do.
+pub struct ServeOptions<'a> {
+    pub start: u64,
+    pub end: u64,
+    pub listen: &'a str,
+    pub lease: Duration,
+    pub state: &'a Path,
+    pub results: &'a Path,
+}
+
+/// Serves until Ctrl-C and returns the exit status.
+pub fn run(options: &ServeOptions) -> u8 {
+    let coordinator = match Coordinator::open(
+        options.start,
+        options.end,
+        options.lease.as_secs(),
+        options.state,
+        options.results,
+    ) {
+        Ok(coordinator) => coordinator,
+        Err(e) => {
+            error!("cannot use {}: {}", options.state.display(), e);
+            return EXIT_USAGE;
+        }
+    };
+    let listener = match TcpListener::bind(options.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("cannot listen on {}: {}", options.listen, e);
+            return EXIT_USAGE;
+        }
+    };
+    // Accepting without blocking lets Ctrl-C stop the loop.
+    if let Err(e) = listener.set_nonblocking(true) {
+        error!("cannot listen on {}: {}", options.listen, e);
+        return EXIT_USAGE;
+    }
+
+    let status = coordinator.status();
+    info!(
+        "Serving p = {} to p = {} on {} ({} done, {} leased); press Ctrl-C to stop.",
+        options.start, options.end, options.listen, status.completed, status.leased
+    );
+    let coordinator = Arc::new(Mutex::new(coordinator));
+    while !CANCEL.is_cancelled() {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                let coordinator = Arc::clone(&coordinator);
+                std::thread::spawn(move || {
+                    if let Err(e) = serve_connection(&stream, peer, &coordinator) {
+                        debug!("dropping the connection from {}: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(STOP_CHECK_INTERVAL);
+            }
+            Err(e) => warn!("could not accept a connection: {}", e),
+        }
+    }
+    info!("Stopped serving; leases held by workers run on and are picked up by the next start.");
+    EXIT_INTERRUPTED
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+fn serve_connection(
+    stream: &TcpStream,
+    peer: SocketAddr,
+    coordinator: &Mutex<Coordinator>,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(http::TIMEOUT))?;
+    stream.set_write_timeout(Some(http::TIMEOUT))?;
+    let request = match http::read_request(stream) {
+        Ok(request) => request,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            return http::respond(stream, 400, &error_body(e));
+        }
+        Err(e) => return Err(e),
+    };
+    let (status, body) = answer(&request, peer, coordinator);
+    http::respond(stream, status, &body)
+}
+
+/// `{"error":"<message>"}`, the body of every status but 200.
+fn error_body(message: impl std::fmt::Display) -> String {
+    json!({ "error": message.to_string() }).to_string()
+}
+
+/// The JSON body of `value`, with its fields in declaration order.
+fn body<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap()
+}
+
+/// The status and JSON body answering `request`.
+fn answer(request: &Request, peer: SocketAddr, coordinator: &Mutex<Coordinator>) -> (u16, String) {
+    let internal = |e: io::Error| {
+        error!("cannot update the server state: {}", e);
+        (500, error_body(e))
+    };
+    let mut coordinator = coordinator.lock().unwrap();
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/work") => match coordinator.next_work(unix_now()) {
+            Ok(work) => {
+                match work.exponent {
+                    Some(p) => debug!("Leased M({}) to {}.", p, peer),
+                    None if work.finished => debug!("Told {} the range is done.", peer),
+                    None => debug!("Nothing to lease to {} right now.", peer),
+                }
+                (200, body(&work))
+            }
+            Err(e) => internal(e),
+        },
+        ("POST", "/result") => {
+            let report: TestReport = match serde_json::from_slice(&request.body) {
+                Ok(report) => report,
+                Err(e) => return (400, error_body(format!("not a test report: {}", e))),
+            };
+            let was_finished = coordinator.is_finished();
+            let accepted = match coordinator.record(&report) {
+                Ok(accepted) => accepted,
+                Err(e) => return internal(e),
+            };
+            let p = report.exponent;
+            if !accepted {
+                debug!("Did not record M({}) from {}.", p, peer);
+            } else if report.prime {
+                info!("M({}) is a Mersenne prime, reported by {}.", p, peer);
+            } else {
+                info!("Recorded M({}) from {}.", p, peer);
+            }
+            if !was_finished && coordinator.is_finished() {
+                let status = coordinator.status();
+                info!(
+                    "All {} exponents from {} to {} are done.",
+                    status.completed, status.start_exponent, status.end_exponent
+                );
+            }
+            (200, body(&ResultResponse { accepted }))
+        }
+        ("GET", "/status") => (200, body(&coordinator.status())),
+        (_, "/work" | "/result" | "/status") => (
+            405,
+            error_body(format!("{} is not allowed here", request.method)),
+        ),
+        (_, path) => (404, error_body(format!("no such path {}", path))),
+    }
+}
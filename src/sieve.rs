@@ -0,0 +1,164 @@
+//! A segmented sieve of Eratosthenes producing the primes of a range lazily.
+//!
+//! Only one segment of the range is held in memory at a time, so candidate
+//! exponents for even enormous ranges can be streamed into the search.
+
+/// Numbers per segment unless overridden with [`Primes::with_segment_size`].
+pub const DEFAULT_SEGMENT_SIZE: u64 = 1 << 18;
+
+/// Iterator over the primes in `start..=end`, in increasing order.
+#[derive(Debug)]
+pub struct Primes {
+    /// First number of the next segment to sieve, or `None` once done.
+    next: Option<u64>,
+    end: u64,
+    segment_size: u64,
+    /// Primes up to at least `sqrt` of the current segment, pulled on demand
+    /// from a smaller sieve over `2..=isqrt(end)`.
+    base_primes: Vec<u64>,
+    base_source: Option<Box<Primes>>,
+    found: Vec<u64>,
+    found_pos: usize,
+}
+
+/// The primes in `start..=end`.
+pub fn primes(start: u64, end: u64) -> Primes {
+    Primes::with_segment_size(start, end, DEFAULT_SEGMENT_SIZE)
+}
+
+impl Primes {
+    pub fn with_segment_size(start: u64, end: u64, segment_size: u64) -> Primes {
+        let root = end.isqrt();
+        Primes {
+            next: if start <= end {
+                Some(start.max(2))
+            } else {
+                None
+            },
+            end,
+            segment_size: segment_size.max(1),
+            base_primes: Vec::new(),
+            base_source: if root >= 2 {
+                Some(Box::new(Primes::with_segment_size(2, root, segment_size)))
+            } else {
+                None
+            },
+            found: Vec::new(),
+            found_pos: 0,
+        }
+    }
+
+    /// Makes sure every prime up to `limit` is in `base_primes`.
+    fn extend_base_primes(&mut self, limit: u64) {
+        while self.base_primes.last().is_none_or(|&p| p < limit) {
+            match self.base_source.as_mut().and_then(|source| source.next()) {
+                Some(p) => self.base_primes.push(p),
+                None => break,
+            }
+        }
+    }
+
+    /// Sieves the next segment into `found`. Returns `false` when the range
+    /// is exhausted.
+    fn sieve_next_segment(&mut self) -> bool {
+        let lo = match self.next {
+            Some(lo) if lo <= self.end => lo,
+            _ => return false,
+        };
+        let hi = lo.saturating_add(self.segment_size - 1).min(self.end);
+        self.next = hi.checked_add(1);
+
+        self.extend_base_primes(hi.isqrt());
+        let mut composite = vec![false; (hi - lo + 1) as usize];
+        for &q in &self.base_primes {
+            if q * q > hi {
+                break;
+            }
+            let first = match lo.div_ceil(q).checked_mul(q) {
+                Some(first) => first.max(q * q),
+                None => continue,
+            };
+            let mut multiple = first;
+            while multiple <= hi {
+                composite[(multiple - lo) as usize] = true;
+                multiple = match multiple.checked_add(q) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        }
+
+        self.found.clear();
+        self.found_pos = 0;
+        self.found.extend(
+            composite
+                .iter()
+                .enumerate()
+                .filter(|(_, &is_composite)| !is_composite)
+                .map(|(offset, _)| lo + offset as u64),
+        );
+        true
+    }
+}
+
+impl Iterator for Primes {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.found_pos == self.found.len() {
+            if !self.sieve_next_segment() {
+                return None;
+            }
+        }
+        let p = self.found[self.found_pos];
+        self.found_pos += 1;
+        Some(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::is_prime;
+
+    fn expected(start: u64, end: u64) -> Vec<u64> {
+        (start..=end).filter(|&n| is_prime(n)).collect()
+    }
+
+    #[test]
+    fn matches_is_prime_on_sampled_windows() {
+        for (start, end) in [
+            (0, 10_000),
+            (1_000_000_000, 1_000_100_000),
+            (1 << 40, (1 << 40) + 20_000),
+            (999_999_000_000, 999_999_050_000),
+        ] {
+            assert_eq!(
+                primes(start, end).collect::<Vec<_>>(),
+                expected(start, end),
+                "window {}..={}",
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn segment_boundaries_do_not_matter() {
+        let reference: Vec<u64> = primes(90_000, 110_000).collect();
+        for segment_size in [1, 7, 1000, 4096, 1 << 20] {
+            let sieved: Vec<u64> =
+                Primes::with_segment_size(90_000, 110_000, segment_size).collect();
+            assert_eq!(sieved, reference, "segment size {}", segment_size);
+        }
+    }
+
+    #[test]
+    fn handles_tiny_and_empty_ranges() {
+        assert_eq!(primes(0, 1).count(), 0);
+        assert_eq!(primes(2, 2).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(primes(4, 4).count(), 0);
+        assert_eq!(primes(10, 5).count(), 0);
+        assert_eq!(primes(89, 89).collect::<Vec<_>>(), vec![89]);
+    }
+}
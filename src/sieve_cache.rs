@@ -0,0 +1,413 @@
+//! A cache of sieved numbers, kept in a file between runs with
+//! `--sieve-cache`, so that runs over overlapping ranges of exponents only
+//! sieve the parts no run has sieved before.
+//!
+//! The numbers are split into blocks of [`SieveCache::block_size`], block
+//! `i` holding `i·size` up to `(i + 1)·size - 1`, and each block sieved is
+//! kept as a bitset with a bit set for each prime, the lowest number in the
+//! lowest bit of the first byte. A cache keeps at most
+//! [`SieveCache::max_bytes`] of them; past that it drops the lowest blocks
+//! first, since searches move up.
+//!
+//! The file is a 32-byte header followed by the blocks in increasing order,
+//! each its index and then its bitset. Every number is little-endian:
+//!
+//! | bytes  | field                                          |
+//! |--------|------------------------------------------------|
+//! | 0..8   | magic, `MERSSIEV`                              |
+//! | 8..12  | format version, [`FORMAT_VERSION`]             |
+//! | 12..16 | CRC-32 of everything from byte 16 to the end   |
+//! | 16..24 | block size in numbers, a multiple of 8         |
+//! | 24..32 | number of blocks                               |
+//! | 32..   | index and `size / 8` bytes of bitset per block |
+//!
+//! A file written with another block size is used with its own: the size
+//! only decides how the numbers are split up, not which of them are prime.
+
+use crate::checkpoint::crc32;
+use crate::sieve::{Primes, DEFAULT_SEGMENT_SIZE};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 8] = b"MERSSIEV";
+const HEADER_LEN: usize = 32;
+
+/// The version of the format written by [`SieveCache::to_bytes`], the only
+/// one read.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The block size of a new cache: a segment of the sieve.
+pub const DEFAULT_BLOCK_SIZE: u64 = DEFAULT_SEGMENT_SIZE;
+
+/// Why a sieve cache file could not be used.
+#[derive(Debug)]
+pub enum SieveCacheError {
+    Io(io::Error),
+    NotASieveCache,
+    UnsupportedVersion(u32),
+    Truncated,
+    ChecksumMismatch,
+    Invalid(String),
+}
+
+impl fmt::Display for SieveCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SieveCacheError::Io(e) => write!(f, "I/O error: {}", e),
+            SieveCacheError::NotASieveCache => write!(f, "not a sieve cache file"),
+            SieveCacheError::UnsupportedVersion(version) => write!(
+                f,
+                "sieve cache format version {} is not supported (expected {})",
+                version, FORMAT_VERSION
+            ),
+            SieveCacheError::Truncated => write!(f, "file is truncated"),
+            SieveCacheError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            SieveCacheError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SieveCacheError {}
+
+impl From<io::Error> for SieveCacheError {
+    fn from(e: io::Error) -> Self {
+        SieveCacheError::Io(e)
+    }
+}
+
+/// Sieved blocks of numbers, shared by the threads that take primes from
+/// it with [`SieveCache::primes`].
+#[derive(Debug)]
+pub struct SieveCache {
+    block_size: u64,
+    max_bytes: u64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The bitset of each block, by index.
+    blocks: BTreeMap<u64, Vec<u8>>,
+    /// Blocks taken from the cache since it was made or loaded.
+    hits: u64,
+    /// Blocks sieved since it was made or loaded.
+    sieved: u64,
+}
+
+impl SieveCache {
+    /// An empty cache of blocks of `block_size` numbers, rounded up to a
+    /// multiple of 8, that keeps as many as it can.
+    pub fn new(block_size: u64) -> SieveCache {
+        SieveCache {
+            block_size: block_size.max(1).next_multiple_of(8),
+            max_bytes: u64::MAX,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Keeps the blocks, with their indices, to at most `max_bytes`,
+    /// dropping the lowest first.
+    pub fn with_max_bytes(self, max_bytes: u64) -> SieveCache {
+        let cache = SieveCache { max_bytes, ..self };
+        cache.evict(&mut cache.state.lock().unwrap());
+        cache
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// How many blocks it holds.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How many blocks were taken from the cache, and how many sieved and
+    /// added to it, since it was made or loaded.
+    pub fn usage(&self) -> (u64, u64) {
+        let state = self.state.lock().unwrap();
+        (state.hits, state.sieved)
+    }
+
+    /// The bytes of a block in the file and in memory.
+    fn block_bytes(&self) -> u64 {
+        8 + self.block_size / 8
+    }
+
+    fn evict(&self, state: &mut State) {
+        let most = self.max_bytes / self.block_bytes();
+        while state.blocks.len() as u64 > most {
+            state.blocks.pop_first();
+        }
+    }
+
+    /// The primes in `start..=end`, in increasing order, sieving the blocks
+    /// the cache does not have yet.
+    pub fn primes(&self, start: u64, end: u64) -> CachedPrimes<'_> {
+        CachedPrimes {
+            cache: self,
+            next: (start <= end).then_some(start),
+            end,
+            found: Vec::new(),
+            found_pos: 0,
+        }
+    }
+
+    /// The bitset of block `index`, sieved and added if it is not cached.
+    fn block(&self, index: u64) -> Vec<u8> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(bits) = state.blocks.get(&index).cloned() {
+                state.hits += 1;
+                return bits;
+            }
+        }
+        // Sieved without the lock, so other threads carry on meanwhile.
+        let lo = index * self.block_size;
+        let hi = lo.saturating_add(self.block_size - 1);
+        let mut bits = vec![0; (self.block_size / 8) as usize];
+        for p in Primes::with_segment_size(lo, hi, self.block_size) {
+            let offset = p - lo;
+            bits[(offset / 8) as usize] |= 1 << (offset % 8);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.sieved += 1;
+        state.blocks.insert(index, bits.clone());
+        self.evict(&mut state);
+        bits
+    }
+
+    /// The cache file's contents, as described in the module docs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut bytes =
+            Vec::with_capacity(HEADER_LEN + state.blocks.len() * self.block_bytes() as usize);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&self.block_size.to_le_bytes());
+        bytes.extend_from_slice(&(state.blocks.len() as u64).to_le_bytes());
+        for (index, bits) in &state.blocks {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(bits);
+        }
+        let crc = crc32(&bytes[16..]);
+        bytes[12..16].copy_from_slice(&crc.to_le_bytes());
+        bytes
+    }
+
+    /// Parses a cache file written by [`to_bytes`](Self::to_bytes), which
+    /// keeps every block in it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SieveCache, SieveCacheError> {
+        if bytes.len() < 12 || &bytes[..8] != MAGIC {
+            return Err(SieveCacheError::NotASieveCache);
+        }
+        let word = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(SieveCacheError::UnsupportedVersion(version));
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(SieveCacheError::Truncated);
+        }
+        if u32::from_le_bytes(bytes[12..16].try_into().unwrap()) != crc32(&bytes[16..]) {
+            return Err(SieveCacheError::ChecksumMismatch);
+        }
+        let block_size = word(16);
+        if block_size == 0 || block_size % 8 != 0 {
+            return Err(SieveCacheError::Invalid(format!(
+                "block size {} is not a positive multiple of 8",
+                block_size
+            )));
+        }
+        let cache = SieveCache::new(block_size);
+        let count = word(24);
+        let body = (bytes.len() - HEADER_LEN) as u64;
+        if count.checked_mul(cache.block_bytes()) != Some(body) {
+            return Err(SieveCacheError::Truncated);
+        }
+        let mut blocks = BTreeMap::new();
+        for block in bytes[HEADER_LEN..].chunks(cache.block_bytes() as usize) {
+            let index = u64::from_le_bytes(block[..8].try_into().unwrap());
+            if index > u64::MAX / block_size || blocks.insert(index, block[8..].to_vec()).is_some()
+            {
+                return Err(SieveCacheError::Invalid(format!(
+                    "block {} is repeated or out of range",
+                    index
+                )));
+            }
+        }
+        cache.state.lock().unwrap().blocks = blocks;
+        Ok(cache)
+    }
+
+    /// Reads the cache in `path`.
+    pub fn load(path: &Path) -> Result<SieveCache, SieveCacheError> {
+        SieveCache::from_bytes(&fs::read(path)?)
+    }
+
+    /// Writes the cache to `path`, atomically, as checkpoints are.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(&self.to_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    }
+}
+
+/// Iterator over the primes of a range from a [`SieveCache`], in increasing
+/// order, one block at a time.
+#[derive(Debug)]
+pub struct CachedPrimes<'a> {
+    cache: &'a SieveCache,
+    /// First number not yet looked at, or `None` once done.
+    next: Option<u64>,
+    end: u64,
+    found: Vec<u64>,
+    found_pos: usize,
+}
+
+impl Iterator for CachedPrimes<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.found_pos == self.found.len() {
+            let lo = self.next.filter(|&lo| lo <= self.end)?;
+            let size = self.cache.block_size;
+            let index = lo / size;
+            let first = index * size;
+            let hi = first.saturating_add(size - 1).min(self.end);
+            let bits = self.cache.block(index);
+            self.found.clear();
+            self.found_pos = 0;
+            self.found.extend(
+                (lo..=hi)
+                    .filter(|&n| bits[((n - first) / 8) as usize] & (1 << ((n - first) % 8)) != 0),
+            );
+            self.next = hi.checked_add(1);
+        }
+        let p = self.found[self.found_pos];
+        self.found_pos += 1;
+        Some(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sieve;
+
+    /// Random windows of up to `width` numbers below `limit`, from
+    /// splitmix64 seeded with `seed`.
+    fn windows(seed: u64, limit: u64, width: u64) -> Vec<(u64, u64)> {
+        let mut state = seed;
+        let mut next = move || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        (0..40)
+            .map(|_| {
+                let start = next() % limit;
+                (start, start + next() % width)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cached_primes_match_freshly_sieved_ones() {
+        let cache = SieveCache::new(4096);
+        for round in 0..2 {
+            for (start, end) in windows(7, 200_000, 30_000) {
+                assert_eq!(
+                    cache.primes(start, end).collect::<Vec<_>>(),
+                    sieve::primes(start, end).collect::<Vec<_>>(),
+                    "window {}..={} in round {}",
+                    start,
+                    end,
+                    round
+                );
+            }
+        }
+        let (hits, sieved) = cache.usage();
+        assert!(hits > sieved, "{} hits, {} sieved", hits, sieved);
+        assert_eq!(sieved, cache.len() as u64);
+        assert_eq!(cache.primes(10, 5).count(), 0);
+        assert_eq!(cache.primes(0, 2).collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn files_of_other_block_sizes_give_the_same_primes() {
+        for block_size in [8, 1000, 1 << 16] {
+            let written = SieveCache::new(block_size);
+            written.primes(50_000, 150_000).for_each(drop);
+            let cache = SieveCache::from_bytes(&written.to_bytes()).unwrap();
+            assert_eq!(cache.block_size(), block_size);
+            assert_eq!(cache.len(), written.len());
+            for (start, end) in windows(block_size, 200_000, 20_000) {
+                assert_eq!(
+                    cache.primes(start, end).collect::<Vec<_>>(),
+                    sieve::primes(start, end).collect::<Vec<_>>(),
+                    "window {}..={} with blocks of {}",
+                    start,
+                    end,
+                    block_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_lowest_blocks_are_dropped_past_the_limit() {
+        let cache = SieveCache::new(1024).with_max_bytes(3 * (8 + 128));
+        cache.primes(0, 6 * 1024 - 1).for_each(drop);
+        assert_eq!(cache.len(), 3);
+        let kept = SieveCache::from_bytes(&cache.to_bytes()).unwrap();
+        let blocks: Vec<u64> = kept.state.lock().unwrap().blocks.keys().copied().collect();
+        assert_eq!(blocks, [3, 4, 5]);
+        assert_eq!(kept.with_max_bytes(8 + 128).len(), 1);
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let cache = SieveCache::new(64);
+        cache.primes(0, 1000).for_each(drop);
+        let bytes = cache.to_bytes();
+        let mut flipped = bytes.clone();
+        flipped[HEADER_LEN + 9] ^= 1;
+        assert!(matches!(
+            SieveCache::from_bytes(&flipped),
+            Err(SieveCacheError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            SieveCache::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SieveCacheError::ChecksumMismatch | SieveCacheError::Truncated)
+        ));
+        assert!(matches!(
+            SieveCache::from_bytes(b"MERSCKPT\x03\0\0\0"),
+            Err(SieveCacheError::NotASieveCache)
+        ));
+        let mut newer = bytes;
+        newer[8] = 2;
+        assert!(matches!(
+            SieveCache::from_bytes(&newer),
+            Err(SieveCacheError::UnsupportedVersion(2))
+        ));
+    }
+}
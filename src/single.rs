@@ -0,0 +1,254 @@
+//! `test` of a single exponent, the front door for testing one number. It
+//! is the run any other selection gets, but a Lucas-Lehmer test
+//! checkpoints to the current directory unless `--checkpoint-dir` says
+//! otherwise, picks up a checkpoint left there only with `--resume`, and
+//! the run ends with a report of the one test instead of the summary.
+
+use crate::progress::{format_duration, format_work, group};
+use crate::{run_tests, Options, Selection, EXIT_USAGE};
+use log::{error, info};
+use mersenne::checkpoint::CheckpointStore;
+use mersenne::default_jacobi_interval;
+use mersenne::known::is_known_mersenne_exponent;
+use mersenne::report::{FactoringStage, Form, TestKind, TestReport};
+use mersenne::run::RunContext;
+use std::path::{Path, PathBuf};
+
+/// Where a single test checkpoints without a `--checkpoint-dir`.
+const DEFAULT_DIR: &str = ".";
+
+/// Tests `p`, an exponent `test` has checked, as `run`, and returns the
+/// exit status.
+pub fn run(p: u64, mut options: Options, resume: bool, run: &RunContext) -> u8 {
+    let checkpointed = options.form == Form::Mersenne && !options.prp;
+    if resume && !checkpointed {
+        error!("--resume needs a Lucas-Lehmer test of a Mersenne number; only those checkpoint.");
+        return EXIT_USAGE;
+    }
+    let here = checkpointed && options.checkpoint_dir.is_none();
+    if here {
+        options.checkpoint_dir = Some(PathBuf::from(DEFAULT_DIR));
+    }
+    if let Some(dir) = options.checkpoint_dir.clone() {
+        if let Err(status) = prepare(p, &dir, here, resume, options.force_restart) {
+            return status;
+        }
+    }
+    options.single = true;
+    let status = run_tests(&options, Selection::List(vec![p]), None, run);
+
+    // The test removes its checkpoint when it gets to the end.
+    let left = options
+        .checkpoint_dir
+        .filter(|_| here)
+        .and_then(|dir| CheckpointStore::new(dir, 1).ok()?.load(p).ok()?);
+    if let Some(checkpoint) = left {
+        info!(
+            "The test of M({}) stopped at iteration {}; run `test {} --resume` to carry on from \
+             its checkpoint.",
+            p, checkpoint.iteration, p
+        );
+    }
+    status
+}
+
+/// Deals with an earlier checkpoint of `M(p)` in `dir` before the test:
+/// one in the current directory, where `here` says it is, is resumed only
+/// with `resume`, and deleted with `force_restart`.
+fn prepare(p: u64, dir: &Path, here: bool, resume: bool, force_restart: bool) -> Result<(), u8> {
+    let store = CheckpointStore::new(dir, 1).map_err(|e| {
+        error!("cannot use checkpoint directory {}: {}", dir.display(), e);
+        EXIT_USAGE
+    })?;
+    let place = match here {
+        true => "the current directory".to_string(),
+        false => dir.display().to_string(),
+    };
+    match store.load(p) {
+        Ok(Some(checkpoint)) if here && !resume && force_restart => {
+            if let Err(e) = store.remove(p) {
+                error!("cannot delete {}: {}", store.path(p).display(), e);
+                return Err(EXIT_USAGE);
+            }
+            info!(
+                "Deleted the checkpoint of M({}) at iteration {}; starting over.",
+                p, checkpoint.iteration
+            );
+        }
+        Ok(Some(checkpoint)) if here && !resume => {
+            error!(
+                "{} has a checkpoint of M({}) at iteration {}; run with --resume to carry on from \
+                 it, or with --force-restart to start over.",
+                store.path(p).display(),
+                p,
+                checkpoint.iteration
+            );
+            return Err(EXIT_USAGE);
+        }
+        Ok(None) if resume => info!(
+            "There is no checkpoint of M({}) in {} to resume; starting from the beginning.",
+            p, place
+        ),
+        // One that cannot be read is reported and set aside by the test.
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Prints the report of the test of a single exponent, in place of the
+/// summary of a run.
+pub fn print_report(report: &TestReport, options: &Options) {
+    let form = report.form;
+    let p = report.exponent;
+    let name = form.number(p);
+    let digits = group(form.digit_count(p));
+    outln!();
+    if let Some(factor) = &report.factor {
+        let stage = match report.factor_stage {
+            Some(FactoringStage::PMinus1) => "P-1 factoring found",
+            Some(FactoringStage::External) => "the --known-factors file gives",
+            _ => "trial factoring found",
+        };
+        outln!(
+            "{} is composite ({} digits): {} the factor {}.",
+            name,
+            digits,
+            stage,
+            factor
+        );
+    } else if let Some(iteration) = report.timed_out_at {
+        outln!(
+            "The test of {} ran out of time at iteration {} ({:.1}%).",
+            name,
+            group(iteration),
+            report.percent_complete().unwrap_or(0.0)
+        );
+    } else if let Some(message) = &report.panic {
+        outln!("The test of {} panicked: {}", name, message);
+    } else if let Some(iteration) = report.failed_at {
+        outln!(
+            "The test of {} failed after arithmetic errors; the last good iteration was {}.",
+            name,
+            group(iteration)
+        );
+    } else if report.prime {
+        let probable = match report.test {
+            Some(TestKind::Prp) => " probable",
+            _ => "",
+        };
+        let novelty = match form {
+            Form::Mersenne if is_known_mersenne_exponent(p) => ", already known",
+            Form::Mersenne => ", new",
+            _ => "",
+        };
+        outln!(
+            "{} is a {}{} prime ({} digits{}).",
+            name,
+            form.title(),
+            probable,
+            digits,
+            novelty
+        );
+    } else {
+        outln!("{} is composite ({} digits).", name, digits);
+    }
+
+    let row = |label: &str, value: String| outln!("  {:<14}{}", format!("{}:", label), value);
+    if let Some(test) = report.test {
+        row("Test", test_name(test).to_string());
+    }
+    if let Some(backend) = report.backend {
+        let check = report
+            .check_backend
+            .filter(|&check| check != backend)
+            .map_or_else(String::new, |check| {
+                format!(", {} for the double-check", check)
+            });
+        row("Backend", format!("{}{}", backend, check));
+    }
+    if let Some(res64) = &report.res64 {
+        row("Res64", res64.clone());
+    }
+    let compute = report.compute_seconds.map_or_else(String::new, |seconds| {
+        format!(" ({} computing)", format_duration(seconds))
+    });
+    row(
+        "Elapsed",
+        format!("{}{}", format_duration(report.seconds), compute),
+    );
+    // Tests without progress reports have only their average speed.
+    match (&report.throughput, report.iterations_per_second()) {
+        (Some(throughput), _) => row(
+            "Throughput",
+            format!(
+                "{} it/s average, {} it/s at the slowest",
+                group(throughput.average.round() as u64),
+                group(throughput.slowest.round() as u64)
+            ),
+        ),
+        (None, Some(rate)) => row(
+            "Throughput",
+            format!("{} it/s average", group(rate.round() as u64)),
+        ),
+        (None, None) => {}
+    }
+    if report.test.is_some() {
+        row(
+            "Work",
+            format!("{} units", format_work(report.work_units())),
+        );
+    }
+    if let (Some(_), Some(dir)) = (report.test, &options.checkpoint_dir) {
+        row(
+            "Checkpoints",
+            format!("{} written to {}", report.checkpoints, dir.display()),
+        );
+    }
+    if let Some(test) = report.test {
+        let caught = match report.errors {
+            0 => "no errors caught".to_string(),
+            1 => "1 error caught".to_string(),
+            n => format!("{} errors caught", n),
+        };
+        let checks = match test {
+            TestKind::LucasLehmer => {
+                match options
+                    .jacobi_interval
+                    .unwrap_or_else(|| default_jacobi_interval(p))
+                {
+                    0 => "Jacobi checks off".to_string(),
+                    // The test runs p - 2 iterations, so a longer interval
+                    // leaves it without a single check.
+                    every if every > p.saturating_sub(2) => "no Jacobi check ran".to_string(),
+                    every => format!("Jacobi every {} iterations", group(every)),
+                }
+            }
+            TestKind::Prp => "Gerbicz".to_string(),
+            _ => "sanity checks".to_string(),
+        };
+        row("Error checks", format!("{}; {}", checks, caught));
+    }
+    if let (Some(double_check), Some(shift)) = (report.double_check, report.shift) {
+        row(
+            "Double-check",
+            format!("{} (second run shifted by {} bits)", double_check, shift),
+        );
+    }
+    if let Some(confirmation) = report.confirmation {
+        let res64 = report
+            .confirm_res64
+            .as_ref()
+            .map_or_else(String::new, |res64| format!(", Res64 {}", res64));
+        row("Confirmation", format!("{}{}", confirmation, res64));
+    }
+}
+
+/// The full name of `test`, for the report.
+fn test_name(test: TestKind) -> &'static str {
+    match test {
+        TestKind::LucasLehmer => "Lucas-Lehmer",
+        TestKind::Prp => "base-3 Fermat probable-prime (PRP)",
+        TestKind::Pepin => "Pépin",
+        TestKind::Llr => "Lucas-Lehmer-Riesel",
+    }
+}
@@ -0,0 +1,97 @@
+//! Lucas–Lehmer tests of small Mersenne numbers in native integers.
+//!
+//! For `p <= 63` the residue fits in a `u64` and its square in a `u128`, so
+//! the whole test runs without allocating. Sweeps over small ranges are
+//! dominated by these exponents.
+
+/// The largest exponent [`is_mersenne_prime_small`] accepts.
+pub const MAX_SMALL_EXPONENT: u64 = 63;
+
+/// Runs the Lucas–Lehmer test on `2^p - 1` for `3 <= p <= 63`.
+///
+/// # Panics
+///
+/// If `p` is outside that range.
+pub fn is_mersenne_prime_small(p: u64) -> bool {
+    small_residue(p) == 0
+}
+
+/// The final Lucas–Lehmer residue of `M(p)`, for `3 <= p <= 63`.
+pub(crate) fn small_residue(p: u64) -> u64 {
+    assert!(
+        (3..=MAX_SMALL_EXPONENT).contains(&p),
+        "exponent {} is out of range for the small test",
+        p
+    );
+    if p <= 31 {
+        residue_u64(p)
+    } else {
+        residue_u128(p)
+    }
+}
+
+/// Squarings in `u64`: the residue is below `2^31`, so its square is below
+/// `2^62`.
+fn residue_u64(p: u64) -> u64 {
+    let modulus = (1u64 << p) - 1;
+    let mut s = 4u64;
+    for _ in 0..p - 2 {
+        let square = s * s;
+        let mut r = (square & modulus) + (square >> p);
+        if r >= modulus {
+            r -= modulus;
+        }
+        s = if r >= 2 { r - 2 } else { r + modulus - 2 };
+    }
+    s
+}
+
+/// Squarings in `u128`: the residue is below `2^63`, so its square is below
+/// `2^126`.
+fn residue_u128(p: u64) -> u64 {
+    let modulus = (1u128 << p) - 1;
+    let mut s = 4u128;
+    for _ in 0..p - 2 {
+        let square = s * s;
+        let mut r = (square & modulus) + (square >> p);
+        if r >= modulus {
+            r -= modulus;
+        }
+        s = if r >= 2 { r - 2 } else { r + modulus - 2 };
+    }
+    s as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arith::MersenneModulus;
+    use crate::res64;
+    use num_bigint::BigUint;
+
+    #[test]
+    fn agrees_with_biguint_for_every_small_exponent() {
+        for p in 3..=MAX_SMALL_EXPONENT {
+            let modulus = MersenneModulus::new(p);
+            let mut s = BigUint::from(4u32);
+            for _ in 0..p - 2 {
+                s = modulus.square_sub2(&s);
+            }
+            assert_eq!(small_residue(p), res64(&s), "M({})", p);
+        }
+    }
+
+    #[test]
+    fn finds_the_small_mersenne_primes() {
+        let primes: Vec<u64> = (3..=MAX_SMALL_EXPONENT)
+            .filter(|&p| is_mersenne_prime_small(p))
+            .collect();
+        assert_eq!(primes, [3, 5, 7, 13, 17, 19, 31, 61]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_large_exponents() {
+        is_mersenne_prime_small(64);
+    }
+}
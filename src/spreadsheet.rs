@@ -0,0 +1,130 @@
+//! The CSV file written with `--csv` or `--csv-append`: one row per
+//! exponent, written and flushed as each finishes, for spreadsheets.
+//!
+//! The columns, which stay as they are so scripts can rely on them, are:
+//!
+//! - `exponent`;
+//! - `candidate_source`: how the run chose it, `range` for a range of
+//!   exponents, `list` for exponents named on the command line, `stdin`
+//!   for those of `test --stdin`, `worktodo` for an assignment and
+//!   `server` for one handed out by a `serve` server;
+//! - `stage_eliminated`: what showed it is not prime, `TF`, `P-1`,
+//!   `external` for a factor of `--known-factors` or the test, such as
+//!   `LL` or `PRP`; empty if it is prime or was not decided;
+//! - `is_prime`: `true` or `false`;
+//! - `res64`: the final Res64 of its test, if it has one;
+//! - `iterations`: the iterations of its test, or as far as the test got
+//!   if it timed out or failed; empty if it was not tested;
+//! - `elapsed_seconds`: the time spent on it, factoring included;
+//! - `iters_per_second`: `iterations` over `elapsed_seconds`;
+//! - `started_at` and `finished_at`: in UTC, as in
+//!   `2024-05-01T12:00:00Z`; `started_at` is `finished_at` less
+//!   `elapsed_seconds`;
+//! - `range`: with `--ranges`, the range it came from, as in `20000-25000`;
+//! - `work_units`: the work of its test, by
+//!   [`work_units`](mersenne::report::work_units);
+//! - `run_id`: the [ID of the run](mersenne::run) that tested it;
+//! - `res2048`: with `--res2048`, the low 2048 bits of the residue `res64`
+//!   is the low 64 of, as 512 lowercase hex digits; empty otherwise.
+//!
+//! Columns are only ever added at the end.
+//!
+//! A number of another form than Mersenne has its exponent in `exponent`;
+//! `--results` and `--json` say which form it is.
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use mersenne::report::TestReport;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// The header row, in the order of [`row`].
+const HEADER: [&str; 14] = [
+    "exponent",
+    "candidate_source",
+    "stage_eliminated",
+    "is_prime",
+    "res64",
+    "iterations",
+    "elapsed_seconds",
+    "iters_per_second",
+    "started_at",
+    "finished_at",
+    "range",
+    "work_units",
+    "run_id",
+    "res2048",
+];
+
+/// A CSV file being written.
+pub struct Spreadsheet {
+    writer: csv::Writer<File>,
+    source: &'static str,
+}
+
+impl Spreadsheet {
+    /// Creates the file at `path`, or with `append` adds to it, writing the
+    /// header unless it is added to an existing file that has one. Every
+    /// row gets `source` as its `candidate_source`.
+    pub fn open(path: &Path, append: bool, source: &'static str) -> io::Result<Spreadsheet> {
+        let file = if append {
+            OpenOptions::new().create(true).append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        let empty = file.metadata()?.len() == 0;
+        let mut writer = csv::Writer::from_writer(file);
+        if empty {
+            writer.write_record(HEADER)?;
+            writer.flush()?;
+        }
+        Ok(Spreadsheet { writer, source })
+    }
+
+    /// Writes the row of `report` and flushes it.
+    pub fn record(&mut self, report: &TestReport) -> io::Result<()> {
+        self.writer
+            .write_record(row(report, self.source, Utc::now()))?;
+        self.writer.flush()
+    }
+}
+
+/// The row of `report`, finished at `finished`.
+fn row(report: &TestReport, source: &str, finished: DateTime<Utc>) -> [String; 14] {
+    let stage = match (report.factor_stage, report.test) {
+        (Some(stage), _) => stage.as_str(),
+        (None, Some(test)) if !report.prime && report.has_result() => test.as_str(),
+        _ => "",
+    };
+    let iterations = match report.test {
+        Some(test) if report.has_result() => Some(test.iterations(report.exponent)),
+        Some(_) => report.timed_out_at.or(report.failed_at),
+        None => None,
+    };
+    let rate = iterations
+        .filter(|_| report.seconds > 0.0)
+        .map(|iterations| iterations as f64 / report.seconds);
+    let started = finished - Duration::milliseconds((report.seconds * 1000.0) as i64);
+    let time = |at: DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Secs, true);
+    [
+        report.exponent.to_string(),
+        source.to_string(),
+        stage.to_string(),
+        report.prime.to_string(),
+        report.res64.clone().unwrap_or_default(),
+        iterations.map_or_else(String::new, |iterations| iterations.to_string()),
+        format!("{:.3}", report.seconds),
+        rate.map_or_else(String::new, |rate| format!("{:.1}", rate)),
+        time(started),
+        time(finished),
+        report
+            .range
+            .map_or_else(String::new, |range| range.to_string()),
+        report.work_units().to_string(),
+        report
+            .run
+            .as_ref()
+            .map_or_else(String::new, |run| run.to_string()),
+        report.res2048.clone().unwrap_or_default(),
+    ]
+}
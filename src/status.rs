@@ -0,0 +1,496 @@
+//! Status reports for long runs: heartbeat lines on stderr, so a run
+//! without `-vv` progress still shows signs of life, and the JSON status file
+//! written with `--status-file`.
+//!
+//! Each running test publishes its iteration counter into an [`Activity`]
+//! slot; reporter threads read the slots on their own schedule, so the
+//! workers never wait on them.
+//!
+//! The status file is replaced as a whole on every update, so it can be
+//! polled at any time, for example with
+//!
+//! ```text
+//! $ jq -r '"\(.completed) done, \(.remaining) to go, ETA \(.estimated_completion)"' status.json
+//! 12 done, 829 to go, ETA 2025-07-03T14:20:00+02:00
+//! $ jq -r '.running[] | "M(\(.exponent)) \(.percent)%"' status.json
+//! M(1000003) 12.3
+//! ```
+//!
+//! Until a run has discovered all of its exponents, which for a huge range
+//! goes on in the background while the first tests run, both count only
+//! those found so far.
+//!
+//! Both also say how far through the work of the whole run it is, as a
+//! [`RunProgress`] reckons it: not the share of exponents done, which
+//! flatters a range whose largest exponents are still to come, but the
+//! share of their cost by the `p² · log p` model of the [ETA](crate::eta),
+//! in-flight tests counting for the part their counters have reached.
+//!
+//! Both say when the tests are paused, or stopping, and with `--control`
+//! a `status` command updates both at once.
+//!
+//! With `--max-mem`, both also show the memory the running tests are
+//! estimated to need and how many tests are waiting for room. Heartbeat
+//! lines end with what each stage of the run has done.
+
+use crate::admission::{self, MemoryBudget};
+use crate::eta::{self, Eta};
+use crate::pipeline::Stage;
+use chrono::{Duration, Local, SecondsFormat};
+use log::info;
+use mersenne::report::{Form, TestReport};
+use mersenne::run::RunContext;
+use mersenne::search::CancellationToken;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// The tests currently running, and how far they have got.
+pub struct Activity {
+    /// The form of the numbers being tested.
+    form: Form,
+    running: Mutex<Vec<Arc<Running>>>,
+    /// What pauses and stops the tests, for the state of the run.
+    token: Option<&'static CancellationToken>,
+    /// Iterations done by tests that have since finished.
+    finished_iterations: AtomicU64,
+    primes: Mutex<Vec<u64>>,
+}
+
+/// One running test.
+pub struct Running {
+    p: u64,
+    total: u64,
+    /// The last completed iteration, written by the test itself.
+    pub iteration: AtomicU64,
+    /// The iteration the test resumed from, so the work done before it is
+    /// not counted towards the rate.
+    resumed_from: AtomicU64,
+}
+
+impl Running {
+    pub fn resumed(&self, iteration: u64) {
+        self.resumed_from.store(iteration, Ordering::Relaxed);
+    }
+
+    fn done_here(&self) -> u64 {
+        let iteration = self.iteration.load(Ordering::Relaxed);
+        iteration.saturating_sub(self.resumed_from.load(Ordering::Relaxed))
+    }
+}
+
+/// Keeps a test listed in its [`Activity`] until dropped.
+pub struct Registration<'a> {
+    activity: &'a Activity,
+    running: Arc<Running>,
+}
+
+impl std::ops::Deref for Registration<'_> {
+    type Target = Running;
+
+    fn deref(&self) -> &Running {
+        &self.running
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        let mut running = self.activity.running.lock().unwrap();
+        running.retain(|other| !Arc::ptr_eq(other, &self.running));
+        self.activity
+            .finished_iterations
+            .fetch_add(self.running.done_here(), Ordering::Relaxed);
+    }
+}
+
+impl Activity {
+    pub fn new(form: Form) -> Activity {
+        Activity {
+            form,
+            running: Mutex::new(Vec::new()),
+            token: None,
+            finished_iterations: AtomicU64::new(0),
+            primes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Has the state of the run reported from `token`.
+    pub fn under(self, token: &'static CancellationToken) -> Activity {
+        Activity {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    /// `running`, `paused` or, once the tests have been told to stop,
+    /// `stopping`.
+    fn state(&self) -> &'static str {
+        match self.token {
+            Some(token) if token.is_cancelled() => "stopping",
+            Some(token) if token.is_paused() => "paused",
+            _ => "running",
+        }
+    }
+
+    /// Notes the outcome of a finished test.
+    pub fn record(&self, report: &TestReport) {
+        if report.prime {
+            self.primes.lock().unwrap().push(report.exponent);
+        }
+    }
+
+    /// Lists a test of exponent `p` that takes `total` iterations.
+    pub fn start(&self, p: u64, total: u64) -> Registration<'_> {
+        let running = Arc::new(Running {
+            p,
+            total,
+            iteration: AtomicU64::new(0),
+            resumed_from: AtomicU64::new(0),
+        });
+        self.running.lock().unwrap().push(Arc::clone(&running));
+        Registration {
+            activity: self,
+            running,
+        }
+    }
+
+    /// Every iteration done so far, by finished and running tests alike.
+    fn iterations(&self) -> u64 {
+        let running = self.running.lock().unwrap();
+        self.finished_iterations.load(Ordering::Relaxed)
+            + running.iter().map(|test| test.done_here()).sum::<u64>()
+    }
+
+    /// For example `M(1000003) 12.3%, M(1000033) 4.0%`, smallest first.
+    fn running_summary(&self) -> String {
+        let mut running: Vec<(u64, f64)> = self
+            .running
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|test| {
+                let iteration = test.iteration.load(Ordering::Relaxed);
+                (test.p, 100.0 * iteration as f64 / test.total.max(1) as f64)
+            })
+            .collect();
+        running.sort_by_key(|&(p, _)| p);
+        running
+            .iter()
+            .map(|&(p, percent)| format!("{} {:.1}%", self.form.number(p), percent))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// How far through the work of a run it is, by the cost of its tests.
+///
+/// The work of a run is the cost of every exponent the [`Eta`] has
+/// discovered. A test that ends adds its cost to the work done, or the
+/// part of it done before it timed out or failed; one still running adds
+/// the part its iteration counter has reached. A failed test that is
+/// retried is re-queued: the work it had done is done again, so the run's
+/// work grows by that much. A timed-out test leaves the rest of its work
+/// to the next run, and the run ends short of 100%.
+///
+/// The percentage never goes back: when re-queued work grows the total, or
+/// a test falls back to an earlier iteration, it holds until the work
+/// catches up.
+pub struct RunProgress<'a> {
+    eta: &'a Eta,
+    activity: &'a Activity,
+    state: Mutex<Work>,
+}
+
+struct Work {
+    /// The cost of the tests that have ended, for the part they did.
+    done: f64,
+    /// The cost of work done by failed tests that is to be done again.
+    requeued: f64,
+    /// The highest percentage reported so far.
+    reached: f64,
+}
+
+impl<'a> RunProgress<'a> {
+    pub fn new(eta: &'a Eta, activity: &'a Activity) -> RunProgress<'a> {
+        RunProgress {
+            eta,
+            activity,
+            state: Mutex::new(Work {
+                done: 0.0,
+                requeued: 0.0,
+                reached: 0.0,
+            }),
+        }
+    }
+
+    /// The cost of the work `report` did.
+    fn work_of(&self, report: &TestReport) -> f64 {
+        let cost = eta::cost(report.form.bits(report.exponent));
+        let stopped = report.timed_out_at.or(report.failed_at);
+        match (stopped, report.test) {
+            (Some(iteration), Some(test)) => {
+                let fraction = iteration as f64 / test.iterations(report.exponent).max(1) as f64;
+                cost * fraction.min(1.0)
+            }
+            // A panic leaves no record of how far the test got.
+            _ if report.panic.is_some() => 0.0,
+            _ => cost,
+        }
+    }
+
+    /// Accounts for a test that has ended. An exponent eliminated by a
+    /// factor leaves the total through [`Eta::record`] instead.
+    pub fn record(&self, report: &TestReport) {
+        if report.factor.is_some() {
+            return;
+        }
+        let work = self.work_of(report);
+        self.state.lock().unwrap().done += work;
+    }
+
+    /// Re-queues the failed test of `report` for a retry, with a note of
+    /// how much that adds to the work of the run.
+    pub fn requeue(&self, report: &TestReport) {
+        let work = self.work_of(report);
+        let (reached, total) = {
+            let mut state = self.state.lock().unwrap();
+            state.requeued += work;
+            (state.reached, self.eta.total_cost() + state.requeued)
+        };
+        if work > 0.0 && total > 0.0 {
+            info!(
+                "Retrying {} adds {:.1}% to the work of the run; the {:.1}% done so far holds \
+                 until the retry catches up with it.",
+                report.form.number(report.exponent),
+                100.0 * work / total,
+                reached
+            );
+        }
+    }
+
+    /// The percentage of the run's work done, or `None` while its
+    /// exponents are still being discovered and the total is not known.
+    pub fn percent(&self) -> Option<f64> {
+        if self.eta.is_discovering() {
+            return None;
+        }
+        let form = self.activity.form;
+        let running: f64 = self
+            .activity
+            .running
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|test| {
+                let iteration = test.iteration.load(Ordering::Relaxed);
+                let fraction = iteration as f64 / test.total.max(1) as f64;
+                eta::cost(form.bits(test.p)) * fraction.min(1.0)
+            })
+            .sum();
+        let mut state = self.state.lock().unwrap();
+        let total = self.eta.total_cost() + state.requeued;
+        let percent = match total > 0.0 {
+            true => (100.0 * (state.done + running) / total).min(100.0),
+            false => 100.0,
+        };
+        state.reached = state.reached.max(percent);
+        Some(state.reached)
+    }
+
+    /// For example `37.5% of the work done`.
+    pub fn summary(&self) -> Option<String> {
+        self.percent()
+            .map(|percent| format!("{:.1}% of the work done", percent))
+    }
+}
+
+/// Builds successive status lines, measuring the iteration rate between
+/// them.
+pub struct Heartbeat {
+    last: Instant,
+    last_iterations: u64,
+}
+
+impl Heartbeat {
+    pub fn new() -> Heartbeat {
+        Heartbeat {
+            last: Instant::now(),
+            last_iterations: 0,
+        }
+    }
+
+    /// For example `[2025-07-01 09:00] 12 exponents done, 829 to go, ETA
+    /// 2025-07-03 14:20 (2d 05h remaining); 0.4% of the work done; running
+    /// M(1000003) 12.3%; 1520 iter/s`, with `paused; ` or `stopping; `
+    /// after the time when the tests are, followed by `; memory 7.6 MB of
+    /// 1024.0 MB, 0 waiting` with a budget, and then by what each stage has
+    /// done, such as `; trial factoring 120 done, 80 eliminated, 0 queued`.
+    pub fn line(
+        &mut self,
+        eta: &Eta,
+        activity: &Activity,
+        progress: &RunProgress,
+        budget: Option<&MemoryBudget>,
+        stages: &[&Stage],
+    ) -> String {
+        let iterations = activity.iterations();
+        let seconds = self.last.elapsed().as_secs_f64();
+        let rate = iterations.saturating_sub(self.last_iterations) as f64 / seconds.max(1e-9);
+        self.last = Instant::now();
+        self.last_iterations = iterations;
+
+        let running = activity.running_summary();
+        let work = progress
+            .summary()
+            .map_or_else(String::new, |work| format!("; {}", work));
+        let state = match activity.state() {
+            "running" => String::new(),
+            state => format!("{}; ", state),
+        };
+        let mut line = format!(
+            "[{}] {}{}{}; running {}; {:.0} iter/s",
+            Local::now().format("%Y-%m-%d %H:%M"),
+            state,
+            eta.status_line(),
+            work,
+            if running.is_empty() {
+                "nothing"
+            } else {
+                &running
+            },
+            rate
+        );
+        if let Some(budget) = budget {
+            line += "; ";
+            line += &budget.status().summary();
+        }
+        for stage in stages {
+            line += "; ";
+            line += &stage.status();
+        }
+        line
+    }
+}
+
+/// The document written to the status file.
+#[derive(Serialize)]
+struct StatusDocument {
+    run: String,
+    started: String,
+    updated: String,
+    /// `running`, `paused` or `stopping`.
+    state: &'static str,
+    start_exponent: u64,
+    end_exponent: u64,
+    completed: usize,
+    /// Of the exponents discovered so far.
+    remaining: usize,
+    /// Whether the run is still discovering its exponents, so that
+    /// `remaining` will grow.
+    discovering: bool,
+    /// The percentage of the run's work done, weighted by the cost of each
+    /// test; `null` while `discovering`.
+    percent_of_work: Option<f64>,
+    running: Vec<RunningStatus>,
+    /// `null` until there is a basis for an estimate.
+    estimated_completion: Option<String>,
+    primes: Vec<u64>,
+    /// `null` without `--max-mem`.
+    memory: Option<MemoryStatus>,
+}
+
+#[derive(Serialize)]
+struct RunningStatus {
+    exponent: u64,
+    iteration: u64,
+    iterations: u64,
+    percent: f64,
+    estimated_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct MemoryStatus {
+    limit_bytes: u64,
+    /// The estimates of the tests running now, added up.
+    estimated_bytes: u64,
+    running: usize,
+    waiting: u64,
+}
+
+/// Writes the status file for `run`, over `bounds`, with `budget` if
+/// there is one. The document goes to a temporary file first and is renamed
+/// over `path`, so readers never see it half-written.
+pub fn write_status_file(
+    path: &Path,
+    run: &RunContext,
+    bounds: (u64, u64),
+    eta: &Eta,
+    activity: &Activity,
+    progress: &RunProgress,
+    budget: Option<&MemoryBudget>,
+) -> io::Result<()> {
+    let now = Local::now();
+    let (completed, total) = eta.progress();
+    let mut running: Vec<RunningStatus> = activity
+        .running
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|test| {
+            let iteration = test.iteration.load(Ordering::Relaxed);
+            RunningStatus {
+                exponent: test.p,
+                iteration,
+                iterations: test.total,
+                percent: (1000.0 * iteration as f64 / test.total.max(1) as f64).round() / 10.0,
+                estimated_bytes: admission::estimated_bytes(activity.form.bits(test.p)),
+            }
+        })
+        .collect();
+    running.sort_by_key(|test| test.exponent);
+    let mut primes = activity.primes.lock().unwrap().clone();
+    primes.sort_unstable();
+
+    let estimated_completion = eta
+        .remaining_seconds()
+        .and_then(|seconds| Duration::try_seconds(seconds.min(1e15) as i64))
+        .and_then(|ahead| now.checked_add_signed(ahead))
+        .map(|finish| finish.to_rfc3339_opts(SecondsFormat::Secs, false));
+    let document = StatusDocument {
+        run: run.id.to_string(),
+        started: run
+            .started
+            .with_timezone(&Local)
+            .to_rfc3339_opts(SecondsFormat::Secs, false),
+        updated: now.to_rfc3339_opts(SecondsFormat::Secs, false),
+        state: activity.state(),
+        start_exponent: bounds.0,
+        end_exponent: bounds.1,
+        completed,
+        remaining: total.saturating_sub(completed),
+        discovering: eta.is_discovering(),
+        percent_of_work: progress
+            .percent()
+            .map(|percent| (10.0 * percent).round() / 10.0),
+        running,
+        estimated_completion,
+        primes,
+        memory: budget.map(|budget| {
+            let status = budget.status();
+            MemoryStatus {
+                limit_bytes: status.limit,
+                estimated_bytes: status.admitted,
+                running: status.running,
+                waiting: status.waiting,
+            }
+        }),
+    };
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(&document)? + "\n")?;
+    fs::rename(&tmp, path)
+}
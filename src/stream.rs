@@ -0,0 +1,117 @@
+//! Exponents read from a stream, such as standard input for `test --stdin`,
+//! one line at a time as they arrive.
+//!
+//! Exponents are separated by whitespace and newlines. Nothing is read
+//! before the previous token has been taken, so a run that takes its
+//! exponents only as fast as it tests them never holds more of the input
+//! than the line it is on.
+
+use std::io::{self, BufRead};
+
+/// A token of the input and the line it is on, counted from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Exponent {
+        line: u64,
+        p: u64,
+    },
+    /// Something other than a decimal number that fits in 64 bits.
+    Malformed {
+        line: u64,
+        text: String,
+    },
+}
+
+/// The tokens of `reader`, read lazily.
+pub struct ExponentReader<R> {
+    reader: R,
+    line: u64,
+    /// The tokens left on the current line, last first.
+    pending: Vec<String>,
+}
+
+impl<R: BufRead> ExponentReader<R> {
+    pub fn new(reader: R) -> ExponentReader<R> {
+        ExponentReader {
+            reader,
+            line: 0,
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ExponentReader<R> {
+    type Item = io::Result<Token>;
+
+    /// The next token, `None` at the end of the input.
+    fn next(&mut self) -> Option<io::Result<Token>> {
+        let mut text = String::new();
+        while self.pending.is_empty() {
+            text.clear();
+            match self.reader.read_line(&mut text) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            self.line += 1;
+            self.pending = text.split_whitespace().rev().map(str::to_string).collect();
+        }
+        let text = self.pending.pop()?;
+        let line = self.line;
+        Some(Ok(match text.parse() {
+            Ok(p) => Token::Exponent { line, p },
+            Err(_) => Token::Malformed { line, text },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        ExponentReader::new(input.as_bytes())
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn exponents_are_read_with_their_lines() {
+        assert_eq!(
+            tokens("3 5\n\n  7\t11\r\nx13 -2\n17"),
+            [
+                Token::Exponent { line: 1, p: 3 },
+                Token::Exponent { line: 1, p: 5 },
+                Token::Exponent { line: 3, p: 7 },
+                Token::Exponent { line: 3, p: 11 },
+                Token::Malformed {
+                    line: 4,
+                    text: "x13".to_string()
+                },
+                Token::Malformed {
+                    line: 4,
+                    text: "-2".to_string()
+                },
+                Token::Exponent { line: 5, p: 17 },
+            ]
+        );
+        assert_eq!(tokens(""), []);
+        assert_eq!(tokens("\n \n"), []);
+    }
+
+    #[test]
+    fn lines_are_read_only_as_tokens_are_taken() {
+        let mut input =
+            ExponentReader::new(io::BufReader::with_capacity(1, "3\n5\n7\n".as_bytes()));
+        assert_eq!(
+            input.next().unwrap().unwrap(),
+            Token::Exponent { line: 1, p: 3 }
+        );
+        assert_eq!(input.reader.buffer(), b"");
+        assert_eq!(
+            input.next().unwrap().unwrap(),
+            Token::Exponent { line: 2, p: 5 }
+        );
+        assert_eq!(input.by_ref().count(), 1);
+    }
+}
@@ -0,0 +1,510 @@
+//! The human-readable report printed at the end of a run.
+
+use crate::progress::{format_duration, format_work, group};
+use mersenne::known::is_known_mersenne_exponent;
+use mersenne::report::{
+    DoubleCheck, DurationBucket, FactoringStage, Form, RunSummary, StageSummary, TestReport,
+    TimingStats,
+};
+
+/// Prints one row per tested exponent, in increasing order.
+fn print_table(reports: &mut [TestReport]) {
+    reports.sort_by_key(|report| report.exponent);
+    let width = reports
+        .iter()
+        .map(|report| report.exponent.to_string().len())
+        .max()
+        .unwrap_or(0)
+        .max("Exponent".len());
+
+    outln!(
+        "{:>width$}  {:<9}  {:<5}  {:>10}  {:>12}",
+        "Exponent",
+        "Result",
+        "Stage",
+        "Seconds",
+        "Iter/s",
+        width = width
+    );
+    for report in reports.iter() {
+        let (result, stage) = if report.is_timed_out() {
+            ("timed out", report.test.map_or("-", |test| test.as_str()))
+        } else if report.is_failed() {
+            ("failed", report.test.map_or("-", |test| test.as_str()))
+        } else if report.is_factored() {
+            (
+                "factored",
+                report.factor_stage.map_or("-", FactoringStage::as_str),
+            )
+        } else {
+            (
+                if report.prime { "prime" } else { "composite" },
+                report.test.map_or("-", |test| test.as_str()),
+            )
+        };
+        let rate = report
+            .iterations_per_second()
+            .map_or_else(|| "-".to_string(), |rate| format!("{:.0}", rate));
+        outln!(
+            "{:>width$}  {:<9}  {:<5}  {:>10.3}  {:>12}",
+            report.exponent,
+            result,
+            stage,
+            report.seconds,
+            rate,
+            width = width
+        );
+    }
+}
+
+/// Counts the double-checked exponents and calls out every mismatch, since
+/// each one means a run produced a wrong residue.
+fn print_double_checks(reports: &[TestReport]) {
+    let checked = reports
+        .iter()
+        .filter(|report| report.double_check.is_some())
+        .count();
+    if checked == 0 {
+        return;
+    }
+    let mismatched: Vec<&TestReport> = reports
+        .iter()
+        .filter(|report| report.is_mismatch())
+        .collect();
+    outln!(
+        "Double-checks: {} matched, {} mismatched",
+        checked - mismatched.len(),
+        mismatched.len()
+    );
+    for report in mismatched {
+        if report.double_check == Some(DoubleCheck::Unresolved) {
+            outln!(
+                "WARNING: M({}) double-check UNRESOLVED: all three runs disagreed, so its result cannot be trusted.",
+                report.exponent
+            );
+        } else {
+            outln!(
+                "WARNING: M({}) double-check MISMATCH: the result comes from a tie-breaking third run.",
+                report.exponent
+            );
+        }
+    }
+}
+
+/// Counts the confirmed primes and calls out every conflict, since each one
+/// needs a manual review before the prime can be believed.
+fn print_confirmations(reports: &[TestReport]) {
+    let confirmed = reports
+        .iter()
+        .filter(|report| report.confirmation.is_some())
+        .count();
+    if confirmed == 0 {
+        return;
+    }
+    let conflicts: Vec<&TestReport> = reports
+        .iter()
+        .filter(|report| report.is_conflict())
+        .collect();
+    outln!(
+        "Confirmations: {} confirmed, {} in conflict",
+        confirmed - conflicts.len(),
+        conflicts.len()
+    );
+    for report in conflicts {
+        outln!(
+            "WARNING: M({}) confirmation CONFLICT: the re-run found it composite (Res64: 0x{}); it needs manual review.",
+            report.exponent,
+            report.confirm_res64.as_deref().unwrap_or_default()
+        );
+    }
+}
+
+/// Calls out the tests whose speed suddenly fell, since their times say
+/// less about the machine than the rest.
+fn print_slowdowns(reports: &[TestReport]) {
+    for report in reports {
+        let Some(throughput) = report.throughput.filter(|speed| speed.slowdowns > 0) else {
+            continue;
+        };
+        outln!(
+            "{} slowed down {} time(s): {:.0} it/s at its slowest, {:.0} it/s on average.",
+            report.form.number(report.exponent),
+            throughput.slowdowns,
+            throughput.slowest,
+            throughput.average
+        );
+    }
+}
+
+/// Counts the arithmetic errors the checks caught, by exponent, lists the
+/// tests that panicked with their messages, and gives the failed tests of
+/// either kind as a `test` argument to retry them.
+fn print_errors(summary: &RunSummary, reports: &[TestReport]) {
+    if summary.errors == 0 && summary.failed.is_empty() {
+        return;
+    }
+    let panicked: Vec<&TestReport> = reports
+        .iter()
+        .filter(|report| report.panic.is_some())
+        .collect();
+    if summary.errors > 0 || reports.iter().any(|report| report.failed_at.is_some()) {
+        print_arithmetic_errors(summary, reports);
+    }
+    if !panicked.is_empty() {
+        outln!("\nTests that panicked: {}", panicked.len());
+        for report in panicked {
+            outln!(
+                "{}: {}",
+                report.form.number(report.exponent),
+                report.panic.as_deref().unwrap_or_default()
+            );
+        }
+    }
+    if !summary.failed.is_empty() {
+        let exponents: Vec<String> = summary.failed.iter().map(u64::to_string).collect();
+        outln!("Retry the failed tests with: test {}", exponents.join(","));
+    }
+}
+
+/// Counts the arithmetic errors, and says how each test that had some
+/// ended.
+fn print_arithmetic_errors(summary: &RunSummary, reports: &[TestReport]) {
+    outln!("\nArithmetic errors caught: {}", summary.errors);
+    for report in reports.iter().filter(|report| report.errors > 0) {
+        let outcome = match report.failed_at {
+            Some(iteration) => format!("failed at iteration {}", iteration),
+            None => "finished".to_string(),
+        };
+        outln!(
+            "{}: {} error(s), {}",
+            report.form.number(report.exponent),
+            report.errors,
+            outcome
+        );
+    }
+}
+
+/// Lists the timed-out exponents last, with a `test` argument to retry
+/// them.
+fn print_timed_out(reports: &[TestReport]) {
+    let timed_out: Vec<&TestReport> = reports
+        .iter()
+        .filter(|report| report.is_timed_out())
+        .collect();
+    if timed_out.is_empty() {
+        return;
+    }
+    outln!("\nTimed out:");
+    for report in &timed_out {
+        outln!(
+            "M({}) at {:.1}% complete",
+            report.exponent,
+            report.percent_complete().unwrap_or(0.0)
+        );
+    }
+    let exponents: Vec<String> = timed_out
+        .iter()
+        .map(|report| report.exponent.to_string())
+        .collect();
+    outln!("Retry them with: test {}", exponents.join(","));
+}
+
+/// Prints what each stage of the run did, so a stage short of threads
+/// shows up as the busy one.
+fn print_stages(stages: &[StageSummary]) {
+    if stages.is_empty() {
+        return;
+    }
+    let total: f64 = stages.iter().map(|stage| stage.busy_seconds).sum();
+    let width = stages
+        .iter()
+        .map(|stage| stage.stage.len())
+        .max()
+        .unwrap_or(0);
+    outln!("\nStages:");
+    for stage in stages {
+        outln!(
+            "  {:<width$}  {:>2} thread(s)  {:>6} exponent(s)  {:>6} eliminated  {:>8.2} thread-seconds busy ({:>3.0}%)",
+            stage.stage,
+            stage.threads,
+            stage.exponents,
+            stage.eliminated,
+            stage.busy_seconds,
+            if total > 0.0 { 100.0 * stage.busy_seconds / total } else { 0.0 },
+            width = width
+        );
+    }
+}
+
+/// Prints the `top` exponents that took longest, slowest first.
+fn print_slowest(reports: &[TestReport], top: usize) {
+    // With one exponent the table above already says it all.
+    if top == 0 || reports.len() < 2 {
+        return;
+    }
+    let mut slowest: Vec<&TestReport> = reports.iter().collect();
+    slowest.sort_by(|a, b| b.seconds.total_cmp(&a.seconds));
+    slowest.truncate(top);
+    let width = slowest
+        .iter()
+        .map(|report| report.exponent.to_string().len())
+        .max()
+        .unwrap_or(0);
+    outln!("\nSlowest exponents:");
+    for report in slowest {
+        let rate = report
+            .iterations_per_second()
+            .map_or_else(|| "-".to_string(), |rate| format!("{:.0} iter/s", rate));
+        outln!(
+            "  {:>width$}  {:>10.3} seconds  {:>16}",
+            report.exponent,
+            report.seconds,
+            rate,
+            width = width
+        );
+    }
+}
+
+/// The widest bar of the histogram of test times.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// A bound of a histogram bucket, such as `500ms`, `30s`, `5m` or `2d`.
+fn bound_label(seconds: f64) -> String {
+    let units = [(86400.0, "d"), (3600.0, "h"), (60.0, "m"), (1.0, "s")];
+    for (size, unit) in units {
+        if seconds >= size {
+            return format!("{}{}", seconds / size, unit);
+        }
+    }
+    if seconds > 0.0 {
+        format!("{}ms", (seconds * 1000.0).round())
+    } else {
+        "0".to_string()
+    }
+}
+
+/// Prints how many exponents took how long, on a logarithmic scale, with
+/// the bars scaled to the fullest bucket.
+fn print_histogram(reports: &[TestReport]) {
+    if reports.len() < 2 {
+        return;
+    }
+    let buckets = DurationBucket::histogram(reports);
+    let labels: Vec<String> = buckets
+        .iter()
+        .map(|bucket| match bucket.to {
+            Some(to) => format!("{}-{}", bound_label(bucket.from), bound_label(to)),
+            None => format!("{}+", bound_label(bucket.from)),
+        })
+        .collect();
+    let width = labels.iter().map(String::len).max().unwrap_or(0);
+    let fullest = buckets.iter().map(|bucket| bucket.count).max().unwrap_or(0);
+    outln!("\nTime per exponent:");
+    for (bucket, label) in buckets.iter().zip(&labels) {
+        // Any bucket with an exponent in it gets at least one mark.
+        let bar = (bucket.count * HISTOGRAM_WIDTH).div_ceil(fullest);
+        outln!(
+            "  {:>width$}  {:<bars$}  {}",
+            label,
+            "#".repeat(bar),
+            bucket.count,
+            width = width,
+            bars = HISTOGRAM_WIDTH
+        );
+    }
+}
+
+/// Prints how much of the time of the tests was spent computing, which
+/// leaves out the time their threads waited for a core, such as when the
+/// pool has more threads than the machine has cores.
+fn print_compute(reports: &[TestReport]) {
+    let (compute, seconds) = reports
+        .iter()
+        .filter_map(|report| Some((report.compute_seconds?, report.seconds)))
+        .fold((0.0, 0.0), |(compute, seconds), (c, s)| {
+            (compute + c, seconds + s)
+        });
+    if seconds > 0.0 {
+        outln!(
+            "Compute time: {} of the {} on the clock ({:.0}%)",
+            format_duration(compute),
+            format_duration(seconds),
+            100.0 * (compute / seconds).min(1.0)
+        );
+    }
+}
+
+/// What the summary needs to know about a run beyond its results.
+pub struct SummaryContext {
+    /// Exponents in the range that the candidate filter ruled out for not
+    /// being prime, if known.
+    pub filtered: Option<u64>,
+    /// Known Mersenne prime exponents left out with `--skip-known`.
+    pub known_skipped: Option<usize>,
+    /// The work units of every result in the `--db` or `--ledger`, if the
+    /// run has one.
+    pub lifetime_work: Option<f64>,
+    /// Every thread of the run, across its stages.
+    pub threads: usize,
+    pub form: Form,
+    pub prp: bool,
+    pub stages: Vec<StageSummary>,
+    /// The totals of each range of a run of several `--ranges`.
+    pub ranges: Vec<RunSummary>,
+    /// How many of the slowest exponents to list, from `--summary-top`.
+    pub top: usize,
+}
+
+/// Prints the totals of each range of a run of several `--ranges`.
+fn print_ranges(ranges: &[RunSummary], form: Form) {
+    if ranges.is_empty() {
+        return;
+    }
+    outln!("\nBy range:");
+    for range in ranges {
+        let mut line = format!(
+            "  {}-{}: {} tested, {} prime(s), {} factored, {} composite",
+            range.start_exponent,
+            range.end_exponent,
+            range.tested,
+            range.primes.len(),
+            range.factored,
+            range.composite
+        );
+        let unfinished = range.timed_out.len() + range.failed.len();
+        if unfinished > 0 {
+            line.push_str(&format!(", {} unfinished", unfinished));
+        }
+        if !range.primes.is_empty() {
+            let primes: Vec<String> = range.primes.iter().map(|&p| form.number(p)).collect();
+            line.push_str(&format!(" ({})", primes.join(", ")));
+        }
+        outln!("{}", line);
+    }
+}
+
+/// Prints the results table, the totals and the timing statistics.
+pub fn print_summary(summary: &RunSummary, reports: &mut [TestReport], context: &SummaryContext) {
+    if !reports.is_empty() {
+        outln!();
+        print_table(reports);
+    }
+
+    let form = context.form;
+    let (found, test_name) = match form {
+        Form::Fermat => (format!("{} prime", form.title()), "Pépin's test"),
+        Form::Riesel { .. } => (format!("{} prime", form.title()), "LLR"),
+        Form::Mersenne if !context.prp => (format!("{} prime", form.title()), "Lucas-Lehmer"),
+        _ => (format!("{} probable prime", form.title()), "PRP"),
+    };
+    outln!("\n{}s found:", found);
+    for &p in &summary.primes {
+        // Only the Mersenne primes are listed in `known`.
+        let novelty = match form {
+            Form::Mersenne if is_known_mersenne_exponent(p) => ", already known",
+            Form::Mersenne => ", new",
+            _ => "",
+        };
+        outln!(
+            "{} is a {} ({} digits{}).",
+            form.number(p),
+            found,
+            group(form.digit_count(p)),
+            novelty
+        );
+    }
+
+    outln!();
+    if let Some(candidates) = summary.candidates {
+        outln!("Candidates found: {}", group(candidates));
+    }
+    if let Some(filtered) = context.filtered {
+        outln!(
+            "Exponents ruled out by the candidate filter: {}",
+            group(filtered)
+        );
+    }
+    if let Some(known_skipped) = context.known_skipped {
+        let new = summary
+            .primes
+            .iter()
+            .filter(|&&p| !is_known_mersenne_exponent(p))
+            .count();
+        outln!("Known Mersenne primes skipped: {}", known_skipped);
+        outln!("New {}s found: {}", found, new);
+    }
+    let by_stage = |stage| {
+        reports
+            .iter()
+            .filter(|report| report.factor_stage == Some(stage))
+            .count()
+    };
+    let by_pminus1 = by_stage(FactoringStage::PMinus1);
+    let by_known = by_stage(FactoringStage::External);
+    outln!(
+        "Composites eliminated by trial factoring: {}",
+        group((summary.factored - by_pminus1 - by_known) as u64)
+    );
+    if by_pminus1 > 0 {
+        outln!("Composites eliminated by P-1: {}", by_pminus1);
+    }
+    if by_known > 0 {
+        outln!(
+            "Composites eliminated by known factors (--known-factors): {}",
+            group(by_known as u64)
+        );
+    }
+    outln!(
+        "Composites found by {}: {}",
+        test_name,
+        group(summary.composite as u64)
+    );
+    if !summary.timed_out.is_empty() {
+        outln!("Tests timed out: {}", summary.timed_out.len());
+    }
+    if !summary.failed.is_empty() {
+        outln!("Tests failed: {}", summary.failed.len());
+    }
+    print_ranges(&context.ranges, form);
+    print_double_checks(reports);
+    print_confirmations(reports);
+    print_slowdowns(reports);
+    print_errors(summary, reports);
+
+    if let Some(stats) = TimingStats::from_reports(reports, summary.seconds) {
+        // How much of the pool was kept busy: a run that ends with one long
+        // test on an otherwise idle pool shows up here.
+        let busy = stats.cpu_seconds / (stats.wall_seconds * context.threads as f64);
+        outln!(
+            "\nCPU time: {} over {} of wall time ({:.0}% of {} threads busy)",
+            format_duration(stats.cpu_seconds),
+            format_duration(stats.wall_seconds),
+            100.0 * busy.min(1.0),
+            context.threads
+        );
+        outln!(
+            "Per exponent: mean {}, median {}",
+            format_duration(stats.mean_seconds),
+            format_duration(stats.median_seconds)
+        );
+        print_compute(reports);
+        outln!(
+            "Throughput: {:.2} exponents/second",
+            stats.exponents_per_second
+        );
+    }
+    print_slowest(reports, context.top);
+    print_histogram(reports);
+    print_stages(&context.stages);
+
+    let lifetime = context.lifetime_work.map_or_else(String::new, |work| {
+        format!(" (lifetime: {})", format_work(work))
+    });
+    outln!(
+        "\nWork completed this run: {} units{}",
+        format_work(summary.work_units),
+        lifetime
+    );
+    outln!("Total time taken: {}", format_duration(summary.seconds));
+    print_timed_out(reports);
+}
@@ -0,0 +1,234 @@
+//! The program build and the machine a run happens on.
+//!
+//! [`SystemInfo`] goes into the results file, the JSON summary and every
+//! checkpoint, so a result can be traced to the build and hardware that
+//! produced it, and a test resumed on another machine says so.
+
+use crate::arith::BACKEND_NAME;
+use serde::Serialize;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The build of this program and the machine it runs on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SystemInfo {
+    pub version: &'static str,
+    /// The git commit built, if the build was made from a checkout.
+    pub commit: Option<&'static str>,
+    /// The CPU model, if the platform says.
+    pub cpu: Option<String>,
+    /// The threads the machine can run at once.
+    pub cores: usize,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// The arithmetic the Lucas–Lehmer tests use: `GMP` with the `gmp`
+    /// feature, `num-bigint` otherwise.
+    pub backend: &'static str,
+}
+
+impl SystemInfo {
+    /// This build on this machine. The CPU is looked up on the first call
+    /// only.
+    pub fn current() -> &'static SystemInfo {
+        static CURRENT: OnceLock<SystemInfo> = OnceLock::new();
+        CURRENT.get_or_init(|| SystemInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: option_env!("MERSENNE_GIT_COMMIT"),
+            cpu: cpu_model(),
+            cores: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            backend: BACKEND_NAME,
+        })
+    }
+
+    /// The description as `key=value` fields for the results file, with the
+    /// CPU last since its value runs to the end of the line.
+    pub fn fields(&self) -> String {
+        let mut fields = format!("version={}", self.version);
+        if let Some(commit) = self.commit {
+            fields.push_str(&format!(" commit={}", commit));
+        }
+        fields.push_str(&format!(
+            " cores={} os={} arch={} backend={}",
+            self.cores, self.os, self.arch, self.backend
+        ));
+        if let Some(cpu) = &self.cpu {
+            fields.push_str(&format!(" cpu={}", cpu));
+        }
+        fields
+    }
+}
+
+/// One line, such as `Mersenne 0.1.0 (commit 1a2b3c4d5e6f) on Intel(R)
+/// Core(TM) i7-8700 CPU @ 3.20GHz, 12 cores, linux x86_64, num-bigint
+/// arithmetic`.
+impl fmt::Display for SystemInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mersenne {}", self.version)?;
+        if let Some(commit) = self.commit {
+            write!(f, " (commit {})", commit)?;
+        }
+        write!(f, " on ")?;
+        if let Some(cpu) = &self.cpu {
+            write!(f, "{}, ", cpu)?;
+        }
+        let plural = if self.cores == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} core{}, {} {}, {} arithmetic",
+            self.cores, plural, self.os, self.arch, self.backend
+        )
+    }
+}
+
+/// The name of this machine, for the runs of a results database: from
+/// `/proc/sys/kernel/hostname` on Linux, otherwise from the `HOSTNAME` or
+/// `COMPUTERNAME` environment variable, or `unknown`.
+pub fn host_name() -> String {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .and_then(|text| tidy(&text))
+    {
+        return name;
+    }
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|variable| std::env::var(variable).ok().and_then(|name| tidy(&name)))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The CPU model: from `cpuid` on x86-64, otherwise from `/proc/cpuinfo`
+/// on Linux and `sysctl` on macOS.
+fn cpu_model() -> Option<String> {
+    #[cfg(target_arch = "x86_64")]
+    if let Some(model) = cpuid_brand() {
+        return Some(model);
+    }
+    #[cfg(target_os = "linux")]
+    if let Ok(text) = std::fs::read_to_string("/proc/cpuinfo") {
+        return model_from_cpuinfo(&text);
+    }
+    #[cfg(target_os = "macos")]
+    if let Ok(output) = std::process::Command::new("sysctl")
+        .args(["-n", "machdep.cpu.brand_string"])
+        .output()
+    {
+        return tidy(&String::from_utf8_lossy(&output.stdout));
+    }
+    None
+}
+
+/// The processor brand string of `cpuid` leaves 0x80000002 to 0x80000004.
+#[cfg(target_arch = "x86_64")]
+fn cpuid_brand() -> Option<String> {
+    use std::arch::x86_64::__cpuid;
+
+    // cpuid is on every x86-64 processor. Newer toolchains no longer count
+    // the call as unsafe.
+    #[allow(unused_unsafe)]
+    let leaf = |leaf: u32| unsafe { __cpuid(leaf) };
+    if leaf(0x8000_0000).eax < 0x8000_0004 {
+        return None;
+    }
+    let bytes: Vec<u8> = (0x8000_0002..=0x8000_0004)
+        .map(leaf)
+        .flat_map(|r| [r.eax, r.ebx, r.ecx, r.edx])
+        .flat_map(u32::to_le_bytes)
+        .collect();
+    tidy(&String::from_utf8_lossy(&bytes))
+}
+
+/// The model named in the text of `/proc/cpuinfo`, which each architecture
+/// labels differently.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn model_from_cpuinfo(text: &str) -> Option<String> {
+    ["model name", "Processor", "cpu model", "Hardware", "cpu"]
+        .iter()
+        .find_map(|&key| {
+            text.lines()
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim() == key)
+                .and_then(|(_, value)| tidy(value))
+        })
+}
+
+/// `text` with the padding of CPU names squeezed out, if anything is left.
+fn tidy(text: &str) -> Option<String> {
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_whitespace() || c == '\0')
+        .filter(|word| !word.is_empty())
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SystemInfo {
+        SystemInfo {
+            version: "0.1.0",
+            commit: Some("1a2b3c4d5e6f"),
+            cpu: Some("Intel(R) Xeon(R) CPU @ 2.20GHz".to_string()),
+            cores: 4,
+            os: "linux",
+            arch: "x86_64",
+            backend: "num-bigint",
+        }
+    }
+
+    #[test]
+    fn describes_the_build_and_machine() {
+        assert_eq!(
+            sample().to_string(),
+            "Mersenne 0.1.0 (commit 1a2b3c4d5e6f) on Intel(R) Xeon(R) CPU @ 2.20GHz, \
+             4 cores, linux x86_64, num-bigint arithmetic"
+        );
+        assert_eq!(
+            sample().fields(),
+            "version=0.1.0 commit=1a2b3c4d5e6f cores=4 os=linux arch=x86_64 \
+             backend=num-bigint cpu=Intel(R) Xeon(R) CPU @ 2.20GHz"
+        );
+        let bare = SystemInfo {
+            commit: None,
+            cpu: None,
+            cores: 1,
+            ..sample()
+        };
+        assert_eq!(
+            bare.to_string(),
+            "Mersenne 0.1.0 on 1 core, linux x86_64, num-bigint arithmetic"
+        );
+        assert_eq!(
+            bare.fields(),
+            "version=0.1.0 cores=1 os=linux arch=x86_64 backend=num-bigint"
+        );
+    }
+
+    #[test]
+    fn finds_the_model_in_cpuinfo() {
+        let x86 = "processor\t: 0\nvendor_id\t: GenuineIntel\n\
+                   model name\t: Intel(R) Core(TM) i7-8700 CPU @ 3.20GHz\n";
+        assert_eq!(
+            model_from_cpuinfo(x86).as_deref(),
+            Some("Intel(R) Core(TM) i7-8700 CPU @ 3.20GHz")
+        );
+        let arm = "processor\t: 0\nBogoMIPS\t: 108.00\n\nHardware\t: BCM2835\n";
+        assert_eq!(model_from_cpuinfo(arm).as_deref(), Some("BCM2835"));
+        assert_eq!(model_from_cpuinfo("processor\t: 0\n"), None);
+        assert_eq!(
+            tidy("  Intel(R)   Xeon(R)\0\0\0").as_deref(),
+            Some("Intel(R) Xeon(R)")
+        );
+    }
+
+    #[test]
+    fn current_is_this_build() {
+        let current = SystemInfo::current();
+        assert_eq!(current.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(current.backend, BACKEND_NAME);
+        assert!(current.cores >= 1);
+    }
+}
@@ -0,0 +1,125 @@
+//! Holding a test to a share of a core.
+//!
+//! A [`Throttle`] given to a test with
+//! [`TestControl::throttle_with`](crate::TestControl::throttle_with) is
+//! looked at where the test looks at its stop flag, before every
+//! iteration. It lets the test run for a slice of each [`CYCLE`] and then
+//! sleeps its thread for the rest, so the thread takes about its share of
+//! a core and the machine stays cool.
+//!
+//! The slice is measured in the CPU time of the thread, where the platform
+//! gives it by [`thread_cpu_time`]: time the thread spends waiting for a
+//! core is not counted as running, so the sleep after each slice is just
+//! as long as it takes for the share to hold over the cycle, however busy
+//! the machine is. Sleeping takes no CPU time, so it is not part of a
+//! test's [compute time](crate::clock::ComputeTimer).
+
+use crate::clock::{thread_cpu_time, Clock};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The length of a duty cycle: a throttled test runs for its share of it
+/// and sleeps for the rest, in bursts short enough that the fans do not
+/// follow them.
+pub const CYCLE: Duration = Duration::from_millis(100);
+
+/// A duty cycle for the thread of one test. See the [module docs](self).
+#[derive(Debug)]
+pub struct Throttle {
+    share: f64,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// When the current slice began, by the test's clock and by the
+    /// thread's CPU time.
+    since: Option<(Duration, Duration)>,
+    slept: Duration,
+}
+
+impl Throttle {
+    /// A throttle to `share` of a core, more than 0 and at most 1.
+    pub fn new(share: f64) -> Throttle {
+        assert!(
+            share > 0.0 && share <= 1.0,
+            "a throttle's share of {} is not in (0, 1]",
+            share
+        );
+        Throttle {
+            share,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn share(&self) -> f64 {
+        self.share
+    }
+
+    /// The time the test has slept so far.
+    pub fn slept(&self) -> Duration {
+        self.state.lock().unwrap().slept
+    }
+
+    /// How long the test should sleep now, if it has used up its slice;
+    /// the slice then starts over once it has. `clock` is the test's.
+    pub(crate) fn due(&self, clock: &dyn Clock) -> Option<Duration> {
+        let now = clock.now();
+        let cpu = thread_cpu_time().unwrap_or(now);
+        let mut state = self.state.lock().unwrap();
+        let Some((started, cpu_started)) = state.since else {
+            state.since = Some((now, cpu));
+            return None;
+        };
+        let ran = cpu.saturating_sub(cpu_started);
+        if ran.as_secs_f64() < self.share * CYCLE.as_secs_f64() {
+            return None;
+        }
+        state.since = None;
+        let sleep = rest_of_cycle(self.share, ran, now.saturating_sub(started));
+        state.slept += sleep;
+        Some(sleep).filter(|sleep| !sleep.is_zero())
+    }
+}
+
+/// The sleep that brings a slice that `ran` for that much CPU time over
+/// `elapsed` on the clock down to `share` of it: none if the thread was
+/// kept off its core for long enough already.
+fn rest_of_cycle(share: f64, ran: Duration, elapsed: Duration) -> Duration {
+    Duration::from_secs_f64(ran.as_secs_f64() / share).saturating_sub(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_sleep_makes_up_the_rest_of_the_share() {
+        let ms = Duration::from_millis;
+        // 50 ms of CPU in 50 ms at a quarter of a core leaves 150 ms.
+        assert_eq!(rest_of_cycle(0.25, ms(50), ms(50)), ms(150));
+        // Time spent waiting for a core counts towards it.
+        assert_eq!(rest_of_cycle(0.25, ms(50), ms(120)), ms(80));
+        assert_eq!(rest_of_cycle(0.25, ms(50), ms(300)), ms(0));
+        assert_eq!(rest_of_cycle(1.0, ms(100), ms(100)), ms(0));
+    }
+
+    #[cfg(all(unix, feature = "native"))]
+    #[test]
+    fn a_busy_thread_takes_about_its_share() {
+        let clock = crate::clock::default_clock();
+        let throttle = Throttle::new(0.5);
+        let (started, cpu_started) = (clock.now(), thread_cpu_time().unwrap());
+        let mut sum = 0u64;
+        while clock.now() - started < Duration::from_millis(600) {
+            sum = std::hint::black_box(sum.wrapping_add(1));
+            if let Some(sleep) = throttle.due(clock) {
+                std::thread::sleep(sleep);
+            }
+        }
+        let cpu = (thread_cpu_time().unwrap() - cpu_started).as_secs_f64();
+        let share = cpu / (clock.now() - started).as_secs_f64();
+        assert!((0.3..0.7).contains(&share), "took {:.2} of a core", share);
+        assert!(throttle.slept() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,263 @@
+//! How fast a test is going, from its progress reports.
+//!
+//! A [`RateMeter`] is handed each [`Progress`] of a test. From the last
+//! `window` intervals between reports it works out the speed of the latest
+//! interval, the speed over the whole window and the time left at that
+//! speed, and it notices when the speed suddenly falls well below the
+//! window's. At the end it sums the test up as a [`Throughput`] for its
+//! [`TestReport`](crate::report::TestReport).
+
+use crate::Progress;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The shortest interval, in seconds, whose speed is compared with the
+/// window's. The speed of shorter ones is mostly timer noise.
+const MIN_INTERVAL: f64 = 0.1;
+
+/// The shortest window, in seconds, that a slowdown is measured against and
+/// that counts towards [`Throughput::slowest`].
+const MIN_WINDOW: f64 = 1.0;
+
+/// How fast a primality test ran, in iterations per second.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Throughput {
+    /// Over the whole test, from its first progress report to its last.
+    pub average: f64,
+    /// Over its slowest window of at least a second, or `average` if it
+    /// never ran that long.
+    pub slowest: f64,
+    /// How many times the speed fell suddenly by more than the threshold.
+    pub slowdowns: u32,
+}
+
+/// The speed of a test at one of its progress reports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    /// Iterations per second since the previous report.
+    pub current: f64,
+    /// Iterations per second over the window.
+    pub average: f64,
+    /// The time to the end of the test at the speed of the window, or
+    /// `None` if the window saw no iterations.
+    pub remaining: Option<Duration>,
+    /// How many percent `current` fell below the speed of the window before
+    /// it, if that is more than the threshold. Reported once per slowdown:
+    /// not again until the speed has recovered.
+    pub slowdown: Option<f64>,
+}
+
+/// Keeps the recent progress reports of a test. See the [module
+/// docs](self).
+#[derive(Debug, Clone)]
+pub struct RateMeter {
+    window: usize,
+    threshold: f64,
+    /// The iteration and elapsed seconds of up to `window + 1` reports of
+    /// the current run, oldest first.
+    samples: VecDeque<(u64, f64)>,
+    /// The first report of the current run.
+    first: Option<(u64, f64)>,
+    /// Iterations and seconds of earlier runs: the first runs of a
+    /// double-checked or confirmed test, and work since redone.
+    earlier: (u64, f64),
+    slowest: Option<f64>,
+    slowdowns: u32,
+    slow: bool,
+}
+
+/// Iterations per second from report `from` to report `to`, if any time
+/// passed between them.
+fn speed(from: (u64, f64), to: (u64, f64)) -> Option<f64> {
+    let seconds = to.1 - from.1;
+    (seconds > 0.0).then(|| to.0.saturating_sub(from.0) as f64 / seconds)
+}
+
+impl RateMeter {
+    /// A meter averaging over the last `window` intervals, at least one,
+    /// that reports a slowdown of more than `threshold` percent.
+    pub fn new(window: usize, threshold: u32) -> RateMeter {
+        RateMeter {
+            window: window.max(1),
+            threshold: f64::from(threshold),
+            samples: VecDeque::new(),
+            first: None,
+            earlier: (0, 0.0),
+            slowest: None,
+            slowdowns: 0,
+            slow: false,
+        }
+    }
+
+    /// Takes the next progress report of the test. Returns its speed, or
+    /// `None` for the first report of a run or if no time has passed.
+    pub fn record(&mut self, progress: &Progress) -> Option<Rate> {
+        let sample = (progress.iteration, progress.elapsed.as_secs_f64());
+        match self.samples.back() {
+            // A new run, or one that went back to redo iterations.
+            Some(&last) if sample.0 < last.0 || sample.1 < last.1 => self.restart(sample),
+            None => self.restart(sample),
+            Some(_) => self.samples.push_back(sample),
+        }
+        while self.samples.len() > self.window + 1 {
+            self.samples.pop_front();
+        }
+        let n = self.samples.len();
+        if n < 2 {
+            return None;
+        }
+        let (oldest, previous) = (self.samples[0], self.samples[n - 2]);
+        let current = speed(previous, sample)?;
+        let average = speed(oldest, sample)?;
+        if sample.1 - oldest.1 >= MIN_WINDOW {
+            self.slowest = Some(self.slowest.map_or(average, |slowest| slowest.min(average)));
+        }
+
+        let mut slowdown = None;
+        let before = speed(oldest, previous).filter(|_| previous.1 - oldest.1 >= MIN_WINDOW);
+        if let (Some(before), true) = (before, sample.1 - previous.1 >= MIN_INTERVAL) {
+            let fall = 100.0 * (1.0 - current / before);
+            if fall <= self.threshold {
+                self.slow = false;
+            } else if !self.slow {
+                self.slow = true;
+                self.slowdowns += 1;
+                slowdown = Some(fall);
+            }
+        }
+        let left = progress.total.saturating_sub(progress.iteration) as f64;
+        let remaining = Duration::try_from_secs_f64(left / average).ok();
+        Some(Rate {
+            current,
+            average,
+            remaining,
+            slowdown,
+        })
+    }
+
+    /// Starts a new run at `sample`, adding the one so far to the earlier
+    /// runs.
+    fn restart(&mut self, sample: (u64, f64)) {
+        if let (Some(first), Some(&last)) = (self.first, self.samples.back()) {
+            self.earlier.0 += last.0 - first.0;
+            self.earlier.1 += last.1 - first.1;
+        }
+        self.samples.clear();
+        self.samples.push_back(sample);
+        self.first = Some(sample);
+        self.slow = false;
+    }
+
+    /// The test so far, or `None` if no time has passed between its
+    /// reports.
+    pub fn throughput(&self) -> Option<Throughput> {
+        let (mut iterations, mut seconds) = self.earlier;
+        if let (Some(first), Some(&last)) = (self.first, self.samples.back()) {
+            iterations += last.0 - first.0;
+            seconds += last.1 - first.1;
+        }
+        if seconds <= 0.0 {
+            return None;
+        }
+        let average = iterations as f64 / seconds;
+        Some(Throughput {
+            average,
+            slowest: self.slowest.unwrap_or(average),
+            slowdowns: self.slowdowns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(iteration: u64, seconds: f64) -> Progress {
+        Progress {
+            iteration,
+            total: 1000,
+            elapsed: Duration::from_secs_f64(seconds),
+            current_res64_hint: None,
+        }
+    }
+
+    #[test]
+    fn rates_cover_the_latest_interval_and_the_window() {
+        let mut meter = RateMeter::new(2, 50);
+        assert_eq!(meter.record(&at(0, 0.0)), None);
+        let rate = meter.record(&at(100, 1.0)).unwrap();
+        assert_eq!((rate.current, rate.average), (100.0, 100.0));
+        assert_eq!(rate.remaining, Some(Duration::from_secs(9)));
+        let rate = meter.record(&at(300, 2.0)).unwrap();
+        assert_eq!((rate.current, rate.average), (200.0, 150.0));
+        // The first interval has left the window.
+        let rate = meter.record(&at(500, 3.0)).unwrap();
+        assert_eq!((rate.current, rate.average), (200.0, 200.0));
+        assert_eq!(rate.remaining, Some(Duration::from_secs_f64(2.5)));
+        assert_eq!(
+            meter.throughput(),
+            Some(Throughput {
+                average: 500.0 / 3.0,
+                slowest: 100.0,
+                slowdowns: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn a_sudden_slowdown_is_reported_once() {
+        let mut meter = RateMeter::new(4, 30);
+        for second in 0..=4 {
+            let rate = meter.record(&at(second * 100, second as f64));
+            assert!(rate.is_none_or(|rate| rate.slowdown.is_none()));
+        }
+        let rate = meter.record(&at(450, 5.0)).unwrap();
+        assert_eq!(rate.slowdown, Some(50.0));
+        let rate = meter.record(&at(500, 6.0)).unwrap();
+        assert_eq!(rate.slowdown, None);
+        // Back to speed, then slow again.
+        meter.record(&at(600, 7.0));
+        meter.record(&at(700, 8.0));
+        meter.record(&at(800, 9.0));
+        meter.record(&at(900, 10.0));
+        assert!(meter.record(&at(910, 11.0)).unwrap().slowdown.is_some());
+        assert_eq!(meter.throughput().unwrap().slowdowns, 2);
+    }
+
+    #[test]
+    fn jitter_below_the_threshold_is_no_slowdown() {
+        let mut meter = RateMeter::new(4, 30);
+        for (second, iteration) in [0, 100, 190, 300, 380, 490, 570, 680]
+            .into_iter()
+            .enumerate()
+        {
+            let rate = meter.record(&at(iteration, second as f64));
+            assert!(rate.is_none_or(|rate| rate.slowdown.is_none()));
+        }
+        assert_eq!(meter.throughput().unwrap().slowdowns, 0);
+    }
+
+    #[test]
+    fn short_intervals_are_not_slowdowns() {
+        let mut meter = RateMeter::new(10, 10);
+        meter.record(&at(0, 0.0));
+        meter.record(&at(10, 0.001));
+        assert_eq!(meter.record(&at(11, 0.002)).unwrap().slowdown, None);
+        let throughput = meter.throughput().unwrap();
+        assert_eq!(throughput.slowest, throughput.average);
+    }
+
+    #[test]
+    fn later_runs_add_to_the_throughput() {
+        let mut meter = RateMeter::new(10, 10);
+        meter.record(&at(0, 0.0));
+        meter.record(&at(1000, 2.0));
+        // A second run starts over from iteration zero.
+        assert_eq!(meter.record(&at(0, 0.0)), None);
+        meter.record(&at(1000, 8.0));
+        let throughput = meter.throughput().unwrap();
+        assert_eq!(throughput.average, 200.0);
+        assert_eq!(throughput.slowest, 125.0);
+    }
+}
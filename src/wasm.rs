@@ -0,0 +1,124 @@
+//! A JavaScript interface for browsers, built with the `wasm` feature for
+//! `wasm32-unknown-unknown`:
+//!
+//! ```text
+//! cargo build --lib --release --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! wasm-bindgen --target web --out-dir examples/wasm/pkg \
+//!     target/wasm32-unknown-unknown/release/mersenne.wasm
+//! ```
+//!
+//! A test runs to the end before returning, so a page should call it from a
+//! Web Worker and pass its progress on with `postMessage`, as
+//! `examples/wasm` does. M(9941) takes about a second.
+
+use crate::clock::Clock;
+use crate::factor::{trial_factor, worthwhile_tf_depth};
+use crate::report::{format_res64, FactoringStage, Form, TestKind, TestReport};
+use crate::{is_mersenne_prime_interruptible, primality, LlResult, TestControl, TestEvent};
+use serde::Serialize;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+/// The deepest the browser trial factors, in bits. The 128-bit arithmetic
+/// of trial factoring is emulated on `wasm32`, which makes it hundreds of
+/// times slower than natively, while the Lucas–Lehmer test is only a few
+/// times slower; at the native [`worthwhile_tf_depth`] factoring would take
+/// most of the time.
+const MAX_TF_DEPTH: u32 = 32;
+
+/// The browser's clock, `Date.now()`, which the standard library cannot
+/// read on `wasm32-unknown-unknown`.
+#[derive(Debug)]
+struct DateClock;
+
+impl Clock for DateClock {
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(js_sys::Date::now() / 1000.0)
+    }
+}
+
+/// Trial factors `M(p)` to at most [`MAX_TF_DEPTH`] bits and, if no factor
+/// turns up, runs a Lucas–Lehmer test of it. Returns its report as a plain
+/// object with the fields of `--json` output, such as `{exponent: 9941,
+/// form: "mersenne", prime: true, test: "LL", seconds: 1.1, ...}`.
+///
+/// `progress`, if given, is called with `(iteration, total)` about every
+/// 1% of the test. Throws if `p` is not prime, or if the test keeps
+/// failing its arithmetic checks.
+#[wasm_bindgen]
+pub fn test_exponent(p: u32, progress: Option<js_sys::Function>) -> Result<JsValue, JsError> {
+    let p = u64::from(p);
+    if !primality::is_prime(p) {
+        return Err(JsError::new(&format!("{} is not prime", p)));
+    }
+    let clock = DateClock;
+    let started = clock.now();
+    let seconds = || clock.now().saturating_sub(started).as_secs_f64();
+    let report = TestReport {
+        exponent: p,
+        form: Form::Mersenne,
+        prime: false,
+        test: None,
+        seconds: 0.0,
+        compute_seconds: None,
+        res64: None,
+        res2048: None,
+        factor: None,
+        factor_stage: None,
+        shift: None,
+        double_check: None,
+        backend: None,
+        check_backend: None,
+        confirmation: None,
+        confirm_res64: None,
+        timed_out_at: None,
+        failed_at: None,
+        errors: 0,
+        checkpoints: 0,
+        throughput: None,
+        panic: None,
+        milestones: Vec::new(),
+        range: None,
+        run: None,
+    };
+
+    let depth = worthwhile_tf_depth(p).min(MAX_TF_DEPTH);
+    let report = if let Some(factor) = trial_factor(p, depth) {
+        TestReport {
+            seconds: seconds(),
+            factor: Some(factor.to_string()),
+            factor_stage: Some(FactoringStage::TrialFactoring),
+            ..report
+        }
+    } else {
+        let never = AtomicBool::new(false);
+        let control = TestControl::new(&never).with_clock(&clock);
+        let result = is_mersenne_prime_interruptible(p, None, control, |event| {
+            if let (TestEvent::Progress(report), Some(progress)) = (event, &progress) {
+                let iteration = JsValue::from_f64(report.iteration as f64);
+                let total = JsValue::from_f64(report.total as f64);
+                // A throwing callback only loses its progress report.
+                let _ = progress.call2(&JsValue::NULL, &iteration, &total);
+            }
+        });
+        // The stop flag is never raised, so only arithmetic errors stop it.
+        let Ok(result) = result else {
+            return Err(JsError::new("the test kept failing its arithmetic checks"));
+        };
+        TestReport {
+            prime: result.is_prime(),
+            test: Some(TestKind::LucasLehmer),
+            seconds: seconds(),
+            compute_seconds: None,
+            res64: match result {
+                LlResult::Composite { res64 } => Some(format_res64(res64)),
+                _ => None,
+            },
+            ..report
+        }
+    };
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    Ok(report.serialize(&serializer)?)
+}
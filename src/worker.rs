@@ -0,0 +1,252 @@
+//! `work`: asks a `serve` server for exponents, tests them and reports
+//! the results, until the server's range is done or Ctrl-C.
+
+use crate::http;
+use crate::notify::Notifier;
+use crate::progress::{format_duration, ProgressDisplay};
+use crate::status::Activity;
+use crate::{
+    audit_log, catch_panic, checkpoint_store, events, idle, panicked, pin_tests, pinning,
+    print_report, spreadsheet, test_exponent, thread_pool, watch_control, watch_load, Options,
+    CANCEL, EXIT_INTERNAL_ERROR, EXIT_INTERRUPTED, EXIT_NONE_FOUND, EXIT_SUCCESS, EXIT_USAGE,
+};
+use log::{debug, error, info, warn, Level};
+use mersenne::coordinator::{ResultResponse, ServerStatus, WorkResponse};
+use mersenne::report::{Form, RunSummary, TestReport};
+use mersenne::results::ResultsFile;
+use mersenne::run::RunContext;
+use mersenne::system::SystemInfo;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long to wait before asking again when the server has nothing to
+/// hand out or cannot be reached.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Works for the server at `server` as `run` and returns the exit status.
+pub fn run(options: &Options, server: &str, run: &RunContext) -> u8 {
+    if options.form != Form::Mersenne {
+        error!(
+            "a server hands out Mersenne exponents, so --form {} cannot be used with work.",
+            options.form
+        );
+        return EXIT_USAGE;
+    }
+    if options.dry_run {
+        error!("the server decides what work runs, so work has no --dry-run.");
+        return EXIT_USAGE;
+    }
+    if options.nice {
+        idle::lower_priority();
+    }
+    let results_file = match &options.results {
+        Some(path) => match ResultsFile::open(path) {
+            Ok(file) => Some(Mutex::new(file.stamped(SystemInfo::current()))),
+            Err(e) => {
+                error!("cannot open {}: {}", path.display(), e);
+                return EXIT_USAGE;
+            }
+        },
+        None => None,
+    };
+    let spreadsheet = match spreadsheet(options, "server") {
+        Ok(spreadsheet) => spreadsheet,
+        Err(status) => return status,
+    };
+    let checkpoints = match checkpoint_store(options, run) {
+        Ok(checkpoints) => checkpoints,
+        Err(status) => return status,
+    };
+    let audit_log = match audit_log(options, run) {
+        Ok(audit_log) => audit_log,
+        Err(status) => return status,
+    };
+    let pinning = pinning(options).filter(|pinning| pin_tests(options, Some(pinning)));
+    let pool = match thread_pool(options.threads, pinning.as_ref()) {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("cannot start worker threads: {}", e);
+            return EXIT_INTERNAL_ERROR;
+        }
+    };
+    if let Some(pinning) = &pinning {
+        info!("Pinned threads: {}.", pinning.layout());
+    }
+
+    let status: ServerStatus = match http::call(server, "GET", "/status", None::<&()>) {
+        Ok(status) => status,
+        Err(e) => {
+            error!("cannot reach the server at {}: {}", server, e);
+            return EXIT_USAGE;
+        }
+    };
+    info!("Run {}.", run.id);
+    info!(
+        "Working for {} on p = {} to p = {} ({} done) with {} thread(s).",
+        server,
+        status.start_exponent,
+        status.end_exponent,
+        status.completed,
+        pool.current_num_threads()
+    );
+
+    let display = ProgressDisplay::new(
+        log::log_enabled!(Level::Trace),
+        options.rate_window,
+        options.slowdown_warning,
+    );
+    let activity = Activity::new(Form::Mersenne).under(&CANCEL);
+    let notifier = Notifier::new(
+        options.form,
+        options.notify_cmd.clone(),
+        options.notify_url.clone(),
+    );
+    events::emit(events::Event::RunStarted {
+        source: "server",
+        form: options.form,
+        start: status.start_exponent,
+        end: status.end_exponent,
+        threads: pool.current_num_threads(),
+    });
+    let summary = Mutex::new(RunSummary::new(status.start_exponent, status.end_exponent));
+    let start_time = Instant::now();
+    // Each thread leases and tests one exponent at a time.
+    std::thread::scope(|threads| {
+        let _watching = watch_load(threads, options);
+        let _controlling = watch_control(threads, options, Vec::new());
+        pool.scope(|scope| {
+            for _ in 0..pool.current_num_threads() {
+                scope.spawn(|_| {
+                    while let Some(p) = lease(server) {
+                        let tested = catch_panic(|| {
+                            test_exponent(
+                                p,
+                                options,
+                                checkpoints.as_ref(),
+                                audit_log.as_ref(),
+                                &display,
+                                &activity,
+                            )
+                        });
+                        let report = match tested {
+                            Ok(Ok(report)) => report,
+                            // Reporting it hands it back to the server.
+                            Err(message) => panicked(p, options.form, message, 0.0),
+                            Ok(Err(interrupted)) => {
+                                info!(
+                                    "Interrupted at iteration {} of {} for p = {}; its lease runs out on the server.",
+                                    interrupted.iteration, interrupted.total, p
+                                );
+                                break;
+                            }
+                        };
+                        let report = TestReport {
+                            run: Some(run.id.clone()),
+                            ..report
+                        };
+                        debug!(
+                            "Finished {} in {}.",
+                            options.form.number(p),
+                            format_duration(report.seconds)
+                        );
+                        activity.record(&report);
+                        events::finished(&report);
+                        display.suspend(|| print_report(&report, options));
+                        if let (Some(notifier), true) = (&notifier, report.prime) {
+                            notifier.prime_found(&report);
+                        }
+                        summary.lock().unwrap().record(&report);
+                        if let Some(results_file) = &results_file {
+                            if let Err(e) = results_file.lock().unwrap().record(&report) {
+                                warn!("could not write to the results file: {}", e);
+                            }
+                        }
+                        if let Some(spreadsheet) = &spreadsheet {
+                            if let Err(e) = spreadsheet.lock().unwrap().record(&report) {
+                                warn!("could not write to the CSV file: {}", e);
+                            }
+                        }
+                        submit(server, &report);
+                    }
+                });
+            }
+        })
+    });
+
+    let mut summary = summary.into_inner().unwrap();
+    summary.seconds = start_time.elapsed().as_secs_f64();
+    let interrupted = CANCEL.is_cancelled();
+    if let Some(notifier) = notifier {
+        notifier.run_finished(&summary, interrupted);
+    }
+    events::run_finished(&summary, interrupted, false);
+    if interrupted {
+        return EXIT_INTERRUPTED;
+    }
+    info!("The server has no more work; its range is done.");
+    if !summary.primes.is_empty() {
+        EXIT_SUCCESS
+    } else {
+        EXIT_NONE_FOUND
+    }
+}
+
+/// Asks the server for an exponent until it hands one out, returning `None`
+/// once the range is done or Ctrl-C is pressed.
+fn lease(server: &str) -> Option<u64> {
+    while !CANCEL.is_cancelled() {
+        match http::call::<(), WorkResponse>(server, "GET", "/work", None) {
+            Ok(WorkResponse {
+                exponent: Some(p), ..
+            }) => return Some(p),
+            Ok(work) if work.finished => return None,
+            Ok(_) => debug!("The server has nothing to hand out right now; asking again later."),
+            Err(e) => warn!(
+                "could not ask {} for work: {}; trying again later.",
+                server, e
+            ),
+        }
+        pause(RETRY_INTERVAL);
+    }
+    None
+}
+
+/// Reports `report` to the server, retrying until it gets through. A report
+/// that still cannot be sent when Ctrl-C is pressed is dropped, and the
+/// exponent goes to another worker once its lease runs out.
+fn submit(server: &str, report: &TestReport) {
+    loop {
+        match http::call(server, "POST", "/result", Some(report)) {
+            Ok(ResultResponse { accepted: true }) => {
+                debug!("The server recorded M({}).", report.exponent);
+                return;
+            }
+            Ok(ResultResponse { accepted: false }) => {
+                if report.has_result() {
+                    info!(
+                        "The server already had a result for M({}).",
+                        report.exponent
+                    );
+                }
+                return;
+            }
+            Err(e) => warn!(
+                "could not report M({}) to {}: {}; trying again later.",
+                report.exponent, server, e
+            ),
+        }
+        pause(RETRY_INTERVAL);
+        if CANCEL.is_cancelled() {
+            warn!("M({}) was not reported to the server.", report.exponent);
+            return;
+        }
+    }
+}
+
+/// Sleeps for `duration`, or until Ctrl-C.
+fn pause(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !CANCEL.is_cancelled() && Instant::now() < until {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
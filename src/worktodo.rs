@@ -0,0 +1,320 @@
+//! Prime95-style `worktodo.txt` assignment files.
+//!
+//! Only Lucas–Lehmer work (`Test=` and `DoubleCheck=` lines) is picked up.
+//! Every other line is kept verbatim, so the file can be rewritten as
+//! assignments complete without disturbing anything this program does not
+//! understand.
+
+use crate::primality::rejection;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A Lucas–Lehmer assignment, e.g. `Test=N/A,44497,75,1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub double_check: bool,
+    /// The assignment ID, absent for bare `Test=44497` lines and `N/A`.
+    pub assignment_id: Option<String>,
+    pub exponent: u64,
+    /// How far the exponent has already been trial factored, in bits.
+    pub tf_bits: Option<u32>,
+    /// Whether P-1 factoring has already been done.
+    pub p1_done: Option<bool>,
+}
+
+/// One line of a worktodo file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Assignment(Assignment),
+    /// Blank lines, comments and `[Worker #n]` section headers.
+    Comment,
+    /// A work type this program does not run, such as `PRP=` or `Factor=`.
+    Unsupported,
+    /// A `Test=`/`DoubleCheck=` line that could not be parsed.
+    Malformed(String),
+}
+
+/// Classifies a single worktodo line.
+pub fn parse_line(line: &str) -> Line {
+    let line = line.trim();
+    if line.is_empty()
+        || line.starts_with(';')
+        || line.starts_with('#')
+        || line.starts_with("//")
+        || line.starts_with('[')
+    {
+        return Line::Comment;
+    }
+
+    let (kind, args) = match line.split_once('=') {
+        Some((kind, args)) => (kind.trim(), args.trim()),
+        None => return Line::Unsupported,
+    };
+    let double_check = if kind.eq_ignore_ascii_case("Test") {
+        false
+    } else if kind.eq_ignore_ascii_case("DoubleCheck") {
+        true
+    } else {
+        return Line::Unsupported;
+    };
+
+    match parse_assignment(args, double_check) {
+        Ok(assignment) => Line::Assignment(assignment),
+        Err(reason) => Line::Malformed(reason),
+    }
+}
+
+fn parse_assignment(args: &str, double_check: bool) -> Result<Assignment, String> {
+    let fields: Vec<&str> = args.split(',').map(str::trim).collect();
+
+    // `Test=44497` has only the exponent; otherwise the assignment ID leads.
+    let (assignment_id, rest) = match fields.as_slice() {
+        [exponent] => (None, vec![*exponent]),
+        [id, rest @ ..] => {
+            let id = if id.eq_ignore_ascii_case("N/A") || id.is_empty() {
+                None
+            } else if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+                Some(id.to_string())
+            } else {
+                return Err(format!("invalid assignment ID '{}'", id));
+            };
+            (id, rest.to_vec())
+        }
+        [] => unreachable!("split always yields at least one field"),
+    };
+    if rest.len() > 3 {
+        return Err("too many fields".to_string());
+    }
+
+    let exponent = match rest.first() {
+        Some(field) => field
+            .parse::<u64>()
+            .map_err(|_| format!("invalid exponent '{}'", field))?,
+        None => return Err("missing exponent".to_string()),
+    };
+    if let Some(rejection) = rejection(exponent) {
+        return Err(rejection);
+    }
+
+    let tf_bits = match rest.get(1) {
+        Some(field) => Some(
+            field
+                .parse::<u32>()
+                .map_err(|_| format!("invalid trial factoring depth '{}'", field))?,
+        ),
+        None => None,
+    };
+    let p1_done = match rest.get(2) {
+        Some(&"0") => Some(false),
+        Some(&"1") => Some(true),
+        Some(field) => return Err(format!("invalid P-1 flag '{}'", field)),
+        None => None,
+    };
+
+    Ok(Assignment {
+        double_check,
+        assignment_id,
+        exponent,
+        tf_bits,
+        p1_done,
+    })
+}
+
+/// A loaded worktodo file, which can be rewritten as assignments complete.
+#[derive(Debug, Clone)]
+pub struct WorkTodo {
+    path: PathBuf,
+    lines: Vec<(String, Line)>,
+}
+
+impl WorkTodo {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<WorkTodo> {
+        let text = fs::read_to_string(path.as_ref())?;
+        Ok(WorkTodo::parse(path.as_ref(), &text))
+    }
+
+    fn parse(path: &Path, text: &str) -> WorkTodo {
+        WorkTodo {
+            path: path.to_path_buf(),
+            lines: text
+                .lines()
+                .map(|line| (line.to_string(), parse_line(line)))
+                .collect(),
+        }
+    }
+
+    /// The exponents to test, in file order and without duplicates.
+    pub fn exponents(&self) -> Vec<u64> {
+        let mut exponents: Vec<u64> = Vec::new();
+        for (_, line) in &self.lines {
+            if let Line::Assignment(assignment) = line {
+                if !exponents.contains(&assignment.exponent) {
+                    exponents.push(assignment.exponent);
+                }
+            }
+        }
+        exponents
+    }
+
+    /// The assignment ID of the first assignment for `exponent` that has
+    /// one.
+    pub fn assignment_id(&self, exponent: u64) -> Option<&str> {
+        self.lines.iter().find_map(|(_, line)| match line {
+            Line::Assignment(assignment) if assignment.exponent == exponent => {
+                assignment.assignment_id.as_deref()
+            }
+            _ => None,
+        })
+    }
+
+    /// Lines that will be left alone, with their 1-based line numbers and
+    /// parsed form, so callers can warn about them.
+    pub fn skipped_lines(&self) -> impl Iterator<Item = (usize, &str, &Line)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, line))| matches!(line, Line::Unsupported | Line::Malformed(_)))
+            .map(|(i, (text, line))| (i + 1, text.as_str(), line))
+    }
+
+    /// Removes every assignment for `exponent` and rewrites the file.
+    pub fn complete(&mut self, exponent: u64) -> io::Result<()> {
+        self.lines.retain(|(_, line)| match line {
+            Line::Assignment(assignment) => assignment.exponent != exponent,
+            _ => true,
+        });
+        self.save()
+    }
+
+    /// Writes the file via a temporary file and a rename, so an interrupted
+    /// write never leaves it truncated.
+    fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for (line, _) in &self.lines {
+            text.push_str(line);
+            text.push('\n');
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment(line: &str) -> Assignment {
+        match parse_line(line) {
+            Line::Assignment(assignment) => assignment,
+            other => panic!("{:?} parsed as {:?}", line, other),
+        }
+    }
+
+    #[test]
+    fn parses_bare_exponent() {
+        let a = assignment("Test=44497");
+        assert_eq!(a.exponent, 44497);
+        assert_eq!(a.assignment_id, None);
+        assert_eq!(a.tf_bits, None);
+        assert!(!a.double_check);
+    }
+
+    #[test]
+    fn parses_full_prime95_lines() {
+        let a = assignment("Test=N/A,44497,75,1");
+        assert_eq!(a.exponent, 44497);
+        assert_eq!(a.assignment_id, None);
+        assert_eq!(a.tf_bits, Some(75));
+        assert_eq!(a.p1_done, Some(true));
+
+        let a = assignment("DoubleCheck=0123456789ABCDEF0123456789abcdef,86243,68,0");
+        assert!(a.double_check);
+        assert_eq!(
+            a.assignment_id.as_deref(),
+            Some("0123456789ABCDEF0123456789abcdef")
+        );
+        assert_eq!(a.exponent, 86243);
+        assert_eq!(a.p1_done, Some(false));
+
+        assert_eq!(assignment("test = N/A, 21701").exponent, 21701);
+    }
+
+    #[test]
+    fn classifies_other_lines() {
+        assert_eq!(parse_line(""), Line::Comment);
+        assert_eq!(parse_line("; a comment"), Line::Comment);
+        assert_eq!(parse_line("[Worker #1]"), Line::Comment);
+        assert_eq!(
+            parse_line("PRP=N/A,1,2,82589933,-1,76,0"),
+            Line::Unsupported
+        );
+        assert_eq!(parse_line("Factor=N/A,1277,60,61"), Line::Unsupported);
+        assert_eq!(parse_line("garbage"), Line::Unsupported);
+    }
+
+    #[test]
+    fn rejects_malformed_assignments() {
+        for line in [
+            "Test=",
+            "Test=abc",
+            "Test=N/A",
+            "Test=N/A,44497,x,1",
+            "Test=N/A,44497,75,2",
+            "Test=N/A,44497,75,1,9",
+            "Test=not-an-id,44497",
+            "Test=N/A,44498",
+        ] {
+            assert!(
+                matches!(parse_line(line), Line::Malformed(_)),
+                "{:?} should be malformed",
+                line
+            );
+        }
+        assert_eq!(
+            parse_line("Test=N/A,44498"),
+            Line::Malformed("rejected exponent 44498 = 2 × 22249".to_string())
+        );
+    }
+
+    #[test]
+    fn looks_up_assignment_ids() {
+        let worktodo = WorkTodo::parse(
+            Path::new("worktodo.txt"),
+            "Test=N/A,21701
+DoubleCheck=0123456789ABCDEF0123456789ABCDEF,21701
+Test=44497
+",
+        );
+        assert_eq!(
+            worktodo.assignment_id(21701),
+            Some("0123456789ABCDEF0123456789ABCDEF")
+        );
+        assert_eq!(worktodo.assignment_id(44497), None);
+        assert_eq!(worktodo.assignment_id(23209), None);
+    }
+
+    #[test]
+    fn completing_assignments_rewrites_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("worktodo.txt");
+        fs::write(
+            &path,
+            "[Worker #1]\nTest=N/A,21701,70,1\nPRP=N/A,1,2,23209,-1,70,0\nTest=44497\nTest=N/A,21701\n",
+        )
+        .unwrap();
+
+        let mut worktodo = WorkTodo::load(&path).unwrap();
+        assert_eq!(worktodo.exponents(), vec![21701, 44497]);
+        let skipped: Vec<usize> = worktodo.skipped_lines().map(|(n, _, _)| n).collect();
+        assert_eq!(skipped, vec![3]);
+
+        worktodo.complete(21701).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[Worker #1]\nPRP=N/A,1,2,23209,-1,70,0\nTest=44497\n"
+        );
+        assert_eq!(WorkTodo::load(&path).unwrap().exponents(), vec![44497]);
+    }
+}
@@ -0,0 +1,2842 @@
+//! End-to-end tests of the command-line interface and its exit statuses.
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+fn mersenne() -> Command {
+    Command::cargo_bin("Mersenne").unwrap()
+}
+
+#[test]
+fn finding_a_prime_exits_with_zero() {
+    mersenne()
+        .args(["search", "2", "31"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "M(31) is a Mersenne prime (10 digits, already known).",
+        ));
+}
+
+#[test]
+fn finding_nothing_exits_with_one() {
+    mersenne()
+        .args(["test", "11,23,29"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("M(23) has factor 47"));
+}
+
+#[test]
+fn invalid_arguments_exit_with_two() {
+    mersenne()
+        .args(["search", "100", "10"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Error:"));
+    mersenne()
+        .args(["test", "15"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("rejected exponent 15 = 3 × 5; it is not prime"));
+    mersenne().args(["--no-such-flag"]).assert().code(2);
+    mersenne()
+        .args(["test", "--threads", "0", "7"])
+        .assert()
+        .code(2);
+    mersenne().args(["search", "7"]).assert().code(2);
+    mersenne().assert().code(2);
+}
+
+#[test]
+fn help_documents_exit_statuses() {
+    mersenne()
+        .arg("--help")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("EXIT STATUS"));
+}
+
+#[test]
+fn selftest_passes() {
+    mersenne()
+        .arg("selftest")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("ok    M(4423) is prime"))
+        .stdout(predicate::str::contains(
+            "Self-test passed: all 40 exponents",
+        ));
+}
+
+#[test]
+fn worker_panic_exits_with_four() {
+    mersenne()
+        .args(["test", "7,13,17", "--debug-panic-on", "13"])
+        .assert()
+        .code(4)
+        .stdout(predicate::str::contains("Found Mersenne prime: M(17)"))
+        .stdout(predicate::str::contains("M(13) failed: its test panicked (--debug-panic-on 13)."))
+        .stdout(predicate::str::contains("Retry the failed tests with: test 13"));
+}
+
+#[test]
+fn retries_recover_failed_tests() {
+    mersenne()
+        .args(["test", "7,13", "--debug-panic-on", "13", "--retries", "1", "--json"])
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("Retrying 1 failed test(s), attempt 1 of 1."))
+        .stdout(predicate::str::contains(r#""panic":"--debug-panic-on 13""#))
+        .stdout(predicate::str::contains(r#""primes":[7,13],"#))
+        .stdout(predicate::str::contains(r#""failed":[]"#));
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c_exits_with_three() {
+    use std::process::Stdio;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("Mersenne"))
+        .args(["test", "86243", "--checkpoint-dir"])
+        .arg(dir.path())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    let killed = std::process::Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Interrupted at iteration"), "{}", stderr);
+    assert!(dir.path().join("M86243.ckpt").exists());
+}
+
+#[test]
+fn stdout_carries_only_results() {
+    let output = mersenne()
+        .args(["search", "2", "31", "--no-summary"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout
+            .lines()
+            .all(|line| line.starts_with("M(") || line.starts_with("*** Found")),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Searching for Mersenne primes"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn log_level_and_log_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("run.log");
+    mersenne()
+        .args(["test", "31", "--log-level", "warn", "--log-file"])
+        .arg(&log)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::is_empty());
+    mersenne()
+        .args(["test", "--log-level", "debug", "37", "--results"])
+        .arg(dir.path())
+        .arg("--log-file")
+        .arg(&log)
+        .assert()
+        .code(2);
+    let text = std::fs::read_to_string(&log).unwrap();
+    assert!(
+        predicate::str::is_match(r"(?m)^\d{4}-\d\d-\d\dT\d\d:\d\d:\d\d\.\d{3}Z ERROR cannot read ")
+            .unwrap()
+            .eval(&text),
+        "{}",
+        text
+    );
+    assert!(!text.contains("Testing M(31)"), "{}", text);
+    mersenne()
+        .args(["test", "31", "--verbose"])
+        .assert()
+        .stderr(predicate::str::contains("Testing M(31) = 2^31 - 1"));
+    mersenne()
+        .args(["test", "31", "--log-level", "loud"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn verbosity_levels_decide_what_is_shown() {
+    let run = |level: &[&str]| {
+        let output = mersenne()
+            .args(["search", "2", "7", "--tf-depth", "0"])
+            .args(level)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        (stdout, stderr)
+    };
+    let results = |stdout: &str| {
+        stdout.lines().filter(|line| line.starts_with("*** Found")).count()
+    };
+
+    let (stdout, stderr) = run(&["-q"]);
+    assert_eq!(results(&stdout), 4, "{}", stdout);
+    assert_eq!(stdout.lines().count(), 4, "{}", stdout);
+    assert_eq!(stderr, "");
+
+    let (stdout, stderr) = run(&[]);
+    assert_eq!(results(&stdout), 4, "{}", stdout);
+    assert!(stdout.contains("Total time taken"), "{}", stdout);
+    assert!(stderr.contains("Searching for Mersenne primes"), "{}", stderr);
+    assert!(!stderr.contains("Testing M(7)"), "{}", stderr);
+
+    for level in ["-v", "-vv"] {
+        let (stdout, stderr) = run(&[level]);
+        assert_eq!(results(&stdout), 4, "{}", stdout);
+        assert!(!stdout.contains("Testing M(7)"), "{}", stdout);
+        assert!(stderr.contains("Testing M(7) = 2^7 - 1"), "{}", stderr);
+        assert!(stderr.contains("Finished M(7) in "), "{}", stderr);
+    }
+
+    let (stdout, _) = run(&["--json", "-v"]);
+    assert!(stdout.lines().all(|line| line.starts_with('{')), "{}", stdout);
+    mersenne().args(["test", "7", "-q", "-v"]).assert().code(2);
+}
+
+#[test]
+fn runs_record_the_build_and_machine() {
+    let output = mersenne().args(["info", "--json"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let system: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(system["version"], env!("CARGO_PKG_VERSION"));
+    assert!(system["cores"].as_u64().unwrap() >= 1);
+    mersenne()
+        .arg("info")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Cores: "))
+        .stdout(predicate::str::contains("Arithmetic: "));
+
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args(["test", "31", "--json", "--results"])
+        .arg(&results)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(r#""system":{"version":"#));
+    let text = std::fs::read_to_string(&results).unwrap();
+    let header = text.lines().next().unwrap();
+    assert!(header.starts_with("# "), "{}", text);
+    assert!(
+        header.contains(" version=") && header.contains(" cores="),
+        "{}",
+        text
+    );
+    assert!(
+        text.lines().nth(1).unwrap().contains(" exponent=31 "),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn audit_log_is_verified_by_recomputing_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("audit.jsonl");
+    mersenne()
+        .args(["test", "61,521,523", "--tf-depth", "0", "--no-summary"])
+        .arg("--audit-log")
+        .arg(&log)
+        .assert()
+        .code(0);
+    let text = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(text.lines().count(), 3);
+    assert!(text.starts_with(
+        r#"{"exponent":61,"test":"LL","interval":4,"milestones":[{"iteration":0,"residue":"4"}"#
+    ));
+    mersenne()
+        .arg("audit-verify")
+        .arg(&log)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "3 test(s), 47 of 47 stretch(es) recomputed, 0 problem(s).",
+        ))
+        .stdout(predicate::str::contains("Audit log OK."));
+    mersenne()
+        .arg("audit-verify")
+        .arg(&log)
+        .args(["--spot-check", "5", "--threads", "2"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("5 of 47 stretch(es) recomputed"));
+
+    std::fs::write(
+        &log,
+        text.replacen(r#""prime":true"#, r#""prime":false"#, 1),
+    )
+    .unwrap();
+    mersenne()
+        .arg("audit-verify")
+        .arg(&log)
+        .assert()
+        .code(8)
+        .stderr(predicate::str::contains("line 1: checksum mismatch"));
+
+    mersenne()
+        .args(["test", "61", "--prp", "--audit-log"])
+        .arg(&log)
+        .assert()
+        .code(2);
+    mersenne()
+        .args(["test", "61", "--form", "wagstaff", "--audit-log"])
+        .arg(&log)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--audit-log applies to Mersenne numbers only",
+        ));
+}
+
+#[test]
+fn compare_finds_where_milestones_diverge() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    let json = dir.path().join("run.jsonl");
+    let output = mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--milestones", "4", "--json"])
+        .arg("--results")
+        .arg(&results)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    std::fs::write(&json, &output.stdout).unwrap();
+    mersenne()
+        .arg("compare")
+        .arg(&results)
+        .arg(&json)
+        .assert()
+        .code(0)
+        .stdout("M(4423): identical (4 milestone(s))\n");
+
+    // Corrupt the second milestone of a copy.
+    let text = std::fs::read_to_string(&results).unwrap();
+    let line = text.lines().find(|line| line.contains("milestones=")).unwrap();
+    let list = line.split_whitespace().find_map(|t| t.strip_prefix("milestones=")).unwrap();
+    let second = list.split(',').nth(1).unwrap();
+    let (iteration, res64) = second.split_once(':').unwrap();
+    let corrupted = dir.path().join("corrupted.txt");
+    let other = if res64 == "0123456789ABCDEF" { "FEDCBA9876543210" } else { "0123456789ABCDEF" };
+    std::fs::write(&corrupted, text.replace(second, &format!("{}:{}", iteration, other))).unwrap();
+    mersenne()
+        .arg("compare")
+        .arg(&results)
+        .arg(&corrupted)
+        .assert()
+        .code(8)
+        .stdout(format!("M(4423): diverged at iteration {} (milestone 2 of 4)\n", iteration));
+}
+
+#[test]
+fn diff_lines_up_runs_in_any_of_the_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    let csv = dir.path().join("run.csv");
+    let json = dir.path().join("run.jsonl");
+    let output = mersenne()
+        .args(["search", "20", "90", "--tf-depth", "0", "--json"])
+        .arg("--results")
+        .arg(&results)
+        .arg("--csv")
+        .arg(&csv)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    std::fs::write(&json, &output.stdout).unwrap();
+    for (a, b) in [(&results, &csv), (&csv, &json), (&json, &results)] {
+        mersenne()
+            .arg("diff")
+            .arg(a)
+            .arg(b)
+            .assert()
+            .code(0)
+            .stdout(predicate::str::contains("No results differ."));
+    }
+
+    // Say M(89) is composite, drop M(61) and corrupt the Res64 of M(29).
+    let text = std::fs::read_to_string(&results).unwrap();
+    let res64 = text
+        .lines()
+        .find(|line| line.contains("exponent=29 "))
+        .and_then(|line| line.split_whitespace().find_map(|t| t.strip_prefix("res64=")))
+        .unwrap();
+    let other = if res64 == "0123456789ABCDEF" { "FEDCBA9876543210" } else { "0123456789ABCDEF" };
+    let changed: String = text
+        .replace(res64, other)
+        .lines()
+        .filter(|line| !line.contains("exponent=61 "))
+        .map(|line| match line.contains("exponent=89 ") {
+            true => line.replace("result=prime", "result=composite") + "\n",
+            false => line.to_string() + "\n",
+        })
+        .collect();
+    let changed_path = dir.path().join("changed.txt");
+    std::fs::write(&changed_path, changed).unwrap();
+    mersenne()
+        .arg("diff")
+        .arg(&csv)
+        .arg(&changed_path)
+        .assert()
+        .code(8)
+        .stdout(predicate::str::contains("a: ").and(predicate::str::contains("(CSV, ")))
+        .stdout(predicate::str::is_match("M\\(89\\) +prime +composite +DISAGREEMENT").unwrap())
+        .stdout(predicate::str::contains(format!("{}  {}  Res64 mismatch", res64, other)))
+        .stdout(predicate::str::is_match("M\\(61\\) +prime +- +only in a").unwrap())
+        .stdout(predicate::str::contains("SERIOUS: 1 number(s) found prime"));
+    let output =
+        mersenne().arg("diff").arg(&json).arg(&changed_path).arg("--json").output().unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    let line: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(line["a"]["format"], "json");
+    assert_eq!(line["b"]["format"], "results");
+    assert_eq!(line["only_in_a"][0]["exponent"], 61);
+    assert_eq!(line["disagreements"][0]["exponent"], 89);
+    assert_eq!(line["disagreements"][0]["prime_in_a"], true);
+    assert_eq!(line["res64_mismatches"][0]["b"], other);
+}
+
+#[test]
+fn run_ids_tie_together_what_a_run_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = |name: &str| dir.path().join(name);
+    let (results, csv, db) = (path("results.txt"), path("run.csv"), path("results.db"));
+    let (events, status) = (path("events.jsonl"), path("status.json"));
+    mersenne()
+        .args(["search", "20", "40", "--run-id", "first", "--json", "--results"])
+        .arg(&results)
+        .arg("--csv")
+        .arg(&csv)
+        .arg("--db")
+        .arg(&db)
+        .arg("--events")
+        .arg(&events)
+        .arg("--status-file")
+        .arg(&status)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("Run first."))
+        .stdout(predicate::str::contains(r#""run":"first""#));
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert!(text.lines().filter(|line| !line.starts_with('#')).all(|l| l.contains(" run=first")));
+    let rows = std::fs::read_to_string(&csv).unwrap();
+    assert!(rows.lines().next().unwrap().ends_with(",work_units,run_id,res2048"));
+    assert!(rows.lines().skip(1).all(|line| line.ends_with(",first,")));
+    let events = std::fs::read_to_string(&events).unwrap();
+    assert!(events.lines().all(|line| line.contains(r#""run":"first""#)));
+    let status: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&status).unwrap()).unwrap();
+    assert_eq!(status["run"], "first");
+
+    // A run without --run-id gets one of its own.
+    let output = mersenne()
+        .args(["search", "40", "62", "--results"])
+        .arg(&results)
+        .arg("--db")
+        .arg(&db)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let second = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("Run ")?.strip_suffix('.'))
+        .unwrap()
+        .to_string();
+    assert!(predicate::str::is_match("^\\d{8}T\\d{6}Z-[0-9a-f]{6}$").unwrap().eval(&second));
+
+    mersenne()
+        .args(["report", "--run", "first"])
+        .arg(&db)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Runs: 1 (0 unfinished)"))
+        .stdout(predicate::str::contains("Results: 4: 1 prime, 3 factored, 0 composite"));
+    mersenne()
+        .args(["report", "--run", "third"])
+        .arg(&db)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("has no run third"));
+    mersenne()
+        .arg("diff")
+        .arg(&results)
+        .arg(&results)
+        .args(["--run-a", "first", "--run-b", &second])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(", run first (a results file, 4 results)"))
+        .stdout(predicate::str::contains("0 number(s) in both."));
+    mersenne()
+        .args(["search", "20", "40", "--run-id", "two words"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("invalid run ID"));
+}
+
+#[test]
+fn compute_time_is_reported_beside_the_time_on_the_clock() {
+    let output = mersenne()
+        .args(["test", "3217,4423", "--tf-depth", "0", "--json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let reports: Vec<serde_json::Value> = stdout
+        .lines()
+        .filter(|line| line.starts_with(r#"{"exponent""#))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(reports.len(), 2, "{}", stdout);
+    for report in &reports {
+        let compute = report["compute_seconds"].as_f64().unwrap();
+        let seconds = report["seconds"].as_f64().unwrap();
+        assert!(compute > 0.0 && compute <= seconds + 0.01, "{}", report);
+    }
+
+    mersenne()
+        .args(["test", "3217,4423", "--tf-depth", "0"])
+        .assert()
+        .code(0)
+        .stdout(
+            predicate::str::is_match(r"\nCompute time: .+ of the .+ on the clock \(\d+%\)\n")
+                .unwrap(),
+        );
+}
+
+#[test]
+fn verify_sample_reruns_a_reproducible_sample() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    let ledger = dir.path().join("ledger.jsonl");
+    mersenne()
+        .args(["search", "2", "400", "--tf-depth", "0", "--no-summary"])
+        .arg("--results")
+        .arg(&results)
+        .arg("--ledger")
+        .arg(&ledger)
+        .assert()
+        .code(0);
+    let sample = |file: &std::path::Path, fraction: &str, seed: &str| {
+        mersenne()
+            .arg("verify-sample")
+            .arg("--results")
+            .arg(file)
+            .args(["--fraction", fraction, "--seed", seed])
+            .current_dir(dir.path())
+            .output()
+            .unwrap()
+    };
+    let first = sample(&results, "0.1", "42");
+    assert_eq!(first.status.code(), Some(0));
+    let stdout = String::from_utf8(first.stdout.clone()).unwrap();
+    assert!(stdout.starts_with("Re-running 7 of 66 composite(s) with seed 42.\n"), "{}", stdout);
+    assert!(stdout.contains("7 re-run(s): 7 agree, 0 mismatch(es)\n"), "{}", stdout);
+    assert!(stdout.contains("Error rate: 0.00% (95% interval 0.00% to "), "{}", stdout);
+    // The same seed picks the same exponents, from the ledger too.
+    assert_eq!(sample(&ledger, "0.1", "42").stdout, first.stdout);
+    assert_ne!(sample(&results, "0.1", "7").stdout, first.stdout);
+    assert!(!dir.path().join("mismatches.txt").exists());
+
+    // Corrupt one Res64, and only it disagrees.
+    let text = std::fs::read_to_string(&results).unwrap();
+    let line = text.lines().find(|line| line.contains(" exponent=101 ")).unwrap();
+    let res64 = line.split_whitespace().find_map(|t| t.strip_prefix("res64=")).unwrap();
+    let other = if res64 == "0123456789ABCDEF" { "FEDCBA9876543210" } else { "0123456789ABCDEF" };
+    let corrupted = dir.path().join("corrupted.txt");
+    std::fs::write(&corrupted, text.replace(res64, other)).unwrap();
+    let output = sample(&corrupted, "1", "42");
+    assert_eq!(output.status.code(), Some(8));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mismatch = format!("M(101): LL MISMATCH: recorded Res64 {}, found {}\n", other, res64);
+    assert!(stdout.contains(&mismatch), "{}", stdout);
+    assert!(stdout.contains("66 re-run(s): 65 agree, 1 mismatch(es)\n"), "{}", stdout);
+    assert!(stdout.contains("Test them again in full with: test $(cat mismatches.txt)\n"));
+    let mismatches = std::fs::read_to_string(dir.path().join("mismatches.txt")).unwrap();
+    assert_eq!(mismatches, "101\n");
+
+    mersenne()
+        .arg("verify-sample")
+        .arg("--results")
+        .arg(&results)
+        .args(["--fraction", "0"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn csv_gets_a_row_per_exponent_and_one_header() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv = dir.path().join("runs.csv");
+    let header = "exponent,candidate_source,stage_eliminated,is_prime,res64,iterations,\
+                  elapsed_seconds,iters_per_second,started_at,finished_at,range,work_units,\
+                  run_id,res2048";
+    mersenne()
+        .args(["test", "29,31", "--tf-depth", "0", "--no-summary"])
+        .arg("--csv")
+        .arg(&csv)
+        .assert()
+        .code(0);
+    mersenne()
+        .args(["test", "37", "--no-summary"])
+        .arg("--csv-append")
+        .arg(&csv)
+        .assert()
+        .code(1);
+    let text = std::fs::read_to_string(&csv).unwrap();
+    let rows: Vec<Vec<&str>> = text.lines().map(|line| line.split(',').collect()).collect();
+    assert_eq!(text.lines().next(), Some(header));
+    assert_eq!(rows.len(), 4);
+    assert!(rows.iter().all(|row| row.len() == 14));
+    assert_eq!(rows[1][..6], ["29", "list", "LL", "false", "000000001B57CB0B", "27"]);
+    assert_eq!(rows[2][..6], ["31", "list", "", "true", "", "29"]);
+    assert_eq!(rows[3][..6], ["37", "list", "TF", "false", "", ""]);
+    assert!(rows[1][11].parse::<f64>().unwrap() > 0.0);
+    assert_eq!(rows[3][11], "0");
+
+    // --csv starts the file afresh.
+    mersenne()
+        .args(["test", "31", "--no-summary"])
+        .arg("--csv")
+        .arg(&csv)
+        .assert()
+        .code(0);
+    assert_eq!(std::fs::read_to_string(&csv).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn res2048_follows_the_res64_when_asked_for() {
+    let dir = tempfile::tempdir().unwrap();
+    let (results, csv) = (dir.path().join("results.txt"), dir.path().join("run.csv"));
+    // M(11) = 2047 is small enough that the low 2048 bits of its final
+    // residue, S(9) = 1736, are the whole of it.
+    let res2048 = format!("{}6c8", "0".repeat(509));
+    mersenne()
+        .args(["test", "11,13", "--tf-depth", "0", "--res2048", "--json", "--no-summary"])
+        .arg("--results")
+        .arg(&results)
+        .arg("--csv")
+        .arg(&csv)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(format!(
+            r#""res64":"00000000000006C8","res2048":"{}""#,
+            res2048
+        )))
+        // M(13) is prime, so its residue is zero and left out like its Res64.
+        .stdout(predicate::str::contains(r#""exponent":13,"#))
+        .stdout(predicate::str::is_match(r#""exponent":13,[^\n]*res2048"#).unwrap().not());
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert!(text.contains(&format!(" res64=00000000000006C8 res2048={} ", res2048)));
+    let rows = std::fs::read_to_string(&csv).unwrap();
+    assert!(rows.lines().any(|line| line.starts_with("11,") && line.ends_with(&res2048)));
+
+    // PRP tests have one too, and runs without --res2048 keep lines short.
+    mersenne()
+        .args(["test", "11", "--tf-depth", "0", "--prp", "--res2048", "--json", "--no-summary"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(format!(r#""res2048":"{}3a1""#, "0".repeat(509))));
+    mersenne()
+        .args(["test", "11", "--tf-depth", "0", "--json", "--no-summary"])
+        .assert()
+        .stdout(predicate::str::contains("res2048").not());
+    mersenne()
+        .args(["test", "11", "--form", "riesel", "--res2048"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn events_are_json_lines_and_take_stdout_with_a_dash() {
+    let dir = tempfile::tempdir().unwrap();
+    let events = |stdout: &[u8]| -> Vec<serde_json::Value> {
+        String::from_utf8(stdout.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    };
+    let output = mersenne()
+        .args(["test", "23,127", "--tf-depth", "0", "--events", "-", "--no-summary"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Found Mersenne prime: M(127)"), "{}", stderr);
+    let lines = events(&output.stdout);
+    let names: Vec<&str> = lines.iter().map(|line| line["event"].as_str().unwrap()).collect();
+    assert_eq!(names[0], "run_started");
+    assert_eq!(names.last(), Some(&"run_finished"));
+    assert!(lines.iter().all(|line| line["timestamp"].as_str().unwrap().ends_with('Z')));
+    let finished: Vec<&serde_json::Value> =
+        lines.iter().filter(|line| line["event"] == "test_finished").collect();
+    assert_eq!(finished.len(), 2);
+    assert_eq!(finished[0]["exponent"], 23);
+    assert_eq!(finished[0]["stage"], "LL");
+    let prime = lines.iter().find(|line| line["event"] == "prime_found").unwrap();
+    assert_eq!((&prime["exponent"], &prime["digits"]), (&127.into(), &39.into()));
+    assert_eq!(lines.last().unwrap()["primes"], 1);
+    assert!(names.contains(&"test_started") && names.contains(&"test_progress"));
+
+    // A file is appended to, and gets the checkpoints too.
+    let path = dir.path().join("events.jsonl");
+    for _ in 0..2 {
+        mersenne()
+            .args(["test", "4423", "--checkpoint-interval", "1000", "--no-summary"])
+            .arg("--checkpoint-dir")
+            .arg(dir.path())
+            .arg("--events")
+            .arg(&path)
+            .assert()
+            .code(0)
+            .stdout(predicate::str::contains("Found Mersenne prime: M(4423)"));
+    }
+    let lines = events(&std::fs::read(&path).unwrap());
+    let count = |name: &str| lines.iter().filter(|line| line["event"] == name).count();
+    assert_eq!(count("run_started"), 2);
+    assert_eq!(count("checkpoint_written"), 8);
+}
+
+#[test]
+fn checkpoints_and_progress_are_paced_by_time() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.jsonl");
+    mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--no-summary"])
+        .args(["--checkpoint-every", "1h", "--progress-every", "1m"])
+        .arg("--checkpoint-dir")
+        .arg(dir.path())
+        .arg("--events")
+        .arg(&path)
+        .assert()
+        .code(0);
+    // The test takes well under a minute: no checkpoints, and only the last
+    // iteration's progress.
+    let events = std::fs::read_to_string(&path).unwrap();
+    assert!(!events.contains("checkpoint_written"));
+    assert_eq!(events.matches("test_progress").count(), 1);
+    for bad in [["--checkpoint-every", "0"], ["--progress-every", "10x"]] {
+        mersenne().args(["test", "31"]).args(bad).assert().code(2);
+    }
+}
+
+#[test]
+fn count_takes_the_next_exponents_not_yet_done() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args(["search", "80", "--count", "3", "--tf-depth", "0", "--no-summary"])
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("M(83)"))
+        .stdout(predicate::str::contains("M(97)"));
+    // M(101) has the factor 7432339208719, and M(107) is prime.
+    let args = ["search", "80", "--count", "3", "--no-summary", "--results"];
+    mersenne()
+        .args(args)
+        .arg(&results)
+        .arg("--dry-run")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Plan for the next 3 exponent(s) to test from M(80), up to M(107):",
+        ))
+        .stdout(predicate::str::contains("skipped, already in the results file or database: 3"));
+    mersenne()
+        .args(args)
+        .arg(&results)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("M(101)"))
+        .stdout(predicate::str::contains("M(107)"))
+        .stdout(predicate::str::contains("M(109)").not());
+}
+
+#[test]
+fn ranges_are_searched_together_and_summarized_apart() {
+    let output = mersenne()
+        .args(["search", "--ranges", "130-140,20-40", "--tf-depth", "0", "--json"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert!(lines[0].starts_with(r#"{"exponent":23,"#));
+    assert!(lines[0].contains(r#""range":"20-40","run":"#));
+    assert!(lines[6].contains(r#""range":"130-140","run":"#));
+    assert!(lines[7].contains(
+        r#""ranges":[{"start_exponent":20,"end_exponent":40,"candidates":null,"tested":4,"primes":[31],"#
+    ));
+    assert!(lines[7].contains(
+        r#"{"start_exponent":130,"end_exponent":140,"candidates":null,"tested":3,"primes":[],"#
+    ));
+
+    mersenne()
+        .args(["search", "--ranges", "130-140,20-40", "--tf-depth", "0"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "  20-40: 4 tested, 1 prime(s), 0 factored, 3 composite (M(31))",
+        ))
+        .stdout(predicate::str::contains(
+            "  130-140: 3 tested, 0 prime(s), 0 factored, 3 composite\n",
+        ));
+    mersenne()
+        .args(["search", "--ranges", "20-40,40-50"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("the ranges 20-40 and 40-50 overlap"));
+    mersenne()
+        .args(["search", "--ranges", "40-20"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("the range 40-20 is reversed"));
+}
+
+#[test]
+fn prp_proofs_are_written_and_verified() {
+    let dir = tempfile::tempdir().unwrap();
+    let proofs = dir.path().join("proofs");
+    mersenne()
+        .args([
+            "test",
+            "1277,1279",
+            "--prp",
+            "--tf-depth",
+            "0",
+            "--no-summary",
+        ])
+        .arg("--proof-dir")
+        .arg(&proofs)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("Wrote the proof of M(1279)"));
+    mersenne()
+        .arg("verify-proof")
+        .arg(proofs.join("M1279.proof"))
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Power: 8"))
+        .stdout(predicate::str::contains("Iterations proved: 1024 of 1279"))
+        .stdout(predicate::str::contains(
+            "Result: probable prime, Res64: 0x0000000000000009",
+        ))
+        .stdout(predicate::str::contains("Proof OK"));
+    mersenne()
+        .arg("verify-proof")
+        .arg(proofs.join("M1277.proof"))
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Result: composite, Res64: 0x42D83C4FEBE2BC55",
+        ));
+
+    let path = proofs.join("M1279.proof");
+    let mut bytes = std::fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&path, &bytes).unwrap();
+    mersenne()
+        .arg("verify-proof")
+        .arg(&path)
+        .assert()
+        .code(8)
+        .stdout(predicate::str::contains("Proof FAILED"));
+    std::fs::write(&path, &bytes[..last]).unwrap();
+    mersenne()
+        .arg("verify-proof")
+        .arg(&path)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("not a valid proof file"));
+
+    mersenne()
+        .args(["test", "127", "--form", "wagstaff", "--proof-power", "3"])
+        .arg("--proof-dir")
+        .arg(&proofs)
+        .assert()
+        .code(0);
+    mersenne()
+        .arg("verify-proof")
+        .arg(proofs.join("W127.proof"))
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Number: W(127)"))
+        .stdout(predicate::str::contains("Power: 3"));
+
+    mersenne()
+        .args(["test", "127", "--proof-dir"])
+        .arg(&proofs)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("needs --prp"));
+    mersenne()
+        .args(["test", "127", "--prp", "--proof-power", "13", "--proof-dir"])
+        .arg(&proofs)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn final_residues_are_saved_and_verified() {
+    let dir = tempfile::tempdir().unwrap();
+    let residues = dir.path().join("residues");
+    let checkpoints = dir.path().join("checkpoints");
+    mersenne()
+        .args(["test", "10007", "--threads", "1", "--tf-depth", "0", "--time-limit", "1s"])
+        .arg("--checkpoint-dir")
+        .arg(&checkpoints)
+        .assert()
+        .code(6);
+    let early = dir.path().join("early.ckpt");
+    std::fs::copy(checkpoints.join("M10007.ckpt"), &early).unwrap();
+    mersenne()
+        .args(["test", "10007", "--threads", "1", "--tf-depth", "0", "--no-summary"])
+        .arg("--checkpoint-dir")
+        .arg(&checkpoints)
+        .arg("--save-residue")
+        .arg(&residues)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("Res64: 0x2CC5456D685892E3"))
+        .stderr(predicate::str::contains("Wrote the residue of M(10007)"));
+    let path = residues.join("M10007.residue");
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 56 + 157 * 8);
+    mersenne()
+        .arg("verify-residue")
+        .arg(&path)
+        .arg("--from")
+        .arg(&early)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Test: LL\nIterations: 10005\nShift: 0\n"))
+        .stdout(predicate::str::contains("Res64: 2CC5456D685892E3\nRes2048: 6dafad58"))
+        .stdout(predicate::str::contains("to 10005 recomputed from"))
+        .stdout(predicate::str::contains("the residue matches."));
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[60] ^= 1;
+    std::fs::write(&path, &bytes).unwrap();
+    mersenne()
+        .arg("verify-residue")
+        .arg(&path)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("checksum mismatch"));
+
+    mersenne()
+        .args(["test", "127", "--prp", "--save-residue"])
+        .arg(&residues)
+        .assert()
+        .code(0);
+    mersenne()
+        .arg("verify-residue")
+        .arg(residues.join("M127.residue"))
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Test: PRP\nIterations: 127\n"))
+        .stdout(predicate::str::contains("Res64: 0000000000000009"));
+    mersenne()
+        .args(["test", "5", "--form", "riesel", "--k", "3", "--save-residue"])
+        .arg(&residues)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with --form riesel"));
+}
+
+#[test]
+fn stages_get_their_own_threads() {
+    let output = mersenne()
+        .args([
+            "search",
+            "2",
+            "200",
+            "--tf-threads",
+            "2",
+            "--ll-threads",
+            "1",
+        ])
+        .args(["--p1-b1", "100", "--json", "--ordered"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let lines: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let (summary, reports) = lines.split_last().unwrap();
+    // Every exponent comes out once, in order, whichever stage finished it.
+    let exponents: Vec<u64> = reports
+        .iter()
+        .map(|report| report["exponent"].as_u64().unwrap())
+        .collect();
+    assert_eq!(exponents.len(), 46);
+    assert!(exponents.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(
+        summary["summary"]["primes"],
+        serde_json::json!([2, 3, 5, 7, 13, 17, 19, 31, 61, 89, 107, 127])
+    );
+
+    let stages = summary["stages"].as_array().unwrap();
+    let names: Vec<&str> = stages
+        .iter()
+        .map(|stage| stage["stage"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["trial factoring", "P-1", "primality test"]);
+    assert_eq!(stages[0]["threads"], 2);
+    assert_eq!(stages[0]["exponents"], 46);
+    assert_eq!(stages[2]["threads"], 1);
+    // Each stage gets what the one before did not eliminate.
+    for pair in stages.windows(2) {
+        let passed =
+            pair[0]["exponents"].as_u64().unwrap() - pair[0]["eliminated"].as_u64().unwrap();
+        assert_eq!(pair[1]["exponents"].as_u64().unwrap(), passed);
+    }
+
+    mersenne()
+        .args(["search", "2", "31", "--tf-threads", "2"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Stages:"))
+        .stdout(
+            predicate::str::is_match(r"(?m)^  trial factoring   2 thread\(s\) +11 exponent\(s\)")
+                .unwrap(),
+        );
+    mersenne()
+        .args(["search", "2", "31", "--tf-threads", "0"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn summary_lists_the_slowest_exponents_and_a_histogram() {
+    let output = mersenne()
+        .args(["search", "2", "200", "--summary-top", "3"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let slowest: Vec<&str> = stdout
+        .lines()
+        .skip_while(|line| *line != "Slowest exponents:")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .collect();
+    assert_eq!(slowest.len(), 3, "{}", stdout);
+    let seconds: Vec<f64> = slowest
+        .iter()
+        .map(|line| line.split_whitespace().nth(1).unwrap().parse().unwrap())
+        .collect();
+    assert!(seconds.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", seconds);
+
+    // Every exponent that got past the candidate filter is in a bucket.
+    let counts: u64 = stdout
+        .lines()
+        .skip_while(|line| *line != "Time per exponent:")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .map(|line| line.split_whitespace().last().unwrap().parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(counts, 46, "{}", stdout);
+    let bucket = predicate::str::is_match(r"(?m)^ +0-1ms  #+ +\d+$").unwrap();
+    assert!(bucket.eval(&stdout), "{}", stdout);
+    let share = predicate::str::is_match(r"thread-seconds busy \( *\d+%\)").unwrap();
+    assert!(share.eval(&stdout), "{}", stdout);
+
+    // With one exponent, or --summary-top 0, there is no list.
+    mersenne()
+        .args(["test", "127"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Slowest exponents:").not())
+        .stdout(predicate::str::contains("Time per exponent:").not());
+    mersenne()
+        .args(["search", "2", "200", "--summary-top", "0"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Slowest exponents:").not());
+}
+
+#[test]
+fn shift_is_applied_and_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args([
+            "test",
+            "89,101",
+            "--shift",
+            "17",
+            "--tf-depth",
+            "0",
+            "--no-summary",
+        ])
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "*** Found Mersenne prime: M(89) (LL, shift 17)",
+        ))
+        .stdout(predicate::str::contains(
+            "M(101) is composite (LL, shift 17). Res64: 0xD0DD748DD7817436",
+        ));
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert!(text.contains("exponent=101 result=composite test=LL res64=D0DD748DD7817436 shift=17 "));
+
+    mersenne()
+        .args([
+            "test",
+            "101",
+            "--shift",
+            "33",
+            "--double-check",
+            "--tf-depth",
+            "0",
+        ])
+        .assert()
+        .stdout(predicate::str::contains(
+            "M(101) double-check: MATCH (shift 33)",
+        ));
+    mersenne()
+        .args([
+            "test",
+            "101",
+            "--shift",
+            "random",
+            "--tf-depth",
+            "0",
+            "--json",
+        ])
+        .assert()
+        .stdout(predicate::str::contains(r#""shift":"#))
+        .stdout(predicate::str::contains(r#""shift":null"#).not());
+    mersenne()
+        .args(["test", "101", "--shift", "0"])
+        .assert()
+        .code(2);
+    mersenne()
+        .args(["test", "101", "--shift", "3", "--prp"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn time_limit_stops_with_a_checkpoint_and_exits_with_six() {
+    let dir = tempfile::tempdir().unwrap();
+    mersenne()
+        .args(["test", "44497,86243", "--threads", "1", "--tf-depth", "0"])
+        .args(["--time-limit", "1s", "--checkpoint-dir"])
+        .arg(dir.path())
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("Time limit of 1.0s reached"))
+        .stderr(predicate::str::contains(
+            "Stopped at the time limit with 0 of 2 exponents done",
+        ));
+    let checkpoint = dir.path().join("M44497.ckpt");
+    assert!(!dir.path().join("M86243.ckpt").exists());
+    mersenne()
+        .arg("checkpoint-info")
+        .arg(&checkpoint)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Exponent: 44497 (M(44497))"))
+        .stdout(predicate::str::contains(" of 44495 ("))
+        .stdout(predicate::str::contains("Format version: 4"))
+        .stdout(predicate::str::contains("Written by: Mersenne "))
+        .stdout(predicate::str::is_match("\nRun: \\d{8}T\\d{6}Z-[0-9a-f]{6}\n").unwrap())
+        .stdout(predicate::str::contains("Test: LL\nShift: 0 bits\nBackend: "))
+        .stdout(predicate::str::contains("Checksum: OK"));
+
+    let mut bytes = std::fs::read(&checkpoint).unwrap();
+    bytes[100] ^= 0x10;
+    std::fs::write(&checkpoint, &bytes).unwrap();
+    mersenne()
+        .arg("checkpoint-info")
+        .arg(&checkpoint)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("checksum mismatch"));
+    mersenne()
+        .args(["test", "44497", "--threads", "1", "--tf-depth", "0"])
+        .args(["--time-limit", "1s", "--checkpoint-dir"])
+        .arg(dir.path())
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains(
+            "ignoring checkpoint for M(44497) (checksum mismatch); restarting the test.",
+        ));
+
+    for bad in ["8x", "h", "10m5", "0"] {
+        mersenne()
+            .args(["test", "31", "--time-limit", bad])
+            .assert()
+            .code(2);
+    }
+}
+
+#[test]
+fn checkpoints_of_another_shift_need_force_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let run = |shift: &[&str]| {
+        let mut command = mersenne();
+        command
+            .args(["test", "44497", "--tf-depth", "0", "--time-limit", "1s"])
+            .args(shift)
+            .arg("--checkpoint-dir")
+            .arg(dir.path());
+        command
+    };
+    run(&["--shift", "5"]).assert().code(6);
+    let checkpoint = dir.path().join("M44497.ckpt");
+    mersenne()
+        .arg("checkpoint-info")
+        .arg(&checkpoint)
+        .assert()
+        .stdout(predicate::str::contains("Shift: 5 bits"));
+
+    // Resuming it with no shift, or a random one, would give a wrong
+    // result. The same shift resumes it.
+    run(&[]).assert().code(2).stderr(predicate::str::contains(format!(
+        "cannot resume M(44497) from {}: its residue is shifted by 5 bits, not 0; use \
+         --force-restart to delete it and start the test over.",
+        checkpoint.display()
+    )));
+    run(&["--shift", "5"])
+        .arg("-v")
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("Resuming M(44497) from iteration"));
+    run(&["--shift", "random"])
+        .arg("-v")
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("Resuming M(44497) from iteration"));
+    run(&["--force-restart"])
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("its residue is shifted by 5 bits, not 0); the test"))
+        .stderr(predicate::str::contains("Resuming").not());
+    mersenne()
+        .arg("checkpoint-info")
+        .arg(&checkpoint)
+        .assert()
+        .stdout(predicate::str::contains("Shift: 0 bits"));
+}
+
+#[test]
+fn summary_table_lists_every_exponent() {
+    mersenne()
+        .args(["test", "23", "31"])
+        .assert()
+        .stdout(predicate::str::is_match(r"(?m)^\s+23  factored\s+TF ").unwrap())
+        .stdout(predicate::str::is_match(r"(?m)^\s+31  prime\s+LL ").unwrap());
+    mersenne()
+        .args(["test", "23,31", "--no-summary"])
+        .assert()
+        .stdout(predicate::str::contains("Total time taken").not())
+        .stdout(predicate::str::contains("M(31) (LL)"));
+}
+
+#[test]
+fn candidates_are_counted_beside_the_tests() {
+    mersenne()
+        .args(["search", "2", "31"])
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("prime exponent(s)").not())
+        .stdout(predicate::str::contains("Candidates found: 11\n"))
+        .stdout(predicate::str::contains("ruled out by the candidate filter: 19\n"));
+    mersenne()
+        .args(["search", "2", "31", "--json"])
+        .assert()
+        .stdout(predicate::str::contains(r#""candidates":11,"#));
+}
+
+#[test]
+fn saves_and_prints_decimal_expansions() {
+    let dir = tempfile::tempdir().unwrap();
+    mersenne()
+        .args(["test", "127,521", "--print-number", "--save-number"])
+        .arg(dir.path())
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "M(127) =\n170141183460469231731687303715884105727\n",
+        ));
+    let saved = std::fs::read_to_string(dir.path().join("M521.txt")).unwrap();
+    let lines: Vec<&str> = saved.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].len(), 80);
+    assert_eq!(lines.concat().len(), 157);
+    assert!(saved.ends_with("5057151\n"));
+}
+
+#[test]
+fn reports_perfect_numbers() {
+    let dir = tempfile::tempdir().unwrap();
+    mersenne()
+        .args(["test", "7", "--perfect", "--save-number"])
+        .arg(dir.path())
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Perfect number from M(7): 2^6 * (2^7 - 1), 4 digits.",
+        ));
+    let saved = std::fs::read_to_string(dir.path().join("M7.perfect.txt")).unwrap();
+    assert_eq!(saved, "8128\n");
+}
+
+#[test]
+fn bench_reports_each_exponent() {
+    mersenne()
+        .args([
+            "bench",
+            "--exponents",
+            "127,1279",
+            "--iterations",
+            "50",
+            "--threads",
+            "2",
+        ])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::is_match(r"(?m)^\s+127\s+50\s+[\d.]+ it/s").unwrap())
+        .stdout(predicate::str::is_match(r"(?m)^\s+1279\s+50\s+[\d.]+ it/s").unwrap());
+    mersenne()
+        .args(["bench", "--iterations", "0"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn skip_known_leaves_out_known_primes() {
+    mersenne()
+        .args(["search", "2", "130", "--skip-known"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Skipping 12 known Mersenne prime exponent(s) in range.",
+        ))
+        .stdout(predicate::str::contains(
+            "Known Mersenne primes skipped: 12",
+        ))
+        .stdout(predicate::str::contains("M(31)").not());
+}
+
+#[test]
+fn exponent_filter_keeps_exponents_of_a_special_shape() {
+    mersenne()
+        .args(["search", "2", "200", "--exponent-filter", "sophie-germain", "--dry-run"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "  skipped, not a Sophie Germain prime (--exponent-filter sophie-germain): 31\n\
+             To test: 15\n",
+        ));
+    // The filter counts only what --skip-known leaves.
+    mersenne()
+        .args(["search", "2", "200", "--exponent-filter", "twin", "--skip-known"])
+        .args(["--dry-run", "--json"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(r#""known_primes":12,"untestable":0,"filtered":14"#))
+        .stdout(predicate::str::contains(r#""to_test":20,"#));
+    mersenne()
+        .args(["search", "90", "96", "--exponent-filter", "safe"])
+        .assert()
+        .code(9)
+        .stderr(predicate::str::contains("that pass --exponent-filter safe — nothing to do."));
+}
+
+#[test]
+fn known_factors_leave_out_exponents_and_are_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let factors = dir.path().join("factors.csv");
+    let results = dir.path().join("results.txt");
+    std::fs::write(&factors, "exponent,factor\n11,23\n29,233\n37,224\nbad line\n41,13367\n")
+        .unwrap();
+    mersenne()
+        .args(["search", "2", "45", "--dry-run", "--known-factors"])
+        .arg(&factors)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("skipping line 5 of "))
+        .stdout(predicate::str::contains(
+            "  skipped, known factors (--known-factors): 3\nTo test: 11\n",
+        ));
+    // 224 does not divide M(37), which trial factoring then shows has 223.
+    mersenne()
+        .args(["search", "2", "45", "--known-factors"])
+        .arg(&factors)
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .stderr(predicate::str::contains(": 224 does not divide M(37)."))
+        .stderr(predicate::str::contains("Read known factors of 3 exponent(s) from "))
+        .stdout(predicate::str::contains(
+            "Composites eliminated by known factors (--known-factors): 3",
+        ))
+        .stdout(predicate::str::contains("Candidates found: 14\n"))
+        .stdout(
+            predicate::str::is_match("known factors +1 thread\\(s\\) +14 exponent\\(s\\) +3 elim")
+                .unwrap(),
+        )
+        .stdout(predicate::str::is_match("trial factoring +1 thread\\(s\\) +11 exponent").unwrap());
+    let results = std::fs::read_to_string(&results).unwrap();
+    assert!(results.contains("exponent=41 result=factored factor=13367 stage=external "));
+    assert!(results.contains("exponent=37 result=factored factor=223 stage=TF "));
+    mersenne()
+        .args(["search", "2", "45", "--form", "wagstaff", "--known-factors"])
+        .arg(&factors)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn exponent_filter_double_mersenne_tests_the_feasible_double_mersenne_numbers() {
+    mersenne()
+        .args(["search", "2", "200", "--exponent-filter", "double-mersenne", "--dry-run"])
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains(
+            "tests MM(2) = M(3), MM(3) = M(7), MM(5) = M(31), MM(7) = M(127) in place of the \
+             range p = 2 to p = 200.",
+        ))
+        .stderr(predicate::str::contains(
+            "leaving out MM(13) = M(8191), MM(17) = M(131071), MM(19) = M(524287), \
+             MM(31) = M(2147483647), MM(61) = M(2305843009213693951) and the larger",
+        ))
+        .stdout(predicate::str::contains("Candidates: 4\nTo test: 4\n"));
+    mersenne()
+        .args(["search", "2", "200", "--exponent-filter", "double-mersenne", "--form", "wagstaff"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--exponent-filter double-mersenne applies to Mersenne numbers only",
+        ));
+}
+
+#[test]
+fn list_known_prints_every_known_prime() {
+    mersenne()
+        .arg("list-known")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("antiquity"))
+        .stdout(predicate::str::is_match(r"52\s+136279841\s+41,024,320\s+2024").unwrap());
+}
+
+#[test]
+fn order_largest_tests_the_largest_exponent_first() {
+    let output = mersenne()
+        .args(["search", "2", "40", "--order", "largest", "--threads", "1"])
+        .args(["--tf-depth", "0", "--no-summary"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let first = stdout.lines().find(|line| line.starts_with("M(")).unwrap();
+    assert!(first.starts_with("M(37) is composite"), "{}", stdout);
+}
+
+#[test]
+fn ordered_prints_results_in_exponent_order() {
+    // With four threads the small exponents finish well before M(4423),
+    // which --ordered holds them back behind.
+    let output = mersenne()
+        .args(["test", "4423,2203,31,61,89,107,127"])
+        .args([
+            "--threads",
+            "4",
+            "--tf-depth",
+            "0",
+            "--ordered",
+            "--no-summary",
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let printed: Vec<u64> = stdout
+        .lines()
+        .filter_map(|line| line.split("M(").nth(1)?.split(')').next())
+        .map(|p| p.parse().unwrap())
+        .collect();
+    assert_eq!(printed, [31, 61, 89, 107, 127, 2203, 4423], "{}", stdout);
+}
+
+#[test]
+fn one_thread_prints_results_in_exponent_order() {
+    // Trial factoring runs ahead of the tests even with one thread, but
+    // its results wait for those of the smaller exponents.
+    let output = mersenne()
+        .args(["search", "2", "200", "--threads", "1", "--no-summary"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let printed: Vec<u64> = stdout
+        .lines()
+        .filter_map(|line| line.split("M(").nth(1)?.split(')').next())
+        .map(|p| p.parse().unwrap())
+        .collect();
+    assert_eq!(printed.len(), 46, "{}", stdout);
+    assert!(printed.windows(2).all(|pair| pair[0] < pair[1]), "{}", stdout);
+}
+
+#[test]
+fn rejects_an_unknown_order() {
+    mersenne()
+        .args(["search", "2", "40", "--order", "sideways"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn pminus1_factors_skip_the_primality_test() {
+    mersenne()
+        .args(["test", "67", "--tf-depth", "0", "--p1-b1", "3000"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("M(67) has factor 193707721 (P-1)"))
+        .stdout(predicate::str::contains(
+            "M(67) is composite (21 digits): P-1 factoring found the factor 193707721.",
+        ));
+}
+
+#[test]
+fn tf_depth_is_searched_in_full_when_asked_for() {
+    // 13367 is past the depth worth searching for M(41) by default.
+    mersenne()
+        .args(["test", "41", "--no-summary"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("M(41) is composite (LL)"));
+    mersenne()
+        .args(["test", "41", "--tf-depth", "32", "--no-summary"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("M(41) has factor 13367 (TF)"));
+}
+
+#[test]
+fn double_check_reports_matching_shifted_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args(["test", "89,97", "--tf-depth", "0", "--double-check"])
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .success()
+        .stdout(predicate::str::is_match(r"M\(89\) double-check: MATCH \(shift \d+\)").unwrap())
+        .stdout(predicate::str::is_match(r"M\(97\) double-check: MATCH \(shift \d+\)").unwrap())
+        .stdout(predicate::str::contains(
+            "Double-checks: 2 matched, 0 mismatched",
+        ));
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert_eq!(
+        text.matches("double_check=MATCH shift=").count(),
+        2,
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn backend_is_chosen_per_exponent_and_recorded() {
+    let res64s: Vec<String> = ["bignum", "fixed"]
+        .iter()
+        .map(|backend| {
+            let output = mersenne()
+                .args(["test", "1277", "--tf-depth", "0", "--json", "--no-summary"])
+                .args(["--backend", backend])
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8(output.stdout).unwrap();
+            assert!(stdout.contains(&format!(r#""backend":"{}""#, backend)), "{}", stdout);
+            stdout.split(r#""res64":""#).nth(1).unwrap()[..16].to_string()
+        })
+        .collect();
+    assert_eq!(res64s[0], res64s[1]);
+
+    // fixed cannot take 4423, which goes to the backend auto picks.
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args(["test", "89,4423", "--tf-depth", "0", "--backend", "fixed", "-v"])
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("M(89) (LL on fixed)"))
+        .stderr(predicate::str::contains(
+            "--backend fixed takes exponents up to 4,096 only",
+        ));
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert!(text.contains("exponent=89 result=prime test=LL backend=fixed "), "{}", text);
+    assert!(!text.contains("exponent=4423 result=prime test=LL backend=fixed"), "{}", text);
+
+    // auto runs the second run of a double-check on the next fastest.
+    mersenne()
+        .args(["test", "1279", "--tf-depth", "0", "--double-check", "-v"])
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Timed 100 iterations of each backend at 2,048 bits:",
+        ))
+        .stdout(predicate::str::is_match(r"double-check: MATCH \(shift \d+, on \w+\)").unwrap());
+    let text = std::fs::read_to_string(&results).unwrap();
+    let last = text.lines().last().unwrap();
+    let field = |key: &str| {
+        let prefix = format!("{}=", key);
+        last.split(' ')
+            .find_map(|field| field.strip_prefix(&prefix).map(str::to_string))
+    };
+    assert!(field("backend").is_some() && field("check_backend").is_some(), "{}", last);
+    assert_ne!(field("backend"), field("check_backend"), "{}", last);
+}
+
+#[test]
+fn primenet_results_get_one_json_line_per_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("results.json.txt");
+    mersenne()
+        .args(["test", "29,31,37", "--tf-depth", "32", "--no-summary"])
+        .args(["--primenet-user", "alice", "--primenet-results"])
+        .arg(&path)
+        .assert()
+        .success();
+    let text = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_json::Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // M(29) and M(37) have small factors, so only M(31) was tested.
+    assert_eq!(lines.len(), 1, "{}", text);
+    assert_eq!(lines[0]["status"], "P");
+    assert_eq!(lines[0]["exponent"], 31);
+    assert_eq!(lines[0]["worktype"], "LL");
+    assert_eq!(lines[0]["error-code"], "00000000");
+    assert_eq!(lines[0]["user"], "alice");
+    assert!(lines[0].get("computer").is_none());
+}
+
+#[test]
+fn max_test_seconds_gives_up_on_long_tests() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args([
+            "test",
+            "2203,44497",
+            "--tf-depth",
+            "0",
+            "--max-test-seconds",
+            "1",
+        ])
+        .arg("--results")
+        .arg(&results)
+        .assert()
+        .code(0)
+        .stdout(
+            predicate::str::is_match(r"M\(44497\) timed out at \d+\.\d% complete \(LL\)").unwrap(),
+        )
+        .stdout(predicate::str::contains("Tests timed out: 1"))
+        .stdout(predicate::str::contains("Retry them with: test 44497"));
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert!(
+        text.contains("exponent=44497 result=timeout test=LL iteration="),
+        "{}",
+        text
+    );
+    assert!(text.contains("exponent=2203 result=prime"), "{}", text);
+}
+
+#[test]
+fn threads_per_test_splits_squarings() {
+    mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--threads-per-test", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Found Mersenne prime: M(4423)"));
+    mersenne()
+        .args(["test", "4423", "--threads-per-test", "0"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn pin_threads_reports_the_layout() {
+    mersenne()
+        .args(["test", "31", "--pin-threads", "--cpus", "0,4096"])
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("cannot run on CPU(s) 4096"))
+        .stderr(predicate::str::contains(
+            "Pinned threads: trial factoring on CPU 0, primality test on CPU 0.",
+        ));
+    for bad in ["3-1", "0-x"] {
+        mersenne().args(["test", "31", "--cpus", bad]).assert().code(2);
+    }
+}
+
+#[test]
+fn pause_file_holds_the_tests_while_it_exists() {
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("pause");
+    std::fs::write(&file, "").unwrap();
+    let removing = {
+        let file = file.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(2));
+            std::fs::remove_file(file).unwrap();
+        })
+    };
+    mersenne()
+        .args(["test", "9941", "--tf-depth", "0", "--nice", "--pause-file"])
+        .arg(&file)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains("Running at nice 19 priority."))
+        .stderr(predicate::str::contains("Pausing the tests:"))
+        .stderr(predicate::str::contains("Resuming the tests after"));
+    removing.join().unwrap();
+    for bad in ["-1", "x", "inf"] {
+        mersenne().args(["test", "31", "--pause-when-busy", bad]).assert().code(2);
+    }
+}
+
+#[test]
+fn reports_record_the_speed_of_each_test() {
+    // Progress is reported every second, so the speed needs a test that
+    // runs for a few.
+    mersenne()
+        .args(["test", "9941", "--tf-depth", "0", "--json", "--rate-window", "5"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(
+                r#""throughput":\{"average":[0-9.e+]+,"slowest":[0-9.e+]+,"slowdowns":[0-9]+\}"#,
+            )
+            .unwrap(),
+        );
+    for bad in [["--rate-window", "0"], ["--slowdown-warning", "100"]] {
+        mersenne().args(["test", "31"]).args(bad).assert().code(2);
+    }
+}
+
+#[test]
+fn clean_runs_record_no_arithmetic_errors() {
+    mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--json", "--on-error", "abort"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""prime":true"#))
+        .stdout(predicate::str::contains(r#""failed_at":null,"errors":0"#));
+    mersenne()
+        .args(["test", "31", "--on-error", "ignore"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn chunks_split_a_search_without_overlap() {
+    let dir = tempfile::tempdir().unwrap();
+    let exponents = |path: &std::path::Path| -> Vec<u64> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let field = line
+                    .split(' ')
+                    .find(|f| f.starts_with("exponent="))
+                    .unwrap();
+                field["exponent=".len()..].parse().unwrap()
+            })
+            .collect()
+    };
+    let whole = dir.path().join("whole.txt");
+    mersenne()
+        .args(["search", "2", "300", "--no-summary", "--results"])
+        .arg(&whole)
+        .assert()
+        .code(0);
+    let mut expected = exponents(&whole);
+    expected.sort_unstable();
+
+    let mut union = Vec::new();
+    for i in 1..=3 {
+        let path = dir.path().join(format!("chunk{}.txt", i));
+        mersenne()
+            .args(["search", "2", "300", "--no-summary", "--chunk"])
+            .arg(format!("{}/3", i))
+            .arg("--results")
+            .arg(&path)
+            .assert()
+            .stderr(predicate::str::contains(format!(
+                "range p = 2 to p = 300, chunk {}/3...",
+                i
+            )));
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .all(|line| line.ends_with(&format!(" chunk={}/3", i))));
+        union.extend(exponents(&path));
+    }
+    union.sort_unstable();
+    assert_eq!(union, expected);
+
+    for bad in ["0/3", "4/3", "3"] {
+        mersenne()
+            .args(["search", "2", "300", "--chunk", bad])
+            .assert()
+            .code(2);
+    }
+}
+
+#[test]
+fn double_check_conflicts_with_prp() {
+    mersenne()
+        .args(["test", "89", "--double-check", "--prp"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn status_interval_prints_heartbeat_lines() {
+    mersenne()
+        .args(["test", "9689,9941", "--threads", "1", "--tf-depth", "0"])
+        .args(["--status-interval", "1", "--no-summary"])
+        .assert()
+        .code(0)
+        .stderr(predicate::str::is_match(r"(?m)^\[.*\] \d+ exponents done, \d+ to go, .*; running M\((9689|9941)\) \d+\.\d%; \d+ iter/s; trial factoring 2 done, 0 eliminated, 0 queued; primality test [01] done, 0 eliminated, [01] queued$").unwrap());
+}
+
+#[test]
+fn status_file_holds_the_final_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("status.json");
+    mersenne()
+        .args(["search", "2", "31", "--status-file"])
+        .arg(&path)
+        .assert()
+        .code(0);
+    let status: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(status["start_exponent"], 2);
+    assert_eq!(status["end_exponent"], 31);
+    assert_eq!(status["completed"], 11);
+    assert_eq!(status["remaining"], 0);
+    assert_eq!(status["discovering"], false);
+    assert_eq!(status["percent_of_work"], 100.0);
+    assert_eq!(status["running"], serde_json::json!([]));
+    assert_eq!(
+        status["primes"],
+        serde_json::json!([2, 3, 5, 7, 13, 17, 19, 31])
+    );
+    assert_eq!(status["memory"], serde_json::Value::Null);
+    assert!(!dir.path().join("status.tmp").exists());
+}
+
+#[test]
+fn status_reports_the_share_of_the_work_done() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("status.json");
+    // M(9941) costs far more than M(3), and runs out of time part of the
+    // way through, so the run ends well short of all of its work.
+    let output = mersenne()
+        .args(["test", "3,9941", "--tf-depth", "0", "--max-test-seconds", "2"])
+        .args(["--status-interval", "1", "--status-file"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let heartbeat = r"(?m)^\[.*\] \d+ exponents done, .*; \d+\.\d% of the work done; running M";
+    assert!(predicate::str::is_match(heartbeat).unwrap().eval(&stderr), "{}", stderr);
+    let status: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(status["completed"], 2);
+    let percent = status["percent_of_work"].as_f64().unwrap();
+    assert!(percent > 0.0 && percent < 100.0, "{}", percent);
+}
+
+#[test]
+fn control_file_pauses_reports_and_stops_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let (control, status) = (dir.path().join("control"), dir.path().join("status.json"));
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("Mersenne"))
+        .args(["test", "9941,9949", "--tf-depth", "0", "--threads", "1"])
+        .args(["--status-interval", "0", "--status-file-interval", "1", "--status-file"])
+        .arg(&status)
+        .arg("--control")
+        .arg(&control)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let state = || {
+        let text = std::fs::read_to_string(&status).unwrap_or_default();
+        let document: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
+        document["state"].as_str().unwrap_or_default().to_string()
+    };
+    let wait_for = |wanted: &str| {
+        for _ in 0..100 {
+            if state() == wanted {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(200));
+        }
+        panic!("the status file never said {}", wanted);
+    };
+    std::fs::write(&control, "pause\n").unwrap();
+    wait_for("paused");
+    // The command is taken, and the file emptied.
+    assert_eq!(std::fs::read_to_string(&control).unwrap(), "");
+    std::fs::write(&control, "status\n").unwrap();
+    while !std::fs::read_to_string(&control).unwrap().is_empty() {
+        std::thread::sleep(std::time::Duration::from_millis(200));
+    }
+    std::fs::write(&control, "resume\n").unwrap();
+    wait_for("running");
+    std::fs::write(&control, "bogus stop\n").unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    let lines = [
+        r"(?m)^\[[-\d: ]+\] Pausing the tests: asked to through --control\.$",
+        r"(?m)^\[[-\d: ]+\] Status asked for through .*control\.$",
+        r"(?m)^\[[-\d: ]+\] paused; 0 exponents done, 2 to go, ",
+        r"(?m)^\[[-\d: ]+\] Resuming the tests after .* paused\.$",
+        r#"unknown command "bogus"; the commands are pause, resume, stop and status"#,
+        r"(?m)^\[[-\d: ]+\] Stopping after saving running tests: `stop` came through .*control\.$",
+    ];
+    for line in lines {
+        assert!(predicate::str::is_match(line).unwrap().eval(&stderr), "{}\n{}", line, stderr);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn control_socket_takes_the_same_commands() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let dir = tempfile::tempdir().unwrap();
+    let socket = dir.path().join("control.sock");
+    let child = std::process::Command::new(assert_cmd::cargo::cargo_bin("Mersenne"))
+        .args(["test", "9941,9949", "--tf-depth", "0", "--threads", "1"])
+        .arg("--control-socket")
+        .arg(&socket)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let send = |command: &str| {
+        for _ in 0..100 {
+            if let Ok(mut stream) = UnixStream::connect(&socket) {
+                writeln!(stream, "{}", command).unwrap();
+                let mut reply = String::new();
+                BufReader::new(stream).read_line(&mut reply).unwrap();
+                return reply;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        panic!("nothing listens on {}", socket.display());
+    };
+    assert_eq!(send("pause"), "ok\n");
+    // The pause watch raises the pause within a second.
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert!(send("halt").starts_with("unknown command \"halt\""));
+    assert_eq!(send("stop"), "ok\n");
+
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("Pausing the tests: asked to through --control."), "{}", stderr);
+    assert!(!socket.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn max_mem_holds_back_tests_that_do_not_fit() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("status.json");
+    // M(1100051) is estimated at over 1 MB, so it waits for M(9689) to
+    // finish and then runs alone. Without trial factoring, which would
+    // find 2200103 before it ever got to the budget, its test takes hours,
+    // so it is interrupted once it starts.
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("Mersenne"))
+        .args(["test", "9689,1100051", "--threads", "2", "--max-mem", "1"])
+        .args(["--tf-depth", "0", "--status-interval", "1", "--status-file"])
+        .arg(&path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = String::new();
+    for line in BufReader::new(child.stderr.take().unwrap()).lines() {
+        let line = line.unwrap();
+        if line.contains("running M(1100051)") && !stderr.contains("running M(1100051)") {
+            let killed = std::process::Command::new("kill")
+                .args(["-INT", &child.id().to_string()])
+                .status()
+                .unwrap();
+            assert!(killed.success());
+        }
+        stderr += &line;
+        stderr += "\n";
+    }
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        stderr.contains("Warning: M(1100051) needs about 2.0 MB, more than --max-mem allows"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("; memory 0.0 MB of 1.0 MB, 1 waiting"),
+        "{}",
+        stderr
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("Found Mersenne prime: M(9689)"),
+        "{}",
+        stdout
+    );
+    let status: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(
+        status["memory"],
+        serde_json::json!({
+            "limit_bytes": 1048576,
+            "estimated_bytes": 0,
+            "running": 0,
+            "waiting": 0,
+        })
+    );
+}
+
+#[test]
+fn cpu_limit_sleeps_out_of_the_compute_time() {
+    let output = mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--cpu-limit", "20", "--json"])
+        .args(["--no-summary", "--threads", "1"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = stdout.lines().find(|line| line.contains(r#""exponent":4423"#)).unwrap();
+    let report: serde_json::Value = serde_json::from_str(line).unwrap();
+    let seconds = report["seconds"].as_f64().unwrap();
+    let compute = report["compute_seconds"].as_f64().unwrap();
+    // A fifth of a core takes five times as long; the slack is for the
+    // first and last slices, which are not slept out.
+    assert!(seconds > 3.0 * compute, "{} seconds, {} computing", seconds, compute);
+    mersenne().args(["test", "89", "--cpu-limit", "0"]).assert().code(2);
+    mersenne().args(["test", "89", "--cpu-limit", "100"]).assert().code(2);
+}
+
+#[test]
+fn workers_test_what_the_server_hands_out() {
+    use std::net::{TcpListener, TcpStream};
+    use std::time::{Duration, Instant};
+
+    let dir = tempfile::tempdir().unwrap();
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut server = std::process::Command::new(assert_cmd::cargo::cargo_bin("Mersenne"))
+        .args(["serve", "--range", "2", "100", "--listen"])
+        .arg(address.to_string())
+        .current_dir(dir.path())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let started = Instant::now();
+    while TcpStream::connect(address).is_err() {
+        assert!(started.elapsed() < Duration::from_secs(30), "no server");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let url = format!("http://{}", address);
+    let worked = mersenne()
+        .args(["work", "--server", &url, "--threads", "1"])
+        .assert();
+    let results = std::fs::read_to_string(dir.path().join("mersenne-results.txt"));
+    let state = std::fs::read_to_string(dir.path().join("mersenne-server.json"));
+    server.kill().unwrap();
+    server.wait().unwrap();
+
+    worked
+        .code(0)
+        .stdout(predicate::str::contains("Found Mersenne prime: M(89)"))
+        .stderr(predicate::str::contains("its range is done"));
+    // The 25 primes up to 100, each once.
+    assert_eq!(results.unwrap().lines().count(), 25);
+    assert!(state.unwrap().contains("\"leases\":{}"));
+
+    mersenne()
+        .args(["work", "--server", &url])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot reach the server"));
+}
+
+#[test]
+fn notifies_of_primes_and_the_end_of_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("notified.txt");
+    let command = format!(
+        "echo \"$MERSENNE_EVENT $MERSENNE_EXPONENT $MERSENNE_DIGITS $MERSENNE_PRIMES\" >> '{}'",
+        log.display()
+    );
+    // Nothing listens on port 1, so every post fails without affecting
+    // the run.
+    mersenne()
+        .args(["search", "11", "13", "--notify-cmd", &command])
+        .args(["--notify-url", "http://127.0.0.1:1/hook"])
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains(
+            "Warning: could not post to --notify-url",
+        ));
+    let notified = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(notified, "prime 13 4 \nfinished   13\n");
+
+    mersenne()
+        .args(["test", "11", "--notify-cmd", "exit 7"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains("--notify-cmd failed"));
+}
+
+#[test]
+fn a_ledger_resumes_a_search_where_it_stopped() {
+    let dir = tempfile::tempdir().unwrap();
+    let ledger = dir.path().join("ledger.jsonl");
+    mersenne()
+        .args(["search", "2", "31", "--ledger"])
+        .arg(&ledger)
+        .assert()
+        .code(0);
+    // The 11 primes up to 31 are done; 37 to 61 are not.
+    mersenne()
+        .args(["search", "2", "61", "--no-summary", "--ledger"])
+        .arg(&ledger)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains(
+            "Resuming: 11 of 18 candidates already done.",
+        ))
+        .stdout(predicate::str::contains("M(61)"))
+        .stdout(predicate::str::contains("M(31)").not());
+    mersenne()
+        .args(["search", "2", "100", "--ledger-report", "--ledger"])
+        .arg(&ledger)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "p = 2 to p = 100: 18 of 25 candidates done, 7 to go",
+        ))
+        .stdout(predicate::str::contains(
+            "Mersenne primes: M(2), M(3), M(5), M(7), M(13), M(17), M(19), M(31), M(61)",
+        ));
+}
+
+#[test]
+fn a_results_database_collects_runs_and_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("results.db");
+    mersenne()
+        .args(["search", "2", "31", "--db"])
+        .arg(&db)
+        .assert()
+        .code(0);
+    mersenne()
+        .args(["search", "2", "61", "--no-summary", "--db"])
+        .arg(&db)
+        .assert()
+        .code(0)
+        .stderr(predicate::str::contains(
+            "Skipped 11 exponent(s) already in",
+        ))
+        .stdout(predicate::str::contains("M(61)"))
+        .stdout(predicate::str::contains("M(31)").not());
+    // A re-test updates the row it already has.
+    mersenne()
+        .args(["test", "31", "--retest", "--db"])
+        .arg(&db)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("M(31)"));
+
+    mersenne()
+        .arg("report")
+        .arg(&db)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Runs: 3 (0 unfinished), on 1 host(s)",
+        ))
+        .stdout(predicate::str::contains(
+            "Results: 18: 9 prime, 4 factored, 5 composite, 0 timed out",
+        ))
+        .stdout(predicate::str::is_match(r"(?m)^  M\(2\) to M\(61\) +18 +9 +4 +5 +0 ").unwrap())
+        .stdout(predicate::str::contains("Slowest exponents:"));
+    let csv = mersenne()
+        .args(["report", "--export", "csv"])
+        .arg(&db)
+        .output()
+        .unwrap();
+    let csv = String::from_utf8(csv.stdout).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 19);
+    assert!(lines[0].starts_with("form,k,exponent,status,"));
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("mersenne,,23,factored,,,47,TF,")));
+    assert_eq!(
+        lines
+            .iter()
+            .filter(|line| line.starts_with("mersenne,,31,"))
+            .count(),
+        1
+    );
+    mersenne()
+        .args(["report", "--export", "json"])
+        .arg(&db)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            r#""exponent":61,"form":"mersenne","prime":true"#,
+        ));
+
+    mersenne()
+        .args(["report", "--export", "xml"])
+        .arg(&db)
+        .assert()
+        .code(2);
+    mersenne()
+        .arg("report")
+        .arg(dir.path().join("missing.db"))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("is not a results database"));
+    mersenne()
+        .args(["search", "2", "31", "--retest"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("needs one of them"));
+}
+
+#[test]
+fn wagstaff_form_tests_wagstaff_numbers() {
+    // W(29) = (2^29 + 1) / 3 has the factor 59; W(31) is prime.
+    mersenne()
+        .args(["test", "29,31", "--form", "wagstaff"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("W(29) has factor 59 (TF)"))
+        .stdout(predicate::str::contains(
+            "Found Wagstaff probable prime: W(31) (PRP), 9 digits",
+        ))
+        .stdout(predicate::str::contains(
+            "W(31) is a Wagstaff probable prime (9 digits).",
+        ));
+    mersenne()
+        .args([
+            "search",
+            "2",
+            "20",
+            "--form",
+            "wagstaff",
+            "--json",
+            "--no-summary",
+        ])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            r#"{"exponent":19,"form":"wagstaff","prime":true,"test":"PRP""#,
+        ))
+        .stdout(predicate::str::contains(r#""exponent":2,"#).not());
+    mersenne()
+        .args(["test", "2", "--form", "wagstaff"])
+        .assert()
+        .code(2);
+    mersenne()
+        .args(["test", "31", "--form", "wagstaff", "--double-check"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "--double-check applies to Mersenne numbers only",
+        ));
+}
+
+#[test]
+fn riesel_form_runs_llr_tests() {
+    // 15·2^n - 1 is prime for n = 4, 5 and 10; n = 2 and 3 have k >= 2^n.
+    mersenne()
+        .args(["search", "2", "12", "--form", "riesel", "--k", "15"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Found Riesel prime: 15*2^10-1 (LLR), 5 digits",
+        ))
+        .stdout(predicate::str::contains(
+            "15*2^12-1 is composite (LLR). Res64: 0x00000000000042F9",
+        ))
+        .stdout(predicate::str::contains("Composites found by LLR: 6"))
+        .stdout(predicate::str::contains("15*2^3-1").not());
+    mersenne()
+        .args([
+            "test",
+            "4",
+            "--form",
+            "riesel",
+            "--k",
+            "5",
+            "--json",
+            "--no-summary",
+        ])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            r#"{"exponent":4,"form":"riesel","k":5,"prime":true,"test":"LLR""#,
+        ));
+    mersenne()
+        .args(["test", "2", "--form", "riesel", "--k", "5"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("k < 2^n"));
+    mersenne()
+        .args(["test", "7", "--form", "riesel", "--k", "4"])
+        .assert()
+        .code(2);
+    mersenne()
+        .args(["test", "7", "--k", "5"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("it needs --form riesel"));
+}
+
+#[test]
+fn fermat_runs_pepin_tests_over_a_range_of_indices() {
+    mersenne()
+        .args(["fermat", "0", "6"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Found Fermat prime: F(4) (Pepin), 5 digits",
+        ))
+        .stdout(predicate::str::contains(
+            "F(5) is composite (Pepin). Res64: 0x00000000009D894F",
+        ))
+        .stdout(predicate::str::contains(
+            "F(0) is a Fermat prime (1 digits).",
+        ))
+        .stdout(predicate::str::contains(
+            "Composites found by Pépin's test: 2",
+        ));
+    mersenne()
+        .args(["fermat", "7", "7", "--json", "--no-summary"])
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with(
+            r#"{"exponent":7,"form":"fermat","prime":false,"test":"Pepin""#,
+        ));
+    mersenne().args(["fermat", "3", "64"]).assert().code(2);
+    mersenne()
+        .args(["fermat", "1", "2", "--form", "wagstaff"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn confirm_reruns_primes_with_the_other_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    mersenne()
+        .args(["test", "31,37", "--confirm", "--no-summary", "--results"])
+        .arg(&results)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "M(31) confirmation: CONFIRMED (Res64: 0x0000000000000009)",
+        ))
+        .stdout(predicate::str::contains("M(37) confirmation").not());
+    let lines = std::fs::read_to_string(&results).unwrap();
+    assert!(lines.contains("test=LL confirm=CONFIRMED confirm_res64=0000000000000009"));
+    mersenne()
+        .args(["test", "61", "--prp", "--confirm", "--json", "--no-summary"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            r#""confirmation":"CONFIRMED","confirm_res64":"0000000000000000""#,
+        ));
+    mersenne()
+        .args(["test", "31", "--form", "wagstaff", "--confirm"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Mersenne numbers only"));
+}
+
+#[test]
+fn dry_run_prints_the_plan_and_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results.txt");
+    let ledger = dir.path().join("ledger.jsonl");
+    std::fs::write(
+        &results,
+        "2026-01-01T00:00:00Z exponent=11 result=factored factor=23 stage=TF seconds=0.000\n",
+    )
+    .unwrap();
+    mersenne()
+        .args([
+            "search",
+            "2",
+            "1000",
+            "--dry-run",
+            "--skip-known",
+            "--results",
+        ])
+        .arg(&results)
+        .arg("--ledger")
+        .arg(&ledger)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("Plan for M(2) to M(1000):"))
+        .stdout(predicate::str::contains("Candidates: 168"))
+        .stdout(predicate::str::contains(
+            "Known Mersenne primes in the range: 14",
+        ))
+        .stdout(predicate::str::contains(
+            "Expected Mersenne primes in the range: 15.97 (Wagstaff conjecture)",
+        ))
+        .stdout(predicate::str::contains(
+            "skipped, known Mersenne primes (--skip-known): 14",
+        ))
+        .stdout(predicate::str::contains(
+            "skipped, already in the results file or database: 1",
+        ))
+        .stdout(predicate::str::contains("To test: 153"))
+        .stdout(predicate::str::is_match(r"100 to 999 +139 exponent\(s\), about ").unwrap())
+        .stdout(predicate::str::contains("Found").not());
+    assert!(!ledger.exists());
+    mersenne()
+        .args(["search", "2", "100", "--dry-run", "--chunk", "2/2", "--json"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::starts_with(
+            r#"{"plan":{"form":"mersenne","start_exponent":2,"end_exponent":100,"candidates":25,"known_primes":0,"untestable":0,"other_chunks":13,"in_ledger":0,"in_results":0,"to_test":12,"known_in_range":10,"expected_primes":10.07"#,
+        ));
+}
+
+#[test]
+fn output_that_is_not_a_terminal_is_never_colored() {
+    for setting in ["auto", "always", "never"] {
+        mersenne()
+            .args(["test", "31,37,41,8", "--color", setting])
+            .env_remove("NO_COLOR")
+            .assert()
+            .code(2)
+            .stderr(predicate::str::contains("\x1b[").not());
+        mersenne()
+            .args(["test", "31,37,41", "--no-summary", "--color", setting])
+            .assert()
+            .code(0)
+            .stdout(
+                predicate::str::is_match(
+                    r"\*\*\* Found Mersenne prime: M\(31\) \(LL\), 10 digits, tested in \S+ms\.\n",
+                )
+                .unwrap(),
+            )
+            .stdout(predicate::str::contains("\x1b[").not());
+    }
+    mersenne()
+        .args(["test", "31", "--color", "blue"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn numbers_and_durations_are_written_for_people() {
+    mersenne()
+        .args(["test", "4423", "--tf-depth", "0"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("M(4423) (LL), 1,332 digits, tested in "))
+        .stdout(predicate::str::contains("M(4423) is a Mersenne prime (1,332 digits"))
+        .stdout(predicate::str::is_match(r"\n  Elapsed:      (\d+ms|\d+\.\ds) \(").unwrap());
+    mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--digit-separator", " "])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("1 332 digits"));
+    mersenne()
+        .args(["test", "4423", "--tf-depth", "0", "--raw-numbers"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("1332 digits"))
+        .stdout(predicate::str::contains("1,332").not());
+}
+
+#[test]
+fn a_single_exponent_checkpoints_here_and_resumes_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = |args: &[&str]| {
+        let output = mersenne()
+            .current_dir(dir.path())
+            .args(["test", "9941", "--tf-depth", "0"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        (output.status.code(), stdout, stderr)
+    };
+
+    let (code, stdout, stderr) = test(&["--max-test-seconds", "1"]);
+    assert_eq!(code, Some(1), "{}", stderr);
+    assert!(stdout.contains("The test of M(9941) ran out of time at iteration "), "{}", stdout);
+    assert!(stdout.contains("  Checkpoints:  1 written to .\n"), "{}", stdout);
+    assert!(stderr.contains("run `test 9941 --resume` to carry on"), "{}", stderr);
+    assert!(dir.path().join("M9941.ckpt").exists());
+
+    let (code, _, stderr) = test(&[]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.contains("has a checkpoint of M(9941) at iteration "), "{}", stderr);
+
+    let (code, _, stderr) = test(&["--resume", "--max-test-seconds", "1"]);
+    assert_eq!(code, Some(1), "{}", stderr);
+    assert!(stderr.contains("Resuming M(9941) from iteration "), "{}", stderr);
+
+    let (code, _, stderr) = test(&["--force-restart", "--max-test-seconds", "1"]);
+    assert_eq!(code, Some(1), "{}", stderr);
+    assert!(stderr.contains("Deleted the checkpoint of M(9941)"), "{}", stderr);
+    assert!(!stderr.contains("Resuming"), "{}", stderr);
+
+    std::fs::remove_file(dir.path().join("M9941.ckpt")).unwrap();
+    let (code, _, stderr) = test(&["--resume", "--max-test-seconds", "1"]);
+    assert_eq!(code, Some(1), "{}", stderr);
+    assert!(stderr.contains("no checkpoint of M(9941) in the current directory"), "{}", stderr);
+}
+
+#[test]
+fn a_single_exponent_ends_with_a_report_of_its_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = mersenne()
+        .current_dir(dir.path())
+        .args(["test", "4423", "--tf-depth", "0", "--jacobi-interval", "1000"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in [
+        "\nM(4423) is a Mersenne prime (1,332 digits, already known).\n",
+        "\n  Test:         Lucas-Lehmer\n",
+        "\n  Checkpoints:  0 written to .\n",
+        "\n  Error checks: Jacobi every 1,000 iterations; no errors caught\n",
+    ] {
+        assert!(stdout.contains(line), "{:?} in {}", line, stdout);
+    }
+    assert!(stdout.contains("\n  Throughput:   "), "{}", stdout);
+    assert!(!stdout.contains("Total time taken"), "{}", stdout);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    mersenne()
+        .args(["test", "4429"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("rejected exponent 4429 = 43 × 103"));
+    mersenne()
+        .args(["test", "31,61", "--resume"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--resume takes a single exponent"));
+    mersenne()
+        .args(["test", "31", "--prp", "--resume"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn the_report_says_when_no_jacobi_check_ran() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = mersenne()
+        .current_dir(dir.path())
+        .args(["test", "4423", "--tf-depth", "0", "--jacobi-interval", "5000"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = "\n  Error checks: no Jacobi check ran; no errors caught\n";
+    assert!(stdout.contains(line), "{}", stdout);
+}
+
+#[test]
+fn sieve_cache_is_reused_and_extended_across_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = dir.path().join("sieve.bin");
+    let search = |args: &[&str]| {
+        let output = mersenne()
+            .arg("search")
+            .args(args)
+            .args(["--tf-depth", "0", "-v", "--sieve-cache"])
+            .arg(&cache)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let found = stdout.lines().filter(|line| line.starts_with("*** Found")).count();
+        (found, stderr)
+    };
+
+    let (found, stderr) = search(&["2", "700"]);
+    assert_eq!(found, 14);
+    assert!(stderr.contains(" 1 sieved, 1 kept."), "{}", stderr);
+    let (found, stderr) = search(&["100", "700"]);
+    assert_eq!(found, 4);
+    assert!(stderr.contains(" 0 sieved, 1 kept."), "{}", stderr);
+    // Only sieved.
+    let (_, stderr) = search(&["262100", "262200", "--dry-run"]);
+    assert!(stderr.contains(" 1 sieved, 2 kept."), "{}", stderr);
+
+    let mut bytes = std::fs::read(&cache).unwrap();
+    bytes[40] ^= 0x10;
+    std::fs::write(&cache, bytes).unwrap();
+    let (found, stderr) = search(&["2", "700"]);
+    assert_eq!(found, 14);
+    assert!(stderr.contains("(checksum mismatch); starting it over."), "{}", stderr);
+}
+
+#[test]
+fn ranges_without_work_or_beyond_memory_are_reported() {
+    let cases: [(&[&str], i32, &str); 8] = [
+        (&["search", "90", "96"], 9, "0 candidate exponents in the range p = 90 to p = 96"),
+        (&["search", "10", "5"], 2, "start_exponent (10) should be less than or equal to"),
+        (&["search", "0", "1"], 9, "p = 0 and p = 1 are not prime exponents"),
+        (&["search", "2", "7", "--skip-known"], 9, "once --skip-known leaves out"),
+        (&["search", "2", "20", "--skip-known"], 1, "--skip-known leaves out 7 of the 8"),
+        (&["search", "2", "2"], 0, "M(2) = 3 is too small for the Lucas-Lehmer test"),
+        (&["search", "1000000000000000", "1000000000000100"], 2, "Use --force to test it"),
+        (&["search", "2", "130", "--skip-known"], 1, ""),
+    ];
+    for (args, code, message) in cases {
+        let assert = mersenne().args(args).assert().code(code);
+        match message {
+            "" => assert.stderr(predicate::str::contains("--skip-known leaves out").not()),
+            _ => assert.stderr(predicate::str::contains(message)),
+        };
+    }
+}
+
+#[test]
+fn tests_too_large_for_the_memory_are_refused_with_the_numbers() {
+    // About 1.7 TB for one test is more than any machine running this has.
+    mersenne()
+        .args(["test", "1000000000039", "--tf-depth", "0"])
+        .assert()
+        .code(2)
+        .stderr(
+            predicate::str::is_match(
+                "the test of M\\(1000000000039\\) needs about 1788147\\.3 MB of memory, but \
+                 this machine has \\d+\\.\\d MB available, of which a run takes at most 80%",
+            )
+            .unwrap(),
+        );
+}
+
+#[test]
+fn exponents_are_read_from_stdin_as_a_stream() {
+    mersenne()
+        .args(["test", "--stdin", "--no-summary"])
+        .write_stdin("31 61\n89 abc\n\n15 127 31\n")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("M(31) (LL)").count(1))
+        .stdout(predicate::str::contains("M(127) (LL)"))
+        .stderr(predicate::str::contains("skipping line 2 of standard input: \"abc\""))
+        .stderr(predicate::str::contains(
+            "skipping line 4 of standard input: rejected exponent 15",
+        ));
+    mersenne()
+        .args(["test", "--stdin", "--strict", "--no-summary"])
+        .write_stdin("31\n15\n127\n")
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains("M(31) (LL)"))
+        .stdout(predicate::str::contains("M(127)").not())
+        .stderr(predicate::str::contains("line 2 of standard input: rejected exponent 15"));
+    mersenne()
+        .args(["test", "--stdin", "--no-summary"])
+        .write_stdin("")
+        .assert()
+        .code(1);
+    mersenne().args(["test", "31", "--stdin"]).assert().code(2);
+}
+
+#[test]
+fn work_is_totalled_for_the_run_and_the_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("results.db");
+    for range in [["2190", "2210"], ["2270", "2290"]] {
+        mersenne()
+            .arg("search")
+            .args(range)
+            .args(["--tf-depth", "0", "--db"])
+            .arg(&db)
+            .assert()
+            .code(0)
+            .stdout(
+                predicate::str::is_match(
+                    r"\nWork completed this run: 0\.\d+ units \(lifetime: 0\.\d+\)\n",
+                )
+                .unwrap(),
+            );
+    }
+    mersenne()
+        .arg("report")
+        .arg(&db)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::is_match(r"\nLifetime work: 0\.\d+ units\n").unwrap());
+    mersenne()
+        .args(["test", "31", "--json", "--no-summary"])
+        .assert()
+        .code(0)
+        .stdout(
+            predicate::str::is_match(r#""milestones":\[\],"run":"[^"]+","work_units":[\d.e-]+\}"#)
+                .unwrap(),
+        );
+    mersenne()
+        .args(["test", "31", "--tf-depth", "0"])
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains("  Work:         <0.000001 units\n"));
+}
+
+#[test]
+fn config_file_and_environment_supply_default_options() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("mersenne.toml"),
+        "results = \"found.txt\"\nskip_known = true\ntf_depth = 20\n",
+    )
+    .unwrap();
+    mersenne()
+        .current_dir(dir.path())
+        .args(["test", "31", "--print-config", "--tf-depth", "8"])
+        .env("MERSENNE_STATUS_INTERVAL", "5")
+        .assert()
+        .code(0)
+        .stdout(
+            predicate::str::is_match(r#"(?m)^results = "found.txt" +# mersenne.toml$"#).unwrap(),
+        )
+        .stdout(predicate::str::is_match(r"(?m)^tf_depth = 8 +# command line$").unwrap())
+        .stdout(
+            predicate::str::is_match(r"(?m)^status_interval = 5 +# MERSENNE_STATUS_INTERVAL$")
+                .unwrap(),
+        )
+        .stdout(predicate::str::is_match(r"(?m)^order = .smallest. +# default$").unwrap())
+        .stdout(predicate::str::contains("# checkpoint_dir is not set"));
+    mersenne()
+        .current_dir(dir.path())
+        .args(["test", "31,61", "--no-summary"])
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Skipping 2 known Mersenne prime exponent(s)",
+        ));
+    assert!(dir.path().join("found.txt").exists());
+
+    let bad = dir.path().join("bad.toml");
+    std::fs::write(&bad, "threads = 2\nthread = 4\n").unwrap();
+    mersenne()
+        .args(["test", "31", "--config"])
+        .arg(&bad)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unknown field `thread`"));
+    std::fs::write(&bad, "threads = \"four\"\n").unwrap();
+    mersenne()
+        .args(["test", "31", "--config"])
+        .arg(&bad)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("threads = \"four\""))
+        .stderr(predicate::str::contains("expected usize"));
+    std::fs::write(&bad, "threads = 0\n").unwrap();
+    mersenne()
+        .args(["test", "31", "--config"])
+        .arg(&bad)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--threads"))
+        .stderr(predicate::str::contains("threads (").and(predicate::str::contains("bad.toml)")));
+    mersenne()
+        .args(["test", "31"])
+        .env("MERSENNE_JSON", "maybe")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "MERSENNE_JSON should be true or false",
+        ));
+
+    // verbose takes the level -v gives, as a number of times.
+    std::fs::write(&bad, "verbose = 2\n").unwrap();
+    mersenne()
+        .args(["test", "31", "--print-config", "--config"])
+        .arg(&bad)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::is_match(r"(?m)^verbose = 2 +# .*bad.toml$").unwrap());
+    mersenne()
+        .args(["test", "31", "--print-config"])
+        .env("MERSENNE_VERBOSE", "true")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::is_match(r"(?m)^verbose = 1 +# MERSENNE_VERBOSE$").unwrap());
+}
@@ -0,0 +1,45 @@
+//! Builds the C example against the library and runs it.
+#![cfg(all(feature = "ffi", unix))]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Where cargo put the library built with these tests: next to them, in
+/// `target/<profile>/deps`. The copy in `target/<profile>` may be from a
+/// build without the feature.
+fn library_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    exe.parent().unwrap().to_path_buf()
+}
+
+#[test]
+fn the_c_example_runs_against_the_library() {
+    let dir = tempfile::tempdir().unwrap();
+    let program = dir.path().join("ll_test");
+    let library = library_dir();
+    let built = Command::new("cc")
+        .args(["examples/ll_test.c", "-Iinclude", "-lmersenne", "-o"])
+        .arg(&program)
+        .arg("-L")
+        .arg(&library)
+        .status()
+        .expect("cannot run cc");
+    assert!(built.success());
+
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", &library)
+        .env("DYLD_LIBRARY_PATH", &library)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("M(127): prime"), "{}", stdout);
+    assert!(stdout.contains("up to iteration 125"), "{}", stdout);
+    assert!(
+        stdout.contains("M(11): composite, res64 00000000000006C8"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("M(15): invalid exponent"), "{}", stdout);
+    assert!(stdout.contains("M(4423): cancelled"), "{}", stdout);
+}